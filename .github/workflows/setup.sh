@@ -6,7 +6,7 @@ cd "$(dirname "$0")"
 TOOLCHAIN=$(cat ../../rust-toolchain | grep channel | awk '{print $3}' | tr -d '"')
 
 sudo apt-get update
-sudo apt-get install -y qemu-system-riscv64 binutils-riscv64-linux-gnu curl
+sudo apt-get install -y qemu-system-riscv64 binutils-riscv64-linux-gnu gcc-riscv64-linux-gnu curl
 sudo rm -rf /var/lib/apt/lists/*
 
 curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | bash -s -- -y --default-toolchain="$TOOLCHAIN" --profile minimal --component clippy --component rustfmt --component miri --component rust-src --target riscv64gc-unknown-none-elf
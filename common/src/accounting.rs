@@ -0,0 +1,21 @@
+/// The I/O a process did so far. Only bytes which were actually
+/// transferred count.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IoStatistics {
+    // Every request of a batch counts as well as the batch itself
+    pub syscalls: u64,
+    // Of files, ptys and the console
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    // Payloads of datagrams and captured frames
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Returned by `sys_process_info`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub cpu_time_milliseconds: u64,
+    pub mapped_pages: usize,
+    pub io: IoStatistics,
+}
@@ -0,0 +1,30 @@
+pub type Uid = u32;
+pub type Gid = u32;
+
+pub const ROOT_UID: Uid = 0;
+pub const ROOT_GID: Gid = 0;
+
+/// Identity of a process. Children inherit the credentials of the
+/// process which started them. Root is allowed to do everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: Uid,
+    pub gid: Gid,
+}
+
+impl Credentials {
+    pub const ROOT: Self = Self::new(ROOT_UID, ROOT_GID);
+
+    pub const fn new(uid: Uid, gid: Gid) -> Self {
+        Self { uid, gid }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == ROOT_UID
+    }
+
+    /// Processes of other users can only be killed by root
+    pub fn may_kill(&self, target: &Credentials) -> bool {
+        self.is_root() || self.uid == target.uid
+    }
+}
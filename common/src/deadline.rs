@@ -0,0 +1,6 @@
+//! Blocking syscalls take an absolute deadline in clocks of the time
+//! CSR, which userspace reads without a syscall. They fail with TimedOut
+//! once the deadline passed.
+
+/// Blocks until the syscall completes
+pub const NO_DEADLINE: u64 = u64::MAX;
@@ -11,15 +11,20 @@ pub enum SchedulerError {
     LoaderError(LoaderError),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ValidationError {
     InvalidPtr,
+    InvalidValue,
 }
 
 #[derive(Debug)]
 #[repr(usize)]
 pub enum SysWaitError {
+    // Not a child of the calling process
     InvalidPid,
+    // Waiting for any child without having one
+    NoChildren,
+    ValidationError(ValidationError),
 }
 
 #[derive(Debug)]
@@ -28,6 +33,35 @@ pub enum SysExecuteError {
     InvalidProgram,
     ValidationError(ValidationError),
     SchedulerError(SchedulerError),
+    InvalidTerminal,
+}
+
+#[derive(Debug)]
+#[repr(usize)]
+pub enum SysSpawnError {
+    ValidationError(ValidationError),
+    SchedulerError(SchedulerError),
+    // The terminal or a mapped descriptor isn't open, or a number is mapped twice
+    InvalidDescriptor,
+    // The started program would exceed its descriptor limit
+    TooManyDescriptors,
+    // Doesn't exist or isn't a directory
+    InvalidWorkingDirectory,
+}
+
+#[derive(Debug)]
+pub enum SysSuspendError {
+    InvalidPid,
+    PermissionDenied,
+    AlreadyStopped,
+}
+
+#[derive(Debug)]
+pub enum SysResumeError {
+    InvalidPid,
+    PermissionDenied,
+    // Only stopped processes can be resumed
+    NotStopped,
 }
 
 #[derive(Debug)]
@@ -44,10 +78,240 @@ pub enum SysSocketError {
     ValidationError(ValidationError),
     InvalidDescriptor,
     NoReceiveIPYet,
+    TooManyDescriptors,
+    // No route matches the destination
+    HostUnreachable,
+    // The mac address of the next hop was requested, try again later
+    AddressNotResolved,
+    // Only root may capture frames
+    PermissionDenied,
+    // Datagrams can't be sent to the unspecified address or port 0
+    InvalidAddress,
+    // The datagram doesn't fit into the MTU of the interface
+    MessageTooLarge,
+    // The kernel was built without networking
+    NotSupported,
+    // The peer closed the connection and everything it sent was read
+    ConnectionClosed,
+    // The peer reset the connection or stopped acknowledging data
+    ConnectionReset,
+}
+
+#[derive(Debug)]
+pub enum SysHartError {
+    InvalidHart,
+    AlreadyStopped,
+    AlreadyRunning,
+    // The boot hart receives the uart interrupts
+    CannotStopBootHart,
+    LastRunningHart,
+    PermissionDenied,
+}
+
+#[derive(Debug)]
+pub enum SysPtyError {
+    ValidationError(ValidationError),
+    InvalidDescriptor,
+    TooManyDescriptors,
+    // The deadline passed before the slave side wrote anything
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub enum SysResourceLimitError {
+    ValidationError(ValidationError),
+    // Only the kernel can raise a hard limit
+    HardLimitRaised,
+}
+
+#[derive(Debug)]
+pub enum SysPermissionError {
+    PermissionDenied,
+}
+
+#[derive(Debug)]
+pub enum SysKillError {
+    InvalidPid,
+    PermissionDenied,
+}
+
+#[derive(Debug)]
+pub enum SysPageTablesError {
+    InvalidPid,
+    PermissionDenied,
+}
+
+#[derive(Debug)]
+pub enum SysMapPhysicalError {
+    ValidationError(ValidationError),
+    PermissionDenied,
+    // Unaligned, empty or wrapping around the address space
+    InvalidRange,
+    // Overlaps RAM or a device the kernel uses itself
+    KernelOwned,
+}
+
+#[derive(Debug)]
+pub enum SysBrkError {
+    // Below the start of the heap or onto memory which is mapped already
+    InvalidBreak,
+    // The mapped pages limit would be exceeded
+    LimitExceeded,
+}
+
+#[derive(Debug)]
+pub enum SysPeriodicError {
+    // The budget must be at least 1 ms and not exceed the period
+    InvalidReservation,
+    // The reservation doesn't fit beside the admitted ones
+    Overloaded,
+    // Only processes with a reservation have periods
+    NotPeriodic,
+    // The process was stopped while it waited for the next period
+    Interrupted,
+}
+
+#[derive(Debug)]
+pub enum SysRouteError {
+    // The prefix is longer than 32 bits or has host bits set
+    InvalidRoute,
+    // Gateways must be reachable without a gateway themselves
+    GatewayNotOnLink,
+    PermissionDenied,
+    // The kernel was built without networking
+    NotSupported,
+}
+
+#[derive(Debug)]
+pub enum SysProfilerError {
+    PermissionDenied,
+    AlreadyRunning,
+    NotRunning,
+    // Samples are taken at least every millisecond
+    InvalidInterval,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SysIrqError {
+    PermissionDenied,
+    // Not a source of the interrupt controller
+    InvalidInterrupt,
+    // The kernel or another process handles the interrupt
+    AlreadyRegistered,
+    // The process didn't register the interrupt
+    NotRegistered,
+    // The deadline passed before the interrupt fired
+    TimedOut,
+    // The process was stopped while it waited for the interrupt
+    Interrupted,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SysInputError {
+    // The deadline passed before any input arrived
+    TimedOut,
+    // The master side of the pty was closed, no input arrives anymore
+    HungUp,
+    // The process was stopped while it waited for input
+    Interrupted,
+}
+
+#[derive(Debug)]
+pub enum SysFileError {
+    ValidationError(ValidationError),
+    // Paths must be absolute
+    InvalidPath,
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    InvalidDescriptor,
+    TooManyDescriptors,
+    BufferTooSmall,
+    ReadOnly,
+    NoSpace,
+    PermissionDenied,
+    AlreadyMounted,
+    InvalidFilesystem,
+    NotABlockDevice,
+    AlreadyExists,
+    DirectoryNotEmpty,
+    CrossDevice,
+    Busy,
+    // Only regular files can be mapped
+    NotAFile,
+    // Offsets of mappings must be page aligned and mappings not empty
+    InvalidRange,
+    // The mapped pages limit would be exceeded
+    LimitExceeded,
+    IoError,
+}
+
+#[derive(Debug)]
+pub enum SysEnvironmentError {
+    ValidationError(ValidationError),
+    // Keys must not be empty and must not contain '=' or zero bytes
+    InvalidKey,
+    InvalidValue,
+    EnvironmentTooLarge,
+}
+
+#[derive(Debug)]
+pub enum SysSymbolizeError {
+    ValidationError(ValidationError),
+    // The program was stripped
+    NoSymbolTable,
+    NoSymbol,
+}
+
+#[derive(Debug)]
+pub enum SysProcessInfoError {
+    InvalidPid,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SysPollError {
+    ValidationError(ValidationError),
+    InvalidDescriptor,
+    // The process was stopped while it waited for an event
+    Interrupted,
 }
 
 impl_from_to!(ValidationError, SysExecuteError);
 impl_from_to!(ValidationError, SysSocketError);
 impl_from_to!(ValidationError, SysArgError);
+impl_from_to!(ValidationError, SysPtyError);
+impl_from_to!(ValidationError, SysResourceLimitError);
+impl_from_to!(ValidationError, SysEnvironmentError);
+impl_from_to!(ValidationError, SysSymbolizeError);
+impl_from_to!(ValidationError, SysPollError);
+impl_from_to!(ValidationError, SysMapPhysicalError);
+impl_from_to!(ValidationError, SysFileError);
+impl_from_to!(ValidationError, SysWaitError);
 impl_from_to!(LoaderError, SchedulerError);
 impl_from_to!(SchedulerError, SysExecuteError);
+impl_from_to!(ValidationError, SysSpawnError);
+impl_from_to!(SchedulerError, SysSpawnError);
+
+// The kernel checks for root with a helper which returns SysPermissionError
+macro_rules! impl_from_permission_error {
+    ($($to:ty),*) => {
+        $(
+            impl From<SysPermissionError> for $to {
+                fn from(_: SysPermissionError) -> Self {
+                    Self::PermissionDenied
+                }
+            }
+        )*
+    };
+}
+
+impl_from_permission_error!(
+    SysHartError,
+    SysPageTablesError,
+    SysMapPhysicalError,
+    SysIrqError,
+    SysProfilerError,
+    SysFileError,
+    SysRouteError,
+    SysSocketError
+);
@@ -0,0 +1,28 @@
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct FileDescriptor(u64);
+
+impl FileDescriptor {
+    pub const fn new(fd: u64) -> Self {
+        Self(fd)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Directory,
+    CharacterDevice,
+    BlockDevice,
+}
+
+/// Returned by `sys_stat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub kind: FileKind,
+    // In bytes, 0 for directories and character devices
+    pub size: u64,
+}
@@ -8,17 +8,31 @@
 #![feature(negative_impls)]
 #![feature(str_from_raw_parts)]
 
+pub mod accounting;
 pub mod array_vec;
 pub mod big_endian;
 pub mod constructable;
 pub mod consumable_buffer;
+pub mod credentials;
+pub mod deadline;
 pub mod errors;
+pub mod fs;
 pub mod leb128;
+pub mod lock_debug;
 pub mod macros;
+pub mod mmap;
 pub mod mutex;
 pub mod net;
 pub mod numbers;
+pub mod perf;
 pub mod pointer;
+pub mod poll;
+pub mod resource_limits;
 pub mod runtime_initialized;
+pub mod spawn;
+pub mod symbols;
 pub mod syscalls;
+pub mod tty;
 pub mod util;
+pub mod vdso;
+pub mod wait;
@@ -0,0 +1,335 @@
+//! Optional bookkeeping for [`Mutex`](crate::mutex::Mutex) to find deadlocks.
+//!
+//! Lock debugging is disabled by default. As soon as the kernel installs its
+//! [`LockDebugHooks`] via [`enable`] every lock acquisition records the owning
+//! hart, PID, caller location and a backtrace. With this information we can
+//! detect recursive acquisitions on the same hart and simple lock-order
+//! inversions (A -> B on one path, B -> A on another) before they deadlock.
+
+use core::{
+    cell::UnsafeCell,
+    fmt::Display,
+    panic::Location,
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use crate::array_vec::ArrayVec;
+
+pub const BACKTRACE_DEPTH: usize = 16;
+
+const MAX_HELD_LOCKS: usize = 64;
+const MAX_LOCK_ORDERS: usize = 256;
+const MAX_TRACKED_HARTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockOwner {
+    pub hart: usize,
+    pub pid: Option<u64>,
+}
+
+impl Display for LockOwner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "hart {} (PID={})", self.hart, pid),
+            None => write!(f, "hart {}", self.hart),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Acquisition {
+    pub owner: LockOwner,
+    pub location: &'static Location<'static>,
+    pub backtrace: [usize; BACKTRACE_DEPTH],
+}
+
+impl Acquisition {
+    /// Returns the captured return addresses without the unused slots.
+    pub fn backtrace(&self) -> impl Iterator<Item = usize> + '_ {
+        self.backtrace
+            .iter()
+            .copied()
+            .take_while(|&address| address != 0)
+    }
+}
+
+impl Display for Acquisition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} at {}", self.owner, self.location)
+    }
+}
+
+#[derive(Debug)]
+pub enum LockViolation {
+    Recursive {
+        lock: usize,
+        held: Acquisition,
+        requested: Acquisition,
+    },
+    OrderInversion {
+        held_lock: usize,
+        requested_lock: usize,
+        held: Acquisition,
+        requested: Acquisition,
+    },
+}
+
+impl Display for LockViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LockViolation::Recursive {
+                lock,
+                held,
+                requested,
+            } => write!(
+                f,
+                "Recursive acquisition of lock {lock:#x}: held by {held}, requested by {requested}"
+            ),
+            LockViolation::OrderInversion {
+                held_lock,
+                requested_lock,
+                held,
+                requested,
+            } => write!(
+                f,
+                "Lock order inversion: lock {requested_lock:#x} was previously taken before lock {held_lock:#x}; held by {held}, requested by {requested}"
+            ),
+        }
+    }
+}
+
+pub struct LockDebugHooks {
+    /// Must not acquire any lock itself.
+    pub current_owner: fn() -> LockOwner,
+    /// Fills the slice with return addresses. Unused slots must stay zero.
+    pub capture_backtrace: fn(&mut [usize; BACKTRACE_DEPTH]),
+    pub report_violation: fn(&LockViolation) -> !,
+}
+
+#[derive(Clone, Copy)]
+struct HeldLock {
+    lock: usize,
+    acquisition: Acquisition,
+}
+
+struct LockTable {
+    held: ArrayVec<HeldLock, MAX_HELD_LOCKS>,
+    // (first, second) means that second was acquired while first was held
+    orders: ArrayVec<(usize, usize), MAX_LOCK_ORDERS>,
+}
+
+// We cannot use a Mutex here because that would track itself
+struct RawLockTable {
+    locked: AtomicBool,
+    table: UnsafeCell<LockTable>,
+}
+
+unsafe impl Sync for RawLockTable {}
+
+impl RawLockTable {
+    fn with<R>(&self, f: impl FnOnce(&mut LockTable) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        // SAFETY: We hold the spin lock
+        let result = f(unsafe { &mut *self.table.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+static HOOKS: AtomicPtr<LockDebugHooks> = AtomicPtr::new(null_mut());
+
+static LOCK_TABLE: RawLockTable = RawLockTable {
+    locked: AtomicBool::new(false),
+    table: UnsafeCell::new(LockTable {
+        held: ArrayVec::new(),
+        orders: ArrayVec::new(),
+    }),
+};
+
+// The hooks may take locks themselves (e.g. when printing). Those must not
+// be tracked, otherwise we recurse endlessly.
+static INSIDE_HOOK: [AtomicBool; MAX_TRACKED_HARTS] =
+    [const { AtomicBool::new(false) }; MAX_TRACKED_HARTS];
+
+pub fn enable(hooks: &'static LockDebugHooks) {
+    HOOKS.store(hooks as *const _ as *mut _, Ordering::SeqCst);
+}
+
+pub fn disable() {
+    HOOKS.store(null_mut(), Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    !HOOKS.load(Ordering::Relaxed).is_null()
+}
+
+fn hooks() -> Option<&'static LockDebugHooks> {
+    // SAFETY: The pointer was created from a static reference
+    unsafe { HOOKS.load(Ordering::Acquire).as_ref() }
+}
+
+struct ReentranceGuard(&'static AtomicBool);
+
+impl ReentranceGuard {
+    fn new(hart: usize) -> Option<Self> {
+        let flag = &INSIDE_HOOK[hart % MAX_TRACKED_HARTS];
+        if flag.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        Some(Self(flag))
+    }
+}
+
+impl Drop for ReentranceGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Checks if acquiring `lock` is fine and records the acquisition.
+/// Must be called before spinning on the lock, otherwise
+/// a recursive acquisition would never return.
+pub fn acquire(lock: usize, location: &'static Location<'static>) {
+    let Some(hooks) = hooks() else {
+        return;
+    };
+    let owner = (hooks.current_owner)();
+    let Some(_guard) = ReentranceGuard::new(owner.hart) else {
+        return;
+    };
+
+    let mut requested = Acquisition {
+        owner,
+        location,
+        backtrace: [0; BACKTRACE_DEPTH],
+    };
+    (hooks.capture_backtrace)(&mut requested.backtrace);
+
+    if let Err(violation) = check_and_record(lock, requested) {
+        disable();
+        (hooks.report_violation)(&violation);
+    }
+}
+
+/// Checks the lock table without reporting. Returns the violation
+/// which would have been reported by a real acquisition.
+#[doc(hidden)]
+#[allow(clippy::result_large_err)]
+pub fn check_and_record(lock: usize, requested: Acquisition) -> Result<(), LockViolation> {
+    LOCK_TABLE.with(|table| {
+        let hart = requested.owner.hart;
+
+        for held in table
+            .held
+            .iter()
+            .filter(|h| h.acquisition.owner.hart == hart)
+        {
+            if held.lock == lock {
+                return Err(LockViolation::Recursive {
+                    lock,
+                    held: held.acquisition,
+                    requested,
+                });
+            }
+            if table.orders.contains(&(lock, held.lock)) {
+                return Err(LockViolation::OrderInversion {
+                    held_lock: held.lock,
+                    requested_lock: lock,
+                    held: held.acquisition,
+                    requested,
+                });
+            }
+        }
+
+        for index in 0..table.held.len() {
+            let held = table.held[index];
+            if held.acquisition.owner.hart != hart {
+                continue;
+            }
+            let order = (held.lock, lock);
+            if !table.orders.contains(&order) {
+                // If we run out of space we just lose some precision
+                let _ = table.orders.push(order);
+            }
+        }
+
+        // Same as above; better miss a violation than panic while debugging
+        let _ = table.held.push(HeldLock {
+            lock,
+            acquisition: requested,
+        });
+
+        Ok(())
+    })
+}
+
+pub fn release(lock: usize) {
+    let Some(hooks) = hooks() else {
+        return;
+    };
+    let hart = (hooks.current_owner)().hart;
+    let Some(_guard) = ReentranceGuard::new(hart) else {
+        return;
+    };
+    remove_held(lock, hart);
+}
+
+#[doc(hidden)]
+pub fn remove_held(lock: usize, hart: usize) {
+    LOCK_TABLE.with(|table| {
+        let held: &[HeldLock] = &table.held;
+        let position = held
+            .iter()
+            .rposition(|h| h.lock == lock && h.acquisition.owner.hart == hart)
+            .or_else(|| held.iter().rposition(|h| h.lock == lock));
+        if let Some(position) = position {
+            let last = table.held.len() - 1;
+            table.held.swap(position, last);
+            table.held.pop();
+        }
+    });
+}
+
+/// Forget everything about a lock. Must be called when a lock is
+/// destroyed because its address might be reused by another lock.
+pub fn forget(lock: usize) {
+    if !is_enabled() {
+        return;
+    }
+    forget_unconditionally(lock);
+}
+
+#[doc(hidden)]
+pub fn forget_unconditionally(lock: usize) {
+    LOCK_TABLE.with(|table| {
+        let mut index = 0;
+        while index < table.orders.len() {
+            let (first, second) = table.orders[index];
+            if first == lock || second == lock {
+                let last = table.orders.len() - 1;
+                table.orders.swap(index, last);
+                table.orders.pop();
+            } else {
+                index += 1;
+            }
+        }
+    });
+}
+
+/// Returns the current holder of `lock` if lock debugging is enabled.
+pub fn holder(lock: usize) -> Option<Acquisition> {
+    LOCK_TABLE.with(|table| {
+        table
+            .held
+            .iter()
+            .find(|h| h.lock == lock)
+            .map(|h| h.acquisition)
+    })
+}
@@ -0,0 +1,10 @@
+use crate::scalar_enum;
+
+scalar_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MapFlags {
+        ReadOnly,
+        ReadWrite,
+    }
+}
@@ -1,10 +1,14 @@
 use core::{
     cell::UnsafeCell,
     fmt::Debug,
+    mem::ManuallyDrop,
     ops::{Deref, DerefMut},
-    sync::atomic::{AtomicBool, Ordering},
+    panic::Location,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
+use crate::lock_debug;
+
 #[derive(Debug)]
 pub struct Mutex<T> {
     locked: AtomicBool,
@@ -24,15 +28,18 @@ impl<T> Mutex<T> {
         }
     }
 
+    #[track_caller]
     pub fn with_lock<'a, R>(&'a self, f: impl FnOnce(MutexGuard<'a, T>) -> R) -> R {
         let lock = self.lock();
         f(lock)
     }
 
+    #[track_caller]
     pub fn lock(&self) -> MutexGuard<T> {
         if self.disarmed.load(Ordering::SeqCst) {
             return MutexGuard { mutex: self };
         }
+        lock_debug::acquire(self.address(), Location::caller());
         while self
             .locked
             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
@@ -43,6 +50,30 @@ impl<T> Mutex<T> {
         MutexGuard { mutex: self }
     }
 
+    /// Fails instead of spinning if the lock is held. Meant for paths
+    /// which must not wait, e.g. while panicking. The acquisition is not
+    /// recorded by the lock debugging.
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self.disarmed.load(Ordering::SeqCst) {
+            return Some(MutexGuard { mutex: self });
+        }
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// Returns who currently holds the lock. Only available
+    /// if lock debugging is enabled.
+    pub fn holder(&self) -> Option<lock_debug::Acquisition> {
+        lock_debug::holder(self.address())
+    }
+
+    #[doc(hidden)]
+    pub fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+
     #[doc(hidden)]
     pub fn get_locked(&self) -> &AtomicBool {
         &self.locked
@@ -61,6 +92,12 @@ impl<T> Mutex<T> {
     }
 }
 
+impl<T> Drop for Mutex<T> {
+    fn drop(&mut self) {
+        lock_debug::forget(self.address());
+    }
+}
+
 unsafe impl<T: Send> Sync for Mutex<T> {}
 unsafe impl<T: Send> Send for Mutex<T> {}
 
@@ -70,6 +107,7 @@ pub struct MutexGuard<'a, T> {
 
 impl<T> Drop for MutexGuard<'_, T> {
     fn drop(&mut self) {
+        lock_debug::release(self.mutex.address());
         self.mutex.locked.store(false, Ordering::Release);
     }
 }
@@ -96,3 +134,303 @@ impl<T: Debug> Debug for MutexGuard<'_, T> {
         unsafe { writeln!(f, "MutexGuard {{\n{:?}\n}}", *self.mutex.data.get()) }
     }
 }
+
+/// A spin lock which disables supervisor interrupts (sstatus.SIE) while held.
+/// Use it for locks which are also taken inside interrupt handlers. Otherwise
+/// an interrupt arriving while the lock is held deadlocks the hart.
+#[derive(Debug)]
+pub struct SpinLockIrqSave<T> {
+    mutex: Mutex<T>,
+}
+
+impl<T> SpinLockIrqSave<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            mutex: Mutex::new(data),
+        }
+    }
+
+    #[track_caller]
+    pub fn with_lock<'a, R>(&'a self, f: impl FnOnce(SpinLockIrqSaveGuard<'a, T>) -> R) -> R {
+        let lock = self.lock();
+        f(lock)
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockIrqSaveGuard<T> {
+        let interrupts_were_enabled = interrupts::disable_and_save();
+        SpinLockIrqSaveGuard {
+            guard: ManuallyDrop::new(self.mutex.lock()),
+            interrupts_were_enabled,
+        }
+    }
+
+    /// See [`Mutex::try_lock`]
+    pub fn try_lock(&self) -> Option<SpinLockIrqSaveGuard<T>> {
+        let interrupts_were_enabled = interrupts::disable_and_save();
+        let Some(guard) = self.mutex.try_lock() else {
+            interrupts::restore(interrupts_were_enabled);
+            return None;
+        };
+        Some(SpinLockIrqSaveGuard {
+            guard: ManuallyDrop::new(guard),
+            interrupts_were_enabled,
+        })
+    }
+
+    pub fn holder(&self) -> Option<lock_debug::Acquisition> {
+        self.mutex.holder()
+    }
+
+    #[doc(hidden)]
+    pub fn get_locked(&self) -> &AtomicBool {
+        self.mutex.get_locked()
+    }
+
+    /// # Safety
+    /// See [`Mutex::disarm`]
+    pub unsafe fn disarm(&self) {
+        unsafe { self.mutex.disarm() }
+    }
+}
+
+pub struct SpinLockIrqSaveGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    interrupts_were_enabled: bool,
+}
+
+impl<T> Drop for SpinLockIrqSaveGuard<'_, T> {
+    fn drop(&mut self) {
+        // The lock must be released before interrupts are enabled again
+        // SAFETY: The guard is never used after this point
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+        }
+        interrupts::restore(self.interrupts_were_enabled);
+    }
+}
+
+impl<T> Deref for SpinLockIrqSaveGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SpinLockIrqSaveGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<T: Debug> Debug for SpinLockIrqSaveGuard<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        (*self.guard).fmt(f)
+    }
+}
+
+const RW_WRITER: usize = 1 << (usize::BITS - 1);
+
+/// A reader-writer spin lock for read-mostly data. Writers are preferred:
+/// as soon as a writer waits no new readers are admitted, so writers
+/// cannot starve. Like [`SpinLockIrqSave`] interrupts are disabled while
+/// a guard is alive, so it can be shared with interrupt handlers.
+/// Recursive read locking deadlocks if a writer is waiting in between.
+#[derive(Debug)]
+pub struct RwLock<T> {
+    // Highest bit is set if a writer holds the lock,
+    // the other bits count the active readers
+    state: AtomicUsize,
+    writers_waiting: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            writers_waiting: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn with_read_lock<'a, R>(&'a self, f: impl FnOnce(RwLockReadGuard<'a, T>) -> R) -> R {
+        let lock = self.read();
+        f(lock)
+    }
+
+    #[track_caller]
+    pub fn with_write_lock<'a, R>(&'a self, f: impl FnOnce(RwLockWriteGuard<'a, T>) -> R) -> R {
+        let lock = self.write();
+        f(lock)
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        let interrupts_were_enabled = interrupts::disable_and_save();
+        loop {
+            if self.writers_waiting.load(Ordering::Relaxed) > 0 {
+                core::hint::spin_loop();
+                continue;
+            }
+            let state = self.state.load(Ordering::Relaxed);
+            if state & RW_WRITER == 0
+                && self
+                    .state
+                    .compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        RwLockReadGuard {
+            lock: self,
+            interrupts_were_enabled,
+        }
+    }
+
+    /// Fails instead of waiting if a writer holds the lock or waits for it,
+    /// e.g. in a debugger which stopped the writer
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        let interrupts_were_enabled = interrupts::disable_and_save();
+        let state = self.state.load(Ordering::Relaxed);
+        if self.writers_waiting.load(Ordering::Relaxed) == 0
+            && state & RW_WRITER == 0
+            && self
+                .state
+                .compare_exchange(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            return Some(RwLockReadGuard {
+                lock: self,
+                interrupts_were_enabled,
+            });
+        }
+        interrupts::restore(interrupts_were_enabled);
+        None
+    }
+
+    #[track_caller]
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        let interrupts_were_enabled = interrupts::disable_and_save();
+        lock_debug::acquire(self.address(), Location::caller());
+        self.writers_waiting.fetch_add(1, Ordering::Relaxed);
+        while self
+            .state
+            .compare_exchange_weak(0, RW_WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        self.writers_waiting.fetch_sub(1, Ordering::Relaxed);
+        RwLockWriteGuard {
+            lock: self,
+            interrupts_were_enabled,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn readers(&self) -> usize {
+        self.state.load(Ordering::Acquire) & !RW_WRITER
+    }
+
+    #[doc(hidden)]
+    pub fn is_write_locked(&self) -> bool {
+        self.state.load(Ordering::Acquire) & RW_WRITER != 0
+    }
+
+    fn address(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl<T> Drop for RwLock<T> {
+    fn drop(&mut self) {
+        lock_debug::forget(self.address());
+    }
+}
+
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+unsafe impl<T: Send> Send for RwLock<T> {}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    interrupts_were_enabled: bool,
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+        interrupts::restore(self.interrupts_were_enabled);
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: No writer can be active while we hold a read guard
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+    interrupts_were_enabled: bool,
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        lock_debug::release(self.lock.address());
+        self.lock.state.store(0, Ordering::Release);
+        interrupts::restore(self.interrupts_were_enabled);
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: We have exclusive access to the data
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: We have exclusive access to the data
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+mod interrupts {
+    const SSTATUS_SIE: usize = 0b10;
+
+    /// Clears sstatus.SIE and returns if it was set before.
+    #[cfg(all(target_arch = "riscv64", not(miri)))]
+    pub fn disable_and_save() -> bool {
+        let sstatus: usize;
+        unsafe {
+            core::arch::asm!("csrrci {}, sstatus, {}", out(reg) sstatus, const SSTATUS_SIE);
+        }
+        sstatus & SSTATUS_SIE != 0
+    }
+
+    #[cfg(all(target_arch = "riscv64", not(miri)))]
+    pub fn restore(enabled: bool) {
+        if enabled {
+            unsafe {
+                core::arch::asm!("csrsi sstatus, {}", const SSTATUS_SIE);
+            }
+        }
+    }
+
+    #[cfg(not(all(target_arch = "riscv64", not(miri))))]
+    pub fn disable_and_save() -> bool {
+        false
+    }
+
+    #[cfg(not(all(target_arch = "riscv64", not(miri))))]
+    pub fn restore(_enabled: bool) {}
+}
@@ -6,7 +6,59 @@ impl UDPDescriptor {
         Self(fd)
     }
 
-    pub fn get(&self) -> u64 {
+    pub const fn get(&self) -> u64 {
         self.0
     }
 }
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct RawDescriptor(u64);
+
+impl RawDescriptor {
+    pub const fn new(fd: u64) -> Self {
+        Self(fd)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct TcpListenerDescriptor(u64);
+
+impl TcpListenerDescriptor {
+    pub const fn new(fd: u64) -> Self {
+        Self(fd)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct TcpStreamDescriptor(u64);
+
+impl TcpStreamDescriptor {
+    pub const fn new(fd: u64) -> Self {
+        Self(fd)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Returned by `sys_read_raw_socket`. The ethernet frame is written into
+/// the buffer of the caller and truncated if it is too small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub length: usize,
+    // Length of the frame on the wire, larger than length if truncated
+    pub frame_length: usize,
+    // Since boot
+    pub timestamp_microseconds: u64,
+    // Sent by us instead of received
+    pub outgoing: bool,
+}
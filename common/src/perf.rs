@@ -0,0 +1,114 @@
+//! Hardware counters and a helper to measure code with them. The cycle,
+//! time and instret CSRs are readable from userspace as well, they count
+//! everything that runs on the hart. `sys_perf_read` returns the part of
+//! the calling process instead.
+
+use core::fmt::Display;
+
+use crate::scalar_enum;
+
+scalar_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PerfCounter {
+        Cycles,
+        // Clocks of the time CSR
+        Time,
+        // Retired instructions
+        Instructions,
+    }
+}
+
+impl PerfCounter {
+    pub const COUNT: usize = 3;
+    pub const ALL: [PerfCounter; Self::COUNT] = [Self::Cycles, Self::Time, Self::Instructions];
+
+    /// Reads the counter of the current hart
+    #[cfg(all(target_arch = "riscv64", not(miri)))]
+    pub fn read(self) -> u64 {
+        let value: u64;
+        // SAFETY: Only reads a counter
+        unsafe {
+            match self {
+                Self::Cycles => core::arch::asm!("rdcycle {}", out(reg) value),
+                Self::Time => core::arch::asm!("rdtime {}", out(reg) value),
+                Self::Instructions => core::arch::asm!("rdinstret {}", out(reg) value),
+            }
+        }
+        value
+    }
+
+    #[cfg(not(all(target_arch = "riscv64", not(miri))))]
+    pub fn read(self) -> u64 {
+        0
+    }
+}
+
+/// Values of all counters, indexed by [`PerfCounter`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerfValues([u64; PerfCounter::COUNT]);
+
+impl PerfValues {
+    pub const ZERO: Self = Self([0; PerfCounter::COUNT]);
+
+    pub fn read() -> Self {
+        Self(PerfCounter::ALL.map(PerfCounter::read))
+    }
+
+    pub fn get(&self, counter: PerfCounter) -> u64 {
+        self.0[counter as usize]
+    }
+
+    /// Counters wrap around
+    pub fn elapsed_since(&self, earlier: &Self) -> Self {
+        Self(core::array::from_fn(|index| {
+            self.0[index].wrapping_sub(earlier.0[index])
+        }))
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        for (value, other) in self.0.iter_mut().zip(other.0) {
+            *value = value.wrapping_add(other);
+        }
+    }
+}
+
+/// Result of [`bench`], the values are the totals of all iterations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    pub iterations: u64,
+    pub total: PerfValues,
+}
+
+impl BenchResult {
+    pub fn per_iteration(&self, counter: PerfCounter) -> u64 {
+        self.total.get(counter) / self.iterations.max(1)
+    }
+}
+
+impl Display for BenchResult {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} iterations, per iteration: {} cycles, {} instructions, {} clocks",
+            self.iterations,
+            self.per_iteration(PerfCounter::Cycles),
+            self.per_iteration(PerfCounter::Instructions),
+            self.per_iteration(PerfCounter::Time)
+        )
+    }
+}
+
+/// Runs `f` `iterations` times and measures all of them together. The
+/// counters include everything else that runs on the hart meanwhile,
+/// e.g. interrupt handlers.
+pub fn bench(iterations: u64, mut f: impl FnMut()) -> BenchResult {
+    let start = PerfValues::read();
+    for _ in 0..iterations {
+        f();
+    }
+    BenchResult {
+        iterations,
+        total: PerfValues::read().elapsed_since(&start),
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{
+    net::{RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor},
+    scalar_enum,
+    tty::PtyDescriptor,
+};
+
+/// Timeout of `sys_poll` to block until a source is ready
+pub const POLL_FOREVER: u64 = u64::MAX;
+
+scalar_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PollKind {
+        // Input of the terminal of the process
+        Stdin,
+        UdpSocket,
+        // Output of the slave side which can be read by the master
+        PtyMaster,
+        RawSocket,
+        // Ready once a connection can be accepted
+        TcpListener,
+        TcpStream,
+    }
+}
+
+/// Something `sys_poll` waits for. The kernel sets `ready` if data
+/// can be read without blocking.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollSource {
+    // Plain numbers because the kernel can't trust the discriminant of
+    // a PollKind or the value of a bool
+    pub kind: usize,
+    pub descriptor: u64,
+    pub ready: usize,
+}
+
+impl PollSource {
+    const fn new(kind: PollKind, descriptor: u64) -> Self {
+        Self {
+            kind: kind as usize,
+            descriptor,
+            ready: 0,
+        }
+    }
+
+    pub const fn stdin() -> Self {
+        Self::new(PollKind::Stdin, 0)
+    }
+
+    pub const fn udp_socket(descriptor: UDPDescriptor) -> Self {
+        Self::new(PollKind::UdpSocket, descriptor.get())
+    }
+
+    pub const fn pty_master(descriptor: PtyDescriptor) -> Self {
+        Self::new(PollKind::PtyMaster, descriptor.get())
+    }
+
+    pub const fn raw_socket(descriptor: RawDescriptor) -> Self {
+        Self::new(PollKind::RawSocket, descriptor.get())
+    }
+
+    pub const fn tcp_listener(descriptor: TcpListenerDescriptor) -> Self {
+        Self::new(PollKind::TcpListener, descriptor.get())
+    }
+
+    pub const fn tcp_stream(descriptor: TcpStreamDescriptor) -> Self {
+        Self::new(PollKind::TcpStream, descriptor.get())
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready != 0
+    }
+}
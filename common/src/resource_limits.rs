@@ -0,0 +1,44 @@
+use crate::scalar_enum;
+
+scalar_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Resource {
+        // Pages mapped into the address space including the program itself
+        MappedPages,
+        // Open UDP sockets and ptys
+        OpenDescriptors,
+        // Milliseconds the process was running on any hart
+        CpuTime,
+        // Pages the main stack may grow to on page faults
+        StackPages,
+    }
+}
+
+impl Resource {
+    pub const COUNT: usize = 4;
+}
+
+pub const UNLIMITED: u64 = u64::MAX;
+
+/// Works like rlimit: The soft limit is enforced by the kernel. A process
+/// can raise it up to the hard limit but the hard limit can only be lowered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+impl ResourceLimit {
+    pub const fn new(soft: u64, hard: u64) -> Self {
+        Self { soft, hard }
+    }
+
+    pub const fn unlimited() -> Self {
+        Self::new(UNLIMITED, UNLIMITED)
+    }
+
+    pub fn allows(&self, usage: u64) -> bool {
+        usage <= self.soft
+    }
+}
@@ -0,0 +1,67 @@
+//! Arguments of `sys_spawn`, which starts a program together with its
+//! descriptors, working directory and terminal in one step.
+
+use crate::tty::PtyDescriptor;
+
+/// The terminal of SpawnOptions if the console or pty of the caller is
+/// inherited
+pub const INHERIT_TERMINAL: u64 = u64::MAX;
+
+/// The descriptor `parent` of the caller is available as `child` in the
+/// started program. Both refer to the same open file, pty or socket.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorMapping {
+    pub parent: u64,
+    pub child: u64,
+}
+
+impl DescriptorMapping {
+    pub const fn new(parent: u64, child: u64) -> Self {
+        Self { parent, child }
+    }
+}
+
+/// Plain numbers because the kernel can't trust the value of a bool
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnOptions {
+    // A pty of the caller whose slave side replaces the console
+    pub terminal: u64,
+    // Not 0 keeps the program stopped until sys_resume
+    pub suspended: usize,
+    // Not 0 lets init adopt the program right away, the caller can't
+    // wait for it
+    pub detached: usize,
+}
+
+impl SpawnOptions {
+    pub const fn new() -> Self {
+        Self {
+            terminal: INHERIT_TERMINAL,
+            suspended: 0,
+            detached: 0,
+        }
+    }
+
+    pub const fn terminal(mut self, pty: PtyDescriptor) -> Self {
+        self.terminal = pty.get();
+        self
+    }
+
+    pub const fn suspended(mut self) -> Self {
+        self.suspended = 1;
+        self
+    }
+
+    pub const fn detached(mut self) -> Self {
+        self.detached = 1;
+        self
+    }
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,8 @@
+/// Returned by `sys_symbolize`. The demangled name of the function is
+/// written into the buffer of the caller and truncated if it is too small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolInfo {
+    pub name_length: usize,
+    // Distance of the address to the start of the function
+    pub offset: usize,
+}
@@ -0,0 +1,96 @@
+use core::mem::MaybeUninit;
+
+use alloc::vec::Vec;
+
+use crate::errors::ValidationError;
+
+use super::{sys_batch, syscall_argument::SyscallTempStorage, SyscallStatus};
+
+extern crate alloc;
+
+/// One syscall of a batch. It is encoded like a single ecall: the syscall
+/// number, a pointer to the arguments and a pointer to the return value.
+/// The kernel writes the status of every executed syscall back.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallRequest {
+    pub nr: usize,
+    pub arg: usize,
+    pub ret: usize,
+    pub status: usize,
+}
+
+/// Type erased syscall such that different syscalls can be executed in
+/// the same batch
+pub trait Batchable {
+    fn request(&mut self) -> SyscallRequest;
+    fn complete(&mut self, status: SyscallStatus);
+}
+
+/// A syscall which is prepared by the functions in `syscalls::prepared`.
+/// It owns its arguments and the space for its return value.
+pub struct BatchedSyscall<A, R> {
+    nr: usize,
+    arguments: A,
+    // Keeps nested arguments alive
+    _temp_storage: SyscallTempStorage,
+    ret: MaybeUninit<R>,
+    status: SyscallStatus,
+}
+
+impl<A, R> BatchedSyscall<A, R> {
+    pub fn new(nr: usize, arguments: A, temp_storage: SyscallTempStorage) -> Self {
+        Self {
+            nr,
+            arguments,
+            _temp_storage: temp_storage,
+            ret: MaybeUninit::uninit(),
+            status: SyscallStatus::NotExecuted,
+        }
+    }
+
+    /// Returns the status instead of the return value if the syscall
+    /// was not executed successfully
+    pub fn result(self) -> Result<R, SyscallStatus> {
+        if self.status != SyscallStatus::Success {
+            return Err(self.status);
+        }
+        // SAFETY: The kernel wrote the return value
+        Ok(unsafe { self.ret.assume_init() })
+    }
+}
+
+impl<A, R> Batchable for BatchedSyscall<A, R> {
+    fn request(&mut self) -> SyscallRequest {
+        SyscallRequest {
+            nr: self.nr,
+            arg: &self.arguments as *const A as usize,
+            ret: self.ret.as_mut_ptr() as usize,
+            status: SyscallStatus::NotExecuted as usize,
+        }
+    }
+
+    fn complete(&mut self, status: SyscallStatus) {
+        self.status = status;
+    }
+}
+
+/// Executes all syscalls with a single trap into the kernel. They are
+/// executed in order and the batch stops if the process exits or a
+/// syscall would have to wait. Returns the number of executed syscalls.
+pub fn execute(syscalls: &mut [&mut dyn Batchable]) -> Result<usize, ValidationError> {
+    let mut requests: Vec<SyscallRequest> = syscalls
+        .iter_mut()
+        .map(|syscall| syscall.request())
+        .collect();
+
+    let executed = sys_batch(&mut requests)?;
+
+    for (syscall, request) in syscalls.iter_mut().zip(&requests) {
+        let status =
+            SyscallStatus::try_from(request.status).expect("Kernel must write a valid status");
+        syscall.complete(status);
+    }
+
+    Ok(executed)
+}
@@ -1,10 +1,29 @@
 use crate::{
-    errors::{SysExecuteError, SysSocketError, SysWaitError, ValidationError},
-    net::UDPDescriptor,
+    accounting::ProcessInfo,
+    credentials::{Gid, Uid},
+    errors::{
+        SysBrkError, SysEnvironmentError, SysExecuteError, SysFileError, SysHartError,
+        SysInputError, SysIrqError, SysKillError, SysMapPhysicalError, SysPageTablesError,
+        SysPeriodicError, SysPermissionError, SysPollError, SysProcessInfoError, SysProfilerError,
+        SysPtyError, SysResourceLimitError, SysResumeError, SysRouteError, SysSocketError,
+        SysSpawnError, SysSuspendError, SysSymbolizeError, SysWaitError, ValidationError,
+    },
+    fs::{FileDescriptor, FileStat},
+    mmap::MapFlags,
+    net::{
+        CapturedFrame, RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor,
+    },
+    perf::PerfCounter,
+    poll::PollSource,
+    resource_limits::{Resource, ResourceLimit},
     scalar_enum,
+    spawn::{DescriptorMapping, SpawnOptions},
+    symbols::SymbolInfo,
+    tty::{InputMode, KeyEvent, PtyDescriptor},
+    wait::{ChildExit, WaitOptions},
 };
 
-use super::macros::syscalls;
+use super::{batch::SyscallRequest, macros::syscalls};
 
 scalar_enum! {
     #[repr(usize)]
@@ -14,20 +33,152 @@ scalar_enum! {
         InvalidSyscallNumber,
         InvalidArgPtr,
         InvalidRetPtr,
+        // Blocking syscalls can't be part of a batch
+        NotAllowedInBatch,
+        NotExecuted,
     }
 }
 
+// New syscalls are appended with the next number, here and in
+// description::ASSIGNED_NUMBERS
 syscalls!(
-    sys_write<'a>(s: &'a str) -> Result<(), ValidationError>;
-    sys_read_input() -> Option<u8>;
-    sys_read_input_wait() -> u8;
-    sys_exit(status: isize) -> ();
-    sys_execute<'a>(name: &'a str, args: &'a [&'a str]) -> Result<u64, SysExecuteError>;
-    sys_wait(pid: u64) -> Result<(), SysWaitError>;
-    sys_mmap_pages(number_of_pages: usize) -> *mut u8;
-    sys_open_udp_socket(port: u16) -> Result<UDPDescriptor, SysSocketError>;
-    sys_write_back_udp_socket<'a>(descriptor: UDPDescriptor, buffer: &'a [u8]) -> Result<usize, SysSocketError>;
-    sys_read_udp_socket<'a>(descriptor: UDPDescriptor, buffer: &'a mut [u8]) -> Result<usize, SysSocketError>;
-    sys_panic() -> ();
-    sys_print_programs() -> ();
+    0 => sys_write<'a>(s: &'a str) -> Result<(), ValidationError>;
+    1 => sys_read_input() -> Option<u8>;
+    // Blocks until input arrives or the deadline passed, see deadline::NO_DEADLINE
+    2 => sys_read_input_wait(deadline: u64) -> Result<u8, SysInputError>;
+    3 => sys_exit(status: isize) -> ();
+    4 => sys_execute<'a>(name: &'a str, args: &'a [&'a str]) -> Result<u64, SysExecuteError>;
+    // Blocks until the child exited and reaps it. Only children can be waited for, other
+    // processes fail with InvalidPid. Use sys_wait_child for the exit status.
+    5 => sys_wait(pid: u64) -> Result<(), SysWaitError>;
+    6 => sys_mmap_pages(number_of_pages: usize) -> *mut u8;
+    // Port 0 assigns a free ephemeral port
+    7 => sys_open_udp_socket(port: u16) -> Result<UDPDescriptor, SysSocketError>;
+    // Writes to the connected peer or otherwise to the sender of the last datagram
+    8 => sys_write_back_udp_socket<'a>(descriptor: UDPDescriptor, buffer: &'a [u8]) -> Result<usize, SysSocketError>;
+    9 => sys_read_udp_socket<'a>(descriptor: UDPDescriptor, buffer: &'a mut [u8]) -> Result<usize, SysSocketError>;
+    10 => sys_panic() -> Result<(), SysPermissionError>;
+    11 => sys_print_programs() -> ();
+    12 => sys_sleep(milliseconds: u64) -> ();
+    13 => sys_shutdown() -> Result<(), SysPermissionError>;
+    14 => sys_reboot() -> Result<(), SysPermissionError>;
+    15 => sys_stop_hart(hart_id: usize) -> Result<(), SysHartError>;
+    16 => sys_start_hart(hart_id: usize) -> Result<(), SysHartError>;
+    17 => sys_set_input_mode(mode: InputMode) -> Result<(), ValidationError>;
+    // Blocks like sys_read_input_wait
+    18 => sys_read_key_event(deadline: u64) -> Result<KeyEvent, SysInputError>;
+    19 => sys_open_pty() -> Result<PtyDescriptor, SysPtyError>;
+    20 => sys_execute_on_pty<'a>(name: &'a str, args: &'a [&'a str], pty: PtyDescriptor) -> Result<u64, SysExecuteError>;
+    21 => sys_read_pty<'a>(pty: PtyDescriptor, buffer: &'a mut [u8]) -> Result<usize, SysPtyError>;
+    22 => sys_write_pty<'a>(pty: PtyDescriptor, buffer: &'a [u8]) -> Result<usize, SysPtyError>;
+    23 => sys_getrlimit(resource: Resource) -> Result<ResourceLimit, ValidationError>;
+    24 => sys_setrlimit(resource: Resource, limit: ResourceLimit) -> Result<(), SysResourceLimitError>;
+    25 => sys_getuid() -> Uid;
+    26 => sys_getgid() -> Gid;
+    27 => sys_setuid(uid: Uid) -> Result<(), SysPermissionError>;
+    28 => sys_setgid(gid: Gid) -> Result<(), SysPermissionError>;
+    29 => sys_kill(pid: u64) -> Result<(), SysKillError>;
+    30 => sys_batch<'a>(requests: &'a mut [SyscallRequest]) -> Result<usize, ValidationError>;
+    31 => sys_setenv<'a>(key: &'a str, value: &'a str) -> Result<(), SysEnvironmentError>;
+    32 => sys_unsetenv<'a>(key: &'a str) -> Result<(), SysEnvironmentError>;
+    33 => sys_symbolize<'a>(address: usize, name: &'a mut [u8]) -> Result<SymbolInfo, SysSymbolizeError>;
+    34 => sys_poll<'a>(sources: &'a mut [PollSource], timeout_milliseconds: u64) -> Result<usize, SysPollError>;
+    // Pid 0 selects the page tables of the kernel
+    35 => sys_print_page_tables(pid: u64) -> Result<(), SysPageTablesError>;
+    // Maps device memory for userspace drivers, RAM can't be mapped
+    36 => sys_map_physical(physical_address: usize, length: usize, flags: MapFlags) -> Result<*mut u8, SysMapPhysicalError>;
+    // Routes the interrupt to the process until it exits
+    37 => sys_irq_register(interrupt_id: u32) -> Result<(), SysIrqError>;
+    // Unmasks the interrupt and blocks until it fires or the deadline passed. Returns how
+    // often it fired.
+    38 => sys_irq_wait(interrupt_id: u32, deadline: u64) -> Result<u64, SysIrqError>;
+    // Directories can't be opened
+    39 => sys_open_file<'a>(path: &'a str) -> Result<FileDescriptor, SysFileError>;
+    // Returns 0 at the end of the file
+    40 => sys_read_file<'a>(file: FileDescriptor, buffer: &'a mut [u8]) -> Result<usize, SysFileError>;
+    41 => sys_write_file<'a>(file: FileDescriptor, buffer: &'a [u8]) -> Result<usize, SysFileError>;
+    42 => sys_close_file(file: FileDescriptor) -> Result<(), SysFileError>;
+    // One entry per line, directories end with a slash. Returns the length of the listing.
+    43 => sys_read_directory<'a>(path: &'a str, buffer: &'a mut [u8]) -> Result<usize, SysFileError>;
+    // Exposes the file as block device /dev/loopN and returns N. Only root may do this.
+    44 => sys_attach_loop<'a>(path: &'a str) -> Result<u64, SysFileError>;
+    // Writes all cached blocks back to their devices
+    45 => sys_sync() -> Result<(), SysFileError>;
+    // Mounts the block device at source on target. Only root may do this.
+    46 => sys_mount<'a>(filesystem: &'a str, source: &'a str, target: &'a str) -> Result<(), SysFileError>;
+    // Relative paths of all file syscalls start at the working directory
+    47 => sys_chdir<'a>(path: &'a str) -> Result<(), SysFileError>;
+    // Returns the length of the absolute path
+    48 => sys_getcwd<'a>(buffer: &'a mut [u8]) -> Result<usize, SysFileError>;
+    49 => sys_stat<'a>(path: &'a str) -> Result<FileStat, SysFileError>;
+    // Creates an empty file and opens it, the file must not exist
+    50 => sys_create_file<'a>(path: &'a str) -> Result<FileDescriptor, SysFileError>;
+    51 => sys_mkdir<'a>(path: &'a str) -> Result<(), SysFileError>;
+    // Removes a file or an empty directory
+    52 => sys_unlink<'a>(path: &'a str) -> Result<(), SysFileError>;
+    // The target must not exist and must be on the same filesystem
+    53 => sys_rename<'a>(from: &'a str, to: &'a str) -> Result<(), SysFileError>;
+    // Maps the file from the page aligned offset on, pages are loaded on the first access.
+    // Writes to ReadWrite mappings stay private.
+    54 => sys_mmap_file(file: FileDescriptor, offset: usize, length: usize, flags: MapFlags) -> Result<*mut u8, SysFileError>;
+    // Moves the end of the heap and returns the new break, 0 only returns the current one.
+    // The heap starts page aligned behind the program and is zeroed when it grows.
+    55 => sys_brk(new_break: usize) -> Result<usize, SysBrkError>;
+    // Reserves the budget in every period, periodic processes run before all others while they
+    // have budget left. A period of 0 returns to round robin scheduling.
+    56 => sys_set_periodic(period_milliseconds: u64, budget_milliseconds: u64) -> Result<(), SysPeriodicError>;
+    // Waits until the next period of the reservation starts
+    57 => sys_wait_period() -> Result<(), SysPeriodicError>;
+    // Addresses are IPv4 addresses in host byte order (Ipv4Addr::to_bits). Packets to the
+    // destination prefix are sent to the gateway, 0 means the prefix is on-link. The longest
+    // matching prefix wins, a route to the same prefix is replaced. Only root may do this.
+    58 => sys_route_add(destination: u32, prefix_length: u8, gateway: u32) -> Result<(), SysRouteError>;
+    // Captures copies of all ethernet frames which are received or sent from now on. Frames are
+    // dropped while the queue of the socket is full. Only root may do this.
+    59 => sys_open_raw_socket() -> Result<RawDescriptor, SysSocketError>;
+    // Returns the oldest captured frame or None if there is none yet
+    60 => sys_read_raw_socket<'a>(descriptor: RawDescriptor, buffer: &'a mut [u8]) -> Result<Option<CapturedFrame>, SysSocketError>;
+    // Addresses are IPv4 addresses in host byte order like for sys_route_add. Afterwards only
+    // datagrams of the peer are received and written back to.
+    61 => sys_connect_udp_socket(descriptor: UDPDescriptor, ip: u32, port: u16) -> Result<(), SysSocketError>;
+    62 => sys_sendto<'a>(descriptor: UDPDescriptor, ip: u32, port: u16, buffer: &'a [u8]) -> Result<usize, SysSocketError>;
+    // Pid 0 selects the calling process
+    63 => sys_process_info(pid: u64) -> Result<ProcessInfo, SysProcessInfoError>;
+    // One event counter of the kernel per line, its name and value separated by a tab. Returns
+    // the length of the listing, the buffer is left untouched if it is too small.
+    64 => sys_read_counters<'a>(buffer: &'a mut [u8]) -> Result<usize, ValidationError>;
+    // Only counts while the calling process runs, including the syscalls it makes
+    65 => sys_perf_read(counter: PerfCounter) -> Result<u64, ValidationError>;
+    // Samples all harts every interval until the profiler is stopped. The flat profile is listed
+    // in /proc/profile.
+    66 => sys_profiler_start(interval_milliseconds: u64) -> Result<(), SysProfilerError>;
+    67 => sys_profiler_stop() -> Result<(), SysProfilerError>;
+    // Reaps an exited child, ANY_CHILD selects the first one which exits. Returns None with
+    // WaitOptions::NoHang if the child didn't exit yet.
+    68 => sys_wait_child(pid: u64, options: WaitOptions) -> Result<Option<ChildExit>, SysWaitError>;
+    // Starts a program like sys_execute. Only the mapped descriptors are passed on, an empty
+    // working directory is inherited.
+    69 => sys_spawn<'a>(program: &'a str, args: &'a [&'a str], descriptors: &'a [DescriptorMapping], working_directory: &'a str, options: SpawnOptions) -> Result<u64, SysSpawnError>;
+    // Lets a process which was spawned suspended or stopped run again
+    70 => sys_resume(pid: u64) -> Result<(), SysResumeError>;
+    // The process isn't scheduled until it is resumed. Its parent notices it with
+    // WaitOptions::Untraced.
+    71 => sys_suspend(pid: u64) -> Result<(), SysSuspendError>;
+    // Blocks until the slave side wrote output or the deadline passed. Returns 0 once all
+    // programs on the slave side exited and their output was read.
+    72 => sys_read_pty_wait<'a>(pty: PtyDescriptor, buffer: &'a mut [u8], deadline: u64) -> Result<usize, SysPtyError>;
+    // Programs on the slave side get SysInputError::HungUp once all master descriptors are closed
+    73 => sys_close_pty(pty: PtyDescriptor) -> Result<(), SysPtyError>;
+    // Waits for tcp connections on the port of our address
+    74 => sys_open_tcp_listener(port: u16) -> Result<TcpListenerDescriptor, SysSocketError>;
+    // Returns None if no connection is established yet
+    75 => sys_accept_tcp_connection(listener: TcpListenerDescriptor) -> Result<Option<TcpStreamDescriptor>, SysSocketError>;
+    // Returns 0 if nothing arrived yet and SysSocketError::ConnectionClosed at the end of the stream
+    76 => sys_read_tcp_stream<'a>(stream: TcpStreamDescriptor, buffer: &'a mut [u8]) -> Result<usize, SysSocketError>;
+    // Blocks while the send buffer is full, the write is short if only a part fits
+    77 => sys_write_tcp_stream<'a>(stream: TcpStreamDescriptor, buffer: &'a [u8]) -> Result<usize, SysSocketError>;
+    // Connections which were not accepted yet are reset
+    78 => sys_close_tcp_listener(listener: TcpListenerDescriptor) -> Result<(), SysSocketError>;
+    // The peer reads the end of the stream after the buffered data
+    79 => sys_close_tcp_stream(stream: TcpStreamDescriptor) -> Result<(), SysSocketError>;
 );
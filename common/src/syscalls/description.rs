@@ -0,0 +1,160 @@
+use core::fmt;
+
+/// Name, number and signature of a syscall as written in the
+/// `syscalls!` invocation, see `definition::SYSCALLS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallDescription {
+    pub number: usize,
+    pub name: &'static str,
+    /// Name and type of every argument
+    pub arguments: &'static [(&'static str, &'static str)],
+    pub return_type: &'static str,
+}
+
+impl fmt::Display for SyscallDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>3} {}(", self.number, self.name)?;
+        for (index, (name, ty)) in self.arguments.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}: {ty}")?;
+        }
+        write!(f, ") -> {}", self.return_type)
+    }
+}
+
+/// Every syscall number which was handed out so far. Numbers are part of
+/// the ABI of programs which are already built, therefore this table is
+/// append-only: a new syscall gets the next number here and in
+/// `definition::SYSCALLS`, existing entries never change.
+pub const ASSIGNED_NUMBERS: &[(usize, &str)] = &[
+    (0, "sys_write"),
+    (1, "sys_read_input"),
+    (2, "sys_read_input_wait"),
+    (3, "sys_exit"),
+    (4, "sys_execute"),
+    (5, "sys_wait"),
+    (6, "sys_mmap_pages"),
+    (7, "sys_open_udp_socket"),
+    (8, "sys_write_back_udp_socket"),
+    (9, "sys_read_udp_socket"),
+    (10, "sys_panic"),
+    (11, "sys_print_programs"),
+    (12, "sys_sleep"),
+    (13, "sys_shutdown"),
+    (14, "sys_reboot"),
+    (15, "sys_stop_hart"),
+    (16, "sys_start_hart"),
+    (17, "sys_set_input_mode"),
+    (18, "sys_read_key_event"),
+    (19, "sys_open_pty"),
+    (20, "sys_execute_on_pty"),
+    (21, "sys_read_pty"),
+    (22, "sys_write_pty"),
+    (23, "sys_getrlimit"),
+    (24, "sys_setrlimit"),
+    (25, "sys_getuid"),
+    (26, "sys_getgid"),
+    (27, "sys_setuid"),
+    (28, "sys_setgid"),
+    (29, "sys_kill"),
+    (30, "sys_batch"),
+    (31, "sys_setenv"),
+    (32, "sys_unsetenv"),
+    (33, "sys_symbolize"),
+    (34, "sys_poll"),
+    (35, "sys_print_page_tables"),
+    (36, "sys_map_physical"),
+    (37, "sys_irq_register"),
+    (38, "sys_irq_wait"),
+    (39, "sys_open_file"),
+    (40, "sys_read_file"),
+    (41, "sys_write_file"),
+    (42, "sys_close_file"),
+    (43, "sys_read_directory"),
+    (44, "sys_attach_loop"),
+    (45, "sys_sync"),
+    (46, "sys_mount"),
+    (47, "sys_chdir"),
+    (48, "sys_getcwd"),
+    (49, "sys_stat"),
+    (50, "sys_create_file"),
+    (51, "sys_mkdir"),
+    (52, "sys_unlink"),
+    (53, "sys_rename"),
+    (54, "sys_mmap_file"),
+    (55, "sys_brk"),
+    (56, "sys_set_periodic"),
+    (57, "sys_wait_period"),
+    (58, "sys_route_add"),
+    (59, "sys_open_raw_socket"),
+    (60, "sys_read_raw_socket"),
+    (61, "sys_connect_udp_socket"),
+    (62, "sys_sendto"),
+    (63, "sys_process_info"),
+    (64, "sys_read_counters"),
+    (65, "sys_perf_read"),
+    (66, "sys_profiler_start"),
+    (67, "sys_profiler_stop"),
+    (68, "sys_wait_child"),
+    (69, "sys_spawn"),
+    (70, "sys_resume"),
+    (71, "sys_suspend"),
+    (72, "sys_read_pty_wait"),
+    (73, "sys_close_pty"),
+    (74, "sys_open_tcp_listener"),
+    (75, "sys_accept_tcp_connection"),
+    (76, "sys_read_tcp_stream"),
+    (77, "sys_write_tcp_stream"),
+    (78, "sys_close_tcp_listener"),
+    (79, "sys_close_tcp_stream"),
+];
+
+/// Used by `syscalls!` to reject renumbered, renamed or removed syscalls
+/// at compile time.
+pub const fn numbers_are_assigned(
+    syscalls: &[SyscallDescription],
+    assigned: &[(usize, &str)],
+) -> bool {
+    if syscalls.len() != assigned.len() {
+        return false;
+    }
+    let mut index = 0;
+    while index < syscalls.len() {
+        let (number, name) = assigned[index];
+        if syscalls[index].number != number || !str_equals(syscalls[index].name, name) {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+const fn str_equals(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut index = 0;
+    while index < a.len() {
+        if a[index] != b[index] {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+/// Used by `syscalls!` to reject reordered or reused numbers at compile
+/// time, see [`ASSIGNED_NUMBERS`].
+pub const fn numbers_are_ascending(syscalls: &[SyscallDescription]) -> bool {
+    let mut index = 1;
+    while index < syscalls.len() {
+        if syscalls[index].number <= syscalls[index - 1].number {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
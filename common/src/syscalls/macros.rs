@@ -1,10 +1,30 @@
 macro_rules! syscalls {
-    ($($name:ident$(<$lt:lifetime>)?($($arg_name:ident: $arg_ty:ty),*) -> $ret:ty);* $(;)?) => {
+    ($($nr:literal => $name:ident$(<$lt:lifetime>)?($($arg_name:ident: $arg_ty:ty),*) -> $ret:ty);* $(;)?) => {
         use $crate::syscalls::syscall_argument::{SyscallArgument, SyscallTempStorage};
+        use $crate::syscalls::description::{self, SyscallDescription};
+
+        /// All syscalls ordered by their number
+        pub const SYSCALLS: &[SyscallDescription] = &[
+            $(SyscallDescription {
+                number: $nr,
+                name: stringify!($name),
+                arguments: &[$((stringify!($arg_name), stringify!($arg_ty))),*],
+                return_type: stringify!($ret),
+            },)*
+        ];
+
+        const _: () = assert!(
+            description::numbers_are_ascending(SYSCALLS),
+            "Syscall numbers must not be reordered or reused"
+        );
+        const _: () = assert!(
+            description::numbers_are_assigned(SYSCALLS, description::ASSIGNED_NUMBERS),
+            "Syscall numbers must match description::ASSIGNED_NUMBERS, append new syscalls there"
+        );
         $(
             #[allow(non_camel_case_types)]
             #[derive(Debug)]
-            struct ${concat($name, Argument)}$(<$lt>)? {
+            pub struct ${concat($name, Argument)}$(<$lt>)? {
                 $(
                     pub $arg_name: <$arg_ty as SyscallArgument>::Converted,
                 )*
@@ -18,15 +38,22 @@ macro_rules! syscalls {
                 };
                 let mut ret = core::mem::MaybeUninit::<$ret>::uninit();
                 let successful: usize;
+                #[cfg(target_arch = "riscv64")]
                 unsafe {
                     core::arch::asm!(
                         "ecall",
-                        in("a0") ${index()},
+                        in("a0") $nr,
                         in("a1") &arguments,
                         in("a2") &mut ret,
                         lateout("a0") successful,
                     );
                 }
+                // Host tests of crates which use syscalls still compile
+                #[cfg(not(target_arch = "riscv64"))]
+                {
+                    let _ = (&arguments, &mut ret);
+                    successful = $crate::syscalls::SyscallStatus::NotExecuted as usize;
+                }
                 let status = $crate::syscalls::SyscallStatus::try_from(successful);
 
                 if status != Ok($crate::syscalls::SyscallStatus::Success) {
@@ -39,35 +66,77 @@ macro_rules! syscalls {
         )*
 
 
+        /// Syscalls which are prepared to be executed with `batch::execute`
+        pub mod prepared {
+            use super::*;
+            use $crate::syscalls::batch::BatchedSyscall;
+
+            $(
+                pub fn $name$(<$lt>)?($($arg_name: $arg_ty),*) -> BatchedSyscall<${concat($name, Argument)}$(<$lt>)?, $ret> {
+                    #[allow(unused_mut)]
+                    let mut temp_storage = SyscallTempStorage::default();
+                    let arguments = ${concat($name, Argument)} {
+                      $($arg_name: $arg_name.convert(&mut temp_storage),)*
+                    };
+                    BatchedSyscall::new($nr, arguments, temp_storage)
+                }
+            )*
+        }
+
+        pub mod numbers {
+            $(
+                #[allow(non_upper_case_globals)]
+                pub const $name: usize = $nr;
+            )*
+        }
+
         pub mod kernel {
             use super::*;
-            use $crate::constructable::Constructable;
+            use $crate::syscalls::syscall_argument::{ArgumentScope, SyscallReturn, UserspaceMemory};
 
-            pub trait KernelSyscalls {
+            /// Arguments which point into the memory of the process are
+            /// translated before the handler is called, see
+            /// `SyscallArgument::hand`. If that fails the syscall returns
+            /// the error without calling the handler. The translated
+            /// arguments live as long as the `ArgumentScope` of the syscall.
+            pub trait KernelSyscalls: UserspaceMemory + Sized {
 
                 type ArgWrapper<T: SyscallArgument>: $crate::constructable::Constructable<T::Converted>;
 
                 // Syscall functions
-                $(fn $name$(<$lt>)?(&mut self, $($arg_name: Self::ArgWrapper<$arg_ty>),*) -> $ret;)*
-
-                /// Validate a pointer such that it is a valid userspace pointer
-                fn validate_and_translate_pointer<PTR: $crate::pointer::Pointer>(&self, ptr: PTR) -> Option<PTR>;
+                // Syscalls without pointer arguments don't use 's
+                $(#[allow(clippy::extra_unused_lifetimes, clippy::needless_lifetimes)]
+                fn $name<'s, $($lt)?>(&mut self, $($arg_name: <$arg_ty as SyscallArgument>::Handed<'s, Self::ArgWrapper<$arg_ty>>),*) -> $ret;)*
 
                 fn dispatch(&mut self, nr: usize, arg: usize, ret: usize) -> $crate::syscalls::SyscallStatus {
                     use $crate::syscalls::SyscallStatus;
                     match nr {
-                        $(${index()} => {
-                            let arg_ptr = $crate::unwrap_or_return!(self.validate_and_translate_pointer(arg as *mut ${concat($name, Argument)}), SyscallStatus::InvalidArgPtr);
+                        $($nr => {
+                            let mut scope = ArgumentScope::new(self.owner());
+                            let arg_ptr = $crate::unwrap_or_return!(scope.translate_struct(self, arg as *const ${concat($name, Argument)}).ok(), SyscallStatus::InvalidArgPtr);
 
-                            let ret_ptr = $crate::unwrap_or_return!(self.validate_and_translate_pointer(ret as *mut core::mem::MaybeUninit::<$ret>), SyscallStatus::InvalidRetPtr);
-                            // SAFETY: We just validated the pointers
+                            let ret_ptr = $crate::unwrap_or_return!(scope.translate_struct(self, ret as *mut core::mem::MaybeUninit::<$ret>).ok(), SyscallStatus::InvalidRetPtr);
+                            // SAFETY: We just validated the pointers and the
+                            // scope keeps copies of them alive
                             let (arg_ref, ret_ref) = unsafe {
                                 (&*arg_ptr, &mut *ret_ptr)
                             };
-                            ret_ref.write(self.$name($(Self::ArgWrapper::new(arg_ref.$arg_name)),*));
-                            $crate::syscalls::SyscallStatus::Success
+                            // Names the lifetime of the arguments
+                            fn call<$($lt,)? H: KernelSyscalls>(handler: &mut H, arguments: &${concat($name, Argument)}$(<$lt>)?, scope: &ArgumentScope<H::Owner>) -> $ret {
+                                $(
+                                    let $arg_name = match <$arg_ty as SyscallArgument>::hand::<H::ArgWrapper<$arg_ty>, H>(arguments.$arg_name, handler, scope) {
+                                        Ok(argument) => argument,
+                                        Err(error) => return <$ret as SyscallReturn<_>>::from_error(error),
+                                    };
+                                )*
+                                handler.$name($($arg_name),*)
+                            }
+                            let result = call(self, arg_ref, &scope);
+                            ret_ref.write(result);
+                            scope.write_back(self);
+                            SyscallStatus::Success
                         })*
-                        _ => $crate::syscalls::SyscallStatus::InvalidSyscallNumber
+                        _ => SyscallStatus::InvalidSyscallNumber
                     }
                 }
             }
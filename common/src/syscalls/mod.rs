@@ -1,4 +1,6 @@
+pub mod batch;
 pub mod definition;
+pub mod description;
 mod macros;
 pub mod syscall_argument;
 pub mod trap_frame;
@@ -1,6 +1,27 @@
-use core::any::Any;
+use core::{
+    any::Any,
+    cell::RefCell,
+    convert::Infallible,
+    mem,
+    ops::{Deref, Range},
+};
 
-use crate::{net::UDPDescriptor, numbers::Number, pointer::FatPointer};
+use crate::{
+    constructable::Constructable,
+    errors::ValidationError,
+    fs::FileDescriptor,
+    mmap::MapFlags,
+    net::{RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor},
+    numbers::Number,
+    perf::PerfCounter,
+    pointer::{FatPointer, Pointer},
+    poll::PollSource,
+    resource_limits::{Resource, ResourceLimit},
+    spawn::{DescriptorMapping, SpawnOptions},
+    syscalls::batch::SyscallRequest,
+    tty::{InputMode, PtyDescriptor},
+    wait::WaitOptions,
+};
 use alloc::{boxed::Box, vec::Vec};
 
 extern crate alloc;
@@ -22,10 +43,268 @@ impl SyscallTempStorage {
     }
 }
 
+/// Access to the memory of the process which makes the syscall
+pub trait UserspaceMemory {
+    /// Keeps the memory alive, e.g. a reference to the process
+    type Owner;
+
+    fn owner(&self) -> Self::Owner;
+
+    /// Returns the address under which the kernel can access the `len`
+    /// elements at `ptr` in one piece. None if the process can't access
+    /// them or they are spread over physical memory.
+    fn translate<PTR: Pointer>(&mut self, ptr: PTR, len: usize) -> Option<PTR>;
+
+    /// Copies the memory at `address` page by page into `buffer`. Returns
+    /// false if the process can't read it.
+    fn copy_from(&mut self, address: usize, buffer: &mut [u8]) -> bool;
+
+    /// Copies `data` page by page to `address`. Returns false if the
+    /// process can't write there.
+    fn copy_to(&mut self, address: usize, data: &[u8]) -> bool;
+}
+
+/// Buffers which are spread over physical memory are copied, the limit
+/// keeps a process from exhausting the heap of the kernel
+pub const MAX_BOUNCE_SIZE: usize = 1 << 20;
+
+/// A copy of a buffer which isn't in one piece of physical memory
+struct Bounce {
+    address: usize,
+    size: usize,
+    writable: bool,
+    // u64 to satisfy the alignment of every argument type
+    data: Vec<u64>,
+}
+
+impl Bounce {
+    fn bytes(&mut self) -> &mut [u8] {
+        // SAFETY: The data covers at least size bytes
+        unsafe { core::slice::from_raw_parts_mut(self.data.as_mut_ptr().cast::<u8>(), self.size) }
+    }
+}
+
+/// Created for every syscall. The arguments which point into the memory
+/// of the process borrow it, therefore they can't outlive the syscall or
+/// the owner of the memory. Remembers the handed buffers such that a
+/// mutable one can't alias another argument.
+pub struct ArgumentScope<O> {
+    _owner: O,
+    // Virtual address ranges and whether they are writable
+    buffers: RefCell<Vec<(Range<usize>, bool)>>,
+    bounces: RefCell<Vec<Bounce>>,
+}
+
+impl<O> ArgumentScope<O> {
+    pub fn new(owner: O) -> Self {
+        Self {
+            _owner: owner,
+            buffers: RefCell::new(Vec::new()),
+            bounces: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Copies the mutable buffers which were spread over physical memory
+    /// back to the process. Must be called after the handler returned.
+    pub fn write_back<M: UserspaceMemory<Owner = O>>(&mut self, memory: &mut M) {
+        for bounce in self.bounces.get_mut().iter_mut() {
+            if bounce.writable {
+                // The process might have unmapped the buffer meanwhile
+                let _ = memory.copy_to(bounce.address, bounce.bytes());
+            }
+        }
+    }
+
+    /// Returns the address of a copy of the buffer
+    fn bounce<M: UserspaceMemory>(
+        &self,
+        memory: &mut M,
+        range: Range<usize>,
+        writable: bool,
+    ) -> Result<usize, ValidationError> {
+        if range.len() > MAX_BOUNCE_SIZE {
+            return Err(ValidationError::InvalidPtr);
+        }
+        let mut bounce = Bounce {
+            address: range.start,
+            size: range.len(),
+            writable,
+            data: alloc::vec![0; range.len().div_ceil(mem::size_of::<u64>())],
+        };
+        // Mutable buffers are copied as well, the handler might not
+        // overwrite all of it
+        if !memory.copy_from(range.start, bounce.bytes()) {
+            return Err(ValidationError::InvalidPtr);
+        }
+        // Moving the bounce doesn't move its data
+        let address = bounce.data.as_ptr() as usize;
+        self.bounces.borrow_mut().push(bounce);
+        Ok(address)
+    }
+
+    /// Translates the struct which holds the arguments or the return value
+    /// of the syscall like a buffer. Therefore no argument can alias the
+    /// return value, which is written after the handler returned.
+    pub fn translate_struct<PTR: Pointer, M: UserspaceMemory<Owner = O>>(
+        &self,
+        memory: &mut M,
+        ptr: PTR,
+    ) -> Result<PTR, ValidationError> {
+        translate_buffer(FatPointer::new(ptr, 1), memory, self)
+    }
+
+    fn add_buffer(&self, range: Range<usize>, writable: bool) -> Result<(), ValidationError> {
+        let mut buffers = self.buffers.borrow_mut();
+        let aliased = buffers.iter().any(|(other, other_writable)| {
+            (writable || *other_writable) && range.start < other.end && other.start < range.end
+        });
+        if aliased {
+            return Err(ValidationError::InvalidPtr);
+        }
+        buffers.push((range, writable));
+        Ok(())
+    }
+}
+
 pub trait SyscallArgument {
     type Converted: Copy + Clone;
+    /// What the syscall handler of the kernel receives. Values are
+    /// wrapped in `W` and validated by the handler, arguments which
+    /// point into the memory of the process are translated beforehand
+    /// and live as long as the `ArgumentScope`.
+    type Handed<'s, W>;
+    type Error;
 
     fn convert(self, storage: &mut SyscallTempStorage) -> Self::Converted;
+
+    fn hand<'s, W: Constructable<Self::Converted>, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<Self::Handed<'s, W>, Self::Error>;
+}
+
+/// A syscall whose arguments can't be translated returns the error
+/// instead of calling the handler. Therefore syscalls which take
+/// pointers must return a Result whose error includes ValidationError.
+pub trait SyscallReturn<E> {
+    fn from_error(error: E) -> Self;
+}
+
+impl<R> SyscallReturn<Infallible> for R {
+    fn from_error(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+impl<T, E: From<ValidationError>> SyscallReturn<ValidationError> for Result<T, E> {
+    fn from_error(error: ValidationError) -> Self {
+        Err(error.into())
+    }
+}
+
+/// Plain values don't need a translation
+macro_rules! by_value {
+    () => {
+        type Handed<'s, W> = W;
+        type Error = Infallible;
+
+        fn hand<W: Constructable<Self::Converted>, M: UserspaceMemory>(
+            value: Self::Converted,
+            _memory: &mut M,
+            _scope: &ArgumentScope<M::Owner>,
+        ) -> Result<W, Infallible> {
+            Ok(W::new(value))
+        }
+    };
+}
+
+/// Checks which apply to every buffer of the process. Empty buffers
+/// don't need to point anywhere.
+fn translate_buffer<PTR: Pointer, M: UserspaceMemory>(
+    buffer: FatPointer<PTR>,
+    memory: &mut M,
+    scope: &ArgumentScope<M::Owner>,
+) -> Result<PTR, ValidationError> {
+    let alignment = mem::align_of::<PTR::Pointee>();
+    // Slices must not be larger than isize::MAX bytes
+    let size = buffer
+        .len()
+        .checked_mul(mem::size_of::<PTR::Pointee>())
+        .filter(|size| isize::try_from(*size).is_ok())
+        .ok_or(ValidationError::InvalidPtr)?;
+    if size == 0 {
+        return Ok(PTR::as_pointer(alignment));
+    }
+    let start = buffer.ptr().as_raw();
+    let end = start.checked_add(size).ok_or(ValidationError::InvalidPtr)?;
+    if start % alignment != 0 {
+        return Err(ValidationError::InvalidPtr);
+    }
+    assert!(
+        alignment <= mem::align_of::<u64>(),
+        "Bounces must satisfy the alignment"
+    );
+    scope.add_buffer(start..end, PTR::WRITABLE)?;
+    match memory.translate(buffer.ptr(), buffer.len()) {
+        Some(translated) => Ok(translated),
+        None => scope
+            .bounce(memory, start..end, PTR::WRITABLE)
+            .map(PTR::as_pointer),
+    }
+}
+
+fn translate_slice<'s, T, M: UserspaceMemory>(
+    buffer: FatPointer<*const T>,
+    memory: &mut M,
+    scope: &'s ArgumentScope<M::Owner>,
+) -> Result<&'s [T], ValidationError> {
+    let ptr = translate_buffer(buffer, memory, scope)?;
+    // SAFETY: The process may access the translated buffer and the scope
+    // keeps its memory alive
+    Ok(unsafe { core::slice::from_raw_parts(ptr, buffer.len()) })
+}
+
+fn translate_slice_mut<'s, T, M: UserspaceMemory>(
+    buffer: FatPointer<*mut T>,
+    memory: &mut M,
+    scope: &'s ArgumentScope<M::Owner>,
+) -> Result<&'s mut [T], ValidationError> {
+    let ptr = translate_buffer(buffer, memory, scope)?;
+    // SAFETY: The process may write the translated buffer and the scope
+    // made sure that no other argument aliases it
+    Ok(unsafe { core::slice::from_raw_parts_mut(ptr, buffer.len()) })
+}
+
+fn translate_str<'s, M: UserspaceMemory>(
+    buffer: FatPointer<*const u8>,
+    memory: &mut M,
+    scope: &'s ArgumentScope<M::Owner>,
+) -> Result<&'s str, ValidationError> {
+    core::str::from_utf8(translate_slice(buffer, memory, scope)?)
+        .map_err(|_| ValidationError::InvalidValue)
+}
+
+/// A translated buffer which remembers where it is in the process, e.g.
+/// such that the kernel can pin its pages.
+#[derive(Debug)]
+pub struct UserspaceSlice<'a, T> {
+    data: &'a [T],
+    address: usize,
+}
+
+impl<T> UserspaceSlice<'_, T> {
+    pub fn address(&self) -> usize {
+        self.address
+    }
+}
+
+impl<T> Deref for UserspaceSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
 }
 
 impl<T: Number> SyscallArgument for T {
@@ -34,6 +313,8 @@ impl<T: Number> SyscallArgument for T {
     fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
         self
     }
+
+    by_value!();
 }
 
 impl SyscallArgument for char {
@@ -42,34 +323,71 @@ impl SyscallArgument for char {
     fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
         self
     }
+
+    by_value!();
 }
 
 impl SyscallArgument for &str {
     type Converted = FatPointer<*const u8>;
+    type Handed<'s, W> = &'s str;
+    type Error = ValidationError;
 
     fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
         FatPointer::new(self.as_ptr(), self.len())
     }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<&'s str, ValidationError> {
+        translate_str(value, memory, scope)
+    }
 }
 
-impl<T: Number> SyscallArgument for &[T] {
+impl<T: Number + 'static> SyscallArgument for &[T] {
     type Converted = FatPointer<*const T>;
+    type Handed<'s, W> = UserspaceSlice<'s, T>;
+    type Error = ValidationError;
 
     fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
         FatPointer::new(self.as_ptr(), self.len())
     }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<UserspaceSlice<'s, T>, ValidationError> {
+        Ok(UserspaceSlice {
+            data: translate_slice(value, memory, scope)?,
+            address: value.ptr() as usize,
+        })
+    }
 }
 
-impl<T: Number> SyscallArgument for &mut [T] {
+impl<T: Number + 'static> SyscallArgument for &mut [T] {
     type Converted = FatPointer<*mut T>;
+    type Handed<'s, W> = &'s mut [T];
+    type Error = ValidationError;
 
     fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
         FatPointer::new(self.as_mut_ptr(), self.len())
     }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<&'s mut [T], ValidationError> {
+        translate_slice_mut(value, memory, scope)
+    }
 }
 
-impl<'a> SyscallArgument for &'a [&'a str] {
+impl SyscallArgument for &[&str] {
     type Converted = FatPointer<*const FatPointer<*const u8>>;
+    type Handed<'s, W> = Vec<&'s str>;
+    type Error = ValidationError;
 
     fn convert(self, storage: &mut SyscallTempStorage) -> Self::Converted {
         let temp_vec: Vec<FatPointer<*const u8>> = self
@@ -82,6 +400,17 @@ impl<'a> SyscallArgument for &'a [&'a str] {
 
         converted
     }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<Vec<&'s str>, ValidationError> {
+        translate_slice(value, memory, scope)?
+            .iter()
+            .map(|s| translate_str(*s, memory, scope))
+            .collect()
+    }
 }
 
 impl SyscallArgument for UDPDescriptor {
@@ -90,4 +419,185 @@ impl SyscallArgument for UDPDescriptor {
     fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
         self
     }
+
+    by_value!();
+}
+
+impl SyscallArgument for RawDescriptor {
+    type Converted = RawDescriptor;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for TcpListenerDescriptor {
+    type Converted = TcpListenerDescriptor;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for TcpStreamDescriptor {
+    type Converted = TcpStreamDescriptor;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for InputMode {
+    // Passed as plain number because the kernel can't trust the discriminant
+    type Converted = usize;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self as usize
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for PtyDescriptor {
+    type Converted = PtyDescriptor;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for FileDescriptor {
+    type Converted = FileDescriptor;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for Resource {
+    // Passed as plain number because the kernel can't trust the discriminant
+    type Converted = usize;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self as usize
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for PerfCounter {
+    // Passed as plain number because the kernel can't trust the discriminant
+    type Converted = usize;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self as usize
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for MapFlags {
+    // Passed as plain number because the kernel can't trust the discriminant
+    type Converted = usize;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self as usize
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for WaitOptions {
+    // Passed as plain number because the kernel can't trust the discriminant
+    type Converted = usize;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self as usize
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for ResourceLimit {
+    type Converted = ResourceLimit;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for SpawnOptions {
+    type Converted = SpawnOptions;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        self
+    }
+
+    by_value!();
+}
+
+impl SyscallArgument for &[DescriptorMapping] {
+    type Converted = FatPointer<*const DescriptorMapping>;
+    type Handed<'s, W> = &'s [DescriptorMapping];
+    type Error = ValidationError;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        FatPointer::new(self.as_ptr(), self.len())
+    }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<&'s [DescriptorMapping], ValidationError> {
+        translate_slice(value, memory, scope)
+    }
+}
+
+impl SyscallArgument for &mut [SyscallRequest] {
+    type Converted = FatPointer<*mut SyscallRequest>;
+    type Handed<'s, W> = &'s mut [SyscallRequest];
+    type Error = ValidationError;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        FatPointer::new(self.as_mut_ptr(), self.len())
+    }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<&'s mut [SyscallRequest], ValidationError> {
+        translate_slice_mut(value, memory, scope)
+    }
+}
+
+impl SyscallArgument for &mut [PollSource] {
+    type Converted = FatPointer<*mut PollSource>;
+    type Handed<'s, W> = &'s mut [PollSource];
+    type Error = ValidationError;
+
+    fn convert(self, _storage: &mut SyscallTempStorage) -> Self::Converted {
+        FatPointer::new(self.as_mut_ptr(), self.len())
+    }
+
+    fn hand<'s, W, M: UserspaceMemory>(
+        value: Self::Converted,
+        memory: &mut M,
+        scope: &'s ArgumentScope<M::Owner>,
+    ) -> Result<&'s mut [PollSource], ValidationError> {
+        translate_slice_mut(value, memory, scope)
+    }
 }
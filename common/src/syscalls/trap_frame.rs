@@ -5,6 +5,8 @@ use core::fmt::Debug;
 pub struct TrapFrame {
     registers: [usize; 32],
     floating_registers: [usize; 32],
+    // fcsr, only saved together with the floating point registers
+    floating_point_csr: usize,
 }
 
 impl Debug for TrapFrame {
@@ -137,6 +139,23 @@ impl TrapFrame {
         Self {
             registers: [0; 32],
             floating_registers: [0; 32],
+            floating_point_csr: 0,
         }
     }
+
+    pub fn registers(&self) -> &[usize; 32] {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [usize; 32] {
+        &mut self.registers
+    }
+
+    pub fn floating_registers(&self) -> &[usize; 32] {
+        &self.floating_registers
+    }
+
+    pub fn floating_point_csr(&self) -> usize {
+        self.floating_point_csr
+    }
 }
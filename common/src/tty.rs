@@ -0,0 +1,45 @@
+use crate::scalar_enum;
+
+scalar_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum InputMode {
+        // Line buffered with echo and line editing done by the kernel
+        Cooked,
+        // Every byte is delivered immediately without echo
+        Raw,
+    }
+}
+
+/// A decoded key press of the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Character(u8),
+    // Holds the lowercase letter, e.g. b'c' for Ctrl-C
+    Control(u8),
+    // Escape followed by a character. Terminals send this for Alt+<key>.
+    Alt(u8),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub struct PtyDescriptor(u64);
+
+impl PtyDescriptor {
+    pub const fn new(fd: u64) -> Self {
+        Self(fd)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,24 @@
+/// Userspace address of the vDSO page
+pub const VDSO_ADDRESS: usize = 0x1000000000;
+
+/// Bits of [`VdsoData::cpu_features`]
+pub const CPU_FEATURE_VECTOR: u64 = 1 << 0;
+
+/// Content of the vDSO page. The kernel maps it read-only into every
+/// process. Together with the time CSR, which is readable from user mode,
+/// programs get the time without a syscall.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct VdsoData {
+    // Clocks of the time CSR per second
+    pub timebase_frequency: u64,
+    // Nanoseconds since the unix epoch at epoch_clocks or 0 without a RTC
+    pub epoch_nanoseconds: u64,
+    pub epoch_clocks: u64,
+    pub number_of_harts: u64,
+    pub pid: u64,
+    // Updated by the kernel whenever the process is scheduled
+    pub hart_id: u64,
+    // Extensions which are usable from userspace, see CPU_FEATURE_*
+    pub cpu_features: u64,
+}
@@ -0,0 +1,41 @@
+//! Exit statuses of child processes. A child which exits stays a zombie
+//! until its parent waits for it, children of a parent which exits are
+//! adopted by init. Stopped children are reported once with
+//! WaitOptions::Untraced.
+
+use crate::scalar_enum;
+
+/// Selects any child in `sys_wait_child`
+pub const ANY_CHILD: u64 = 0;
+
+scalar_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum WaitOptions {
+        // Blocks until a child exited
+        Block,
+        // Returns None if no child exited yet, like WNOHANG
+        NoHang,
+        // Blocks until a child exited or was stopped, like WUNTRACED.
+        // Stopped children aren't reaped.
+        Untraced,
+    }
+}
+
+/// How a process ended or why it doesn't run anymore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    // Passed to sys_exit or returned from main
+    Exited(isize),
+    // By sys_kill, Ctrl+C or the kernel, e.g. after a crash
+    Killed,
+    // By sys_suspend or Ctrl+Z, only reported with WaitOptions::Untraced
+    Stopped,
+}
+
+/// Returned by `sys_wait_child`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildExit {
+    pub pid: u64,
+    pub status: ExitStatus,
+}
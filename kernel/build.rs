@@ -4,6 +4,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo:rerun-if-changed=qemu.ld");
     println!("cargo:rerun-if-changed=../userspace/");
     println!("cargo:rerun-if-changed=../common/");
+    println!("cargo:rerun-if-changed=../libc/");
+    println!("cargo:rerun-if-env-changed=RISCV_CC");
     println!("cargo:rustc-link-arg-bin=kernel=-Tkernel/qemu.ld");
 
     if is_miri_execution() {
@@ -11,7 +13,9 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     build_userspace_programs()?;
+    build_c_programs()?;
     generate_userspace_programs_include()?;
+    generate_initramfs()?;
     Ok(())
 }
 
@@ -71,6 +75,127 @@ fn generate_userspace_programs_include() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Writes the userspace programs into /bin and the configuration from
+/// userspace/etc into /etc of a cpio archive in the "new ASCII" format.
+/// qemu_wrapper.sh passes it to QEMU as initrd.
+fn generate_initramfs() -> Result<(), Box<dyn Error>> {
+    const INITRAMFS_PATH: &str = "../kernel/initramfs.cpio";
+
+    fn append_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let fields = [
+            0,
+            mode as usize,
+            0,
+            0,
+            1,
+            0,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0,
+        ];
+        archive.extend_from_slice(b"070701");
+        for field in fields {
+            archive.extend_from_slice(format!("{field:08x}").as_bytes());
+        }
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    let mut programs = Vec::new();
+    for entry in std::fs::read_dir("../kernel/compiled_userspace")? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+        programs.push((name, path));
+    }
+    programs.sort();
+
+    let mut archive = Vec::new();
+    append_entry(&mut archive, "bin", 0o040755, &[]);
+    for (name, path) in programs {
+        let data = std::fs::read(path)?;
+        append_entry(&mut archive, &format!("bin/{name}"), 0o100755, &data);
+    }
+    append_entry(&mut archive, "etc", 0o040755, &[]);
+    append_entry(
+        &mut archive,
+        "etc/services",
+        0o100644,
+        &std::fs::read("../userspace/etc/services")?,
+    );
+    append_entry(&mut archive, "TRAILER!!!", 0, &[]);
+
+    std::fs::write(INITRAMFS_PATH, archive)?;
+    Ok(())
+}
+
+/// C programs are only built with the c-programs feature because they
+/// need a RISC-V C compiler. The compiler can be set with RISCV_CC.
+fn build_c_programs() -> Result<(), Box<dyn Error>> {
+    if env::var_os("CARGO_FEATURE_C_PROGRAMS").is_none() {
+        return Ok(());
+    }
+    let compiler = env::var("RISCV_CC").unwrap_or_else(|_| "riscv64-linux-gnu-gcc".into());
+    if Command::new(&compiler).arg("--version").output().is_err() {
+        return Err(From::from(format!(
+            "The c-programs feature needs the C compiler {compiler}, set RISCV_CC to use another one"
+        )));
+    }
+
+    let status = Command::new("cargo")
+        .current_dir("../libc")
+        .env("CARGO_ENCODED_RUSTFLAGS", "-Cforce-frame-pointers=yes")
+        .args([
+            "build",
+            "--target-dir",
+            "../../target-userspace",
+            "--release",
+        ])
+        .status()?;
+    if !status.success() {
+        return Err(From::from("Failed to build libc"));
+    }
+
+    for entry in std::fs::read_dir("../libc/programs")? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "c") {
+            continue;
+        }
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let status = Command::new(&compiler)
+            .args([
+                "-march=rv64gc",
+                "-mabi=lp64d",
+                "-mcmodel=medany",
+                "-O2",
+                "-fno-omit-frame-pointer",
+                "-ffreestanding",
+                "-fno-pie",
+                "-no-pie",
+                "-static",
+                "-nostdlib",
+                "-I../libc/include",
+                "-T../userspace/userspace.ld",
+                "-o",
+                &format!("../kernel/compiled_userspace/{name}"),
+                path.to_str().unwrap(),
+                "../../target-userspace/riscv64gc-unknown-none-elf/release/liblibc.a",
+            ])
+            .status()?;
+        if !status.success() {
+            return Err(From::from(format!("Failed to build C program {name}")));
+        }
+    }
+
+    Ok(())
+}
+
 fn build_userspace_programs() -> Result<(), Box<dyn Error>> {
     let compiled_userspace_path = Path::new("../kernel/compiled_userspace");
 
@@ -78,6 +203,8 @@ fn build_userspace_programs() -> Result<(), Box<dyn Error>> {
 
     let mut command = Command::new("cargo");
     command.current_dir("../userspace");
+    // Panics in userspace print a backtrace by following the frame pointers
+    command.env("CARGO_ENCODED_RUSTFLAGS", "-Cforce-frame-pointers=yes");
 
     command.args([
         "build",
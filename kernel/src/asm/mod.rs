@@ -3,7 +3,7 @@ use core::arch::{asm, global_asm};
 use crate::cpu;
 
 global_asm!(include_str!("boot.S"), KERNEL_PAGE_TABLES_SATP_OFFSET = const cpu::KERNEL_PAGE_TABLES_SATP_OFFSET);
-global_asm!(include_str!("trap.S"), TRAP_FRAME_OFFSET = const cpu::TRAP_FRAME_OFFSET, KERNEL_PAGE_TABLES_SATP_OFFSET = const cpu::KERNEL_PAGE_TABLES_SATP_OFFSET);
+global_asm!(include_str!("trap.S"), TRAP_FRAME_OFFSET = const cpu::TRAP_FRAME_OFFSET, KERNEL_PAGE_TABLES_SATP_OFFSET = const cpu::KERNEL_PAGE_TABLES_SATP_OFFSET, KERNEL_BREAKPOINT_TRAP_FRAME_OFFSET = const cpu::KERNEL_BREAKPOINT_TRAP_FRAME_OFFSET, INTERRUPT_NESTING_OFFSET = const cpu::INTERRUPT_NESTING_OFFSET, TRAP_SCRATCH_OFFSET = const cpu::TRAP_SCRATCH_OFFSET);
 global_asm!(include_str!("powersave.S"));
 global_asm!(include_str!("panic.S"));
 
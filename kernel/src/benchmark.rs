@@ -0,0 +1,179 @@
+//! Microbenchmarks of the scheduler and the synchronization primitives.
+//! They are built with the benchmark feature and run in a kernel thread
+//! once the harts are scheduling. Every result is printed as one line
+//! which starts with [`RESULT_PREFIX`] followed by a JSON object, such
+//! that CI can extract and track them. QEMU exits afterwards.
+//!
+//! The costs are measured with the cycle CSR. Under QEMU a cycle is
+//! not a real cycle, the numbers are only comparable between runs on
+//! the same machine.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::{string::String, vec::Vec};
+use common::{mutex::Mutex, perf::PerfCounter, runtime_initialized::RuntimeInitializedData};
+
+use crate::{
+    cpu::Cpu,
+    info, println,
+    processes::{hotplug, kthread, process::Pid},
+    test::qemu_exit,
+};
+
+const RESULT_PREFIX: &str = "BENCHMARK ";
+
+const CONTEXT_SWITCH_ITERATIONS: usize = 1000;
+const TRAP_ITERATIONS: usize = 10_000;
+const MUTEX_ITERATIONS: usize = 10_000;
+
+static RUNNER: RuntimeInitializedData<Pid> = RuntimeInitializedData::new();
+
+struct Measurement {
+    name: &'static str,
+    threads: usize,
+    samples: Vec<u64>,
+}
+
+impl Measurement {
+    fn new(name: &'static str, threads: usize) -> Self {
+        Self {
+            name,
+            threads,
+            samples: Vec::new(),
+        }
+    }
+
+    fn measure(&mut self, f: impl FnOnce()) {
+        let start = PerfCounter::Cycles.read();
+        f();
+        self.samples.push(PerfCounter::Cycles.read() - start);
+    }
+
+    fn report(self) {
+        println!("{}", self.result());
+    }
+
+    fn result(mut self) -> String {
+        self.samples.sort_unstable();
+        let samples = self.samples.len();
+        let total: u64 = self.samples.iter().sum();
+        format!(
+            "{RESULT_PREFIX}{{\"name\":\"{}\",\"threads\":{},\"samples\":{},\"min_cycles\":{},\"median_cycles\":{},\"mean_cycles\":{}}}",
+            self.name,
+            self.threads,
+            samples,
+            self.samples.first().copied().unwrap_or(0),
+            self.samples.get(samples / 2).copied().unwrap_or(0),
+            total / samples.max(1) as u64,
+        )
+    }
+}
+
+/// Starts the benchmarks in a kernel thread
+pub fn start() {
+    RUNNER.initialize(kthread::spawn("benchmark", run));
+}
+
+extern "C" fn run() -> ! {
+    info!("Running benchmarks");
+    trap_round_trip();
+    context_switch();
+    mutex(1);
+    mutex(hotplug::online_harts().count_ones() as usize);
+    info!("Benchmarks done");
+    qemu_exit::exit_success();
+}
+
+/// A software interrupt on the current hart takes the same trap entry
+/// and exit path as a syscall
+fn trap_round_trip() {
+    let mut measurement = Measurement::new("trap_round_trip", 1);
+    for _ in 0..TRAP_ITERATIONS {
+        measurement.measure(Cpu::raise_software_interrupt);
+    }
+    measurement.report();
+}
+
+static PONG: RuntimeInitializedData<Pid> = RuntimeInitializedData::new();
+static PONGS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn pong() -> ! {
+    loop {
+        kthread::park();
+        PONGS.fetch_add(1, Ordering::SeqCst);
+        kthread::unpark(*RUNNER);
+    }
+}
+
+/// Every iteration is a park and unpark of two threads, which are two
+/// context switches if the threads share a hart
+fn context_switch() {
+    PONG.initialize(kthread::spawn("benchmark-pong", pong));
+    let mut measurement = Measurement::new("context_switch", 2);
+    for iteration in 1..=CONTEXT_SWITCH_ITERATIONS {
+        measurement.measure(|| {
+            kthread::unpark(*PONG);
+            while PONGS.load(Ordering::SeqCst) < iteration {
+                kthread::park();
+            }
+        });
+    }
+    measurement.report();
+}
+
+static COUNTER: Mutex<u64> = Mutex::new(0);
+static RUNNING_LOCKERS: AtomicUsize = AtomicUsize::new(0);
+// Mean cycles per lock of every thread
+static LOCKER_SAMPLES: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+extern "C" fn locker() -> ! {
+    let start = PerfCounter::Cycles.read();
+    for _ in 0..MUTEX_ITERATIONS {
+        // Being preempted while holding the lock would let the others spin
+        Cpu::without_interrupts(|| *COUNTER.lock() += 1);
+    }
+    let cycles_per_lock = (PerfCounter::Cycles.read() - start) / MUTEX_ITERATIONS as u64;
+    Cpu::without_interrupts(|| LOCKER_SAMPLES.lock().push(cycles_per_lock));
+    if RUNNING_LOCKERS.fetch_sub(1, Ordering::SeqCst) == 1 {
+        kthread::unpark(*RUNNER);
+    }
+    kthread::exit();
+}
+
+/// All threads increment the same counter. With one thread per hart
+/// they contend for the lock.
+fn mutex(threads: usize) {
+    RUNNING_LOCKERS.store(threads, Ordering::SeqCst);
+    for _ in 0..threads {
+        kthread::spawn("benchmark-locker", locker);
+    }
+    while RUNNING_LOCKERS.load(Ordering::SeqCst) > 0 {
+        kthread::park();
+    }
+    let mut measurement = Measurement::new("mutex", threads);
+    measurement.samples = Cpu::without_interrupts(|| core::mem::take(&mut *LOCKER_SAMPLES.lock()));
+    measurement.report();
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::Measurement;
+
+    #[test_case]
+    fn results_are_one_json_line() {
+        let mut measurement = Measurement::new("example", 2);
+        measurement.samples = vec![9, 1, 5, 3];
+        assert_eq!(
+            measurement.result(),
+            "BENCHMARK {\"name\":\"example\",\"threads\":2,\"samples\":4,\"min_cycles\":1,\"median_cycles\":5,\"mean_cycles\":4}"
+        );
+
+        // Nothing was measured
+        let measurement = Measurement::new("empty", 1);
+        assert!(measurement
+            .result()
+            .ends_with("\"samples\":0,\"min_cycles\":0,\"median_cycles\":0,\"mean_cycles\":0}"));
+    }
+}
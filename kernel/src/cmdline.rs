@@ -0,0 +1,231 @@
+//! The kernel command line is passed by QEMU with `-append` and ends up
+//! in the bootargs property of the chosen node. Options are separated by
+//! whitespace, e.g. `loglevel=debug init=sesh test=mutex`.
+//! With `exit_with_init` QEMU exits as soon as init exits and reports
+//! the exit status of init, which lets tests check how init ended.
+//! `ramdisk_size=<KiB>` creates /dev/ram0 with the given size and
+//! `block_cache_size=<KiB>` sets the size of the cache of every loop
+//! device, 0 disables the cache. `leak_check` verifies that exiting
+//! processes return all their pages, see memory::leak_check.
+//! `page_poison` and `page_backtraces` help with memory bugs, see
+//! memory::page_owner. `lock_debug` records the owner and backtrace of
+//! every held lock to find deadlocks, see debugging::lock_debug.
+//! `console=sbi` prints through the firmware
+//! instead of the UART, see io::console. `idle=` decides what happens
+//! once no user process is left: `shutdown` (the default) turns the
+//! machine off through the SBI, `restart_init` starts init again and
+//! `wfi` keeps the harts waiting for interrupts.
+
+use common::runtime_initialized::RuntimeInitializedData;
+
+use crate::{
+    device_tree,
+    logging::configuration::{self, LogLevel},
+    warn,
+};
+
+static THE: RuntimeInitializedData<Cmdline<'static>> = RuntimeInitializedData::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    Uart,
+    /// The debug console of the firmware, see io::console
+    Sbi,
+}
+
+/// What the scheduler does once no user process is left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idle {
+    Shutdown,
+    RestartInit,
+    /// Only interrupts are handled, which suits boards without a way
+    /// to power off
+    Wfi,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cmdline<'a> {
+    pub loglevel: LogLevel,
+    /// The program which is started as PID 1
+    pub init: &'a str,
+    /// Only the kernel tests whose name contains the filter are executed
+    pub test: Option<&'a str>,
+    pub console: Console,
+    /// Propagate the exit status of init to the QEMU exit device
+    pub exit_with_init: bool,
+    /// Size of /dev/ram0 in KiB, 0 means that there is no ramdisk
+    pub ramdisk_size: usize,
+    /// Per cached block device in KiB
+    pub block_cache_size: usize,
+    pub leak_check: bool,
+    /// Detect writes to freed pages
+    pub page_poison: bool,
+    /// Record the backtrace of every page allocation
+    pub page_backtraces: bool,
+    /// Check lock recursion and ordering, expensive
+    pub lock_debug: bool,
+    pub idle: Idle,
+}
+
+impl<'a> Cmdline<'a> {
+    pub const fn new() -> Self {
+        Self {
+            loglevel: LogLevel::Info,
+            init: "init",
+            test: None,
+            console: Console::Uart,
+            exit_with_init: false,
+            ramdisk_size: 0,
+            block_cache_size: 256,
+            leak_check: false,
+            page_poison: false,
+            page_backtraces: false,
+            lock_debug: false,
+            idle: Idle::Shutdown,
+        }
+    }
+
+    /// Unknown or invalid options are reported and ignored
+    pub fn parse(cmdline: &'a str) -> Self {
+        let mut parsed = Self::new();
+        for option in cmdline.split_ascii_whitespace() {
+            let (key, value) = option.split_once('=').unwrap_or((option, ""));
+            match key {
+                "loglevel" => match LogLevel::parse(value) {
+                    Some(loglevel) => parsed.loglevel = loglevel,
+                    None => {
+                        warn!("Invalid loglevel {value}");
+                    }
+                },
+                "init" if !value.is_empty() => parsed.init = value,
+                "test" if !value.is_empty() => parsed.test = Some(value),
+                "console" => match value {
+                    "ttyS0" | "uart" => parsed.console = Console::Uart,
+                    "sbi" => parsed.console = Console::Sbi,
+                    _ => {
+                        warn!("Unsupported console {value}, using the UART");
+                    }
+                },
+                "exit_with_init" => match value {
+                    "" | "1" => parsed.exit_with_init = true,
+                    "0" => parsed.exit_with_init = false,
+                    _ => {
+                        warn!("Invalid value {value} for exit_with_init");
+                    }
+                },
+                "ramdisk_size" => match value.parse() {
+                    Ok(size) => parsed.ramdisk_size = size,
+                    Err(_) => {
+                        warn!("Invalid ramdisk_size {value}");
+                    }
+                },
+                "block_cache_size" => match value.parse() {
+                    Ok(size) => parsed.block_cache_size = size,
+                    Err(_) => {
+                        warn!("Invalid block_cache_size {value}");
+                    }
+                },
+                "leak_check" => match value {
+                    "" | "1" => parsed.leak_check = true,
+                    "0" => parsed.leak_check = false,
+                    _ => {
+                        warn!("Invalid value {value} for leak_check");
+                    }
+                },
+                "page_poison" => match value {
+                    "" | "1" => parsed.page_poison = true,
+                    "0" => parsed.page_poison = false,
+                    _ => {
+                        warn!("Invalid value {value} for page_poison");
+                    }
+                },
+                "page_backtraces" => match value {
+                    "" | "1" => parsed.page_backtraces = true,
+                    "0" => parsed.page_backtraces = false,
+                    _ => {
+                        warn!("Invalid value {value} for page_backtraces");
+                    }
+                },
+                "lock_debug" => match value {
+                    "" | "1" => parsed.lock_debug = true,
+                    "0" => parsed.lock_debug = false,
+                    _ => {
+                        warn!("Invalid value {value} for lock_debug");
+                    }
+                },
+                "idle" => match value {
+                    "shutdown" => parsed.idle = Idle::Shutdown,
+                    "restart_init" => parsed.idle = Idle::RestartInit,
+                    "wfi" => parsed.idle = Idle::Wfi,
+                    _ => {
+                        warn!("Invalid value {value} for idle");
+                    }
+                },
+                _ => {
+                    warn!("Unknown kernel command line option {option}");
+                }
+            }
+        }
+        parsed
+    }
+}
+
+fn read_bootargs() -> Option<&'static str> {
+    let chosen = device_tree::THE.root_node().find_node("chosen")?;
+    chosen.get_property("bootargs")?.consume_str()
+}
+
+/// Must be called after the device tree is initialized
+pub fn init() {
+    let cmdline = read_bootargs().map_or(Cmdline::new(), Cmdline::parse);
+    configuration::set_log_level(cmdline.loglevel);
+    THE.initialize(cmdline);
+}
+
+pub fn get() -> &'static Cmdline<'static> {
+    &THE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cmdline, Console, Idle};
+    use crate::logging::configuration::LogLevel;
+
+    #[test_case]
+    fn options_are_parsed() {
+        let cmdline = Cmdline::parse(
+            "loglevel=debug  init=/bin/sesh test=mutex console=ttyS0 exit_with_init ramdisk_size=64 block_cache_size=0 leak_check page_poison page_backtraces=1 lock_debug idle=restart_init",
+        );
+        assert_eq!(
+            cmdline,
+            Cmdline {
+                loglevel: LogLevel::Debug,
+                init: "/bin/sesh",
+                test: Some("mutex"),
+                console: Console::Uart,
+                exit_with_init: true,
+                ramdisk_size: 64,
+                block_cache_size: 0,
+                leak_check: true,
+                page_poison: true,
+                page_backtraces: true,
+                lock_debug: true,
+                idle: Idle::RestartInit,
+            }
+        );
+        assert!(!Cmdline::parse("exit_with_init=0").exit_with_init);
+        assert_eq!(Cmdline::parse("console=sbi").console, Console::Sbi);
+        assert_eq!(Cmdline::parse("idle=wfi").idle, Idle::Wfi);
+    }
+
+    #[test_case]
+    fn invalid_options_keep_the_defaults() {
+        assert_eq!(Cmdline::parse(""), Cmdline::new());
+        assert_eq!(
+            Cmdline::parse(
+                "loglevel=verbose init= quiet console=hvc0 exit_with_init=yes ramdisk_size=1M idle=halt"
+            ),
+            Cmdline::new()
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! The optional subsystems the kernel was built with. They are cargo
+//! features which are enabled by default, e.g.
+//! `cargo build --no-default-features --features hostfs` leaves out
+//! the network stack.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::info;
+
+pub const NET: bool = cfg!(feature = "net");
+pub const HOSTFS: bool = cfg!(feature = "hostfs");
+
+const SUBSYSTEMS: [(&str, bool); 2] = [("net", NET), ("hostfs", HOSTFS)];
+
+/// The names of the subsystems which were built in or left out,
+/// separated by spaces
+pub fn subsystems(enabled: bool) -> String {
+    SUBSYSTEMS
+        .iter()
+        .filter(|(_, built_in)| *built_in == enabled)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn log() {
+    info!(
+        "Subsystems: [{}] left out: [{}]",
+        subsystems(true),
+        subsystems(false)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{subsystems, HOSTFS, NET};
+
+    #[test_case]
+    fn every_subsystem_is_listed_once() {
+        let enabled = subsystems(true);
+        let disabled = subsystems(false);
+        assert_eq!(enabled.contains("net"), NET);
+        assert_eq!(disabled.contains("net"), !NET);
+        assert_eq!(enabled.contains("hostfs"), HOSTFS);
+        assert_eq!(disabled.contains("hostfs"), !HOSTFS);
+    }
+}
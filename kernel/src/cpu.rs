@@ -4,16 +4,18 @@ use core::{
     cell::Cell,
     mem::offset_of,
     ops::{Deref, DerefMut},
-    ptr::addr_of,
+    ptr::{addr_of, addr_of_mut},
 };
 
-use common::{mutex::MutexGuard, runtime_initialized::RuntimeInitializedData};
+use common::{
+    mutex::MutexGuard, runtime_initialized::RuntimeInitializedData, syscalls::trap_frame::TrapFrame,
+};
 
 use crate::{
     klibc::sizes::KiB,
     memory::page_tables::RootPageTableHolder,
     processes::{
-        process::Process,
+        process::{Pid, Process},
         process_table::ProcessRef,
         scheduler::{self, CpuScheduler},
     },
@@ -22,7 +24,12 @@ use crate::{
 const KERNEL_STACK_SIZE: usize = KiB(512);
 
 const SIE_STIE: usize = 5;
+const SIP_SSIP: usize = 1;
+const SSTATUS_SIE: usize = 1;
 const SSTATUS_SPP: usize = 8;
+const SCOUNTEREN_CY: usize = 0;
+const SCOUNTEREN_TM: usize = 1;
+const SCOUNTEREN_IR: usize = 2;
 
 pub static STARTING_CPU_ID: RuntimeInitializedData<usize> = RuntimeInitializedData::new();
 
@@ -30,9 +37,24 @@ pub const TRAP_FRAME_OFFSET: usize = offset_of!(Cpu, scheduler) + scheduler::TRA
 
 pub const KERNEL_PAGE_TABLES_SATP_OFFSET: usize = offset_of!(Cpu, kernel_page_tables_satp_value);
 
+pub const KERNEL_BREAKPOINT_TRAP_FRAME_OFFSET: usize =
+    offset_of!(Cpu, kernel_breakpoint_trap_frame);
+
+pub const INTERRUPT_NESTING_OFFSET: usize = offset_of!(Cpu, interrupt_nesting);
+
+pub const TRAP_SCRATCH_OFFSET: usize = offset_of!(Cpu, trap_scratch);
+
 pub struct Cpu {
     kernel_page_tables_satp_value: usize,
     scheduler: CpuScheduler,
+    // Breakpoints in kernel mode must not overwrite the trap frame
+    // of the interrupted process
+    kernel_breakpoint_trap_frame: TrapFrame,
+    // Number of handlers which currently let higher priority interrupts
+    // in, see interrupts::nesting. Read by the trap entry.
+    interrupt_nesting: usize,
+    // Holds a register while the trap entry checks for nesting
+    trap_scratch: usize,
     cpu_id: usize,
     kernel_page_tables: RootPageTableHolder,
     mutable_reference_alive: Cell<bool>,
@@ -97,12 +119,15 @@ impl Cpu {
     read_csrr!(sscratch);
     read_csrr!(sie);
     read_csrr!(sstatus);
+    read_csrr!(sip);
 
     write_csrr!(satp);
     write_csrr!(sepc);
     write_csrr!(sscratch);
     write_csrr!(sstatus);
     write_csrr!(sie);
+    write_csrr!(sip);
+    write_csrr!(scounteren);
 
     pub fn init(cpu_id: usize) -> *mut Cpu {
         let kernel_stack =
@@ -125,6 +150,9 @@ impl Cpu {
         let cpu = Box::new(Self {
             kernel_page_tables_satp_value: satp_value,
             scheduler: CpuScheduler::new(),
+            kernel_breakpoint_trap_frame: TrapFrame::zero(),
+            interrupt_nesting: 0,
+            trap_scratch: 0,
             cpu_id,
             kernel_page_tables: page_tables,
             mutable_reference_alive: Cell::new(false),
@@ -184,6 +212,57 @@ impl Cpu {
         unsafe { *addr_of!((*ptr).cpu_id) }
     }
 
+    /// Returns the pid of the process currently scheduled on this cpu without
+    /// taking any lock. Returns None if the cpu struct is not yet initialized.
+    pub fn maybe_current_pid() -> Option<Pid> {
+        let ptr = Self::read_sscratch() as *mut Self;
+        if ptr.is_null() || !ptr.is_aligned() {
+            return None;
+        }
+        // SAFETY: The cpu struct is static and the pid is a plain value
+        unsafe { Some((*addr_of!((*ptr).scheduler)).current_pid()) }
+    }
+
+    /// Returns the registers saved by the last trap on this cpu without
+    /// taking any lock. Returns None if the cpu struct is not yet initialized.
+    pub fn maybe_trap_frame() -> Option<TrapFrame> {
+        let ptr = Self::read_sscratch() as *mut Self;
+        if ptr.is_null() || !ptr.is_aligned() {
+            return None;
+        }
+        // SAFETY: The cpu struct is static and the trap frame is only
+        // written by this cpu on trap entry.
+        unsafe { Some(*(*addr_of!((*ptr).scheduler)).trap_frame()) }
+    }
+
+    /// Returns how many handlers on this cpu currently let higher
+    /// priority interrupts in without taking any lock.
+    pub fn interrupt_nesting() -> usize {
+        let ptr = Self::get_per_cpu_data();
+        // SAFETY: The cpu struct is static and the depth is only
+        // written by this cpu.
+        unsafe { *addr_of!((*ptr).interrupt_nesting) }
+    }
+
+    pub fn set_interrupt_nesting(depth: usize) {
+        let ptr = Self::get_per_cpu_data();
+        // SAFETY: The cpu struct is static and the depth is only
+        // written by this cpu.
+        unsafe { *addr_of_mut!((*ptr).interrupt_nesting) = depth };
+    }
+
+    /// Trap handlers run on the kernel stack at the end of the address space
+    pub fn is_on_trap_stack() -> bool {
+        if cfg!(miri) {
+            return true;
+        }
+        let sp: usize;
+        unsafe {
+            asm!("mv {}, sp", out(reg) sp);
+        }
+        sp >= 0usize.wrapping_sub(KERNEL_STACK_SIZE)
+    }
+
     pub fn activate_kernel_page_table(&self) {
         self.kernel_page_tables.activate_page_table();
     }
@@ -225,9 +304,33 @@ impl Cpu {
         (sie & (1 << SIE_STIE)) > 0
     }
 
+    pub fn clear_software_interrupt() {
+        Self::csrc_sip(1 << SIP_SSIP);
+    }
+
+    /// Runs `f` without being interrupted on the current hart
+    pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+        let sstatus = Self::read_sstatus();
+        Self::csrc_sstatus(1 << SSTATUS_SIE);
+        let result = f();
+        Self::csrs_sstatus(sstatus & (1 << SSTATUS_SIE));
+        result
+    }
+
+    /// Lets the current hart take a software interrupt as soon as
+    /// interrupts are enabled
+    pub fn raise_software_interrupt() {
+        Self::csrs_sip(1 << SIP_SSIP);
+    }
+
     pub fn enable_timer_interrupt() {
         Self::csrs_sie(1 << SIE_STIE);
     }
+
+    /// Allows userspace to read the cycle, time and instret CSRs
+    pub fn enable_userspace_counters() {
+        Self::csrs_scounteren((1 << SCOUNTEREN_CY) | (1 << SCOUNTEREN_TM) | (1 << SCOUNTEREN_IR));
+    }
     pub fn is_in_kernel_mode() -> bool {
         let sstatus = Self::read_sstatus();
         (sstatus & (1 << SSTATUS_SPP)) > 0
@@ -1,12 +1,13 @@
 use super::eh_frame_parser;
 use crate::{
     assert::static_assert_size,
+    cpu::Cpu,
     debugging::{
         self,
         eh_frame_parser::EhFrameParser,
         unwinder::{RegisterRule, Unwinder},
     },
-    info,
+    info, initcall,
     memory::linker_information::LinkerInformation,
 };
 use alloc::vec::Vec;
@@ -22,6 +23,43 @@ use common::runtime_initialized::RuntimeInitializedData;
 enum BacktraceNextError {
     RaIsZero,
     CouldNotGetFde(usize),
+    /// Userspace code has no unwind information in the kernel
+    ReachedUserspace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    /// The address is a return address and points behind the call
+    Call,
+    /// The address is the instruction which was interrupted by a trap
+    Trap,
+    Userspace,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    address: usize,
+    kind: FrameKind,
+}
+
+struct UnwindState {
+    registers: Registers,
+    pc: usize,
+    kind: FrameKind,
+    crossed_trap: bool,
+    reached_userspace: bool,
+}
+
+impl UnwindState {
+    fn new(registers: Registers) -> Self {
+        Self {
+            pc: registers.ra(),
+            registers,
+            kind: FrameKind::Call,
+            crossed_trap: false,
+            reached_userspace: false,
+        }
+    }
 }
 
 /// We keep the already parsed information in a Vec
@@ -66,36 +104,63 @@ impl<'a> Backtrace<'a> {
         }
     }
 
-    fn next(&self, regs: &mut CallerSavedRegs) -> Result<usize, BacktraceNextError> {
-        let ra = regs.ra();
+    fn next(&self, state: &mut UnwindState) -> Result<Frame, BacktraceNextError> {
+        if state.reached_userspace {
+            return Err(BacktraceNextError::ReachedUserspace);
+        }
+
+        let frame = Frame {
+            address: state.pc,
+            kind: state.kind,
+        };
 
-        if ra == 0 {
+        if frame.address == 0 {
             return Err(BacktraceNextError::RaIsZero);
         }
 
-        // RA points to the next instruction. Move it back one byte such
-        // that it points into the previous instruction.
+        if frame.kind == FrameKind::Userspace {
+            state.reached_userspace = true;
+            return Ok(frame);
+        }
+
+        // A return address points to the next instruction. Move it back one
+        // byte such that it points into the previous instruction.
         // This case must be handled different as soon as we have
         // signal trampolines.
-        let fde = self
-            .find(ra - 1)
-            .ok_or(BacktraceNextError::CouldNotGetFde(ra))?;
+        let lookup_address = match frame.kind {
+            FrameKind::Call => frame.address - 1,
+            FrameKind::Trap | FrameKind::Userspace => frame.address,
+        };
+
+        let Some(fde) = self.find(lookup_address) else {
+            if !state.crossed_trap && is_trap_handler(frame.address) {
+                Self::cross_trap(state);
+                return Ok(frame);
+            }
+            return Err(BacktraceNextError::CouldNotGetFde(frame.address));
+        };
 
         let unwinder = Unwinder::new(fde);
 
-        let row = unwinder.find_row_for_address(ra);
+        let row = unwinder.find_row_for_address(lookup_address);
 
+        let regs = &state.registers;
         let cfa = regs[row.cfa_register as usize].wrapping_add(row.cfa_offset as usize);
 
         let mut new_regs = regs.clone();
         new_regs.set_sp(cfa);
-        new_regs.set_ra(0);
+        // Only an interrupted function may not have saved
+        // its return address yet, e.g. a leaf function.
+        if frame.kind == FrameKind::Call {
+            new_regs.set_ra(0);
+        }
 
         for (reg_index, rule) in row.register_rules.iter().enumerate() {
             let value = match rule {
-                RegisterRule::None => {
+                RegisterRule::None | RegisterRule::SameValue => {
                     continue;
                 }
+                RegisterRule::Undefined => 0,
                 RegisterRule::Offset(offset) => {
                     let ptr = (cfa.wrapping_add(*offset as usize)) as *const usize;
                     unsafe { ptr.read() }
@@ -104,10 +169,37 @@ impl<'a> Backtrace<'a> {
             new_regs[reg_index] = value;
         }
 
-        *regs = new_regs;
+        state.pc = new_regs.ra();
+        state.registers = new_regs;
+        state.kind = FrameKind::Call;
+
+        Ok(frame)
+    }
+
+    /// Continues with the code which was interrupted by the trap. Traps don't
+    /// nest, therefore the trap frame of the cpu belongs to this trap handler.
+    fn cross_trap(state: &mut UnwindState) {
+        state.crossed_trap = true;
+        let Some(trap_frame) = Cpu::maybe_trap_frame() else {
+            state.pc = 0;
+            return;
+        };
+        state.registers = Registers(*trap_frame.registers());
+        state.pc = Cpu::read_sepc();
+        state.kind = if Cpu::is_in_kernel_mode() {
+            FrameKind::Trap
+        } else {
+            FrameKind::Userspace
+        };
+    }
+}
 
-        Ok(ra)
+fn is_trap_handler(address: usize) -> bool {
+    extern "C" {
+        fn __trap_handlers_start();
+        fn __trap_handlers_end();
     }
+    (__trap_handlers_start as usize..__trap_handlers_end as usize).contains(&address)
 }
 
 // We leave that here for debugging purposes
@@ -133,120 +225,43 @@ impl<'a> Backtrace<'a> {
 //     _Unwind_Backtrace(callback, &mut data as *mut _ as _);
 // }
 
-/// You ask where I got the registers from? This is a good question.
-/// I just looked what registers were mentioned in the eh_frame and added those.
-/// Maybe there will be more in the future, then we have to add them.
-/// I tried to generate the following code via a macro. However this is not possible,
-/// because they won't allow to concatenate x$num_reg as a identifier and I need the
-/// literal number to access it via an index.
+/// All general purpose registers such that unwinding can continue from a
+/// trap frame. Starting from a call only the callee saved registers are
+/// needed, the others stay zero.
 #[derive(Debug, Clone, Default)]
-struct CallerSavedRegs {
-    x1: usize,
-    x2: usize,
-    x8: usize,
-    x9: usize,
-    x18: usize,
-    x19: usize,
-    x20: usize,
-    x21: usize,
-    x22: usize,
-    x23: usize,
-    x24: usize,
-    x25: usize,
-    x26: usize,
-    x27: usize,
-}
-
-impl core::fmt::Display for CallerSavedRegs {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        macro_rules! print_reg {
-            ($reg:ident) => {
-                writeln!(f, "{}: {:#x}", stringify!($reg), self.$reg)?
-            };
-        }
-
-        print_reg!(x1);
-        print_reg!(x2);
-        print_reg!(x8);
-        print_reg!(x9);
-        print_reg!(x18);
-        print_reg!(x19);
-        print_reg!(x20);
-        print_reg!(x21);
-        print_reg!(x22);
-        print_reg!(x23);
-        print_reg!(x24);
-        print_reg!(x25);
-        print_reg!(x26);
-        print_reg!(x27);
-
-        Ok(())
-    }
-}
+struct Registers([usize; 32]);
 
-impl core::ops::Index<usize> for CallerSavedRegs {
+impl core::ops::Index<usize> for Registers {
     type Output = usize;
 
     fn index(&self, index: usize) -> &Self::Output {
-        match index {
-            1 => &self.x1,
-            2 => &self.x2,
-            8 => &self.x8,
-            9 => &self.x9,
-            18 => &self.x18,
-            19 => &self.x19,
-            20 => &self.x20,
-            21 => &self.x21,
-            22 => &self.x22,
-            23 => &self.x23,
-            24 => &self.x24,
-            25 => &self.x25,
-            26 => &self.x26,
-            27 => &self.x27,
-            _ => panic!("Invalid index"),
-        }
+        &self.0[index]
     }
 }
 
-impl core::ops::IndexMut<usize> for CallerSavedRegs {
+impl core::ops::IndexMut<usize> for Registers {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        match index {
-            1 => &mut self.x1,
-            2 => &mut self.x2,
-            8 => &mut self.x8,
-            9 => &mut self.x9,
-            18 => &mut self.x18,
-            19 => &mut self.x19,
-            20 => &mut self.x20,
-            21 => &mut self.x21,
-            22 => &mut self.x22,
-            23 => &mut self.x23,
-            24 => &mut self.x24,
-            25 => &mut self.x25,
-            26 => &mut self.x26,
-            27 => &mut self.x27,
-            _ => panic!("Invalid index"),
-        }
+        &mut self.0[index]
     }
 }
 
 // This value is referenced in the assembly of extern "C-unwind" fn dispatch
-static_assert_size!(CallerSavedRegs, 0x70);
+static_assert_size!(Registers, 0x100);
 
-impl CallerSavedRegs {
+impl Registers {
     fn ra(&self) -> usize {
-        self.x1
+        self.0[1]
     }
 
     fn set_ra(&mut self, value: usize) {
-        self.x1 = value;
+        self.0[1] = value;
     }
 
     fn set_sp(&mut self, value: usize) {
-        self.x2 = value;
+        self.0[2] = value;
     }
 
-    fn with_context<F: FnMut(&mut CallerSavedRegs)>(f: F) {
+    fn with_context<F: FnMut(&mut Registers)>(f: F) {
         // Inspired by the unwinder crate
         // https://github.com/nbdd0121/unwinding/
 
@@ -259,48 +274,49 @@ impl CallerSavedRegs {
         // very convenient for the caller side.
 
         #[repr(C)]
-        struct ClosureWrapper<F: FnMut(&mut CallerSavedRegs)>(F);
+        struct ClosureWrapper<F: FnMut(&mut Registers)>(F);
 
         let mut closure = ClosureWrapper(f);
 
         dispatch(
-            &mut CallerSavedRegs::default(),
+            &mut Registers::default(),
             &mut closure,
             closure_to_fn_pointer,
         );
 
-        extern "C" fn closure_to_fn_pointer<F: FnMut(&mut CallerSavedRegs)>(
-            regs: &mut CallerSavedRegs,
+        extern "C" fn closure_to_fn_pointer<F: FnMut(&mut Registers)>(
+            regs: &mut Registers,
             f_data: &mut ClosureWrapper<F>,
         ) {
             (f_data.0)(regs);
         }
 
         #[naked]
-        extern "C-unwind" fn dispatch<F: FnMut(&mut CallerSavedRegs)>(
-            regs: &mut CallerSavedRegs,
+        extern "C-unwind" fn dispatch<F: FnMut(&mut Registers)>(
+            regs: &mut Registers,
             f_data: &mut ClosureWrapper<F>,
-            f: extern "C" fn(&mut CallerSavedRegs, &mut ClosureWrapper<F>),
+            f: extern "C" fn(&mut Registers, &mut ClosureWrapper<F>),
         ) {
             unsafe {
                 core::arch::naked_asm!(
                     "
                      # regs is in a0
                      # f to call in a2
-                     sd x1, 0x00(a0)   
-                     sd x2, 0x08(a0)
-                     sd x8, 0x10(a0)
-                     sd x9, 0x18(a0)
-                     sd x18, 0x20(a0)
-                     sd x19, 0x28(a0)
-                     sd x20, 0x30(a0)
-                     sd x21, 0x38(a0)
-                     sd x22, 0x40(a0)
-                     sd x23, 0x48(a0)
-                     sd x24, 0x50(a0)
-                     sd x25, 0x58(a0)
-                     sd x26, 0x60(a0)
-                     sd x27, 0x68(a0)
+                     # Every register is stored at index * 8
+                     sd x1, 0x08(a0)
+                     sd x2, 0x10(a0)
+                     sd x8, 0x40(a0)
+                     sd x9, 0x48(a0)
+                     sd x18, 0x90(a0)
+                     sd x19, 0x98(a0)
+                     sd x20, 0xa0(a0)
+                     sd x21, 0xa8(a0)
+                     sd x22, 0xb0(a0)
+                     sd x23, 0xb8(a0)
+                     sd x24, 0xc0(a0)
+                     sd x25, 0xc8(a0)
+                     sd x26, 0xd0(a0)
+                     sd x27, 0xd8(a0)
                      # Save return address on stack
                      # It is important to change the stack
                      # pointer after the previous instructions
@@ -318,17 +334,20 @@ impl CallerSavedRegs {
     }
 }
 
+initcall!(Core, init);
+
 pub fn init() {
     BACKTRACE.initialize(Backtrace::new());
 }
 
 pub fn print() {
-    CallerSavedRegs::with_context(|regs| {
+    Registers::with_context(|regs| {
+        let mut state = UnwindState::new(regs.clone());
         let mut counter = 0u64;
         loop {
-            match BACKTRACE.next(regs) {
-                Ok(address) => {
-                    print_stacktrace_frame(counter, address);
+            match BACKTRACE.next(&mut state) {
+                Ok(frame) => {
+                    print_frame(counter, frame);
                     counter += 1;
                 }
                 Err(BacktraceNextError::RaIsZero) => {
@@ -341,11 +360,52 @@ pub fn print() {
                     print_stacktrace_frame(counter, address);
                     break;
                 }
+                Err(BacktraceNextError::ReachedUserspace) => break,
+            }
+        }
+    });
+}
+
+/// Fills `addresses` with the return addresses of the current call stack.
+/// Does not allocate and can therefore be used in the lock debugging hooks.
+pub fn capture(addresses: &mut [usize]) {
+    if !BACKTRACE
+        .initialized()
+        .load(core::sync::atomic::Ordering::SeqCst)
+    {
+        return;
+    }
+    Registers::with_context(|regs| {
+        let mut state = UnwindState::new(regs.clone());
+        for slot in addresses.iter_mut() {
+            match BACKTRACE.next(&mut state) {
+                Ok(frame) if frame.kind != FrameKind::Userspace => *slot = frame.address,
+                _ => break,
             }
         }
     });
 }
 
+pub fn print_addresses(addresses: impl Iterator<Item = usize>) {
+    for (counter, address) in addresses.enumerate() {
+        print_stacktrace_frame(counter as u64, address);
+    }
+}
+
+fn print_frame(counter: u64, frame: Frame) {
+    match frame.kind {
+        FrameKind::Call => print_stacktrace_frame(counter, frame.address),
+        FrameKind::Trap => {
+            info!("--- interrupted kernel code ---");
+            print_stacktrace_frame(counter, frame.address);
+        }
+        FrameKind::Userspace => {
+            info!("--- interrupted userspace code ---");
+            info!("{counter}: {:#x} <userspace>\n", frame.address);
+        }
+    }
+}
+
 fn print_stacktrace_frame(counter: u64, address: usize) {
     let symbol = debugging::symbols::get_symbol(address);
     if let Some(symbol) = symbol {
@@ -366,7 +426,7 @@ fn print_stacktrace_frame(counter: u64, address: usize) {
 #[cfg(not(miri))]
 #[cfg(test)]
 mod tests {
-    use crate::debugging::backtrace::{Backtrace, BacktraceNextError, CallerSavedRegs};
+    use crate::debugging::backtrace::{Backtrace, BacktraceNextError, Registers, UnwindState};
     use alloc::collections::VecDeque;
     use core::ffi::c_void;
     use unwinding::abi::{UnwindContext, UnwindReasonCode, _Unwind_Backtrace, _Unwind_GetIP};
@@ -390,14 +450,15 @@ mod tests {
         let mut data = CallbackData::default();
 
         _Unwind_Backtrace(callback, &mut data as *mut _ as _);
-        CallerSavedRegs::with_context(|regs| {
+        Registers::with_context(|regs| {
             let backtrace = Backtrace::new();
+            let mut state = UnwindState::new(regs.clone());
             let mut own_addr = VecDeque::new();
 
             loop {
-                match backtrace.next(regs) {
-                    Ok(address) => {
-                        own_addr.push_back(address);
+                match backtrace.next(&mut state) {
+                    Ok(frame) => {
+                        own_addr.push_back(frame.address);
                     }
                     Err(BacktraceNextError::RaIsZero) => {
                         own_addr.push_back(0);
@@ -407,6 +468,7 @@ mod tests {
                         own_addr.push_back(address);
                         break;
                     }
+                    Err(BacktraceNextError::ReachedUserspace) => break,
                 }
             }
 
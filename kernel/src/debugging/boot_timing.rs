@@ -0,0 +1,146 @@
+//! Timestamps of the stages of kernel_init. Every stage lasts from the
+//! end of the previous one until it is marked as done. The breakdown is
+//! logged when the boot hart starts scheduling and is listed in
+//! /proc/boot.
+//!
+//! Only the boot hart records stages, cycles and instructions are
+//! counted per hart.
+
+use alloc::string::String;
+use common::{
+    array_vec::ArrayVec,
+    mutex::Mutex,
+    perf::{PerfCounter, PerfValues},
+};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    cpu::{self, Cpu},
+    info,
+    processes::timer,
+};
+
+const MAX_STAGES: usize = 16;
+
+struct Stage {
+    name: &'static str,
+    duration: PerfValues,
+}
+
+struct BootTiming {
+    entered: Option<PerfValues>,
+    last_mark: PerfValues,
+    stages: ArrayVec<Stage, MAX_STAGES>,
+}
+
+// The heap doesn't exist during the first stages
+static TIMING: Mutex<BootTiming> = Mutex::new(BootTiming {
+    entered: None,
+    last_mark: PerfValues::ZERO,
+    stages: ArrayVec::new(),
+});
+
+static FINISHED: AtomicBool = AtomicBool::new(false);
+
+/// Called first thing on kernel entry
+pub fn start() {
+    let now = PerfValues::read();
+    let mut timing = TIMING.lock();
+    timing.entered = Some(now);
+    timing.last_mark = now;
+}
+
+pub fn stage_done(name: &'static str) {
+    let now = PerfValues::read();
+    let mut timing = TIMING.lock();
+    let duration = now.elapsed_since(&timing.last_mark);
+    timing.last_mark = now;
+    assert!(
+        timing.stages.push(Stage { name, duration }).is_ok(),
+        "Too many boot stages, increase MAX_STAGES"
+    );
+}
+
+/// Records the last stage and logs the breakdown. Only the boot hart
+/// does that, and only the first time it starts scheduling.
+pub fn finish() {
+    if Cpu::cpu_id() != *cpu::STARTING_CPU_ID || FINISHED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    stage_done("scheduler");
+    for line in report().lines() {
+        info!("{line}");
+    }
+}
+
+fn microseconds(clocks: u64) -> u64 {
+    clocks * 1_000_000 / timer::clocks_per_second()
+}
+
+/// One stage per line with its duration in microseconds, cycles and
+/// retired instructions, followed by the total
+pub fn report() -> String {
+    let timing = TIMING.lock();
+    let mut report = String::new();
+    if let Some(entered) = timing.entered {
+        let _ = writeln!(
+            report,
+            "Kernel entered after {} us",
+            microseconds(entered.get(PerfCounter::Time))
+        );
+    }
+    let _ = writeln!(
+        report,
+        "{:<16} {:>10} {:>14} {:>14}",
+        "Stage", "Time (us)", "Cycles", "Instructions"
+    );
+    let mut total = PerfValues::ZERO;
+    for stage in timing.stages.iter() {
+        total.add(&stage.duration);
+        write_stage(&mut report, stage.name, &stage.duration);
+    }
+    write_stage(&mut report, "total", &total);
+    report
+}
+
+fn write_stage(report: &mut String, name: &str, duration: &PerfValues) {
+    let _ = writeln!(
+        report,
+        "{:<16} {:>10} {:>14} {:>14}",
+        name,
+        microseconds(duration.get(PerfCounter::Time)),
+        duration.get(PerfCounter::Cycles),
+        duration.get(PerfCounter::Instructions)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::report;
+
+    // There is no kernel_init under miri
+    #[cfg(not(miri))]
+    #[test_case]
+    fn stages_before_the_tests_are_reported() {
+        let report = report();
+        let stages: alloc::vec::Vec<&str> = report
+            .lines()
+            .skip(2)
+            .filter_map(|line| line.split("  ").next())
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                "early",
+                "page allocator",
+                "device tree",
+                "platform",
+                "filesystems",
+                "total"
+            ]
+        );
+    }
+}
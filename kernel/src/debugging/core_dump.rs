@@ -0,0 +1,493 @@
+//! ELF core files of crashed userspace processes. There is no filesystem
+//! yet, therefore the core file is printed base64 encoded to the console
+//! between two markers. `just extract-core <log>` turns it back into a
+//! file which gdb loads together with the original binary.
+//!
+//! The trap handler only copies the memory of the process. Printing takes
+//! long, therefore a kernel thread does it with interrupts enabled and
+//! kills the process afterwards. The parent doesn't notice the exit before
+//! the whole core file is on the console.
+
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use common::{
+    mutex::SpinLockIrqSave, runtime_initialized::RuntimeInitializedData, wait::ExitStatus,
+};
+
+use crate::{
+    cpu::Cpu,
+    initcall,
+    interrupts::trap_cause::exception::{
+        BREAKPOINT, ILLEGAL_INSTRUCTION, INSTRUCTION_ADDRESS_MISALIGNED, LOAD_ADDRESS_MISALIGNED,
+        STORE_AMO_ADDRESS_MISALIGNED,
+    },
+    memory::{page::PAGE_SIZE, page_tables::XWRMode},
+    power::{self, InitExit},
+    println,
+    processes::{
+        kthread,
+        process::{Pid, Process, INIT_PID},
+        process_table,
+    },
+};
+
+pub const BEGIN_MARKER: &str = "-----BEGIN CORE DUMP-----";
+pub const END_MARKER: &str = "-----END CORE DUMP-----";
+
+const BASE64_LINE_LENGTH: usize = 76;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+
+const NOTE_NAME: &[u8] = b"CORE\0";
+
+// Sizes of struct elf_prstatus and struct elf_prpsinfo on riscv64 linux
+const PRSTATUS_SIZE: usize = 376;
+const PRSTATUS_REGISTERS_OFFSET: usize = 112;
+const PRPSINFO_SIZE: usize = 136;
+
+const SIGILL: u16 = 4;
+const SIGTRAP: u16 = 5;
+const SIGBUS: u16 = 7;
+const SIGSEGV: u16 = 11;
+
+static PENDING: SpinLockIrqSave<VecDeque<CoreDump>> = SpinLockIrqSave::new(VecDeque::new());
+
+static PRINTER: RuntimeInitializedData<Pid> = RuntimeInitializedData::new();
+
+initcall!(Late, init);
+
+pub fn init() {
+    PRINTER.initialize(kthread::spawn("kcoredump", printer));
+}
+
+/// Everything the core file needs to know about the crashed thread
+struct CrashedThread<'a> {
+    pid: u64,
+    name: &'a str,
+    pc: usize,
+    registers: &'a [usize; 32],
+    exception_code: usize,
+}
+
+struct Segment {
+    address: usize,
+    privileges: XWRMode,
+    data: Vec<u8>,
+}
+
+/// A copy of the crashed process, it doesn't refer to its memory anymore
+pub struct CoreDump {
+    pid: Pid,
+    name: String,
+    pc: usize,
+    registers: [usize; 32],
+    exception_code: usize,
+    segments: Vec<Segment>,
+}
+
+impl CoreDump {
+    /// Must be called before the process continues or is torn down
+    pub fn capture(
+        process: &Process,
+        pc: usize,
+        registers: &[usize; 32],
+        exception_code: usize,
+    ) -> Self {
+        Self {
+            pid: process.get_pid(),
+            name: process.get_name().into(),
+            pc,
+            registers: *registers,
+            exception_code,
+            segments: collect_segments(process),
+        }
+    }
+
+    fn print(&self) {
+        let thread = CrashedThread {
+            pid: self.pid,
+            name: &self.name,
+            pc: self.pc,
+            registers: &self.registers,
+            exception_code: self.exception_code,
+        };
+        let core = build(&thread, &self.segments);
+
+        println!("{BEGIN_MARKER}");
+        println!(
+            "pid: {} name: {} size: {}",
+            thread.pid,
+            thread.name,
+            core.len()
+        );
+        let encoded = base64(&core);
+        for line in encoded.chunks(BASE64_LINE_LENGTH) {
+            // The alphabet is ascii only
+            println!(
+                "{}",
+                core::str::from_utf8(line).expect("base64 must be ascii")
+            );
+        }
+        println!("{END_MARKER}");
+    }
+}
+
+/// Prints the core file and kills the process afterwards. The process
+/// must not be scheduled anymore, see Scheduler::hold_crashed_current_process.
+/// Can be called from interrupt context.
+pub fn submit(dump: CoreDump) {
+    PENDING.lock().push_back(dump);
+    kthread::unpark(*PRINTER);
+}
+
+extern "C" fn printer() -> ! {
+    loop {
+        while let Some(dump) = PENDING.lock().pop_front() {
+            dump.print();
+            kill(dump.pid);
+        }
+        kthread::park();
+    }
+}
+
+fn kill(pid: Pid) {
+    if pid == INIT_PID {
+        power::init_exited(InitExit::Killed);
+    }
+    Cpu::without_interrupts(|| process_table::THE.update(|pt| pt.kill(pid, ExitStatus::Killed)));
+}
+
+fn collect_segments(process: &Process) -> Vec<Segment> {
+    let page_table = process.get_page_table();
+    page_table
+        .userspace_mappings()
+        .map(|(address, size, privileges)| {
+            let mut data = Vec::with_capacity(size);
+            for page in (0..size).step_by(PAGE_SIZE) {
+                match page_table.translate(address + page) {
+                    Some((physical_address, _)) => {
+                        // SAFETY: Physical memory is identity mapped in the kernel
+                        let bytes = unsafe {
+                            core::slice::from_raw_parts(physical_address as *const u8, PAGE_SIZE)
+                        };
+                        data.extend_from_slice(bytes);
+                    }
+                    None => data.resize(data.len() + PAGE_SIZE, 0),
+                }
+            }
+            Segment {
+                address,
+                privileges,
+                data,
+            }
+        })
+        .collect()
+}
+
+fn signal_of_exception(exception_code: usize) -> u16 {
+    match exception_code {
+        ILLEGAL_INSTRUCTION => SIGILL,
+        BREAKPOINT => SIGTRAP,
+        INSTRUCTION_ADDRESS_MISALIGNED | LOAD_ADDRESS_MISALIGNED | STORE_AMO_ADDRESS_MISALIGNED => {
+            SIGBUS
+        }
+        _ => SIGSEGV,
+    }
+}
+
+fn segment_flags(privileges: XWRMode) -> u32 {
+    match privileges {
+        XWRMode::PointerToNextLevel => 0,
+        XWRMode::ReadOnly => PF_R,
+        XWRMode::ReadWrite => PF_R | PF_W,
+        XWRMode::ExecuteOnly => PF_X,
+        XWRMode::ReadExecute => PF_R | PF_X,
+        XWRMode::ReadWriteExecute => PF_R | PF_W | PF_X,
+    }
+}
+
+fn build(thread: &CrashedThread, segments: &[Segment]) -> Vec<u8> {
+    let notes = notes(thread);
+    let program_header_count = segments.len() + 1;
+    let notes_offset = ELF_HEADER_SIZE + program_header_count * PROGRAM_HEADER_SIZE;
+    let first_segment_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+
+    let mut core = Vec::new();
+
+    // ELF header
+    core.extend_from_slice(b"\x7fELF");
+    // 64 bit, little endian, version 1, System V ABI
+    core.extend_from_slice(&[2, 1, 1, 0]);
+    core.resize(16, 0);
+    push_u16(&mut core, ET_CORE);
+    push_u16(&mut core, EM_RISCV);
+    push_u32(&mut core, 1);
+    // Entry point
+    push_u64(&mut core, 0);
+    // Program header offset
+    push_u64(&mut core, ELF_HEADER_SIZE as u64);
+    // Section header offset
+    push_u64(&mut core, 0);
+    // Flags
+    push_u32(&mut core, 0);
+    push_u16(&mut core, ELF_HEADER_SIZE as u16);
+    push_u16(&mut core, PROGRAM_HEADER_SIZE as u16);
+    push_u16(&mut core, program_header_count as u16);
+    // Section header entry size, count and string table index
+    push_u16(&mut core, 0);
+    push_u16(&mut core, 0);
+    push_u16(&mut core, 0);
+
+    push_program_header(
+        &mut core,
+        PT_NOTE,
+        0,
+        notes_offset,
+        0,
+        notes.len(),
+        notes.len(),
+        4,
+    );
+
+    let mut offset = first_segment_offset;
+    for segment in segments {
+        push_program_header(
+            &mut core,
+            PT_LOAD,
+            segment_flags(segment.privileges),
+            offset,
+            segment.address,
+            segment.data.len(),
+            segment.data.len(),
+            PAGE_SIZE,
+        );
+        offset += segment.data.len();
+    }
+
+    core.extend_from_slice(&notes);
+    core.resize(first_segment_offset, 0);
+
+    for segment in segments {
+        core.extend_from_slice(&segment.data);
+    }
+
+    core
+}
+
+fn notes(thread: &CrashedThread) -> Vec<u8> {
+    let signal = signal_of_exception(thread.exception_code);
+
+    let mut prstatus = Vec::with_capacity(PRSTATUS_SIZE);
+    // pr_info.si_signo, si_code and si_errno
+    push_u32(&mut prstatus, signal as u32);
+    push_u32(&mut prstatus, 0);
+    push_u32(&mut prstatus, 0);
+    // pr_cursig
+    push_u16(&mut prstatus, signal);
+    prstatus.resize(32, 0);
+    // pr_pid, the thread and the process are the same
+    push_u32(&mut prstatus, thread.pid as u32);
+    prstatus.resize(PRSTATUS_REGISTERS_OFFSET, 0);
+    // pr_reg starts with the pc at the place of the zero register
+    push_u64(&mut prstatus, thread.pc as u64);
+    for register in &thread.registers[1..] {
+        push_u64(&mut prstatus, *register as u64);
+    }
+    prstatus.resize(PRSTATUS_SIZE, 0);
+
+    let mut prpsinfo = Vec::with_capacity(PRPSINFO_SIZE);
+    // pr_state and pr_sname
+    prpsinfo.extend_from_slice(&[0, b'R']);
+    prpsinfo.resize(24, 0);
+    push_u32(&mut prpsinfo, thread.pid as u32);
+    prpsinfo.resize(40, 0);
+    // pr_fname is 16 bytes and pr_psargs 80 bytes, both null terminated
+    let name = thread.name.as_bytes();
+    prpsinfo.extend_from_slice(&name[..name.len().min(15)]);
+    prpsinfo.resize(56, 0);
+    prpsinfo.extend_from_slice(&name[..name.len().min(79)]);
+    prpsinfo.resize(PRPSINFO_SIZE, 0);
+
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRSTATUS, &prstatus);
+    push_note(&mut notes, NT_PRPSINFO, &prpsinfo);
+    notes
+}
+
+fn push_note(output: &mut Vec<u8>, note_type: u32, description: &[u8]) {
+    push_u32(output, NOTE_NAME.len() as u32);
+    push_u32(output, description.len() as u32);
+    push_u32(output, note_type);
+    output.extend_from_slice(NOTE_NAME);
+    output.resize(output.len().next_multiple_of(4), 0);
+    output.extend_from_slice(description);
+    output.resize(output.len().next_multiple_of(4), 0);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn push_program_header(
+    output: &mut Vec<u8>,
+    segment_type: u32,
+    flags: u32,
+    offset: usize,
+    virtual_address: usize,
+    file_size: usize,
+    memory_size: usize,
+    align: usize,
+) {
+    push_u32(output, segment_type);
+    push_u32(output, flags);
+    push_u64(output, offset as u64);
+    push_u64(output, virtual_address as u64);
+    // Physical address
+    push_u64(output, 0);
+    push_u64(output, file_size as u64);
+    push_u64(output, memory_size as u64);
+    push_u64(output, align as u64);
+}
+
+fn push_u16(output: &mut Vec<u8>, value: u16) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(output: &mut Vec<u8>, value: u64) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn base64(data: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (index, byte)| {
+            value | ((*byte as u32) << (16 - index * 8))
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[((value >> (18 - index * 6)) & 0x3f) as usize]);
+            } else {
+                encoded.push(b'=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{
+        base64, build, CoreDump, CrashedThread, Segment, ELF_HEADER_SIZE, PROGRAM_HEADER_SIZE,
+        PT_LOAD, PT_NOTE,
+    };
+    use crate::{
+        autogenerated::userspace_programs::PROG1,
+        klibc::elf::ElfFile,
+        memory::{page::PAGE_SIZE, page_tables::XWRMode},
+        processes::{environment::Environment, process::Process},
+    };
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_u64(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test_case]
+    fn base64_is_padded() {
+        assert_eq!(base64(b""), b"");
+        assert_eq!(base64(b"f"), b"Zg==");
+        assert_eq!(base64(b"fo"), b"Zm8=");
+        assert_eq!(base64(b"foo"), b"Zm9v");
+        assert_eq!(base64(b"\x7fELF"), b"f0VMRg==");
+    }
+
+    #[test_case]
+    fn core_file_contains_registers_and_segments() {
+        let mut registers = [0; 32];
+        registers[1] = 0x1234;
+        registers[31] = 0xdead;
+        let thread = CrashedThread {
+            pid: 42,
+            name: "segfault",
+            pc: 0x1000,
+            registers: &registers,
+            exception_code: 15,
+        };
+        let segments = [Segment {
+            address: 0x10000,
+            privileges: XWRMode::ReadWrite,
+            data: vec![0xaa; PAGE_SIZE],
+        }];
+
+        let core = build(&thread, &segments);
+
+        assert_eq!(&core[..4], b"\x7fELF");
+        // Two program headers: notes and one load segment
+        assert_eq!(u16::from_le_bytes([core[56], core[57]]), 2);
+
+        let note_header = ELF_HEADER_SIZE;
+        assert_eq!(read_u32(&core, note_header), PT_NOTE);
+        let notes_offset = read_u64(&core, note_header + 8) as usize;
+
+        let load_header = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+        assert_eq!(read_u32(&core, load_header), PT_LOAD);
+        // Readable and writable
+        assert_eq!(read_u32(&core, load_header + 4), 0b110);
+        let load_offset = read_u64(&core, load_header + 8) as usize;
+        assert_eq!(load_offset % PAGE_SIZE, 0);
+        assert_eq!(read_u64(&core, load_header + 16), 0x10000);
+        assert_eq!(&core[load_offset..], &[0xaa; PAGE_SIZE]);
+
+        // NT_PRSTATUS comes first, its description starts after the 8 byte name
+        assert_eq!(read_u32(&core, notes_offset + 8), 1);
+        let prstatus = notes_offset + 20;
+        // SIGSEGV for a store page fault
+        assert_eq!(read_u32(&core, prstatus), 11);
+        assert_eq!(read_u32(&core, prstatus + 32), 42);
+        assert_eq!(read_u64(&core, prstatus + 112), 0x1000);
+        assert_eq!(read_u64(&core, prstatus + 120), 0x1234);
+        assert_eq!(read_u64(&core, prstatus + 112 + 31 * 8), 0xdead);
+    }
+
+    #[test_case]
+    fn captured_dump_keeps_a_copy_of_the_memory() {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        let dump = CoreDump::capture(&process, 0x1000, &[0; 32], 15);
+        let pid = process.get_pid();
+        drop(process);
+
+        assert_eq!(dump.pid, pid);
+        assert_eq!(dump.name, "prog1");
+        assert!(!dump.segments.is_empty());
+        for segment in &dump.segments {
+            assert_eq!(segment.data.len() % PAGE_SIZE, 0);
+        }
+        // The program code is part of the copy
+        assert!(dump
+            .segments
+            .iter()
+            .any(|segment| segment.data.iter().any(|byte| *byte != 0)));
+    }
+}
@@ -0,0 +1,216 @@
+//! Machine readable summary of the kernel state after a panic. Every
+//! record is a JSON object on its own line. The records are enclosed
+//! by two markers such that tools can cut them out of the console output.
+
+use core::{
+    fmt::{self, Display, Write},
+    panic::PanicInfo,
+};
+
+use common::mutex::Mutex;
+
+use crate::{
+    cpu::Cpu,
+    logging::log_tail::LOG_TAIL,
+    memory,
+    per_cpu::MAX_CPUS,
+    println,
+    processes::{process::Pid, process_table},
+};
+
+pub const BEGIN_MARKER: &str = "-----BEGIN CRASH DUMP-----";
+pub const END_MARKER: &str = "-----END CRASH DUMP-----";
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy)]
+struct HartState {
+    pid: Option<Pid>,
+    sepc: usize,
+    kernel_mode: bool,
+    registers: [usize; 32],
+}
+
+// Every hart fills its own slot before it is frozen
+static HART_STATES: [Mutex<Option<HartState>>; MAX_CPUS] = [const { Mutex::new(None) }; MAX_CPUS];
+
+/// Records the registers of the last trap on the current hart
+pub fn record_current_hart() {
+    let Some(trap_frame) = Cpu::maybe_trap_frame() else {
+        return;
+    };
+    *HART_STATES[Cpu::cpu_id()].lock() = Some(HartState {
+        pid: Cpu::maybe_current_pid(),
+        sepc: Cpu::read_sepc(),
+        kernel_mode: Cpu::is_in_kernel_mode(),
+        registers: *trap_frame.registers(),
+    });
+}
+
+/// Must only be called by the panicking hart after the
+/// other harts recorded their state.
+pub fn write(info: &PanicInfo) {
+    println!("{BEGIN_MARKER}");
+
+    let location = info.location().map(JsonString);
+    println!(
+        "{{\"type\":\"panic\",\"version\":{FORMAT_VERSION},\"hart\":{},\"message\":{},\"location\":{}}}",
+        Cpu::cpu_id(),
+        JsonString(info.message()),
+        JsonOption(location)
+    );
+
+    for (hart, state) in HART_STATES.iter().enumerate() {
+        if let Some(state) = *state.lock() {
+            write_hart(hart, &state);
+        }
+    }
+
+    write_processes();
+
+    println!(
+        "{{\"type\":\"memory\",\"heap_allocated\":{},\"pages_used\":{},\"pages_total\":{}}}",
+        memory::heap::allocated_size(),
+        memory::used_heap_pages(),
+        memory::total_heap_pages()
+    );
+
+    write_log_tail();
+
+    println!("{END_MARKER}");
+}
+
+fn write_hart(hart: usize, state: &HartState) {
+    crate::print!(
+        "{{\"type\":\"hart\",\"hart\":{hart},\"pid\":{},\"kernel_mode\":{},\"sepc\":{},\"registers\":[",
+        JsonOption(state.pid),
+        state.kernel_mode,
+        state.sepc
+    );
+    for (index, register) in state.registers.iter().enumerate() {
+        if index > 0 {
+            crate::print!(",");
+        }
+        crate::print!("{register}");
+    }
+    println!("]}}");
+}
+
+fn write_processes() {
+    if !process_table::THE
+        .initialized()
+        .load(core::sync::atomic::Ordering::SeqCst)
+    {
+        return;
+    }
+    let process_table = process_table::THE.read();
+    for process in process_table.processes() {
+        // The panic might have happened while the process was locked
+        if process
+            .get_locked()
+            .load(core::sync::atomic::Ordering::SeqCst)
+        {
+            continue;
+        }
+        let process = process.lock();
+        println!(
+            "{{\"type\":\"process\",\"pid\":{},\"name\":{},\"state\":\"{:?}\",\"pc\":{}}}",
+            process.get_pid(),
+            JsonString(process.get_name()),
+            process.get_state(),
+            process.get_program_counter()
+        );
+    }
+}
+
+fn write_log_tail() {
+    // Copy first because printing appends to the log tail
+    let mut buffer = [0; 4096];
+    // Don't wait for a hart which was stopped while holding the lock
+    let tail = LOG_TAIL
+        .try_lock()
+        .map(|log_tail| log_tail.copy_to(&mut buffer))
+        .map(|length| JsonString(LossyUtf8(&buffer[..length])));
+    println!("{{\"type\":\"log\",\"tail\":{}}}", JsonOption(tail));
+}
+
+struct JsonOption<T: Display>(Option<T>);
+
+impl<T: Display> Display for JsonOption<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("null"),
+        }
+    }
+}
+
+/// Quotes and escapes the inner value without allocating
+struct JsonString<T: Display>(T);
+
+impl<T: Display> Display for JsonString<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        write!(JsonEscaper(f), "{}", self.0)?;
+        f.write_char('"')
+    }
+}
+
+struct JsonEscaper<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl Write for JsonEscaper<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '"' => self.0.write_str("\\\"")?,
+                '\\' => self.0.write_str("\\\\")?,
+                '\n' => self.0.write_str("\\n")?,
+                '\r' => self.0.write_str("\\r")?,
+                '\t' => self.0.write_str("\\t")?,
+                c if c.is_control() => write!(self.0, "\\u{:04x}", c as u32)?,
+                c => self.0.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The log tail might start in the middle of a multi byte character
+struct LossyUtf8<'a>(&'a [u8]);
+
+impl Display for LossyUtf8<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                f.write_char(char::REPLACEMENT_CHARACTER)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonOption, JsonString, LossyUtf8};
+    use alloc::format;
+
+    #[test_case]
+    fn json_strings_are_escaped() {
+        assert_eq!(
+            format!("{}", JsonString("a \"quote\"\\\n\x01")),
+            "\"a \\\"quote\\\"\\\\\\n\\u0001\""
+        );
+    }
+
+    #[test_case]
+    fn json_options() {
+        assert_eq!(format!("{}", JsonOption(Some(3))), "3");
+        assert_eq!(format!("{}", JsonOption::<u8>(None)), "null");
+    }
+
+    #[test_case]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(format!("{}", LossyUtf8(b"\xa4ok")), "\u{fffd}ok");
+    }
+}
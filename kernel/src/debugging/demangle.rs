@@ -0,0 +1,101 @@
+//! Demangling of Rust symbols in the legacy mangling scheme. The kernel
+//! symbols are demangled by nm at build time, userspace programs only
+//! carry mangled names in their symbol table.
+
+use alloc::{string::String, vec::Vec};
+
+/// Returns the symbol unchanged if it is not a mangled Rust symbol
+pub fn demangle(symbol: &str) -> String {
+    demangle_legacy(symbol).unwrap_or_else(|| symbol.into())
+}
+
+fn demangle_legacy(symbol: &str) -> Option<String> {
+    let mut rest = symbol.strip_prefix("_ZN")?.strip_suffix('E')?;
+    let mut components = Vec::new();
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let length: usize = rest[..digits].parse().ok()?;
+        components.push(rest.get(digits..digits + length)?);
+        rest = &rest[digits + length..];
+    }
+
+    if components.last().is_some_and(|last| is_hash(last)) {
+        components.pop();
+    }
+
+    let mut demangled = String::new();
+    for (index, component) in components.iter().enumerate() {
+        if index > 0 {
+            demangled.push_str("::");
+        }
+        unescape(component, &mut demangled)?;
+    }
+    Some(demangled)
+}
+
+fn is_hash(component: &str) -> bool {
+    component.len() == 17
+        && component.starts_with('h')
+        && component[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn unescape(component: &str, demangled: &mut String) -> Option<()> {
+    // Components which would start with an escape are prefixed with _
+    let mut rest = if component.starts_with("_$") {
+        &component[1..]
+    } else {
+        component
+    };
+    while let Some(c) = rest.chars().next() {
+        if let Some(tail) = rest.strip_prefix("..") {
+            demangled.push_str("::");
+            rest = tail;
+        } else if c == '$' {
+            let end = rest[1..].find('$')? + 1;
+            let replacement = match &rest[1..end] {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                escape => {
+                    let code = u32::from_str_radix(escape.strip_prefix('u')?, 16).ok()?;
+                    char::from_u32(code)?
+                }
+            };
+            demangled.push(replacement);
+            rest = &rest[end + 1..];
+        } else {
+            demangled.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::demangle;
+
+    #[test_case]
+    fn paths_are_demangled() {
+        assert_eq!(
+            demangle("_ZN4core9panicking5panic17h445eb2eb91f3d54dE"),
+            "core::panicking::panic"
+        );
+        assert_eq!(
+            demangle("_ZN70_$LT$alloc..vec..Vec$LT$T$C$A$GT$$u20$as$u20$core..ops..drop..Drop$GT$4drop17he4a1c0cfb39d0c75E"),
+            "<alloc::vec::Vec<T,A> as core::ops::drop::Drop>::drop"
+        );
+    }
+
+    #[test_case]
+    fn unmangled_symbols_are_unchanged() {
+        assert_eq!(demangle("main"), "main");
+        assert_eq!(demangle("_ZN4core"), "_ZN4core");
+        assert_eq!(demangle("_ZN99coreE"), "_ZN99coreE");
+    }
+}
@@ -283,6 +283,34 @@ impl<'a> EhFrameIterator<'a> {
                 let offset = instructions.consume_unsized_type::<UnsignedLEB128>()?.get();
                 Some(Instruction::DefCfaOffset { offset })
             }
+            consts::DW_CFA_DEF_CFA_REGISTER => {
+                let register = Self::parse_register(instructions)?;
+                Some(Instruction::DefCfaRegister { register })
+            }
+            consts::DW_CFA_OFFSET_EXTENDED => {
+                let register = Self::parse_register(instructions)?;
+                let offset = instructions.consume_unsized_type::<UnsignedLEB128>()?.get();
+                Some(Instruction::Offset { register, offset })
+            }
+            consts::DW_CFA_OFFSET_EXTENDED_SF => {
+                let register = Self::parse_register(instructions)?;
+                let offset = instructions.consume_unsized_type::<SignedLEB128>()?.get();
+                Some(Instruction::OffsetExtendedSf { register, offset })
+            }
+            consts::DW_CFA_RESTORE_EXTENDED => {
+                let register = Self::parse_register(instructions)?;
+                Some(Instruction::Restore { register })
+            }
+            consts::DW_CFA_UNDEFINED => {
+                let register = Self::parse_register(instructions)?;
+                Some(Instruction::Undefined { register })
+            }
+            consts::DW_CFA_SAME_VALUE => {
+                let register = Self::parse_register(instructions)?;
+                Some(Instruction::SameValue { register })
+            }
+            consts::DW_CFA_REMEMBER_STATE => Some(Instruction::RememberState),
+            consts::DW_CFA_RESTORE_STATE => Some(Instruction::RestoreState),
             consts::DW_CFA_NOP => Some(Instruction::Nop),
             consts::DW_CFA_ADVANCE_LOC1 => {
                 let delta = instructions.consume_sized_type::<u8>()?;
@@ -303,6 +331,11 @@ impl<'a> EhFrameIterator<'a> {
             _ => panic!("Instruction {:#x} no implemented.", instruction),
         }
     }
+
+    fn parse_register(instructions: &mut ConsumableBuffer) -> Option<u16> {
+        let register = instructions.consume_unsized_type::<UnsignedLEB128>()?.get();
+        Some(u16::try_from(register).expect("Register numbers must fit into u16"))
+    }
 }
 
 mod consts {
@@ -317,6 +350,14 @@ mod consts {
     pub const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
     pub const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
     pub const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+    pub const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+    pub const DW_CFA_RESTORE_EXTENDED: u8 = 0x06;
+    pub const DW_CFA_UNDEFINED: u8 = 0x07;
+    pub const DW_CFA_SAME_VALUE: u8 = 0x08;
+    pub const DW_CFA_REMEMBER_STATE: u8 = 0x0a;
+    pub const DW_CFA_RESTORE_STATE: u8 = 0x0b;
+    pub const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0d;
+    pub const DW_CFA_OFFSET_EXTENDED_SF: u8 = 0x11;
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -326,6 +367,12 @@ pub enum Instruction {
     Restore { register: u16 },
     DefCfa { register: u16, offset: u64 },
     DefCfaOffset { offset: u64 },
+    DefCfaRegister { register: u16 },
+    OffsetExtendedSf { register: u16, offset: i64 },
+    Undefined { register: u16 },
+    SameValue { register: u16 },
+    RememberState,
+    RestoreState,
     Nop,
 }
 
@@ -448,6 +495,34 @@ mod tests {
                 Instruction::DefCfaOffset { offset: offset_ } => {
                     matches!(self, CallFrameInstruction::DefCfaOffset { offset } if offset == offset_)
                 }
+                Instruction::DefCfaRegister {
+                    register: register_,
+                } => {
+                    matches!(self, CallFrameInstruction::DefCfaRegister { register } if register.0 == *register_)
+                }
+                Instruction::OffsetExtendedSf {
+                    register: register_,
+                    offset,
+                } => matches!(
+                self,
+                CallFrameInstruction::OffsetExtendedSf {
+                    register,
+                    factored_offset
+                }
+                if register.0 == *register_ && offset == factored_offset
+                ),
+                Instruction::Undefined {
+                    register: register_,
+                } => {
+                    matches!(self, CallFrameInstruction::Undefined { register } if register.0 == *register_)
+                }
+                Instruction::SameValue {
+                    register: register_,
+                } => {
+                    matches!(self, CallFrameInstruction::SameValue { register } if register.0 == *register_)
+                }
+                Instruction::RememberState => matches!(self, CallFrameInstruction::RememberState),
+                Instruction::RestoreState => matches!(self, CallFrameInstruction::RestoreState),
                 Instruction::Nop => matches!(self, CallFrameInstruction::Nop),
             }
         }
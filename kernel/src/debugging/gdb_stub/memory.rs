@@ -0,0 +1,221 @@
+//! Memory access on behalf of gdb. Every address is checked against the
+//! page tables first because a fault inside the stub would be fatal.
+//! Locks which the stopped code might hold are never waited for, the
+//! access fails instead.
+
+use alloc::vec::Vec;
+use core::{arch::asm, sync::atomic::Ordering};
+
+use crate::{
+    cpu::Cpu,
+    memory::{page::PAGE_SIZE, page_tables::XWRMode},
+    processes::process_table,
+    sbi::extensions::rfence_extension,
+};
+
+enum Location {
+    /// User pages are accessed through the identity mapping of the kernel
+    User { physical_address: usize },
+    Kernel {
+        writable: bool,
+        identity_mapped: bool,
+    },
+}
+
+pub fn read(address: usize, length: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(length);
+    for_each_page(address, length, |address, length| {
+        let pointer = match locate(address, false)? {
+            Location::User { physical_address } => physical_address,
+            Location::Kernel { .. } => address,
+        } as *const u8;
+        // SAFETY: The range is mapped and lies within a single page
+        let bytes = unsafe { core::slice::from_raw_parts(pointer, length) };
+        data.extend_from_slice(bytes);
+        Some(())
+    })?;
+    Some(data)
+}
+
+pub fn write(address: usize, data: &[u8]) -> Option<()> {
+    let mut offset = 0;
+    for_each_page(address, data.len(), |address, length| {
+        let chunk = &data[offset..offset + length];
+        offset += length;
+        match locate(address, true)? {
+            Location::User { physical_address } => {
+                // SAFETY: The range is mapped and lies within a single page
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        chunk.as_ptr(),
+                        physical_address as *mut u8,
+                        length,
+                    )
+                }
+            }
+            Location::Kernel { writable: true, .. } => {
+                // SAFETY: The range is mapped writable and lies within a single page
+                unsafe {
+                    core::ptr::copy_nonoverlapping(chunk.as_ptr(), address as *mut u8, length)
+                }
+            }
+            Location::Kernel {
+                writable: false,
+                identity_mapped: true,
+            } => {
+                for (index, byte) in chunk.iter().enumerate() {
+                    // SAFETY: The physical address is backed by memory
+                    unsafe { write_byte_physical(address + index, *byte) };
+                }
+            }
+            Location::Kernel {
+                writable: false,
+                identity_mapped: false,
+            } => return None,
+        }
+        Some(())
+    })?;
+    // Breakpoints are written into the instruction stream. The other
+    // harts might have fetched the old instructions already.
+    unsafe { asm!("fence.i") };
+    rfence_extension::remote_fence_i(0, rfence_extension::ALL_HARTS).assert_success();
+    Some(())
+}
+
+fn for_each_page(
+    address: usize,
+    length: usize,
+    mut f: impl FnMut(usize, usize) -> Option<()>,
+) -> Option<()> {
+    let end = address.checked_add(length)?;
+    let mut current = address;
+    while current < end {
+        let page_end = (current / PAGE_SIZE + 1) * PAGE_SIZE;
+        let chunk_end = end.min(page_end);
+        f(current, chunk_end - current)?;
+        current = chunk_end;
+    }
+    Some(())
+}
+
+/// Writes to user pages get a page which is private to the process
+fn locate(address: usize, write: bool) -> Option<Location> {
+    if let Some(physical_address) = translate_userspace_address(address, write).ok()? {
+        return Some(Location::User { physical_address });
+    }
+    let (physical_address, privileges) = Cpu::maybe_kernel_page_tables()?.translate(address)?;
+    match privileges {
+        XWRMode::ReadOnly | XWRMode::ReadExecute => Some(Location::Kernel {
+            writable: false,
+            identity_mapped: physical_address == address,
+        }),
+        XWRMode::ReadWrite | XWRMode::ReadWriteExecute => Some(Location::Kernel {
+            writable: true,
+            identity_mapped: physical_address == address,
+        }),
+        XWRMode::PointerToNextLevel | XWRMode::ExecuteOnly => None,
+    }
+}
+
+/// A user address which isn't mapped, or the table or the process is
+/// locked by the stopped code. Waiting for the lock would never end.
+struct Inaccessible;
+
+/// Returns None if it isn't an address of the current process
+fn translate_userspace_address(address: usize, write: bool) -> Result<Option<usize>, Inaccessible> {
+    if !process_table::THE.initialized().load(Ordering::SeqCst) {
+        return Ok(None);
+    }
+    let Some(pid) = Cpu::maybe_current_pid() else {
+        return Ok(None);
+    };
+    let process_table = process_table::THE.try_read().ok_or(Inaccessible)?;
+    let Some(process) = process_table.get_process(pid) else {
+        return Ok(None);
+    };
+    if process.get_locked().load(Ordering::SeqCst) {
+        return Err(Inaccessible);
+    }
+    let mut process = process.lock();
+    if !process.get_page_table().is_userspace_address(address) {
+        return Ok(None);
+    }
+    let physical_address = if write {
+        process.private_physical_address(address)
+    } else {
+        process
+            .get_page_table()
+            .translate(address)
+            .map(|(physical_address, _)| physical_address)
+    };
+    physical_address.map(Some).ok_or(Inaccessible)
+}
+
+/// Writes to pages which are mapped without write permission, e.g. the
+/// kernel text. Translation is switched off for the duration of the store.
+/// This only works for identity mapped addresses and must not touch the
+/// stack because it is not identity mapped.
+unsafe fn write_byte_physical(address: usize, byte: u8) {
+    unsafe {
+        asm!(
+            "csrrw {satp}, satp, zero",
+            "sfence.vma",
+            "sb {byte}, 0({address})",
+            "csrw satp, {satp}",
+            "sfence.vma",
+            satp = out(reg) _,
+            byte = in(reg) byte,
+            address = in(reg) address,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use crate::memory::page::PAGE_SIZE;
+
+    use super::for_each_page;
+
+    #[test_case]
+    fn ranges_are_split_at_page_boundaries() {
+        let mut chunks = Vec::new();
+        for_each_page(PAGE_SIZE - 2, PAGE_SIZE + 4, |address, length| {
+            chunks.push((address, length));
+            Some(())
+        })
+        .unwrap();
+        assert_eq!(
+            chunks,
+            [
+                (PAGE_SIZE - 2, 2),
+                (PAGE_SIZE, PAGE_SIZE),
+                (2 * PAGE_SIZE, 2)
+            ]
+        );
+    }
+
+    #[test_case]
+    fn wrapping_ranges_are_rejected() {
+        assert!(for_each_page(usize::MAX, 2, |_, _| Some(())).is_none());
+    }
+
+    // Neither page tables nor fence.i exist under miri
+    #[cfg(not(miri))]
+    #[test_case]
+    fn kernel_memory_is_read_and_written() {
+        let mut data = vec![1u8, 2, 3, 4];
+        let address = data.as_mut_ptr() as usize;
+        assert_eq!(super::read(address, 4).as_deref(), Some(&[1, 2, 3, 4][..]));
+        super::write(address + 1, &[9, 9]).unwrap();
+        assert_eq!(data, [1, 9, 9, 4]);
+    }
+
+    #[cfg(not(miri))]
+    #[test_case]
+    fn unmapped_memory_is_not_accessed() {
+        assert!(super::read(0, 4).is_none());
+        assert!(super::write(0, &[0]).is_none());
+    }
+}
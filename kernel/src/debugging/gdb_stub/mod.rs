@@ -0,0 +1,523 @@
+//! Stub for the gdb remote serial protocol on a virtio console. Breakpoints
+//! work in the kernel and in userspace. gdb can break in with Ctrl-C at any
+//! time because the console is polled on every timer interrupt.
+//!
+//! Only the hart which stopped is controlled by gdb. The other harts spin
+//! in their next timer interrupt until gdb resumes.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::{arch::asm, fmt::Write, sync::atomic::Ordering};
+
+use common::{mutex::Mutex, syscalls::trap_frame::TrapFrame};
+use packet::{Event, PacketReader};
+
+use crate::{cpu::Cpu, drivers::virtio::console::ConsoleDevice, info, println};
+
+mod memory;
+mod packet;
+mod step;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGABRT: u8 = 6;
+
+const PC_REGISTER: usize = 32;
+const PACKET_SIZE: usize = 0x1000;
+
+const EBREAK: u32 = 0x0010_0073;
+const C_EBREAK: u16 = 0x9002;
+
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+static STUB: Mutex<Option<GdbStub>> = Mutex::new(None);
+
+pub fn init(console: ConsoleDevice) {
+    *STUB.lock() = Some(GdbStub::new(console));
+    info!("gdb stub is listening on the virtio console");
+}
+
+/// Lets gdb break in. Must be called at the start of a trap handler
+/// such that the trap frame holds the interrupted context.
+pub fn poll() {
+    let mut stub = STUB.lock();
+    let Some(stub) = stub.as_mut() else {
+        return;
+    };
+    let Some(byte) = stub.console.read_byte() else {
+        return;
+    };
+    stub.pending_byte = Some(byte);
+
+    let mut trap_frame = *Cpu::current().scheduler().trap_frame();
+    let mut stop = Stop::new(trap_frame.registers_mut(), Cpu::read_sepc());
+    // gdb asks for the stop reason itself after connecting
+    stub.handle_stop(&mut stop, None);
+    Cpu::write_sepc(stop.pc);
+    *Cpu::current().scheduler_mut().trap_frame_mut() = trap_frame;
+}
+
+/// Reports a breakpoint exception to gdb and waits until gdb resumes.
+/// Returns false if there is no stub.
+pub fn handle_breakpoint(trap_frame: &mut TrapFrame) -> bool {
+    let mut stub = STUB.lock();
+    let Some(stub) = stub.as_mut() else {
+        return false;
+    };
+    let mut stop = Stop::new(trap_frame.registers_mut(), Cpu::read_sepc());
+    // An ebreak compiled into the code would trap again on resume
+    stop.skip_instruction = !stub.owns_breakpoint(stop.pc);
+    stub.handle_stop(&mut stop, Some(SIGTRAP));
+    Cpu::write_sepc(stop.pc);
+    true
+}
+
+/// Lets gdb inspect the kernel after a panic. Never returns if there
+/// is a stub because gdb can't resume the kernel.
+pub fn handle_panic() {
+    // The panic might have happened inside the stub
+    if STUB.get_locked().load(Ordering::SeqCst) {
+        return;
+    }
+    let mut stub = STUB.lock();
+    let Some(stub) = stub.as_mut() else {
+        return;
+    };
+    println!("Waiting for gdb on the virtio console ;) use 'just attach-stub'");
+
+    // Enough state for gdb to unwind from here
+    let mut registers = [0; 32];
+    let pc: usize;
+    unsafe {
+        asm!(
+            "auipc {pc}, 0",
+            "mv {sp}, sp",
+            "mv {fp}, s0",
+            pc = out(reg) pc,
+            sp = out(reg) registers[2],
+            fp = out(reg) registers[8],
+        );
+    }
+    let mut stop = Stop::new(&mut registers, pc);
+    loop {
+        stub.handle_stop(&mut stop, Some(SIGABRT));
+    }
+}
+
+struct Stop<'a> {
+    registers: &'a mut [usize; 32],
+    pc: usize,
+    skip_instruction: bool,
+}
+
+impl<'a> Stop<'a> {
+    fn new(registers: &'a mut [usize; 32], pc: usize) -> Self {
+        Self {
+            registers,
+            pc,
+            skip_instruction: false,
+        }
+    }
+
+    fn register(&self, number: usize) -> Option<usize> {
+        match number {
+            PC_REGISTER => Some(self.pc),
+            _ => self.registers.get(number).copied(),
+        }
+    }
+
+    fn set_register(&mut self, number: usize, value: usize) -> Option<()> {
+        match number {
+            // x0 is hardwired to zero
+            0 => {}
+            PC_REGISTER => self.pc = value,
+            _ => *self.registers.get_mut(number)? = value,
+        }
+        Some(())
+    }
+
+    fn instruction(&self) -> Option<u32> {
+        let low = memory::read(self.pc, 2)?;
+        let low = u16::from_le_bytes([low[0], low[1]]) as u32;
+        if step::instruction_length(low) == 2 {
+            return Some(low);
+        }
+        let high = memory::read(self.pc + 2, 2)?;
+        Some(low | ((u16::from_le_bytes([high[0], high[1]]) as u32) << 16))
+    }
+}
+
+enum Resume {
+    Continue,
+    Step,
+}
+
+struct GdbStub {
+    console: ConsoleDevice,
+    reader: PacketReader,
+    pending_byte: Option<u8>,
+    // Original bytes of the instructions which were replaced
+    breakpoints: BTreeMap<usize, Vec<u8>>,
+    step_breakpoint: Option<(usize, Vec<u8>)>,
+    last_signal: u8,
+}
+
+impl GdbStub {
+    fn new(console: ConsoleDevice) -> Self {
+        Self {
+            console,
+            reader: PacketReader::new(),
+            pending_byte: None,
+            breakpoints: BTreeMap::new(),
+            step_breakpoint: None,
+            last_signal: SIGTRAP,
+        }
+    }
+
+    fn owns_breakpoint(&self, address: usize) -> bool {
+        self.breakpoints.contains_key(&address)
+            || self
+                .step_breakpoint
+                .as_ref()
+                .is_some_and(|(step_address, _)| *step_address == address)
+    }
+
+    fn handle_stop(&mut self, stop: &mut Stop, signal: Option<u8>) {
+        if let Some((address, original)) = self.step_breakpoint.take() {
+            // Only fails if the page was unmapped in the meantime
+            let _ = memory::write(address, &original);
+        }
+        if let Some(signal) = signal {
+            self.last_signal = signal;
+            self.send_stop_reply();
+        }
+
+        let resume = loop {
+            let packet = match self.read_event() {
+                Event::Packet(packet) => packet,
+                Event::BadChecksum => {
+                    self.console.write(b"-");
+                    continue;
+                }
+                Event::Interrupt => {
+                    self.last_signal = SIGINT;
+                    self.send_stop_reply();
+                    continue;
+                }
+            };
+            self.console.write(b"+");
+            if let Some(resume) = self.handle_packet(&packet, stop) {
+                break resume;
+            }
+        };
+
+        let mut next_pc = None;
+        if stop.skip_instruction {
+            if let Some(instruction) = stop.instruction() {
+                stop.pc += step::instruction_length(instruction);
+                // Skipping the instruction already was the step
+                next_pc = Some(stop.pc);
+            }
+            stop.skip_instruction = false;
+        }
+
+        if let Resume::Step = resume {
+            let next_pc = next_pc.or_else(|| {
+                let instruction = stop.instruction()?;
+                Some(step::next_pc(stop.pc, instruction, stop.registers))
+            });
+            if let Some(next_pc) = next_pc {
+                self.insert_step_breakpoint(next_pc);
+            }
+        }
+    }
+
+    fn read_event(&mut self) -> Event {
+        loop {
+            let byte = match self.pending_byte.take() {
+                Some(byte) => byte,
+                None => match self.console.read_byte() {
+                    Some(byte) => byte,
+                    None => continue,
+                },
+            };
+            if let Some(event) = self.reader.feed(byte) {
+                return event;
+            }
+        }
+    }
+
+    fn send(&mut self, payload: &[u8]) {
+        self.console.write(&packet::encode(payload));
+    }
+
+    fn send_stop_reply(&mut self) {
+        let mut reply = b"S".to_vec();
+        packet::write_hex_byte(&mut reply, self.last_signal);
+        self.send(&reply);
+    }
+
+    fn handle_packet(&mut self, packet: &[u8], stop: &mut Stop) -> Option<Resume> {
+        let Some((command, arguments)) = packet.split_first() else {
+            self.send(b"");
+            return None;
+        };
+        let reply = match command {
+            b'?' => {
+                self.send_stop_reply();
+                return None;
+            }
+            b'g' => {
+                let mut reply = Vec::new();
+                for number in 0..=PC_REGISTER {
+                    write_register(&mut reply, stop.register(number).unwrap_or_default());
+                }
+                reply
+            }
+            b'G' => Self::status(write_registers(stop, arguments)),
+            b'p' => parse_number(arguments)
+                .and_then(|number| stop.register(number))
+                .map_or_else(error, |value| {
+                    let mut reply = Vec::new();
+                    write_register(&mut reply, value);
+                    reply
+                }),
+            b'P' => Self::status(write_register_command(stop, arguments)),
+            b'm' => parse_address_and_length(arguments)
+                .and_then(|(address, length)| memory::read(address, length))
+                .map_or_else(error, |bytes| {
+                    let mut reply = Vec::new();
+                    packet::write_hex_bytes(&mut reply, &bytes);
+                    reply
+                }),
+            b'M' => Self::status(write_memory_command(arguments)),
+            b'Z' => Self::status(self.insert_breakpoint_command(arguments)),
+            b'z' => Self::status(self.remove_breakpoint_command(arguments)),
+            b'c' | b's' => {
+                if !arguments.is_empty() {
+                    let Some(pc) = parse_number(arguments) else {
+                        self.send(&error());
+                        return None;
+                    };
+                    stop.pc = pc;
+                    stop.skip_instruction = false;
+                }
+                return Some(if *command == b'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                });
+            }
+            b'D' => {
+                self.remove_all_breakpoints();
+                self.send(b"OK");
+                return Some(Resume::Continue);
+            }
+            b'k' => {
+                self.remove_all_breakpoints();
+                return Some(Resume::Continue);
+            }
+            b'H' => b"OK".to_vec(),
+            b'q' => self.query(arguments),
+            // Unsupported packets get an empty reply
+            _ => Vec::new(),
+        };
+        self.send(&reply);
+        None
+    }
+
+    fn query(&self, arguments: &[u8]) -> Vec<u8> {
+        if arguments.starts_with(b"Supported") {
+            return format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+").into_bytes();
+        }
+        if arguments == b"Attached" {
+            return b"1".to_vec();
+        }
+        if let Some(range) = arguments.strip_prefix(b"Xfer:features:read:target.xml:") {
+            return parse_address_and_length(range).map_or_else(error, |(offset, length)| {
+                let target_description = target_description();
+                let document = target_description.as_bytes();
+                let start = offset.min(document.len());
+                let end = start.saturating_add(length).min(document.len());
+                let marker = if end == document.len() { b'l' } else { b'm' };
+                [&[marker], &document[start..end]].concat()
+            });
+        }
+        Vec::new()
+    }
+
+    fn insert_breakpoint_command(&mut self, arguments: &[u8]) -> Option<()> {
+        let (address, kind) = parse_breakpoint(arguments)?;
+        if self.breakpoints.contains_key(&address) {
+            return Some(());
+        }
+        let original = memory::read(address, kind)?;
+        let ebreak = match kind {
+            2 => C_EBREAK.to_le_bytes().to_vec(),
+            _ => EBREAK.to_le_bytes().to_vec(),
+        };
+        memory::write(address, &ebreak)?;
+        self.breakpoints.insert(address, original);
+        Some(())
+    }
+
+    fn remove_breakpoint_command(&mut self, arguments: &[u8]) -> Option<()> {
+        let (address, _) = parse_breakpoint(arguments)?;
+        let original = self.breakpoints.remove(&address)?;
+        memory::write(address, &original)
+    }
+
+    fn remove_all_breakpoints(&mut self) {
+        for (address, original) in core::mem::take(&mut self.breakpoints) {
+            let _ = memory::write(address, &original);
+        }
+    }
+
+    /// Steps are emulated by stopping at the next instruction
+    fn insert_step_breakpoint(&mut self, address: usize) {
+        // A breakpoint of gdb stops there anyway
+        if self.breakpoints.contains_key(&address) {
+            return;
+        }
+        let Some(original) = memory::read(address, 2) else {
+            return;
+        };
+        if memory::write(address, &C_EBREAK.to_le_bytes()).is_some() {
+            self.step_breakpoint = Some((address, original));
+        }
+    }
+
+    fn status(result: Option<()>) -> Vec<u8> {
+        result.map_or_else(error, |_| b"OK".to_vec())
+    }
+}
+
+fn error() -> Vec<u8> {
+    b"E01".to_vec()
+}
+
+fn write_register(output: &mut Vec<u8>, value: usize) {
+    packet::write_hex_bytes(output, &value.to_le_bytes());
+}
+
+fn write_registers(stop: &mut Stop, arguments: &[u8]) -> Option<()> {
+    let bytes = packet::decode_hex_bytes(arguments)?;
+    for (number, value) in bytes.chunks_exact(size_of::<usize>()).enumerate() {
+        let value = usize::from_le_bytes(value.try_into().ok()?);
+        stop.set_register(number, value)?;
+    }
+    Some(())
+}
+
+fn write_register_command(stop: &mut Stop, arguments: &[u8]) -> Option<()> {
+    let (number, value) = split_once(arguments, b'=')?;
+    let value = packet::decode_hex_bytes(value)?;
+    let value = usize::from_le_bytes(value.as_slice().try_into().ok()?);
+    stop.set_register(parse_number(number)?, value)
+}
+
+fn write_memory_command(arguments: &[u8]) -> Option<()> {
+    let (range, data) = split_once(arguments, b':')?;
+    let (address, length) = parse_address_and_length(range)?;
+    let data = packet::decode_hex_bytes(data)?;
+    if data.len() != length {
+        return None;
+    }
+    memory::write(address, &data)
+}
+
+/// Parses `<type>,<address>,<kind>`. Only software breakpoints are supported.
+fn parse_breakpoint(arguments: &[u8]) -> Option<(usize, usize)> {
+    let arguments = arguments.strip_prefix(b"0,")?;
+    let (address, kind) = parse_address_and_length(arguments)?;
+    matches!(kind, 2 | 4).then_some((address, kind))
+}
+
+fn parse_address_and_length(arguments: &[u8]) -> Option<(usize, usize)> {
+    let (address, length) = split_once(arguments, b',')?;
+    Some((parse_number(address)?, parse_number(length)?))
+}
+
+fn parse_number(digits: &[u8]) -> Option<usize> {
+    packet::parse_hex(digits).map(|value| value as usize)
+}
+
+fn split_once(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let position = bytes.iter().position(|byte| *byte == separator)?;
+    Some((&bytes[..position], &bytes[position + 1..]))
+}
+
+fn target_description() -> String {
+    let mut description = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><architecture>riscv:rv64</architecture>\
+         <feature name=\"org.gnu.gdb.riscv.cpu\">",
+    );
+    for name in REGISTER_NAMES {
+        let register_type = match name {
+            "ra" => "code_ptr",
+            "sp" => "data_ptr",
+            _ => "int",
+        };
+        let _ = write!(
+            description,
+            "<reg name=\"{name}\" bitsize=\"64\" type=\"{register_type}\"/>"
+        );
+    }
+    description.push_str("<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/></feature></target>");
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_address_and_length, parse_breakpoint, target_description, write_register_command,
+        write_registers, Stop, PC_REGISTER,
+    };
+
+    #[test_case]
+    fn breakpoints_are_parsed() {
+        assert_eq!(parse_breakpoint(b"0,80200000,4"), Some((0x80200000, 4)));
+        assert_eq!(parse_breakpoint(b"0,1000,2"), Some((0x1000, 2)));
+        // Only software breakpoints of instruction sizes
+        assert_eq!(parse_breakpoint(b"1,1000,4"), None);
+        assert_eq!(parse_breakpoint(b"0,1000,3"), None);
+        assert_eq!(parse_breakpoint(b"0,1000"), None);
+    }
+
+    #[test_case]
+    fn memory_ranges_are_parsed() {
+        assert_eq!(parse_address_and_length(b"ff,10"), Some((0xff, 0x10)));
+        assert_eq!(parse_address_and_length(b"ff"), None);
+        assert_eq!(parse_address_and_length(b"zz,1"), None);
+    }
+
+    #[test_case]
+    fn registers_are_written() {
+        let mut registers = [0; 32];
+        let mut stop = Stop::new(&mut registers, 0x1000);
+
+        // ra = 0x42, little endian
+        write_register_command(&mut stop, b"1=4200000000000000").unwrap();
+        write_register_command(&mut stop, b"20=0020000000000000").unwrap();
+        // x0 stays zero
+        write_register_command(&mut stop, b"0=0100000000000000").unwrap();
+        assert!(write_register_command(&mut stop, b"1=42").is_none());
+        assert_eq!(stop.register(1), Some(0x42));
+        assert_eq!(stop.register(PC_REGISTER), Some(0x2000));
+        assert_eq!(stop.register(0), Some(0));
+
+        write_registers(&mut stop, b"00000000000000000300000000000000").unwrap();
+        assert_eq!(stop.register(1), Some(3));
+    }
+
+    #[test_case]
+    fn target_description_lists_all_registers() {
+        let description = target_description();
+        assert_eq!(description.matches("<reg ").count(), 33);
+        assert!(description.contains("<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/>"));
+    }
+}
@@ -0,0 +1,195 @@
+//! Framing of the gdb remote serial protocol: `$<payload>#<checksum>`
+
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+const INTERRUPT: u8 = 0x03;
+const ESCAPE: u8 = b'}';
+const ESCAPE_XOR: u8 = 0x20;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event {
+    Packet(Vec<u8>),
+    BadChecksum,
+    /// Ctrl-C sent by gdb to stop the target
+    Interrupt,
+}
+
+enum State {
+    Idle,
+    Payload,
+    Escape,
+    Checksum(Option<u8>),
+}
+
+pub struct PacketReader {
+    state: State,
+    payload: Vec<u8>,
+    checksum: u8,
+}
+
+impl PacketReader {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            payload: Vec::new(),
+            checksum: 0,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Option<Event> {
+        match self.state {
+            State::Idle => match byte {
+                b'$' => self.start(),
+                INTERRUPT => return Some(Event::Interrupt),
+                // Acknowledgements and garbage between packets
+                _ => {}
+            },
+            State::Payload => {
+                self.checksum = self.checksum.wrapping_add(byte);
+                match byte {
+                    // A new start character aborts the current packet
+                    b'$' => self.start(),
+                    b'#' => {
+                        // The end character is not part of the checksum
+                        self.checksum = self.checksum.wrapping_sub(byte);
+                        self.state = State::Checksum(None);
+                    }
+                    ESCAPE => self.state = State::Escape,
+                    _ => self.payload.push(byte),
+                }
+            }
+            State::Escape => {
+                self.checksum = self.checksum.wrapping_add(byte);
+                self.payload.push(byte ^ ESCAPE_XOR);
+                self.state = State::Payload;
+            }
+            State::Checksum(None) => self.state = State::Checksum(Some(byte)),
+            State::Checksum(Some(high)) => {
+                self.state = State::Idle;
+                let payload = core::mem::take(&mut self.payload);
+                let expected = parse_hex(&[high, byte]);
+                return Some(if expected == Some(self.checksum as u64) {
+                    Event::Packet(payload)
+                } else {
+                    Event::BadChecksum
+                });
+            }
+        }
+        None
+    }
+
+    fn start(&mut self) {
+        self.state = State::Payload;
+        self.payload.clear();
+        self.checksum = 0;
+    }
+}
+
+/// Frames the payload. Characters with a special meaning are escaped.
+pub fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(payload.len() + 4);
+    packet.push(b'$');
+    for &byte in payload {
+        if matches!(byte, b'$' | b'#' | b'*' | ESCAPE) {
+            packet.push(ESCAPE);
+            packet.push(byte ^ ESCAPE_XOR);
+        } else {
+            packet.push(byte);
+        }
+    }
+    let checksum = packet[1..]
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_add(*byte));
+    packet.push(b'#');
+    write_hex_byte(&mut packet, checksum);
+    packet
+}
+
+pub fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |value, digit| {
+        let digit = (*digit as char).to_digit(16)?;
+        Some((value << 4) | digit as u64)
+    })
+}
+
+pub fn decode_hex_bytes(digits: &[u8]) -> Option<Vec<u8>> {
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| parse_hex(pair).map(|byte| byte as u8))
+        .collect()
+}
+
+pub fn write_hex_byte(output: &mut Vec<u8>, byte: u8) {
+    let mut digits = HexWriter(output);
+    let _ = write!(digits, "{byte:02x}");
+}
+
+pub fn write_hex_bytes(output: &mut Vec<u8>, bytes: &[u8]) {
+    for byte in bytes {
+        write_hex_byte(output, *byte);
+    }
+}
+
+struct HexWriter<'a>(&'a mut Vec<u8>);
+
+impl Write for HexWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{decode_hex_bytes, encode, parse_hex, Event, PacketReader};
+
+    fn read(input: &[u8]) -> Vec<Event> {
+        let mut reader = PacketReader::new();
+        input.iter().filter_map(|b| reader.feed(*b)).collect()
+    }
+
+    #[test_case]
+    fn packets_are_read() {
+        assert_eq!(
+            read(b"+$g#67$m80000000,4#55\x03"),
+            [
+                Event::Packet(b"g".to_vec()),
+                Event::Packet(b"m80000000,4".to_vec()),
+                Event::Interrupt,
+            ]
+        );
+        assert_eq!(read(b"$g#00"), [Event::BadChecksum]);
+    }
+
+    #[test_case]
+    fn escaped_bytes_are_restored() {
+        assert_eq!(read(b"$}\x03#80"), [Event::Packet(b"#".to_vec())]);
+    }
+
+    #[test_case]
+    fn packets_are_encoded() {
+        assert_eq!(encode(b"OK"), b"$OK#9a");
+        assert_eq!(encode(b"#"), b"$}\x03#80");
+    }
+
+    #[test_case]
+    fn hex() {
+        assert_eq!(parse_hex(b"80200000"), Some(0x8020_0000));
+        assert_eq!(parse_hex(b"x"), None);
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(
+            decode_hex_bytes(b"13000100"),
+            Some([0x13, 0, 1, 0].to_vec())
+        );
+        assert_eq!(decode_hex_bytes(b"130"), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! The hardware has no single step mode. A step is emulated by placing a
+//! temporary breakpoint on the instruction which is executed next.
+
+const OPCODE_BRANCH: u32 = 0x63;
+const OPCODE_JALR: u32 = 0x67;
+const OPCODE_JAL: u32 = 0x6f;
+
+pub fn instruction_length(instruction: u32) -> usize {
+    if instruction & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
+
+/// Returns the address of the instruction after the one at `pc`. Branches
+/// are evaluated with the current register values.
+pub fn next_pc(pc: usize, instruction: u32, registers: &[usize; 32]) -> usize {
+    let target = if instruction_length(instruction) == 4 {
+        next_pc_of_instruction(pc, instruction, registers)
+    } else {
+        next_pc_of_compressed_instruction(pc, instruction as u16, registers)
+    };
+    target.unwrap_or(pc + instruction_length(instruction))
+}
+
+fn next_pc_of_instruction(pc: usize, instruction: u32, registers: &[usize; 32]) -> Option<usize> {
+    let rs1 = registers[bits(instruction, 15, 5) as usize];
+    let rs2 = registers[bits(instruction, 20, 5) as usize];
+    match instruction & 0x7f {
+        OPCODE_JAL => {
+            let immediate = (bits(instruction, 31, 1) << 20)
+                | (bits(instruction, 21, 10) << 1)
+                | (bits(instruction, 20, 1) << 11)
+                | (bits(instruction, 12, 8) << 12);
+            Some(pc.wrapping_add_signed(sign_extend(immediate, 21)))
+        }
+        OPCODE_JALR => {
+            let immediate = sign_extend(bits(instruction, 20, 12), 12);
+            Some(rs1.wrapping_add_signed(immediate) & !1)
+        }
+        OPCODE_BRANCH => {
+            let taken = match bits(instruction, 12, 3) {
+                0b000 => rs1 == rs2,
+                0b001 => rs1 != rs2,
+                0b100 => (rs1 as isize) < (rs2 as isize),
+                0b101 => (rs1 as isize) >= (rs2 as isize),
+                0b110 => rs1 < rs2,
+                0b111 => rs1 >= rs2,
+                _ => return None,
+            };
+            let immediate = (bits(instruction, 31, 1) << 12)
+                | (bits(instruction, 25, 6) << 5)
+                | (bits(instruction, 8, 4) << 1)
+                | (bits(instruction, 7, 1) << 11);
+            taken.then(|| pc.wrapping_add_signed(sign_extend(immediate, 13)))
+        }
+        _ => None,
+    }
+}
+
+fn next_pc_of_compressed_instruction(
+    pc: usize,
+    instruction: u16,
+    registers: &[usize; 32],
+) -> Option<usize> {
+    let instruction = instruction as u32;
+    let quadrant = instruction & 0b11;
+    let funct3 = bits(instruction, 13, 3);
+    match (quadrant, funct3) {
+        // c.j
+        (0b01, 0b101) => {
+            let immediate = (bits(instruction, 12, 1) << 11)
+                | (bits(instruction, 11, 1) << 4)
+                | (bits(instruction, 9, 2) << 8)
+                | (bits(instruction, 8, 1) << 10)
+                | (bits(instruction, 7, 1) << 6)
+                | (bits(instruction, 6, 1) << 7)
+                | (bits(instruction, 3, 3) << 1)
+                | (bits(instruction, 2, 1) << 5);
+            Some(pc.wrapping_add_signed(sign_extend(immediate, 12)))
+        }
+        // c.beqz and c.bnez
+        (0b01, 0b110 | 0b111) => {
+            let rs1 = registers[8 + bits(instruction, 7, 3) as usize];
+            let taken = (rs1 == 0) == (funct3 == 0b110);
+            let immediate = (bits(instruction, 12, 1) << 8)
+                | (bits(instruction, 10, 2) << 3)
+                | (bits(instruction, 5, 2) << 6)
+                | (bits(instruction, 3, 2) << 1)
+                | (bits(instruction, 2, 1) << 5);
+            taken.then(|| pc.wrapping_add_signed(sign_extend(immediate, 9)))
+        }
+        // c.jr and c.jalr, c.ebreak has rs1 == 0
+        (0b10, 0b100) => {
+            let rs1 = bits(instruction, 7, 5) as usize;
+            let rs2 = bits(instruction, 2, 5);
+            (rs1 != 0 && rs2 == 0).then(|| registers[rs1] & !1)
+        }
+        _ => None,
+    }
+}
+
+fn bits(value: u32, start: u32, count: u32) -> u32 {
+    (value >> start) & ((1 << count) - 1)
+}
+
+fn sign_extend(value: u32, width: u32) -> isize {
+    let shift = 32 - width;
+    ((value << shift) as i32 >> shift) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{instruction_length, next_pc};
+
+    const PC: usize = 0x8020_0000;
+
+    fn registers(values: &[(usize, usize)]) -> [usize; 32] {
+        let mut registers = [0; 32];
+        for (register, value) in values {
+            registers[*register] = *value;
+        }
+        registers
+    }
+
+    #[test_case]
+    fn sequential_instructions() {
+        // addi a0, a0, 1 and c.addi a0, 1
+        assert_eq!(next_pc(PC, 0x0015_0513, &registers(&[])), PC + 4);
+        assert_eq!(next_pc(PC, 0x0505, &registers(&[])), PC + 2);
+        assert_eq!(instruction_length(0x0015_0513), 4);
+        assert_eq!(instruction_length(0x0505), 2);
+    }
+
+    #[test_case]
+    fn jumps() {
+        // jal ra, 256
+        assert_eq!(next_pc(PC, 0x1000_00ef, &registers(&[])), PC + 256);
+        // jalr zero, 8(a1)
+        assert_eq!(
+            next_pc(PC, 0x0085_8067, &registers(&[(11, 0x1001)])),
+            0x1008
+        );
+        // c.j -8
+        assert_eq!(next_pc(PC, 0xbfe5, &registers(&[])), PC - 8);
+        // c.jr ra and c.jalr a5
+        assert_eq!(next_pc(PC, 0x8082, &registers(&[(1, 0x1234)])), 0x1234);
+        assert_eq!(next_pc(PC, 0x9782, &registers(&[(15, 0x4320)])), 0x4320);
+    }
+
+    #[test_case]
+    fn branches() {
+        // beq a0, a1, -16
+        let beq = 0xfeb5_08e3;
+        assert_eq!(next_pc(PC, beq, &registers(&[(10, 1), (11, 1)])), PC - 16);
+        assert_eq!(next_pc(PC, beq, &registers(&[(10, 1)])), PC + 4);
+        // c.beqz a0, 12
+        assert_eq!(next_pc(PC, 0xc511, &registers(&[])), PC + 12);
+        assert_eq!(next_pc(PC, 0xc511, &registers(&[(10, 1)])), PC + 2);
+    }
+
+    #[test_case]
+    fn compressed_ebreak_is_not_a_jump() {
+        assert_eq!(next_pc(PC, 0x9002, &registers(&[])), PC + 2);
+    }
+}
@@ -0,0 +1,54 @@
+use common::lock_debug::{self, LockDebugHooks, LockOwner, LockViolation, BACKTRACE_DEPTH};
+
+use crate::{cmdline, cpu::Cpu, debugging::backtrace, info, initcall, io::uart::CONSOLE_UART};
+
+static HOOKS: LockDebugHooks = LockDebugHooks {
+    current_owner,
+    capture_backtrace,
+    report_violation,
+};
+
+initcall!(Postcore, init);
+
+// Capturing a backtrace on every lock acquisition is expensive, so it
+// is only done when `lock_debug` is passed on the kernel command line.
+pub fn init() {
+    if cmdline::get().lock_debug {
+        info!("Lock debugging enabled");
+        lock_debug::enable(&HOOKS);
+    }
+}
+
+fn current_owner() -> LockOwner {
+    LockOwner {
+        hart: Cpu::cpu_id(),
+        pid: Cpu::maybe_current_pid(),
+    }
+}
+
+fn capture_backtrace(addresses: &mut [usize; BACKTRACE_DEPTH]) {
+    backtrace::capture(addresses);
+}
+
+fn report_violation(violation: &LockViolation) -> ! {
+    // The violation might have happened on the uart lock itself
+    unsafe {
+        CONSOLE_UART.disarm();
+    }
+
+    let (held, requested) = match violation {
+        LockViolation::Recursive {
+            held, requested, ..
+        }
+        | LockViolation::OrderInversion {
+            held, requested, ..
+        } => (held, requested),
+    };
+
+    info!("Lock held by {held}");
+    backtrace::print_addresses(held.backtrace());
+    info!("Lock requested by {requested}");
+    backtrace::print_addresses(requested.backtrace());
+
+    panic!("{violation}");
+}
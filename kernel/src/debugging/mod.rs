@@ -1,7 +1,20 @@
-use crate::{cpu::Cpu, info, klibc::sizes::MiB, processes::process_table};
+use crate::{
+    cpu::Cpu,
+    drivers::platform,
+    info,
+    klibc::sizes::MiB,
+    processes::{idle, process_table, scheduler, timer},
+};
 
 pub mod backtrace;
+pub mod boot_timing;
+pub mod core_dump;
+pub mod crash_dump;
+pub mod demangle;
 mod eh_frame_parser;
+pub mod gdb_stub;
+pub mod lock_debug;
+pub mod profiler;
 pub mod symbols;
 mod unwinder;
 
@@ -20,7 +33,23 @@ pub fn dump_current_state() {
         used_heap_pages, total_heap_pages
     );
 
-    process_table::THE.lock().dump();
+    info!(
+        "Page cache: {} pages",
+        crate::memory::page_cache::cached_pages()
+    );
+
+    info!(
+        "Context switches on this cpu: {}",
+        scheduler::context_switches_on_current_cpu()
+    );
+    info!(
+        "Idle time on this cpu: {} ms",
+        idle::idle_clocks_on_current_cpu() / timer::milliseconds_to_clocks(1)
+    );
+
+    platform::dump();
+
+    process_table::THE.read().dump();
     Cpu::current_process().with_lock(|p| {
         info!(
             "Current Process: PID={} NAME={} STATE={:?}",
@@ -0,0 +1,260 @@
+//! A sampling profiler. While it runs, every timer interrupt records the
+//! interrupted program counter and process into the buffer of its hart.
+//! A periodic timer guarantees an interrupt per interval even if no
+//! process is preempted meanwhile.
+//!
+//! The flat profile of kernel and userspace functions is listed in
+//! /proc/profile, the samples are kept until the profiler is started
+//! again.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use common::mutex::SpinLockIrqSave;
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use crate::{
+    cpu::Cpu,
+    debugging::{demangle::demangle, symbols},
+    klibc::elf::ElfFile,
+    per_cpu::MAX_CPUS,
+    processes::{process::Pid, programs, timer},
+};
+
+const MAX_SAMPLES_PER_CPU: usize = 8192;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static INTERVAL_MILLISECONDS: AtomicU64 = AtomicU64::new(1);
+static DROPPED_SAMPLES: AtomicU64 = AtomicU64::new(0);
+
+// Indexed by the hart. Samples are taken in interrupt handlers which
+// can't use the per_cpu data of other harts, the report needs all of
+// them.
+static SAMPLES: [SpinLockIrqSave<Vec<Sample>>; MAX_CPUS] =
+    [const { SpinLockIrqSave::new(Vec::new()) }; MAX_CPUS];
+
+// Whether the sampling timer of the hart is pending
+static ARMED: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+// Processes might have exited when the report is made
+static PROCESS_NAMES: SpinLockIrqSave<BTreeMap<Pid, String>> =
+    SpinLockIrqSave::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    address: usize,
+    // None if the kernel was interrupted
+    pid: Option<Pid>,
+}
+
+/// Discards the previous samples. Returns false if the profiler is
+/// already running.
+pub fn start(interval_milliseconds: u64) -> bool {
+    if RUNNING.load(Ordering::Relaxed) {
+        return false;
+    }
+    for samples in &SAMPLES {
+        *samples.lock() = Vec::new();
+    }
+    PROCESS_NAMES.lock().clear();
+    DROPPED_SAMPLES.store(0, Ordering::Relaxed);
+    INTERVAL_MILLISECONDS.store(interval_milliseconds, Ordering::Relaxed);
+    RUNNING.store(true, Ordering::Relaxed);
+    arm_current_hart();
+    true
+}
+
+/// Returns false if the profiler isn't running
+pub fn stop() -> bool {
+    RUNNING.swap(false, Ordering::Relaxed)
+}
+
+/// Called by every timer interrupt. Must not take any lock which
+/// might be held by the interrupted code.
+pub fn sample() {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+    let sample = Sample {
+        address: Cpu::read_sepc(),
+        pid: if Cpu::is_in_kernel_mode() {
+            None
+        } else {
+            Cpu::maybe_current_pid()
+        },
+    };
+    SAMPLES[Cpu::cpu_id()].with_lock(|mut samples| {
+        if samples.len() < MAX_SAMPLES_PER_CPU {
+            samples.push(sample);
+        } else {
+            DROPPED_SAMPLES.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+    arm_current_hart();
+}
+
+/// The scheduler notes every process it switches to
+pub fn note_process(pid: Pid, name: &str) {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+    PROCESS_NAMES
+        .lock()
+        .entry(pid)
+        .or_insert_with(|| name.into());
+}
+
+fn arm_current_hart() {
+    let hart = Cpu::cpu_id();
+    if !ARMED[hart].swap(true, Ordering::Relaxed) {
+        arm(hart);
+    }
+}
+
+fn arm(hart: usize) {
+    timer::add_timer_in(INTERVAL_MILLISECONDS.load(Ordering::Relaxed), move || {
+        // The timers of a hart which goes offline are adopted by another
+        // hart which has a sampling timer of its own
+        if RUNNING.load(Ordering::Relaxed) && Cpu::cpu_id() == hart {
+            arm(hart);
+        } else {
+            ARMED[hart].store(false, Ordering::Relaxed);
+        }
+    });
+}
+
+/// The samples of all harts as a flat profile, one function per line
+/// sorted by the number of samples
+pub fn report() -> String {
+    let mut samples = Vec::new();
+    for hart_samples in &SAMPLES {
+        samples.extend_from_slice(&hart_samples.lock());
+    }
+    let names = PROCESS_NAMES.lock().clone();
+    flat_profile(
+        &samples,
+        DROPPED_SAMPLES.load(Ordering::Relaxed),
+        |pid, addresses| match pid {
+            None => addresses
+                .iter()
+                .map(|address| kernel_function(*address))
+                .collect(),
+            Some(pid) => user_functions(pid, names.get(&pid), addresses),
+        },
+    )
+}
+
+fn kernel_function(address: usize) -> String {
+    match symbols::get_symbol(address) {
+        Some(symbol) => format!("kernel: {}", symbol.symbol),
+        None => format!("kernel: {address:#x}"),
+    }
+}
+
+fn user_functions(pid: Pid, name: Option<&String>, addresses: &[usize]) -> Vec<String> {
+    let Some(name) = name else {
+        return addresses
+            .iter()
+            .map(|address| format!("PID {pid}: {address:#x}"))
+            .collect();
+    };
+    let program = programs::find(name);
+    let elf = program
+        .as_ref()
+        .map(|program| ElfFile::parse(program.data()).expect("Programs must be valid ELF files"));
+    let symbol_table = elf.as_ref().and_then(|elf| elf.get_symbol_table());
+    addresses
+        .iter()
+        .map(|address| {
+            match symbol_table
+                .as_ref()
+                .and_then(|table| table.find_function(*address))
+            {
+                Some((symbol, _)) => format!("{name}: {}", demangle(symbol)),
+                None => format!("{name}: {address:#x}"),
+            }
+        })
+        .collect()
+}
+
+/// Every distinct address is symbolized once, the addresses of a
+/// process are passed at once
+fn flat_profile(
+    samples: &[Sample],
+    dropped: u64,
+    mut symbolize: impl FnMut(Option<Pid>, &[usize]) -> Vec<String>,
+) -> String {
+    let mut addresses: BTreeMap<Option<Pid>, BTreeMap<usize, usize>> = BTreeMap::new();
+    for sample in samples {
+        *addresses
+            .entry(sample.pid)
+            .or_default()
+            .entry(sample.address)
+            .or_default() += 1;
+    }
+
+    let mut functions: BTreeMap<String, usize> = BTreeMap::new();
+    for (pid, counts) in addresses {
+        let unique: Vec<usize> = counts.keys().copied().collect();
+        for (function, count) in symbolize(pid, &unique)
+            .into_iter()
+            .zip(counts.into_values())
+        {
+            *functions.entry(function).or_default() += count;
+        }
+    }
+    let mut functions: Vec<(String, usize)> = functions.into_iter().collect();
+    functions.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+    });
+
+    let mut report = format!("Samples: {} Dropped: {dropped}\n", samples.len());
+    for (function, count) in functions {
+        let percent = count as f64 * 100.0 / samples.len() as f64;
+        let _ = writeln!(report, "{count:>7} {percent:>5.1}% {function}");
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, vec::Vec};
+
+    use super::{flat_profile, Sample};
+
+    #[test_case]
+    fn samples_are_grouped_by_function() {
+        let sample = |address, pid| Sample { address, pid };
+        let samples = [
+            sample(0x100, None),
+            sample(0x104, None),
+            sample(0x200, None),
+            sample(0x100, Some(3)),
+            sample(0x100, None),
+        ];
+        let mut symbolized = Vec::new();
+        let report = flat_profile(&samples, 2, |pid, addresses| {
+            symbolized.push((pid, addresses.to_vec()));
+            addresses
+                .iter()
+                .map(|address| match pid {
+                    None => format!("kernel: {}", address & !0xff),
+                    Some(pid) => format!("PID {pid}"),
+                })
+                .collect()
+        });
+
+        assert_eq!(
+            symbolized,
+            [
+                (None, [0x100, 0x104, 0x200].to_vec()),
+                (Some(3), [0x100].to_vec())
+            ]
+        );
+        assert_eq!(
+            report,
+            "Samples: 5 Dropped: 2\n      3  60.0% kernel: 256\n      1  20.0% PID 3\n      1  20.0% kernel: 512\n"
+        );
+    }
+}
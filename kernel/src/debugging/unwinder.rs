@@ -70,6 +70,7 @@ impl<'a> Unwinder<'a> {
         let mut current_address = self.fde.pc_begin;
         debug!("current address: {current_address}");
         let mut current_row = self.last_row();
+        let mut remembered_rows: ArrayVec<Row, 8> = ArrayVec::default();
         for instruction in instructions {
             match instruction {
                 Instruction::AdvanceLoc { delta } => {
@@ -106,6 +107,43 @@ impl<'a> Unwinder<'a> {
                     debug!("DefCfaOffset(offset={})", *offset);
                     current_row.cfa_offset = *offset as i64;
                 }
+                Instruction::DefCfaRegister { register } => {
+                    debug!("DefCfaRegister(register={})", *register);
+                    current_row.cfa_register = *register as u64;
+                }
+                Instruction::OffsetExtendedSf { register, offset } => {
+                    debug!(
+                        "OffsetExtendedSf(register={}, offset={})",
+                        *register, *offset
+                    );
+                    let real_offset = offset.wrapping_mul(self.fde.cie.data_alignment_factor);
+                    current_row.register_rules[*register as usize] =
+                        RegisterRule::Offset(real_offset);
+                }
+                Instruction::Undefined { register } => {
+                    debug!("Undefined(register={})", *register);
+                    current_row.register_rules[*register as usize] = RegisterRule::Undefined;
+                }
+                Instruction::SameValue { register } => {
+                    debug!("SameValue(register={})", *register);
+                    current_row.register_rules[*register as usize] = RegisterRule::SameValue;
+                }
+                Instruction::RememberState => {
+                    debug!("RememberState");
+                    remembered_rows
+                        .push(current_row.clone())
+                        .expect("Not enough space to remember the state.");
+                }
+                Instruction::RestoreState => {
+                    debug!("RestoreState");
+                    // Only the rules are restored, the location stays
+                    let remembered = remembered_rows
+                        .pop()
+                        .expect("There must be a remembered state.");
+                    current_row.cfa_register = remembered.cfa_register;
+                    current_row.cfa_offset = remembered.cfa_offset;
+                    current_row.register_rules = remembered.register_rules;
+                }
                 Instruction::Nop => {}
             }
         }
@@ -156,7 +194,11 @@ impl Row {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RegisterRule {
+    /// No rule was given, the register is treated as unchanged
     None,
+    /// The register can't be recovered in the caller
+    Undefined,
+    SameValue,
     Offset(i64),
 }
 
@@ -167,7 +209,104 @@ impl PartialEq<gimli::RegisterRule<usize>> for RegisterRule {
             RegisterRule::Offset(offset) => {
                 matches!(other, gimli::RegisterRule::Offset(control_offset) if offset == control_offset)
             }
-            _ => panic!("Register rule not implemented."),
+            RegisterRule::SameValue => matches!(other, gimli::RegisterRule::SameValue),
+            RegisterRule::None | RegisterRule::Undefined => {
+                matches!(other, gimli::RegisterRule::Undefined)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec, vec::Vec};
+
+    use super::{RegisterRule, Unwinder};
+    use crate::debugging::eh_frame_parser::{Instruction, ParsedCIE, ParsedFDE};
+
+    const SP: u16 = 2;
+    const RA: u16 = 1;
+    const FP: u16 = 8;
+
+    fn fde(instructions: Vec<Instruction>) -> ParsedFDE<'static> {
+        let cie = ParsedCIE {
+            version: 1,
+            augmentation_string: "zR",
+            address_size: 8,
+            code_alignment_factor: 1,
+            data_alignment_factor: -8,
+            augmentation_data: None,
+            return_address_register: RA as u64,
+            initial_instructions: vec![Instruction::DefCfa {
+                register: SP,
+                offset: 0,
+            }],
+        };
+        ParsedFDE {
+            cie: Arc::new(cie),
+            pc_begin: 0x1000,
+            address_range: 0x40,
+            augmentation_data: None,
+            instructions,
         }
     }
+
+    #[test_case]
+    fn epilogue_state_is_remembered_and_restored() {
+        let fde = fde(vec![
+            Instruction::AdvanceLoc { delta: 4 },
+            Instruction::DefCfaOffset { offset: 16 },
+            Instruction::Offset {
+                register: RA,
+                offset: 1,
+            },
+            Instruction::Offset {
+                register: FP,
+                offset: 2,
+            },
+            Instruction::AdvanceLoc { delta: 4 },
+            Instruction::DefCfaRegister { register: FP },
+            Instruction::AdvanceLoc { delta: 0x10 },
+            // Early return in the middle of the function
+            Instruction::RememberState,
+            Instruction::DefCfa {
+                register: SP,
+                offset: 0,
+            },
+            Instruction::Restore { register: RA },
+            Instruction::Undefined { register: FP },
+            Instruction::AdvanceLoc { delta: 4 },
+            Instruction::RestoreState,
+        ]);
+        let unwinder = Unwinder::new(&fde);
+        assert_eq!(unwinder.rows().len(), 5);
+
+        let prologue = unwinder.find_row_for_address(0x1002);
+        assert_eq!((prologue.cfa_register, prologue.cfa_offset), (SP as u64, 0));
+        assert_eq!(prologue.register_rules[RA as usize], RegisterRule::None);
+
+        let body = unwinder.find_row_for_address(0x1010);
+        assert_eq!((body.cfa_register, body.cfa_offset), (FP as u64, 16));
+        assert_eq!(body.register_rules[RA as usize], RegisterRule::Offset(-8));
+        assert_eq!(body.register_rules[FP as usize], RegisterRule::Offset(-16));
+
+        let early_return = unwinder.find_row_for_address(0x101a);
+        assert_eq!(
+            (early_return.cfa_register, early_return.cfa_offset),
+            (SP as u64, 0)
+        );
+        assert_eq!(early_return.register_rules[RA as usize], RegisterRule::None);
+        assert_eq!(
+            early_return.register_rules[FP as usize],
+            RegisterRule::Undefined
+        );
+
+        // The rest of the function has the rules from before the return
+        let rest = unwinder.find_row_for_address(0x1030);
+        assert_eq!(rest.start_address, 0x101c);
+        assert_eq!(rest.end_address, 0x1040);
+        assert_eq!(rest.cfa_register, FP as u64);
+        assert_eq!(rest.cfa_offset, 16);
+        assert_eq!(rest.register_rules, body.register_rules);
+    }
 }
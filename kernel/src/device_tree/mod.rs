@@ -10,6 +10,8 @@ use core::{
     slice,
 };
 
+pub mod tree;
+
 const FDT_MAGIC: u32 = 0xd00dfeed;
 const FDT_VERSION: u32 = 17;
 
@@ -46,7 +48,7 @@ pub struct DeviceTree {
 }
 
 impl DeviceTree {
-    fn new(device_tree_pointer: *const ()) -> &'static Self {
+    pub fn new(device_tree_pointer: *const ()) -> &'static Self {
         let magic = device_tree_pointer as *const BigEndian<u32>;
         assert!(magic.is_aligned(), "Device tree must be 4 byte aligned");
         assert!(!device_tree_pointer.is_null());
@@ -87,7 +89,7 @@ impl DeviceTree {
             loop {
                 let entry = &*start.add(len);
                 // The last entry is marked with address and size set to 0
-                if entry.address == 0 && entry.size == 0 {
+                if entry.address.get() == 0 && entry.size.get() == 0 {
                     break;
                 }
                 len += 1;
@@ -128,8 +130,15 @@ impl DeviceTree {
 #[repr(C)]
 #[derive(Debug)]
 pub struct ReserveEntry {
-    address: u64,
-    size: u64,
+    address: BigEndian<u64>,
+    size: BigEndian<u64>,
+}
+
+impl ReserveEntry {
+    pub fn range(&self) -> Range<*const u8> {
+        let start = self.address.get() as usize as *const u8;
+        start..start.wrapping_add(self.size.get() as usize)
+    }
 }
 
 impl Display for ReserveEntry {
@@ -137,9 +146,9 @@ impl Display for ReserveEntry {
         write!(
             f,
             "RESERVED: {:#x} - {:#x} (size: {:#x})",
-            self.address,
-            self.address + self.size - 1,
-            self.size
+            self.address.get(),
+            self.address.get() + self.size.get() - 1,
+            self.size.get()
         )
     }
 }
@@ -243,6 +252,10 @@ impl<'a> Node<'a> {
         None
     }
 
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
     pub fn get_property(&self, name: &'a str) -> Option<ConsumableBuffer<'a>> {
         for token in self {
             match token {
@@ -347,10 +360,10 @@ pub fn get_devicetree_range() -> Range<*const u8> {
 pub fn init(device_tree_pointer: *const ()) {
     info!("Initialize device tree at {device_tree_pointer:p}");
     let device_tree = DeviceTree::new(device_tree_pointer);
-    assert!(
-        device_tree.get_reserved_areas().is_empty(),
-        "There should be no reserved memory regions"
-    );
+    // Bootloaders like U-Boot reserve the device tree itself
+    for area in device_tree.get_reserved_areas() {
+        info!("{area}");
+    }
     THE.initialize(device_tree);
 }
 
@@ -363,7 +376,7 @@ mod tests {
     };
     use common::{big_endian::BigEndian, include_bytes_align_as};
 
-    const DTB: &[u8] = include_bytes_align_as!(Header, "test/test_data/dtb");
+    const DTB: &[u8] = include_bytes_align_as!(Header, "../test/test_data/dtb");
 
     fn get_root_node() -> Node<'static> {
         let device_tree = DeviceTree::new(DTB.as_ptr() as *const ());
@@ -0,0 +1,448 @@
+//! A parsed version of the device tree. Walking the raw tokens is fine
+//! during early boot, but drivers want to look up nodes by path and read
+//! typed properties. The tree is built once after the heap is available
+//! and the nodes and properties reference the data of the flattened
+//! device tree.
+
+use alloc::vec::Vec;
+use common::runtime_initialized::RuntimeInitializedData;
+
+use super::{FdtToken, Node, Reg};
+
+// Defaults as defined by the device tree specification
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+static TREE: RuntimeInitializedData<DeviceTreeNode<'static>> = RuntimeInitializedData::new();
+
+#[derive(Debug)]
+pub struct DeviceTreeNode<'a> {
+    name: &'a str,
+    properties: Vec<Property<'a>>,
+    children: Vec<DeviceTreeNode<'a>>,
+    /// Cells of the reg property of this node
+    parent_address_cells: u32,
+    parent_size_cells: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Property<'a> {
+    name: &'a str,
+    value: &'a [u8],
+}
+
+/// One entry of a ranges property. The child address can span up to
+/// four cells, e.g. PCI uses three cells: a bitfield followed by the
+/// 64 bit address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressRange {
+    pub child_address: u128,
+    pub parent_address: usize,
+    pub size: usize,
+}
+
+impl<'a> DeviceTreeNode<'a> {
+    pub fn parse(root_node: Node<'a>) -> Self {
+        let mut tokens = root_node;
+        Self::parse_node("", &mut tokens, DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS)
+    }
+
+    /// The tokens start right behind the begin token of the node and
+    /// are consumed until the matching end token.
+    fn parse_node(
+        name: &'a str,
+        tokens: &mut impl Iterator<Item = FdtToken<'a>>,
+        parent_address_cells: u32,
+        parent_size_cells: u32,
+    ) -> Self {
+        let mut node = Self {
+            name,
+            properties: Vec::new(),
+            children: Vec::new(),
+            parent_address_cells,
+            parent_size_cells,
+        };
+        while let Some(token) = tokens.next() {
+            match token {
+                FdtToken::BeginNode(child_name) => {
+                    // Properties always come before the child nodes
+                    let child = Self::parse_node(
+                        child_name,
+                        tokens,
+                        node.address_cells(),
+                        node.size_cells(),
+                    );
+                    node.children.push(child);
+                }
+                FdtToken::Prop(name, value) => node.properties.push(Property {
+                    name,
+                    value: value.buffer(),
+                }),
+                FdtToken::Nop => {}
+                FdtToken::EndNode | FdtToken::End => break,
+            }
+        }
+        node
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The name without the unit address, e.g. uart for uart@10000000
+    pub fn base_name(&self) -> &'a str {
+        self.name.split('@').next().unwrap_or(self.name)
+    }
+
+    /// Looks up a node by its absolute path, e.g. /soc/uart@10000000.
+    /// A path component without unit address matches the first node
+    /// with that name.
+    pub fn find(&self, path: &str) -> Option<&Self> {
+        path.split('/')
+            .filter(|component| !component.is_empty())
+            .try_fold(self, |node, component| node.child(component))
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Self> {
+        let matches = |child: &&Self| {
+            child.name == name || (!name.contains('@') && child.base_name() == name)
+        };
+        self.children.iter().find(matches)
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &Self> {
+        self.children.iter()
+    }
+
+    /// This node and all nodes below it in depth-first order
+    pub fn walk(&self) -> impl Iterator<Item = &Self> {
+        let mut stack = alloc::vec![self];
+        core::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    pub fn property(&self, name: &str) -> Option<Property<'a>> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+            .copied()
+    }
+
+    /// Cells of the addresses of the child nodes
+    pub fn address_cells(&self) -> u32 {
+        self.property("#address-cells")
+            .and_then(|property| property.u32())
+            .unwrap_or(DEFAULT_ADDRESS_CELLS)
+    }
+
+    /// Cells of the sizes of the child nodes
+    pub fn size_cells(&self) -> u32 {
+        self.property("#size-cells")
+            .and_then(|property| property.u32())
+            .unwrap_or(DEFAULT_SIZE_CELLS)
+    }
+
+    /// Nodes without a status property are enabled as well
+    pub fn is_enabled(&self) -> bool {
+        self.property("status")
+            .and_then(|status| status.str())
+            .is_none_or(|status| status == "okay" || status == "ok")
+    }
+
+    /// Returns the entries of the compatible property, most specific first.
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.property("compatible")
+            .into_iter()
+            .flat_map(|property| property.strings())
+    }
+
+    pub fn reg(&self) -> Vec<Reg> {
+        let Some(property) = self.property("reg") else {
+            return Vec::new();
+        };
+        if self.parent_address_cells + self.parent_size_cells == 0 {
+            return Vec::new();
+        }
+        let mut cells = property.u32_array();
+        let mut regs = Vec::new();
+        while let (Some(address), Some(size)) = (
+            read_cells(&mut cells, self.parent_address_cells),
+            read_cells(&mut cells, self.parent_size_cells),
+        ) {
+            regs.push(Reg {
+                address: address as usize,
+                size: size as usize,
+            });
+        }
+        regs
+    }
+
+    /// The first entry of the reg property
+    pub fn first_reg(&self) -> Option<Reg> {
+        self.reg().into_iter().next()
+    }
+
+    /// Translation of the child address space into the address space of
+    /// the parent. An empty ranges property (identity mapping) results
+    /// in no entries.
+    pub fn ranges(&self) -> Vec<AddressRange> {
+        let Some(property) = self.property("ranges") else {
+            return Vec::new();
+        };
+        if self.address_cells() + self.parent_address_cells + self.size_cells() == 0 {
+            return Vec::new();
+        }
+        let mut cells = property.u32_array();
+        let mut ranges = Vec::new();
+        while let (Some(child_address), Some(parent_address), Some(size)) = (
+            read_cells(&mut cells, self.address_cells()),
+            read_cells(&mut cells, self.parent_address_cells),
+            read_cells(&mut cells, self.size_cells()),
+        ) {
+            ranges.push(AddressRange {
+                child_address,
+                parent_address: parent_address as usize,
+                size: size as usize,
+            });
+        }
+        ranges
+    }
+
+    /// Returns the first interrupt specifier. Only single cell
+    /// specifiers are supported which is what the PLIC uses.
+    pub fn interrupt(&self) -> Option<u32> {
+        self.property("interrupts")?.u32_array().next()
+    }
+
+    /// Checks the riscv,isa-extensions list and falls back to
+    /// the older riscv,isa string (e.g. rv64imac_zicsr_sstc).
+    /// Single letter extensions are part of the base in the string.
+    pub fn has_riscv_isa_extension(&self, extension: &str) -> bool {
+        if let Some(extensions) = self.property("riscv,isa-extensions") {
+            return extensions
+                .strings()
+                .any(|e| e.eq_ignore_ascii_case(extension));
+        }
+        self.property("riscv,isa")
+            .and_then(|property| property.str())
+            .is_some_and(|isa| {
+                let mut parts = isa.split('_');
+                let base = parts.next().unwrap_or_default();
+                if let [letter] = extension.as_bytes() {
+                    // Skip rv32 or rv64
+                    return base.get(4..).is_some_and(|letters| {
+                        letters.bytes().any(|l| l.eq_ignore_ascii_case(letter))
+                    });
+                }
+                parts.any(|e| e.eq_ignore_ascii_case(extension))
+            })
+    }
+}
+
+/// Values wider than 128 bits or with a missing cell result in None
+fn read_cells(cells: &mut impl Iterator<Item = u32>, count: u32) -> Option<u128> {
+    if count > 4 {
+        return None;
+    }
+    (0..count).try_fold(0u128, |value, _| {
+        Some((value << 32) | cells.next()? as u128)
+    })
+}
+
+impl<'a> Property<'a> {
+    pub fn u32(&self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.value.try_into().ok()?))
+    }
+
+    /// Accepts values which are encoded in one or two cells
+    pub fn u64(&self) -> Option<u64> {
+        match self.value.len() {
+            4 => self.u32().map(u64::from),
+            8 => Some(u64::from_be_bytes(self.value.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// Trailing bytes which don't form a whole cell are ignored
+    pub fn u32_array(&self) -> impl Iterator<Item = u32> + use<'a> {
+        self.value
+            .chunks_exact(size_of::<u32>())
+            .map(|chunk| u32::from_be_bytes(chunk.try_into().expect("chunks are 4 bytes")))
+    }
+
+    pub fn str(&self) -> Option<&'a str> {
+        let string = self.value.strip_suffix(&[0])?;
+        core::str::from_utf8(string).ok()
+    }
+
+    /// Entries of a string list property
+    pub fn strings(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        self.value
+            .split(|&b| b == 0)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| core::str::from_utf8(entry).ok())
+    }
+}
+
+/// Builds the tree from the raw device tree. Needs the heap.
+pub fn init() {
+    TREE.initialize(DeviceTreeNode::parse(super::THE.root_node()));
+}
+
+pub fn root() -> &'static DeviceTreeNode<'static> {
+    &TREE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressRange, DeviceTreeNode};
+    use crate::device_tree::{DeviceTree, Header};
+    use common::include_bytes_align_as;
+
+    const DTB: &[u8] = include_bytes_align_as!(Header, "../test/test_data/dtb");
+
+    fn get_tree() -> DeviceTreeNode<'static> {
+        DeviceTreeNode::parse(DeviceTree::new(DTB.as_ptr() as *const ()).root_node())
+    }
+
+    #[test_case]
+    fn path_lookup() {
+        let tree = get_tree();
+        assert_eq!(tree.find("/").map(|node| node.name()), Some(""));
+        assert_eq!(
+            tree.find("/soc/serial@10000000").map(|node| node.name()),
+            Some("serial@10000000")
+        );
+        assert_eq!(
+            tree.find("/cpus/cpu").map(|node| node.name()),
+            Some("cpu@0")
+        );
+        assert!(tree.find("/soc/serial@10000001").is_none());
+        assert!(tree.find("/serial@10000000").is_none());
+        assert_eq!(
+            tree.find("/chosen")
+                .and_then(|chosen| chosen.property("stdout-path"))
+                .and_then(|property| property.str()),
+            Some("/soc/serial@10000000")
+        );
+    }
+
+    #[test_case]
+    fn typed_properties() {
+        let tree = get_tree();
+        assert_eq!(
+            tree.property("compatible").and_then(|p| p.str()),
+            Some("riscv-virtio")
+        );
+        let cpus = tree.find("/cpus").expect("cpus node must exist");
+        assert_eq!(
+            cpus.property("timebase-frequency").and_then(|p| p.u32()),
+            Some(10_000_000)
+        );
+        assert_eq!(
+            cpus.property("timebase-frequency").and_then(|p| p.u64()),
+            Some(10_000_000)
+        );
+        let plic = tree.find("/soc/plic").expect("plic node must exist");
+        assert_eq!(
+            plic.property("riscv,ndev").and_then(|p| p.u32()),
+            Some(0x5f)
+        );
+        assert_eq!(plic.property("foobar"), None);
+    }
+
+    #[test_case]
+    fn isa_extensions() {
+        let cpu0 = get_tree();
+        let cpu0 = cpu0.find("/cpus/cpu@0").expect("cpu node must exist");
+        assert!(cpu0.has_riscv_isa_extension("sstc"));
+        assert!(cpu0.has_riscv_isa_extension("zicsr"));
+        assert!(!cpu0.has_riscv_isa_extension("rv64imafdch"));
+        assert!(!cpu0.has_riscv_isa_extension("svpbmt"));
+        assert!(cpu0.has_riscv_isa_extension("h"));
+        assert!(cpu0.has_riscv_isa_extension("F"));
+        assert!(!cpu0.has_riscv_isa_extension("v"));
+    }
+
+    #[test_case]
+    fn compatible() {
+        let tree = get_tree();
+        let plic = tree.find("/soc/plic").expect("plic node must exist");
+        let mut compatible = plic.compatible();
+        assert_eq!(compatible.next(), Some("sifive,plic-1.0.0"));
+        assert_eq!(compatible.next(), Some("riscv,plic0"));
+        assert_eq!(compatible.next(), None);
+        assert_eq!(
+            tree.find("/chosen")
+                .expect("chosen node must exist")
+                .compatible()
+                .count(),
+            0
+        );
+    }
+
+    #[test_case]
+    fn walk_all_nodes() {
+        let tree = get_tree();
+        let mut virtio_mmio_nodes = 0;
+        let mut rtc_found = false;
+        for node in tree.walk() {
+            if node.compatible().any(|c| c == "virtio,mmio") {
+                virtio_mmio_nodes += 1;
+                assert!(node.first_reg().is_some());
+            }
+            if node.name() == "rtc@101000" {
+                rtc_found = true;
+                assert_eq!(node.interrupt(), Some(11));
+            }
+        }
+        assert_eq!(virtio_mmio_nodes, 8);
+        assert!(rtc_found);
+    }
+
+    #[test_case]
+    fn reg_uses_cells_of_parent() {
+        let tree = get_tree();
+        let memory = tree.find("/memory").expect("memory node must exist");
+        let regs = memory.reg();
+        assert_eq!(regs.len(), 1);
+        assert_eq!(regs[0].address, 0x8000_0000);
+
+        let cpu0 = tree.find("/cpus/cpu@0").expect("cpu node must exist");
+        let reg = cpu0.first_reg().expect("cpu must have a reg property");
+        assert_eq!((reg.address, reg.size), (0, 0));
+
+        let uart = tree
+            .find("/soc/serial@10000000")
+            .expect("uart node must exist");
+        let reg = uart.first_reg().expect("uart must have a reg property");
+        assert_eq!((reg.address, reg.size), (0x1000_0000, 0x100));
+    }
+
+    #[test_case]
+    fn ranges_use_cells_of_node_and_parent() {
+        let tree = get_tree();
+        let pci = tree.find("/soc/pci").expect("pci node must exist");
+        let ranges = pci.ranges();
+        assert!(!ranges.is_empty());
+        for AddressRange {
+            child_address,
+            parent_address,
+            size,
+        } in ranges
+        {
+            let space_code = (child_address >> 88) & 0b11;
+            assert_ne!(space_code, 0, "configuration space is not a range");
+            assert!(parent_address > 0);
+            assert!(size > 0);
+        }
+        assert!(tree
+            .find("/soc")
+            .expect("soc must exist")
+            .ranges()
+            .is_empty());
+    }
+}
@@ -1 +1,3 @@
+pub mod platform;
+pub mod rtc;
 pub mod virtio;
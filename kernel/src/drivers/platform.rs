@@ -0,0 +1,195 @@
+//! Drivers for devices described in the device tree.
+//!
+//! Every driver declares the `compatible` strings it supports. At boot we walk
+//! the whole device tree once and call the probe function of the first driver
+//! which matches a node. Nodes with a compatible property which no driver
+//! claims are recorded such that they can be inspected later.
+
+use alloc::{string::String, vec::Vec};
+use common::runtime_initialized::RuntimeInitializedData;
+use core::sync::atomic::Ordering;
+
+use crate::{
+    debug,
+    device_tree::tree::{self, DeviceTreeNode},
+    drivers::{rtc, virtio},
+    info,
+    interrupts::plic,
+    io::uart,
+    memory::page_tables::{MappingDescription, XWRMode},
+    pci,
+    processes::timer,
+    test::qemu_exit,
+    warn,
+};
+
+pub struct DeviceTreeDriver {
+    pub name: &'static str,
+    pub compatible: &'static [&'static str],
+    /// If set, the reg range of every bound device is mapped into the kernel page table.
+    pub needs_mapping: bool,
+    pub probe: fn(&DeviceTreeNode) -> Result<(), ProbeError>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProbeError {
+    MissingProperty(&'static str),
+    UnexpectedAddress { expected: usize, found: usize },
+    Unsupported(&'static str),
+}
+
+#[derive(Debug)]
+pub struct BoundDevice {
+    pub node_name: String,
+    pub driver: &'static str,
+    pub address: usize,
+    pub size: usize,
+    needs_mapping: bool,
+}
+
+static DRIVERS: &[DeviceTreeDriver] = &[
+    uart::NS16550_DEVICE_TREE_DRIVER,
+    uart::SIFIVE_DEVICE_TREE_DRIVER,
+    plic::DEVICE_TREE_DRIVER,
+    timer::CLINT_DEVICE_TREE_DRIVER,
+    rtc::DEVICE_TREE_DRIVER,
+    virtio::mmio::DEVICE_TREE_DRIVER,
+    pci::DEVICE_TREE_DRIVER,
+    qemu_exit::DEVICE_TREE_DRIVER,
+];
+
+static BOUND_DEVICES: RuntimeInitializedData<Vec<BoundDevice>> = RuntimeInitializedData::new();
+static UNCLAIMED_NODES: RuntimeInitializedData<Vec<String>> = RuntimeInitializedData::new();
+
+fn find_driver<'d>(
+    drivers: &'d [DeviceTreeDriver],
+    node: &DeviceTreeNode,
+) -> Option<&'d DeviceTreeDriver> {
+    // The compatible list is ordered from most to least specific
+    node.compatible().find_map(|compatible| {
+        drivers
+            .iter()
+            .find(|driver| driver.compatible.contains(&compatible))
+    })
+}
+
+pub fn probe_all() {
+    let mut bound_devices = Vec::new();
+    let mut unclaimed_nodes = Vec::new();
+
+    for node in tree::root().walk() {
+        if node.compatible().next().is_none() {
+            continue;
+        }
+        let Some(driver) = find_driver(DRIVERS, node) else {
+            debug!("No driver for device tree node {}", node.name());
+            unclaimed_nodes.push(node.name().into());
+            continue;
+        };
+        if let Err(err) = (driver.probe)(node) {
+            warn!(
+                "Driver {} failed to probe {}: {:?}",
+                driver.name,
+                node.name(),
+                err
+            );
+            unclaimed_nodes.push(node.name().into());
+            continue;
+        }
+        let (address, size) = node
+            .first_reg()
+            .map_or((0, 0), |reg| (reg.address, reg.size));
+        debug!("Bound {} to {}", driver.name, node.name());
+        bound_devices.push(BoundDevice {
+            node_name: node.name().into(),
+            driver: driver.name,
+            address,
+            size,
+            needs_mapping: driver.needs_mapping,
+        });
+    }
+
+    info!(
+        "Device tree: {} devices bound, {} nodes unclaimed",
+        bound_devices.len(),
+        unclaimed_nodes.len()
+    );
+
+    BOUND_DEVICES.initialize(bound_devices);
+    UNCLAIMED_NODES.initialize(unclaimed_nodes);
+}
+
+pub fn bound_devices() -> &'static [BoundDevice] {
+    &BOUND_DEVICES
+}
+
+pub fn unclaimed_nodes() -> &'static [String] {
+    &UNCLAIMED_NODES
+}
+
+pub fn dump() {
+    // We might be called from a panic before the device tree was probed
+    if !BOUND_DEVICES.initialized().load(Ordering::SeqCst) {
+        return;
+    }
+    for device in bound_devices() {
+        info!(
+            "Device {} at {:#x} bound to {}",
+            device.node_name, device.address, device.driver
+        );
+    }
+    info!("Unclaimed device tree nodes: {:?}", unclaimed_nodes());
+}
+
+/// Mappings for all bound devices whose drivers access them after boot.
+pub fn runtime_mappings() -> impl Iterator<Item = MappingDescription> {
+    bound_devices()
+        .iter()
+        .filter(|device| device.needs_mapping && device.size > 0)
+        .map(|device| MappingDescription {
+            virtual_address_start: device.address,
+            size: device.size,
+            privileges: XWRMode::ReadWrite,
+            name: device.driver,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_driver, DeviceTreeDriver};
+    use crate::device_tree::{tree::DeviceTreeNode, DeviceTree, Header};
+    use common::include_bytes_align_as;
+
+    const DTB: &[u8] = include_bytes_align_as!(Header, "../test/test_data/dtb");
+
+    const TEST_DRIVERS: &[DeviceTreeDriver] = &[
+        DeviceTreeDriver {
+            name: "generic",
+            compatible: &["riscv,plic0"],
+            needs_mapping: false,
+            probe: |_| Ok(()),
+        },
+        DeviceTreeDriver {
+            name: "specific",
+            compatible: &["sifive,plic-1.0.0"],
+            needs_mapping: false,
+            probe: |_| Ok(()),
+        },
+    ];
+
+    #[test_case]
+    fn most_specific_driver_wins() {
+        let tree = DeviceTreeNode::parse(DeviceTree::new(DTB.as_ptr() as *const ()).root_node());
+        let plic = tree.find("/soc/plic").expect("plic node must exist");
+        assert_eq!(
+            find_driver(TEST_DRIVERS, plic).map(|d| d.name),
+            Some("specific")
+        );
+        assert_eq!(
+            find_driver(&TEST_DRIVERS[..1], plic).map(|d| d.name),
+            Some("generic")
+        );
+        let rtc = tree.find("/soc/rtc").expect("rtc node must exist");
+        assert!(find_driver(TEST_DRIVERS, rtc).is_none());
+    }
+}
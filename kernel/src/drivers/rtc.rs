@@ -0,0 +1,40 @@
+use common::runtime_initialized::RuntimeInitializedData;
+use core::sync::atomic::Ordering;
+
+use crate::{
+    device_tree::tree::DeviceTreeNode,
+    drivers::platform::{DeviceTreeDriver, ProbeError},
+    klibc::MMIO,
+};
+
+// Register offsets of the Goldfish RTC
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+pub const DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "goldfish-rtc",
+    compatible: &["google,goldfish-rtc"],
+    needs_mapping: true,
+    probe,
+};
+
+static RTC_BASE: RuntimeInitializedData<usize> = RuntimeInitializedData::new();
+
+fn probe(node: &DeviceTreeNode) -> Result<(), ProbeError> {
+    let reg = node.first_reg().ok_or(ProbeError::MissingProperty("reg"))?;
+    RTC_BASE.initialize(reg.address);
+    Ok(())
+}
+
+/// Nanoseconds since the unix epoch. The registers are only
+/// accessible after the runtime mappings are active.
+pub fn now_nanoseconds() -> Option<u64> {
+    if !RTC_BASE.initialized().load(Ordering::SeqCst) {
+        return None;
+    }
+    let base = *RTC_BASE;
+    // Reading the low register latches the high register
+    let low = MMIO::<u32>::new(base + TIME_LOW).read() as u64;
+    let high = MMIO::<u32>::new(base + TIME_HIGH).read() as u64;
+    Some((high << 32) | low)
+}
@@ -0,0 +1,100 @@
+use alloc::collections::VecDeque;
+
+use crate::{
+    drivers::virtio::{
+        device::VirtioDevice,
+        transport::Transport,
+        virtqueue::{Buffer, QueueError, VirtQueue},
+    },
+    info,
+    memory::dma::DmaBuffer,
+};
+
+const QUEUE_SIZE: u16 = 0x80;
+
+const RECEIVE_QUEUE_INDEX: u16 = 0;
+const TRANSMIT_QUEUE_INDEX: u16 = 1;
+
+const RECEIVE_BUFFER_SIZE: usize = 64;
+const RECEIVE_BUFFER_COUNT: usize = 16;
+
+pub const VIRTIO_DEVICE_ID_CONSOLE: u32 = 3;
+
+/// A virtio console which is driven by polling. Multiport is not
+/// negotiated, therefore only the first port is used. It is meant for
+/// debugging facilities which run with interrupts disabled.
+pub struct ConsoleDevice {
+    device: VirtioDevice,
+    receive_queue: VirtQueue,
+    transmit_queue: VirtQueue,
+    received: VecDeque<u8>,
+}
+
+impl ConsoleDevice {
+    pub fn initialize(transport: impl Transport + 'static) -> Result<Self, &'static str> {
+        let mut device = VirtioDevice::new(transport)?;
+
+        device.negotiate().finish()?;
+
+        let mut receive_queue = device.setup_queue(RECEIVE_QUEUE_INDEX, QUEUE_SIZE)?;
+        let transmit_queue = device.setup_queue(TRANSMIT_QUEUE_INDEX, QUEUE_SIZE)?;
+
+        device.activate()?;
+
+        for _ in 0..RECEIVE_BUFFER_COUNT {
+            receive_queue
+                .put_buffer(Buffer::Writable(DmaBuffer::new(RECEIVE_BUFFER_SIZE)))
+                .expect("Receive buffer must be insertable to the queue");
+        }
+        device.notify(&mut receive_queue);
+
+        info!("Successfully initialized console device");
+
+        Ok(Self {
+            device,
+            receive_queue,
+            transmit_queue,
+            received: VecDeque::new(),
+        })
+    }
+
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.received.is_empty() {
+            self.fetch_received();
+        }
+        self.received.pop_front()
+    }
+
+    /// Queues the data and waits until the device consumed it
+    pub fn write(&mut self, data: &[u8]) {
+        let token = loop {
+            match self
+                .transmit_queue
+                .put_buffer(Buffer::Readable(DmaBuffer::from_slice(data)))
+            {
+                Ok(token) => break token,
+                Err(QueueError::NoFreeDescriptors) => {
+                    self.transmit_queue.poll_used();
+                }
+            }
+        };
+        self.device.notify(&mut self.transmit_queue);
+        self.transmit_queue.wait_for(token);
+    }
+
+    fn fetch_received(&mut self) {
+        let completions = self.receive_queue.poll_used();
+        if completions.is_empty() {
+            return;
+        }
+        for completion in completions {
+            let mut buffer = completion.into_written();
+            self.received.extend(buffer.iter());
+            buffer.reset_length();
+            self.receive_queue
+                .put_buffer(Buffer::Writable(buffer))
+                .expect("Receive buffer must be insertable into the queue.");
+        }
+        self.device.notify(&mut self.receive_queue);
+    }
+}
@@ -0,0 +1,181 @@
+use alloc::boxed::Box;
+
+use crate::{
+    debug,
+    drivers::virtio::{
+        transport::Transport,
+        virtqueue::{negotiated_queue_size, VirtQueue},
+    },
+    klibc::MMIO,
+};
+
+const DEVICE_STATUS_ACKNOWLEDGE: u8 = 1;
+const DEVICE_STATUS_DRIVER: u8 = 2;
+const DEVICE_STATUS_DRIVER_OK: u8 = 4;
+const DEVICE_STATUS_FEATURES_OK: u8 = 8;
+const DEVICE_STATUS_FAILED: u8 = 128;
+#[allow(dead_code)]
+const DEVICE_STATUS_DEVICE_NEEDS_RESTART: u8 = 64;
+
+pub const VIRTIO_F_EVENT_IDX: u64 = 1 << 29;
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// A virtio device independent of its transport. Device drivers (net, blk,
+/// rng, ...) are built on top of it and only deal with their device specific
+/// features, queues and configuration.
+///
+/// The initialization order required by the specification is:
+/// [`VirtioDevice::new`] -> [`VirtioDevice::negotiate`] ->
+/// [`VirtioDevice::setup_queue`] -> [`VirtioDevice::activate`]
+pub struct VirtioDevice {
+    transport: Box<dyn Transport>,
+    features: u64,
+}
+
+impl VirtioDevice {
+    /// Resets the device and announces that we found and can drive it.
+    pub fn new(transport: impl Transport + 'static) -> Result<Self, &'static str> {
+        let mut device = Self {
+            transport: Box::new(transport),
+            features: 0,
+        };
+        device.reset();
+        device.add_status(DEVICE_STATUS_ACKNOWLEDGE)?;
+        device.add_status(DEVICE_STATUS_DRIVER)?;
+        Ok(device)
+    }
+
+    pub fn negotiate(&mut self) -> FeatureNegotiation<'_> {
+        FeatureNegotiation {
+            device: self,
+            required: VIRTIO_F_VERSION_1,
+            // Handled by the queues for every device
+            optional: VIRTIO_F_EVENT_IDX,
+        }
+    }
+
+    pub fn has_feature(&self, feature: u64) -> bool {
+        self.features & feature == feature
+    }
+
+    /// The queue gets at most `wanted_size` entries, less if the device
+    /// doesn't offer that many. Check with [`VirtQueue::size`].
+    pub fn setup_queue(
+        &mut self,
+        queue_index: u16,
+        wanted_size: u16,
+    ) -> Result<VirtQueue, &'static str> {
+        let size = negotiated_queue_size(wanted_size, self.transport.max_queue_size(queue_index))
+            .ok_or("Queue is not available")?;
+        let queue = VirtQueue::new(size, queue_index, self.has_feature(VIRTIO_F_EVENT_IDX));
+        self.transport.activate_queue(
+            queue.queue_index(),
+            size,
+            queue.descriptor_area_physical_address(),
+            queue.driver_area_physical_address(),
+            queue.device_area_physical_address(),
+        );
+        Ok(queue)
+    }
+
+    /// Tells the device that the driver is ready. Afterwards the
+    /// device may use its queues.
+    pub fn activate(&mut self) -> Result<(), &'static str> {
+        self.add_status(DEVICE_STATUS_DRIVER_OK)?;
+        debug!("Device initialized: {:#x?}", self.transport.device_status());
+        Ok(())
+    }
+
+    /// Tells the device about new buffers unless it suppressed notifications
+    pub fn notify(&mut self, queue: &mut VirtQueue) {
+        if queue.needs_notification() {
+            self.transport.notify(queue.queue_index());
+        }
+    }
+
+    /// Returns the device specific configuration. Reads of fields larger than
+    /// 32 bit or of multiple fields should go through [`VirtioDevice::read_config`].
+    pub fn config<T>(&mut self) -> Result<MMIO<T>, &'static str> {
+        Ok(MMIO::new(self.transport.device_config_address()?))
+    }
+
+    /// Reads the configuration until we get a consistent view
+    /// because the device may change it at any time.
+    pub fn read_config<T, R>(&mut self, read: impl Fn(&MMIO<T>) -> R) -> Result<R, &'static str> {
+        let config = self.config::<T>()?;
+        loop {
+            let generation = self.transport.config_generation();
+            let value = read(&config);
+            if generation == self.transport.config_generation() {
+                return Ok(value);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.transport.set_device_status(0x0);
+        while self.transport.device_status() != 0x0 {}
+    }
+
+    fn add_status(&mut self, status: u8) -> Result<(), &'static str> {
+        let current_status = self.transport.device_status();
+        self.transport.set_device_status(current_status | status);
+        let new_status = self.transport.device_status();
+        if new_status & DEVICE_STATUS_FAILED != 0 {
+            return Err("Device failed");
+        }
+        if new_status & status != status {
+            return Err("Device did not accept status");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VirtioDevice {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+/// Builder for the feature negotiation. [`VIRTIO_F_VERSION_1`] is always required.
+pub struct FeatureNegotiation<'a> {
+    device: &'a mut VirtioDevice,
+    required: u64,
+    optional: u64,
+}
+
+impl FeatureNegotiation<'_> {
+    /// Initialization fails if the device doesn't offer `feature`.
+    pub fn require(mut self, feature: u64) -> Self {
+        self.required |= feature;
+        self
+    }
+
+    /// `feature` is only used if the device offers it.
+    /// Check with [`VirtioDevice::has_feature`] afterwards.
+    pub fn request(mut self, feature: u64) -> Self {
+        self.optional |= feature;
+        self
+    }
+
+    /// Returns the accepted features.
+    pub fn finish(self) -> Result<u64, &'static str> {
+        let device_features = self.device.transport.device_features();
+        debug!("Device features: {device_features:#x}");
+
+        if device_features & VIRTIO_F_VERSION_1 == 0 {
+            return Err("Virtio version 1 not supported");
+        }
+
+        if device_features & self.required != self.required {
+            return Err("Device does not support wanted features");
+        }
+
+        let features = self.required | (self.optional & device_features);
+        self.device.transport.set_driver_features(features);
+        self.device.add_status(DEVICE_STATUS_FEATURES_OK)?;
+        self.device.features = features;
+
+        Ok(features)
+    }
+}
@@ -0,0 +1,220 @@
+use alloc::vec::Vec;
+use common::mutex::Mutex;
+
+use crate::{
+    assert::static_assert_size,
+    debug,
+    device_tree::tree::DeviceTreeNode,
+    drivers::{
+        platform::{DeviceTreeDriver, ProbeError},
+        virtio::transport::Transport,
+    },
+    info,
+    klibc::MMIO,
+    mmio_struct, warn,
+};
+
+const VIRTIO_MMIO_MAGIC: u32 = 0x7472_6976; // "virt"
+const VIRTIO_MMIO_MODERN_VERSION: u32 = 2;
+const DEVICE_CONFIG_OFFSET: usize = 0x100;
+
+pub const DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "virtio-mmio",
+    compatible: &["virtio,mmio"],
+    needs_mapping: true,
+    probe,
+};
+
+/// A virtio-mmio slot found in the device tree. The slot may be empty
+/// which is only visible after mapping it and reading the device id.
+#[derive(Debug, Clone)]
+pub struct VirtioMmioSlot {
+    pub address: usize,
+    pub interrupt: Option<u32>,
+}
+
+static SLOTS: Mutex<Vec<VirtioMmioSlot>> = Mutex::new(Vec::new());
+
+fn probe(node: &DeviceTreeNode) -> Result<(), ProbeError> {
+    let reg = node.first_reg().ok_or(ProbeError::MissingProperty("reg"))?;
+    SLOTS.lock().push(VirtioMmioSlot {
+        address: reg.address,
+        interrupt: node.interrupt(),
+    });
+    Ok(())
+}
+
+pub fn slots() -> Vec<VirtioMmioSlot> {
+    SLOTS.lock().clone()
+}
+
+/// Returns a transport for every populated slot. The slots
+/// must already be mapped.
+pub fn discover_devices() -> Vec<MmioTransport> {
+    slots()
+        .into_iter()
+        .filter_map(|slot| {
+            let registers: MMIO<virtio_mmio_registers> = MMIO::new(slot.address);
+            if registers.magic_value().read() != VIRTIO_MMIO_MAGIC {
+                warn!("No virtio-mmio device at {:#x}", slot.address);
+                return None;
+            }
+            let device_id = registers.device_id().read();
+            // Device id 0 means that the slot is empty
+            if device_id == 0 {
+                return None;
+            }
+            let version = registers.version().read();
+            if version != VIRTIO_MMIO_MODERN_VERSION {
+                warn!(
+                    "Ignoring legacy virtio-mmio device {} at {:#x} (version {})",
+                    device_id, slot.address, version
+                );
+                return None;
+            }
+            info!(
+                "Found virtio-mmio device {} at {:#x} (interrupt {:?})",
+                device_id, slot.address, slot.interrupt
+            );
+            Some(MmioTransport {
+                slot,
+                registers,
+                device_id,
+            })
+        })
+        .collect()
+}
+
+pub struct MmioTransport {
+    slot: VirtioMmioSlot,
+    registers: MMIO<virtio_mmio_registers>,
+    device_id: u32,
+}
+
+impl MmioTransport {
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+}
+
+impl Transport for MmioTransport {
+    fn device_status(&self) -> u8 {
+        self.registers.status().read() as u8
+    }
+
+    fn set_device_status(&mut self, status: u8) {
+        self.registers.status().write(status as u32);
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.registers.device_features_sel().write(0);
+        let mut device_features = self.registers.device_features().read() as u64;
+
+        self.registers.device_features_sel().write(1);
+        device_features |= (self.registers.device_features().read() as u64) << 32;
+        device_features
+    }
+
+    fn set_driver_features(&mut self, features: u64) {
+        self.registers.driver_features_sel().write(0);
+        self.registers.driver_features().write(features as u32);
+
+        self.registers.driver_features_sel().write(1);
+        self.registers
+            .driver_features()
+            .write((features >> 32) as u32);
+    }
+
+    fn max_queue_size(&mut self, queue_index: u16) -> u16 {
+        self.registers.queue_sel().write(queue_index as u32);
+        self.registers.queue_num_max().read() as u16
+    }
+
+    fn activate_queue(
+        &mut self,
+        queue_index: u16,
+        queue_size: u16,
+        descriptor_area: u64,
+        driver_area: u64,
+        device_area: u64,
+    ) {
+        let registers = &self.registers;
+        registers.queue_sel().write(queue_index as u32);
+        assert!(
+            registers.queue_ready().read() == 0,
+            "Queue must not be in use"
+        );
+        registers.queue_num().write(queue_size as u32);
+        registers.queue_desc_low().write(descriptor_area as u32);
+        registers
+            .queue_desc_high()
+            .write((descriptor_area >> 32) as u32);
+        registers.queue_driver_low().write(driver_area as u32);
+        registers
+            .queue_driver_high()
+            .write((driver_area >> 32) as u32);
+        registers.queue_device_low().write(device_area as u32);
+        registers
+            .queue_device_high()
+            .write((device_area >> 32) as u32);
+        registers.queue_ready().write(1);
+        debug!(
+            "Activated queue {} of virtio-mmio device at {:#x}",
+            queue_index, self.slot.address
+        );
+    }
+
+    fn notify(&mut self, queue_index: u16) {
+        // All registers must be accessed with 32 bit
+        self.registers.queue_notify().write(queue_index as u32);
+    }
+
+    fn config_generation(&self) -> u32 {
+        self.registers.config_generation().read()
+    }
+
+    fn device_config_address(&mut self) -> Result<usize, &'static str> {
+        Ok(self.slot.address + DEVICE_CONFIG_OFFSET)
+    }
+}
+
+mmio_struct! {
+    #[repr(C)]
+    struct virtio_mmio_registers {
+        magic_value: u32,          // 0x000
+        version: u32,              // 0x004
+        device_id: u32,            // 0x008
+        vendor_id: u32,            // 0x00c
+        device_features: u32,      // 0x010
+        device_features_sel: u32,  // 0x014
+        reserved0: [u32; 2],
+        driver_features: u32,      // 0x020
+        driver_features_sel: u32,  // 0x024
+        reserved1: [u32; 2],
+        queue_sel: u32,            // 0x030
+        queue_num_max: u32,        // 0x034
+        queue_num: u32,            // 0x038
+        reserved2: [u32; 2],
+        queue_ready: u32,          // 0x044
+        reserved3: [u32; 2],
+        queue_notify: u32,         // 0x050
+        reserved4: [u32; 3],
+        interrupt_status: u32,     // 0x060
+        interrupt_ack: u32,        // 0x064
+        reserved5: [u32; 2],
+        status: u32,               // 0x070
+        reserved6: [u32; 3],
+        queue_desc_low: u32,       // 0x080
+        queue_desc_high: u32,      // 0x084
+        reserved7: [u32; 2],
+        queue_driver_low: u32,     // 0x090
+        queue_driver_high: u32,    // 0x094
+        reserved8: [u32; 2],
+        queue_device_low: u32,     // 0x0a0
+        queue_device_high: u32,    // 0x0a4
+        reserved9: [u32; 21],
+        config_generation: u32,    // 0x0fc
+    }
+}
+
+static_assert_size!(virtio_mmio_registers, DEVICE_CONFIG_OFFSET);
@@ -1,3 +1,11 @@
 mod capability;
+pub mod console;
+pub mod device;
+pub mod mmio;
+#[cfg(feature = "net")]
 pub mod net;
+#[cfg(feature = "hostfs")]
+pub mod p9;
+pub mod pci;
+pub mod transport;
 mod virtqueue;
@@ -2,293 +2,271 @@ use crate::{
     assert::static_assert_size,
     debug,
     drivers::virtio::{
-        capability::{
-            virtio_pci_cap, VIRTIO_PCI_CAP_COMMON_CFG, VIRTIO_PCI_CAP_DEVICE_CFG,
-            VIRTIO_PCI_CAP_NOTIFY_CFG,
-        },
-        virtqueue::{BufferDirection, VirtQueue},
+        device::VirtioDevice,
+        transport::Transport,
+        virtqueue::{Buffer, VirtQueue},
     },
     info,
     klibc::{
-        util::{is_power_of_2_or_zero, BufferExtension, ByteInterpretable},
+        util::{BufferExtension, ByteInterpretable},
         MMIO,
     },
+    memory::{dma::DmaBuffer, vma::PinnedBuffer},
     mmio_struct,
     net::mac::MacAddress,
-    pci::PCIDevice,
 };
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
+use common::mutex::Mutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use super::virtqueue::QueueError;
+use super::virtqueue::{QueueError, Token};
 
-const EXPECTED_QUEUE_SIZE: usize = 0x100;
+const QUEUE_SIZE: u16 = 0x100;
+const CONTROL_QUEUE_SIZE: u16 = 0x40;
 
-const VIRTIO_VENDOR_SPECIFIC_CAPABILITY_ID: u8 = 0x9;
-
-const DEVICE_STATUS_ACKNOWLEDGE: u8 = 1;
-const DEVICE_STATUS_DRIVER: u8 = 2;
-const DEVICE_STATUS_DRIVER_OK: u8 = 4;
-const DEVICE_STATUS_FEATURES_OK: u8 = 8;
-const DEVICE_STATUS_FAILED: u8 = 128;
-#[allow(dead_code)]
-const DEVICE_STATUS_DEVICE_NEEDS_RESTART: u8 = 64;
+pub const VIRTIO_DEVICE_ID_NET: u32 = 1;
 
+const VIRTIO_NET_F_GUEST_CSUM: u64 = 1 << 1;
+const VIRTIO_NET_F_MTU: u64 = 1 << 3;
 const VIRTIO_NET_F_MAC: u64 = 1 << 5;
-const VIRTIO_F_VERSION_1: u64 = 1 << 32;
-
-#[allow(dead_code)]
-pub struct NetworkDevice {
-    device: PCIDevice,
-    common_cfg: MMIO<virtio_pci_common_cfg>,
-    net_cfg: MMIO<virtio_net_config>,
-    notify_cfg: MMIO<virtio_pci_notify_cap>,
-    transmit_queue: VirtQueue<EXPECTED_QUEUE_SIZE>,
-    receive_queue: VirtQueue<EXPECTED_QUEUE_SIZE>,
-    mac_address: MacAddress,
-}
-
-impl NetworkDevice {
-    pub fn initialize(mut pci_device: PCIDevice) -> Result<Self, &'static str> {
-        let capabilities = pci_device.capabilities();
-        let mut virtio_capabilities: Vec<MMIO<virtio_pci_cap>> = capabilities
-            .filter(|cap| cap.id().read() == VIRTIO_VENDOR_SPECIFIC_CAPABILITY_ID)
-            .map(|cap| unsafe { cap.new_type::<virtio_pci_cap>() })
-            .collect();
-
-        let common_cfg = virtio_capabilities
-            .iter()
-            .find(|cap| cap.cfg_type().read() == VIRTIO_PCI_CAP_COMMON_CFG)
-            .ok_or("Common configuration capability not found")?;
-
-        debug!("Common configuration capability found at {:?}", common_cfg);
+const VIRTIO_NET_F_STATUS: u64 = 1 << 16;
+const VIRTIO_NET_F_CTRL_VQ: u64 = 1 << 17;
+const VIRTIO_NET_F_MQ: u64 = 1 << 22;
 
-        let config_bar = pci_device.get_or_initialize_bar(common_cfg.bar().read());
+const VIRTIO_NET_S_LINK_UP: u16 = 1;
 
-        let common_cfg: MMIO<virtio_pci_common_cfg> =
-            MMIO::new(config_bar.cpu_address + common_cfg.offset().read() as usize);
+const VIRTIO_NET_CTRL_MQ: u8 = 4;
+const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+const VIRTIO_NET_OK: u8 = 0;
 
-        debug!("Common config: {:#x?}", common_cfg);
+// Used if the device doesn't tell us its MTU
+const DEFAULT_MTU: u16 = 1500;
+const ETHERNET_HEADER_SIZE: usize = 14;
 
-        // Let's try to initialize the device
-        common_cfg.device_status().write(0x0);
-
-        #[allow(clippy::while_immutable_condition)]
-        while common_cfg.device_status().read() != 0x0 {}
-
-        let mut device_status = common_cfg.device_status();
-        device_status |= DEVICE_STATUS_ACKNOWLEDGE;
-
-        assert!(
-            common_cfg.device_status().read() & DEVICE_STATUS_FAILED == 0,
-            "Device failed"
-        );
-
-        device_status |= DEVICE_STATUS_DRIVER;
-
-        assert!(
-            common_cfg.device_status().read() & DEVICE_STATUS_FAILED == 0,
-            "Device failed"
-        );
-
-        // Read features and write subset to it
-        common_cfg.device_feature_select().write(0);
-        let mut device_features = common_cfg.device_feature().read() as u64;
-
-        common_cfg.device_feature_select().write(1);
-        device_features |= (common_cfg.device_feature().read() as u64) << 32;
-
-        assert!(
-            device_features & VIRTIO_F_VERSION_1 != 0,
-            "Virtio version 1 not supported"
-        );
+// Every queue pair is polled by its own kernel thread
+const MAX_QUEUE_PAIRS: usize = 4;
 
-        let wanted_features: u64 = VIRTIO_F_VERSION_1 | VIRTIO_NET_F_MAC;
-
-        assert!(
-            device_features & wanted_features == wanted_features,
-            "Device does not support wanted features"
-        );
-
-        common_cfg.driver_feature_select().write(0);
-        common_cfg.driver_feature().write(wanted_features as u32);
-
-        common_cfg.driver_feature_select().write(1);
-        common_cfg
-            .driver_feature()
-            .write((wanted_features >> 32) as u32);
-
-        device_status |= DEVICE_STATUS_FEATURES_OK;
-
-        assert!(
-            device_status.read() & DEVICE_STATUS_FAILED == 0,
-            "Device failed"
-        );
-
-        assert!(
-            device_status.read() & DEVICE_STATUS_FEATURES_OK != 0,
-            "Device features not ok"
-        );
-
-        // Get notification configuration
-        let notify_cfg = virtio_capabilities
-            .iter()
-            .find(|cap| cap.cfg_type().read() == VIRTIO_PCI_CAP_NOTIFY_CFG)
-            .ok_or("Notification capability not found")?;
-
-        // SAFTEY: Notification capability is a different type
-        let notify_cfg = unsafe { notify_cfg.new_type::<virtio_pci_notify_cap>() };
+// Queue pair n uses the queues 2n (receive) and 2n + 1 (transmit)
+const fn receive_queue_index(pair: u16) -> u16 {
+    2 * pair
+}
 
-        assert!(
-            is_power_of_2_or_zero(notify_cfg.notify_off_multiplier().read()),
-            "Notify offset multiplier must be a power of 2 or zero"
-        );
+const fn transmit_queue_index(pair: u16) -> u16 {
+    2 * pair + 1
+}
 
-        assert!(
-            notify_cfg.cap().offset().read() % 16 == 0,
-            "Notify offset must be 2 byte aligned"
-        );
+pub struct ReceivedPacket {
+    pub data: Vec<u8>,
+    // The device checked the UDP checksum already
+    pub checksum_validated: bool,
+}
 
-        assert!(
-            notify_cfg.cap().length().read() >= 2,
-            "Notify length must be at least 2"
-        );
+/// One receive and transmit queue per hart such that the harts don't
+/// contend for a single queue. The queues are locked separately, only
+/// notifications go through the lock of the device.
+#[allow(dead_code)]
+pub struct NetworkDevice {
+    // Dropped first such that the device is reset before the queues are freed
+    device: Mutex<VirtioDevice>,
+    transmit_queues: Vec<Mutex<VirtQueue>>,
+    receive_queues: Vec<Mutex<VirtQueue>>,
+    control_queue: Option<Mutex<VirtQueue>>,
+    // Packets received on every queue pair
+    received: Vec<AtomicUsize>,
+    mac_address: MacAddress,
+    mtu: u16,
+}
 
-        let notify_bar = pci_device.get_or_initialize_bar(notify_cfg.cap().bar().read());
+impl NetworkDevice {
+    pub fn initialize(
+        transport: impl Transport + 'static,
+        number_of_harts: usize,
+    ) -> Result<Self, &'static str> {
+        let mut device = VirtioDevice::new(transport)?;
+
+        device
+            .negotiate()
+            .require(VIRTIO_NET_F_MAC)
+            .request(VIRTIO_NET_F_MTU)
+            .request(VIRTIO_NET_F_GUEST_CSUM)
+            .request(VIRTIO_NET_F_STATUS)
+            .request(VIRTIO_NET_F_CTRL_VQ)
+            .request(VIRTIO_NET_F_MQ)
+            .finish()?;
+
+        // The device offers multiple queues only together with the control queue
+        let max_queue_pairs = if device.has_feature(VIRTIO_NET_F_MQ) {
+            device.read_config(|config: &MMIO<virtio_net_config>| {
+                config.max_virtqueue_pairs().read()
+            })?
+        } else {
+            1
+        };
+        let queue_pairs = (max_queue_pairs as usize)
+            .min(number_of_harts)
+            .clamp(1, MAX_QUEUE_PAIRS) as u16;
+
+        let mtu = if device.has_feature(VIRTIO_NET_F_MTU) {
+            device.read_config(|config: &MMIO<virtio_net_config>| config.mtu().read())?
+        } else {
+            DEFAULT_MTU
+        };
 
         // Intialize virtqueues
-        // index 0
-        common_cfg.queue_select().write(0);
-        let mut receive_queue: VirtQueue<EXPECTED_QUEUE_SIZE> =
-            VirtQueue::new(common_cfg.queue_size().read(), 0);
-        // index 1
-        common_cfg.queue_select().write(1);
-        let mut transmit_queue: VirtQueue<EXPECTED_QUEUE_SIZE> =
-            VirtQueue::new(common_cfg.queue_size().read(), 1);
-
-        assert!(
-            notify_cfg.cap().length().read()
-                >= common_cfg.queue_notify_off().read() as u32
-                    * notify_cfg.notify_off_multiplier().read()
-                    + 2,
-            "Notify length must be at least the notify offset"
-        );
-
-        let transmit_notify: MMIO<u16> = MMIO::new(
-            notify_bar.cpu_address
-                + notify_cfg.cap().offset().read() as usize
-                + common_cfg.queue_notify_off().read() as usize
-                    * notify_cfg.notify_off_multiplier().read() as usize,
-        );
-
-        transmit_queue.set_notify(transmit_notify);
-
-        common_cfg.queue_select().write(0);
-        common_cfg
-            .queue_desc()
-            .write(receive_queue.descriptor_area_physical_address());
-        common_cfg
-            .queue_driver()
-            .write(receive_queue.driver_area_physical_address());
-        common_cfg
-            .queue_device()
-            .write(receive_queue.device_area_physical_address());
-        common_cfg.queue_enable().write(1);
-
-        common_cfg.queue_select().write(1);
-        common_cfg
-            .queue_desc()
-            .write(transmit_queue.descriptor_area_physical_address());
-        common_cfg
-            .queue_driver()
-            .write(transmit_queue.driver_area_physical_address());
-        common_cfg
-            .queue_device()
-            .write(transmit_queue.device_area_physical_address());
-        common_cfg.queue_enable().write(1);
-
-        device_status |= DEVICE_STATUS_DRIVER_OK;
-
-        assert!(
-            device_status.read() & DEVICE_STATUS_FAILED == 0,
-            "Device failed"
-        );
-
-        assert!(
-            device_status.read() & DEVICE_STATUS_DRIVER_OK != 0,
-            "Device driver not ok"
-        );
-
-        debug!("Device initialized: {:#x?}", device_status);
+        let mut receive_queues = (0..queue_pairs)
+            .map(|pair| device.setup_queue(receive_queue_index(pair), QUEUE_SIZE))
+            .collect::<Result<Vec<_>, _>>()?;
+        let transmit_queues = (0..queue_pairs)
+            .map(|pair| device.setup_queue(transmit_queue_index(pair), QUEUE_SIZE))
+            .collect::<Result<Vec<_>, _>>()?;
+        // The control queue follows the pairs the device supports, not
+        // the ones we use
+        let mut control_queue = device
+            .has_feature(VIRTIO_NET_F_MQ)
+            .then(|| device.setup_queue(receive_queue_index(max_queue_pairs), CONTROL_QUEUE_SIZE))
+            .transpose()?;
+
+        device.activate()?;
 
         // Get net configuration
-        let net_cfg_cap = virtio_capabilities
-            .iter_mut()
-            .find(|cap| cap.cfg_type().read() == VIRTIO_PCI_CAP_DEVICE_CFG)
-            .ok_or("Device configuration capability not found")?;
-
-        debug!("Device configuration capability found at {:?}", net_cfg_cap);
-
-        let net_config_bar = pci_device.get_or_initialize_bar(net_cfg_cap.bar().read());
-
-        let net_cfg: MMIO<virtio_net_config> =
-            MMIO::new(net_config_bar.cpu_address + net_cfg_cap.offset().read() as usize);
+        let net_cfg: MMIO<virtio_net_config> = device.config()?;
 
         debug!("Net config: {:#x?}", net_cfg);
 
-        // Fill receive buffers
-        for _ in 0..EXPECTED_QUEUE_SIZE {
-            let receive_buffer = vec![0xffu8; 1526];
-            receive_queue
-                .put_buffer(receive_buffer, BufferDirection::DeviceWritable)
-                .expect("Receive buffer must be insertable to the queue");
+        // Fill receive buffers. Every buffer must hold a whole frame
+        // because we don't merge buffers.
+        let receive_buffer_size =
+            core::mem::size_of::<virtio_net_hdr>() + ETHERNET_HEADER_SIZE + mtu as usize;
+        for receive_queue in &mut receive_queues {
+            for _ in 0..receive_queue.size() {
+                receive_queue
+                    .put_buffer(Buffer::Writable(DmaBuffer::new(receive_buffer_size)))
+                    .expect("Receive buffer must be insertable to the queue");
+            }
+            device.notify(receive_queue);
         }
 
-        let mac_address = net_cfg.mac().read();
+        // Only the first pair is used until the device is told otherwise
+        if let Some(control_queue) = &mut control_queue {
+            let mut command = vec![VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET];
+            command.extend_from_slice(&queue_pairs.to_le_bytes());
+            let token = control_queue
+                .put_request(DmaBuffer::from_slice(&command), DmaBuffer::new(1))
+                .expect("Control queue must be empty");
+            device.notify(control_queue);
+
+            let ack = control_queue.wait_for(token).into_written();
+            if *ack != [VIRTIO_NET_OK] {
+                return Err("Device did not accept the number of queue pairs");
+            }
+        }
+
+        let mac_address =
+            device.read_config(|config: &MMIO<virtio_net_config>| config.mac().read())?;
+
+        if device.has_feature(VIRTIO_NET_F_STATUS) {
+            let status =
+                device.read_config(|config: &MMIO<virtio_net_config>| config.status().read())?;
+            info!(
+                "Network link is {}",
+                if status & VIRTIO_NET_S_LINK_UP != 0 {
+                    "up"
+                } else {
+                    "down"
+                }
+            );
+        }
 
         info!(
-            "Successfully initialized network device at {:p} with mac {}",
-            *pci_device.configuration_space(),
-            mac_address
+            "Successfully initialized network device with mac {}, mtu {} and {} queue pairs",
+            mac_address, mtu, queue_pairs
         );
 
         Ok(Self {
-            device: pci_device,
-            common_cfg,
-            net_cfg,
-            notify_cfg,
+            device: Mutex::new(device),
+            transmit_queues: transmit_queues.into_iter().map(Mutex::new).collect(),
+            receive_queues: receive_queues.into_iter().map(Mutex::new).collect(),
+            control_queue: control_queue.map(Mutex::new),
+            received: (0..queue_pairs).map(|_| AtomicUsize::new(0)).collect(),
             mac_address,
-            receive_queue,
-            transmit_queue,
+            mtu,
         })
     }
 
-    pub fn receive_packets(&mut self) -> Vec<Vec<u8>> {
-        let new_receive_buffers = self.receive_queue.receive_buffer();
+    pub fn queue_pairs(&self) -> usize {
+        self.receive_queues.len()
+    }
+
+    pub fn received_per_queue_pair(&self) -> Vec<usize> {
+        self.received
+            .iter()
+            .map(|received| received.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    pub fn receive_packets(&self, pair: usize) -> Vec<ReceivedPacket> {
+        let mut receive_queue = self.receive_queues[pair].lock();
+        let completions = receive_queue.poll_used();
         let mut received_packets = Vec::new();
 
-        for receive_buffer in new_receive_buffers {
-            let (net_hdr, data_bytes) = receive_buffer.buffer.split_as::<virtio_net_hdr>();
+        for completion in completions {
+            let mut buffer = completion.into_written();
+            let (net_hdr, data_bytes) = buffer.split_as::<virtio_net_hdr>();
 
             assert!(net_hdr.gso_type == VIRTIO_NET_HDR_GSO_NONE);
-            assert!(net_hdr.flags == 0);
-
-            let data = data_bytes.to_vec();
-            received_packets.push(data);
 
-            // Put buffer back into receive queue
-            self.receive_queue
-                .put_buffer(receive_buffer.buffer, BufferDirection::DeviceWritable)
+            // Packets which still need a checksum come from the host
+            // itself and only carry a partial one
+            let checksum_validated =
+                net_hdr.flags & (VIRTIO_NET_HDR_F_NEEDS_CSUM | VIRTIO_NET_HDR_F_DATA_VALID) != 0;
+            received_packets.push(ReceivedPacket {
+                data: data_bytes.to_vec(),
+                checksum_validated,
+            });
+
+            // Put buffer back into receive queue. It was truncated to
+            // the received packet.
+            buffer.reset_length();
+            receive_queue
+                .put_buffer(Buffer::Writable(buffer))
                 .expect("Receive buffer must be insertable into the queue.");
         }
+        if !received_packets.is_empty() {
+            self.device.lock().notify(&mut receive_queue);
+        }
+        self.received[pair].fetch_add(received_packets.len(), Ordering::Relaxed);
 
         received_packets
     }
 
-    pub fn send_packet(&mut self, data: Vec<u8>) -> Result<u16, QueueError> {
-        // First free all already transmited packets
+    pub fn send_packet(&self, pair: usize, data: Vec<u8>) -> Result<Token, QueueError> {
+        self.transmit(pair, &data, None)
+    }
+
+    /// The payload gets a descriptor of its own, the device reads it
+    /// where it is
+    pub fn send_frame(
+        &self,
+        pair: usize,
+        headers: &[u8],
+        payload: PinnedBuffer,
+    ) -> Result<Token, QueueError> {
+        self.transmit(pair, headers, Some(payload))
+    }
+
+    fn transmit(
+        &self,
+        pair: usize,
+        data: &[u8],
+        payload: Option<PinnedBuffer>,
+    ) -> Result<Token, QueueError> {
+        let mut transmit_queue = self.transmit_queues[pair].lock();
+
+        // First free all already transmited packets. This also unpins
+        // their payloads.
         debug!("Going to free all buffers which were used to send packets.");
-        for transmitted_packet in self.transmit_queue.receive_buffer() {
-            debug!("Transmitted packet: {:?}", transmitted_packet.index);
+        for transmitted_packet in transmit_queue.poll_used() {
+            debug!("Transmitted packet: {:?}", transmitted_packet.token);
         }
 
         let header = virtio_net_hdr {
@@ -301,49 +279,27 @@ impl NetworkDevice {
             num_buffers: 0,
         };
 
-        let data = [header.as_slice(), data.as_slice()].concat();
-        let index = self
-            .transmit_queue
-            .put_buffer(data, BufferDirection::DriverWritable);
+        let header = header.as_slice();
+        let mut buffer = DmaBuffer::new(header.len() + data.len());
+        buffer[..header.len()].copy_from_slice(header);
+        buffer[header.len()..].copy_from_slice(data);
+        let mut chain = vec![Buffer::Readable(buffer)];
+        chain.extend(payload.map(Buffer::Pinned));
+        let token = transmit_queue.submit(chain)?;
 
         // Notify device
-        self.transmit_queue.notify();
+        self.device.lock().notify(&mut transmit_queue);
 
-        index
+        Ok(token)
     }
 
     pub fn get_mac_address(&self) -> MacAddress {
         self.mac_address
     }
-}
 
-impl Drop for NetworkDevice {
-    fn drop(&mut self) {
-        info!("Reset network device becuase of drop");
-        self.common_cfg.device_status().write(0x0);
-    }
-}
-
-mmio_struct! {
-    #[repr(C)]
-    struct virtio_pci_common_cfg {
-        device_feature_select: u32,
-        device_feature: u32,
-        driver_feature_select: u32,
-        driver_feature: u32,
-        config_msix_vector: u16,
-        num_queues: u16,
-        device_status: u8,
-        config_generation: u8,
-        /* About a specific virtqueue. */
-        queue_select: u16,
-        queue_size: u16,
-        queue_msix_vector: u16,
-        queue_enable: u16,
-        queue_notify_off: u16,
-        queue_desc: u64,
-        queue_driver: u64,
-        queue_device: u64,
+    /// Largest IP packet the device sends and receives
+    pub fn mtu(&self) -> usize {
+        self.mtu as usize
     }
 }
 
@@ -362,6 +318,8 @@ mmio_struct! {
     }
 }
 
+const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 2;
 const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
 
 #[allow(non_camel_case_types)]
@@ -383,11 +341,3 @@ struct virtio_net_hdr {
 static_assert_size!(virtio_net_hdr, 12);
 
 impl ByteInterpretable for virtio_net_hdr {}
-
-mmio_struct! {
-    #[repr(C)]
-    struct virtio_pci_notify_cap {
-        cap: crate::drivers::virtio::capability::virtio_pci_cap,
-        notify_off_multiplier: u32,
-    }
-}
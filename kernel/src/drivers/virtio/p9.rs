@@ -0,0 +1,84 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    drivers::virtio::{device::VirtioDevice, transport::Transport, virtqueue::VirtQueue},
+    fs::p9::P9Transport,
+    info,
+    klibc::MMIO,
+    memory::dma::DmaBuffer,
+    mmio_struct,
+};
+
+const QUEUE_SIZE: u16 = 0x80;
+
+const REQUEST_QUEUE_INDEX: u16 = 0;
+
+pub const VIRTIO_DEVICE_ID_9P: u32 = 9;
+
+const VIRTIO_9P_MOUNT_TAG: u64 = 1;
+
+const MAX_MOUNT_TAG_LENGTH: usize = 256;
+
+/// Transports 9p messages to the host. Like the console it is driven by
+/// polling: a request is only sent after the previous one was answered.
+pub struct P9Device {
+    device: VirtioDevice,
+    request_queue: VirtQueue,
+    mount_tag: String,
+}
+
+impl P9Device {
+    pub fn initialize(transport: impl Transport + 'static) -> Result<Self, &'static str> {
+        let mut device = VirtioDevice::new(transport)?;
+
+        device.negotiate().require(VIRTIO_9P_MOUNT_TAG).finish()?;
+
+        let request_queue = device.setup_queue(REQUEST_QUEUE_INDEX, QUEUE_SIZE)?;
+
+        device.activate()?;
+
+        let mount_tag = device.read_config(|config: &MMIO<virtio_9p_config>| {
+            let length = config.tag_len().read() as usize;
+            if length > MAX_MOUNT_TAG_LENGTH {
+                return Err("Mount tag too long");
+            }
+            let tag = config.tag();
+            let tag: Vec<u8> = (0..length).map(|index| tag.read_index(index)).collect();
+            String::from_utf8(tag).map_err(|_| "Mount tag is not valid utf-8")
+        })??;
+
+        info!("Successfully initialized 9p device with mount tag {mount_tag}");
+
+        Ok(Self {
+            device,
+            request_queue,
+            mount_tag,
+        })
+    }
+
+    pub fn mount_tag(&self) -> &str {
+        &self.mount_tag
+    }
+}
+
+impl P9Transport for P9Device {
+    fn request(&mut self, request: Vec<u8>, response_capacity: usize) -> Vec<u8> {
+        let token = self
+            .request_queue
+            .put_request(
+                DmaBuffer::from_slice(&request),
+                DmaBuffer::new(response_capacity),
+            )
+            .expect("Only one request is outstanding");
+        self.device.notify(&mut self.request_queue);
+        self.request_queue.wait_for(token).into_written().to_vec()
+    }
+}
+
+mmio_struct! {
+    #[repr(C)]
+    struct virtio_9p_config {
+        tag_len: u16,
+        tag: [u8; MAX_MOUNT_TAG_LENGTH],
+    }
+}
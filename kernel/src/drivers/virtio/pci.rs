@@ -0,0 +1,210 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{
+    debug,
+    drivers::virtio::{
+        capability::{
+            virtio_pci_cap, VIRTIO_PCI_CAP_COMMON_CFG, VIRTIO_PCI_CAP_DEVICE_CFG,
+            VIRTIO_PCI_CAP_NOTIFY_CFG,
+        },
+        transport::Transport,
+    },
+    klibc::{util::is_power_of_2_or_zero, MMIO},
+    mmio_struct,
+    pci::PciDevice,
+};
+
+const VIRTIO_VENDOR_SPECIFIC_CAPABILITY_ID: u8 = 0x9;
+
+pub struct PciTransport {
+    device: PciDevice,
+    virtio_capabilities: Vec<MMIO<virtio_pci_cap>>,
+    common_cfg: MMIO<virtio_pci_common_cfg>,
+    notify_cfg: MMIO<virtio_pci_notify_cap>,
+    notify_base: usize,
+    queue_notify: BTreeMap<u16, MMIO<u16>>,
+}
+
+impl PciTransport {
+    pub fn new(mut pci_device: PciDevice) -> Result<Self, &'static str> {
+        debug!("Virtio PCI device at {}", pci_device.address());
+
+        let virtio_capabilities: Vec<MMIO<virtio_pci_cap>> = pci_device
+            .capabilities()
+            .filter(|cap| cap.id().read() == VIRTIO_VENDOR_SPECIFIC_CAPABILITY_ID)
+            .map(|cap| unsafe { cap.new_type::<virtio_pci_cap>() })
+            .collect();
+
+        let common_cfg = virtio_capabilities
+            .iter()
+            .find(|cap| cap.cfg_type().read() == VIRTIO_PCI_CAP_COMMON_CFG)
+            .ok_or("Common configuration capability not found")?;
+
+        debug!("Common configuration capability found at {:?}", common_cfg);
+
+        let config_bar = pci_device.get_or_initialize_bar(common_cfg.bar().read());
+
+        let common_cfg: MMIO<virtio_pci_common_cfg> =
+            MMIO::new(config_bar.cpu_address + common_cfg.offset().read() as usize);
+
+        debug!("Common config: {:#x?}", common_cfg);
+
+        // Get notification configuration
+        let notify_cfg = virtio_capabilities
+            .iter()
+            .find(|cap| cap.cfg_type().read() == VIRTIO_PCI_CAP_NOTIFY_CFG)
+            .ok_or("Notification capability not found")?;
+
+        // SAFTEY: Notification capability is a different type
+        let notify_cfg = unsafe { notify_cfg.new_type::<virtio_pci_notify_cap>() };
+
+        assert!(
+            is_power_of_2_or_zero(notify_cfg.notify_off_multiplier().read()),
+            "Notify offset multiplier must be a power of 2 or zero"
+        );
+
+        assert!(
+            notify_cfg.cap().offset().read() % 16 == 0,
+            "Notify offset must be 2 byte aligned"
+        );
+
+        assert!(
+            notify_cfg.cap().length().read() >= 2,
+            "Notify length must be at least 2"
+        );
+
+        let notify_bar = pci_device.get_or_initialize_bar(notify_cfg.cap().bar().read());
+        let notify_base = notify_bar.cpu_address + notify_cfg.cap().offset().read() as usize;
+
+        Ok(Self {
+            device: pci_device,
+            virtio_capabilities,
+            common_cfg,
+            notify_cfg,
+            notify_base,
+            queue_notify: BTreeMap::new(),
+        })
+    }
+}
+
+impl Transport for PciTransport {
+    fn device_status(&self) -> u8 {
+        self.common_cfg.device_status().read()
+    }
+
+    fn set_device_status(&mut self, status: u8) {
+        self.common_cfg.device_status().write(status);
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.common_cfg.device_feature_select().write(0);
+        let mut device_features = self.common_cfg.device_feature().read() as u64;
+
+        self.common_cfg.device_feature_select().write(1);
+        device_features |= (self.common_cfg.device_feature().read() as u64) << 32;
+        device_features
+    }
+
+    fn set_driver_features(&mut self, features: u64) {
+        self.common_cfg.driver_feature_select().write(0);
+        self.common_cfg.driver_feature().write(features as u32);
+
+        self.common_cfg.driver_feature_select().write(1);
+        self.common_cfg
+            .driver_feature()
+            .write((features >> 32) as u32);
+    }
+
+    fn max_queue_size(&mut self, queue_index: u16) -> u16 {
+        self.common_cfg.queue_select().write(queue_index);
+        self.common_cfg.queue_size().read()
+    }
+
+    fn activate_queue(
+        &mut self,
+        queue_index: u16,
+        queue_size: u16,
+        descriptor_area: u64,
+        driver_area: u64,
+        device_area: u64,
+    ) {
+        self.common_cfg.queue_select().write(queue_index);
+        self.common_cfg.queue_size().write(queue_size);
+
+        let notify_offset = self.common_cfg.queue_notify_off().read() as u32
+            * self.notify_cfg.notify_off_multiplier().read();
+        assert!(
+            self.notify_cfg.cap().length().read() >= notify_offset + 2,
+            "Notify length must be at least the notify offset"
+        );
+        self.queue_notify.insert(
+            queue_index,
+            MMIO::new(self.notify_base + notify_offset as usize),
+        );
+
+        self.common_cfg.queue_desc().write(descriptor_area);
+        self.common_cfg.queue_driver().write(driver_area);
+        self.common_cfg.queue_device().write(device_area);
+        self.common_cfg.queue_enable().write(1);
+    }
+
+    fn notify(&mut self, queue_index: u16) {
+        if let Some(notify) = self.queue_notify.get_mut(&queue_index) {
+            notify.write(queue_index);
+        }
+    }
+
+    fn config_generation(&self) -> u32 {
+        self.common_cfg.config_generation().read() as u32
+    }
+
+    fn device_config_address(&mut self) -> Result<usize, &'static str> {
+        let device_cfg_cap = self
+            .virtio_capabilities
+            .iter()
+            .find(|cap| cap.cfg_type().read() == VIRTIO_PCI_CAP_DEVICE_CFG)
+            .ok_or("Device configuration capability not found")?;
+
+        debug!(
+            "Device configuration capability found at {:?}",
+            device_cfg_cap
+        );
+
+        let bar = device_cfg_cap.bar().read();
+        let offset = device_cfg_cap.offset().read() as usize;
+        let config_bar = self.device.get_or_initialize_bar(bar);
+
+        Ok(config_bar.cpu_address + offset)
+    }
+}
+
+mmio_struct! {
+    #[repr(C)]
+    struct virtio_pci_common_cfg {
+        device_feature_select: u32,
+        device_feature: u32,
+        driver_feature_select: u32,
+        driver_feature: u32,
+        config_msix_vector: u16,
+        num_queues: u16,
+        device_status: u8,
+        config_generation: u8,
+        /* About a specific virtqueue. */
+        queue_select: u16,
+        queue_size: u16,
+        queue_msix_vector: u16,
+        queue_enable: u16,
+        queue_notify_off: u16,
+        queue_desc: u64,
+        queue_driver: u64,
+        queue_device: u64,
+    }
+}
+
+mmio_struct! {
+    #[repr(C)]
+    struct virtio_pci_notify_cap {
+        cap: crate::drivers::virtio::capability::virtio_pci_cap,
+        notify_off_multiplier: u32,
+    }
+}
@@ -0,0 +1,22 @@
+/// The way a virtio device is attached to the system (PCI or MMIO).
+/// Everything above is handled by [`VirtioDevice`](super::device::VirtioDevice).
+pub trait Transport: Send {
+    fn device_status(&self) -> u8;
+    fn set_device_status(&mut self, status: u8);
+    fn device_features(&mut self) -> u64;
+    fn set_driver_features(&mut self, features: u64);
+    fn max_queue_size(&mut self, queue_index: u16) -> u16;
+    fn activate_queue(
+        &mut self,
+        queue_index: u16,
+        queue_size: u16,
+        descriptor_area: u64,
+        driver_area: u64,
+        device_area: u64,
+    );
+    fn notify(&mut self, queue_index: u16);
+    /// Address of the device specific configuration
+    fn device_config_address(&mut self) -> Result<usize, &'static str>;
+    /// Changes whenever the device modifies its configuration
+    fn config_generation(&self) -> u32;
+}
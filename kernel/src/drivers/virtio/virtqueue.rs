@@ -1,73 +1,149 @@
-use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
-
-use crate::{cpu::Cpu, debug, klibc::MMIO};
-
-/// A virtio queue.
-/// Using Box to prevent content from being moved.
-pub struct VirtQueue<const QUEUE_SIZE: usize> {
-    descriptor_area: Box<[virtq_desc; QUEUE_SIZE]>,
-    free_descriptor_indices: Vec<u16>,
-    outstanding_buffers: BTreeMap<u16, DeconstructedVec>,
-    last_used_ring_index: u16,
-    driver_area: Box<virtq_avail<QUEUE_SIZE>>,
-    device_area: Box<virtq_used<QUEUE_SIZE>>,
-    queue_index: u16,
-    notify: Option<MMIO<u16>>,
-}
-
-#[allow(dead_code)]
-struct DeconstructedVec {
-    ptr: *mut u8,
-    length: usize,
-    capacity: usize,
+//! Split virtqueues. A driver submits chains of buffers and gets a
+//! [`Token`] back, which identifies the chain once the device used it.
+//! Completions are either returned by [`VirtQueue::poll_used`] or handed
+//! to the callback given on submission. The size of the queue is agreed
+//! with the device at runtime.
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+
+use crate::{
+    cpu::Cpu,
+    debug,
+    memory::{dma::DmaBuffer, vma::PinnedBuffer},
+};
+
+/// Identifies a submitted descriptor chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Token(u16);
+
+/// A buffer of a descriptor chain, named from the view of the device.
+/// Readable buffers must come before the writable ones.
+#[derive(Debug)]
+pub enum Buffer {
+    Readable(DmaBuffer),
+    Writable(DmaBuffer),
+    // Memory of a process, only read by the device
+    Pinned(PinnedBuffer),
 }
 
-// SAFETY: A deconstructed Vec<u8> can be send to other threads just like Vec<u8>
-unsafe impl Send for DeconstructedVec {}
+impl Buffer {
+    fn is_writable(&self) -> bool {
+        matches!(self, Buffer::Writable(_))
+    }
 
-impl DeconstructedVec {
-    fn from_vec(vec: Vec<u8>) -> Self {
-        let (ptr, length, capacity) = vec.into_raw_parts();
-        Self {
-            ptr,
-            length,
-            capacity,
+    fn physical_address(&self) -> u64 {
+        match self {
+            Buffer::Readable(data) | Buffer::Writable(data) => data.physical_address(),
+            Buffer::Pinned(data) => data.physical_address(),
         }
     }
 
-    fn into_vec_with_len(self, length: usize) -> Vec<u8> {
-        assert!(
-            length <= self.capacity,
-            "Length must be smaller or equal capacity"
-        );
-        unsafe { Vec::from_raw_parts(self.ptr, length, self.capacity) }
+    fn len(&self) -> usize {
+        match self {
+            Buffer::Readable(data) | Buffer::Writable(data) => data.len(),
+            Buffer::Pinned(data) => data.len(),
+        }
     }
 }
 
-pub enum BufferDirection {
-    DriverWritable,
-    DeviceWritable,
+/// A chain the device is done with. Writable buffers are truncated to
+/// the bytes the device wrote into them.
+#[derive(Debug)]
+pub struct Completion {
+    pub token: Token,
+    pub buffers: Vec<Buffer>,
 }
 
+impl Completion {
+    /// The first writable buffer, for chains with a single one
+    pub fn into_written(self) -> DmaBuffer {
+        self.buffers
+            .into_iter()
+            .find_map(|buffer| match buffer {
+                Buffer::Writable(data) => Some(data),
+                _ => None,
+            })
+            .expect("Chain must have a writable buffer")
+    }
+}
+
+/// Runs when the device used the chain. The queue is borrowed at this
+/// point, callbacks can't submit to it.
+pub type Callback = Box<dyn FnOnce(Completion) + Send>;
+
 #[derive(Debug)]
 pub enum QueueError {
     NoFreeDescriptors,
 }
 
-impl<const QUEUE_SIZE: usize> VirtQueue<QUEUE_SIZE> {
-    pub fn new(queue_size: u16, queue_index: u16) -> Self {
-        assert!(queue_size == QUEUE_SIZE as u16, "Queue size must be equal");
-        assert!(queue_size % 2 == 0, "Queue size must be a power of 2");
-        let queue = VirtQueue {
-            descriptor_area: Box::new(core::array::from_fn(|_| virtq_desc::default())),
-            free_descriptor_indices: (0..queue_size).collect(),
-            outstanding_buffers: BTreeMap::new(),
+/// The size we use for a queue the device offers up to `maximum`
+/// entries of. Split queues must be a power of 2 in size. None if the
+/// queue doesn't exist.
+pub fn negotiated_queue_size(wanted: u16, maximum: u16) -> Option<u16> {
+    let size = wanted.min(maximum);
+    if size == 0 {
+        return None;
+    }
+    Some(1 << size.ilog2())
+}
+
+struct OutstandingBuffer {
+    descriptor_index: u16,
+    buffer: Buffer,
+}
+
+struct OutstandingChain {
+    buffers: Vec<OutstandingBuffer>,
+    callback: Option<Callback>,
+}
+
+/// A virtio queue. The areas are shared with the device and therefore
+/// allocated as DMA buffers.
+pub struct VirtQueue {
+    size: u16,
+    // virtq_desc[size]
+    descriptor_area: DmaBuffer,
+    // flags, idx, ring[size], used_event as u16
+    driver_area: DmaBuffer,
+    // flags and idx as u16, ring[size] of id and len as u32, avail_event
+    device_area: DmaBuffer,
+    free_descriptor_indices: Vec<u16>,
+    outstanding_chains: BTreeMap<u16, OutstandingChain>,
+    // Used chains which were passed by while waiting for another one
+    completed: VecDeque<Completion>,
+    last_used_ring_index: u16,
+    // VIRTIO_F_EVENT_IDX was negotiated
+    event_index: bool,
+    // Available index when the device was notified the last time
+    notified_available_index: u16,
+    queue_index: u16,
+}
+
+impl VirtQueue {
+    pub fn new(size: u16, queue_index: u16, event_index: bool) -> Self {
+        assert!(size.is_power_of_two(), "Queue size must be a power of 2");
+        let entries = size as usize;
+        let mut queue = VirtQueue {
+            size,
+            descriptor_area: DmaBuffer::new(entries * core::mem::size_of::<virtq_desc>()),
+            driver_area: DmaBuffer::new((entries + 3) * core::mem::size_of::<u16>()),
+            device_area: DmaBuffer::new(4 + entries * core::mem::size_of::<virtq_used_elem>() + 2),
+            free_descriptor_indices: (0..size).collect(),
+            outstanding_chains: BTreeMap::new(),
+            completed: VecDeque::new(),
             last_used_ring_index: 0,
-            driver_area: Box::<virtq_avail<QUEUE_SIZE>>::default(),
-            device_area: Box::<virtq_used<QUEUE_SIZE>>::default(),
+            event_index,
+            notified_available_index: 0,
             queue_index,
-            notify: None,
         };
+        // We poll the queues, interrupts are only in the way
+        queue.driver_ring()[0] = VIRTQ_AVAIL_F_NO_INTERRUPT;
+        queue.suppress_interrupts();
+
         assert!(
             queue.descriptor_area_physical_address() % 16 == 0,
             "Descriptor area not aligned"
@@ -84,112 +160,258 @@ impl<const QUEUE_SIZE: usize> VirtQueue<QUEUE_SIZE> {
         queue
     }
 
-    pub fn set_notify(&mut self, notify: MMIO<u16>) {
-        self.notify = Some(notify);
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn queue_index(&self) -> u16 {
+        self.queue_index
     }
 
     pub fn descriptor_area_physical_address(&self) -> u64 {
-        self.descriptor_area.as_ptr() as u64
+        self.descriptor_area.physical_address()
     }
 
     pub fn driver_area_physical_address(&self) -> u64 {
-        &*self.driver_area as *const _ as u64
+        self.driver_area.physical_address()
     }
 
     pub fn device_area_physical_address(&self) -> u64 {
-        &*self.device_area as *const _ as u64
+        self.device_area.physical_address()
+    }
+
+    fn descriptor(&mut self, index: u16) -> &mut virtq_desc {
+        assert!(index < self.size, "Descriptor index out of bounds");
+        // SAFETY: The area holds `size` descriptors and is aligned to a
+        // cache line
+        unsafe {
+            &mut *self
+                .descriptor_area
+                .as_mut_ptr()
+                .cast::<virtq_desc>()
+                .add(index as usize)
+        }
+    }
+
+    // Only written by us
+    fn driver_ring(&mut self) -> &mut [u16] {
+        let entries = self.size as usize + 3;
+        // SAFETY: The area holds `size + 3` u16 and is aligned to a cache line
+        unsafe { core::slice::from_raw_parts_mut(self.driver_area.as_mut_ptr().cast(), entries) }
+    }
+
+    /// Put a single buffer into the virtqueue
+    pub fn put_buffer(&mut self, buffer: Buffer) -> Result<Token, QueueError> {
+        self.submit(alloc::vec![buffer])
+    }
+
+    /// Puts a request which the device reads together with a buffer for
+    /// the response into the queue as one descriptor chain.
+    pub fn put_request(
+        &mut self,
+        request: DmaBuffer,
+        response: DmaBuffer,
+    ) -> Result<Token, QueueError> {
+        self.submit(alloc::vec![
+            Buffer::Readable(request),
+            Buffer::Writable(response)
+        ])
     }
 
-    /// Put a buffer into the virtque.
-    /// Returns the id of the descriptor if the request was successful.
-    /// Returns the original request data in case the request was errornous.
-    pub fn put_buffer(
+    /// The completion is returned by [`VirtQueue::poll_used`]
+    pub fn submit(&mut self, chain: Vec<Buffer>) -> Result<Token, QueueError> {
+        self.submit_chain(chain, None)
+    }
+
+    /// The completion is given to `callback` instead of being returned
+    #[allow(dead_code)]
+    pub fn submit_with_callback(
         &mut self,
-        buffer: Vec<u8>,
-        direction: BufferDirection,
-    ) -> Result<u16, QueueError> {
-        let free_descriptor_index = self
+        chain: Vec<Buffer>,
+        callback: Callback,
+    ) -> Result<Token, QueueError> {
+        self.submit_chain(chain, Some(callback))
+    }
+
+    fn submit_chain(
+        &mut self,
+        chain: Vec<Buffer>,
+        callback: Option<Callback>,
+    ) -> Result<Token, QueueError> {
+        assert!(!chain.is_empty(), "Chain must not be empty");
+        assert!(
+            chain
+                .windows(2)
+                .all(|pair| !pair[0].is_writable() || pair[1].is_writable()),
+            "Readable buffers must come before writable ones"
+        );
+        if self.free_descriptor_indices.len() < chain.len() {
+            return Err(QueueError::NoFreeDescriptors);
+        }
+        let indices = self
             .free_descriptor_indices
-            .pop()
-            .ok_or(QueueError::NoFreeDescriptors)?;
-        let descriptor = &mut self.descriptor_area[free_descriptor_index as usize];
-        descriptor.addr = buffer.as_ptr() as u64;
-        descriptor.len = buffer.len() as u32;
-        descriptor.flags = match direction {
-            BufferDirection::DeviceWritable => VIRTQ_DESC_F_WRITE,
-            BufferDirection::DriverWritable => 0,
-        };
-        descriptor.next = 0;
+            .split_off(self.free_descriptor_indices.len() - chain.len());
+
+        let mut buffers = Vec::with_capacity(chain.len());
+        for (position, buffer) in chain.into_iter().enumerate() {
+            let descriptor_index = indices[position];
+            let descriptor = self.descriptor(descriptor_index);
+            descriptor.addr = buffer.physical_address();
+            descriptor.len = buffer.len() as u32;
+            descriptor.flags = if buffer.is_writable() {
+                VIRTQ_DESC_F_WRITE
+            } else {
+                0
+            };
+            descriptor.next = 0;
+            if let Some(&next) = indices.get(position + 1) {
+                descriptor.flags |= VIRTQ_DESC_F_NEXT;
+                descriptor.next = next;
+            }
+            buffers.push(OutstandingBuffer {
+                descriptor_index,
+                buffer,
+            });
+        }
 
-        // Set available ring
+        let head_index = indices[0];
+        assert!(
+            self.outstanding_chains
+                .insert(head_index, OutstandingChain { buffers, callback })
+                .is_none(),
+            "Outstanding chains is not allowed to contain this index"
+        );
+        self.make_available(head_index);
+
+        Ok(Token(head_index))
+    }
+
+    fn make_available(&mut self, head_index: u16) {
         // avail->ring[avail->idx % qsz] = head;
-        self.driver_area.ring[self.driver_area.idx as usize % QUEUE_SIZE] = free_descriptor_index;
+        let size = self.size;
+        let driver_ring = self.driver_ring();
+        let available_index = driver_ring[1];
+        driver_ring[2 + (available_index % size) as usize] = head_index;
 
         Cpu::memory_fence();
 
-        self.driver_area.idx = self.driver_area.idx.wrapping_add(1);
+        self.driver_ring()[1] = available_index.wrapping_add(1);
 
         Cpu::memory_fence();
+    }
+
+    /// Whether the device wants to be notified about the buffers made
+    /// available since the last notification
+    pub fn needs_notification(&mut self) -> bool {
+        Cpu::memory_fence();
+        let available_index = self.driver_ring()[1];
+        let last_notified = core::mem::replace(&mut self.notified_available_index, available_index);
+        if self.event_index {
+            let avail_event = self.read_device_u16(2 + 4 * self.size as usize);
+            // The device wants a notification once avail_event was passed
+            available_index.wrapping_sub(avail_event).wrapping_sub(1)
+                < available_index.wrapping_sub(last_notified)
+        } else {
+            self.read_device_u16(0) & VIRTQ_USED_F_NO_NOTIFY == 0
+        }
+    }
 
-        let insert_result = self
-            .outstanding_buffers
-            .insert(free_descriptor_index, DeconstructedVec::from_vec(buffer))
-            .is_none();
+    // Offset in the device area in units of u16
+    fn read_device_u16(&self, offset: usize) -> u16 {
+        // SAFETY: Offsets are within the device area, which the device
+        // writes concurrently
+        unsafe {
+            (self.device_area.as_ptr() as *const u16)
+                .add(offset)
+                .read_volatile()
+        }
+    }
 
-        assert!(
-            insert_result,
-            "Outstanding buffers is not allowed to contain this index"
-        );
+    fn read_used_element(&self, ring_index: u16) -> (u16, usize) {
+        let slot = 1 + 2 * (ring_index % self.size) as usize;
+        // SAFETY: The slot is within the device area
+        let (id, len) = unsafe {
+            let element = (self.device_area.as_ptr() as *const u32).add(slot);
+            (element.read_volatile(), element.add(1).read_volatile())
+        };
+        (id as u16, len as usize)
+    }
+
+    /// With VIRTIO_F_EVENT_IDX the flags are ignored. The event is
+    /// put as far into the future as possible instead.
+    fn suppress_interrupts(&mut self) {
+        if self.event_index {
+            let used_event = self.size as usize + 2;
+            let last_used_ring_index = self.last_used_ring_index;
+            self.driver_ring()[used_event] = last_used_ring_index.wrapping_sub(1);
+        }
+    }
 
-        Ok(free_descriptor_index)
+    fn free_descriptor(&mut self, index: u16) {
+        let descriptor = self.descriptor(index);
+        descriptor.addr = 0;
+        descriptor.len = 0;
+        descriptor.flags = 0;
+        self.free_descriptor_indices.push(index);
     }
 
-    pub fn receive_buffer(&mut self) -> Vec<UsedBuffer> {
+    /// Processes the chains the device used since the last call. Chains
+    /// which were submitted with a callback are passed to it, all others
+    /// are returned.
+    pub fn poll_used(&mut self) -> Vec<Completion> {
+        let mut completions: Vec<Completion> = self.completed.drain(..).collect();
         Cpu::memory_fence();
         // Prevent re/reading the hardware. Only tackle the current amount of buffers.
-        let current_device_index = self.device_area.idx;
-        if self.last_used_ring_index == current_device_index {
-            return Vec::new();
-        }
-        debug!("Current device index: {:#x?}", current_device_index);
-        let mut return_buffers: Vec<UsedBuffer> = Vec::new();
+        let current_device_index = self.read_device_u16(1);
         while self.last_used_ring_index != current_device_index {
             debug!("last used ring index: {:#x?}", self.last_used_ring_index);
-            let result_descriptor =
-                &mut self.device_area.ring[self.last_used_ring_index as usize % QUEUE_SIZE];
-            let descriptor_entry = &mut self.descriptor_area[result_descriptor.id as usize];
-            debug!("Received packet from descriptor {:#x?}", descriptor_entry);
-            debug!("Result descriptor {:#x?}", result_descriptor);
-            let index = result_descriptor.id as u16;
-            let buffer = self
-                .outstanding_buffers
-                .remove(&index)
-                .expect("There must be an outstanding buffer for this id")
-                .into_vec_with_len(result_descriptor.len as usize);
-            return_buffers.push(UsedBuffer { index, buffer });
-            descriptor_entry.addr = 0;
-            descriptor_entry.len = 0;
-            self.free_descriptor_indices.push(index);
+            let (head_index, mut written) = self.read_used_element(self.last_used_ring_index);
+            let chain = self
+                .outstanding_chains
+                .remove(&head_index)
+                .expect("There must be an outstanding chain for this id");
+
+            let mut buffers = Vec::with_capacity(chain.buffers.len());
+            for outstanding in chain.buffers {
+                self.free_descriptor(outstanding.descriptor_index);
+                let mut buffer = outstanding.buffer;
+                if let Buffer::Writable(data) = &mut buffer {
+                    let length = written.min(data.len());
+                    written -= length;
+                    data.truncate(length);
+                }
+                buffers.push(buffer);
+            }
+
+            let completion = Completion {
+                token: Token(head_index),
+                buffers,
+            };
+            match chain.callback {
+                Some(callback) => callback(completion),
+                None => completions.push(completion),
+            }
             self.last_used_ring_index = self.last_used_ring_index.wrapping_add(1);
         }
-        return_buffers
+        self.suppress_interrupts();
+        completions
     }
 
-    pub fn notify(&mut self) {
-        if let Some(notify) = &mut self.notify {
-            notify.write(self.queue_index);
+    /// Spins until the device used the chain of `token`. Other chains
+    /// used in the meantime are returned by the next [`VirtQueue::poll_used`].
+    pub fn wait_for(&mut self, token: Token) -> Completion {
+        loop {
+            for completion in self.poll_used() {
+                if completion.token == token {
+                    return completion;
+                }
+                self.completed.push_back(completion);
+            }
         }
     }
 }
 
-#[derive(Debug)]
-pub struct UsedBuffer {
-    pub index: u16,
-    pub buffer: Vec<u8>,
-}
-
 /* This marks a buffer as continuing via the next field. */
-#[allow(dead_code)]
 const VIRTQ_DESC_F_NEXT: u16 = 1;
 /* This marks a buffer as device write-only (otherwise device read-only). */
 const VIRTQ_DESC_F_WRITE: u16 = 2;
@@ -207,57 +429,187 @@ struct virtq_desc {
     next: u16,
 }
 
-const VIRTQ_AVAIL_F_NO_INTERRUPT: u16 = 1;
-
 #[allow(non_camel_case_types)]
-#[repr(C, align(2))]
-struct virtq_avail<const QUEUE_SIZE: usize> {
-    flags: u16,
-    idx: u16,
-    ring: [u16; QUEUE_SIZE],
-    used_event: u16, /* Only if VIRTIO_F_EVENT_IDX */
+#[repr(C)]
+struct virtq_used_elem {
+    id: u32, /* Index of start of used descriptor chain. */
+    len: u32, /*
+              * The number of bytes written into the device writable portion of
+              * the buffer described by the descriptor chain.
+              */
 }
 
-impl<const QUEUE_SIZE: usize> Default for virtq_avail<QUEUE_SIZE> {
-    fn default() -> Self {
-        Self {
-            flags: VIRTQ_AVAIL_F_NO_INTERRUPT, // Ignore interrupts for the beginning
-            idx: 0,
-            ring: [0; QUEUE_SIZE],
-            used_event: Default::default(),
-        }
-    }
-}
+const VIRTQ_AVAIL_F_NO_INTERRUPT: u16 = 1;
 
 const VIRTQ_USED_F_NO_NOTIFY: u16 = 1;
 
-#[allow(non_camel_case_types)]
-#[repr(C, align(4))]
-struct virtq_used<const QUEUE_SIZE: usize> {
-    flags: u16,
-    idx: u16,
-    ring: [virtq_used_elem; QUEUE_SIZE],
-    avail_event: u16, /* Only if VIRTIO_F_EVENT_IDX */
-}
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+    use common::mutex::Mutex;
+
+    use super::{
+        negotiated_queue_size, Buffer, QueueError, Token, VirtQueue, VIRTQ_DESC_F_NEXT,
+        VIRTQ_DESC_F_WRITE, VIRTQ_USED_F_NO_NOTIFY,
+    };
+    use crate::memory::dma::DmaBuffer;
+
+    fn write_device_u16(queue: &mut VirtQueue, offset: usize, value: u16) {
+        // SAFETY: The tests only write within the device area
+        unsafe {
+            queue
+                .device_area
+                .as_mut_ptr()
+                .cast::<u16>()
+                .add(offset)
+                .write_volatile(value)
+        };
+    }
 
-impl<const QUEUE_SIZE: usize> Default for virtq_used<QUEUE_SIZE> {
-    fn default() -> Self {
-        Self {
-            flags: VIRTQ_USED_F_NO_NOTIFY,
-            idx: 0,
-            ring: core::array::from_fn(|_| virtq_used_elem::default()),
-            avail_event: Default::default(),
+    /// Plays the device: Uses the next available chain and writes
+    /// `reply` into its writable buffers
+    fn use_next_chain(queue: &mut VirtQueue, reply: &[u8]) -> Token {
+        let size = queue.size as usize;
+        let used_index = queue.read_device_u16(1);
+        let head_index = queue.driver_ring()[2 + used_index as usize % size];
+
+        let mut index = head_index;
+        let mut written = 0;
+        loop {
+            let descriptor = queue.descriptor(index);
+            if descriptor.flags & VIRTQ_DESC_F_WRITE != 0 {
+                let length = (descriptor.len as usize).min(reply.len() - written);
+                // SAFETY: The descriptor points to the buffer of the driver
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        reply[written..].as_ptr(),
+                        descriptor.addr as *mut u8,
+                        length,
+                    );
+                }
+                written += length;
+            }
+            if descriptor.flags & VIRTQ_DESC_F_NEXT == 0 {
+                break;
+            }
+            index = descriptor.next;
         }
+
+        // The element is two u32, i.e. four u16
+        let element = 2 + 4 * (used_index as usize % size);
+        write_device_u16(queue, element, head_index);
+        write_device_u16(queue, element + 2, written as u16);
+        write_device_u16(queue, 1, used_index.wrapping_add(1));
+        Token(head_index)
     }
-}
 
-#[allow(non_camel_case_types)]
-#[repr(C)]
-#[derive(Default, Debug)]
-struct virtq_used_elem {
-    id: u32, /* Index of start of used descriptor chain. */
-    len: u32, /*
-              * The number of bytes written into the device writable portion of
-              * the buffer described by the descriptor chain.
-              */
+    fn set_avail_event(queue: &mut VirtQueue, avail_event: u16) {
+        let offset = 2 + 4 * queue.size as usize;
+        write_device_u16(queue, offset, avail_event);
+    }
+
+    fn data(buffer: &Buffer) -> &[u8] {
+        match buffer {
+            Buffer::Readable(data) | Buffer::Writable(data) => data,
+            Buffer::Pinned(data) => data,
+        }
+    }
+
+    fn readable(data: &[u8]) -> Buffer {
+        Buffer::Readable(DmaBuffer::from_slice(data))
+    }
+
+    fn writable(size: usize) -> Buffer {
+        Buffer::Writable(DmaBuffer::new(size))
+    }
+
+    #[test_case]
+    fn queue_sizes_are_powers_of_two() {
+        assert_eq!(negotiated_queue_size(0x100, 0x400), Some(0x100));
+        assert_eq!(negotiated_queue_size(0x100, 0x80), Some(0x80));
+        assert_eq!(negotiated_queue_size(0x100, 0xc0), Some(0x80));
+        assert_eq!(negotiated_queue_size(0x100, 0), None);
+    }
+
+    #[test_case]
+    fn used_chains_are_completed_by_token() {
+        let mut queue = VirtQueue::new(4, 0, false);
+        let token = queue
+            .put_request(DmaBuffer::from_slice(&[1, 2, 3]), DmaBuffer::new(8))
+            .expect("Queue must have room");
+        let single = queue.put_buffer(writable(2)).unwrap();
+        assert!(matches!(
+            queue.submit(vec![readable(&[0]), readable(&[0])]),
+            Err(QueueError::NoFreeDescriptors)
+        ));
+        assert!(queue.poll_used().is_empty());
+
+        assert_eq!(use_next_chain(&mut queue, b"abc"), token);
+        let completions = queue.poll_used();
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].token, token);
+        let buffers = &completions[0].buffers;
+        assert!(matches!(buffers[0], Buffer::Readable(_)));
+        assert_eq!(data(&buffers[0]), [1, 2, 3]);
+        assert!(matches!(buffers[1], Buffer::Writable(_)));
+        assert_eq!(data(&buffers[1]), b"abc");
+        assert_eq!(queue.free_descriptor_indices.len(), 3);
+
+        assert_eq!(use_next_chain(&mut queue, b"xyz"), single);
+        let completion = queue.poll_used().pop().unwrap();
+        // Only as much as fits into the buffer
+        assert_eq!(&*completion.into_written(), b"xy");
+        assert_eq!(queue.free_descriptor_indices.len(), 4);
+    }
+
+    #[test_case]
+    fn callbacks_get_their_completions() {
+        let mut queue = VirtQueue::new(4, 0, false);
+        let received: Arc<Mutex<Vec<Vec<u8>>>> = Arc::new(Mutex::new(Vec::new()));
+        let shared = received.clone();
+        queue
+            .submit_with_callback(
+                vec![writable(4)],
+                Box::new(move |completion| {
+                    shared.lock().push(completion.into_written().to_vec());
+                }),
+            )
+            .unwrap();
+        let passed_by = queue.put_buffer(writable(4)).unwrap();
+        let other = queue.put_buffer(writable(4)).unwrap();
+
+        use_next_chain(&mut queue, b"one");
+        use_next_chain(&mut queue, b"two");
+        use_next_chain(&mut queue, b"six");
+        assert_eq!(&*queue.wait_for(other).into_written(), b"six");
+        assert_eq!(*received.lock(), [b"one".to_vec()]);
+
+        // Passed by while waiting
+        let completions = queue.poll_used();
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].token, passed_by);
+    }
+
+    #[test_case]
+    fn notifications_are_suppressed() {
+        let mut queue = VirtQueue::new(4, 0, false);
+        queue.put_buffer(readable(&[0])).unwrap();
+        assert!(queue.needs_notification());
+        write_device_u16(&mut queue, 0, VIRTQ_USED_F_NO_NOTIFY);
+        queue.put_buffer(readable(&[0])).unwrap();
+        assert!(!queue.needs_notification());
+
+        let mut queue = VirtQueue::new(4, 0, true);
+        // The device wants to know when the first buffer is available
+        set_avail_event(&mut queue, 0);
+        queue.put_buffer(readable(&[0])).unwrap();
+        assert!(queue.needs_notification());
+        // It didn't process the first one yet
+        queue.put_buffer(readable(&[0])).unwrap();
+        assert!(!queue.needs_notification());
+        set_avail_event(&mut queue, 2);
+        queue.put_buffer(readable(&[0])).unwrap();
+        queue.put_buffer(readable(&[0])).unwrap();
+        assert!(queue.needs_notification());
+    }
 }
@@ -0,0 +1,313 @@
+//! Caches blocks of slow devices in memory, e.g. of loop devices whose
+//! file lives on the host share. Writes only mark the cached block as
+//! dirty, it is written back when it is evicted, when the flusher thread
+//! wakes up or when userspace calls `sys_sync`. The least recently used
+//! block is evicted when the cache is full.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use common::{mutex::Mutex, runtime_initialized::RuntimeInitializedData};
+
+use crate::{
+    cpu::Cpu,
+    initcall,
+    io::device::BlockDevice,
+    processes::{kthread, process::Pid, timer},
+    warn,
+};
+
+use super::vfs::FsError;
+
+const FLUSH_INTERVAL_MILLISECONDS: u64 = 5000;
+
+static CACHES: Mutex<Vec<Arc<BlockCache>>> = Mutex::new(Vec::new());
+
+static FLUSHER: RuntimeInitializedData<Pid> = RuntimeInitializedData::new();
+
+struct CachedBlock {
+    data: Vec<u8>,
+    dirty: bool,
+    last_use: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    blocks: BTreeMap<u64, CachedBlock>,
+    // Maps the time of the last use to the block index, the first entry
+    // is the least recently used block
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+impl CacheState {
+    fn touch(&mut self, index: u64) {
+        self.clock += 1;
+        let clock = self.clock;
+        let block = self.blocks.get_mut(&index).expect("Block must be cached");
+        self.lru.remove(&block.last_use);
+        block.last_use = clock;
+        self.lru.insert(clock, index);
+    }
+}
+
+pub struct BlockCache {
+    device: Arc<dyn BlockDevice>,
+    // In blocks
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl BlockCache {
+    pub fn new(device: Arc<dyn BlockDevice>, capacity: usize) -> Self {
+        assert!(capacity > 0, "Block cache must hold at least one block");
+        Self {
+            device,
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Makes room for one more block by evicting the least recently
+    /// used one. A dirty block stays cached if it can't be written back.
+    fn make_room(&self, state: &mut CacheState) -> Result<(), FsError> {
+        if state.blocks.len() < self.capacity {
+            return Ok(());
+        }
+        let (&last_use, &index) = state
+            .lru
+            .first_key_value()
+            .expect("Cache must not be empty");
+        let block = &state.blocks[&index];
+        if block.dirty {
+            self.device.write_block(index, &block.data)?;
+        }
+        state.lru.remove(&last_use);
+        state.blocks.remove(&index);
+        Ok(())
+    }
+
+    fn insert(
+        &self,
+        state: &mut CacheState,
+        index: u64,
+        data: Vec<u8>,
+        dirty: bool,
+    ) -> Result<(), FsError> {
+        self.make_room(state)?;
+        state.blocks.insert(
+            index,
+            CachedBlock {
+                data,
+                dirty,
+                last_use: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Writes all dirty blocks back. Blocks which fail stay dirty, the
+    /// first error is returned.
+    pub fn flush(&self) -> Result<(), FsError> {
+        let mut state = self.state.lock();
+        let mut result = Ok(());
+        for (&index, block) in state.blocks.iter_mut().filter(|(_, block)| block.dirty) {
+            match self.device.write_block(index, &block.data) {
+                Ok(()) => block.dirty = false,
+                Err(error) => result = result.and(Err(error)),
+            }
+        }
+        result
+    }
+
+    /// Drops dirty blocks which can't be written back, otherwise they
+    /// would be retried forever
+    fn discard_dirty(&self) {
+        let mut state = self.state.lock();
+        let dirty: Vec<(u64, u64)> = state
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.dirty)
+            .map(|(&index, block)| (index, block.last_use))
+            .collect();
+        for (index, last_use) in dirty {
+            state.blocks.remove(&index);
+            state.lru.remove(&last_use);
+        }
+    }
+}
+
+impl BlockDevice for BlockCache {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_block(&self, index: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        let mut state = self.state.lock();
+        match state.blocks.get(&index) {
+            Some(block) => buffer.copy_from_slice(&block.data),
+            None => {
+                self.device.read_block(index, buffer)?;
+                self.insert(&mut state, index, buffer.to_vec(), false)?;
+            }
+        }
+        state.touch(index);
+        Ok(())
+    }
+
+    /// Whole blocks are written, therefore a miss doesn't read the block
+    fn write_block(&self, index: u64, data: &[u8]) -> Result<(), FsError> {
+        if index >= self.block_count() {
+            return Err(FsError::NoSpace);
+        }
+        let mut state = self.state.lock();
+        match state.blocks.get_mut(&index) {
+            Some(block) => {
+                block.data.copy_from_slice(data);
+                block.dirty = true;
+            }
+            None => self.insert(&mut state, index, data.to_vec(), true)?,
+        }
+        state.touch(index);
+        Ok(())
+    }
+}
+
+/// Wraps the device in a cache with room for `size` bytes. The cache is
+/// flushed periodically and by [`sync`].
+pub fn cached(device: Arc<dyn BlockDevice>, size: usize) -> Arc<BlockCache> {
+    let capacity = (size / device.block_size()).max(1);
+    let cache = Arc::new(BlockCache::new(device, capacity));
+    CACHES.lock().push(cache.clone());
+    cache
+}
+
+/// Writes back the dirty blocks of all caches and returns the first error
+pub fn sync() -> Result<(), FsError> {
+    // Flushing might take some time, don't hold the lock meanwhile
+    let caches = CACHES.lock().clone();
+    caches
+        .iter()
+        .map(|cache| cache.flush())
+        .fold(Ok(()), Result::and)
+}
+
+initcall!(Late, init);
+
+pub fn init() {
+    FLUSHER.initialize(kthread::spawn("kflushd", flusher));
+}
+
+extern "C" fn flusher() -> ! {
+    loop {
+        // The caches are shared with syscalls, which run with interrupts
+        // disabled
+        Cpu::without_interrupts(|| {
+            let caches = CACHES.lock().clone();
+            for cache in caches {
+                if let Err(error) = cache.flush() {
+                    warn!("Cannot write back cached blocks, discarding them: {error:?}");
+                    cache.discard_dirty();
+                }
+            }
+            timer::add_timer_in(FLUSH_INTERVAL_MILLISECONDS, || kthread::unpark(*FLUSHER));
+        });
+        kthread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{
+        fs::vfs::FsError,
+        io::{device::BlockDevice, ramdisk::RamDisk},
+    };
+
+    use super::BlockCache;
+
+    /// Counts the accesses which reach the device
+    struct CountingDevice {
+        disk: RamDisk,
+        reads: AtomicUsize,
+        writes: AtomicUsize,
+    }
+
+    impl CountingDevice {
+        fn new(block_count: u64) -> Arc<Self> {
+            Arc::new(Self {
+                disk: RamDisk::new(4, block_count),
+                reads: AtomicUsize::new(0),
+                writes: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    impl BlockDevice for CountingDevice {
+        fn block_size(&self) -> usize {
+            self.disk.block_size()
+        }
+
+        fn block_count(&self) -> u64 {
+            self.disk.block_count()
+        }
+
+        fn read_block(&self, index: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.disk.read_block(index, buffer)
+        }
+
+        fn write_block(&self, index: u64, data: &[u8]) -> Result<(), FsError> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.disk.write_block(index, data)
+        }
+    }
+
+    #[test_case]
+    fn repeated_reads_hit_the_cache() {
+        let device = CountingDevice::new(4);
+        let cache = BlockCache::new(device.clone(), 2);
+        let mut block = [0; 4];
+        for _ in 0..3 {
+            cache.read_block(1, &mut block).unwrap();
+        }
+        assert_eq!(device.reads.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.read_block(4, &mut block), Err(FsError::NoSpace));
+    }
+
+    #[test_case]
+    fn least_recently_used_block_is_written_back() {
+        let device = CountingDevice::new(4);
+        let cache = BlockCache::new(device.clone(), 2);
+        let mut block = [0; 4];
+
+        cache.write_block(0, &[1; 4]).unwrap();
+        cache.write_block(1, &[2; 4]).unwrap();
+        cache.read_block(0, &mut block).unwrap();
+        assert_eq!(block, [1; 4]);
+        assert_eq!(device.writes.load(Ordering::Relaxed), 0);
+
+        // Block 1 is the least recently used one
+        cache.read_block(2, &mut block).unwrap();
+        assert_eq!(device.writes.load(Ordering::Relaxed), 1);
+        device.disk.read_block(1, &mut block).unwrap();
+        assert_eq!(block, [2; 4]);
+        device.disk.read_block(0, &mut block).unwrap();
+        assert_eq!(block, [0; 4], "Block 0 is still only cached");
+
+        cache.flush().unwrap();
+        device.disk.read_block(0, &mut block).unwrap();
+        assert_eq!(block, [1; 4]);
+        cache.flush().unwrap();
+        assert_eq!(
+            device.writes.load(Ordering::Relaxed),
+            2,
+            "Clean blocks are not written again"
+        );
+        assert_eq!(cache.write_block(4, &block), Err(FsError::NoSpace));
+    }
+}
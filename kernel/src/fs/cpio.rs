@@ -0,0 +1,157 @@
+//! Parser for cpio archives in the "new ASCII" format (magic 070701),
+//! which is what `cpio -H newc` and the kernel build create.
+
+const MAGIC: &[u8] = b"070701";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+const MODE_TYPE_MASK: u32 = 0o170000;
+const MODE_REGULAR_FILE: u32 = 0o100000;
+const MODE_DIRECTORY: u32 = 0o040000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+    pub fn is_file(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_REGULAR_FILE
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_DIRECTORY
+    }
+}
+
+/// Iterates over the entries until the trailer. A malformed entry ends
+/// the iteration as well.
+pub struct Entries<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+pub fn entries(data: &[u8]) -> Entries<'_> {
+    Entries { data, position: 0 }
+}
+
+// The header fields are 8 hex digits each, following the magic
+fn header_field(header: &[u8], index: usize) -> Option<usize> {
+    let start = MAGIC.len() + index * 8;
+    let digits = core::str::from_utf8(header.get(start..start + 8)?).ok()?;
+    usize::from_str_radix(digits, 16).ok()
+}
+
+impl<'a> Entries<'a> {
+    fn parse_entry(&mut self) -> Option<Entry<'a>> {
+        let header = self.data.get(self.position..self.position + HEADER_SIZE)?;
+        if !header.starts_with(MAGIC) {
+            return None;
+        }
+        let mode = header_field(header, 1)? as u32;
+        let file_size = header_field(header, 6)?;
+        let name_size = header_field(header, 11)?;
+
+        let name_start = self.position + HEADER_SIZE;
+        // The name includes the zero terminator
+        let name = self
+            .data
+            .get(name_start..name_start + name_size.checked_sub(1)?)?;
+        let name = core::str::from_utf8(name).ok()?;
+
+        // Name and data are padded to 4 bytes
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let data = self.data.get(data_start..data_start + file_size)?;
+        self.position = (data_start + file_size).next_multiple_of(4);
+
+        Some(Entry { name, mode, data })
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.parse_entry();
+        match entry {
+            Some(entry) if entry.name != TRAILER => Some(entry),
+            _ => {
+                // Stay at the end
+                self.position = self.data.len();
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use alloc::{format, vec::Vec};
+
+    use super::entries;
+
+    pub fn append_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let header = format!(
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    pub fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        append_entry(&mut archive, "bin", 0o040755, &[]);
+        for (name, data) in files {
+            append_entry(&mut archive, name, 0o100755, data);
+        }
+        append_entry(&mut archive, "TRAILER!!!", 0, &[]);
+        archive
+    }
+
+    #[test_case]
+    fn entries_are_parsed() {
+        let archive = archive(&[("bin/a", b"hello"), ("bin/bc", b"")]);
+        let entries: Vec<_> = entries(&archive).collect();
+        assert_eq!(entries.len(), 3);
+        assert!(!entries[0].is_file());
+        assert!(entries[0].is_directory());
+        assert_eq!(entries[0].name, "bin");
+        assert!(entries[1].is_file());
+        assert_eq!(entries[1].name, "bin/a");
+        assert_eq!(entries[1].data, b"hello");
+        assert_eq!(entries[2].name, "bin/bc");
+        assert_eq!(entries[2].data, b"");
+    }
+
+    #[test_case]
+    fn malformed_archives_end_the_iteration() {
+        let mut corrupted = archive(&[("bin/a", b"hello")]);
+        corrupted[0] = b'1';
+        assert_eq!(entries(&corrupted).count(), 0);
+
+        // Entries in front of the truncation are still found
+        let full = archive(&[("bin/a", b"hello")]);
+        let truncated = &full[..full.len() - 20];
+        assert_eq!(entries(truncated).count(), 2);
+        assert_eq!(entries(&[]).count(), 0);
+    }
+}
@@ -0,0 +1,234 @@
+//! Device nodes are registered by name and show up in /dev. Reads and
+//! writes of a node go straight to its device.
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use common::mutex::Mutex;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    cmdline, info,
+    io::{
+        device::{self, BlockDevice, CharacterDevice},
+        ramdisk::{self, LoopDevice, RamDisk},
+    },
+};
+
+use super::{
+    block_cache,
+    vfs::{self, DirectoryEntry, FileSystem, FsError, Inode, InodeKind},
+};
+
+static DEVICES: Mutex<BTreeMap<String, Arc<dyn Inode>>> = Mutex::new(BTreeMap::new());
+// Loop devices can't be detached, therefore their numbers are never reused
+static NEXT_LOOP_NUMBER: AtomicU64 = AtomicU64::new(0);
+
+struct CharacterNode(Arc<dyn CharacterDevice>);
+
+impl Inode for CharacterNode {
+    fn kind(&self) -> InodeKind {
+        InodeKind::CharacterDevice
+    }
+
+    /// Character devices have no position
+    fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        self.0.read(buffer)
+    }
+
+    fn write_at(&self, _offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        self.0.write(data)
+    }
+
+    /// Streams might never end
+    fn size(&self) -> Result<usize, FsError> {
+        Ok(0)
+    }
+}
+
+struct BlockNode(Arc<dyn BlockDevice>);
+
+impl BlockNode {
+    fn size(&self) -> usize {
+        self.0.block_size() * self.0.block_count() as usize
+    }
+
+    /// Calls `f` with the index of every block in the range, where the
+    /// range starts in the block, where the block starts in the range and
+    /// how many bytes of the block are in the range
+    fn for_each_block(
+        &self,
+        offset: usize,
+        length: usize,
+        mut f: impl FnMut(u64, usize, usize, usize) -> Result<(), FsError>,
+    ) -> Result<usize, FsError> {
+        let block_size = self.0.block_size();
+        let length = length.min(self.size().saturating_sub(offset));
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let offset_in_block = position % block_size;
+            let count = (block_size - offset_in_block).min(length - done);
+            f((position / block_size) as u64, offset_in_block, done, count)?;
+            done += count;
+        }
+        Ok(done)
+    }
+}
+
+impl Inode for BlockNode {
+    fn kind(&self) -> InodeKind {
+        InodeKind::BlockDevice
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let mut block = vec![0; self.0.block_size()];
+        self.for_each_block(offset, buffer.len(), |index, start, done, count| {
+            self.0.read_block(index, &mut block)?;
+            buffer[done..done + count].copy_from_slice(&block[start..start + count]);
+            Ok(())
+        })
+    }
+
+    /// Partial blocks are read before they are written
+    fn write_at(&self, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        if offset >= self.size() && !data.is_empty() {
+            return Err(FsError::NoSpace);
+        }
+        let block_size = self.0.block_size();
+        let mut block = vec![0; block_size];
+        self.for_each_block(offset, data.len(), |index, start, done, count| {
+            if count != block_size {
+                self.0.read_block(index, &mut block)?;
+            }
+            block[start..start + count].copy_from_slice(&data[done..done + count]);
+            self.0.write_block(index, &block)
+        })
+    }
+
+    fn size(&self) -> Result<usize, FsError> {
+        Ok(BlockNode::size(self))
+    }
+
+    fn block_device(&self) -> Option<Arc<dyn BlockDevice>> {
+        Some(self.0.clone())
+    }
+}
+
+struct Root;
+
+impl Inode for Root {
+    fn kind(&self) -> InodeKind {
+        InodeKind::Directory
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        DEVICES.lock().get(name).cloned().ok_or(FsError::NotFound)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        Ok(DEVICES
+            .lock()
+            .iter()
+            .map(|(name, node)| DirectoryEntry {
+                name: name.clone(),
+                kind: node.kind(),
+            })
+            .collect())
+    }
+
+    fn read_at(&self, _offset: usize, _buffer: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::IsADirectory)
+    }
+}
+
+pub struct DevFs;
+
+impl FileSystem for DevFs {
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(Root)
+    }
+}
+
+fn register(name: &str, node: Arc<dyn Inode>) {
+    let previous = DEVICES.lock().insert(name.to_string(), node);
+    assert!(previous.is_none(), "Device {name} is already registered");
+}
+
+pub fn register_character_device(name: &str, device: Arc<dyn CharacterDevice>) {
+    register(name, Arc::new(CharacterNode(device)));
+}
+
+pub fn register_block_device(name: &str, device: Arc<dyn BlockDevice>) {
+    register(name, Arc::new(BlockNode(device)));
+}
+
+/// Makes the file available as /dev/loopN and returns N
+pub fn attach_loop_device(path: &str) -> Result<u64, FsError> {
+    let inode = vfs::resolve(path)?;
+    if inode.kind() != InodeKind::File {
+        return Err(FsError::IsADirectory);
+    }
+    let mut device: Arc<dyn BlockDevice> = Arc::new(LoopDevice::new(inode)?);
+    let cache_size = cmdline::get().block_cache_size;
+    if cache_size > 0 {
+        device = block_cache::cached(device, cache_size * 1024);
+    }
+    let number = NEXT_LOOP_NUMBER.fetch_add(1, Ordering::Relaxed);
+    register_block_device(&format!("loop{number}"), device);
+    Ok(number)
+}
+
+/// Registers the devices which always exist and the ramdisk if the
+/// command line asks for one
+pub fn init() {
+    register_character_device("console", Arc::new(device::Console));
+    register_character_device("null", Arc::new(device::Null));
+    register_character_device("zero", Arc::new(device::Zero));
+    register_character_device("random", Arc::new(device::Random::new()));
+
+    let ramdisk_size = cmdline::get().ramdisk_size;
+    if ramdisk_size > 0 {
+        let block_count = (ramdisk_size * 1024 / ramdisk::BLOCK_SIZE) as u64;
+        register_block_device(
+            "ram0",
+            Arc::new(RamDisk::new(ramdisk::BLOCK_SIZE, block_count)),
+        );
+        info!("Ramdisk with {ramdisk_size} KiB at /dev/ram0");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use crate::{
+        fs::vfs::{FsError, Inode},
+        io::ramdisk::RamDisk,
+    };
+
+    use super::BlockNode;
+
+    #[test_case]
+    fn block_devices_are_accessed_at_byte_offsets() {
+        let node = BlockNode(Arc::new(RamDisk::from_bytes(4, (0..12).collect())));
+
+        let mut buffer = [0; 6];
+        assert_eq!(node.read_at(3, &mut buffer), Ok(6));
+        assert_eq!(buffer, [3, 4, 5, 6, 7, 8]);
+        assert_eq!(node.read_at(10, &mut buffer), Ok(2));
+        assert_eq!(node.read_at(12, &mut buffer), Ok(0));
+
+        assert_eq!(node.write_at(2, &[20, 21, 22, 23, 24]), Ok(5));
+        let mut content = [0; 12];
+        assert_eq!(node.read_at(0, &mut content), Ok(12));
+        assert_eq!(content, [0, 1, 20, 21, 22, 23, 24, 7, 8, 9, 10, 11]);
+        assert_eq!(node.write_at(11, &[30, 31]), Ok(1));
+        assert_eq!(node.write_at(12, &[1]), Err(FsError::NoSpace));
+    }
+}
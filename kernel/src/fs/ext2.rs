@@ -0,0 +1,582 @@
+//! Read-only ext2 on top of a block device, e.g. a loop device of an
+//! image which was created with `mke2fs -t ext2 -d <directory>` on the
+//! host. Only the filetype feature is supported beyond the original
+//! format, images with other incompatible features are rejected.
+//! Symbolic links and device files are not shown.
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+use crate::io::device::BlockDevice;
+
+use super::vfs::{DirectoryEntry, FileSystem, FsError, Inode, InodeKind};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const MAGIC: u16 = 0xef53;
+const ROOT_INODE: u32 = 2;
+const GROUP_DESCRIPTOR_SIZE: usize = 32;
+// Revision 0 has a fixed inode size
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+const INCOMPAT_FILETYPE: u32 = 0x2;
+
+const MODE_TYPE_MASK: u16 = 0o170000;
+const MODE_REGULAR_FILE: u16 = 0o100000;
+const MODE_DIRECTORY: u16 = 0o040000;
+
+const FILETYPE_REGULAR_FILE: u8 = 1;
+const FILETYPE_DIRECTORY: u8 = 2;
+
+// The remaining three block pointers are single, double and triple
+// indirect
+const DIRECT_BLOCKS: usize = 12;
+const BLOCK_POINTERS: usize = 15;
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(
+        data[offset..offset + 4]
+            .try_into()
+            .expect("Slice must have 4 bytes"),
+    )
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Superblock {
+    inodes_count: u32,
+    block_size: usize,
+    first_data_block: u32,
+    inodes_per_group: u32,
+    inode_size: usize,
+    has_filetype: bool,
+}
+
+impl Superblock {
+    fn parse(data: &[u8]) -> Result<Self, FsError> {
+        if u16_at(data, 56) != MAGIC {
+            return Err(FsError::InvalidFilesystem);
+        }
+        let log_block_size = u32_at(data, 24);
+        let revision = u32_at(data, 76);
+        let incompatible_features = if revision == 0 { 0 } else { u32_at(data, 96) };
+        if log_block_size > 6 || incompatible_features & !INCOMPAT_FILETYPE != 0 {
+            return Err(FsError::InvalidFilesystem);
+        }
+        let superblock = Self {
+            inodes_count: u32_at(data, 0),
+            block_size: 1024 << log_block_size,
+            first_data_block: u32_at(data, 20),
+            inodes_per_group: u32_at(data, 40),
+            inode_size: if revision == 0 {
+                GOOD_OLD_INODE_SIZE
+            } else {
+                u16_at(data, 88) as usize
+            },
+            has_filetype: incompatible_features & INCOMPAT_FILETYPE != 0,
+        };
+        if superblock.inodes_per_group == 0 || superblock.inode_size < GOOD_OLD_INODE_SIZE {
+            return Err(FsError::InvalidFilesystem);
+        }
+        Ok(superblock)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RawInode {
+    mode: u16,
+    size: u64,
+    blocks: [u32; BLOCK_POINTERS],
+}
+
+impl RawInode {
+    fn parse(data: &[u8]) -> Self {
+        let mode = u16_at(data, 0);
+        let mut size = u32_at(data, 4) as u64;
+        // The upper half of the size is only used by regular files
+        if mode & MODE_TYPE_MASK == MODE_REGULAR_FILE {
+            size |= (u32_at(data, 108) as u64) << 32;
+        }
+        Self {
+            mode,
+            size,
+            blocks: core::array::from_fn(|index| u32_at(data, 40 + index * 4)),
+        }
+    }
+
+    fn kind(&self) -> Option<InodeKind> {
+        match self.mode & MODE_TYPE_MASK {
+            MODE_REGULAR_FILE => Some(InodeKind::File),
+            MODE_DIRECTORY => Some(InodeKind::Directory),
+            _ => None,
+        }
+    }
+}
+
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    superblock: Superblock,
+    // The first block of the inode table of every group
+    inode_tables: Vec<u32>,
+}
+
+impl Volume {
+    fn open(device: Arc<dyn BlockDevice>) -> Result<Self, FsError> {
+        let mut data = vec![0; SUPERBLOCK_SIZE];
+        read_bytes(&*device, SUPERBLOCK_OFFSET, &mut data)?;
+        let superblock = Superblock::parse(&data)?;
+
+        let groups = superblock
+            .inodes_count
+            .div_ceil(superblock.inodes_per_group) as usize;
+        // The descriptors start in the block after the superblock
+        let mut descriptors = vec![0; groups * GROUP_DESCRIPTOR_SIZE];
+        let table_block = superblock.first_data_block as u64 + 1;
+        read_bytes(
+            &*device,
+            table_block * superblock.block_size as u64,
+            &mut descriptors,
+        )?;
+        let inode_tables = descriptors
+            .chunks(GROUP_DESCRIPTOR_SIZE)
+            .map(|descriptor| u32_at(descriptor, 8))
+            .collect();
+
+        Ok(Self {
+            device,
+            superblock,
+            inode_tables,
+        })
+    }
+
+    fn block_size(&self) -> usize {
+        self.superblock.block_size
+    }
+
+    fn read_block(&self, block: u32, buffer: &mut [u8]) -> Result<(), FsError> {
+        read_bytes(
+            &*self.device,
+            block as u64 * self.block_size() as u64,
+            buffer,
+        )
+    }
+
+    fn read_inode(&self, number: u32) -> Result<RawInode, FsError> {
+        if number == 0 || number > self.superblock.inodes_count {
+            return Err(FsError::InvalidFilesystem);
+        }
+        let group = ((number - 1) / self.superblock.inodes_per_group) as usize;
+        let index = ((number - 1) % self.superblock.inodes_per_group) as u64;
+        let table = *self
+            .inode_tables
+            .get(group)
+            .ok_or(FsError::InvalidFilesystem)?;
+        let mut data = vec![0; self.superblock.inode_size];
+        read_bytes(
+            &*self.device,
+            table as u64 * self.block_size() as u64 + index * self.superblock.inode_size as u64,
+            &mut data,
+        )?;
+        Ok(RawInode::parse(&data))
+    }
+
+    /// Returns the block which holds the given block of the file, 0 means
+    /// that the file has a hole there
+    fn block_of(&self, inode: &RawInode, index: usize) -> Result<u32, FsError> {
+        if index < DIRECT_BLOCKS {
+            return Ok(inode.blocks[index]);
+        }
+        let per_block = self.block_size() / 4;
+        let mut pointers = vec![0; self.block_size()];
+        let mut index = index - DIRECT_BLOCKS;
+        // How many blocks of the file the indirect block of a level spans
+        let mut span = per_block;
+        for level in 0..BLOCK_POINTERS - DIRECT_BLOCKS {
+            if index < span {
+                let mut block = inode.blocks[DIRECT_BLOCKS + level];
+                for depth in (0..=level as u32).rev() {
+                    if block == 0 {
+                        return Ok(0);
+                    }
+                    self.read_block(block, &mut pointers)?;
+                    let slot = index / per_block.pow(depth) % per_block;
+                    block = u32_at(&pointers, slot * 4);
+                }
+                return Ok(block);
+            }
+            index -= span;
+            span *= per_block;
+        }
+        Err(FsError::InvalidFilesystem)
+    }
+
+    fn read_at(
+        &self,
+        inode: &RawInode,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, FsError> {
+        let size = usize::try_from(inode.size).map_err(|_| FsError::InvalidFilesystem)?;
+        let length = buffer.len().min(size.saturating_sub(offset));
+        let block_size = self.block_size();
+        let mut block = vec![0; block_size];
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let offset_in_block = position % block_size;
+            let count = (block_size - offset_in_block).min(length - done);
+            match self.block_of(inode, position / block_size)? {
+                0 => block.fill(0),
+                number => self.read_block(number, &mut block)?,
+            }
+            buffer[done..done + count]
+                .copy_from_slice(&block[offset_in_block..offset_in_block + count]);
+            done += count;
+        }
+        Ok(done)
+    }
+
+    /// Returns the name, inode number and kind of every entry except .
+    /// and .., entries of unsupported kinds are skipped
+    fn entries(&self, directory: &RawInode) -> Result<Vec<(String, u32, InodeKind)>, FsError> {
+        let size = usize::try_from(directory.size).map_err(|_| FsError::InvalidFilesystem)?;
+        let mut data = vec![0; size];
+        let length = self.read_at(directory, 0, &mut data)?;
+        let data = &data[..length];
+
+        let mut entries = Vec::new();
+        let mut position = 0;
+        while position + 8 <= data.len() {
+            let number = u32_at(data, position);
+            let record_length = u16_at(data, position + 4) as usize;
+            let name_length = data[position + 6] as usize;
+            let file_type = data[position + 7];
+            if record_length < 8 || name_length + 8 > record_length {
+                return Err(FsError::InvalidFilesystem);
+            }
+            let name = data
+                .get(position + 8..position + 8 + name_length)
+                .ok_or(FsError::InvalidFilesystem)?;
+            let name = core::str::from_utf8(name).map_err(|_| FsError::InvalidFilesystem)?;
+            position += record_length;
+
+            if number == 0 || name == "." || name == ".." {
+                continue;
+            }
+            let kind = if self.superblock.has_filetype {
+                match file_type {
+                    FILETYPE_REGULAR_FILE => Some(InodeKind::File),
+                    FILETYPE_DIRECTORY => Some(InodeKind::Directory),
+                    _ => None,
+                }
+            } else {
+                self.read_inode(number)?.kind()
+            };
+            if let Some(kind) = kind {
+                entries.push((String::from(name), number, kind));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Reads bytes at any offset of the device, whole blocks are read from
+/// the device
+fn read_bytes(device: &dyn BlockDevice, offset: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+    let block_size = device.block_size();
+    let mut block = vec![0; block_size];
+    let mut done = 0;
+    while done < buffer.len() {
+        let position = offset + done as u64;
+        let index = position / block_size as u64;
+        if index >= device.block_count() {
+            return Err(FsError::InvalidFilesystem);
+        }
+        let offset_in_block = (position % block_size as u64) as usize;
+        let count = (block_size - offset_in_block).min(buffer.len() - done);
+        device.read_block(index, &mut block)?;
+        buffer[done..done + count]
+            .copy_from_slice(&block[offset_in_block..offset_in_block + count]);
+        done += count;
+    }
+    Ok(())
+}
+
+struct Ext2Inode {
+    volume: Arc<Volume>,
+    inode: RawInode,
+    kind: InodeKind,
+}
+
+impl Ext2Inode {
+    fn new(volume: Arc<Volume>, number: u32) -> Result<Arc<Self>, FsError> {
+        let inode = volume.read_inode(number)?;
+        let kind = inode.kind().ok_or(FsError::NotFound)?;
+        Ok(Arc::new(Self {
+            volume,
+            inode,
+            kind,
+        }))
+    }
+}
+
+impl Inode for Ext2Inode {
+    fn kind(&self) -> InodeKind {
+        self.kind
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        if self.kind != InodeKind::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        let (_, number, _) = self
+            .volume
+            .entries(&self.inode)?
+            .into_iter()
+            .find(|(entry, _, _)| entry == name)
+            .ok_or(FsError::NotFound)?;
+        Ok(Ext2Inode::new(self.volume.clone(), number)?)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        if self.kind != InodeKind::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(self
+            .volume
+            .entries(&self.inode)?
+            .into_iter()
+            .map(|(name, _, kind)| DirectoryEntry { name, kind })
+            .collect())
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        if self.kind != InodeKind::File {
+            return Err(FsError::IsADirectory);
+        }
+        self.volume.read_at(&self.inode, offset, buffer)
+    }
+
+    fn size(&self) -> Result<usize, FsError> {
+        match self.kind {
+            InodeKind::Directory => Ok(0),
+            _ => usize::try_from(self.inode.size).map_err(|_| FsError::InvalidFilesystem),
+        }
+    }
+}
+
+pub struct Ext2 {
+    root: Arc<Ext2Inode>,
+}
+
+impl Ext2 {
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Self, FsError> {
+        let volume = Arc::new(Volume::open(device)?);
+        let root = Ext2Inode::new(volume, ROOT_INODE)?;
+        if root.kind != InodeKind::Directory {
+            return Err(FsError::InvalidFilesystem);
+        }
+        Ok(Self { root })
+    }
+}
+
+impl FileSystem for Ext2 {
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec, vec::Vec};
+
+    use crate::{
+        fs::vfs::{read_to_end, FileSystem, FsError, InodeKind},
+        io::ramdisk::RamDisk,
+    };
+
+    use super::{Ext2, FILETYPE_DIRECTORY, FILETYPE_REGULAR_FILE, MAGIC};
+
+    const BLOCK_SIZE: usize = 1024;
+    const INODE_SIZE: usize = 128;
+    const INODE_TABLE: usize = 5;
+    const INODES: usize = 32;
+
+    /// Builds an image with one group: superblock in block 1, the group
+    /// descriptor in block 2 and the inode table from block 5 on
+    struct Image {
+        data: Vec<u8>,
+        next_block: usize,
+    }
+
+    impl Image {
+        fn new() -> Self {
+            let mut data = vec![0; 64 * BLOCK_SIZE];
+            let superblock = &mut data[1024..2048];
+            superblock[0..4].copy_from_slice(&(INODES as u32).to_le_bytes());
+            superblock[4..8].copy_from_slice(&64u32.to_le_bytes());
+            superblock[20..24].copy_from_slice(&1u32.to_le_bytes());
+            superblock[32..36].copy_from_slice(&8192u32.to_le_bytes());
+            superblock[40..44].copy_from_slice(&(INODES as u32).to_le_bytes());
+            superblock[56..58].copy_from_slice(&MAGIC.to_le_bytes());
+            superblock[76..80].copy_from_slice(&1u32.to_le_bytes());
+            superblock[88..90].copy_from_slice(&(INODE_SIZE as u16).to_le_bytes());
+            superblock[96..100].copy_from_slice(&2u32.to_le_bytes());
+            data[2 * BLOCK_SIZE + 8..2 * BLOCK_SIZE + 12]
+                .copy_from_slice(&(INODE_TABLE as u32).to_le_bytes());
+            Self {
+                data,
+                next_block: INODE_TABLE + INODES * INODE_SIZE / BLOCK_SIZE,
+            }
+        }
+
+        fn allocate(&mut self, content: &[u8]) -> u32 {
+            let block = self.next_block;
+            self.next_block += 1;
+            let start = block * BLOCK_SIZE;
+            self.data[start..start + content.len()].copy_from_slice(content);
+            block as u32
+        }
+
+        fn inode(&mut self, number: usize, mode: u16, size: usize, blocks: &[u32]) {
+            let start = INODE_TABLE * BLOCK_SIZE + (number - 1) * INODE_SIZE;
+            let inode = &mut self.data[start..start + INODE_SIZE];
+            inode[0..2].copy_from_slice(&mode.to_le_bytes());
+            inode[4..8].copy_from_slice(&(size as u32).to_le_bytes());
+            for (index, block) in blocks.iter().enumerate() {
+                inode[40 + index * 4..44 + index * 4].copy_from_slice(&block.to_le_bytes());
+            }
+        }
+
+        /// The last entry spans the rest of the block
+        fn directory(&mut self, number: usize, entries: &[(&str, u32, u8)]) {
+            let mut content = Vec::new();
+            for (index, (name, inode, file_type)) in entries.iter().enumerate() {
+                let length = if index + 1 == entries.len() {
+                    BLOCK_SIZE - content.len()
+                } else {
+                    (8 + name.len()).next_multiple_of(4)
+                };
+                content.extend_from_slice(&inode.to_le_bytes());
+                content.extend_from_slice(&(length as u16).to_le_bytes());
+                content.extend_from_slice(&[name.len() as u8, *file_type]);
+                content.extend_from_slice(name.as_bytes());
+                content.resize(content.len() + length - 8 - name.len(), 0);
+            }
+            let block = self.allocate(&content);
+            self.inode(number, 0o040755, BLOCK_SIZE, &[block]);
+        }
+
+        fn file(&mut self, number: usize, content: &[u8]) {
+            let mut blocks: Vec<u32> = content
+                .chunks(BLOCK_SIZE)
+                .map(|chunk| self.allocate(chunk))
+                .collect();
+            if blocks.len() > 12 {
+                let pointers: Vec<u8> = blocks[12..]
+                    .iter()
+                    .flat_map(|block| block.to_le_bytes())
+                    .collect();
+                let indirect = self.allocate(&pointers);
+                blocks.truncate(12);
+                blocks.push(indirect);
+            }
+            self.inode(number, 0o100644, content.len(), &blocks);
+        }
+
+        fn into_filesystem(self) -> Result<Ext2, FsError> {
+            Ext2::new(Arc::new(RamDisk::from_bytes(512, self.data)))
+        }
+    }
+
+    fn sample() -> Ext2 {
+        let mut image = Image::new();
+        image.directory(
+            2,
+            &[
+                (".", 2, FILETYPE_DIRECTORY),
+                ("..", 2, FILETYPE_DIRECTORY),
+                ("hello", 12, FILETYPE_REGULAR_FILE),
+                ("link", 14, 7),
+                ("sub", 13, FILETYPE_DIRECTORY),
+            ],
+        );
+        image.file(12, b"Hello from ext2\n");
+        image.directory(
+            13,
+            &[
+                (".", 13, FILETYPE_DIRECTORY),
+                ("..", 2, FILETYPE_DIRECTORY),
+                ("big", 15, FILETYPE_REGULAR_FILE),
+            ],
+        );
+        let big: Vec<u8> = (0..14 * BLOCK_SIZE + 100).map(|i| (i / 7) as u8).collect();
+        image.file(15, &big);
+        image.into_filesystem().unwrap()
+    }
+
+    #[test_case]
+    fn files_and_directories_are_read() {
+        let root = sample().root();
+        let names: Vec<_> = root
+            .read_dir()
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.kind))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("hello".into(), InodeKind::File),
+                ("sub".into(), InodeKind::Directory)
+            ],
+            "Symbolic links are skipped"
+        );
+
+        let hello = root.lookup("hello").unwrap();
+        assert_eq!(read_to_end(&*hello).unwrap(), b"Hello from ext2\n");
+        let mut buffer = [0; 4];
+        assert_eq!(hello.read_at(6, &mut buffer), Ok(4));
+        assert_eq!(&buffer, b"from");
+
+        assert_eq!(root.lookup("missing").err(), Some(FsError::NotFound));
+        assert_eq!(root.lookup("link").err(), Some(FsError::NotFound));
+        assert_eq!(hello.lookup("x").err(), Some(FsError::NotADirectory));
+        assert_eq!(
+            root.lookup("sub").unwrap().read_at(0, &mut buffer),
+            Err(FsError::IsADirectory)
+        );
+    }
+
+    #[test_case]
+    fn indirect_blocks_are_followed() {
+        let big = sample()
+            .root()
+            .lookup("sub")
+            .unwrap()
+            .lookup("big")
+            .unwrap();
+        let content = read_to_end(&*big).unwrap();
+        assert_eq!(content.len(), 14 * BLOCK_SIZE + 100);
+        assert!(content
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == (i / 7) as u8));
+    }
+
+    #[test_case]
+    fn other_data_is_rejected() {
+        let ramdisk = Arc::new(RamDisk::new(512, 8));
+        assert_eq!(Ext2::new(ramdisk).err(), Some(FsError::InvalidFilesystem));
+
+        let mut image = Image::new();
+        // Extents are an ext4 feature
+        image.data[1024 + 96] |= 0x40;
+        assert_eq!(
+            image.into_filesystem().err(),
+            Some(FsError::InvalidFilesystem)
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Host directories which QEMU shares with virtio-9p are mounted at
+//! /<mount tag>, e.g. `/host/bin/prog` is `bin/prog` of the share with
+//! the tag "host". Inodes only remember their path, every operation walks
+//! from the root of the share. Programs are cached by their path,
+//! therefore a program which changes on the host is only picked up after
+//! a reboot.
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use common::mutex::Mutex;
+
+use crate::{drivers::virtio::p9::P9Device, info, warn};
+
+use super::{
+    p9::{P9Client, P9Error},
+    vfs::{self, DirectoryEntry, FileSystem, FsError, Inode, InodeKind},
+};
+
+const ENOENT: u32 = 2;
+const ENOTDIR: u32 = 20;
+const EISDIR: u32 = 21;
+
+type SharedClient = Arc<Mutex<P9Client<P9Device>>>;
+
+impl From<P9Error> for FsError {
+    fn from(error: P9Error) -> Self {
+        match error {
+            P9Error::Server(ENOENT) => FsError::NotFound,
+            P9Error::Server(ENOTDIR) => FsError::NotADirectory,
+            P9Error::Server(EISDIR) => FsError::IsADirectory,
+            _ => FsError::Io,
+        }
+    }
+}
+
+pub struct HostFs {
+    client: SharedClient,
+}
+
+struct HostInode {
+    client: SharedClient,
+    // Relative to the root of the share
+    path: String,
+    kind: InodeKind,
+}
+
+impl Inode for HostInode {
+    fn kind(&self) -> InodeKind {
+        self.kind
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        if self.kind != InodeKind::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        let path = format!("{}/{name}", self.path);
+        let kind = if self.client.lock().is_directory(&path)? {
+            InodeKind::Directory
+        } else {
+            InodeKind::File
+        };
+        Ok(Arc::new(HostInode {
+            client: self.client.clone(),
+            path,
+            kind,
+        }))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        if self.kind != InodeKind::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        let entries = self.client.lock().read_dir(&self.path)?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| DirectoryEntry {
+                name: entry.name,
+                kind: if entry.is_directory {
+                    InodeKind::Directory
+                } else {
+                    InodeKind::File
+                },
+            })
+            .collect())
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        if self.kind != InodeKind::File {
+            return Err(FsError::IsADirectory);
+        }
+        Ok(self.client.lock().read_at(&self.path, offset, buffer)?)
+    }
+
+    fn size(&self) -> Result<usize, FsError> {
+        if self.kind != InodeKind::File {
+            return Ok(0);
+        }
+        let size = self.client.lock().size(&self.path)?;
+        usize::try_from(size).map_err(|_| FsError::Io)
+    }
+}
+
+impl FileSystem for HostFs {
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(HostInode {
+            client: self.client.clone(),
+            path: String::new(),
+            kind: InodeKind::Directory,
+        })
+    }
+}
+
+pub fn mount(device: P9Device) {
+    let mount_point = format!("/{}", device.mount_tag());
+    let result = P9Client::attach(device)
+        .map_err(FsError::from)
+        .and_then(|client| {
+            vfs::mount(
+                &mount_point,
+                Arc::new(HostFs {
+                    client: Arc::new(Mutex::new(client)),
+                }),
+            )
+        });
+    match result {
+        Ok(()) => info!("Mounted host share at {mount_point}"),
+        Err(error) => {
+            warn!("Cannot mount host share at {mount_point}: {error:?}");
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! The initramfs is a cpio archive which QEMU loads with `-initrd`. Its
+//! location is passed in the chosen node of the device tree. The archive
+//! stays in memory and its files form a read-only filesystem which is
+//! mounted at /. The archive itself is /dev/initrd.
+
+use core::ops::Range;
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use crate::{device_tree, info, io::device::BlockDevice, warn};
+
+use super::{
+    cpio, devfs,
+    vfs::{self, DirectoryEntry, FileSystem, FsError, Inode, InodeKind},
+};
+
+enum Node {
+    File(&'static [u8]),
+    Directory(BTreeMap<String, Arc<Node>>),
+}
+
+impl Node {
+    /// Missing parent directories are created. Names below a file are
+    /// ignored.
+    fn insert(&mut self, components: &[&str], node: Node) {
+        let Node::Directory(entries) = self else {
+            return;
+        };
+        match components {
+            [] => {}
+            [name] => {
+                // A directory might be listed after its content
+                if !matches!(node, Node::Directory(_)) || !entries.contains_key(*name) {
+                    entries.insert(String::from(*name), Arc::new(node));
+                }
+            }
+            [name, rest @ ..] => {
+                let child = entries
+                    .entry(String::from(*name))
+                    .or_insert_with(|| Arc::new(Node::Directory(BTreeMap::new())));
+                Arc::get_mut(child)
+                    .expect("The tree is not shared while it is built")
+                    .insert(rest, node);
+            }
+        }
+    }
+}
+
+impl Inode for Node {
+    fn kind(&self) -> InodeKind {
+        match self {
+            Node::File(_) => InodeKind::File,
+            Node::Directory(_) => InodeKind::Directory,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        let Node::Directory(entries) = self else {
+            return Err(FsError::NotADirectory);
+        };
+        entries
+            .get(name)
+            .map(|node| node.clone() as Arc<dyn Inode>)
+            .ok_or(FsError::NotFound)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        let Node::Directory(entries) = self else {
+            return Err(FsError::NotADirectory);
+        };
+        Ok(entries
+            .iter()
+            .map(|(name, node)| DirectoryEntry {
+                name: name.clone(),
+                kind: node.kind(),
+            })
+            .collect())
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let data = self.static_data().ok_or(FsError::IsADirectory)?;
+        let data = data.get(offset..).unwrap_or_default();
+        let count = data.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+
+    fn static_data(&self) -> Option<&'static [u8]> {
+        match self {
+            Node::File(data) => Some(data),
+            Node::Directory(_) => None,
+        }
+    }
+}
+
+const ARCHIVE_BLOCK_SIZE: usize = 512;
+
+/// The raw archive as read-only block device, the last block is padded
+/// with zeros
+struct ArchiveDevice(&'static [u8]);
+
+impl BlockDevice for ArchiveDevice {
+    fn block_size(&self) -> usize {
+        ARCHIVE_BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.0.len().div_ceil(ARCHIVE_BLOCK_SIZE) as u64
+    }
+
+    fn read_block(&self, index: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        let start = index as usize * ARCHIVE_BLOCK_SIZE;
+        let data = self
+            .0
+            .get(start..)
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .chain(core::iter::repeat(0));
+        buffer
+            .iter_mut()
+            .zip(data)
+            .for_each(|(byte, data)| *byte = data);
+        Ok(())
+    }
+
+    fn write_block(&self, _index: u64, _data: &[u8]) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+}
+
+pub struct Initramfs {
+    root: Arc<Node>,
+    files: usize,
+}
+
+impl Initramfs {
+    pub fn from_archive(archive: &'static [u8]) -> Self {
+        let mut root = Node::Directory(BTreeMap::new());
+        let mut files = 0;
+        for entry in cpio::entries(archive) {
+            let components: Vec<&str> = entry
+                .name
+                .split('/')
+                .filter(|name| !name.is_empty() && *name != ".")
+                .collect();
+            if entry.is_file() {
+                root.insert(&components, Node::File(entry.data));
+                files += 1;
+            } else if entry.is_directory() {
+                root.insert(&components, Node::Directory(BTreeMap::new()));
+            }
+        }
+        Self {
+            root: Arc::new(root),
+            files,
+        }
+    }
+}
+
+impl FileSystem for Initramfs {
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+fn read_address(property: &[u8]) -> Option<usize> {
+    match property.len() {
+        4 => Some(u32::from_be_bytes(property.try_into().ok()?) as usize),
+        8 => Some(u64::from_be_bytes(property.try_into().ok()?) as usize),
+        _ => None,
+    }
+}
+
+/// The memory of the archive must be reserved before the page allocator
+/// is initialized
+pub fn initrd_range() -> Option<Range<*const u8>> {
+    let chosen = device_tree::THE.root_node().find_node("chosen")?;
+    let start = read_address(chosen.get_property("linux,initrd-start")?.buffer())?;
+    let end = read_address(chosen.get_property("linux,initrd-end")?.buffer())?;
+    if start >= end {
+        warn!("Invalid initrd range {start:#x}-{end:#x}");
+        return None;
+    }
+    Some(start as *const u8..end as *const u8)
+}
+
+/// Without an archive the root is an empty directory, other filesystems
+/// are mounted below it nevertheless
+pub fn init() {
+    let initramfs = match initrd_range() {
+        Some(range) => {
+            // SAFETY: The range is reserved forever and QEMU put the archive there
+            let archive: &'static [u8] = unsafe {
+                core::slice::from_raw_parts(range.start, range.end as usize - range.start as usize)
+            };
+            let initramfs = Initramfs::from_archive(archive);
+            devfs::register_block_device("initrd", Arc::new(ArchiveDevice(archive)));
+            info!(
+                "Initramfs at {:p} with {} files",
+                range.start, initramfs.files
+            );
+            initramfs
+        }
+        None => {
+            info!("No initramfs provided");
+            Initramfs::from_archive(&[])
+        }
+    };
+    if let Err(error) = vfs::mount("/", Arc::new(initramfs)) {
+        warn!("Cannot mount the initramfs: {error:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        fs::{
+            cpio::tests::archive,
+            vfs::{read_to_end, FileSystem, FsError, InodeKind},
+        },
+        io::device::BlockDevice,
+    };
+
+    use super::{ArchiveDevice, Initramfs};
+
+    #[test_case]
+    fn archive_forms_a_tree() {
+        let archive = archive(&[
+            ("bin/hello", b"hello program"),
+            ("./etc/motd", b"welcome"),
+            ("bin/sub/nested", b"nested"),
+        ]);
+        let initramfs = Initramfs::from_archive(archive.leak());
+        let root = initramfs.root();
+
+        let names: alloc::vec::Vec<_> = root
+            .read_dir()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["bin", "etc"]);
+
+        let motd = root.lookup("etc").unwrap().lookup("motd").unwrap();
+        assert_eq!(motd.kind(), InodeKind::File);
+        assert_eq!(read_to_end(&*motd).unwrap(), b"welcome");
+        assert_eq!(motd.static_data(), Some(&b"welcome"[..]));
+
+        let mut buffer = [0; 4];
+        assert_eq!(motd.read_at(5, &mut buffer), Ok(2));
+        assert_eq!(&buffer[..2], b"me");
+        assert_eq!(motd.read_at(100, &mut buffer), Ok(0));
+
+        let bin = root.lookup("bin").unwrap();
+        assert_eq!(bin.kind(), InodeKind::Directory);
+        assert_eq!(bin.lookup("sub").unwrap().kind(), InodeKind::Directory);
+        assert_eq!(bin.lookup("missing").err(), Some(FsError::NotFound));
+        assert_eq!(motd.lookup("x").err(), Some(FsError::NotADirectory));
+        assert_eq!(
+            bin.read_at(0, &mut buffer).err(),
+            Some(FsError::IsADirectory)
+        );
+    }
+
+    #[test_case]
+    fn last_block_of_the_archive_is_padded() {
+        let device = ArchiveDevice(&[1; 600]);
+        assert_eq!(device.block_count(), 2);
+
+        let mut block = [0xff; 512];
+        device.read_block(1, &mut block).unwrap();
+        assert!(block[..88].iter().all(|byte| *byte == 1));
+        assert!(block[88..].iter().all(|byte| *byte == 0));
+        assert_eq!(device.write_block(0, &block), Err(FsError::ReadOnly));
+    }
+}
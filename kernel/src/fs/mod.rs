@@ -0,0 +1,52 @@
+use alloc::sync::Arc;
+
+use crate::{info, initcall, processes::programs, warn};
+
+use self::vfs::{FileSystem, FsError};
+
+pub mod block_cache;
+pub mod cpio;
+pub mod devfs;
+pub mod ext2;
+#[cfg(feature = "hostfs")]
+pub mod hostfs;
+pub mod initramfs;
+#[cfg(feature = "hostfs")]
+pub mod p9;
+pub mod procfs;
+pub mod tmpfs;
+pub mod vfs;
+
+initcall!(Fs, init);
+
+/// Mounts the root filesystem and the pseudo filesystems. Programs are
+/// searched in the filesystems before the embedded ones.
+pub fn init() {
+    initramfs::init();
+    if let Err(error) = vfs::mount("/proc", Arc::new(procfs::ProcFs::new())) {
+        warn!("Cannot mount /proc: {error:?}");
+    }
+    if let Err(error) = vfs::mount("/tmp", Arc::new(tmpfs::TmpFs::new())) {
+        warn!("Cannot mount /tmp: {error:?}");
+    }
+    devfs::init();
+    if let Err(error) = vfs::mount("/dev", Arc::new(devfs::DevFs)) {
+        warn!("Cannot mount /dev: {error:?}");
+    }
+    programs::register_source(Arc::new(vfs::FilesystemPrograms));
+}
+
+/// Mounts the filesystem of the given type on the block device at
+/// `source`. Only ext2 is supported so far.
+pub fn mount(filesystem: &str, source: &str, target: &str) -> Result<(), FsError> {
+    let device = vfs::resolve(source)?
+        .block_device()
+        .ok_or(FsError::NotABlockDevice)?;
+    let filesystem: Arc<dyn FileSystem> = match filesystem {
+        "ext2" => Arc::new(ext2::Ext2::new(device)?),
+        _ => return Err(FsError::InvalidFilesystem),
+    };
+    vfs::mount(target, filesystem)?;
+    info!("Mounted {source} at {target}");
+    Ok(())
+}
@@ -0,0 +1,598 @@
+//! A 9P2000.L client, QEMU shares host directories with it. Only what is
+//! needed to read files and directories is implemented. Every message
+//! starts with size[4] type[1] tag[2]. Numbers are little endian and
+//! strings are prefixed with their length[2].
+
+use alloc::{string::String, vec::Vec};
+use common::consumable_buffer::ConsumableBuffer;
+
+use crate::debug;
+
+pub trait P9Transport: Send {
+    /// Sends the request and returns the response which is at most
+    /// `response_capacity` bytes long
+    fn request(&mut self, request: Vec<u8>, response_capacity: usize) -> Vec<u8>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum P9Error {
+    // The errno which the server returned
+    Server(u32),
+    InvalidResponse,
+    UnsupportedVersion,
+}
+
+const VERSION: &str = "9P2000.L";
+const MESSAGE_SIZE: u32 = 64 * 1024;
+// Header of read and write messages, the rest of a message is payload
+const IO_HEADER_SIZE: u32 = 24;
+const HEADER_SIZE: usize = 7;
+// Walks are limited to this many names per message
+const MAX_WALK_ELEMENTS: usize = 16;
+
+const NO_TAG: u16 = !0;
+const NO_FID: u32 = !0;
+// Requests are sent one after another, therefore one tag suffices
+const TAG: u16 = 0;
+const ROOT_FID: u32 = 0;
+
+const ENOENT: u32 = 2;
+const O_RDONLY: u32 = 0;
+const QID_TYPE_DIRECTORY: u8 = 0x80;
+const QID_SIZE: usize = 13;
+const GETATTR_SIZE: u64 = 0x200;
+// valid[8] qid[13] mode[4] uid[4] gid[4] nlink[8] rdev[8] come before
+// the size in a getattr response
+const GETATTR_SIZE_OFFSET: usize = 8 + QID_SIZE + 4 + 4 + 4 + 8 + 8;
+
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TGETATTR: u8 = 24;
+const TREADDIR: u8 = 40;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TCLUNK: u8 = 120;
+
+/// Every R-message has the type of its T-message plus one
+const fn response_type(request_type: u8) -> u8 {
+    request_type + 1
+}
+
+struct Message {
+    message_type: u8,
+    buffer: Vec<u8>,
+}
+
+impl Message {
+    fn new(message_type: u8, tag: u16) -> Self {
+        let mut buffer = Vec::new();
+        // The size is filled in by finish
+        buffer.extend_from_slice(&[0; 4]);
+        buffer.push(message_type);
+        buffer.extend_from_slice(&tag.to_le_bytes());
+        Self {
+            message_type,
+            buffer,
+        }
+    }
+
+    fn u16(mut self, value: u16) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn string(self, value: &str) -> Self {
+        let mut message = self.u16(value.len() as u16);
+        message.buffer.extend_from_slice(value.as_bytes());
+        message
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let size = self.buffer.len() as u32;
+        self.buffer[..4].copy_from_slice(&size.to_le_bytes());
+        self.buffer
+    }
+}
+
+struct Response(Vec<u8>);
+
+impl Response {
+    fn parse(data: Vec<u8>, expected_type: u8) -> Result<Self, P9Error> {
+        let mut header = ConsumableBuffer::new(&data);
+        let size = header
+            .consume_sized_type::<u32>()
+            .ok_or(P9Error::InvalidResponse)?;
+        let message_type = header
+            .consume_sized_type::<u8>()
+            .ok_or(P9Error::InvalidResponse)?;
+        if size as usize != data.len() || data.len() < HEADER_SIZE {
+            return Err(P9Error::InvalidResponse);
+        }
+        let response = Self(data);
+        if message_type == RLERROR {
+            let errno = response
+                .body()
+                .consume_sized_type::<u32>()
+                .ok_or(P9Error::InvalidResponse)?;
+            return Err(P9Error::Server(errno));
+        }
+        if message_type != expected_type {
+            return Err(P9Error::InvalidResponse);
+        }
+        Ok(response)
+    }
+
+    fn body(&self) -> ConsumableBuffer<'_> {
+        ConsumableBuffer::new(&self.0[HEADER_SIZE..])
+    }
+}
+
+fn consume_string<'a>(buffer: &mut ConsumableBuffer<'a>) -> Option<&'a str> {
+    let length = buffer.consume_sized_type::<u16>()?;
+    core::str::from_utf8(buffer.consume_slice(length as usize)?).ok()
+}
+
+fn consume_qid_type(buffer: &mut ConsumableBuffer) -> Option<u8> {
+    let qid = buffer.consume_slice(QID_SIZE)?;
+    // type[1] version[4] path[8]
+    Some(qid[0])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub is_directory: bool,
+}
+
+pub struct P9Client<T: P9Transport> {
+    transport: T,
+    message_size: u32,
+    next_fid: u32,
+}
+
+impl<T: P9Transport> P9Client<T> {
+    /// Negotiates the protocol and attaches to the root of the share
+    pub fn attach(transport: T) -> Result<Self, P9Error> {
+        let mut client = Self {
+            transport,
+            message_size: MESSAGE_SIZE,
+            next_fid: ROOT_FID + 1,
+        };
+
+        let response = client.rpc(
+            Message::new(TVERSION, NO_TAG)
+                .u32(MESSAGE_SIZE)
+                .string(VERSION),
+        )?;
+        let mut body = response.body();
+        let message_size = body
+            .consume_sized_type::<u32>()
+            .ok_or(P9Error::InvalidResponse)?;
+        if consume_string(&mut body) != Some(VERSION) {
+            return Err(P9Error::UnsupportedVersion);
+        }
+        if message_size <= IO_HEADER_SIZE {
+            return Err(P9Error::InvalidResponse);
+        }
+        client.message_size = message_size.min(MESSAGE_SIZE);
+
+        client.rpc(
+            Message::new(TATTACH, TAG)
+                .u32(ROOT_FID)
+                .u32(NO_FID)
+                .string("root")
+                .string("")
+                .u32(0),
+        )?;
+
+        Ok(client)
+    }
+
+    fn rpc(&mut self, message: Message) -> Result<Response, P9Error> {
+        let expected_type = response_type(message.message_type);
+        let data = self
+            .transport
+            .request(message.finish(), self.message_size as usize);
+        Response::parse(data, expected_type)
+    }
+
+    fn allocate_fid(&mut self) -> u32 {
+        let fid = self.next_fid;
+        self.next_fid = match self.next_fid.wrapping_add(1) {
+            NO_FID => ROOT_FID + 1,
+            next => next,
+        };
+        fid
+    }
+
+    /// Returns a new fid for the path which is relative to the root and
+    /// the qid type of the last name
+    fn walk(&mut self, path: &str) -> Result<(u32, Option<u8>), P9Error> {
+        let names: Vec<&str> = path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .collect();
+        let fid = self.allocate_fid();
+
+        // A walk without names clones the fid
+        let mut chunks: Vec<&[&str]> = names.chunks(MAX_WALK_ELEMENTS).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+
+        let mut source = ROOT_FID;
+        let mut qid_type = None;
+        for chunk in chunks {
+            let result = self.walk_names(source, fid, chunk);
+            if result.is_err() && source == fid {
+                // The fid still refers to the last directory
+                self.clunk(fid);
+            }
+            qid_type = result?.or(qid_type);
+            source = fid;
+        }
+        Ok((fid, qid_type))
+    }
+
+    fn walk_names(
+        &mut self,
+        fid: u32,
+        new_fid: u32,
+        names: &[&str],
+    ) -> Result<Option<u8>, P9Error> {
+        let mut message = Message::new(TWALK, TAG)
+            .u32(fid)
+            .u32(new_fid)
+            .u16(names.len() as u16);
+        for name in names {
+            message = message.string(name);
+        }
+        let response = self.rpc(message)?;
+        let mut body = response.body();
+        let walked = body
+            .consume_sized_type::<u16>()
+            .ok_or(P9Error::InvalidResponse)?;
+        // The new fid is only valid if all names were walked
+        if walked as usize != names.len() {
+            return Err(P9Error::Server(ENOENT));
+        }
+        let mut qid_type = None;
+        for _ in 0..walked {
+            qid_type = Some(consume_qid_type(&mut body).ok_or(P9Error::InvalidResponse)?);
+        }
+        Ok(qid_type)
+    }
+
+    fn clunk(&mut self, fid: u32) {
+        if let Err(error) = self.rpc(Message::new(TCLUNK, TAG).u32(fid)) {
+            debug!("Clunking fid {fid} failed: {error:?}");
+        }
+    }
+
+    /// Opens the path and clunks the fid after `operation`
+    fn with_open<R>(
+        &mut self,
+        path: &str,
+        operation: impl FnOnce(&mut Self, u32) -> Result<R, P9Error>,
+    ) -> Result<R, P9Error> {
+        let (fid, _) = self.walk(path)?;
+        let result = self
+            .rpc(Message::new(TLOPEN, TAG).u32(fid).u32(O_RDONLY))
+            .and_then(|_| operation(self, fid));
+        self.clunk(fid);
+        result
+    }
+
+    fn payload_size(&self) -> u32 {
+        self.message_size - IO_HEADER_SIZE
+    }
+
+    /// The root of the share is a directory as well
+    pub fn is_directory(&mut self, path: &str) -> Result<bool, P9Error> {
+        let (fid, qid_type) = self.walk(path)?;
+        self.clunk(fid);
+        Ok(qid_type.is_none_or(|qid_type| qid_type & QID_TYPE_DIRECTORY != 0))
+    }
+
+    pub fn size(&mut self, path: &str) -> Result<u64, P9Error> {
+        let (fid, _) = self.walk(path)?;
+        let result = self
+            .rpc(Message::new(TGETATTR, TAG).u32(fid).u64(GETATTR_SIZE))
+            .and_then(|response| {
+                let mut body = response.body();
+                body.consume_slice(GETATTR_SIZE_OFFSET)
+                    .and_then(|_| body.consume_sized_type::<u64>())
+                    .ok_or(P9Error::InvalidResponse)
+            });
+        self.clunk(fid);
+        result
+    }
+
+    /// Appends at most `count` bytes to `data` and returns how many
+    fn read_chunk(
+        &mut self,
+        fid: u32,
+        offset: u64,
+        count: u32,
+        data: &mut Vec<u8>,
+    ) -> Result<usize, P9Error> {
+        let count = count.min(self.payload_size());
+        let response = self.rpc(Message::new(TREAD, TAG).u32(fid).u64(offset).u32(count))?;
+        let mut body = response.body();
+        let read = body
+            .consume_sized_type::<u32>()
+            .ok_or(P9Error::InvalidResponse)?;
+        if read > count {
+            return Err(P9Error::InvalidResponse);
+        }
+        data.extend_from_slice(
+            body.consume_slice(read as usize)
+                .ok_or(P9Error::InvalidResponse)?,
+        );
+        Ok(read as usize)
+    }
+
+    /// Reads at most one message worth of data, 0 marks the end of the file
+    pub fn read_at(
+        &mut self,
+        path: &str,
+        offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, P9Error> {
+        self.with_open(path, |client, fid| {
+            let mut data = Vec::new();
+            let count = u32::try_from(buffer.len()).unwrap_or(u32::MAX);
+            let read = client.read_chunk(fid, offset as u64, count, &mut data)?;
+            buffer[..read].copy_from_slice(&data);
+            Ok(read)
+        })
+    }
+
+    /// The entries . and .. are skipped
+    pub fn read_dir(&mut self, path: &str) -> Result<Vec<DirectoryEntry>, P9Error> {
+        self.with_open(path, |client, fid| {
+            let mut entries = Vec::new();
+            let mut offset = 0;
+            loop {
+                let response = client.rpc(
+                    Message::new(TREADDIR, TAG)
+                        .u32(fid)
+                        .u64(offset)
+                        .u32(client.payload_size()),
+                )?;
+                let mut body = response.body();
+                let count = body
+                    .consume_sized_type::<u32>()
+                    .ok_or(P9Error::InvalidResponse)?;
+                if count == 0 {
+                    return Ok(entries);
+                }
+                let mut data = ConsumableBuffer::new(
+                    body.consume_slice(count as usize)
+                        .ok_or(P9Error::InvalidResponse)?,
+                );
+                while !data.empty() {
+                    // qid[13] offset[8] type[1] name[s]
+                    let qid_type = consume_qid_type(&mut data).ok_or(P9Error::InvalidResponse)?;
+                    offset = data
+                        .consume_sized_type::<u64>()
+                        .ok_or(P9Error::InvalidResponse)?;
+                    data.consume_sized_type::<u8>()
+                        .ok_or(P9Error::InvalidResponse)?;
+                    let name = consume_string(&mut data).ok_or(P9Error::InvalidResponse)?;
+                    if name != "." && name != ".." {
+                        entries.push(DirectoryEntry {
+                            name: name.into(),
+                            is_directory: qid_type & QID_TYPE_DIRECTORY != 0,
+                        });
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::BTreeMap, string::String, vec::Vec};
+    use common::consumable_buffer::ConsumableBuffer;
+
+    use super::*;
+
+    /// Serves a flat directory. Fids are only tracked as far as the
+    /// tests need it.
+    struct FakeServer {
+        files: BTreeMap<&'static str, Vec<u8>>,
+        fids: BTreeMap<u32, String>,
+    }
+
+    impl FakeServer {
+        fn new(files: &[(&'static str, &[u8])]) -> Self {
+            Self {
+                files: files
+                    .iter()
+                    .map(|(name, data)| (*name, data.to_vec()))
+                    .collect(),
+                fids: BTreeMap::new(),
+            }
+        }
+
+        fn handle(&mut self, message_type: u8, body: &mut ConsumableBuffer) -> Message {
+            let response = Message::new(response_type(message_type), TAG);
+            match message_type {
+                TVERSION => {
+                    let message_size = body.consume_sized_type::<u32>().unwrap();
+                    let version = consume_string(body).unwrap();
+                    response.u32(message_size.min(64)).string(version)
+                }
+                TATTACH => {
+                    self.fids
+                        .insert(body.consume_sized_type().unwrap(), "".into());
+                    response.u8(QID_TYPE_DIRECTORY).u32(0).u64(0)
+                }
+                TWALK => {
+                    let fid: u32 = body.consume_sized_type().unwrap();
+                    let new_fid: u32 = body.consume_sized_type().unwrap();
+                    let count: u16 = body.consume_sized_type().unwrap();
+                    let mut path = self.fids[&fid].clone();
+                    for _ in 0..count {
+                        let name = consume_string(body).unwrap();
+                        if !self.files.contains_key(name) {
+                            return Message::new(RLERROR, TAG).u32(ENOENT);
+                        }
+                        path = name.into();
+                    }
+                    self.fids.insert(new_fid, path);
+                    let mut response = response.u16(count);
+                    for _ in 0..count {
+                        response = response.u8(0).u32(0).u64(0);
+                    }
+                    response
+                }
+                TLOPEN => response.u8(0).u32(0).u64(0).u32(0),
+                TGETATTR => {
+                    let fid: u32 = body.consume_sized_type().unwrap();
+                    let size = self
+                        .files
+                        .get(self.fids[&fid].as_str())
+                        .map_or(0, |data| data.len());
+                    let mut response = response;
+                    response.buffer.resize(HEADER_SIZE + GETATTR_SIZE_OFFSET, 0);
+                    response.u64(size as u64)
+                }
+                TREAD => {
+                    let fid: u32 = body.consume_sized_type().unwrap();
+                    let offset: u64 = body.consume_sized_type().unwrap();
+                    let count: u32 = body.consume_sized_type().unwrap();
+                    let data = &self.files[self.fids[&fid].as_str()];
+                    let start = (offset as usize).min(data.len());
+                    let end = (start + count as usize).min(data.len());
+                    let mut response = response.u32((end - start) as u32);
+                    response.buffer.extend_from_slice(&data[start..end]);
+                    response
+                }
+                TREADDIR => {
+                    let fid: u32 = body.consume_sized_type().unwrap();
+                    let offset: u64 = body.consume_sized_type().unwrap();
+                    assert_eq!(self.fids[&fid], "", "Only the root is a directory");
+                    // One entry per message to test the offsets
+                    let Some(name) = [".", ".."]
+                        .into_iter()
+                        .chain(self.files.keys().copied())
+                        .nth(offset as usize)
+                    else {
+                        return response.u32(0);
+                    };
+                    let entry = Message::new(0, 0)
+                        .u8(0)
+                        .u32(0)
+                        .u64(0)
+                        .u64(offset + 1)
+                        .u8(0)
+                        .string(name);
+                    let entry = &entry.buffer[HEADER_SIZE..];
+                    let mut response = response.u32(entry.len() as u32);
+                    response.buffer.extend_from_slice(entry);
+                    response
+                }
+                TCLUNK => {
+                    assert!(self
+                        .fids
+                        .remove(&body.consume_sized_type().unwrap())
+                        .is_some());
+                    response
+                }
+                _ => panic!("Unexpected message type {message_type}"),
+            }
+        }
+    }
+
+    impl Message {
+        fn u8(mut self, value: u8) -> Self {
+            self.buffer.push(value);
+            self
+        }
+    }
+
+    impl P9Transport for FakeServer {
+        fn request(&mut self, request: Vec<u8>, response_capacity: usize) -> Vec<u8> {
+            let mut buffer = ConsumableBuffer::new(&request);
+            let size: u32 = buffer.consume_sized_type().unwrap();
+            assert_eq!(size as usize, request.len());
+            let message_type: u8 = buffer.consume_sized_type().unwrap();
+            buffer.consume_sized_type::<u16>().unwrap();
+            let response = self.handle(message_type, &mut buffer).finish();
+            assert!(response.len() <= response_capacity);
+            response
+        }
+    }
+
+    #[test_case]
+    fn messages_are_encoded_little_endian() {
+        let message = Message::new(TWALK, 0x0102)
+            .u32(0x03040506)
+            .string("ab")
+            .finish();
+        assert_eq!(
+            message,
+            [13, 0, 0, 0, TWALK, 0x02, 0x01, 0x06, 0x05, 0x04, 0x03, 2, 0, b'a', b'b']
+        );
+    }
+
+    #[test_case]
+    fn files_are_read_in_chunks_of_the_message_size() {
+        let content: Vec<u8> = (0..200).collect();
+        let server = FakeServer::new(&[("big", &content), ("small", b"hello")]);
+        let mut client = P9Client::attach(server).expect("Attach must work");
+
+        let mut buffer = [0; 256];
+        assert_eq!(client.read_at("small", 0, &mut buffer), Ok(5));
+        assert_eq!(&buffer[..5], b"hello");
+        // The message size is 64 bytes
+        assert_eq!(client.read_at("/./big", 0, &mut buffer), Ok(40));
+        assert_eq!(&buffer[..40], &content[..40]);
+        assert_eq!(client.read_at("big", 200, &mut buffer), Ok(0));
+        assert_eq!(
+            client.read_at("missing", 0, &mut buffer),
+            Err(P9Error::Server(ENOENT))
+        );
+
+        assert_eq!(client.read_at("big", 196, &mut buffer), Ok(4));
+        assert_eq!(&buffer[..4], &content[196..]);
+        assert_eq!(client.read_at("small", 1, &mut buffer[..2]), Ok(2));
+        assert_eq!(&buffer[..2], b"el");
+        assert_eq!(client.is_directory("small"), Ok(false));
+        assert_eq!(client.is_directory(""), Ok(true));
+        assert_eq!(client.size("big"), Ok(200));
+        assert_eq!(client.size("missing"), Err(P9Error::Server(ENOENT)));
+        // Only the root fid is left
+        assert_eq!(client.transport.fids.len(), 1);
+    }
+
+    #[test_case]
+    fn directories_are_read_without_dot_entries() {
+        let server = FakeServer::new(&[("a", b""), ("b", b"")]);
+        let mut client = P9Client::attach(server).expect("Attach must work");
+
+        let names: Vec<String> = client
+            .read_dir("")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(client.transport.fids.len(), 1);
+    }
+}
@@ -0,0 +1,361 @@
+//! Exposes kernel state as text files under /proc. The content of a file
+//! is generated on every read. Processes have a directory named after
+//! their pid, /proc/self is the one of the reading process.
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use crate::{
+    cpu::Cpu,
+    debugging::{boot_timing, profiler},
+    interrupts::plic,
+    klibc::counters,
+    memory::{self, PAGE_SIZE},
+    processes::{
+        kernel_stack,
+        process::{Pid, Process},
+        process_table,
+    },
+};
+
+#[cfg(feature = "net")]
+use crate::net::{received_per_queue_pair, ARP_CACHE, OPEN_UDP_SOCKETS, RECEIVE_STATISTICS};
+
+use super::vfs::{DirectoryEntry, FileSystem, FsError, Inode, InodeKind};
+
+const SELF: &str = "self";
+
+type Generator = Box<dyn Fn() -> Result<String, FsError> + Send + Sync>;
+
+struct GeneratedFile(Generator);
+
+impl GeneratedFile {
+    fn new(generator: impl Fn() -> Result<String, FsError> + Send + Sync + 'static) -> Arc<Self> {
+        Arc::new(Self(Box::new(generator)))
+    }
+}
+
+impl Inode for GeneratedFile {
+    fn kind(&self) -> InodeKind {
+        InodeKind::File
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let content = (self.0)()?;
+        let content = content.as_bytes().get(offset..).unwrap_or_default();
+        let count = content.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&content[..count]);
+        Ok(count)
+    }
+}
+
+struct Directory(BTreeMap<&'static str, Arc<dyn Inode>>);
+
+impl Inode for Directory {
+    fn kind(&self) -> InodeKind {
+        InodeKind::Directory
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        self.0.get(name).cloned().ok_or(FsError::NotFound)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        Ok(self
+            .0
+            .iter()
+            .map(|(name, inode)| DirectoryEntry {
+                name: name.to_string(),
+                kind: inode.kind(),
+            })
+            .collect())
+    }
+
+    fn read_at(&self, _offset: usize, _buffer: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::IsADirectory)
+    }
+}
+
+/// The process might have exited since its directory was looked up
+fn with_process(pid: Pid, f: impl FnOnce(&Process) -> String) -> Result<String, FsError> {
+    let process = process_table::THE
+        .read()
+        .get_process(pid)
+        .cloned()
+        .ok_or(FsError::NotFound)?;
+    let process = process.lock();
+    Ok(f(&process))
+}
+
+fn status(process: &Process) -> String {
+    let credentials = process.credentials();
+    format!(
+        "Name:\t{}\nPid:\t{}\nPPid:\t{}\nState:\t{}\nUid:\t{}\nGid:\t{}\nCpuTime:\t{} ms\nMappedPages:\t{}\n",
+        process.get_name(),
+        process.get_pid(),
+        // 0 for init and kernel threads
+        process_table::THE
+            .read()
+            .parent_of(process.get_pid())
+            .unwrap_or(0),
+        process.state_name(),
+        credentials.uid,
+        credentials.gid,
+        process.cpu_time_milliseconds(),
+        process.mapped_pages()
+    )
+}
+
+fn io(process: &Process) -> String {
+    let io = process.io_statistics();
+    format!(
+        "Syscalls:\t{}\nBytesRead:\t{}\nBytesWritten:\t{}\nBytesSent:\t{}\nBytesReceived:\t{}\n",
+        io.syscalls, io.bytes_read, io.bytes_written, io.bytes_sent, io.bytes_received
+    )
+}
+
+/// The kernel is mapped into every process, only the userspace part is
+/// shown
+fn maps(process: &Process) -> String {
+    process
+        .get_page_table()
+        .walk()
+        .into_iter()
+        .filter(|mapping| mapping.user_mode_accessible)
+        .fold(String::new(), |mut content, mapping| {
+            let _ = writeln!(content, "{mapping}");
+            content
+        })
+}
+
+fn descriptors(process: &Process) -> String {
+    process
+        .describe_descriptors()
+        .into_iter()
+        .fold(String::new(), |mut content, descriptor| {
+            let _ = writeln!(content, "{descriptor}");
+            content
+        })
+}
+
+fn process_directory(pid: Pid) -> Arc<dyn Inode> {
+    Arc::new(Directory(BTreeMap::from([
+        (
+            "status",
+            GeneratedFile::new(move || with_process(pid, status)) as Arc<dyn Inode>,
+        ),
+        ("io", GeneratedFile::new(move || with_process(pid, io))),
+        ("maps", GeneratedFile::new(move || with_process(pid, maps))),
+        (
+            "fd",
+            GeneratedFile::new(move || with_process(pid, descriptors)),
+        ),
+    ])))
+}
+
+fn meminfo() -> Result<String, FsError> {
+    let total = memory::total_heap_pages() * PAGE_SIZE / 1024;
+    let used = memory::used_heap_pages() * PAGE_SIZE / 1024;
+    // Part of the used memory
+    let dma = memory::dma::allocated_bytes() / 1024;
+    Ok(format!(
+        "MemTotal:\t{total} kB\nMemUsed:\t{used} kB\nMemFree:\t{} kB\nDmaUsed:\t{dma} kB\nDmaBuffers:\t{}\nCachedKernelStacks:\t{}\n",
+        total - used,
+        memory::dma::live_buffers(),
+        kernel_stack::cached()
+    ))
+}
+
+fn syscalls() -> Result<String, FsError> {
+    let mut content = String::new();
+    for syscall in common::syscalls::SYSCALLS {
+        let _ = writeln!(content, "{syscall}");
+    }
+    Ok(content)
+}
+
+fn interrupts() -> Result<String, FsError> {
+    let mut content = String::new();
+    for (interrupt_id, count) in plic::interrupt_counts() {
+        let _ = writeln!(content, "{interrupt_id:>4}: {count}");
+    }
+    Ok(content)
+}
+
+#[cfg(feature = "net")]
+fn arp() -> Result<String, FsError> {
+    let mut content = String::from("IP address\tHW address\n");
+    for (ip, mac) in ARP_CACHE.read().iter() {
+        let _ = writeln!(content, "{ip}\t{mac}");
+    }
+    Ok(content)
+}
+
+#[cfg(feature = "net")]
+fn queues() -> Result<String, FsError> {
+    let mut content = String::from("Pair\tReceived\n");
+    for (pair, received) in received_per_queue_pair().iter().enumerate() {
+        let _ = writeln!(content, "{pair}\t{received}");
+    }
+    Ok(content)
+}
+
+// Queued is in bytes, the others count datagrams
+#[cfg(feature = "net")]
+fn udp() -> Result<String, FsError> {
+    let mut content = String::from("Port\tQueued\tReceived\tDropped\tSent\n");
+    for (port, statistics) in OPEN_UDP_SOCKETS.lock().statistics() {
+        let _ = writeln!(
+            content,
+            "{port}\t{}\t{}\t{}\t{}",
+            statistics.queued, statistics.received, statistics.dropped, statistics.sent
+        );
+    }
+    Ok(content)
+}
+
+#[cfg(feature = "net")]
+fn stats() -> Result<String, FsError> {
+    Ok(format!(
+        "Received:\t{}\nChecksumValidated:\t{}\nInvalidIpChecksums:\t{}\nInvalidUdpChecksums:\t{}\nInvalidTcpChecksums:\t{}\n",
+        RECEIVE_STATISTICS.received.get(),
+        RECEIVE_STATISTICS.checksum_validated.get(),
+        RECEIVE_STATISTICS.invalid_ip_checksums.get(),
+        RECEIVE_STATISTICS.invalid_udp_checksums.get(),
+        RECEIVE_STATISTICS.invalid_tcp_checksums.get(),
+    ))
+}
+
+#[cfg(feature = "net")]
+fn net_directory() -> Directory {
+    Directory(BTreeMap::from([
+        ("arp", GeneratedFile::new(arp) as Arc<dyn Inode>),
+        ("queues", GeneratedFile::new(queues)),
+        ("udp", GeneratedFile::new(udp)),
+        ("stats", GeneratedFile::new(stats)),
+    ]))
+}
+
+/// Holds everything except the process directories
+struct Root(Directory);
+
+impl Inode for Root {
+    fn kind(&self) -> InodeKind {
+        InodeKind::Directory
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        if name == SELF {
+            return Ok(process_directory(Cpu::with_current_process(|p| {
+                p.get_pid()
+            })));
+        }
+        match name.parse::<Pid>() {
+            Ok(pid) if process_table::THE.read().get_process(pid).is_some() => {
+                Ok(process_directory(pid))
+            }
+            Ok(_) => Err(FsError::NotFound),
+            Err(_) => self.0.lookup(name),
+        }
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        let mut entries = self.0.read_dir()?;
+        entries.push(DirectoryEntry {
+            name: SELF.into(),
+            kind: InodeKind::Directory,
+        });
+        entries.extend(process_table::THE.read().pids().map(|pid| DirectoryEntry {
+            name: pid.to_string(),
+            kind: InodeKind::Directory,
+        }));
+        Ok(entries)
+    }
+
+    fn read_at(&self, _offset: usize, _buffer: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::IsADirectory)
+    }
+}
+
+pub struct ProcFs {
+    root: Arc<Root>,
+}
+
+impl ProcFs {
+    pub fn new() -> Self {
+        let root = Root(Directory(BTreeMap::from([
+            ("meminfo", GeneratedFile::new(meminfo) as Arc<dyn Inode>),
+            ("interrupts", GeneratedFile::new(interrupts)),
+            ("syscalls", GeneratedFile::new(syscalls)),
+            ("counters", GeneratedFile::new(|| Ok(counters::listing()))),
+            ("boot", GeneratedFile::new(|| Ok(boot_timing::report()))),
+            ("profile", GeneratedFile::new(|| Ok(profiler::report()))),
+            (
+                "page_owner",
+                GeneratedFile::new(|| Ok(memory::page_owner_report())),
+            ),
+            #[cfg(feature = "net")]
+            ("net", Arc::new(net_directory())),
+        ])));
+        Self {
+            root: Arc::new(root),
+        }
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config,
+        fs::vfs::{read_to_end, FileSystem, FsError, Inode, InodeKind},
+    };
+
+    use super::{GeneratedFile, ProcFs};
+
+    #[test_case]
+    fn generated_files_are_read_at_offsets() {
+        let file = GeneratedFile::new(|| Ok("generated".into()));
+        let mut buffer = [0; 4];
+        assert_eq!(file.read_at(0, &mut buffer), Ok(4));
+        assert_eq!(&buffer, b"gene");
+        assert_eq!(file.read_at(7, &mut buffer), Ok(2));
+        assert_eq!(&buffer[..2], b"ed");
+        assert_eq!(file.read_at(20, &mut buffer), Ok(0));
+    }
+
+    #[test_case]
+    fn static_entries_are_found() {
+        let root = ProcFs::new().root();
+        let meminfo = read_to_end(&*root.lookup("meminfo").unwrap()).unwrap();
+        assert!(meminfo.starts_with(b"MemTotal:"));
+        let syscalls = read_to_end(&*root.lookup("syscalls").unwrap()).unwrap();
+        assert!(syscalls.starts_with(b"  0 sys_write(s: &'a str) -> Result<(), ValidationError>\n"));
+
+        if config::NET {
+            let net = root.lookup("net").unwrap();
+            assert_eq!(net.kind(), InodeKind::Directory);
+            let arp = read_to_end(&*net.lookup("arp").unwrap()).unwrap();
+            assert!(arp.starts_with(b"IP address"));
+            let udp = read_to_end(&*net.lookup("udp").unwrap()).unwrap();
+            assert!(udp.starts_with(b"Port\tQueued"));
+            let stats = read_to_end(&*net.lookup("stats").unwrap()).unwrap();
+            assert!(stats.starts_with(b"Received:"));
+        } else {
+            assert_eq!(root.lookup("net").err(), Some(FsError::NotFound));
+        }
+        assert_eq!(root.lookup("missing").err(), Some(FsError::NotFound));
+    }
+}
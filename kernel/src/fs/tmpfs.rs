@@ -0,0 +1,170 @@
+//! A writable filesystem which lives in kernel memory, mounted at /tmp.
+//! Its content is gone after a reboot.
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use common::mutex::Mutex;
+
+use super::vfs::{DirectoryEntry, FileSystem, FsError, Inode, InodeKind};
+
+type Entries = Mutex<BTreeMap<String, Arc<dyn Inode>>>;
+
+enum Node {
+    File(Mutex<Vec<u8>>),
+    Directory(Entries),
+}
+
+impl Node {
+    fn new(kind: InodeKind) -> Result<Self, FsError> {
+        match kind {
+            InodeKind::File => Ok(Node::File(Mutex::new(Vec::new()))),
+            InodeKind::Directory => Ok(Node::Directory(Mutex::new(BTreeMap::new()))),
+            InodeKind::CharacterDevice | InodeKind::BlockDevice => Err(FsError::InvalidPath),
+        }
+    }
+
+    fn entries(&self) -> Result<&Entries, FsError> {
+        match self {
+            Node::Directory(entries) => Ok(entries),
+            Node::File(_) => Err(FsError::NotADirectory),
+        }
+    }
+}
+
+impl Inode for Node {
+    fn kind(&self) -> InodeKind {
+        match self {
+            Node::File(_) => InodeKind::File,
+            Node::Directory(_) => InodeKind::Directory,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        self.entries()?
+            .lock()
+            .get(name)
+            .cloned()
+            .ok_or(FsError::NotFound)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        Ok(self
+            .entries()?
+            .lock()
+            .iter()
+            .map(|(name, inode)| DirectoryEntry {
+                name: name.clone(),
+                kind: inode.kind(),
+            })
+            .collect())
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let Node::File(data) = self else {
+            return Err(FsError::IsADirectory);
+        };
+        let data = data.lock();
+        let data = data.get(offset..).unwrap_or_default();
+        let count = data.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+
+    /// Writing behind the end fills the gap with zeros
+    fn write_at(&self, offset: usize, data: &[u8]) -> Result<usize, FsError> {
+        let Node::File(content) = self else {
+            return Err(FsError::IsADirectory);
+        };
+        let mut content = content.lock();
+        let end = offset + data.len();
+        if content.len() < end {
+            content.resize(end, 0);
+        }
+        content[offset..end].copy_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn size(&self) -> Result<usize, FsError> {
+        match self {
+            Node::File(data) => Ok(data.lock().len()),
+            Node::Directory(_) => Ok(0),
+        }
+    }
+
+    fn create(&self, name: &str, kind: InodeKind) -> Result<Arc<dyn Inode>, FsError> {
+        let inode: Arc<dyn Inode> = Arc::new(Node::new(kind)?);
+        self.link(name, inode.clone())?;
+        Ok(inode)
+    }
+
+    fn link(&self, name: &str, inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        let mut entries = self.entries()?.lock();
+        if entries.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+        entries.insert(name.to_string(), inode);
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        self.entries()?.lock().remove(name).ok_or(FsError::NotFound)
+    }
+}
+
+pub struct TmpFs {
+    root: Arc<Node>,
+}
+
+impl TmpFs {
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(Node::Directory(Mutex::new(BTreeMap::new()))),
+        }
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fs::vfs::{read_to_end, FileSystem, FsError, InodeKind};
+
+    use super::TmpFs;
+
+    #[test_case]
+    fn files_are_written_and_read() {
+        let root = TmpFs::new().root();
+        let file = root.create("file", InodeKind::File).unwrap();
+        assert_eq!(file.write_at(0, b"hello"), Ok(5));
+        assert_eq!(file.write_at(7, b"world"), Ok(5));
+        assert_eq!(read_to_end(&*file).unwrap(), b"hello\0\0world");
+        assert_eq!(file.size(), Ok(12));
+
+        assert_eq!(
+            root.create("file", InodeKind::Directory).err(),
+            Some(FsError::AlreadyExists)
+        );
+        assert_eq!(
+            file.create("x", InodeKind::File).err(),
+            Some(FsError::NotADirectory)
+        );
+    }
+
+    #[test_case]
+    fn entries_are_removed() {
+        let root = TmpFs::new().root();
+        root.create("directory", InodeKind::Directory).unwrap();
+        assert_eq!(root.read_dir().unwrap().len(), 1);
+        assert!(root.remove("directory").is_ok());
+        assert_eq!(root.lookup("directory").err(), Some(FsError::NotFound));
+        assert_eq!(root.remove("directory").err(), Some(FsError::NotFound));
+    }
+}
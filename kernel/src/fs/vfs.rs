@@ -0,0 +1,600 @@
+//! The virtual filesystem puts all mounted filesystems into one
+//! namespace. A path belongs to the filesystem with the longest mount
+//! point which is a prefix of it, the rest of the path is looked up name
+//! by name starting at the root of that filesystem. Paths are absolute
+//! and `..` is resolved before the mount point is chosen. Relative paths
+//! of processes are made absolute with their working directory first.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use common::{errors::SysFileError, mutex::Mutex};
+
+use crate::{
+    io::device::BlockDevice,
+    memory::page_cache,
+    processes::programs::{Program, ProgramSource},
+};
+
+const PROGRAM_DIRECTORY: &str = "/bin";
+// Files are read in chunks of this size if their data is not in memory
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+static MOUNTS: Mutex<MountTable> = Mutex::new(MountTable::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    // Paths must be absolute
+    InvalidPath,
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    AlreadyMounted,
+    ReadOnly,
+    NoSpace,
+    // The data on the device is not a supported filesystem
+    InvalidFilesystem,
+    NotABlockDevice,
+    AlreadyExists,
+    DirectoryNotEmpty,
+    // Renames can't move inodes to another filesystem
+    CrossDevice,
+    // Mount points can't be removed or renamed
+    Busy,
+    Io,
+}
+
+impl From<FsError> for SysFileError {
+    fn from(error: FsError) -> Self {
+        match error {
+            FsError::InvalidPath => SysFileError::InvalidPath,
+            FsError::NotFound => SysFileError::NotFound,
+            FsError::NotADirectory => SysFileError::NotADirectory,
+            FsError::IsADirectory => SysFileError::IsADirectory,
+            FsError::ReadOnly => SysFileError::ReadOnly,
+            FsError::NoSpace => SysFileError::NoSpace,
+            FsError::AlreadyMounted => SysFileError::AlreadyMounted,
+            FsError::InvalidFilesystem => SysFileError::InvalidFilesystem,
+            FsError::NotABlockDevice => SysFileError::NotABlockDevice,
+            FsError::AlreadyExists => SysFileError::AlreadyExists,
+            FsError::DirectoryNotEmpty => SysFileError::DirectoryNotEmpty,
+            FsError::CrossDevice => SysFileError::CrossDevice,
+            FsError::Busy => SysFileError::Busy,
+            FsError::Io => SysFileError::IoError,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InodeKind {
+    File,
+    Directory,
+    CharacterDevice,
+    BlockDevice,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub kind: InodeKind,
+}
+
+/// A file or directory of a filesystem. The defaults are those of a
+/// regular file.
+pub trait Inode: Send + Sync {
+    fn kind(&self) -> InodeKind;
+
+    fn lookup(&self, _name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        Err(FsError::NotADirectory)
+    }
+
+    /// The entries . and .. are not part of the result
+    fn read_dir(&self) -> Result<Vec<DirectoryEntry>, FsError> {
+        Err(FsError::NotADirectory)
+    }
+
+    /// Returns 0 at the end of the file
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize, FsError>;
+
+    fn write_at(&self, _offset: usize, _data: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// In bytes, directories have size 0. Files without a better way are
+    /// read until their end.
+    fn size(&self) -> Result<usize, FsError> {
+        if let Some(data) = self.static_data() {
+            return Ok(data.len());
+        }
+        if self.kind() == InodeKind::Directory {
+            return Ok(0);
+        }
+        let mut chunk = alloc::vec![0; READ_CHUNK_SIZE];
+        let mut size = 0;
+        loop {
+            match self.read_at(size, &mut chunk)? {
+                0 => return Ok(size),
+                count => size += count,
+            }
+        }
+    }
+
+    /// Creates an empty file or directory in this directory
+    fn create(&self, _name: &str, _kind: InodeKind) -> Result<Arc<dyn Inode>, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Adds an inode of the same filesystem under another name, used to
+    /// rename it
+    fn link(&self, _name: &str, _inode: Arc<dyn Inode>) -> Result<(), FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Removes the entry without looking at the inode, directories are
+    /// checked for emptiness by the VFS
+    fn remove(&self, _name: &str) -> Result<Arc<dyn Inode>, FsError> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Files which live as long as the kernel can be used without a copy
+    fn static_data(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    /// The device behind a block device node, filesystems are mounted
+    /// from it
+    fn block_device(&self) -> Option<Arc<dyn BlockDevice>> {
+        None
+    }
+}
+
+pub trait FileSystem: Send + Sync {
+    fn root(&self) -> Arc<dyn Inode>;
+}
+
+struct Mount {
+    components: Vec<String>,
+    filesystem: Arc<dyn FileSystem>,
+}
+
+struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    const fn new() -> Self {
+        Self { mounts: Vec::new() }
+    }
+
+    fn mount(&mut self, path: &str, filesystem: Arc<dyn FileSystem>) -> Result<(), FsError> {
+        let components: Vec<String> = components(path)?.into_iter().map(String::from).collect();
+        if self
+            .mounts
+            .iter()
+            .any(|mount| mount.components == components)
+        {
+            return Err(FsError::AlreadyMounted);
+        }
+        self.mounts.push(Mount {
+            components,
+            filesystem,
+        });
+        Ok(())
+    }
+
+    /// Returns the filesystem and how many components its mount point has
+    fn find(&self, components: &[&str]) -> Option<(Arc<dyn FileSystem>, usize)> {
+        self.mounts
+            .iter()
+            .filter(|mount| {
+                mount.components.len() <= components.len()
+                    && mount
+                        .components
+                        .iter()
+                        .zip(components)
+                        .all(|(mounted, name)| mounted == name)
+            })
+            .max_by_key(|mount| mount.components.len())
+            .map(|mount| (mount.filesystem.clone(), mount.components.len()))
+    }
+
+    fn is_mount_point(&self, components: &[&str]) -> bool {
+        self.mounts
+            .iter()
+            .any(|mount| mount.components.iter().eq(components))
+    }
+
+    /// Mount points directly below the directory, they might not exist
+    /// in the filesystem of the directory
+    fn mount_points_in(&self, components: &[&str]) -> Vec<String> {
+        self.mounts
+            .iter()
+            .filter_map(|mount| {
+                let (name, parent) = mount.components.split_last()?;
+                (parent.len() == components.len()
+                    && parent.iter().zip(components).all(|(a, b)| a == b))
+                .then(|| name.clone())
+            })
+            .collect()
+    }
+}
+
+/// Splits an absolute path into its names with . and .. resolved
+fn components(path: &str) -> Result<Vec<&str>, FsError> {
+    let relative = path.strip_prefix('/').ok_or(FsError::InvalidPath)?;
+    let mut components = Vec::new();
+    for name in relative.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+    Ok(components)
+}
+
+/// Relative paths are appended to the directory
+pub fn absolute_path(directory: &str, path: &str) -> String {
+    if path.starts_with('/') {
+        path.into()
+    } else {
+        format!("{directory}/{path}")
+    }
+}
+
+/// Returns the absolute path with . and .. resolved
+pub fn normalize(path: &str) -> Result<String, FsError> {
+    Ok(format!("/{}", components(path)?.join("/")))
+}
+
+/// An open file or device. Reads and writes are serialized by the offset.
+pub struct OpenFile {
+    inode: Arc<dyn Inode>,
+    // Normalized
+    path: String,
+    offset: Mutex<usize>,
+}
+
+pub type SharedOpenFile = Arc<OpenFile>;
+
+impl OpenFile {
+    /// The path must be normalized
+    pub fn new(inode: Arc<dyn Inode>, path: String) -> Self {
+        Self {
+            inode,
+            path,
+            offset: Mutex::new(0),
+        }
+    }
+
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let mut offset = self.offset.lock();
+        let count = self.inode.read_at(*offset, buffer)?;
+        *offset += count;
+        Ok(count)
+    }
+
+    pub fn write(&self, data: &[u8]) -> Result<usize, FsError> {
+        let mut offset = self.offset.lock();
+        let count = self.inode.write_at(*offset, data)?;
+        *offset += count;
+        page_cache::invalidate_file(&self.path);
+        Ok(count)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn inode(&self) -> &Arc<dyn Inode> {
+        &self.inode
+    }
+}
+
+pub fn mount(path: &str, filesystem: Arc<dyn FileSystem>) -> Result<(), FsError> {
+    MOUNTS.lock().mount(path, filesystem)
+}
+
+fn resolve_in(mounts: &Mutex<MountTable>, path: &str) -> Result<Arc<dyn Inode>, FsError> {
+    let components = components(path)?;
+    // Lookups might take some time, don't hold the lock meanwhile
+    let (filesystem, depth) = mounts.lock().find(&components).ok_or(FsError::NotFound)?;
+    components[depth..]
+        .iter()
+        .try_fold(filesystem.root(), |inode, name| inode.lookup(name))
+}
+
+/// Splits the path into the directory which holds the entry and the name
+/// of the entry. Mount points and the root can't be changed.
+fn parent_in<'a>(
+    mounts: &Mutex<MountTable>,
+    path: &'a str,
+) -> Result<(Arc<dyn Inode>, &'a str), FsError> {
+    let components = components(path)?;
+    let (name, parent) = components.split_last().ok_or(FsError::Busy)?;
+    if mounts.lock().is_mount_point(&components) {
+        return Err(FsError::Busy);
+    }
+    let parent = resolve_in(mounts, &format!("/{}", parent.join("/")))?;
+    Ok((parent, name))
+}
+
+fn create_in(
+    mounts: &Mutex<MountTable>,
+    path: &str,
+    kind: InodeKind,
+) -> Result<Arc<dyn Inode>, FsError> {
+    let (parent, name) = parent_in(mounts, path)?;
+    match parent.lookup(name) {
+        Ok(_) => Err(FsError::AlreadyExists),
+        Err(FsError::NotFound) => parent.create(name, kind),
+        Err(error) => Err(error),
+    }
+}
+
+fn unlink_in(mounts: &Mutex<MountTable>, path: &str) -> Result<(), FsError> {
+    let (parent, name) = parent_in(mounts, path)?;
+    let inode = parent.lookup(name)?;
+    if inode.kind() == InodeKind::Directory && !inode.read_dir()?.is_empty() {
+        return Err(FsError::DirectoryNotEmpty);
+    }
+    parent.remove(name)?;
+    Ok(())
+}
+
+/// The target must not exist. Directories can't be moved below
+/// themselves.
+fn rename_in(mounts: &Mutex<MountTable>, from: &str, to: &str) -> Result<(), FsError> {
+    let (from_components, to_components) = (components(from)?, components(to)?);
+    if to_components.starts_with(&from_components) {
+        return Err(FsError::InvalidPath);
+    }
+    let filesystem_of = |components: &[&str]| {
+        mounts
+            .lock()
+            .find(components)
+            .map(|(filesystem, _)| Arc::as_ptr(&filesystem) as *const ())
+    };
+    if filesystem_of(&from_components) != filesystem_of(&to_components) {
+        return Err(FsError::CrossDevice);
+    }
+    let (from_parent, from_name) = parent_in(mounts, from)?;
+    let (to_parent, to_name) = parent_in(mounts, to)?;
+    let inode = from_parent.lookup(from_name)?;
+    match to_parent.lookup(to_name) {
+        Ok(_) => return Err(FsError::AlreadyExists),
+        Err(FsError::NotFound) => {}
+        Err(error) => return Err(error),
+    }
+    to_parent.link(to_name, inode)?;
+    from_parent.remove(from_name)?;
+    Ok(())
+}
+
+fn read_dir_in(mounts: &Mutex<MountTable>, path: &str) -> Result<Vec<DirectoryEntry>, FsError> {
+    let mut entries = resolve_in(mounts, path)?.read_dir()?;
+    for name in mounts.lock().mount_points_in(&components(path)?) {
+        if !entries.iter().any(|entry| entry.name == name) {
+            entries.push(DirectoryEntry {
+                name,
+                kind: InodeKind::Directory,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+pub fn resolve(path: &str) -> Result<Arc<dyn Inode>, FsError> {
+    resolve_in(&MOUNTS, path)
+}
+
+pub fn open(path: &str) -> Result<OpenFile, FsError> {
+    open_inode(path, resolve(path)?)
+}
+
+fn open_inode(path: &str, inode: Arc<dyn Inode>) -> Result<OpenFile, FsError> {
+    if inode.kind() == InodeKind::Directory {
+        return Err(FsError::IsADirectory);
+    }
+    Ok(OpenFile::new(inode, normalize(path)?))
+}
+
+/// Creates an empty file and opens it
+pub fn create_file(path: &str) -> Result<OpenFile, FsError> {
+    open_inode(path, create(path, InodeKind::File)?)
+}
+
+/// Mount points are listed as directories of their parent
+pub fn read_dir(path: &str) -> Result<Vec<DirectoryEntry>, FsError> {
+    read_dir_in(&MOUNTS, path)
+}
+
+pub fn create(path: &str, kind: InodeKind) -> Result<Arc<dyn Inode>, FsError> {
+    create_in(&MOUNTS, path, kind)
+}
+
+/// Removes a file or an empty directory
+pub fn unlink(path: &str) -> Result<(), FsError> {
+    unlink_in(&MOUNTS, path)?;
+    page_cache::invalidate_file(&normalize(path)?);
+    Ok(())
+}
+
+pub fn rename(from: &str, to: &str) -> Result<(), FsError> {
+    rename_in(&MOUNTS, from, to)?;
+    page_cache::invalidate_file(&normalize(from)?);
+    Ok(())
+}
+
+pub fn read_to_end(inode: &dyn Inode) -> Result<Vec<u8>, FsError> {
+    if let Some(data) = inode.static_data() {
+        return Ok(data.to_vec());
+    }
+    let mut data = Vec::new();
+    let mut chunk = alloc::vec![0; READ_CHUNK_SIZE];
+    loop {
+        let count = inode.read_at(data.len(), &mut chunk)?;
+        if count == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(&chunk[..count]);
+    }
+}
+
+/// Programs are found by their path or by their name if they are in /bin
+pub struct FilesystemPrograms;
+
+impl ProgramSource for FilesystemPrograms {
+    fn load(&self, name: &str) -> Option<Program> {
+        let path = if name.starts_with('/') {
+            name.to_string()
+        } else {
+            format!("{PROGRAM_DIRECTORY}/{name}")
+        };
+        let inode = resolve(&path).ok()?;
+        if inode.kind() != InodeKind::File {
+            return None;
+        }
+        match inode.static_data() {
+            Some(data) => Some(Program::from_static(data)),
+            None => read_to_end(&*inode)
+                .ok()
+                .map(|data| Program::from_bytes(&data)),
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        read_dir(PROGRAM_DIRECTORY)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| entry.kind == InodeKind::File)
+            .map(|entry| entry.name)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, sync::Arc, vec::Vec};
+    use common::mutex::Mutex;
+
+    use crate::fs::{cpio::tests::archive, initramfs::Initramfs, tmpfs::TmpFs};
+
+    use super::{
+        absolute_path, components, create_in, normalize, read_dir_in, read_to_end, rename_in,
+        resolve_in, unlink_in, FsError, InodeKind, MountTable,
+    };
+
+    fn filesystem(files: &[(&str, &[u8])]) -> Arc<Initramfs> {
+        Arc::new(Initramfs::from_archive(archive(files).leak()))
+    }
+
+    fn read(mounts: &Mutex<MountTable>, path: &str) -> Result<Vec<u8>, FsError> {
+        read_to_end(&*resolve_in(mounts, path)?)
+    }
+
+    fn names(mounts: &Mutex<MountTable>, path: &str) -> Vec<String> {
+        read_dir_in(mounts, path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect()
+    }
+
+    #[test_case]
+    fn paths_are_normalized() {
+        assert_eq!(components("/"), Ok(Vec::new()));
+        assert_eq!(components("//a/./b/"), Ok(alloc::vec!["a", "b"]));
+        assert_eq!(components("/a/../../b/.."), Ok(Vec::new()));
+        assert_eq!(components("a/b"), Err(FsError::InvalidPath));
+        assert_eq!(components(""), Err(FsError::InvalidPath));
+    }
+
+    #[test_case]
+    fn relative_paths_start_at_the_directory() {
+        assert_eq!(absolute_path("/home", "/etc/motd"), "/etc/motd");
+        assert_eq!(
+            normalize(&absolute_path("/home/user", "../other/./file")),
+            Ok("/home/other/file".into())
+        );
+        assert_eq!(normalize(&absolute_path("/", "")), Ok("/".into()));
+        assert_eq!(normalize(&absolute_path("/", "..")), Ok("/".into()));
+    }
+
+    #[test_case]
+    fn longest_mount_point_wins() {
+        let mounts = Mutex::new(MountTable::new());
+        assert_eq!(
+            resolve_in(&mounts, "/").err(),
+            Some(FsError::NotFound),
+            "Nothing is mounted yet"
+        );
+        {
+            let mut table = mounts.lock();
+            table
+                .mount("/", filesystem(&[("bin/a", b"root"), ("mnt/b", b"hidden")]))
+                .unwrap();
+            table
+                .mount("/mnt", filesystem(&[("bin/b", b"mounted")]))
+                .unwrap();
+            table
+                .mount("/host", filesystem(&[("bin/c", b"host")]))
+                .unwrap();
+            assert_eq!(
+                table.mount("/mnt/", filesystem(&[])).err(),
+                Some(FsError::AlreadyMounted)
+            );
+        }
+
+        assert_eq!(read(&mounts, "/bin/a").unwrap(), b"root");
+        assert_eq!(read(&mounts, "/mnt/bin/b").unwrap(), b"mounted");
+        assert_eq!(read(&mounts, "/mnt/b").err(), Some(FsError::NotFound));
+        assert_eq!(read(&mounts, "/host/../mnt/./bin/b").unwrap(), b"mounted");
+        assert_eq!(read(&mounts, "/mntx").err(), Some(FsError::NotFound));
+
+        // /mnt exists in the root filesystem, /host doesn't
+        assert_eq!(names(&mounts, "/"), ["bin", "host", "mnt"]);
+        assert_eq!(names(&mounts, "/mnt"), ["bin"]);
+        assert_eq!(
+            read_dir_in(&mounts, "/bin/a").err(),
+            Some(FsError::NotADirectory)
+        );
+    }
+
+    #[test_case]
+    fn entries_are_created_removed_and_renamed() {
+        let mounts = Mutex::new(MountTable::new());
+        {
+            let mut table = mounts.lock();
+            table.mount("/", Arc::new(TmpFs::new())).unwrap();
+            table.mount("/ro", filesystem(&[("file", b"x")])).unwrap();
+        }
+        create_in(&mounts, "/a", InodeKind::Directory).unwrap();
+        create_in(&mounts, "/a/file", InodeKind::File).unwrap();
+        assert_eq!(
+            create_in(&mounts, "/a/file", InodeKind::File).err(),
+            Some(FsError::AlreadyExists)
+        );
+        assert_eq!(unlink_in(&mounts, "/a"), Err(FsError::DirectoryNotEmpty));
+
+        assert_eq!(rename_in(&mounts, "/a", "/a/b"), Err(FsError::InvalidPath));
+        rename_in(&mounts, "/a", "/b").unwrap();
+        assert_eq!(names(&mounts, "/b"), ["file"]);
+        assert_eq!(
+            rename_in(&mounts, "/b/file", "/ro/file"),
+            Err(FsError::CrossDevice)
+        );
+
+        assert_eq!(unlink_in(&mounts, "/ro"), Err(FsError::Busy));
+        assert_eq!(unlink_in(&mounts, "/ro/file"), Err(FsError::ReadOnly));
+        unlink_in(&mounts, "/b/file").unwrap();
+        unlink_in(&mounts, "/b").unwrap();
+        assert_eq!(names(&mounts, "/"), ["ro"]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Subsystems register their initialization with `initcall!` instead of
+//! being called from kernel_init. The registrations are collected in the
+//! initcalls section (see qemu.ld) and kernel_init runs them level by
+//! level. Within a level the order is unspecified, a function which
+//! depends on another one must use a later level.
+
+use core::mem::size_of;
+
+use crate::{debug, memory::linker_information::LinkerInformation};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The heap, the device tree and the command line are available
+    Core,
+    /// Timers and backtraces work
+    Postcore,
+    /// The platform devices are probed
+    Fs,
+    /// The process table exists, e.g. for kernel threads
+    Late,
+}
+
+pub struct Initcall {
+    pub name: &'static str,
+    pub level: Level,
+    pub function: fn(),
+}
+
+/// Registers a function which kernel_init calls at the given level,
+/// e.g. `initcall!(Core, init);`
+#[macro_export]
+macro_rules! initcall {
+    ($level:ident, $function:path) => {
+        const _: () = {
+            #[used]
+            #[link_section = ".initcall"]
+            static INITCALL: $crate::initcall::Initcall = $crate::initcall::Initcall {
+                name: concat!(module_path!(), "::", stringify!($function)),
+                level: $crate::initcall::Level::$level,
+                function: $function,
+            };
+        };
+    };
+}
+
+fn initcalls() -> &'static [Initcall] {
+    let start = LinkerInformation::__start_initcalls() as *const Initcall;
+    let len = LinkerInformation::initcalls_size() / size_of::<Initcall>();
+    // SAFETY: The section only contains the statics of initcall! which
+    // all have the same size and alignment
+    unsafe { core::slice::from_raw_parts(start, len) }
+}
+
+/// Calls every function which is registered for the level
+pub fn run(level: Level) {
+    for initcall in initcalls().iter().filter(|i| i.level == level) {
+        debug!("Calling {}", initcall.name);
+        (initcall.function)();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{initcalls, Level};
+
+    // There are no sections under miri
+    #[cfg(not(miri))]
+    #[test_case]
+    fn registered_functions_are_found() {
+        assert!(initcalls()
+            .iter()
+            .any(|i| i.level == Level::Late && i.name == "kernel::processes::workqueue::init"));
+    }
+}
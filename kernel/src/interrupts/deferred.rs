@@ -0,0 +1,55 @@
+//! Deferred work of interrupt handlers. A handler only does what can't
+//! wait, e.g. draining a hardware fifo, and queues the rest. Queuing
+//! raises a software interrupt on the current hart which is taken right
+//! after the handler returned. Pending external interrupts have a higher
+//! priority and are taken first, therefore the time between an
+//! interrupt and its handler stays short even if a lot of work is queued.
+//! The work itself still runs with interrupts disabled, lengthy work can
+//! let higher priority interrupts in, see [`super::nesting`].
+
+use alloc::{boxed::Box, collections::VecDeque};
+
+use crate::{cpu::Cpu, per_cpu::per_cpu};
+
+pub type DeferredWork = Box<dyn FnOnce() + Send>;
+
+per_cpu!(static PENDING: VecDeque<DeferredWork> = VecDeque::new());
+
+/// Runs `work` on the current hart after the current interrupt handler
+pub fn defer(work: impl FnOnce() + Send + 'static) {
+    PENDING.with(|pending| pending.push_back(Box::new(work)));
+    Cpu::raise_software_interrupt();
+}
+
+/// Runs the queued work in order. Work which is queued meanwhile runs
+/// as well.
+pub fn run_pending() {
+    while let Some(work) = PENDING.with(|pending| pending.pop_front()) {
+        work();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{sync::Arc, vec::Vec};
+    use common::mutex::Mutex;
+
+    use super::{defer, run_pending};
+
+    #[test_case]
+    fn work_runs_in_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for index in 0..3 {
+            let order = order.clone();
+            defer(move || order.lock().push(index));
+        }
+        let nested = order.clone();
+        defer(move || {
+            let order = nested.clone();
+            defer(move || order.lock().push(4));
+            nested.lock().push(3);
+        });
+        run_pending();
+        assert_eq!(*order.lock(), [0, 1, 2, 3, 4]);
+    }
+}
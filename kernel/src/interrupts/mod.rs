@@ -1,3 +1,6 @@
+pub mod deferred;
+pub mod nesting;
 pub mod plic;
 pub mod trap;
-mod trap_cause;
+pub mod trap_cause;
+pub mod userspace;
@@ -0,0 +1,107 @@
+//! Lengthy trap handlers, e.g. processing received packets, let
+//! interrupts of a higher priority in such that the timer stays
+//! punctual. Priorities are ordered software < external < timer.
+//!
+//! A nested trap is handled on the stack of the handler it interrupted
+//! and only handles the interrupt itself. It never switches processes,
+//! an expired time slice is handled once the outer handler is done.
+//! Everything a nested handler touches must therefore be locked with
+//! interrupts disabled.
+
+use crate::{cpu::Cpu, klibc::counters::Counter, per_cpu::per_cpu};
+
+const SIE_SSIE: usize = 1;
+const SIE_STIE: usize = 5;
+const SIE_SEIE: usize = 9;
+const SSTATUS_SIE: usize = 1;
+const SSTATUS_SPIE: usize = 5;
+const SSTATUS_SPP: usize = 8;
+
+static NESTED_INTERRUPTS: Counter = Counter::new("interrupts.nested");
+
+per_cpu!(static PREEMPTION_REQUESTED: bool = false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Software,
+    External,
+    Timer,
+}
+
+impl Priority {
+    fn sie_bit(self) -> usize {
+        match self {
+            Priority::Software => 1 << SIE_SSIE,
+            Priority::External => 1 << SIE_SEIE,
+            Priority::Timer => 1 << SIE_STIE,
+        }
+    }
+
+    /// The sie bits of all interrupts with a lower or the same priority
+    fn masked_sie_bits(self) -> usize {
+        [Priority::Software, Priority::External, Priority::Timer]
+            .into_iter()
+            .filter(|priority| *priority <= self)
+            .fold(0, |bits, priority| bits | priority.sie_bit())
+    }
+}
+
+/// Runs `f` with interrupts of a higher priority than `priority`
+/// enabled. Must only be called by trap handlers, interrupts are
+/// disabled again when `f` returns.
+pub fn allow_higher_than<R>(priority: Priority, f: impl FnOnce() -> R) -> R {
+    assert!(
+        Cpu::is_on_trap_stack(),
+        "Only trap handlers can let interrupts nest"
+    );
+    // Nested traps overwrite the trap csrs of the handler
+    let sepc = Cpu::read_sepc();
+    let previous_mode = Cpu::read_sstatus() & ((1 << SSTATUS_SPP) | (1 << SSTATUS_SPIE));
+
+    let masked = Cpu::read_sie() & priority.masked_sie_bits();
+    Cpu::csrc_sie(masked);
+    Cpu::set_interrupt_nesting(Cpu::interrupt_nesting() + 1);
+    Cpu::csrs_sstatus(1 << SSTATUS_SIE);
+
+    let result = f();
+
+    Cpu::csrc_sstatus(1 << SSTATUS_SIE);
+    Cpu::set_interrupt_nesting(Cpu::interrupt_nesting() - 1);
+    Cpu::csrs_sie(masked);
+
+    Cpu::write_sepc(sepc);
+    Cpu::csrc_sstatus((1 << SSTATUS_SPP) | (1 << SSTATUS_SPIE));
+    Cpu::csrs_sstatus(previous_mode);
+    result
+}
+
+pub fn count_nested_interrupt() {
+    NESTED_INTERRUPTS.increment();
+}
+
+/// The time slice expired during a nested timer interrupt
+pub fn request_preemption() {
+    PREEMPTION_REQUESTED.with(|requested| *requested = true);
+}
+
+pub fn take_preemption_request() -> bool {
+    PREEMPTION_REQUESTED.with(core::mem::take)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Priority, SIE_SEIE, SIE_SSIE, SIE_STIE};
+
+    #[test_case]
+    fn only_higher_priorities_stay_enabled() {
+        assert_eq!(Priority::Software.masked_sie_bits(), 1 << SIE_SSIE);
+        assert_eq!(
+            Priority::External.masked_sie_bits(),
+            (1 << SIE_SSIE) | (1 << SIE_SEIE)
+        );
+        assert_eq!(
+            Priority::Timer.masked_sie_bits(),
+            (1 << SIE_SSIE) | (1 << SIE_SEIE) | (1 << SIE_STIE)
+        );
+    }
+}
@@ -1,93 +1,316 @@
-use common::{mutex::Mutex, runtime_initialized::RuntimeInitializedData};
+use alloc::{collections::BTreeMap, vec::Vec};
+use common::{mutex::SpinLockIrqSave, runtime_initialized::RuntimeInitializedData};
 
-use crate::{info, klibc::MMIO};
+use crate::{
+    device_tree::tree::{self, DeviceTreeNode},
+    drivers::platform::{DeviceTreeDriver, ProbeError},
+    info,
+    klibc::{counters::CounterGroup, MMIO},
+    warn,
+};
 
-pub const PLIC_BASE: usize = 0x0c00_0000;
-pub const PLIC_SIZE: usize = 0x1000_0000;
+pub const DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "plic",
+    compatible: &["sifive,plic-1.0.0", "riscv,plic0"],
+    needs_mapping: true,
+    probe,
+};
+
+static NUMBER_OF_SOURCES: RuntimeInitializedData<u32> = RuntimeInitializedData::new();
+
+fn probe(node: &DeviceTreeNode) -> Result<(), ProbeError> {
+    let reg = node.first_reg().ok_or(ProbeError::MissingProperty("reg"))?;
+    let number_of_sources = node
+        .property("riscv,ndev")
+        .and_then(|property| property.u32())
+        .ok_or(ProbeError::MissingProperty("riscv,ndev"))?;
+    info!("PLIC with {number_of_sources} interrupt sources");
+    NUMBER_OF_SOURCES.initialize(number_of_sources);
+    let mut plic = PLIC.lock();
+    plic.base = reg.address;
+    plic.contexts = supervisor_contexts(tree::root(), node);
+    Ok(())
+}
+
+// Cause of the supervisor external interrupt in interrupts-extended
+const SUPERVISOR_EXTERNAL_INTERRUPT: u32 = 9;
+
+/// The context of every hart which receives supervisor interrupts. The
+/// interrupts-extended property lists the interrupt of every context.
+/// Harts without supervisor mode, like the monitor core of the JH7110,
+/// only have a machine context.
+fn supervisor_contexts(root: &DeviceTreeNode, plic: &DeviceTreeNode) -> BTreeMap<usize, usize> {
+    let Some(interrupts) = plic.property("interrupts-extended") else {
+        warn!("PLIC has no interrupts-extended, assuming two contexts per hart");
+        return BTreeMap::new();
+    };
+    // The phandle of the interrupt controller of every cpu
+    let harts: BTreeMap<u32, usize> = root
+        .find("/cpus")
+        .into_iter()
+        .flat_map(|cpus| cpus.children())
+        .filter_map(|cpu| {
+            let hart_id = cpu.first_reg()?.address;
+            let phandle = cpu
+                .child("interrupt-controller")?
+                .property("phandle")?
+                .u32()?;
+            Some((phandle, hart_id))
+        })
+        .collect();
+    // Every entry consists of the phandle and the cause
+    let cells: Vec<u32> = interrupts.u32_array().collect();
+    cells
+        .chunks_exact(2)
+        .enumerate()
+        .filter(|(_, entry)| entry[1] == SUPERVISOR_EXTERNAL_INTERRUPT)
+        .filter_map(|(context, entry)| Some((*harts.get(&entry[0])?, context)))
+        .collect()
+}
+
+// Called with the id of the interrupt which fired
+pub type InterruptHandler = fn(u32);
+
+// Only priorities above the threshold of a context are delivered
+const MAX_PRIORITY: u32 = 7;
+const DEFAULT_PRIORITY: u32 = 1;
+
+const PRIORITY_OFFSET: usize = 0x0;
+const ENABLE_OFFSET: usize = 0x2000;
+const ENABLE_CONTEXT_STRIDE: usize = 0x80;
+const CONTEXT_OFFSET: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD_OFFSET: usize = 0x0;
+const CLAIM_COMPLETE_OFFSET: usize = 0x4;
 
 struct Plic {
-    priority_register_base: MMIO<u32>,
-    // pending_register: MMIO<u32>,
-    enable_register: MMIO<u32>,
-    threshold_register: MMIO<u32>,
-    claim_complete_register: MMIO<u32>,
+    base: usize,
+    /// The supervisor context of every hart
+    contexts: BTreeMap<usize, usize>,
 }
 
 impl Plic {
-    fn new(plic_base: usize, hart_id: usize) -> Self {
-        let context = hart_id * 2 + 1;
-        // These constants are set to interrupt context 1 which corresponds to Supervisor Mode on Hart 0
-        // If we support multiple harts, we will need to change these constants to be configurable
+    const fn new() -> Self {
         Self {
-            priority_register_base: MMIO::new(plic_base),
-            // pending_register: MMIO::new(plic_base + 0x1000),
-            enable_register: MMIO::new(plic_base + 0x2000 + (0x80 * context)),
-            threshold_register: MMIO::new(plic_base + 0x20_0000 + (0x1000 * context)),
-            claim_complete_register: MMIO::new(plic_base + 0x20_0004 + (0x1000 * context)),
+            base: 0,
+            contexts: BTreeMap::new(),
         }
     }
-    pub fn enable(&mut self, interrupt_id: u32) {
-        self.enable_register |= 1 << interrupt_id;
+
+    /// Without a description of the contexts every hart has a machine
+    /// and a supervisor context.
+    fn context(&self, hart_id: usize) -> usize {
+        self.contexts
+            .get(&hart_id)
+            .copied()
+            .unwrap_or(hart_id * 2 + 1)
+    }
+
+    fn enable_register(&self, hart_id: usize, interrupt_id: u32) -> MMIO<u32> {
+        MMIO::new(
+            self.base
+                + ENABLE_OFFSET
+                + ENABLE_CONTEXT_STRIDE * self.context(hart_id)
+                + size_of::<u32>() * (interrupt_id as usize / 32),
+        )
     }
 
-    pub fn set_priority(&mut self, interrupt_id: u32, priority: u32) {
-        assert!(priority <= 7);
+    fn context_register(&self, hart_id: usize, offset: usize) -> MMIO<u32> {
+        MMIO::new(self.base + CONTEXT_OFFSET + CONTEXT_STRIDE * self.context(hart_id) + offset)
+    }
+
+    fn set_enabled(&self, hart_id: usize, interrupt_id: u32, enabled: bool) {
+        let mut register = self.enable_register(hart_id, interrupt_id);
+        let bit = 1 << (interrupt_id % 32);
+        if enabled {
+            register |= bit;
+        } else {
+            register &= !bit;
+        }
+    }
+
+    fn set_priority(&self, interrupt_id: u32, priority: u32) {
+        assert!(priority <= MAX_PRIORITY);
+        let priority_registers = MMIO::<u32>::new(self.base + PRIORITY_OFFSET);
+        // SAFETY: Every source has a priority register
         unsafe {
-            self.priority_register_base
+            priority_registers
                 .add(interrupt_id as usize)
                 .write(priority);
         }
     }
 
-    pub fn set_threshold(&mut self, threshold: u32) {
-        assert!(threshold <= 7);
-        self.threshold_register.write(threshold);
+    fn set_threshold(&self, hart_id: usize, threshold: u32) {
+        assert!(threshold <= MAX_PRIORITY);
+        self.context_register(hart_id, THRESHOLD_OFFSET)
+            .write(threshold);
     }
 
-    pub fn get_next_pending(&mut self) -> Option<InterruptSource> {
-        let open_interrupt = self.claim_complete_register.read();
-
-        match open_interrupt {
+    fn claim(&self, hart_id: usize) -> Option<u32> {
+        match self.context_register(hart_id, CLAIM_COMPLETE_OFFSET).read() {
             0 => None,
-            UART_INTERRUPT_NUMBER => Some(InterruptSource::Uart),
-            _ => Some(InterruptSource::Else),
+            interrupt_id => Some(interrupt_id),
         }
     }
 
-    pub fn complete_interrupt(&mut self, source: InterruptSource) {
-        let interrupt_id = match source {
-            InterruptSource::Uart => UART_INTERRUPT_NUMBER,
-            InterruptSource::Else => panic!("Invalid interrupt source to complete."),
-        };
-        self.claim_complete_register.write(interrupt_id);
+    fn complete(&self, hart_id: usize, interrupt_id: u32) {
+        self.context_register(hart_id, CLAIM_COMPLETE_OFFSET)
+            .write(interrupt_id);
     }
 }
 
-static PLIC: RuntimeInitializedData<Mutex<Plic>> = RuntimeInitializedData::new();
+// The base address is set when the PLIC is probed
+static PLIC: SpinLockIrqSave<Plic> = SpinLockIrqSave::new(Plic::new());
+
+static HANDLERS: SpinLockIrqSave<BTreeMap<u32, InterruptHandler>> =
+    SpinLockIrqSave::new(BTreeMap::new());
 
-const UART_INTERRUPT_NUMBER: u32 = 10;
+// How often each source was claimed since boot
+static COUNTS: CounterGroup = CounterGroup::new("interrupts");
 
-#[derive(PartialEq, Eq)]
-pub enum InterruptSource {
-    Uart,
-    Else,
+pub fn is_valid_source(interrupt_id: u32) -> bool {
+    // Source 0 is reserved and means no interrupt
+    interrupt_id > 0 && interrupt_id <= *NUMBER_OF_SOURCES
 }
 
-pub fn init_uart_interrupt(hart_id: usize) {
-    info!("Initializing plic uart interrupt");
+fn assert_valid_source(interrupt_id: u32) {
+    assert!(
+        is_valid_source(interrupt_id),
+        "Invalid interrupt source {interrupt_id}"
+    );
+}
 
-    PLIC.initialize(Mutex::new(Plic::new(PLIC_BASE, hart_id)));
+/// Sets up the context of the hart such that it receives every interrupt
+/// it has enabled
+pub fn init_hart(hart_id: usize) {
+    info!("Initializing plic for hart {hart_id}");
+    PLIC.lock().set_threshold(hart_id, 0);
+}
 
-    let mut plic = PLIC.lock();
-    plic.set_threshold(0);
-    plic.enable(UART_INTERRUPT_NUMBER);
-    plic.set_priority(UART_INTERRUPT_NUMBER, 1);
+/// Registers the handler and routes the interrupt to the given hart. The
+/// handler runs in interrupt context before the interrupt is completed.
+pub fn register_handler(interrupt_id: u32, hart_id: usize, handler: InterruptHandler) {
+    assert!(
+        try_register_handler(interrupt_id, hart_id, handler),
+        "Interrupt {interrupt_id} already has a handler"
+    );
+}
+
+/// Like `register_handler` but returns false if the interrupt already
+/// has a handler
+pub fn try_register_handler(interrupt_id: u32, hart_id: usize, handler: InterruptHandler) -> bool {
+    assert_valid_source(interrupt_id);
+    let mut handlers = HANDLERS.lock();
+    if handlers.contains_key(&interrupt_id) {
+        return false;
+    }
+    handlers.insert(interrupt_id, handler);
+    let plic = PLIC.lock();
+    plic.set_priority(interrupt_id, DEFAULT_PRIORITY);
+    plic.set_enabled(hart_id, interrupt_id, true);
+    true
+}
+
+/// Disables the interrupt on the hart it was routed to
+pub fn unregister_handler(interrupt_id: u32, hart_id: usize) {
+    assert_valid_source(interrupt_id);
+    PLIC.lock().set_enabled(hart_id, interrupt_id, false);
+    assert!(
+        HANDLERS.lock().remove(&interrupt_id).is_some(),
+        "Interrupt {interrupt_id} has no handler"
+    );
 }
 
-pub fn get_next_pending() -> Option<InterruptSource> {
-    PLIC.lock().get_next_pending()
+#[allow(dead_code)]
+pub fn set_priority(interrupt_id: u32, priority: u32) {
+    assert_valid_source(interrupt_id);
+    PLIC.lock().set_priority(interrupt_id, priority);
 }
 
-pub fn complete_interrupt(source: InterruptSource) {
-    PLIC.lock().complete_interrupt(source);
+/// Interrupts with a priority less or equal than the threshold are masked
+#[allow(dead_code)]
+pub fn set_threshold(hart_id: usize, threshold: u32) {
+    PLIC.lock().set_threshold(hart_id, threshold);
+}
+
+pub fn enable(interrupt_id: u32, hart_id: usize) {
+    assert_valid_source(interrupt_id);
+    PLIC.lock().set_enabled(hart_id, interrupt_id, true);
+}
+
+pub fn disable(interrupt_id: u32, hart_id: usize) {
+    assert_valid_source(interrupt_id);
+    PLIC.lock().set_enabled(hart_id, interrupt_id, false);
+}
+
+/// Sources which never fired are left out
+pub fn interrupt_counts() -> BTreeMap<u32, u64> {
+    COUNTS
+        .values()
+        .into_iter()
+        .map(|(interrupt_id, count)| (interrupt_id as u32, count))
+        .collect()
+}
+
+/// Claims and handles all pending interrupts of the hart
+pub fn handle_pending(hart_id: usize) {
+    loop {
+        // The guard of a while let scrutinee would live until the end of
+        // the body but the handler might need the lock, e.g. to disable
+        // its interrupt
+        let Some(interrupt_id) = PLIC.lock().claim(hart_id) else {
+            break;
+        };
+        COUNTS.increment(interrupt_id.into());
+        let handler = HANDLERS.lock().get(&interrupt_id).copied();
+        match handler {
+            Some(handler) => handler(interrupt_id),
+            None => {
+                warn!("No handler for interrupt {interrupt_id}");
+            }
+        }
+        PLIC.lock().complete(hart_id, interrupt_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        supervisor_contexts, try_register_handler, unregister_handler, NUMBER_OF_SOURCES, PLIC,
+    };
+    use crate::{
+        cpu::Cpu,
+        device_tree::{tree::DeviceTreeNode, DeviceTree, Header},
+    };
+    use alloc::vec::Vec;
+    use common::include_bytes_align_as;
+
+    const DTB: &[u8] = include_bytes_align_as!(Header, "../test/test_data/dtb");
+
+    #[test_case]
+    fn contexts_are_taken_from_interrupts_extended() {
+        let tree = DeviceTreeNode::parse(DeviceTree::new(DTB.as_ptr() as *const ()).root_node());
+        let plic = tree.find("/soc/plic").expect("plic node must exist");
+        let contexts = supervisor_contexts(&tree, plic);
+        assert_eq!(contexts.into_iter().collect::<Vec<_>>(), [(0, 1)]);
+    }
+
+    fn is_enabled(hart_id: usize, interrupt_id: u32) -> bool {
+        PLIC.lock().enable_register(hart_id, interrupt_id).read() & (1 << (interrupt_id % 32)) != 0
+    }
+
+    #[test_case]
+    fn sources_have_one_handler_at_a_time() {
+        // No device of the test machine uses the last source
+        let interrupt_id = *NUMBER_OF_SOURCES;
+        let hart_id = Cpu::cpu_id();
+        assert!(try_register_handler(interrupt_id, hart_id, |_| {}));
+        assert!(is_enabled(hart_id, interrupt_id));
+        assert!(!try_register_handler(interrupt_id, hart_id, |_| {}));
+
+        unregister_handler(interrupt_id, hart_id);
+        assert!(!is_enabled(hart_id, interrupt_id));
+        assert!(try_register_handler(interrupt_id, hart_id, |_| {}));
+        unregister_handler(interrupt_id, hart_id);
+    }
 }
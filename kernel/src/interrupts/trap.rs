@@ -1,15 +1,35 @@
-use super::trap_cause::{exception::ENVIRONMENT_CALL_FROM_U_MODE, InterruptCause};
+use super::trap_cause::{
+    exception::{
+        BREAKPOINT, ENVIRONMENT_CALL_FROM_U_MODE, ILLEGAL_INSTRUCTION, INSTRUCTION_PAGE_FAULT,
+        LOAD_PAGE_FAULT, STORE_AMO_PAGE_FAULT,
+    },
+    interrupt::{SUPERVISOR_EXTERNAL_INTERRUPT, SUPERVISOR_TIMER_INTERRUPT},
+    InterruptCause,
+};
 use crate::{
     cpu::Cpu,
     debug,
-    interrupts::plic::{self, InterruptSource},
-    io::{stdin_buf::STDIN_BUFFER, uart},
-    processes::process::ProcessState,
+    debugging::{
+        core_dump::{self, CoreDump},
+        gdb_stub, profiler,
+    },
+    interrupts::{deferred, nesting, plic},
+    klibc::counters::Counter,
+    memory::{
+        page_cache,
+        page_owner::{self, Subsystem},
+        vma,
+    },
+    processes::{floating_point, hotplug, kernel_stack, process::ProcessState, timer, vector},
     syscalls::{self},
+    warn,
 };
-use common::syscalls::trap_frame::Register;
+use common::syscalls::trap_frame::{Register, TrapFrame};
 use core::panic;
 
+static TIMER_INTERRUPTS: Counter = Counter::new("interrupts.timer");
+static PAGE_FAULTS: Counter = Counter::new("memory.page_faults");
+
 #[no_mangle]
 extern "C" fn get_process_satp_value() -> usize {
     Cpu::with_current_process(|p| p.get_page_table().get_satp_value_from_page_tables())
@@ -17,27 +37,83 @@ extern "C" fn get_process_satp_value() -> usize {
 
 #[no_mangle]
 extern "C" fn handle_timer_interrupt() {
-    Cpu::with_scheduler(|s| s.schedule());
+    TIMER_INTERRUPTS.increment();
+    profiler::sample();
+    let time_slice_expired = timer::handle_timer_interrupt();
+    finish_timer_interrupt(time_slice_expired);
+}
+
+fn finish_timer_interrupt(time_slice_expired: bool) {
+    gdb_stub::poll();
+    page_cache::submit_reclaim_if_requested();
+    Cpu::with_scheduler(|s| {
+        if !s.enforce_cpu_time_limit() && time_slice_expired {
+            s.schedule();
+        }
+    });
 }
 
+/// Interrupts taken while a handler lets them in, see
+/// interrupts::nesting. The handler might hold any lock, therefore
+/// only the interrupt itself is handled here.
 #[no_mangle]
-fn handle_external_interrupt() {
-    debug!("External interrupt occurred!");
-    let plic_interrupt = plic::get_next_pending().expect("There should be a pending interrupt.");
-    assert!(
-        plic_interrupt == InterruptSource::Uart,
-        "Plic interrupt should be uart."
-    );
+extern "C" fn handle_nested_trap() {
+    let cause = InterruptCause::from_scause();
+    match (cause.is_interrupt(), cause.get_exception_code()) {
+        (true, SUPERVISOR_TIMER_INTERRUPT) => {
+            TIMER_INTERRUPTS.increment();
+            profiler::sample();
+            if timer::handle_timer_interrupt() {
+                nesting::request_preemption();
+            }
+        }
+        (true, SUPERVISOR_EXTERNAL_INTERRUPT) => handle_external_interrupt(),
+        _ => panic!(
+            "Unexpected nested trap: {} (stval: {:#x} sepc: {:#x})",
+            cause.get_reason(),
+            Cpu::read_stval(),
+            Cpu::read_sepc()
+        ),
+    }
+    nesting::count_nested_interrupt();
+}
 
-    let input = uart::read().expect("There should be input from the uart.");
+/// Called by every trap handler before it returns
+#[no_mangle]
+extern "C" fn handle_deferred_preemption() {
+    if nesting::take_preemption_request() {
+        finish_timer_interrupt(true);
+    }
+}
 
-    plic::complete_interrupt(plic_interrupt);
+#[no_mangle]
+extern "C" fn handle_supervisor_software_interrupt() {
+    // Software interrupts are used to wake up idle harts,
+    // to hand over timers, to stop harts, to freeze
+    // them after a panic or before a shutdown and to
+    // run deferred work.
+    Cpu::clear_software_interrupt();
+    crate::panic::handle_panic_broadcast();
+    hotplug::handle_park_request();
+    deferred::run_pending();
+    timer::adopt_migrated_timers();
+    hotplug::handle_stop_request();
+    Cpu::with_scheduler(|s| {
+        if s.is_current_process_energy_saver() {
+            s.schedule();
+        } else {
+            s.park_current_process_if_requested();
+        }
+    });
+}
 
-    match input {
-        3 => Cpu::current().scheduler_mut().send_ctrl_c(),
-        4 => crate::debugging::dump_current_state(),
-        _ => STDIN_BUFFER.lock().push(input),
-    }
+#[no_mangle]
+fn handle_external_interrupt() {
+    debug!("External interrupt occurred!");
+    page_owner::charge_to(
+        Subsystem::Interrupts,
+        || plic::handle_pending(Cpu::cpu_id()),
+    );
 }
 
 fn handle_syscall() {
@@ -63,8 +139,12 @@ fn handle_syscall() {
         Cpu::write_sepc(Cpu::read_sepc() + 4); // Skip the ecall instruction
     }
 
-    // In case our current process was set to waiting state we need to reschedule
-    if scheduler.get_current_process().lock().get_state() == ProcessState::Waiting {
+    // In case our current process was set to waiting state or stopped itself we
+    // need to reschedule
+    if scheduler
+        .get_current_process()
+        .with_lock(|p| p.get_state() == ProcessState::Waiting || p.is_stopped())
+    {
         scheduler.schedule();
     }
 }
@@ -73,6 +153,10 @@ fn handle_unhandled_exception() {
     let cause = InterruptCause::from_scause();
     let stval = Cpu::read_stval();
     let sepc = Cpu::read_sepc();
+    if !Cpu::is_in_kernel_mode() {
+        handle_userspace_crash(cause, stval, sepc);
+        return;
+    }
     let cpu = Cpu::current();
     let scheduler = cpu.scheduler();
     let message= cpu.scheduler().get_current_process().with_lock(|p| {
@@ -90,15 +174,93 @@ fn handle_unhandled_exception() {
     panic!("{}", message);
 }
 
+/// A faulting userspace process does not take the kernel down. It is
+/// killed after its core file has been written.
+fn handle_userspace_crash(cause: InterruptCause, stval: usize, sepc: usize) {
+    let mut cpu = Cpu::current();
+    let scheduler = cpu.scheduler_mut();
+    let registers = *scheduler.trap_frame().registers();
+    let dump = scheduler.get_current_process().with_lock(|p| {
+        warn!(
+            "Process {} (PID={}) crashed: {} (stval: {:#x} sepc: {:#x})",
+            p.get_name(),
+            p.get_pid(),
+            cause.get_reason(),
+            stval,
+            sepc
+        );
+        CoreDump::capture(&p, sepc, &registers, cause.get_exception_code())
+    });
+    scheduler.hold_crashed_current_process();
+    core_dump::submit(dump);
+}
+
+fn handle_breakpoint() {
+    let mut trap_frame = *Cpu::current().scheduler().trap_frame();
+    if !gdb_stub::handle_breakpoint(&mut trap_frame) {
+        // The process is killed, the trap frame belongs to the next one
+        handle_unhandled_exception();
+        return;
+    }
+    *Cpu::current().scheduler_mut().trap_frame_mut() = trap_frame;
+}
+
+/// Pages of mapped files are loaded on the first access, shared pages
+/// are copied on the first write and the stack grows below its end
+fn handle_page_fault(write: bool) {
+    if Cpu::is_in_kernel_mode() {
+        let address = Cpu::read_stval();
+        if let Some(pid) = Cpu::maybe_current_pid() {
+            assert!(
+                !kernel_stack::is_overflow(pid, address),
+                "Kernel thread (PID={pid}) overflowed its stack (stval: {address:#x} sepc: {:#x})",
+                Cpu::read_sepc()
+            );
+        }
+        handle_unhandled_exception();
+        return;
+    }
+    PAGE_FAULTS.increment();
+    let process = Cpu::current_process();
+    let address = Cpu::read_stval();
+    if !vma::handle_page_fault(&process, address, write)
+        && !process.with_lock(|mut p| p.grow_stack(address))
+    {
+        handle_unhandled_exception();
+    }
+}
+
 #[no_mangle]
 extern "C" fn handle_exception() {
     let cause = InterruptCause::from_scause();
     match cause.get_exception_code() {
         ENVIRONMENT_CALL_FROM_U_MODE => handle_syscall(),
+        BREAKPOINT => handle_breakpoint(),
+        ILLEGAL_INSTRUCTION => {
+            if !floating_point::handle_illegal_instruction()
+                && !vector::handle_illegal_instruction()
+            {
+                handle_unhandled_exception();
+            }
+        }
+        INSTRUCTION_PAGE_FAULT | LOAD_PAGE_FAULT => handle_page_fault(false),
+        STORE_AMO_PAGE_FAULT => handle_page_fault(true),
         _ => handle_unhandled_exception(),
     }
 }
 
+/// Breakpoints in kernel mode have their own trap frame,
+/// see asm_dispatch_exception.
+#[no_mangle]
+extern "C" fn handle_kernel_breakpoint(trap_frame: &mut TrapFrame) {
+    if !gdb_stub::handle_breakpoint(trap_frame) {
+        panic!(
+            "Breakpoint in kernel mode without a debugger (sepc: {:#x})",
+            Cpu::read_sepc()
+        );
+    }
+}
+
 #[no_mangle]
 extern "C" fn handle_unimplemented() {
     let sepc = Cpu::read_sepc();
@@ -0,0 +1,198 @@
+//! Delivers interrupts to userspace drivers, similar to UIO on Linux.
+//! Only the driver knows how to silence its device, therefore the
+//! interrupt is masked when it fires. Waiting for the next interrupt
+//! unmasks it again. A wait which times out leaves it unmasked, the
+//! interrupts until the next wait are counted as usual.
+
+use alloc::collections::BTreeMap;
+use common::{errors::SysIrqError, mutex::SpinLockIrqSave};
+
+use crate::{
+    cpu::Cpu,
+    processes::{process::Pid, process_table::ProcessRef, timer, wait_queue::WaitQueue},
+};
+
+use super::plic;
+
+type WaitResult = Result<u64, SysIrqError>;
+
+struct Registration {
+    pid: Pid,
+    hart_id: usize,
+    // Interrupts since the last wait returned
+    pending: u64,
+    // Only the owner waits, the queue holds its current wait
+    waiters: WaitQueue<WaitResult>,
+}
+
+impl Registration {
+    /// Takes the waiter together with the interrupts it has to be
+    /// resumed with. Returns None if there is nothing to deliver.
+    fn take_delivery(&mut self) -> Option<(WaitQueue<WaitResult>, u64)> {
+        if self.pending == 0 || self.waiters.is_empty() {
+            return None;
+        }
+        let waiters = core::mem::replace(&mut self.waiters, WaitQueue::new());
+        Some((waiters, core::mem::take(&mut self.pending)))
+    }
+}
+
+static REGISTRATIONS: SpinLockIrqSave<BTreeMap<u32, Registration>> =
+    SpinLockIrqSave::new(BTreeMap::new());
+
+/// Owned by the process, the interrupt is released when it is dropped
+#[derive(Debug)]
+pub struct UserspaceInterrupt {
+    interrupt_id: u32,
+}
+
+impl Drop for UserspaceInterrupt {
+    fn drop(&mut self) {
+        let registration = REGISTRATIONS
+            .lock()
+            .remove(&self.interrupt_id)
+            .expect("Registration must exist");
+        plic::unregister_handler(self.interrupt_id, registration.hart_id);
+    }
+}
+
+/// Routes the interrupt to the current hart
+pub fn register(interrupt_id: u32, pid: Pid) -> Result<UserspaceInterrupt, SysIrqError> {
+    if !plic::is_valid_source(interrupt_id) {
+        return Err(SysIrqError::InvalidInterrupt);
+    }
+    let hart_id = Cpu::cpu_id();
+    // Locked before the handler is registered such that the handler
+    // always finds the registration
+    let mut registrations = REGISTRATIONS.lock();
+    if !plic::try_register_handler(interrupt_id, hart_id, handle_interrupt) {
+        return Err(SysIrqError::AlreadyRegistered);
+    }
+    registrations.insert(
+        interrupt_id,
+        Registration {
+            pid,
+            hart_id,
+            pending: 0,
+            waiters: WaitQueue::new(),
+        },
+    );
+    Ok(UserspaceInterrupt { interrupt_id })
+}
+
+/// Returns the number of interrupts since the last wait. If there were
+/// none, the process is put to sleep until the interrupt fires or the
+/// deadline passed. The returned value is overwritten when it is resumed.
+/// Must not be called with the process locked.
+pub fn wait(interrupt_id: u32, deadline: u64, pid: Pid, process: &ProcessRef) -> WaitResult {
+    let mut registrations = REGISTRATIONS.lock();
+    let registration = registrations
+        .get_mut(&interrupt_id)
+        .filter(|registration| registration.pid == pid)
+        .ok_or(SysIrqError::NotRegistered)?;
+    if registration.pending > 0 {
+        return Ok(core::mem::take(&mut registration.pending));
+    }
+    if deadline <= timer::get_current_clocks() {
+        return Err(SysIrqError::TimedOut);
+    }
+    // Must happen before the interrupt can fire
+    registration
+        .waiters
+        .wait_until(process, deadline, Err(SysIrqError::TimedOut));
+    plic::enable(interrupt_id, registration.hart_id);
+    Ok(0)
+}
+
+fn handle_interrupt(interrupt_id: u32) {
+    let hart_id = {
+        let mut registrations = REGISTRATIONS.lock();
+        let registration = registrations
+            .get_mut(&interrupt_id)
+            .expect("Handler is only registered together with the registration");
+        registration.pending += 1;
+        registration.hart_id
+    };
+    plic::disable(interrupt_id, hart_id);
+    deliver(interrupt_id);
+}
+
+/// Resumes the waiting process with the pending interrupts. If its wait
+/// ended in the meantime they stay pending, unless it already waits again.
+fn deliver(interrupt_id: u32) {
+    loop {
+        let Some((mut waiters, count)) = REGISTRATIONS
+            .lock()
+            .get_mut(&interrupt_id)
+            .and_then(Registration::take_delivery)
+        else {
+            return;
+        };
+        // Dropping the last reference of a killed process drops the
+        // registration as well, therefore the lock must not be held
+        if waiters.wake_one(Ok(count)) {
+            return;
+        }
+        match REGISTRATIONS.lock().get_mut(&interrupt_id) {
+            Some(registration) => registration.pending += count,
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use common::mutex::Mutex;
+
+    use crate::{
+        autogenerated::userspace_programs::PROG1,
+        klibc::elf::ElfFile,
+        processes::{
+            environment::Environment, process::Process, process_table::ProcessRef,
+            wait_queue::WaitQueue,
+        },
+    };
+
+    use super::Registration;
+
+    fn registration() -> Registration {
+        Registration {
+            pid: 0,
+            hart_id: 0,
+            pending: 0,
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    fn process() -> ProcessRef {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        Arc::new(Mutex::new(process))
+    }
+
+    #[test_case]
+    fn interrupts_without_waiter_are_counted() {
+        let mut registration = registration();
+        registration.pending += 2;
+        assert!(registration.take_delivery().is_none());
+        assert_eq!(registration.pending, 2);
+    }
+
+    #[test_case]
+    fn waiter_is_delivered_to_once() {
+        let mut registration = registration();
+        let process = process();
+        registration.waiters.wait(&process);
+        assert!(registration.take_delivery().is_none());
+
+        registration.pending += 1;
+        let (waiters, count) = registration.take_delivery().unwrap();
+        assert_eq!(count, 1);
+        assert!(!waiters.is_empty());
+        registration.pending += 1;
+        assert!(registration.take_delivery().is_none());
+        // The process is killed, it is never resumed with the value
+        process.lock().cancel_wait();
+    }
+}
@@ -0,0 +1,100 @@
+//! The kernel console is the UART unless `console=sbi` selects the debug
+//! console of the firmware, e.g. on boards where the UART is not mapped
+//! or not where QEMU has it. The firmware console has no interrupt,
+//! therefore its input is polled.
+
+use alloc::vec::Vec;
+use common::mutex::SpinLockIrqSave;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    cmdline::{self, Console},
+    cpu::Cpu,
+    debugging, info, initcall,
+    interrupts::deferred,
+    io::stdin_buf,
+    processes::timer,
+    sbi::extensions::debug_console_extension,
+    warn,
+};
+
+const POLL_INTERVAL_MILLISECONDS: u64 = 10;
+const BUFFER_SIZE: usize = 256;
+
+static USES_SBI: AtomicBool = AtomicBool::new(false);
+
+// The firmware needs identity mapped buffers, the stack of a kernel
+// thread or of a trap is not
+static OUTPUT: SpinLockIrqSave<[u8; BUFFER_SIZE]> = SpinLockIrqSave::new([0; BUFFER_SIZE]);
+static INPUT: SpinLockIrqSave<[u8; BUFFER_SIZE]> = SpinLockIrqSave::new([0; BUFFER_SIZE]);
+
+initcall!(Postcore, init);
+
+/// Must be called after the command line is parsed and timers work
+pub fn init() {
+    if cmdline::get().console != Console::Sbi {
+        return;
+    }
+    if !debug_console_extension::is_available() {
+        warn!("The firmware has no debug console, using the UART");
+        return;
+    }
+    USES_SBI.store(true, Ordering::Relaxed);
+    info!("Console switched to the SBI debug console");
+    poll_input();
+}
+
+/// Whether the output goes to the firmware instead of the UART
+pub fn uses_sbi() -> bool {
+    USES_SBI.load(Ordering::Relaxed)
+}
+
+/// Blocks until the firmware wrote everything
+pub fn write_sbi(s: &str) {
+    let mut buffer = OUTPUT.lock();
+    for chunk in s.as_bytes().chunks(BUFFER_SIZE) {
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let mut written = 0;
+        while written < chunk.len() {
+            let result = debug_console_extension::write(&buffer[written..chunk.len()]);
+            if result.is_error() {
+                // Nothing is left to report the error to
+                return;
+            }
+            written += result.value as usize;
+        }
+    }
+}
+
+fn poll_input() {
+    let input = {
+        let mut buffer = INPUT.lock();
+        let result = debug_console_extension::read(&mut *buffer);
+        let length = if result.is_error() {
+            0
+        } else {
+            result.value as usize
+        };
+        buffer[..length].to_vec()
+    };
+    receive_input(input);
+    timer::add_timer_in(POLL_INTERVAL_MILLISECONDS, poll_input);
+}
+
+/// Handles the bytes typed on the console. Called in interrupt context.
+pub fn receive_input(input: Vec<u8>) {
+    if input.is_empty() {
+        return;
+    }
+
+    deferred::defer(move || {
+        for byte in input {
+            match byte {
+                3 => Cpu::current().scheduler_mut().send_ctrl_c(),
+                26 => Cpu::current().scheduler_mut().send_ctrl_z(),
+                4 => debugging::dump_current_state(),
+                _ => stdin_buf::receive_console_input(byte),
+            }
+        }
+    });
+}
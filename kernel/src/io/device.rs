@@ -0,0 +1,158 @@
+//! Devices which are accessed through files in /dev. Character devices
+//! are streams without a position, block devices are addressed in blocks
+//! and devfs translates byte offsets to them.
+
+use common::mutex::Mutex;
+
+use crate::{device_tree, fs::vfs::FsError, io::stdin_buf::STDIN_BUFFER, print, processes::timer};
+
+pub trait CharacterDevice: Send + Sync {
+    /// Must not block, 0 means that no data is available right now
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError>;
+    fn write(&self, data: &[u8]) -> Result<usize, FsError>;
+}
+
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+    /// The buffer is exactly one block long
+    fn read_block(&self, index: u64, buffer: &mut [u8]) -> Result<(), FsError>;
+    /// The data is exactly one block long
+    fn write_block(&self, index: u64, data: &[u8]) -> Result<(), FsError>;
+}
+
+/// Discards writes and is always at the end
+pub struct Null;
+
+impl CharacterDevice for Null {
+    fn read(&self, _buffer: &mut [u8]) -> Result<usize, FsError> {
+        Ok(0)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, FsError> {
+        Ok(data.len())
+    }
+}
+
+/// Discards writes and reads zeros
+pub struct Zero;
+
+impl CharacterDevice for Zero {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        buffer.fill(0);
+        Ok(buffer.len())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, FsError> {
+        Ok(data.len())
+    }
+}
+
+/// The system console, independent of the terminal of the process
+pub struct Console;
+
+impl CharacterDevice for Console {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let mut stdin = STDIN_BUFFER.lock();
+        let mut count = 0;
+        while count < buffer.len() {
+            let Some(byte) = stdin.pop() else {
+                break;
+            };
+            buffer[count] = byte;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, FsError> {
+        print!("{}", alloc::string::String::from_utf8_lossy(data));
+        Ok(data.len())
+    }
+}
+
+/// A xorshift64* generator seeded from the device tree and the time.
+/// Good enough for tests and games, not for cryptography.
+pub struct Random {
+    state: Mutex<u64>,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let seed = device_tree::THE
+            .root_node()
+            .find_node("chosen")
+            .and_then(|chosen| chosen.get_property("rng-seed"))
+            .map_or(0, |seed| {
+                seed.buffer().chunks(8).fold(0, |seed, chunk| {
+                    let mut bytes = [0; 8];
+                    bytes[..chunk.len()].copy_from_slice(chunk);
+                    seed ^ u64::from_le_bytes(bytes)
+                })
+            });
+        Self::with_seed(seed ^ timer::get_current_clocks())
+    }
+
+    fn with_seed(seed: u64) -> Self {
+        // The state must never be zero
+        Self {
+            state: Mutex::new(seed | 1),
+        }
+    }
+
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl CharacterDevice for Random {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let mut state = self.state.lock();
+        for chunk in buffer.chunks_mut(8) {
+            let value = Self::next(&mut state).to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+        Ok(buffer.len())
+    }
+
+    /// Mixes the data into the state
+    fn write(&self, data: &[u8]) -> Result<usize, FsError> {
+        let mut state = self.state.lock();
+        for chunk in data.chunks(8) {
+            let mut bytes = [0; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            *state = (*state ^ u64::from_le_bytes(bytes)) | 1;
+            Self::next(&mut state);
+        }
+        Ok(data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CharacterDevice, Null, Random, Zero};
+
+    #[test_case]
+    fn null_and_zero() {
+        let mut buffer = [1; 4];
+        assert_eq!(Null.read(&mut buffer), Ok(0));
+        assert_eq!(Null.write(b"abc"), Ok(3));
+        assert_eq!(Zero.read(&mut buffer), Ok(4));
+        assert_eq!(buffer, [0; 4]);
+    }
+
+    #[test_case]
+    fn random_depends_on_seed() {
+        let mut first = [0; 13];
+        let mut second = [0; 13];
+        Random::with_seed(1).read(&mut first).unwrap();
+        Random::with_seed(1).read(&mut second).unwrap();
+        assert_eq!(first, second);
+        Random::with_seed(2).read(&mut second).unwrap();
+        assert_ne!(first, second);
+        assert_ne!(first, [0; 13]);
+    }
+}
@@ -0,0 +1,69 @@
+//! Output before the UART driver is initialized. The UART is looked up
+//! in the device tree, which needs the heap, therefore everything up to
+//! the page allocator is printed here. The debug console extension of
+//! the SBI needs no driver at all. With firmware which doesn't have it
+//! the early output is repeated once the UART is initialized.
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{
+    device_tree::tree,
+    io::uart::{self, CONSOLE_UART},
+    logging::log_tail::{LOG_TAIL, LOG_TAIL_SIZE},
+    sbi::extensions::debug_console_extension,
+    warn,
+};
+
+const UNKNOWN: u8 = 0;
+const AVAILABLE: u8 = 1;
+const UNAVAILABLE: u8 = 2;
+
+// Probed on first use, printing might start before anything is set up
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+pub fn is_available() -> bool {
+    match STATE.load(Ordering::Relaxed) {
+        AVAILABLE => true,
+        UNAVAILABLE => false,
+        _ => {
+            let available = debug_console_extension::is_available();
+            let state = if available { AVAILABLE } else { UNAVAILABLE };
+            STATE.store(state, Ordering::Relaxed);
+            available
+        }
+    }
+}
+
+/// Blocks until the firmware wrote everything
+pub fn write_str(s: &str) {
+    if !is_available() {
+        return;
+    }
+    for byte in s.bytes() {
+        // Nothing is left to report the error to
+        let _ = debug_console_extension::write_byte(byte);
+    }
+}
+
+/// Initializes the console UART of the device tree, all output goes
+/// there afterwards. Without a supported UART the earlycon stays in use.
+pub fn switch_to_uart() {
+    let Some(hardware) = uart::find_console(tree::root()) else {
+        warn!("No supported uart found in the device tree");
+        return;
+    };
+    let mut uart = CONSOLE_UART.lock();
+    uart.init(hardware);
+    if is_available() {
+        return;
+    }
+    // Everything so far is still in the log tail
+    let mut output = [0; LOG_TAIL_SIZE];
+    let length = LOG_TAIL.lock().copy_to(&mut output);
+    for chunk in output[..length].utf8_chunks() {
+        let _ = uart.write_str(chunk.valid());
+    }
+}
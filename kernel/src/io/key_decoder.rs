@@ -0,0 +1,176 @@
+use common::tty::KeyEvent;
+
+const ESCAPE: u8 = 0x1b;
+const BACKSPACE: u8 = 8;
+const DELETE: u8 = 127;
+const MAX_PARAMETER: u16 = 1000;
+
+enum State {
+    Ground,
+    Escape,
+    // Control sequence introducer: ESC [ <parameter> <final>
+    Csi(u16),
+    // Modifiers like in ESC [ 1 ; 5 C are not reported
+    CsiModifier(u16),
+    // Single shift three: ESC O <final>
+    Ss3,
+}
+
+/// Turns the byte stream of a terminal into key events. The state is
+/// kept between calls such that a sequence may arrive in pieces.
+pub struct KeyDecoder {
+    state: State,
+}
+
+impl KeyDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Option<KeyEvent> {
+        match self.state {
+            State::Ground => self.ground(byte),
+            State::Escape => {
+                self.state = match byte {
+                    b'[' => State::Csi(0),
+                    b'O' => State::Ss3,
+                    _ => {
+                        self.state = State::Ground;
+                        return Some(KeyEvent::Alt(byte));
+                    }
+                };
+                None
+            }
+            State::Csi(parameter) => match byte {
+                b'0'..=b'9' => {
+                    let parameter = parameter * 10 + (byte - b'0') as u16;
+                    self.state = State::Csi(parameter.min(MAX_PARAMETER));
+                    None
+                }
+                b';' => {
+                    self.state = State::CsiModifier(parameter);
+                    None
+                }
+                _ => {
+                    self.state = State::Ground;
+                    Self::csi(parameter, byte)
+                }
+            },
+            State::CsiModifier(parameter) => match byte {
+                b'0'..=b'9' => None,
+                _ => {
+                    self.state = State::Ground;
+                    Self::csi(parameter, byte)
+                }
+            },
+            State::Ss3 => {
+                self.state = State::Ground;
+                Self::cursor_key(byte)
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8) -> Option<KeyEvent> {
+        let event = match byte {
+            ESCAPE => {
+                self.state = State::Escape;
+                return None;
+            }
+            b'\r' | b'\n' => KeyEvent::Enter,
+            b'\t' => KeyEvent::Tab,
+            BACKSPACE | DELETE => KeyEvent::Backspace,
+            1..=26 => KeyEvent::Control(byte - 1 + b'a'),
+            byte if byte.is_ascii_control() => return None,
+            byte => KeyEvent::Character(byte),
+        };
+        Some(event)
+    }
+
+    fn csi(parameter: u16, final_byte: u8) -> Option<KeyEvent> {
+        match (parameter, final_byte) {
+            (1 | 7, b'~') => Some(KeyEvent::Home),
+            (4 | 8, b'~') => Some(KeyEvent::End),
+            (3, b'~') => Some(KeyEvent::Delete),
+            (_, b'~') => None,
+            _ => Self::cursor_key(final_byte),
+        }
+    }
+
+    fn cursor_key(final_byte: u8) -> Option<KeyEvent> {
+        match final_byte {
+            b'A' => Some(KeyEvent::Up),
+            b'B' => Some(KeyEvent::Down),
+            b'C' => Some(KeyEvent::Right),
+            b'D' => Some(KeyEvent::Left),
+            b'H' => Some(KeyEvent::Home),
+            b'F' => Some(KeyEvent::End),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use common::tty::KeyEvent;
+
+    use super::KeyDecoder;
+
+    fn decode(input: &[u8]) -> Vec<KeyEvent> {
+        let mut decoder = KeyDecoder::new();
+        input.iter().filter_map(|b| decoder.feed(*b)).collect()
+    }
+
+    #[test_case]
+    fn plain_keys() {
+        assert_eq!(
+            decode(b"a1\r\t\x7f\x15"),
+            [
+                KeyEvent::Character(b'a'),
+                KeyEvent::Character(b'1'),
+                KeyEvent::Enter,
+                KeyEvent::Tab,
+                KeyEvent::Backspace,
+                KeyEvent::Control(b'u'),
+            ]
+        );
+    }
+
+    #[test_case]
+    fn cursor_keys() {
+        assert_eq!(
+            decode(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1bOH\x1b[F"),
+            [
+                KeyEvent::Up,
+                KeyEvent::Down,
+                KeyEvent::Right,
+                KeyEvent::Left,
+                KeyEvent::Home,
+                KeyEvent::End,
+            ]
+        );
+    }
+
+    #[test_case]
+    fn tilde_sequences() {
+        assert_eq!(
+            decode(b"\x1b[1~\x1b[4~\x1b[3~\x1b[2~x"),
+            [
+                KeyEvent::Home,
+                KeyEvent::End,
+                KeyEvent::Delete,
+                KeyEvent::Character(b'x'),
+            ]
+        );
+    }
+
+    #[test_case]
+    fn modifiers_and_alt() {
+        assert_eq!(
+            decode(b"\x1b[1;5C\x1b[3;5~\x1bb"),
+            [KeyEvent::Right, KeyEvent::Delete, KeyEvent::Alt(b'b')]
+        );
+    }
+}
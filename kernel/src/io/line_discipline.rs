@@ -0,0 +1,137 @@
+use alloc::vec::Vec;
+use common::tty::InputMode;
+
+const BACKSPACE: u8 = 8;
+const DELETE: u8 = 127;
+const CTRL_U: u8 = 0x15;
+const CTRL_W: u8 = 0x17;
+
+const ERASE_CHARACTER: &[u8] = &[BACKSPACE, b' ', BACKSPACE];
+
+/// Sits between the uart and the stdin buffer. In cooked mode input
+/// is collected and edited until a newline arrives, in raw mode every
+/// byte is passed through.
+pub struct LineDiscipline {
+    mode: InputMode,
+    line: Vec<u8>,
+}
+
+impl LineDiscipline {
+    pub const fn new() -> Self {
+        Self {
+            mode: InputMode::Cooked,
+            line: Vec::new(),
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: InputMode) {
+        // A partially typed line does not survive a mode switch
+        self.line.clear();
+        self.mode = mode;
+    }
+
+    /// Processes a single input byte. Bytes that should be echoed are
+    /// appended to `echo`. Returns the bytes which are ready to be read.
+    pub fn process(&mut self, byte: u8, echo: &mut Vec<u8>) -> Option<Vec<u8>> {
+        if self.mode == InputMode::Raw {
+            return Some(alloc::vec![byte]);
+        }
+
+        match byte {
+            b'\r' | b'\n' => {
+                echo.push(b'\n');
+                self.line.push(b'\n');
+                return Some(core::mem::take(&mut self.line));
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() {
+                    echo.extend_from_slice(ERASE_CHARACTER);
+                }
+            }
+            CTRL_U => {
+                self.erase_while(echo, |_| true);
+            }
+            CTRL_W => {
+                self.erase_while(echo, |c| c.is_ascii_whitespace());
+                self.erase_while(echo, |c| !c.is_ascii_whitespace());
+            }
+            byte if !byte.is_ascii() || (byte.is_ascii_control() && byte != b'\t') => {
+                // Other control characters have no meaning in a line and
+                // the echo must stay valid ascii
+            }
+            _ => {
+                echo.push(byte);
+                self.line.push(byte);
+            }
+        }
+        None
+    }
+
+    fn erase_while(&mut self, echo: &mut Vec<u8>, predicate: impl Fn(u8) -> bool) {
+        while let Some(&last) = self.line.last() {
+            if !predicate(last) {
+                break;
+            }
+            self.line.pop();
+            echo.extend_from_slice(ERASE_CHARACTER);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use common::tty::InputMode;
+
+    use super::LineDiscipline;
+
+    fn feed(discipline: &mut LineDiscipline, input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut echo = Vec::new();
+        let mut delivered = Vec::new();
+        for byte in input {
+            if let Some(bytes) = discipline.process(*byte, &mut echo) {
+                delivered.extend(bytes);
+            }
+        }
+        (delivered, echo)
+    }
+
+    #[test_case]
+    fn cooked_mode_delivers_lines() {
+        let mut discipline = LineDiscipline::new();
+        let (delivered, echo) = feed(&mut discipline, b"ab");
+        assert!(delivered.is_empty());
+        assert_eq!(echo, b"ab");
+
+        let (delivered, echo) = feed(&mut discipline, b"c\r");
+        assert_eq!(delivered, b"abc\n");
+        assert_eq!(echo, b"c\n");
+    }
+
+    #[test_case]
+    fn cooked_mode_editing() {
+        let mut discipline = LineDiscipline::new();
+        let (delivered, _) = feed(&mut discipline, b"abd\x7fc\n");
+        assert_eq!(delivered, b"abc\n");
+
+        let (delivered, _) = feed(&mut discipline, b"echo hello  \x17world\n");
+        assert_eq!(delivered, b"echo world\n");
+
+        let (delivered, _) = feed(&mut discipline, b"garbage\x15ls\n");
+        assert_eq!(delivered, b"ls\n");
+
+        let (delivered, echo) = feed(&mut discipline, b"\x7f\n");
+        assert_eq!(delivered, b"\n");
+        assert_eq!(echo, b"\n");
+    }
+
+    #[test_case]
+    fn raw_mode_passes_through() {
+        let mut discipline = LineDiscipline::new();
+        feed(&mut discipline, b"pending");
+        discipline.set_mode(InputMode::Raw);
+        let (delivered, echo) = feed(&mut discipline, b"a\x7f\x1b[A");
+        assert_eq!(delivered, b"a\x7f\x1b[A");
+        assert!(echo.is_empty());
+    }
+}
@@ -1,4 +1,9 @@
+pub mod console;
+pub mod device;
+pub mod earlycon;
+pub mod key_decoder;
+pub mod line_discipline;
+pub mod pty;
+pub mod ramdisk;
 pub mod stdin_buf;
 pub mod uart;
-
-pub const TEST_DEVICE_ADDRESSS: usize = 0x100000;
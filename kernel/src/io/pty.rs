@@ -0,0 +1,303 @@
+use alloc::{collections::VecDeque, sync::Arc};
+use common::mutex::{Mutex, MutexGuard};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    io::stdin_buf::StdinBuffer,
+    processes::{poll, process_table::ProcessRef, wait_queue::WaitQueue},
+};
+
+/// Writers of the slave side block while the master didn't pick up
+/// this much output. Echoed input beyond it is dropped.
+const MAX_BUFFERED_OUTPUT: usize = 64 * 1024;
+
+type SharedPty = Arc<Mutex<Pty>>;
+
+/// A pseudo terminal. The master side is held by a process like a
+/// terminal multiplexer or a remote shell server. Processes attached
+/// to the slave side use it instead of the console.
+///
+/// Both sides are referred to by handles which count how often the
+/// side is open. Once all slaves are gone the master reads the end of
+/// the output, once all masters are gone nobody can type anymore.
+pub struct Pty {
+    input: StdinBuffer,
+    output: VecDeque<u8>,
+    // Masters which wait for output and slaves which wait for space in
+    // the output. Both execute their syscall again when woken.
+    readers: WaitQueue<()>,
+    writers: WaitQueue<()>,
+    // Shared by all slave handles, see PtyMaster::slave
+    slaves: Arc<AtomicUsize>,
+    slaves_gone: bool,
+    masters_gone: bool,
+}
+
+impl Pty {
+    /// Returns the only master handle of the new pty
+    pub fn open() -> PtyMaster {
+        let pty = Arc::new(Mutex::new(Self {
+            input: StdinBuffer::new(),
+            output: VecDeque::new(),
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+            slaves: Arc::new(AtomicUsize::new(0)),
+            slaves_gone: false,
+            masters_gone: false,
+        }));
+        PtyMaster(Handle::new(pty))
+    }
+
+    /// The input of the slave side
+    pub fn input(&mut self) -> &mut StdinBuffer {
+        &mut self.input
+    }
+
+    /// Input typed on the master side. It goes through the line
+    /// discipline like input on the console.
+    pub fn write_from_master(&mut self, data: &[u8]) {
+        for byte in data {
+            let echo = self.input.receive(*byte);
+            let space = MAX_BUFFERED_OUTPUT.saturating_sub(self.output.len());
+            self.push_output(&echo[..echo.len().min(space)]);
+        }
+    }
+
+    pub fn read_from_master(&mut self, buffer: &mut [u8]) -> usize {
+        let count = buffer.len().min(self.output.len());
+        for (destination, byte) in buffer.iter_mut().zip(self.output.drain(..count)) {
+            *destination = byte;
+        }
+        if count > 0 {
+            self.writers.wake_all(());
+        }
+        count
+    }
+
+    pub fn has_output(&self) -> bool {
+        !self.output.is_empty()
+    }
+
+    /// A read of the master returns right away, with the end of the
+    /// output if no slave is left
+    pub fn is_readable(&self) -> bool {
+        self.has_output() || self.slaves_gone
+    }
+
+    pub fn is_hung_up(&self) -> bool {
+        self.slaves_gone
+    }
+
+    /// Returns false if the output has no space for the data. A write
+    /// which is larger than the whole buffer is taken once the buffer
+    /// is empty. Without a master the data is dropped.
+    pub fn write_from_slave(&mut self, data: &[u8]) -> bool {
+        if self.masters_gone {
+            return true;
+        }
+        if !self.output.is_empty() && self.output.len() + data.len() > MAX_BUFFERED_OUTPUT {
+            return false;
+        }
+        self.push_output(data);
+        true
+    }
+
+    /// The process executes its read again once there is output or
+    /// the deadline passed
+    pub fn wait_for_output(&mut self, process: &ProcessRef, deadline: u64) {
+        self.readers.wait_until(process, deadline, ());
+    }
+
+    /// The process executes its write again once the master read
+    pub fn wait_for_space(&mut self, process: &ProcessRef) {
+        self.writers.wait(process);
+    }
+
+    fn push_output(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        self.output.extend(data);
+        self.readers.wake_all(());
+        poll::notify();
+    }
+
+    fn attach_slave(&mut self) {
+        self.slaves_gone = false;
+    }
+
+    fn all_slaves_gone(&mut self) {
+        // A new slave might have been attached in the meantime
+        if self.slaves.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        self.slaves_gone = true;
+        self.readers.wake_all(());
+        poll::notify();
+    }
+
+    fn all_masters_gone(&mut self) {
+        self.masters_gone = true;
+        self.output.clear();
+        self.writers.wake_all(());
+        self.input.hang_up();
+    }
+}
+
+/// One side of a pty. Cloning and dropping only counts, the pty is
+/// locked when the last handle of the side is dropped.
+struct Handle {
+    pty: SharedPty,
+    count: Arc<AtomicUsize>,
+}
+
+impl Handle {
+    fn new(pty: SharedPty) -> Self {
+        Self {
+            pty,
+            count: Arc::new(AtomicUsize::new(1)),
+        }
+    }
+
+    /// Returns true if this was the last handle of the side
+    fn release(&self) -> bool {
+        self.count.fetch_sub(1, Ordering::SeqCst) == 1
+    }
+}
+
+impl Clone for Handle {
+    fn clone(&self) -> Self {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        Self {
+            pty: self.pty.clone(),
+            count: self.count.clone(),
+        }
+    }
+}
+
+/// Must not be dropped while the process which held it is locked
+#[derive(Clone)]
+pub struct PtyMaster(Handle);
+
+impl PtyMaster {
+    pub fn lock(&self) -> MutexGuard<'_, Pty> {
+        self.0.pty.lock()
+    }
+
+    /// A new handle of the slave side, e.g. for the terminal of a
+    /// started program
+    pub fn slave(&self) -> PtySlave {
+        let mut pty = self.lock();
+        let count = pty.slaves.clone();
+        if count.fetch_add(1, Ordering::SeqCst) == 0 {
+            pty.attach_slave();
+        }
+        PtySlave(Handle {
+            pty: self.0.pty.clone(),
+            count,
+        })
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        if self.0.release() {
+            self.lock().all_masters_gone();
+        }
+    }
+}
+
+/// Must not be dropped while the process which held it is locked
+#[derive(Clone)]
+pub struct PtySlave(Handle);
+
+impl PtySlave {
+    pub fn lock(&self) -> MutexGuard<'_, Pty> {
+        self.0.pty.lock()
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        if self.0.release() {
+            self.lock().all_slaves_gone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::tty::InputMode;
+
+    use super::{Pty, MAX_BUFFERED_OUTPUT};
+
+    #[test_case]
+    fn master_input_is_echoed_and_line_buffered() {
+        let master = Pty::open();
+        let mut pty = master.lock();
+        pty.write_from_master(b"ls");
+        assert_eq!(pty.input().pop(), None);
+        pty.write_from_master(b"\r");
+        assert_eq!(pty.input().pop(), Some(b'l'));
+
+        assert!(pty.write_from_slave(b"out"));
+        let mut buffer = [0; 16];
+        let count = pty.read_from_master(&mut buffer);
+        assert_eq!(&buffer[..count], b"ls\nout");
+        assert_eq!(pty.read_from_master(&mut buffer), 0);
+    }
+
+    #[test_case]
+    fn raw_mode_does_not_echo() {
+        let master = Pty::open();
+        let mut pty = master.lock();
+        pty.input().set_mode(InputMode::Raw);
+        pty.write_from_master(b"x");
+        assert_eq!(pty.input().pop(), Some(b'x'));
+        assert_eq!(pty.read_from_master(&mut [0; 4]), 0);
+    }
+
+    #[test_case]
+    fn slaves_wait_until_the_master_read() {
+        let master = Pty::open();
+        let mut pty = master.lock();
+        assert!(pty.write_from_slave(&[b'a'; MAX_BUFFERED_OUTPUT - 1]));
+        assert!(pty.write_from_slave(b"b"));
+        assert!(!pty.write_from_slave(b"c"));
+
+        assert_eq!(pty.read_from_master(&mut [0; 1]), 1);
+        assert!(pty.write_from_slave(b"c"));
+
+        // Larger writes are taken once everything was read
+        let mut buffer = alloc::vec![0; MAX_BUFFERED_OUTPUT];
+        assert_eq!(pty.read_from_master(&mut buffer), MAX_BUFFERED_OUTPUT);
+        assert!(pty.write_from_slave(&[b'd'; MAX_BUFFERED_OUTPUT + 1]));
+    }
+
+    #[test_case]
+    fn closed_sides_are_noticed() {
+        let master = Pty::open();
+        assert!(!master.lock().is_readable());
+
+        let slave = master.slave();
+        let second_slave = slave.clone();
+        drop(slave);
+        assert!(!master.lock().is_hung_up());
+        drop(second_slave);
+        assert!(master.lock().is_hung_up());
+        assert!(master.lock().is_readable());
+
+        // A new program was started on the pty
+        let slave = master.slave();
+        assert!(!master.lock().is_hung_up());
+
+        let second_master = master.clone();
+        drop(master);
+        assert!(!slave.lock().input().is_hung_up());
+        drop(second_master);
+        assert!(slave.lock().input().is_hung_up());
+        // Nobody reads the output anymore
+        assert!(slave.lock().write_from_slave(&[0; 2 * MAX_BUFFERED_OUTPUT]));
+        assert!(slave.lock().write_from_slave(b"more"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Block devices without hardware. A ramdisk keeps its blocks in kernel
+//! memory and is gone after a reboot. A loop device exposes a file as
+//! block device, e.g. a filesystem image on the host share. Both work
+//! in the kernel tests, which have no virtio-blk device.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use common::mutex::Mutex;
+
+use crate::fs::vfs::{FsError, Inode};
+
+use super::device::BlockDevice;
+
+pub const BLOCK_SIZE: usize = 512;
+
+pub struct RamDisk {
+    block_size: usize,
+    data: Mutex<Vec<u8>>,
+}
+
+impl RamDisk {
+    pub fn new(block_size: usize, block_count: u64) -> Self {
+        Self::from_bytes(block_size, vec![0; block_size * block_count as usize])
+    }
+
+    /// The data must consist of whole blocks
+    pub fn from_bytes(block_size: usize, data: Vec<u8>) -> Self {
+        assert!(block_size > 0, "Block size must not be zero");
+        assert_eq!(
+            data.len() % block_size,
+            0,
+            "Ramdisk must consist of whole blocks"
+        );
+        Self {
+            block_size,
+            data: Mutex::new(data),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.lock().len() / self.block_size) as u64
+    }
+
+    fn read_block(&self, index: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        let start = index as usize * self.block_size;
+        let data = self.data.lock();
+        let block = data
+            .get(start..start + self.block_size)
+            .ok_or(FsError::NoSpace)?;
+        buffer.copy_from_slice(block);
+        Ok(())
+    }
+
+    fn write_block(&self, index: u64, data: &[u8]) -> Result<(), FsError> {
+        let start = index as usize * self.block_size;
+        let mut blocks = self.data.lock();
+        let block = blocks
+            .get_mut(start..start + self.block_size)
+            .ok_or(FsError::NoSpace)?;
+        block.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// The size of the file is determined when the device is created, the
+/// last block is padded with zeros
+pub struct LoopDevice {
+    inode: Arc<dyn Inode>,
+    block_count: u64,
+}
+
+impl LoopDevice {
+    pub fn new(inode: Arc<dyn Inode>) -> Result<Self, FsError> {
+        let size = inode.size()?;
+        Ok(Self {
+            inode,
+            block_count: size.div_ceil(BLOCK_SIZE) as u64,
+        })
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_block(&self, index: u64, buffer: &mut [u8]) -> Result<(), FsError> {
+        let start = index as usize * BLOCK_SIZE;
+        let mut done = 0;
+        while done < buffer.len() {
+            match self.inode.read_at(start + done, &mut buffer[done..])? {
+                0 => break,
+                count => done += count,
+            }
+        }
+        buffer[done..].fill(0);
+        Ok(())
+    }
+
+    fn write_block(&self, index: u64, data: &[u8]) -> Result<(), FsError> {
+        let start = index as usize * BLOCK_SIZE;
+        let mut done = 0;
+        while done < data.len() {
+            match self.inode.write_at(start + done, &data[done..])? {
+                0 => return Err(FsError::NoSpace),
+                count => done += count,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::{
+        fs::{
+            cpio::tests::archive,
+            initramfs::Initramfs,
+            vfs::{FileSystem, FsError},
+        },
+        io::device::BlockDevice,
+    };
+
+    use super::{LoopDevice, RamDisk, BLOCK_SIZE};
+
+    #[test_case]
+    fn ramdisk_keeps_written_blocks() {
+        let disk = RamDisk::new(4, 3);
+        assert_eq!(disk.block_count(), 3);
+
+        let mut block = [1; 4];
+        disk.read_block(2, &mut block).unwrap();
+        assert_eq!(block, [0; 4]);
+
+        disk.write_block(1, &[1, 2, 3, 4]).unwrap();
+        disk.read_block(1, &mut block).unwrap();
+        assert_eq!(block, [1, 2, 3, 4]);
+        assert_eq!(disk.read_block(3, &mut block), Err(FsError::NoSpace));
+        assert_eq!(disk.write_block(3, &block), Err(FsError::NoSpace));
+    }
+
+    #[test_case]
+    fn loop_device_pads_the_last_block() {
+        let content = vec![7; BLOCK_SIZE + 3];
+        let filesystem = Initramfs::from_archive(archive(&[("image", &content)]).leak());
+        let device = LoopDevice::new(filesystem.root().lookup("image").unwrap()).unwrap();
+        assert_eq!(device.block_count(), 2);
+
+        let mut block = [0; BLOCK_SIZE];
+        device.read_block(1, &mut block).unwrap();
+        assert_eq!(block[..3], [7; 3]);
+        assert!(block[3..].iter().all(|byte| *byte == 0));
+        assert_eq!(
+            device.write_block(0, &block),
+            Err(FsError::ReadOnly),
+            "The initramfs is read-only"
+        );
+    }
+}
@@ -1,58 +1,155 @@
 use crate::{
     cpu::Cpu,
-    processes::{process::Pid, process_table, timer},
+    io::{key_decoder::KeyDecoder, line_discipline::LineDiscipline, pty::PtySlave},
+    print,
+    processes::{poll, process_table::ProcessRef, timer, wait_queue::WaitQueue},
 };
-use alloc::collections::{BTreeSet, VecDeque};
-use common::mutex::Mutex;
+use alloc::{collections::VecDeque, vec::Vec};
+use common::{
+    errors::SysInputError,
+    mutex::SpinLockIrqSave,
+    tty::{InputMode, KeyEvent},
+};
+
+pub static STDIN_BUFFER: SpinLockIrqSave<StdinBuffer> = SpinLockIrqSave::new(StdinBuffer::new());
+
+/// Input typed on the console. The echo is printed after releasing
+/// the buffer.
+pub fn receive_console_input(byte: u8) {
+    let echo = STDIN_BUFFER.lock().receive(byte);
+    if !echo.is_empty() {
+        print!(
+            "{}",
+            core::str::from_utf8(&echo).expect("Echo must be ascii")
+        );
+    }
+}
 
-pub static STDIN_BUFFER: Mutex<StdinBuffer> = Mutex::new(StdinBuffer::new());
+/// The input of the terminal of a process, which is the console without
+/// a pty
+pub fn with_input<R>(terminal: Option<&PtySlave>, f: impl FnOnce(&mut StdinBuffer) -> R) -> R {
+    match terminal {
+        Some(pty) => f(pty.lock().input()),
+        None => f(&mut STDIN_BUFFER.lock()),
+    }
+}
 
 pub struct StdinBuffer {
     data: VecDeque<u8>,
-    wakeup_queue: BTreeSet<Pid>,
+    readers: WaitQueue<Result<u8, SysInputError>>,
+    key_event_readers: WaitQueue<Result<KeyEvent, SysInputError>>,
+    line_discipline: LineDiscipline,
+    key_decoder: KeyDecoder,
+    unread_key_event: Option<KeyEvent>,
+    // The master side of the pty was closed
+    hung_up: bool,
 }
 
 impl StdinBuffer {
-    const fn new() -> Self {
+    pub const fn new() -> Self {
         StdinBuffer {
             data: VecDeque::new(),
-            wakeup_queue: BTreeSet::new(),
+            readers: WaitQueue::new(),
+            key_event_readers: WaitQueue::new(),
+            line_discipline: LineDiscipline::new(),
+            key_decoder: KeyDecoder::new(),
+            unread_key_event: None,
+            hung_up: false,
         }
     }
 
-    pub fn register_wakeup(&mut self, pid: Pid) {
-        self.wakeup_queue.insert(pid);
+    pub fn set_mode(&mut self, mode: InputMode) {
+        self.line_discipline.set_mode(mode);
+    }
+
+    /// Runs the received byte through the line discipline and makes
+    /// the result available to readers. Returns what must be echoed.
+    pub fn receive(&mut self, byte: u8) -> Vec<u8> {
+        let mut echo = Vec::new();
+        let ready = self.line_discipline.process(byte, &mut echo);
+        for byte in ready.into_iter().flatten() {
+            self.push(byte);
+        }
+        echo
     }
 
-    pub fn push(&mut self, byte: u8) {
-        let notified = !self.wakeup_queue.is_empty();
-        process_table::THE.with_lock(|pt| {
-            for pid in &self.wakeup_queue {
-                if let Some(process) = pt.get_process(*pid) {
-                    process.with_lock(|mut p| {
-                        p.resume_on_syscall(byte);
-                    })
-                }
+    /// The process gets the next byte or TimedOut at the deadline
+    pub fn wait_for_input(&mut self, process: &ProcessRef, deadline: u64) {
+        self.readers
+            .wait_until(process, deadline, Err(SysInputError::TimedOut));
+    }
+
+    /// The process gets the next key event or TimedOut at the deadline
+    pub fn wait_for_key_event(&mut self, process: &ProcessRef, deadline: u64) {
+        self.key_event_readers
+            .wait_until(process, deadline, Err(SysInputError::TimedOut));
+    }
+
+    /// Nobody can type anymore. Waiting readers get HungUp and so do
+    /// all readers once the buffered input is consumed.
+    pub fn hang_up(&mut self) {
+        self.hung_up = true;
+        self.readers.wake_all(Err(SysInputError::HungUp));
+        self.key_event_readers.wake_all(Err(SysInputError::HungUp));
+        poll::notify();
+    }
+
+    pub fn is_hung_up(&self) -> bool {
+        self.hung_up
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.readers.wake_one(Ok(byte)) {
+            Self::run_woken_reader();
+            return;
+        }
+        self.data.push_back(byte);
+        poll::notify();
+        if !self.key_event_readers.has_waiters() {
+            return;
+        }
+        if let Some(event) = self.pop_key_event() {
+            if self.key_event_readers.wake_one(Ok(event)) {
+                Self::run_woken_reader();
+            } else {
+                // The reader timed out in the meantime
+                self.unread_key_event = Some(event);
             }
-        });
+        }
+    }
+
+    fn run_woken_reader() {
         Cpu::with_scheduler(|s| {
-            if notified && s.is_current_process_energy_saver() {
+            if s.is_current_process_energy_saver() {
                 s.schedule();
             }
         });
-        self.wakeup_queue.clear();
-        if notified {
-            if !Cpu::is_timer_enabled() {
-                // Enable timer because we were sleeping and waiting
-                // for input
-                timer::set_timer(0);
-            }
-            return;
+        if !Cpu::is_timer_enabled() {
+            // Enable timer because we were sleeping and waiting
+            // for input
+            timer::set_timer(0);
         }
-        self.data.push_back(byte);
+    }
+
+    pub fn has_data(&self) -> bool {
+        !self.data.is_empty()
     }
 
     pub fn pop(&mut self) -> Option<u8> {
         self.data.pop_front()
     }
+
+    /// Consumes buffered bytes until a complete key event is decoded.
+    /// An incomplete escape sequence stays in the decoder.
+    pub fn pop_key_event(&mut self) -> Option<KeyEvent> {
+        if let Some(event) = self.unread_key_event.take() {
+            return Some(event);
+        }
+        while let Some(byte) = self.data.pop_front() {
+            if let Some(event) = self.key_decoder.feed(byte) {
+                return Some(event);
+            }
+        }
+        None
+    }
 }
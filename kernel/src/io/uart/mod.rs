@@ -0,0 +1,346 @@
+//! The console UART. Its driver is chosen by the compatible property of
+//! the node the stdout-path of /chosen points to, without one the first
+//! enabled UART with a known compatible is used. The drivers only know
+//! their registers, queueing the output for the transmitter empty
+//! interrupt is shared.
+
+use alloc::vec::Vec;
+use core::{fmt::Write, sync::atomic::Ordering};
+
+use common::{mutex::SpinLockIrqSave, runtime_initialized::RuntimeInitializedData};
+
+use crate::{
+    device_tree::tree::DeviceTreeNode,
+    drivers::platform::{DeviceTreeDriver, ProbeError},
+    interrupts::plic,
+    io::console,
+};
+
+use self::{ns16550::Ns16550, sifive::SifiveUart};
+
+pub mod ns16550;
+pub mod sifive;
+
+/// Bytes which can be queued for interrupt driven output. If the buffer
+/// runs full we fall back to busy waiting on the transmitter.
+const TX_BUFFER_SIZE: usize = 16 * 1024;
+
+/// The registers of a UART
+pub trait UartHardware {
+    /// Configures 8 bit words and enables the receive interrupt
+    fn init(&mut self);
+    /// Bytes which can be transmitted at once when the transmitter is empty
+    fn fifo_size(&self) -> usize;
+    fn transmitter_empty(&self) -> bool;
+    fn transmit(&mut self, byte: u8);
+    fn receive(&mut self) -> Option<u8>;
+    fn set_transmitter_empty_interrupt(&mut self, enabled: bool);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hardware {
+    Ns16550(Ns16550),
+    Sifive(SifiveUart),
+}
+
+impl Hardware {
+    fn from_node(node: &DeviceTreeNode) -> Option<Self> {
+        let is = |compatible: &[&str]| node.compatible().any(|c| compatible.contains(&c));
+        if is(ns16550::COMPATIBLE) {
+            Ns16550::from_node(node).map(Self::Ns16550)
+        } else if is(sifive::COMPATIBLE) {
+            SifiveUart::from_node(node).map(Self::Sifive)
+        } else {
+            None
+        }
+    }
+
+    pub fn base_address(&self) -> usize {
+        match self {
+            Hardware::Ns16550(uart) => uart.base_address(),
+            Hardware::Sifive(uart) => uart.base_address(),
+        }
+    }
+
+    fn registers(&mut self) -> &mut dyn UartHardware {
+        match self {
+            Hardware::Ns16550(uart) => uart,
+            Hardware::Sifive(uart) => uart,
+        }
+    }
+}
+
+/// The UART which the firmware uses for its output
+pub fn find_console(root: &DeviceTreeNode) -> Option<Hardware> {
+    stdout_node(root).and_then(Hardware::from_node).or_else(|| {
+        root.walk()
+            .filter(|node| node.is_enabled())
+            .find_map(Hardware::from_node)
+    })
+}
+
+fn stdout_node<'t, 'a>(root: &'t DeviceTreeNode<'a>) -> Option<&'t DeviceTreeNode<'a>> {
+    let stdout_path = root.find("/chosen")?.property("stdout-path")?.str()?;
+    // The path might be followed by options, e.g. serial0:115200n8
+    let path = stdout_path.split(':').next()?;
+    if path.starts_with('/') {
+        return root.find(path);
+    }
+    let aliased = root.find("/aliases")?.property(path)?.str()?;
+    root.find(aliased)
+}
+
+// The UART is initialized before the drivers are probed.
+// Therefore, we can only verify that the node is the console.
+pub const NS16550_DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "ns16550",
+    compatible: ns16550::COMPATIBLE,
+    needs_mapping: true,
+    probe,
+};
+
+pub const SIFIVE_DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "sifive-uart",
+    compatible: sifive::COMPATIBLE,
+    needs_mapping: true,
+    probe,
+};
+
+static INTERRUPT: RuntimeInitializedData<u32> = RuntimeInitializedData::new();
+
+fn probe(node: &DeviceTreeNode) -> Result<(), ProbeError> {
+    let console = CONSOLE_UART.lock().hardware.map(|h| h.base_address());
+    let reg = node.first_reg().ok_or(ProbeError::MissingProperty("reg"))?;
+    match console {
+        Some(expected) if expected != reg.address => {
+            return Err(ProbeError::UnexpectedAddress {
+                expected,
+                found: reg.address,
+            })
+        }
+        Some(_) => {}
+        None => return Err(ProbeError::Unsupported("the console is not a uart")),
+    }
+    let interrupt = node
+        .interrupt()
+        .ok_or(ProbeError::MissingProperty("interrupts"))?;
+    INTERRUPT.initialize(interrupt);
+    Ok(())
+}
+
+pub static CONSOLE_UART: SpinLockIrqSave<Uart> = SpinLockIrqSave::new(Uart::new());
+
+unsafe impl Sync for Uart {}
+unsafe impl Send for Uart {}
+
+struct TxRing<const SIZE: usize> {
+    data: [u8; SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl<const SIZE: usize> TxRing<SIZE> {
+    const fn new() -> Self {
+        Self {
+            data: [0; SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == SIZE
+    }
+
+    fn push(&mut self, byte: u8) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        self.data[(self.head + self.len) % SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+pub struct Uart {
+    hardware: Option<Hardware>,
+    tx_buffer: TxRing<TX_BUFFER_SIZE>,
+    interrupt_driven: bool,
+}
+
+impl Uart {
+    const fn new() -> Self {
+        Self {
+            hardware: None,
+            tx_buffer: TxRing::new(),
+            interrupt_driven: false,
+        }
+    }
+
+    fn registers(&mut self) -> &mut dyn UartHardware {
+        self.hardware
+            .as_mut()
+            .expect("Uart must be initialized")
+            .registers()
+    }
+
+    pub fn init(&mut self, hardware: Hardware) {
+        self.hardware = Some(hardware);
+        self.registers().init();
+    }
+
+    /// Output is written through the earlycon until then
+    pub fn is_initialized(&self) -> bool {
+        self.hardware.is_some()
+    }
+
+    /// From now on output is queued and sent by the transmitter empty
+    /// interrupt. Must only be called once the PLIC routes the uart interrupt.
+    fn enable_interrupt_driven_output(&mut self) {
+        self.interrupt_driven = true;
+    }
+
+    /// Sends all queued bytes and writes synchronously afterwards.
+    /// Used on panic where we cannot rely on interrupts anymore.
+    pub fn switch_to_synchronous_output(&mut self) {
+        if !self.is_initialized() {
+            return;
+        }
+        self.interrupt_driven = false;
+        self.registers().set_transmitter_empty_interrupt(false);
+        while let Some(byte) = self.tx_buffer.pop() {
+            self.write_synchronous(byte);
+        }
+    }
+
+    fn write_synchronous(&mut self, character: u8) {
+        let registers = self.registers();
+        while !registers.transmitter_empty() {
+            core::hint::spin_loop();
+        }
+        registers.transmit(character);
+    }
+
+    fn write(&mut self, character: u8) {
+        if !self.interrupt_driven {
+            self.write_synchronous(character);
+            return;
+        }
+        if self.tx_buffer.is_full() {
+            // Make room by sending the oldest byte ourselves
+            let oldest = self.tx_buffer.pop().expect("Full buffer can't be empty");
+            self.write_synchronous(oldest);
+        }
+        assert!(self.tx_buffer.push(character));
+    }
+
+    /// Moves queued bytes into the transmit fifo if it is empty and
+    /// enables the transmitter empty interrupt as long as bytes are left.
+    fn fill_transmit_fifo(&mut self) {
+        if self.registers().transmitter_empty() {
+            for _ in 0..self.registers().fifo_size() {
+                let Some(byte) = self.tx_buffer.pop() else {
+                    break;
+                };
+                self.registers().transmit(byte);
+            }
+        }
+        let bytes_left = !self.tx_buffer.is_empty();
+        self.registers().set_transmitter_empty_interrupt(bytes_left);
+    }
+
+    /// Drains the receive fifo and refills the transmit fifo.
+    /// Returns the received bytes.
+    pub fn handle_interrupt(&mut self) -> Vec<u8> {
+        let mut received = Vec::new();
+        while let Some(byte) = self.registers().receive() {
+            received.push(byte);
+        }
+        if self.interrupt_driven {
+            self.fill_transmit_fifo();
+        }
+        received
+    }
+}
+
+impl Write for Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if !self.is_initialized() {
+            return Ok(());
+        }
+        for c in s.bytes() {
+            self.write(c);
+        }
+        if self.interrupt_driven {
+            self.fill_transmit_fifo();
+        }
+        Ok(())
+    }
+}
+
+/// Routes the interrupt of the UART to the given hart and queues the
+/// output from now on. Without a bound UART the output stays synchronous.
+pub fn init_interrupt(hart_id: usize) {
+    if !INTERRUPT.initialized().load(Ordering::SeqCst) {
+        return;
+    }
+    plic::register_handler(*INTERRUPT, hart_id, handle_interrupt);
+    CONSOLE_UART.lock().enable_interrupt_driven_output();
+}
+
+fn handle_interrupt(_interrupt_id: u32) {
+    // The interrupt is either for received data or for an empty transmitter
+    let input = CONSOLE_UART.lock().handle_interrupt();
+    console::receive_input(input);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_console, Hardware, TxRing};
+    use crate::device_tree::{tree::DeviceTreeNode, DeviceTree, Header};
+    use common::include_bytes_align_as;
+
+    const DTB: &[u8] = include_bytes_align_as!(Header, "../../test/test_data/dtb");
+
+    #[test_case]
+    fn console_is_found_through_stdout_path() {
+        let tree = DeviceTreeNode::parse(DeviceTree::new(DTB.as_ptr() as *const ()).root_node());
+        let console = find_console(&tree).expect("console must be found");
+        assert!(matches!(console, Hardware::Ns16550(_)));
+        assert_eq!(console.base_address(), 0x1000_0000);
+    }
+
+    #[test_case]
+    fn tx_ring_wraps_around() {
+        let mut ring = TxRing::<4>::new();
+        assert!(ring.is_empty());
+        for byte in 0..4 {
+            assert!(ring.push(byte));
+        }
+        assert!(ring.is_full());
+        assert!(!ring.push(4));
+        assert_eq!(ring.pop(), Some(0));
+        assert_eq!(ring.pop(), Some(1));
+        assert!(ring.push(4));
+        assert!(ring.push(5));
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), Some(3));
+        assert_eq!(ring.pop(), Some(4));
+        assert_eq!(ring.pop(), Some(5));
+        assert_eq!(ring.pop(), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! The 16550 compatible UART of QEMU and of many SoCs. The registers are
+//! bytes in QEMU, but SoCs like the JH7110 space them four bytes apart
+//! and only allow 32 bit accesses. The device tree describes both with
+//! reg-shift and reg-io-width.
+
+use crate::{device_tree::tree::DeviceTreeNode, klibc::MMIO};
+
+use super::UartHardware;
+
+pub const COMPATIBLE: &[&str] = &["ns16550a", "ns16550", "snps,dw-apb-uart"];
+
+const FIFO_SIZE: usize = 16;
+
+// Register indices
+const RBR_THR: usize = 0;
+const IER: usize = 1;
+const IIR_FCR: usize = 2;
+const LCR: usize = 3;
+const LSR: usize = 5;
+
+const IER_RECEIVED_DATA_AVAILABLE: u8 = 1 << 0;
+const IER_TRANSMITTER_EMPTY: u8 = 1 << 1;
+
+const LCR_EIGHT_BITS: u8 = 0b11;
+const LCR_DIVISOR_LATCH_ACCESS: u8 = 1 << 7;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TRANSMITTER_EMPTY: u8 = 1 << 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ns16550 {
+    base_address: usize,
+    reg_shift: u32,
+    reg_io_width: u32,
+    /// Only known if the device tree has the clock and the baud rate.
+    /// Otherwise the divisor of the firmware is kept.
+    divisor: Option<u16>,
+}
+
+impl Ns16550 {
+    pub fn from_node(node: &DeviceTreeNode) -> Option<Self> {
+        let u32_property = |name| node.property(name).and_then(|p| p.u32());
+        let divisor = match (
+            u32_property("clock-frequency"),
+            u32_property("current-speed"),
+        ) {
+            (Some(clock_hz), Some(baud)) if baud > 0 => {
+                // divisor = ceil(clock_hz / (baud x 16))
+                u16::try_from(clock_hz.div_ceil(baud * 16)).ok()
+            }
+            _ => None,
+        };
+        Some(Self {
+            base_address: node.first_reg()?.address,
+            reg_shift: u32_property("reg-shift").unwrap_or(0),
+            reg_io_width: u32_property("reg-io-width").unwrap_or(1),
+            divisor,
+        })
+    }
+
+    pub fn base_address(&self) -> usize {
+        self.base_address
+    }
+
+    fn read(&self, register: usize) -> u8 {
+        let address = self.base_address + (register << self.reg_shift);
+        // Only the lowest byte of the wider registers is used
+        match self.reg_io_width {
+            4 => MMIO::<u32>::new(address).read() as u8,
+            2 => MMIO::<u16>::new(address).read() as u8,
+            _ => MMIO::<u8>::new(address).read(),
+        }
+    }
+
+    fn write(&mut self, register: usize, value: u8) {
+        let address = self.base_address + (register << self.reg_shift);
+        match self.reg_io_width {
+            4 => MMIO::<u32>::new(address).write(value.into()),
+            2 => MMIO::<u16>::new(address).write(value.into()),
+            _ => MMIO::<u8>::new(address).write(value),
+        }
+    }
+}
+
+impl UartHardware for Ns16550 {
+    fn init(&mut self) {
+        // Set word length to 8 bit
+        self.write(LCR, LCR_EIGHT_BITS);
+        // Enable and clear both fifos
+        self.write(IIR_FCR, 0b111);
+        // Enable receiver buffer interrupts
+        self.write(IER, IER_RECEIVED_DATA_AVAILABLE);
+
+        if let Some(divisor) = self.divisor {
+            // The divisor latch (DLL and DLM) shares its address with
+            // RBR/THR and IER while the divisor latch access bit is set
+            self.write(LCR, LCR_EIGHT_BITS | LCR_DIVISOR_LATCH_ACCESS);
+            self.write(RBR_THR, (divisor & 0xff) as u8);
+            self.write(IER, (divisor >> 8) as u8);
+            self.write(LCR, LCR_EIGHT_BITS);
+        }
+    }
+
+    fn fifo_size(&self) -> usize {
+        FIFO_SIZE
+    }
+
+    fn transmitter_empty(&self) -> bool {
+        self.read(LSR) & LSR_TRANSMITTER_EMPTY != 0
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.write(RBR_THR, byte);
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        if self.read(LSR) & LSR_DATA_READY == 0 {
+            return None;
+        }
+        Some(self.read(RBR_THR))
+    }
+
+    fn set_transmitter_empty_interrupt(&mut self, enabled: bool) {
+        let ier = if enabled {
+            IER_RECEIVED_DATA_AVAILABLE | IER_TRANSMITTER_EMPTY
+        } else {
+            IER_RECEIVED_DATA_AVAILABLE
+        };
+        self.write(IER, ier);
+    }
+}
@@ -0,0 +1,96 @@
+//! The UART of SiFive cores, e.g. on the HiFive Unleashed and the
+//! sifive_u machine of QEMU. The baud rate divisor depends on the bus
+//! clock, therefore the divisor of the firmware is kept.
+
+use crate::{device_tree::tree::DeviceTreeNode, klibc::MMIO};
+
+use super::UartHardware;
+
+pub const COMPATIBLE: &[&str] = &["sifive,uart0"];
+
+const FIFO_SIZE: usize = 8;
+
+// Register offsets
+const TXDATA: usize = 0x00;
+const RXDATA: usize = 0x04;
+const TXCTRL: usize = 0x08;
+const RXCTRL: usize = 0x0c;
+const IE: usize = 0x10;
+const IP: usize = 0x14;
+
+const TXDATA_FULL: u32 = 1 << 31;
+const RXDATA_EMPTY: u32 = 1 << 31;
+
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_WATERMARK_SHIFT: u32 = 16;
+
+// The transmit watermark is pending while fewer bytes than the
+// watermark are queued, the receive watermark while more are queued.
+const TX_WATERMARK: u32 = 1;
+const RX_WATERMARK: u32 = 0;
+
+const IE_TXWM: u32 = 1 << 0;
+const IE_RXWM: u32 = 1 << 1;
+const IP_TXWM: u32 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SifiveUart {
+    base_address: usize,
+}
+
+impl SifiveUart {
+    pub fn from_node(node: &DeviceTreeNode) -> Option<Self> {
+        Some(Self {
+            base_address: node.first_reg()?.address,
+        })
+    }
+
+    pub fn base_address(&self) -> usize {
+        self.base_address
+    }
+
+    fn register(&self, offset: usize) -> MMIO<u32> {
+        MMIO::new(self.base_address + offset)
+    }
+}
+
+impl UartHardware for SifiveUart {
+    fn init(&mut self) {
+        self.register(TXCTRL)
+            .write(CTRL_ENABLE | (TX_WATERMARK << CTRL_WATERMARK_SHIFT));
+        self.register(RXCTRL)
+            .write(CTRL_ENABLE | (RX_WATERMARK << CTRL_WATERMARK_SHIFT));
+        self.register(IE).write(IE_RXWM);
+    }
+
+    fn fifo_size(&self) -> usize {
+        FIFO_SIZE
+    }
+
+    fn transmitter_empty(&self) -> bool {
+        // With a watermark of one this means that no byte is queued
+        self.register(IP).read() & IP_TXWM != 0
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        // Bytes written to a full fifo are dropped
+        while self.register(TXDATA).read() & TXDATA_FULL != 0 {
+            core::hint::spin_loop();
+        }
+        self.register(TXDATA).write(byte.into());
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        // Reading the register dequeues the byte
+        let rxdata = self.register(RXDATA).read();
+        if rxdata & RXDATA_EMPTY != 0 {
+            return None;
+        }
+        Some(rxdata as u8)
+    }
+
+    fn set_transmitter_empty_interrupt(&mut self, enabled: bool) {
+        let ie = if enabled { IE_RXWM | IE_TXWM } else { IE_RXWM };
+        self.register(IE).write(ie);
+    }
+}
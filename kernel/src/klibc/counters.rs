@@ -0,0 +1,176 @@
+//! Event counters which any module can declare as a static, e.g.
+//! `static PAGE_FAULTS: Counter = Counter::new("memory.page_faults");`.
+//! A counter registers itself when it is incremented for the first time,
+//! counters which never fired are therefore not listed. Names are made
+//! of the subsystem and the event, separated by a dot.
+//!
+//! All counters are listed in /proc/counters and by `sys_read_counters`.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use common::{array_vec::ArrayVec, mutex::SpinLockIrqSave};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+#[derive(Clone, Copy)]
+enum Registered {
+    Counter(&'static Counter),
+    Group(&'static CounterGroup),
+}
+
+const MAX_COUNTERS: usize = 64;
+
+// Counters are incremented in interrupt handlers and by the heap. The
+// registry therefore never allocates while it is locked.
+static REGISTRY: SpinLockIrqSave<ArrayVec<Registered, MAX_COUNTERS>> =
+    SpinLockIrqSave::new(ArrayVec::new());
+
+fn register(registered: &AtomicBool, entry: Registered) {
+    let mut registry = REGISTRY.lock();
+    if !registered.swap(true, Ordering::Relaxed) {
+        assert!(
+            registry.push(entry).is_ok(),
+            "Too many counters, increase MAX_COUNTERS"
+        );
+    }
+}
+
+pub struct Counter {
+    name: &'static str,
+    value: AtomicU64,
+    registered: AtomicBool,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn increment(&'static self) {
+        self.add(1);
+    }
+
+    pub fn add(&'static self, amount: u64) {
+        self.value.fetch_add(amount, Ordering::Relaxed);
+        if !self.registered.load(Ordering::Relaxed) {
+            register(&self.registered, Registered::Counter(self));
+        }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Counters of the same event for different sources, e.g. one per
+/// interrupt. They are listed as `name.key`.
+pub struct CounterGroup {
+    name: &'static str,
+    values: SpinLockIrqSave<BTreeMap<u64, u64>>,
+    registered: AtomicBool,
+}
+
+impl CounterGroup {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            values: SpinLockIrqSave::new(BTreeMap::new()),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn increment(&'static self, key: u64) {
+        *self.values.lock().entry(key).or_default() += 1;
+        if !self.registered.load(Ordering::Relaxed) {
+            register(&self.registered, Registered::Group(self));
+        }
+    }
+
+    /// Keys which never fired are left out
+    pub fn values(&self) -> BTreeMap<u64, u64> {
+        self.values.lock().clone()
+    }
+}
+
+/// All registered counters sorted by name
+pub fn snapshot() -> Vec<(String, u64)> {
+    let registry = REGISTRY.with_lock(|registry| {
+        let mut copy = ArrayVec::<Registered, MAX_COUNTERS>::new();
+        for entry in registry.iter() {
+            let _ = copy.push(*entry);
+        }
+        copy
+    });
+    let mut counters = Vec::new();
+    for entry in registry.iter() {
+        match entry {
+            Registered::Counter(counter) => counters.push((counter.name.into(), counter.get())),
+            Registered::Group(group) => counters.extend(
+                group
+                    .values()
+                    .into_iter()
+                    .map(|(key, value)| (format!("{}.{key}", group.name), value)),
+            ),
+        }
+    }
+    counters.sort();
+    counters
+}
+
+/// One counter per line, the name and its value separated by a tab
+pub fn listing() -> String {
+    snapshot()
+        .into_iter()
+        .fold(String::new(), |mut listing, (name, value)| {
+            let _ = writeln!(listing, "{name}\t{value}");
+            listing
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{listing, snapshot, Counter, CounterGroup};
+
+    static TEST_COUNTER: Counter = Counter::new("test.counter");
+    static TEST_GROUP: CounterGroup = CounterGroup::new("test.group");
+
+    fn value_of(name: &str) -> Option<u64> {
+        snapshot()
+            .into_iter()
+            .find(|(counter, _)| counter == name)
+            .map(|(_, value)| value)
+    }
+
+    #[test_case]
+    fn counters_are_registered_on_first_use() {
+        assert_eq!(value_of("test.counter"), None);
+        TEST_COUNTER.increment();
+        TEST_COUNTER.add(2);
+        assert_eq!(value_of("test.counter"), Some(3));
+        TEST_COUNTER.increment();
+        assert_eq!(TEST_COUNTER.get(), 4);
+        assert_eq!(
+            snapshot()
+                .iter()
+                .filter(|(name, _)| name == "test.counter")
+                .count(),
+            1,
+            "Counters are registered once"
+        );
+
+        TEST_GROUP.increment(10);
+        TEST_GROUP.increment(10);
+        TEST_GROUP.increment(3);
+        assert_eq!(value_of("test.group.10"), Some(2));
+        assert_eq!(value_of("test.group.3"), Some(1));
+        assert!(listing().contains("test.group.10\t2\n"));
+
+        let names: alloc::vec::Vec<_> = snapshot().into_iter().map(|(name, _)| name).collect();
+        assert!(names.is_sorted(), "Counters are sorted by name");
+    }
+}
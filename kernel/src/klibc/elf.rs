@@ -220,6 +220,72 @@ pub struct ElfProgramHeaderEntry {
 
 static_assert_size!(ElfProgramHeaderEntry, 0x38);
 
+const SECTION_TYPE_SYMBOL_TABLE: u32 = 2;
+const SYMBOL_TYPE_FUNCTION: u8 = 2;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct ElfSectionHeaderEntry {
+    pub name: u32,
+    pub section_type: u32,
+    pub flags: u64,
+    pub virtual_address: u64,
+    pub offset_in_file: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub alignment: u64,
+    pub entry_size: u64,
+}
+
+static_assert_size!(ElfSectionHeaderEntry, 0x40);
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct ElfSymbol {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub section_index: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+static_assert_size!(ElfSymbol, 0x18);
+
+impl ElfSymbol {
+    fn is_function(&self) -> bool {
+        self.info & 0xf == SYMBOL_TYPE_FUNCTION
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        let start = self.value as usize;
+        (start..start + self.size as usize).contains(&address)
+    }
+}
+
+pub struct SymbolTable<'a> {
+    symbols: &'a [ElfSymbol],
+    names: &'a [u8],
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Returns the (mangled) name of the function which contains the
+    /// address and the offset of the address into the function
+    pub fn find_function(&self, address: usize) -> Option<(&'a str, usize)> {
+        let symbol = self
+            .symbols
+            .iter()
+            .find(|symbol| symbol.is_function() && symbol.contains(address))?;
+        let name = self.names.get(symbol.name as usize..)?;
+        let name = core::ffi::CStr::from_bytes_until_nul(name)
+            .ok()?
+            .to_str()
+            .ok()?;
+        Some((name, address - symbol.value as usize))
+    }
+}
+
 #[derive(Debug)]
 pub enum ElfParseErrors {
     FileTooShort,
@@ -291,6 +357,71 @@ impl<'a> ElfFile<'a> {
         &self.data[start..start + size]
     }
 
+    pub fn get_section_headers(&self) -> &[ElfSectionHeaderEntry] {
+        let header = self.get_header();
+        let number_of_entries = header.number_of_entries_section_header as usize;
+        let position_section_header = header.start_of_section_header as usize;
+
+        if number_of_entries == 0 {
+            return &[];
+        }
+
+        assert_eq!(
+            header.size_section_header_entry as usize,
+            core::mem::size_of::<ElfSectionHeaderEntry>()
+        );
+        assert!(
+            position_section_header
+                + core::mem::size_of::<ElfSectionHeaderEntry>() * number_of_entries
+                <= self.data.len()
+        );
+
+        unsafe {
+            let section_header_pointer = self.data.as_ptr().byte_add(position_section_header)
+                as *const ElfSectionHeaderEntry;
+            core::slice::from_raw_parts(section_header_pointer, number_of_entries)
+        }
+    }
+
+    pub fn get_section_data(&self, section_header: &ElfSectionHeaderEntry) -> &'a [u8] {
+        let start = section_header.offset_in_file as usize;
+        let size = section_header.size as usize;
+        let data: &'a [u8] = self.data;
+
+        &data[start..start + size]
+    }
+
+    /// Returns None if the file is stripped
+    pub fn get_symbol_table(&self) -> Option<SymbolTable<'a>> {
+        let section_headers = self.get_section_headers();
+        let symbol_section = section_headers
+            .iter()
+            .find(|header| header.section_type == SECTION_TYPE_SYMBOL_TABLE)?;
+        let names_section = section_headers.get(symbol_section.link as usize)?;
+
+        assert_eq!(
+            symbol_section.entry_size as usize,
+            core::mem::size_of::<ElfSymbol>()
+        );
+        let data = self.get_section_data(symbol_section);
+        assert_eq!(
+            data.as_ptr() as usize % core::mem::align_of::<ElfSymbol>(),
+            0,
+            "Symbol table must be aligned"
+        );
+        let symbols = unsafe {
+            core::slice::from_raw_parts(
+                data.as_ptr() as *const ElfSymbol,
+                data.len() / core::mem::size_of::<ElfSymbol>(),
+            )
+        };
+
+        Some(SymbolTable {
+            symbols,
+            names: self.get_section_data(names_section),
+        })
+    }
+
     fn check_validity(data: &[u8]) -> Option<ElfParseErrors> {
         if data.len() < core::mem::size_of::<ElfHeader>() {
             return Some(ElfParseErrors::FileTooShort);
@@ -423,4 +554,15 @@ mod tests {
 
         // The fourth element is GNU_STACK. We don't need it, therefore we don't check for it here.
     }
+
+    #[test_case]
+    fn find_function_in_symbol_table() {
+        let elf = ElfFile::parse(TEST_ELF_FILE).expect("Elf file must be parsable");
+        let symbol_table = elf.get_symbol_table().expect("Symbol table must exist");
+
+        assert_eq!(symbol_table.find_function(0x1000), Some(("main", 0)));
+        assert_eq!(symbol_table.find_function(0x1010), Some(("main", 0x10)));
+        assert_eq!(symbol_table.find_function(0x1040), Some(("_start", 4)));
+        assert_eq!(symbol_table.find_function(0x0), None);
+    }
 }
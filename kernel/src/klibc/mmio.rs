@@ -127,7 +127,7 @@ mod tests {
         ptr::{addr_of, addr_of_mut},
     };
 
-    use crate::io::uart::QEMU_UART;
+    use crate::io::uart::CONSOLE_UART;
 
     use super::*;
 
@@ -180,7 +180,7 @@ mod tests {
         let mut value = get_test_data();
 
         unsafe {
-            QEMU_UART.disarm();
+            CONSOLE_UART.disarm();
         }
 
         crate::println!("value at {:p}", &value);
@@ -1,5 +1,7 @@
+pub mod counters;
 pub mod elf;
 pub mod mmio;
+pub mod rcu;
 pub mod sizes;
 pub mod util;
 
@@ -0,0 +1,81 @@
+use alloc::sync::Arc;
+use common::mutex::{Mutex, RwLock};
+
+/// Read-copy-update cell for read-mostly data.
+///
+/// Readers get a reference counted snapshot and can work on it without
+/// holding any lock. Writers copy the current value, modify the copy
+/// and publish it. The old value is freed as soon as the last reader
+/// drops its snapshot (the reference count is our grace period).
+/// Readers may therefore see slightly outdated data.
+pub struct Rcu<T> {
+    current: RwLock<Arc<T>>,
+    // Serializes writers so no update gets lost
+    writer: Mutex<()>,
+}
+
+impl<T: Clone> Rcu<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(data)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The read lock is only held while cloning the Arc, so readers
+    /// never wait for a writer which is busy copying.
+    pub fn read(&self) -> Arc<T> {
+        self.current.read().clone()
+    }
+
+    /// Fails if a writer publishes a new value at the moment
+    pub fn try_read(&self) -> Option<Arc<T>> {
+        self.current.try_read().map(|current| current.clone())
+    }
+
+    /// True while some hart is within `update`
+    pub fn is_being_updated(&self) -> bool {
+        self.writer
+            .get_locked()
+            .load(core::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _writer = self.writer.lock();
+        let mut copy = T::clone(&self.read());
+        let result = f(&mut copy);
+        *self.current.write() = Arc::new(copy);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rcu;
+
+    #[test_case]
+    fn readers_keep_their_snapshot() {
+        let rcu = Rcu::new(vec![1, 2]);
+        let snapshot = rcu.read();
+        rcu.update(|v| v.push(3));
+        assert_eq!(*snapshot, [1, 2]);
+        assert_eq!(*rcu.read(), [1, 2, 3]);
+    }
+
+    #[test_case]
+    fn try_read_fails_while_a_value_is_published() {
+        let rcu = Rcu::new(42);
+        let current = rcu.current.write();
+        assert!(rcu.try_read().is_none());
+        drop(current);
+        assert_eq!(rcu.try_read().as_deref(), Some(&42));
+    }
+
+    #[test_case]
+    fn update_returns_value() {
+        let rcu = Rcu::new(42);
+        let old = rcu.update(|v| core::mem::replace(v, 45));
+        assert_eq!(old, 42);
+        assert_eq!(*rcu.read(), 45);
+    }
+}
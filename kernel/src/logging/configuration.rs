@@ -1,3 +1,36 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Warnings are always logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Warn,
+    Info,
+    /// Debug messages of all modules except the excluded ones
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+}
+
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn should_log(level: LogLevel) -> bool {
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
 // This variable contains the list of modules that should be logged. If "kernel" is specified, every module is logged.
 const LOG_FOLLOWING_MODULES: &[&str] = &[];
 const DONT_LOG_FOLLOWING_MODULES: &[&str] = &[
@@ -13,6 +46,9 @@ pub fn should_log_module(module_name: &str) -> bool {
             return false;
         }
     }
+    if should_log(LogLevel::Debug) {
+        return true;
+    }
     for &log_module in LOG_FOLLOWING_MODULES {
         if module_name.starts_with(log_module) {
             return true;
@@ -0,0 +1,71 @@
+use common::mutex::SpinLockIrqSave;
+
+pub const LOG_TAIL_SIZE: usize = 4096;
+
+/// The most recent console output. Used by the crash dump.
+pub static LOG_TAIL: SpinLockIrqSave<LogTail<LOG_TAIL_SIZE>> = SpinLockIrqSave::new(LogTail::new());
+
+/// Keeps the last `SIZE` bytes written to it
+pub struct LogTail<const SIZE: usize> {
+    data: [u8; SIZE],
+    // Position of the next write
+    position: usize,
+    wrapped: bool,
+}
+
+impl<const SIZE: usize> LogTail<SIZE> {
+    const fn new() -> Self {
+        Self {
+            data: [0; SIZE],
+            position: 0,
+            wrapped: false,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.data[self.position] = byte;
+            self.position += 1;
+            if self.position == SIZE {
+                self.position = 0;
+                self.wrapped = true;
+            }
+        }
+    }
+
+    /// Copies the content in chronological order into `buffer` and
+    /// returns the number of copied bytes.
+    pub fn copy_to(&self, buffer: &mut [u8; SIZE]) -> usize {
+        if !self.wrapped {
+            buffer[..self.position].copy_from_slice(&self.data[..self.position]);
+            return self.position;
+        }
+        let (newer, older) = self.data.split_at(self.position);
+        buffer[..older.len()].copy_from_slice(older);
+        buffer[older.len()..].copy_from_slice(newer);
+        SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogTail;
+
+    #[test_case]
+    fn keeps_the_last_bytes() {
+        let mut tail = LogTail::<4>::new();
+        let mut buffer = [0; 4];
+
+        tail.write(b"ab");
+        assert_eq!(tail.copy_to(&mut buffer), 2);
+        assert_eq!(&buffer[..2], b"ab");
+
+        tail.write(b"cdef");
+        assert_eq!(tail.copy_to(&mut buffer), 4);
+        assert_eq!(&buffer, b"cdef");
+
+        tail.write(b"g");
+        tail.copy_to(&mut buffer);
+        assert_eq!(&buffer, b"defg");
+    }
+}
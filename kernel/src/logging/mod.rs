@@ -1,11 +1,14 @@
 use core::fmt;
 
 pub mod configuration;
+pub mod log_tail;
 
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => {
-        $crate::println!("[CPU {}][info][{}] {}", $crate::Cpu::cpu_id(), module_path!(), format_args!($($arg)*));
+        if $crate::logging::configuration::should_log($crate::logging::configuration::LogLevel::Info) {
+            $crate::println!("[CPU {}][info][{}] {}", $crate::Cpu::cpu_id(), module_path!(), format_args!($($arg)*));
+        }
     };
 }
 
@@ -50,6 +53,39 @@ pub fn _print(args: fmt::Arguments) {
     {
         use crate::io::uart;
         use core::fmt::Write;
-        uart::QEMU_UART.lock().write_fmt(args).unwrap();
+        Console {
+            uart: &mut uart::CONSOLE_UART.lock(),
+        }
+        .write_fmt(args)
+        .unwrap();
+    }
+}
+
+/// Writes to the console and remembers the output for the crash dump.
+/// Until the uart is initialized the earlycon is used. The uart lock
+/// serializes the output of every backend.
+#[cfg(not(miri))]
+struct Console<'a> {
+    uart: &'a mut crate::io::uart::Uart,
+}
+
+#[cfg(not(miri))]
+impl fmt::Write for Console<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        use crate::io::{console, earlycon};
+
+        // A hart which was stopped by a panic might hold the lock
+        if let Some(mut log_tail) = log_tail::LOG_TAIL.try_lock() {
+            log_tail.write(s.as_bytes());
+        }
+        if console::uses_sbi() {
+            console::write_sbi(s);
+            return Ok(());
+        }
+        if !self.uart.is_initialized() {
+            earlycon::write_str(s);
+            return Ok(());
+        }
+        self.uart.write_str(s)
     }
 }
@@ -5,6 +5,11 @@
 #![cfg_attr(miri, allow(unused_macros))]
 #![cfg_attr(test, allow(dead_code))]
 #![cfg_attr(test, allow(unused_imports))]
+// Drivers are shared by subsystems which can be left out
+#![cfg_attr(
+    not(any(test, all(feature = "net", feature = "hostfs"))),
+    allow(dead_code)
+)]
 #![feature(nonzero_ops)]
 #![feature(custom_test_frameworks)]
 #![feature(let_chains)]
@@ -20,33 +25,40 @@
 #![test_runner(test::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
-use crate::{
-    interrupts::plic, io::uart::QEMU_UART, memory::page_tables, pci::enumerate_devices,
-    processes::timer,
-};
+use crate::{interrupts::plic, memory::page_tables, processes::timer};
 use alloc::vec::Vec;
 use asm::wfi_loop;
 use cpu::Cpu;
-use debugging::{backtrace, symbols};
-use device_tree::get_devicetree_range;
+use debugging::{boot_timing, symbols};
+use device_tree::{get_devicetree_range, ReserveEntry};
+use initcall::Level;
 use memory::page_tables::MappingDescription;
 use processes::process_table;
 
 mod asm;
 mod assert;
 mod autogenerated;
+#[cfg(feature = "benchmark")]
+mod benchmark;
+mod cmdline;
+mod config;
 mod cpu;
 mod debugging;
 mod device_tree;
 mod drivers;
+mod fs;
+mod initcall;
 mod interrupts;
 mod io;
 mod klibc;
 mod logging;
 mod memory;
+#[cfg(feature = "net")]
 mod net;
 mod panic;
 mod pci;
+mod per_cpu;
+mod power;
 mod processes;
 mod sbi;
 mod syscalls;
@@ -59,9 +71,9 @@ extern crate alloc;
 #[unsafe(no_mangle)]
 extern "C" fn kernel_init(hart_id: usize, device_tree_pointer: *const ()) -> ! {
     cpu::STARTING_CPU_ID.initialize(hart_id);
+    boot_timing::start();
 
-    QEMU_UART.lock().init();
-
+    // Printed through the earlycon until the uart is initialized
     info!("Hello World from SentientOS!\n");
     info!("Device Tree Pointer: {:p}", device_tree_pointer);
 
@@ -72,98 +84,200 @@ extern "C" fn kernel_init(hart_id: usize, device_tree_pointer: *const ()) -> ! {
         "Supported SBI Versions >= 0.2"
     );
 
-    let num_cpus = sbi::extensions::hart_state_extension::get_number_of_harts();
-    info!("Number of Cores: {num_cpus}");
+    config::log();
 
     symbols::init();
+    boot_timing::stage_done("early");
     device_tree::init(device_tree_pointer);
-    let device_tree_range = get_devicetree_range();
-
-    memory::init_page_allocator(&[device_tree_range]);
-
-    backtrace::init();
-    processes::timer::init();
+    cmdline::init();
+
+    // There is no heap yet
+    let reserved_areas = [Some(get_devicetree_range()), fs::initramfs::initrd_range()]
+        .into_iter()
+        .flatten()
+        .chain(
+            device_tree::THE
+                .get_reserved_areas()
+                .iter()
+                .map(ReserveEntry::range),
+        );
+    memory::init_page_allocator(reserved_areas);
+    boot_timing::stage_done("page allocator");
+
+    device_tree::tree::init();
+    io::earlycon::switch_to_uart();
+    let harts = processes::hotplug::usable_harts(device_tree::tree::root());
+    let num_cpus = harts.iter().max().map_or(1, |hart_id| hart_id + 1);
+    info!("Usable harts: {harts:?}");
+    boot_timing::stage_done("device tree");
+    initcall::run(Level::Core);
+    initcall::run(Level::Postcore);
+    drivers::platform::probe_all();
+    processes::vdso::init(num_cpus);
+    boot_timing::stage_done("platform");
+    initcall::run(Level::Fs);
+    boot_timing::stage_done("filesystems");
 
     #[cfg(test)]
     test_main();
 
-    let pci_information = pci::parse().expect("pci information must be parsable");
-
-    {
-        let pci_space_64_bit = pci_information
-            .get_first_range_for_type(pci::PCIBitField::MEMORY_SPACE_64_BIT_CODE)
-            .expect("There must be a 64 bit allocation space.");
-        let mut pci_allocator = pci::PCI_ALLOCATOR_64_BIT.lock();
-        pci_allocator.init(pci_space_64_bit);
-    }
+    let pci_information = pci::parse();
 
     let mut runtime_mapping = Vec::new();
 
-    runtime_mapping.push(MappingDescription {
-        virtual_address_start: pci_information.pci_host_bridge_address,
-        size: pci_information.pci_host_bridge_length,
-        privileges: page_tables::XWRMode::ReadWrite,
-        name: "PCI Space",
-    });
+    if let Some(pci_information) = &pci_information {
+        pci::init_allocators(pci_information);
 
-    for range in &pci_information.ranges {
         runtime_mapping.push(MappingDescription {
-            virtual_address_start: range.cpu_address,
-            size: range.size,
+            virtual_address_start: pci_information.pci_host_bridge_address,
+            size: pci_information.pci_host_bridge_length,
             privileges: page_tables::XWRMode::ReadWrite,
-            name: "PCI Range",
+            name: "PCI Space",
         });
+
+        for range in &pci_information.ranges {
+            runtime_mapping.push(MappingDescription {
+                virtual_address_start: range.cpu_address,
+                size: range.size,
+                privileges: page_tables::XWRMode::ReadWrite,
+                name: "PCI Range",
+            });
+        }
+    } else {
+        info!("No PCI host bridge found");
     }
 
+    runtime_mapping.extend(drivers::platform::runtime_mappings());
+
     memory::initialize_runtime_mappings(&runtime_mapping);
+    boot_timing::stage_done("pci");
 
     process_table::init();
+    initcall::run(Level::Late);
+    boot_timing::stage_done("processes");
+    #[cfg(feature = "benchmark")]
+    benchmark::start();
 
-    Cpu::write_sscratch(Cpu::init(hart_id) as usize);
+    let cpu_struct = Cpu::init(hart_id);
+    processes::hotplug::register_cpu_struct(hart_id, cpu_struct);
+    Cpu::write_sscratch(cpu_struct as usize);
 
     Cpu::current().activate_kernel_page_table();
 
-    plic::init_uart_interrupt(hart_id);
+    if let Some(now) = drivers::rtc::now_nanoseconds() {
+        info!("RTC time: {} s since epoch", now / 1_000_000_000);
+    }
 
-    let mut pci_devices = enumerate_devices(&pci_information);
+    plic::init_hart(hart_id);
+    // The firmware reads the uart itself if it is the console
+    if !io::console::uses_sbi() {
+        io::uart::init_interrupt(hart_id);
+    }
 
-    if let Some(network_device) = pci_devices.network_devices.pop() {
-        let network_device = drivers::virtio::net::NetworkDevice::initialize(network_device)
-            .expect("Initialization must work.");
+    let mut mmio_devices = drivers::virtio::mmio::discover_devices();
+
+    #[cfg(feature = "net")]
+    init_network(pci_information.as_ref(), &mut mmio_devices, num_cpus);
 
-        net::assign_network_device(network_device);
+    if let Some(index) = mmio_devices
+        .iter()
+        .position(|d| d.device_id() == drivers::virtio::console::VIRTIO_DEVICE_ID_CONSOLE)
+    {
+        let console =
+            drivers::virtio::console::ConsoleDevice::initialize(mmio_devices.swap_remove(index))
+                .expect("Initialization must work.");
+        debugging::gdb_stub::init(console);
     }
 
+    #[cfg(feature = "hostfs")]
+    while let Some(index) = mmio_devices
+        .iter()
+        .position(|d| d.device_id() == drivers::virtio::p9::VIRTIO_DEVICE_ID_9P)
+    {
+        let device = drivers::virtio::p9::P9Device::initialize(mmio_devices.swap_remove(index))
+            .expect("Initialization must work.");
+        fs::hostfs::mount(device);
+    }
+
+    boot_timing::stage_done("drivers");
+
     info!("kernel_init done! Starting other harts");
 
-    start_other_harts(hart_id, num_cpus);
+    start_other_harts(hart_id, &harts);
+    boot_timing::stage_done("harts");
 
     prepare_for_scheduling();
 }
 
 #[no_mangle]
 pub extern "C" fn prepare_for_scheduling() -> ! {
+    processes::hotplug::mark_online();
+
     // Enable all interrupts
     Cpu::write_sie(usize::MAX);
 
+    Cpu::enable_userspace_counters();
+
+    processes::floating_point::disable();
+    processes::vector::disable();
+
     // Enable global interrupts
     Cpu::csrs_sstatus(0b10);
 
+    boot_timing::finish();
+
     timer::set_timer(0);
 
     wfi_loop();
 }
 
-fn start_other_harts(current_hart_id: usize, number_of_cpus: usize) {
+/// Network cards on the PCI bus are preferred over virtio-mmio ones
+#[cfg(feature = "net")]
+fn init_network(
+    pci_information: Option<&pci::PCIInformation>,
+    mmio_devices: &mut Vec<drivers::virtio::mmio::MmioTransport>,
+    num_cpus: usize,
+) {
+    let pci_network_device = pci_information.and_then(|pci_information| {
+        pci::enumerate_devices(pci_information)
+            .network_devices
+            .pop()
+    });
+
+    let network_device = if let Some(network_device) = pci_network_device {
+        let transport = drivers::virtio::pci::PciTransport::new(network_device)
+            .expect("PCI transport must be initializable.");
+        Some(drivers::virtio::net::NetworkDevice::initialize(
+            transport, num_cpus,
+        ))
+    } else {
+        mmio_devices
+            .iter()
+            .position(|d| d.device_id() == drivers::virtio::net::VIRTIO_DEVICE_ID_NET)
+            .map(|index| {
+                drivers::virtio::net::NetworkDevice::initialize(
+                    mmio_devices.swap_remove(index),
+                    num_cpus,
+                )
+            })
+    };
+
+    if let Some(network_device) = network_device {
+        net::assign_network_device(network_device.expect("Initialization must work."));
+    }
+}
+
+fn start_other_harts(current_hart_id: usize, harts: &[usize]) {
     extern "C" {
         fn start_hart();
     }
-    for cpu_id in 0..number_of_cpus {
+    for &cpu_id in harts {
         if cpu_id == current_hart_id {
             continue;
         }
 
         let cpu_struct = Cpu::init(cpu_id);
+        processes::hotplug::register_cpu_struct(cpu_id, cpu_struct);
         sbi::extensions::hart_state_extension::start_hart(
             cpu_id,
             start_hart as usize,
@@ -0,0 +1,163 @@
+//! Buffers which devices access directly. They are physically contiguous
+//! and start and end on a cache line such that no other data shares a
+//! cache line with them. Devices only ever get the address from
+//! [`DmaBuffer::physical_address`].
+
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error};
+use core::{
+    alloc::Layout,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::page_owner::{self, Subsystem};
+
+pub const CACHE_LINE_SIZE: usize = 64;
+
+// Allocated bytes of all live buffers
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BUFFERS: AtomicUsize = AtomicUsize::new(0);
+
+/// A zeroed buffer, freed on drop. It can be truncated to the part
+/// the device wrote and be reset to its full size before it is reused.
+pub struct DmaBuffer {
+    ptr: NonNull<u8>,
+    length: usize,
+    size: usize,
+}
+
+// SAFETY: The buffer is owned like a Vec<u8>
+unsafe impl Send for DmaBuffer {}
+// SAFETY: Shared references only allow reads
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    pub fn new(size: usize) -> Self {
+        let layout = Self::layout(size);
+        // SAFETY: The layout has a non zero size
+        let ptr = page_owner::charge_to(Subsystem::Dma, || unsafe { alloc_zeroed(layout) });
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| handle_alloc_error(layout));
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        LIVE_BUFFERS.fetch_add(1, Ordering::Relaxed);
+        Self {
+            ptr,
+            length: size,
+            size,
+        }
+    }
+
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut buffer = Self::new(data.len());
+        buffer.copy_from_slice(data);
+        buffer
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(
+            size.max(1).next_multiple_of(CACHE_LINE_SIZE),
+            CACHE_LINE_SIZE,
+        )
+        .expect("Buffer size must not overflow")
+    }
+
+    /// The kernel heap is identity mapped, the pointer is the physical
+    /// address. Heap allocations never span discontiguous pages.
+    pub fn physical_address(&self) -> u64 {
+        self.ptr.as_ptr() as u64
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn truncate(&mut self, length: usize) {
+        self.length = self.length.min(length);
+    }
+
+    /// Makes the whole buffer usable again
+    pub fn reset_length(&mut self) {
+        self.length = self.size;
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The first `length` bytes are allocated and initialized
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.length) }
+    }
+}
+
+impl DerefMut for DmaBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: The first `length` bytes are allocated and initialized
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.length) }
+    }
+}
+
+impl core::fmt::Debug for DmaBuffer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DmaBuffer")
+            .field(
+                "physical_address",
+                &format_args!("{:#x}", self.physical_address()),
+            )
+            .field("length", &self.length)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        let layout = Self::layout(self.size);
+        // SAFETY: Allocated in new with the same layout
+        unsafe { dealloc(self.ptr.as_ptr(), layout) };
+        ALLOCATED_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        LIVE_BUFFERS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Bytes allocated for buffers which are alive
+pub fn allocated_bytes() -> usize {
+    ALLOCATED_BYTES.load(Ordering::Relaxed)
+}
+
+pub fn live_buffers() -> usize {
+    LIVE_BUFFERS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{live_buffers, DmaBuffer, CACHE_LINE_SIZE};
+
+    #[test_case]
+    fn buffers_are_aligned_to_cache_lines() {
+        let buffers = [
+            DmaBuffer::new(1),
+            DmaBuffer::new(CACHE_LINE_SIZE + 1),
+            DmaBuffer::new(3 * 4096),
+        ];
+        for buffer in &buffers {
+            assert_eq!(buffer.physical_address() % CACHE_LINE_SIZE as u64, 0);
+            assert!(buffer.iter().all(|&byte| byte == 0));
+        }
+        assert_eq!(buffers[1].len(), CACHE_LINE_SIZE + 1);
+    }
+
+    #[test_case]
+    fn buffers_can_be_truncated_and_reset() {
+        let mut buffer = DmaBuffer::from_slice(b"hello");
+        assert!(live_buffers() > 0);
+
+        buffer.truncate(2);
+        assert_eq!(&*buffer, b"he");
+        // Never grows
+        buffer.truncate(4);
+        assert_eq!(buffer.len(), 2);
+        buffer.reset_length();
+        assert_eq!(&*buffer, b"hello");
+    }
+}
@@ -5,11 +5,18 @@ use core::{
     ptr::{null_mut, NonNull},
 };
 
-use common::{mutex::Mutex, util::align_up};
+use common::{mutex::SpinLockIrqSave, util::align_up};
 
-use crate::{assert::static_assert_size, klibc::util::minimum_amount_of_pages};
+use crate::{
+    assert::static_assert_size,
+    klibc::{counters::Counter, util::minimum_amount_of_pages},
+};
 
-use super::{page_allocator::PageAllocator, PAGE_SIZE};
+use super::{
+    page_allocator::PageAllocator,
+    page_owner::{self, Subsystem},
+    PAGE_SIZE,
+};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
@@ -152,7 +159,8 @@ impl<Allocator: PageAllocator> Heap<Allocator> {
             block
         } else {
             let pages = minimum_amount_of_pages(requested_size.total_size());
-            let allocation = if let Some(allocation) = Allocator::alloc(pages) {
+            let allocation = page_owner::charge_to(Subsystem::Heap, || Allocator::alloc(pages));
+            let allocation = if let Some(allocation) = allocation {
                 allocation
             } else {
                 return null_mut();
@@ -233,8 +241,13 @@ impl<Allocator: PageAllocator> Heap<Allocator> {
     }
 }
 
+// Counted after the heap is unlocked, registering the counter allocates
+static ALLOCATIONS: Counter = Counter::new("memory.heap_allocations");
+
+// Nested interrupt handlers allocate, the lock must not be held when
+// they are taken
 struct MutexHeap<Allocator: PageAllocator> {
-    inner: Mutex<Heap<Allocator>>,
+    inner: SpinLockIrqSave<Heap<Allocator>>,
 }
 
 // SAFETY: Heap can be send between threads
@@ -243,14 +256,25 @@ unsafe impl<Allocator: PageAllocator> Send for Heap<Allocator> {}
 impl<Allocator: PageAllocator> MutexHeap<Allocator> {
     const fn new() -> Self {
         Self {
-            inner: Mutex::new(Heap::new()),
+            inner: SpinLockIrqSave::new(Heap::new()),
         }
     }
 }
 
 unsafe impl<Allocator: PageAllocator> GlobalAlloc for MutexHeap<Allocator> {
     unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
-        self.inner.lock().alloc(layout)
+        loop {
+            // The lock must be released before the out of memory handler
+            // runs because it frees memory.
+            let ptr = self.inner.lock().alloc(layout);
+            if !ptr.is_null() {
+                ALLOCATIONS.increment();
+                return ptr;
+            }
+            if !Allocator::handle_out_of_memory() {
+                return ptr;
+            }
+        }
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
@@ -278,6 +302,7 @@ mod test {
     use crate::memory::{
         page::Page,
         page_allocator::{MetadataPageAllocator, PageAllocator},
+        page_owner,
     };
     use common::mutex::Mutex;
     use core::{
@@ -297,7 +322,9 @@ mod test {
     struct TestAllocator;
     impl PageAllocator for TestAllocator {
         fn alloc(number_of_pages_requested: usize) -> Option<Range<NonNull<Page>>> {
-            PAGE_ALLOC.lock().alloc(number_of_pages_requested)
+            PAGE_ALLOC
+                .lock()
+                .alloc(number_of_pages_requested, page_owner::current())
         }
 
         fn dealloc(page: NonNull<Page>) -> usize {
@@ -0,0 +1,144 @@
+//! With the `leak_check` command line option the teardown of every
+//! exiting process is verified: all pages it owned must be returned to
+//! the page allocator. Pages which are not are reported together with
+//! the subsystem which owned them, such that slow leaks show up long
+//! before the stress test runs out of memory.
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::{
+    cmdline, cpu::Cpu, klibc::counters::Counter, memory::PAGE_SIZE, per_cpu::per_cpu,
+    processes::process::Pid, warn,
+};
+
+static LEAKED_PAGES: Counter = Counter::new("memory.leaked_pages");
+
+// Number of harts which currently tear down a process, the page
+// allocator only reports frees while there is one
+static TEARDOWNS: AtomicUsize = AtomicUsize::new(0);
+
+per_cpu!(static TEARDOWN: Option<Inventory> = None);
+
+struct Entry {
+    owner: &'static str,
+    pages: Range<usize>,
+    freed: bool,
+}
+
+/// The pages a process owns exclusively, e.g. not the shared pages of
+/// the page cache
+pub struct Inventory {
+    entries: Vec<Entry>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, owner: &'static str, start: usize, number_of_pages: usize) {
+        self.entries.push(Entry {
+            owner,
+            pages: start..start + number_of_pages * PAGE_SIZE,
+            freed: false,
+        });
+    }
+
+    fn mark_freed(&mut self, freed: &Range<usize>) {
+        for entry in &mut self.entries {
+            if entry.pages.start < freed.end && freed.start < entry.pages.end {
+                entry.freed = true;
+            }
+        }
+    }
+
+    /// Pages which are kept on purpose, e.g. in a cache, are no leak
+    fn leaks(self, kept: impl Fn(&'static str, usize) -> bool) -> Vec<Entry> {
+        self.entries
+            .into_iter()
+            .filter(|entry| !entry.freed && !kept(entry.owner, entry.pages.start))
+            .collect()
+    }
+}
+
+pub fn is_enabled() -> bool {
+    cmdline::get().leak_check
+}
+
+/// Runs `teardown` and reports the pages of the inventory which were
+/// not freed by it. Pages for which `kept` returns true don't count.
+pub fn verify_teardown(
+    pid: Pid,
+    inventory: Inventory,
+    teardown: impl FnOnce(),
+    kept: impl Fn(&'static str, usize) -> bool,
+) {
+    // Frees of interrupt handlers would be attributed to the process
+    let inventory = Cpu::without_interrupts(|| {
+        TEARDOWN.with(|current| *current = Some(inventory));
+        TEARDOWNS.fetch_add(1, Ordering::SeqCst);
+        teardown();
+        TEARDOWNS.fetch_sub(1, Ordering::SeqCst);
+        TEARDOWN
+            .with(|current| current.take())
+            .expect("Inventory must still be there")
+    });
+    let leaks = inventory.leaks(kept);
+    if leaks.is_empty() {
+        return;
+    }
+    let mut report = String::new();
+    let mut leaked_pages = 0;
+    for leak in &leaks {
+        let number_of_pages = leak.pages.len() / PAGE_SIZE;
+        leaked_pages += number_of_pages;
+        report += &format!(
+            "\n  {}: {:#x}-{:#x} ({number_of_pages} pages)",
+            leak.owner, leak.pages.start, leak.pages.end
+        );
+    }
+    LEAKED_PAGES.add(leaked_pages as u64);
+    warn!("Process (PID={pid}) leaked {leaked_pages} pages:{report}");
+}
+
+/// Called by the page allocator for every free. Must not allocate.
+pub fn record_free(start: usize, number_of_pages: usize) {
+    if TEARDOWNS.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    TEARDOWN.with(|current| {
+        if let Some(inventory) = current {
+            inventory.mark_freed(&(start..start + number_of_pages * PAGE_SIZE));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::PAGE_SIZE;
+
+    use super::Inventory;
+
+    #[test_case]
+    fn freed_and_kept_pages_are_no_leak() {
+        let mut inventory = Inventory::new();
+        inventory.add("stack", 0x1000, 1);
+        inventory.add("vma", 0x4000, 2);
+        inventory.add("kernel stack", 0x8000, 1);
+        inventory.add("page tables", 0x9000, 1);
+
+        inventory.mark_freed(&(0x1000..0x1000 + PAGE_SIZE));
+        // Frees cover the whole allocation
+        inventory.mark_freed(&(0x4000..0x4000 + 2 * PAGE_SIZE));
+        let leaks = inventory.leaks(|owner, _| owner == "kernel stack");
+        assert_eq!(leaks.len(), 1);
+        assert_eq!(leaks[0].owner, "page tables");
+    }
+}
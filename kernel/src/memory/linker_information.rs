@@ -84,6 +84,7 @@ macro_rules! sections {
 sections! {
     text, XWRMode::ReadExecute;
     rodata, XWRMode::ReadOnly;
+    initcalls, XWRMode::ReadOnly;
     eh_frame, XWRMode::ReadOnly;
     data, XWRMode::ReadWrite;
     bss, XWRMode::ReadWrite;
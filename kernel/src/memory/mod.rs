@@ -1,35 +1,59 @@
-use crate::{device_tree, info};
+use crate::{cmdline, cpu::Cpu, device_tree, info, processes::oom};
 
 use self::{
     page::Page,
     page_allocator::{MetadataPageAllocator, PageAllocator},
+    page_owner::{Owner, Subsystem},
 };
-use common::mutex::Mutex;
+use alloc::{string::String, vec::Vec};
+use common::mutex::SpinLockIrqSave;
 use core::{mem::MaybeUninit, ops::Range, ptr::NonNull, slice::from_raw_parts_mut};
 use linker_information::LinkerInformation;
 
+pub mod dma;
 pub mod heap;
+pub mod leak_check;
 pub mod linker_information;
 pub mod page;
 mod page_allocator;
+pub mod page_cache;
+pub mod page_owner;
 pub mod page_tables;
 mod runtime_mappings;
+pub mod vma;
 
 pub use page::PAGE_SIZE;
 
 pub use runtime_mappings::initialize_runtime_mappings;
 
-static PAGE_ALLOCATOR: Mutex<MetadataPageAllocator> = Mutex::new(MetadataPageAllocator::new());
+static PAGE_ALLOCATOR: SpinLockIrqSave<MetadataPageAllocator> =
+    SpinLockIrqSave::new(MetadataPageAllocator::new());
 
 pub struct StaticPageAllocator;
 
 impl PageAllocator for StaticPageAllocator {
     fn alloc(number_of_pages_requested: usize) -> Option<Range<NonNull<Page>>> {
-        PAGE_ALLOCATOR.lock().alloc(number_of_pages_requested)
+        let owner = page_owner::current();
+        let mut allocator = PAGE_ALLOCATOR.lock();
+        let allocation = allocator.alloc(number_of_pages_requested, owner);
+        page_cache::check_watermark(allocator.free_heap_pages(), allocator.total_heap_pages());
+        let poison_violation = allocator.take_poison_violation();
+        drop(allocator);
+        if let Some(violation) = poison_violation {
+            page_owner::report_poison_violation(&violation);
+        }
+        allocation
     }
 
     fn dealloc(page: NonNull<Page>) -> usize {
-        PAGE_ALLOCATOR.lock().dealloc(page)
+        let number_of_pages = PAGE_ALLOCATOR.lock().dealloc(page);
+        leak_check::record_free(page.as_ptr() as usize, number_of_pages);
+        number_of_pages
+    }
+
+    fn handle_out_of_memory() -> bool {
+        // Dropping caches is always preferred over killing a process
+        page_cache::reclaim(usize::MAX) > 0 || oom::handle_out_of_memory()
     }
 }
 
@@ -40,6 +64,11 @@ pub fn heap_size() -> usize {
 
 #[cfg(not(miri))]
 pub fn heap_size() -> usize {
+    ram_range().end - LinkerInformation::__start_heap()
+}
+
+/// All of RAM including the firmware in front of the kernel image
+pub fn ram_range() -> Range<usize> {
     let memory_node = device_tree::THE
         .root_node()
         .find_node("memory")
@@ -49,13 +78,45 @@ pub fn heap_size() -> usize {
         .parse_reg_property()
         .expect("Memory node must have a reg property");
 
-    let ram_end_address = reg.address + reg.size;
-    ram_end_address - LinkerInformation::__start_heap()
+    reg.address..reg.address + reg.size
+}
+
+/// Userspace must never get access to RAM or to a device the kernel
+/// drives itself. Everything the kernel uses is mapped in its page tables.
+pub fn is_kernel_owned(physical_range: &Range<usize>) -> bool {
+    let ram = ram_range();
+    if physical_range.start < ram.end && ram.start < physical_range.end {
+        return true;
+    }
+    Cpu::maybe_kernel_page_tables()
+        .expect("Kernel page tables must exist after boot")
+        .maps_physical(physical_range)
 }
 
-pub fn init_page_allocator(reserved_areas: &[Range<*const u8>]) {
+/// The page allocator manages all the RAM behind the kernel image
+#[cfg(not(miri))]
+fn heap_memory() -> &'static mut [MaybeUninit<u8>] {
     let heap_start = LinkerInformation::__start_heap();
-    let heap_size = heap_size();
+    // SAFETY: Nobody else uses the RAM behind the kernel image
+    unsafe { from_raw_parts_mut(heap_start as *mut MaybeUninit<u8>, heap_size()) }
+}
+
+/// Miri doesn't know about our RAM, therefore the memory of the page
+/// allocator is taken from the host. Enough for the unit tests.
+#[cfg(miri)]
+fn heap_memory() -> &'static mut [MaybeUninit<u8>] {
+    const MIRI_HEAP_PAGES: usize = 1024;
+    let pages = alloc::boxed::Box::leak(alloc::boxed::Box::<[Page]>::new_uninit_slice(
+        MIRI_HEAP_PAGES,
+    ));
+    // SAFETY: The pages are leaked and therefore valid forever
+    unsafe { from_raw_parts_mut(pages.as_mut_ptr().cast(), MIRI_HEAP_PAGES * PAGE_SIZE) }
+}
+
+pub fn init_page_allocator(reserved_areas: impl IntoIterator<Item = Range<*const u8>>) {
+    let memory = heap_memory();
+    let heap_start = memory.as_ptr() as usize;
+    let heap_size = memory.len();
 
     info!("Initializing page allocator");
     info!(
@@ -66,8 +127,17 @@ pub fn init_page_allocator(reserved_areas: &[Range<*const u8>]) {
         common::util::PrintMemorySizeHumanFriendly(heap_size)
     );
 
-    let memory = unsafe { from_raw_parts_mut(heap_start as *mut MaybeUninit<u8>, heap_size) };
-    PAGE_ALLOCATOR.lock().init(memory, reserved_areas);
+    let mut allocator = PAGE_ALLOCATOR.lock();
+    allocator.init(memory, &[]);
+    for area in reserved_areas {
+        allocator.reserve(&area);
+    }
+    if cmdline::get().page_poison {
+        info!("Freed pages are poisoned");
+        allocator.enable_poisoning();
+    }
+    drop(allocator);
+    page_owner::init();
 }
 
 pub fn used_heap_pages() -> usize {
@@ -77,3 +147,26 @@ pub fn used_heap_pages() -> usize {
 pub fn total_heap_pages() -> usize {
     PAGE_ALLOCATOR.lock().total_heap_pages()
 }
+
+/// Charges the allocation starting at the page to the subsystem, e.g.
+/// if it outlives the process which allocated it
+pub fn charge_pages_to(page: NonNull<Page>, subsystem: Subsystem) {
+    PAGE_ALLOCATOR
+        .lock()
+        .set_owner(page, Owner::kernel(subsystem));
+}
+
+/// See memory::page_owner
+pub fn page_owner_report() -> String {
+    // Allocating with the page allocator locked would deadlock,
+    // allocations made in the meantime are missing
+    let mut allocations = Vec::with_capacity(used_heap_pages() + 64);
+    PAGE_ALLOCATOR
+        .lock()
+        .for_each_allocation(|owner, number_of_pages| {
+            if allocations.len() < allocations.capacity() {
+                allocations.push((owner, number_of_pages));
+            }
+        });
+    page_owner::report(&allocations)
+}
@@ -1,4 +1,7 @@
-use super::page::Page;
+use super::{
+    page::Page,
+    page_owner::{Owner, PoisonViolation},
+};
 use crate::{debug, klibc::util::minimum_amount_of_pages, memory::PAGE_SIZE};
 use common::util::align_down_ptr;
 use core::{
@@ -8,6 +11,9 @@ use core::{
     ptr::{null_mut, NonNull},
 };
 
+// Freed pages are filled with it if poisoning is enabled
+const POISON: u64 = 0x6b6b_6b6b_6b6b_6b6b;
+
 #[repr(u8)]
 #[derive(Debug, PartialEq, Eq)]
 enum PageStatus {
@@ -25,7 +31,15 @@ impl PageStatus {
 
 pub(super) struct MetadataPageAllocator<'a> {
     metadata: &'a mut [PageStatus],
+    // Kept after a free, such that poison violations name the previous owner
+    owners: &'a mut [Owner],
     pages: Range<*mut MaybeUninit<Page>>,
+    // Counted such that the watermark can be checked on every allocation
+    used_pages: usize,
+    poison: bool,
+    // The first violation since it was taken, reporting it here might
+    // allocate
+    poison_violation: Option<PoisonViolation>,
 }
 
 // SAFETY: The metadata page allocator can be accessed from any thread
@@ -44,7 +58,11 @@ impl<'a> MetadataPageAllocator<'a> {
     pub(super) const fn new() -> Self {
         Self {
             metadata: &mut [],
+            owners: &mut [],
             pages: null_mut()..null_mut(),
+            used_pages: 0,
+            poison: false,
+            poison_violation: None,
         }
     }
 
@@ -54,36 +72,47 @@ impl<'a> MetadataPageAllocator<'a> {
         reserved_areas: &[Range<*const u8>],
     ) {
         let heap_size = memory.len();
-        let number_of_heap_pages = heap_size / (PAGE_SIZE + 1); // We need one byte per page as metadata
+        // We need one byte per page and its owner as metadata
+        let number_of_heap_pages = heap_size / (PAGE_SIZE + 1 + size_of::<Owner>());
 
-        let (metadata, heap) = memory.split_at_mut(number_of_heap_pages);
+        let (metadata, rest) = memory.split_at_mut(number_of_heap_pages);
 
         let (begin, metadata, end) = unsafe { metadata.align_to_mut::<MaybeUninit<PageStatus>>() };
         assert!(begin.is_empty());
         assert!(end.is_empty());
 
+        let (_begin, owners, _end) = unsafe { rest.align_to_mut::<MaybeUninit<Owner>>() };
+        let (owners, heap) = owners.split_at_mut(number_of_heap_pages);
+
         let (_begin, heap, _end) = unsafe { heap.align_to_mut::<MaybeUninit<Page>>() };
         assert!(metadata.len() <= heap.len());
         assert!(heap[0].as_ptr() as usize % PAGE_SIZE == 0);
 
-        let size_metadata = core::mem::size_of_val(metadata);
+        let size_metadata = core::mem::size_of_val(metadata) + core::mem::size_of_val(owners);
         let size_heap = core::mem::size_of_val(heap);
         assert!(size_metadata + size_heap <= heap_size);
 
         metadata.iter_mut().for_each(|x| {
             x.write(PageStatus::FirstUse);
         });
+        owners.iter_mut().for_each(|x| {
+            x.write(Owner::NOBODY);
+        });
 
-        // SAFTEY: We initialized all the data in the previous statement
+        // SAFTEY: We initialized all the data in the previous statements
         self.metadata = unsafe {
             core::mem::transmute::<&mut [MaybeUninit<PageStatus>], &mut [PageStatus]>(metadata)
         };
+        self.owners =
+            unsafe { core::mem::transmute::<&mut [MaybeUninit<Owner>], &mut [Owner]>(owners) };
 
         self.pages = heap.as_mut_ptr_range();
+        self.used_pages = 0;
+        self.poison = false;
+        self.poison_violation = None;
 
-        // Set reserved areas to used
         for area in reserved_areas {
-            self.mark_pointer_range_as_used_without_initialize(area);
+            self.reserve(area);
         }
 
         debug!("Page allocator initalized");
@@ -97,7 +126,55 @@ impl<'a> MetadataPageAllocator<'a> {
     }
 
     pub fn used_heap_pages(&self) -> usize {
-        self.metadata.iter().filter(|m| !m.is_free()).count()
+        self.used_pages
+    }
+
+    pub fn free_heap_pages(&self) -> usize {
+        self.total_heap_pages() - self.used_pages
+    }
+
+    /// Freed pages are filled with a pattern which is verified when they
+    /// are allocated again. Must be called before the first free.
+    pub(super) fn enable_poisoning(&mut self) {
+        self.poison = true;
+    }
+
+    pub(super) fn take_poison_violation(&mut self) -> Option<PoisonViolation> {
+        self.poison_violation.take()
+    }
+
+    /// Calls `f` with the owner and number of pages of every allocation
+    pub(super) fn for_each_allocation(&self, mut f: impl FnMut(Owner, usize)) {
+        let mut start = None;
+        for (idx, status) in self.metadata.iter().enumerate() {
+            match status {
+                PageStatus::Used => {
+                    start.get_or_insert(idx);
+                }
+                PageStatus::Last => {
+                    let start = start.take().unwrap_or(idx);
+                    f(self.owners[start], idx - start + 1);
+                }
+                PageStatus::FirstUse | PageStatus::Free => {}
+            }
+        }
+    }
+
+    /// Charges the allocation starting at the page to another owner
+    pub(super) fn set_owner(&mut self, page: NonNull<Page>, owner: Owner) {
+        // Under miri the global allocator is the one of the host
+        if !self.pages.contains(&page.as_ptr().cast()) {
+            return;
+        }
+        let mut idx = self.page_pointer_to_page_idx(page.cast());
+        assert!(!self.metadata[idx].is_free(), "Page must be allocated");
+        loop {
+            self.owners[idx] = owner;
+            if self.metadata[idx] == PageStatus::Last {
+                break;
+            }
+            idx += 1;
+        }
     }
 
     fn page_idx_to_pointer(&self, page_index: usize) -> NonNull<MaybeUninit<Page>> {
@@ -115,7 +192,11 @@ impl<'a> MetadataPageAllocator<'a> {
         offset as usize
     }
 
-    pub fn alloc(&mut self, number_of_pages_requested: usize) -> Option<Range<NonNull<Page>>> {
+    pub fn alloc(
+        &mut self,
+        number_of_pages_requested: usize,
+        owner: Owner,
+    ) -> Option<Range<NonNull<Page>>> {
         let total_pages = self.total_heap_pages();
         if number_of_pages_requested > total_pages {
             return None;
@@ -123,7 +204,7 @@ impl<'a> MetadataPageAllocator<'a> {
         (0..=(self.total_heap_pages() - number_of_pages_requested))
             .find(|&idx| self.is_range_free(idx, number_of_pages_requested))
             .map(|start_idx| {
-                self.mark_range_as_used(start_idx, number_of_pages_requested, true);
+                self.mark_range_as_used(start_idx, number_of_pages_requested, true, owner);
                 // NonNull<MaybeUninit<Page>> can be cast to NonNull<Page> because they are
                 // initialized in mark_range_as_used
                 self.page_idx_to_pointer(start_idx).cast()
@@ -142,6 +223,7 @@ impl<'a> MetadataPageAllocator<'a> {
         start_idx: usize,
         number_of_pages: usize,
         initialize_if_needed: bool,
+        owner: Owner,
     ) {
         // It is clearer to express this the current way it is
         #[allow(clippy::needless_range_loop)]
@@ -154,6 +236,9 @@ impl<'a> MetadataPageAllocator<'a> {
                     page.write(MaybeUninit::zeroed());
                 }
             }
+            if self.poison && self.metadata[idx] == PageStatus::Free {
+                self.verify_poison(idx);
+            }
             let status = if idx == start_idx + number_of_pages - 1 {
                 PageStatus::Last
             } else {
@@ -161,31 +246,50 @@ impl<'a> MetadataPageAllocator<'a> {
             };
 
             self.metadata[idx] = status;
+            self.owners[idx] = owner;
         }
+        self.used_pages += number_of_pages;
     }
 
-    fn range_to_start_aligned_and_number_of_pages<T>(
-        &self,
-        range: &Range<*const T>,
-    ) -> (usize, usize) {
-        let start_aligned = align_down_ptr(range.start, PAGE_SIZE);
-        // We don't use the offset_from pointer functions because this requires
-        // that both pointers point to the same allocation which is not the case
-        let new_length = range.end as usize - start_aligned as usize;
-        let number_of_pages = minimum_amount_of_pages(new_length);
-        let start_idx = self.page_pointer_to_page_idx(
-            NonNull::new(start_aligned as *mut _).expect("start_aligned is not allowed to be NULL"),
-        );
-        (start_idx, number_of_pages)
+    fn verify_poison(&mut self, idx: usize) {
+        let page = self.page_idx_to_pointer(idx).cast::<[u64; PAGE_SIZE / 8]>();
+        // SAFETY: Free pages are only accessed by the allocator
+        let words = unsafe { page.as_ref() };
+        if let Some(offset) = words.iter().position(|word| *word != POISON) {
+            self.poison_violation.get_or_insert(PoisonViolation {
+                address: page.as_ptr() as usize + offset * 8,
+                previous_owner: self.owners[idx],
+            });
+        }
     }
 
-    fn mark_pointer_range_as_used_without_initialize<T>(&mut self, range: &Range<*const T>) {
-        let (start_idx, number_of_pages) = self.range_to_start_aligned_and_number_of_pages(range);
-        assert!(
-            self.is_range_free(start_idx, number_of_pages),
-            "Reserved area should be free. Otherwise with have problems with overlapping LAST bits"
-        );
-        self.mark_range_as_used(start_idx, number_of_pages, false);
+    /// The firmware might reserve memory in front of the heap or the same
+    /// memory twice, e.g. the device tree. Only the pages inside the heap
+    /// which are not reserved yet are marked.
+    pub(super) fn reserve<T>(&mut self, range: &Range<*const T>) {
+        let heap_start = self.pages.start as usize;
+        let heap_end = self.pages.end as usize;
+        let start = align_down_ptr(range.start, PAGE_SIZE) as usize;
+        let start = start.clamp(heap_start, heap_end);
+        let end = (range.end as usize).clamp(heap_start, heap_end);
+        if start >= end {
+            return;
+        }
+        let first_idx = (start - heap_start) / PAGE_SIZE;
+        let end_idx = first_idx + minimum_amount_of_pages(end - start);
+        let mut idx = first_idx;
+        while idx < end_idx {
+            let run_start = idx;
+            while idx < end_idx && self.metadata[idx].is_free() {
+                idx += 1;
+            }
+            if idx > run_start {
+                self.mark_range_as_used(run_start, idx - run_start, false, Owner::RESERVED);
+            }
+            while idx < end_idx && !self.metadata[idx].is_free() {
+                idx += 1;
+            }
+        }
     }
 
     pub fn dealloc(&mut self, page: NonNull<Page>) -> usize {
@@ -199,6 +303,14 @@ impl<'a> MetadataPageAllocator<'a> {
         }
         self.metadata[idx] = PageStatus::Free;
         count += 1;
+        self.used_pages -= count;
+        if self.poison {
+            // SAFETY: The caller gave the pages back
+            unsafe {
+                page.cast::<u64>()
+                    .write_bytes(POISON as u8, count * PAGE_SIZE / 8);
+            }
+        }
         count
     }
 }
@@ -206,12 +318,22 @@ impl<'a> MetadataPageAllocator<'a> {
 pub trait PageAllocator {
     fn alloc(number_of_pages_requested: usize) -> Option<Range<NonNull<Page>>>;
     fn dealloc(page: NonNull<Page>) -> usize;
+
+    /// Called without any heap lock held after an allocation failed.
+    /// Returns true if memory was freed and the allocation should be retried.
+    fn handle_out_of_memory() -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{MetadataPageAllocator, Page, PAGE_SIZE};
-    use crate::memory::page_allocator::PageStatus;
+    use crate::memory::{
+        page_allocator::PageStatus,
+        page_owner::{Owner, OwnerTag, Subsystem},
+    };
+    use alloc::vec::Vec;
     use common::mutex::Mutex;
     use core::{
         mem::MaybeUninit,
@@ -238,8 +360,10 @@ mod tests {
         }
     }
 
+    const OWNER: Owner = Owner::kernel(Subsystem::Heap);
+
     fn alloc(number_of_pages: usize) -> Option<Range<NonNull<Page>>> {
-        PAGE_ALLOC.lock().alloc(number_of_pages)
+        PAGE_ALLOC.lock().alloc(number_of_pages, OWNER)
     }
 
     fn dealloc(pages: Range<NonNull<Page>>) -> usize {
@@ -270,6 +394,17 @@ mod tests {
         assert_eq!(*last, PageStatus::Last);
     }
 
+    #[test_case]
+    fn used_pages_are_counted() {
+        init_allocator(false, &[]);
+        let total_pages = PAGE_ALLOC.lock().total_heap_pages();
+        let pages = alloc(3).unwrap();
+        assert_eq!(PAGE_ALLOC.lock().used_heap_pages(), 3);
+        assert_eq!(PAGE_ALLOC.lock().free_heap_pages(), total_pages - 3);
+        assert_eq!(dealloc(pages), 3);
+        assert_eq!(PAGE_ALLOC.lock().used_heap_pages(), 0);
+    }
+
     #[test_case]
     fn beyond_capacity() {
         init_allocator(false, &[]);
@@ -362,7 +497,7 @@ mod tests {
         init_allocator(true, &[]);
         let first_page = PAGE_ALLOC.lock().pages.start as *const u8;
         unsafe { assert_eq!((*first_page), MEMORY_PATTERN) }
-        let page = PAGE_ALLOC.lock().alloc(1).unwrap().start;
+        let page = alloc(1).unwrap().start;
         unsafe {
             assert_eq!(page.read(), Page::zero());
         }
@@ -378,7 +513,69 @@ mod tests {
         let first_page = PAGE_ALLOC.lock().pages.start as *const u8;
         unsafe { assert_eq!((*first_page), MEMORY_PATTERN) }
 
-        let _page = PAGE_ALLOC.lock().alloc(1).unwrap().start;
+        let _page = alloc(1).unwrap().start;
         unsafe { assert_eq!((*first_page), MEMORY_PATTERN) }
     }
+
+    #[test_case]
+    fn overlapping_reserved_areas() {
+        init_allocator(false, &[]);
+        let heap_start = PAGE_ALLOC.lock().pages.start as *const u8;
+        let second_page = heap_start.wrapping_add(PAGE_SIZE);
+        init_allocator(
+            false,
+            &[
+                heap_start.wrapping_sub(PAGE_SIZE)..second_page,
+                heap_start..second_page.wrapping_add(PAGE_SIZE),
+            ],
+        );
+        assert_eq!(PAGE_ALLOC.lock().used_heap_pages(), 2);
+        let page = alloc(1).unwrap().start;
+        assert_eq!(
+            page.as_ptr() as *const u8,
+            second_page.wrapping_add(PAGE_SIZE)
+        );
+    }
+
+    #[test_case]
+    fn allocations_are_listed_with_their_owner() {
+        init_allocator(false, &[]);
+        let process = Owner {
+            tag: OwnerTag::Process(7),
+            backtrace: None,
+        };
+        let _heap = alloc(1).unwrap();
+        let pages = PAGE_ALLOC.lock().alloc(2, process).unwrap();
+        let cache = alloc(3).unwrap();
+        let cache_owner = Owner::kernel(Subsystem::PageCache);
+        PAGE_ALLOC.lock().set_owner(cache.start, cache_owner);
+        dealloc(pages);
+
+        let mut allocations = Vec::new();
+        PAGE_ALLOC
+            .lock()
+            .for_each_allocation(|owner, number_of_pages| {
+                allocations.push((owner, number_of_pages))
+            });
+        assert_eq!(allocations, [(OWNER, 1), (cache_owner, 3)]);
+    }
+
+    #[test_case]
+    fn writes_after_free_are_detected() {
+        init_allocator(false, &[]);
+        PAGE_ALLOC.lock().enable_poisoning();
+        let pages = alloc(2).unwrap();
+        let second_page = pages.end.as_ptr() as usize - PAGE_SIZE;
+        dealloc(pages);
+        let pages = alloc(2).unwrap();
+        assert!(PAGE_ALLOC.lock().take_poison_violation().is_none());
+        dealloc(pages);
+
+        // SAFETY: The memory belongs to the test allocator
+        unsafe { (second_page as *mut u8).add(9).write(0) };
+        let _pages = alloc(2).unwrap();
+        let violation = PAGE_ALLOC.lock().take_poison_violation().unwrap();
+        assert_eq!(violation.address, second_page + 8);
+        assert_eq!(violation.previous_owner, OWNER);
+    }
 }
@@ -0,0 +1,308 @@
+//! Cache for pages whose content can be recreated at any time, e.g.
+//! the segments of programs and pages of mapped files. The pages
+//! are shared between all users. Pages which are only referenced by the
+//! cache are clean and can be freed under memory pressure, least recently
+//! used first.
+
+use core::{
+    convert::Infallible,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use common::mutex::Mutex;
+
+use crate::{
+    debug,
+    memory::{self, page::PinnedHeapPages, page_owner::Subsystem},
+    processes::workqueue,
+};
+
+pub type SharedPages = Arc<PinnedHeapPages>;
+
+/// Reclaim starts when less than 1/LOW_WATERMARK_DIVISOR of the pages are free
+const LOW_WATERMARK_DIVISOR: usize = 16;
+/// and stops when 1/HIGH_WATERMARK_DIVISOR of the pages are free again
+const HIGH_WATERMARK_DIVISOR: usize = 8;
+
+static PAGE_CACHE: Mutex<PageCache> = Mutex::new(PageCache::new());
+
+static RECLAIM_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CachedContent {
+    // The offset is the one of the segment in the ELF file
+    ProgramSegment,
+    // A single page, the offset is page aligned
+    FilePage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PageCacheKey {
+    pub content: CachedContent,
+    pub file: String,
+    pub offset: usize,
+}
+
+struct Entry {
+    pages: SharedPages,
+    last_used: u64,
+}
+
+struct PageCache {
+    entries: BTreeMap<PageCacheKey, Entry>,
+    // Ordered by the time of the last use, oldest first
+    lru: BTreeMap<u64, PageCacheKey>,
+    clock: u64,
+}
+
+impl PageCache {
+    const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: &PageCacheKey) -> Option<SharedPages> {
+        let now = self.tick();
+        let entry = self.entries.get_mut(key)?;
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(now, key.clone());
+        entry.last_used = now;
+        Some(entry.pages.clone())
+    }
+
+    /// Returns the already cached pages if another user was faster
+    fn insert(&mut self, key: PageCacheKey, pages: SharedPages) -> SharedPages {
+        if let Some(existing) = self.get(&key) {
+            return existing;
+        }
+        let now = self.tick();
+        self.lru.insert(now, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                pages: pages.clone(),
+                last_used: now,
+            },
+        );
+        pages
+    }
+
+    /// Removes unused entries, least recently used first, until at least
+    /// `number_of_pages` are removed. The caller drops the returned pages
+    /// after the cache lock is released.
+    fn take_reclaimable(&mut self, number_of_pages: usize) -> Vec<SharedPages> {
+        let mut reclaimed = Vec::new();
+        let mut reclaimed_pages = 0;
+        let unused: Vec<(u64, PageCacheKey)> = self
+            .lru
+            .iter()
+            .filter(|(_, key)| Arc::strong_count(&self.entries[*key].pages) == 1)
+            .map(|(last_used, key)| (*last_used, key.clone()))
+            .collect();
+        for (last_used, key) in unused {
+            if reclaimed_pages >= number_of_pages {
+                break;
+            }
+            self.lru.remove(&last_used);
+            let entry = self.entries.remove(&key).expect("LRU entry must exist");
+            reclaimed_pages += entry.pages.len();
+            reclaimed.push(entry.pages);
+        }
+        reclaimed
+    }
+
+    /// Removes the file pages of the path and of everything below it.
+    /// The caller drops the returned pages after the cache lock is released.
+    fn take_file(&mut self, path: &str) -> Vec<SharedPages> {
+        let is_affected = |key: &PageCacheKey| {
+            key.content == CachedContent::FilePage
+                && key
+                    .file
+                    .strip_prefix(path)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+        let affected: Vec<PageCacheKey> = self
+            .entries
+            .keys()
+            .filter(|key| is_affected(key))
+            .cloned()
+            .collect();
+        affected
+            .iter()
+            .map(|key| {
+                let entry = self.entries.remove(key).expect("Entry must exist");
+                self.lru.remove(&entry.last_used);
+                entry.pages
+            })
+            .collect()
+    }
+
+    fn cached_pages(&self) -> usize {
+        self.entries.values().map(|entry| entry.pages.len()).sum()
+    }
+}
+
+/// Returns the cached pages or caches the pages returned by `create`
+pub fn get_or_insert_with(
+    key: PageCacheKey,
+    create: impl FnOnce() -> PinnedHeapPages,
+) -> SharedPages {
+    let Ok(pages) = try_get_or_insert_with(key, || Ok::<_, Infallible>(create()));
+    pages
+}
+
+/// Like [`get_or_insert_with`], nothing is cached if `create` fails
+pub fn try_get_or_insert_with<E>(
+    key: PageCacheKey,
+    create: impl FnOnce() -> Result<PinnedHeapPages, E>,
+) -> Result<SharedPages, E> {
+    if let Some(pages) = PAGE_CACHE.lock().get(&key) {
+        return Ok(pages);
+    }
+    // Created outside of the lock because the allocation might reclaim
+    // and reading a file might take some time
+    let mut pages = create()?;
+    // Cached pages outlive the process which read them
+    memory::charge_pages_to(pages.as_mut_ptr(), Subsystem::PageCache);
+    let pages = Arc::new(pages);
+    Ok(PAGE_CACHE.lock().insert(key, pages))
+}
+
+/// Drops the cached pages of a file which was changed, removed or
+/// renamed. Existing mappings keep the pages they already have.
+pub fn invalidate_file(path: &str) {
+    let pages = PAGE_CACHE.lock().take_file(path);
+    drop(pages);
+}
+
+pub fn cached_pages() -> usize {
+    PAGE_CACHE.lock().cached_pages()
+}
+
+/// Frees unused cached pages. Must not be called while a heap lock is held.
+/// Returns the number of freed pages.
+pub fn reclaim(number_of_pages: usize) -> usize {
+    // The current hart might be within the cache
+    if PAGE_CACHE.get_locked().load(Ordering::SeqCst) {
+        return 0;
+    }
+    let reclaimed = PAGE_CACHE.lock().take_reclaimable(number_of_pages);
+    let freed_pages = reclaimed.iter().map(|pages| pages.len()).sum();
+    if freed_pages > 0 {
+        debug!("Reclaimed {freed_pages} cached pages");
+    }
+    freed_pages
+}
+
+/// Called by the page allocator on every allocation. Reclaim itself is
+/// deferred because the page allocator is locked.
+pub fn check_watermark(free_pages: usize, total_pages: usize) {
+    if free_pages < total_pages / LOW_WATERMARK_DIVISOR {
+        RECLAIM_REQUESTED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Hands a requested reclaim over to the work queue. Called from
+/// interrupt context.
+pub fn submit_reclaim_if_requested() {
+    if RECLAIM_REQUESTED.swap(false, Ordering::Relaxed) {
+        workqueue::submit(reclaim_to_high_watermark);
+    }
+}
+
+fn reclaim_to_high_watermark() {
+    let total_pages = super::total_heap_pages();
+    let free_pages = total_pages - super::used_heap_pages();
+    let target = total_pages / HIGH_WATERMARK_DIVISOR;
+    if free_pages < target {
+        reclaim(target - free_pages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, sync::Arc};
+
+    use super::{CachedContent, PageCache, PageCacheKey};
+    use crate::memory::page::PinnedHeapPages;
+
+    fn key(offset: usize) -> PageCacheKey {
+        PageCacheKey {
+            content: CachedContent::ProgramSegment,
+            file: "prog1".to_string(),
+            offset,
+        }
+    }
+
+    #[test_case]
+    fn cached_pages_are_shared() {
+        let mut cache = PageCache::new();
+        let pages = cache.insert(key(0), Arc::new(PinnedHeapPages::new(1)));
+        let again = cache.get(&key(0)).expect("Pages must be cached");
+        assert!(Arc::ptr_eq(&pages, &again));
+        assert!(cache.get(&key(1)).is_none());
+
+        // A second insert returns the cached pages
+        let other = cache.insert(key(0), Arc::new(PinnedHeapPages::new(1)));
+        assert!(Arc::ptr_eq(&pages, &other));
+    }
+
+    #[test_case]
+    fn least_recently_used_unused_pages_are_reclaimed_first() {
+        let mut cache = PageCache::new();
+        cache.insert(key(0), Arc::new(PinnedHeapPages::new(1)));
+        cache.insert(key(1), Arc::new(PinnedHeapPages::new(2)));
+        let in_use = cache.insert(key(2), Arc::new(PinnedHeapPages::new(1)));
+        // Makes key(0) the most recently used entry
+        cache.get(&key(0));
+
+        let reclaimed = cache.take_reclaimable(1);
+        assert_eq!(reclaimed.len(), 1);
+        assert_eq!(reclaimed[0].len(), 2);
+        assert!(cache.get(&key(1)).is_none());
+
+        // Pages which are mapped somewhere are never reclaimed
+        let reclaimed = cache.take_reclaimable(usize::MAX);
+        assert_eq!(reclaimed.len(), 1);
+        assert!(cache.get(&key(0)).is_none());
+        assert!(cache
+            .get(&key(2))
+            .is_some_and(|pages| Arc::ptr_eq(&pages, &in_use)));
+        assert_eq!(cache.cached_pages(), 1);
+    }
+
+    #[test_case]
+    fn file_pages_are_invalidated_with_their_directory() {
+        let file_page = |file: &str| PageCacheKey {
+            content: CachedContent::FilePage,
+            file: file.to_string(),
+            offset: 0,
+        };
+        let mut cache = PageCache::new();
+        for file in ["/tmp/a", "/tmp/a/b", "/tmp/ab"] {
+            cache.insert(file_page(file), Arc::new(PinnedHeapPages::new(1)));
+        }
+        cache.insert(
+            PageCacheKey {
+                content: CachedContent::ProgramSegment,
+                file: "/tmp/a".to_string(),
+                offset: 0,
+            },
+            Arc::new(PinnedHeapPages::new(1)),
+        );
+
+        assert_eq!(cache.take_file("/tmp/a").len(), 2);
+        assert!(cache.get(&file_page("/tmp/ab")).is_some());
+        assert_eq!(cache.cached_pages(), 2, "Program segments stay cached");
+        assert_eq!(cache.lru.len(), 2);
+    }
+}
@@ -0,0 +1,297 @@
+//! Every allocation of the page allocator records its owner: the process
+//! which was running or the kernel subsystem the allocation is charged
+//! to. /proc/page_owner sums up the pages per owner, which answers who
+//! is using all the memory.
+//!
+//! With `page_backtraces` on the command line the kernel backtrace of
+//! every allocation is recorded as well. Equal backtraces are stored
+//! once in a fixed table and the metadata refers to them by id. With
+//! `page_poison` freed pages are filled with a pattern which is verified
+//! on their next allocation. Writes after a free are reported together
+//! with the previous owner of the page.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use common::mutex::SpinLockIrqSave;
+use core::{
+    fmt::{Display, Write},
+    num::NonZeroU16,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use crate::{
+    cmdline,
+    cpu::Cpu,
+    debugging::{backtrace, symbols},
+    per_cpu::per_cpu,
+    processes::process::Pid,
+    warn,
+};
+
+const BACKTRACE_DEPTH: usize = 16;
+const MAX_BACKTRACES: usize = 256;
+
+static BACKTRACES_ENABLED: AtomicBool = AtomicBool::new(false);
+// Allocations whose backtrace didn't fit into the depot anymore
+static DROPPED_BACKTRACES: AtomicU64 = AtomicU64::new(0);
+static POISON_VIOLATIONS: AtomicU64 = AtomicU64::new(0);
+
+static DEPOT: SpinLockIrqSave<Depot> = SpinLockIrqSave::new(Depot::new());
+
+per_cpu!(static CHARGED: Option<Subsystem> = None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Subsystem {
+    /// Allocated before the first process runs
+    Boot,
+    /// Areas like the device tree which are never allocated
+    Reserved,
+    /// Pages which the kernel heap splits into small allocations
+    Heap,
+    PageCache,
+    Dma,
+    Interrupts,
+}
+
+impl Display for Subsystem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            Subsystem::Boot => "boot",
+            Subsystem::Reserved => "reserved",
+            Subsystem::Heap => "heap",
+            Subsystem::PageCache => "page cache",
+            Subsystem::Dma => "dma",
+            Subsystem::Interrupts => "interrupts",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OwnerTag {
+    Process(Pid),
+    Kernel(Subsystem),
+}
+
+impl Display for OwnerTag {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OwnerTag::Process(pid) => write!(f, "PID {pid}"),
+            OwnerTag::Kernel(subsystem) => write!(f, "{subsystem}"),
+        }
+    }
+}
+
+/// Recorded for every page of an allocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Owner {
+    pub tag: OwnerTag,
+    /// Only recorded with `page_backtraces`
+    pub backtrace: Option<BacktraceId>,
+}
+
+impl Owner {
+    /// Pages which were never allocated
+    pub const NOBODY: Self = Self::kernel(Subsystem::Boot);
+    pub const RESERVED: Self = Self::kernel(Subsystem::Reserved);
+
+    pub const fn kernel(subsystem: Subsystem) -> Self {
+        Self {
+            tag: OwnerTag::Kernel(subsystem),
+            backtrace: None,
+        }
+    }
+}
+
+/// Index into the depot plus one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BacktraceId(NonZeroU16);
+
+struct Depot {
+    backtraces: [[usize; BACKTRACE_DEPTH]; MAX_BACKTRACES],
+    len: usize,
+}
+
+impl Depot {
+    const fn new() -> Self {
+        Self {
+            backtraces: [[0; BACKTRACE_DEPTH]; MAX_BACKTRACES],
+            len: 0,
+        }
+    }
+
+    fn insert(&mut self, backtrace: &[usize; BACKTRACE_DEPTH]) -> Option<BacktraceId> {
+        let idx = match self.backtraces[..self.len]
+            .iter()
+            .position(|known| known == backtrace)
+        {
+            Some(idx) => idx,
+            None if self.len < MAX_BACKTRACES => {
+                self.backtraces[self.len] = *backtrace;
+                self.len += 1;
+                self.len - 1
+            }
+            None => return None,
+        };
+        NonZeroU16::new(idx as u16 + 1).map(BacktraceId)
+    }
+
+    fn get(&self, id: BacktraceId) -> [usize; BACKTRACE_DEPTH] {
+        self.backtraces[usize::from(id.0.get()) - 1]
+    }
+}
+
+/// A freed page which was written before it was allocated again
+#[derive(Debug, Clone, Copy)]
+pub struct PoisonViolation {
+    /// The first modified word
+    pub address: usize,
+    pub previous_owner: Owner,
+}
+
+/// Must be called after the command line is parsed
+pub fn init() {
+    BACKTRACES_ENABLED.store(cmdline::get().page_backtraces, Ordering::Relaxed);
+}
+
+/// The owner of the pages allocated right now. Must not allocate.
+pub fn current() -> Owner {
+    let charged = CHARGED.with(|charged| *charged);
+    let tag = match (charged, Cpu::maybe_current_pid()) {
+        (Some(subsystem), _) => OwnerTag::Kernel(subsystem),
+        (None, Some(pid)) => OwnerTag::Process(pid),
+        (None, None) => OwnerTag::Kernel(Subsystem::Boot),
+    };
+    Owner {
+        tag,
+        backtrace: capture_backtrace(),
+    }
+}
+
+fn capture_backtrace() -> Option<BacktraceId> {
+    if !BACKTRACES_ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut addresses = [0; BACKTRACE_DEPTH];
+    backtrace::capture(&mut addresses);
+    // The unwinder isn't initialized during early boot
+    if addresses[0] == 0 {
+        return None;
+    }
+    let id = DEPOT.lock().insert(&addresses);
+    if id.is_none() {
+        DROPPED_BACKTRACES.fetch_add(1, Ordering::Relaxed);
+    }
+    id
+}
+
+/// Charges the page allocations of `f` to the subsystem instead of the
+/// running process. `f` runs with interrupts disabled, therefore no
+/// other process can run on the hart meanwhile.
+pub fn charge_to<R>(subsystem: Subsystem, f: impl FnOnce() -> R) -> R {
+    Cpu::without_interrupts(|| {
+        let previous = CHARGED.with(|charged| charged.replace(subsystem));
+        let result = f();
+        CHARGED.with(|charged| *charged = previous);
+        result
+    })
+}
+
+/// Called by the page allocator after it was unlocked. Must not allocate.
+pub fn report_poison_violation(violation: &PoisonViolation) {
+    POISON_VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+    warn!(
+        "Freed page written at {:#x}, previously owned by {}",
+        violation.address, violation.previous_owner.tag
+    );
+    if let Some(id) = violation.previous_owner.backtrace {
+        let addresses = DEPOT.lock().get(id);
+        backtrace::print_addresses(addresses.into_iter().take_while(|address| *address != 0));
+    }
+}
+
+/// The pages of every owner, the largest first. The backtraces of the
+/// allocations are listed below the owners if they are recorded.
+pub fn report(allocations: &[(Owner, usize)]) -> String {
+    let mut per_tag: BTreeMap<OwnerTag, (usize, usize)> = BTreeMap::new();
+    let mut per_owner: BTreeMap<Owner, (usize, usize)> = BTreeMap::new();
+    for (owner, number_of_pages) in allocations {
+        for sums in [
+            per_tag.entry(owner.tag).or_default(),
+            per_owner.entry(*owner).or_default(),
+        ] {
+            sums.0 += number_of_pages;
+            sums.1 += 1;
+        }
+    }
+
+    let mut content = format!(
+        "PoisonViolations:\t{}\nDroppedBacktraces:\t{}\n\nPages\tAllocations\tOwner\n",
+        POISON_VIOLATIONS.load(Ordering::Relaxed),
+        DROPPED_BACKTRACES.load(Ordering::Relaxed)
+    );
+    for (tag, (pages, count)) in sorted_by_pages(per_tag) {
+        let _ = writeln!(content, "{pages}\t{count}\t{tag}");
+    }
+
+    for (owner, (pages, count)) in sorted_by_pages(per_owner) {
+        let Some(id) = owner.backtrace else {
+            continue;
+        };
+        let _ = writeln!(
+            content,
+            "\n{pages} pages in {count} allocations of {}:",
+            owner.tag
+        );
+        let addresses = DEPOT.lock().get(id);
+        for address in addresses.into_iter().take_while(|address| *address != 0) {
+            match symbols::get_symbol(address) {
+                Some(symbol) => {
+                    let offset = address - symbol.address;
+                    let _ = writeln!(content, "  {address:#x} <{}+{offset}>", symbol.symbol);
+                }
+                None => {
+                    let _ = writeln!(content, "  {address:#x}");
+                }
+            }
+        }
+    }
+    content
+}
+
+fn sorted_by_pages<K>(sums: BTreeMap<K, (usize, usize)>) -> Vec<(K, (usize, usize))> {
+    let mut sorted: Vec<_> = sums.into_iter().collect();
+    sorted.sort_by(|a, b| b.1 .0.cmp(&a.1 .0));
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, Depot, Owner, OwnerTag, Subsystem, BACKTRACE_DEPTH, MAX_BACKTRACES};
+
+    #[test_case]
+    fn equal_backtraces_are_stored_once() {
+        let mut depot = Depot::new();
+        let first = depot.insert(&[1; BACKTRACE_DEPTH]).unwrap();
+        let second = depot.insert(&[2; BACKTRACE_DEPTH]).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(depot.insert(&[1; BACKTRACE_DEPTH]), Some(first));
+        assert_eq!(depot.get(second), [2; BACKTRACE_DEPTH]);
+
+        for value in 3..=MAX_BACKTRACES {
+            assert!(depot.insert(&[value; BACKTRACE_DEPTH]).is_some());
+        }
+        assert!(depot.insert(&[0; BACKTRACE_DEPTH]).is_none());
+    }
+
+    #[test_case]
+    fn owners_are_sorted_by_pages() {
+        let process = Owner {
+            tag: OwnerTag::Process(3),
+            backtrace: None,
+        };
+        let heap = Owner::kernel(Subsystem::Heap);
+        let content = report(&[(heap, 1), (process, 4), (heap, 2), (process, 1)]);
+        let table = content.split_once("Owner\n").unwrap().1;
+        assert_eq!(table, "5\t2\tPID 3\n3\t2\theap\n");
+    }
+}
@@ -9,21 +9,22 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use common::{pointer::Pointer, unwrap_or_return, util::align_up};
+use common::{
+    pointer::Pointer,
+    unwrap_or_return,
+    util::{align_down, align_up},
+};
 
 use crate::{
     assert::static_assert_size,
     cpu::Cpu,
     debug, debugging,
-    interrupts::plic,
-    io::TEST_DEVICE_ADDRESSS,
     klibc::{
         elf,
         sizes::{GiB, MiB},
         util::{get_bit, get_multiple_bits, is_aligned, set_multiple_bits, set_or_clear_bit},
     },
     memory::page::PAGE_SIZE,
-    processes::timer,
 };
 
 use super::{
@@ -31,6 +32,9 @@ use super::{
     runtime_mappings::get_runtime_mappings,
 };
 
+// Sv39 page table entries hold 56 bit physical addresses
+pub const PHYSICAL_ADDRESS_LIMIT: usize = 1 << 56;
+
 #[derive(Clone)]
 pub struct MappingDescription {
     pub virtual_address_start: usize,
@@ -75,6 +79,45 @@ impl Display for MappingEntry {
     }
 }
 
+/// A contiguous range of the hardware page tables with the same
+/// attributes, as found by [RootPageTableHolder::walk]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub virtual_address: usize,
+    pub physical_address: usize,
+    pub size: usize,
+    pub privileges: XWRMode,
+    pub user_mode_accessible: bool,
+    pub name: String,
+}
+
+impl Mapping {
+    fn continues_with(&self, other: &Self) -> bool {
+        // The last page of the address space wraps around
+        self.virtual_address.wrapping_add(self.size) == other.virtual_address
+            && self.physical_address + self.size == other.physical_address
+            && self.privileges == other.privileges
+            && self.user_mode_accessible == other.user_mode_accessible
+            && self.name == other.name
+    }
+}
+
+impl Display for Mapping {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#018x} (Size: {:#010x}) ({:?}{})\t({})",
+            self.virtual_address,
+            self.virtual_address + (self.size - 1),
+            self.physical_address,
+            self.size,
+            self.privileges,
+            if self.user_mode_accessible { ", U" } else { "" },
+            self.name
+        )
+    }
+}
+
 pub struct RootPageTableHolder {
     root_table: *mut PageTable,
     already_mapped: Vec<MappingEntry>,
@@ -127,7 +170,7 @@ impl Drop for RootPageTableHolder {
 }
 
 impl RootPageTableHolder {
-    fn empty() -> Self {
+    pub fn empty() -> Self {
         let root_table = Box::leak(Box::new(PageTable::zero()));
         Self {
             root_table,
@@ -135,6 +178,29 @@ impl RootPageTableHolder {
         }
     }
 
+    /// The addresses of the tables themselves, which are freed on drop
+    pub fn table_pages(&self) -> Vec<usize> {
+        let table = self.table();
+        let mut pages = vec![table.get_physical_address()];
+        for first_level_entry in table.0.iter() {
+            if !first_level_entry.get_validity() || first_level_entry.is_leaf() {
+                continue;
+            }
+            let second_level_table = first_level_entry.get_target_page_table();
+            pages.push(second_level_table.get_physical_address());
+            for second_level_entry in second_level_table.0.iter() {
+                if !second_level_entry.get_validity() || second_level_entry.is_leaf() {
+                    continue;
+                }
+                let third_level_table = second_level_entry.get_physical_address();
+                if !third_level_table.is_null() {
+                    pages.push(third_level_table.addr());
+                }
+            }
+        }
+        pages
+    }
+
     fn table(&self) -> &PageTable {
         // SAFETY: It is always allocated
         unsafe { &*self.root_table }
@@ -162,8 +228,25 @@ impl RootPageTableHolder {
     pub fn new_with_kernel_mapping() -> Self {
         let mut root_page_table_holder = RootPageTableHolder::empty();
 
+        // The kernel image and the heap are mapped as one linear mapping so
+        // that huge pages can be used across section boundaries. The
+        // sections restrict the privileges afterwards.
+        let kernel_start = LinkerInformation::all_mappings()
+            .first()
+            .map_or(LinkerInformation::__start_symbols(), |mapping| {
+                mapping.virtual_address_start
+            });
+        let kernel_end = LinkerInformation::__start_heap() + heap_size();
+
+        root_page_table_holder.map_identity_kernel(
+            kernel_start,
+            kernel_end - kernel_start,
+            XWRMode::ReadWrite,
+            "HEAP".to_string(),
+        );
+
         for mapping in LinkerInformation::all_mappings() {
-            root_page_table_holder.map_identity_kernel(
+            root_page_table_holder.protect(
                 mapping.virtual_address_start,
                 mapping.size,
                 mapping.privileges,
@@ -171,41 +254,13 @@ impl RootPageTableHolder {
             );
         }
 
-        root_page_table_holder.map_identity_kernel(
+        root_page_table_holder.protect(
             LinkerInformation::__start_symbols(),
             debugging::symbols::symbols_size(),
             XWRMode::ReadOnly,
             "SYMBOLS".to_string(),
         );
 
-        root_page_table_holder.map_identity_kernel(
-            LinkerInformation::__start_heap(),
-            heap_size(),
-            XWRMode::ReadWrite,
-            "HEAP".to_string(),
-        );
-
-        root_page_table_holder.map_identity_kernel(
-            plic::PLIC_BASE,
-            plic::PLIC_SIZE,
-            XWRMode::ReadWrite,
-            "PLIC".to_string(),
-        );
-
-        root_page_table_holder.map_identity_kernel(
-            timer::CLINT_BASE,
-            timer::CLINT_SIZE,
-            XWRMode::ReadWrite,
-            "CLINT".to_string(),
-        );
-
-        root_page_table_holder.map_identity_kernel(
-            TEST_DEVICE_ADDRESSS,
-            PAGE_SIZE,
-            XWRMode::ReadWrite,
-            "Qemu Test Device".to_string(),
-        );
-
         for runtime_mapping in get_runtime_mappings() {
             root_page_table_holder.map_identity_kernel(
                 runtime_mapping.virtual_address_start,
@@ -236,29 +291,23 @@ impl RootPageTableHolder {
         );
     }
 
-    fn get_page_table_entry_for_address(&self, address: usize) -> Option<&PageTableEntry> {
-        let root_page_table = self.table();
+    /// Returns the leaf entry which maps the address together with the
+    /// size of the mapped page. Leaf entries can be on any level.
+    fn get_page_table_entry_for_address(&self, address: usize) -> Option<(&PageTableEntry, usize)> {
+        let mut table = self.table();
 
-        let first_level_entry = root_page_table.get_entry_for_virtual_address(address, 2);
-        if !first_level_entry.get_validity() {
-            return None;
-        }
-
-        let second_level_entry = first_level_entry
-            .get_target_page_table()
-            .get_entry_for_virtual_address(address, 1);
-        if !second_level_entry.get_validity() {
-            return None;
-        }
-
-        let third_level_entry = second_level_entry
-            .get_target_page_table()
-            .get_entry_for_virtual_address(address, 0);
-        if !third_level_entry.get_validity() {
-            return None;
+        for level in (0..=2).rev() {
+            let entry = table.get_entry_for_virtual_address(address, level);
+            if !entry.get_validity() {
+                return None;
+            }
+            if entry.is_leaf() {
+                return Some((entry, PageTable::page_size(level)));
+            }
+            table = entry.get_target_page_table();
         }
 
-        Some(third_level_entry)
+        None
     }
 
     pub fn map(
@@ -330,91 +379,204 @@ impl RootPageTableHolder {
         // We try to be smart and save memory by mapping as least as possible
 
         while offset < size {
-            // Check if we can map a 1GiB page
-            if can_be_mapped_with(GiB(1), offset) {
-                let first_level_entry = root_page_table
-                    .get_entry_for_virtual_address_mut(virtual_address_with_offset(offset), 2);
-
+            // Check if we can map a 1GiB page, then a 2MiB page
+            let level = if can_be_mapped_with(GiB(1), offset) {
+                2
+            } else if can_be_mapped_with(MiB(2), offset) {
+                1
+            } else {
                 assert!(
-                    !first_level_entry.get_validity()
-                        && first_level_entry.get_physical_address().is_null(),
-                    "Entry must be an invalid value and physical address must be zero"
+                    is_aligned(virtual_address_with_offset(offset), PAGE_SIZE),
+                    "Virtual address must be aligned with page size"
                 );
-                first_level_entry.set_xwr_mode(privileges);
-                first_level_entry.set_validity(true);
-                first_level_entry.set_leaf_address(physical_address_with_offset(offset));
-                first_level_entry.set_user_mode_accessible(is_user_mode_accessible);
-                offset += GiB(1);
-                continue;
-            }
-
-            // Check if we can map a 2MiB page
-            if can_be_mapped_with(MiB(2), offset) {
-                let first_level_entry = root_page_table
-                    .get_entry_for_virtual_address_mut(virtual_address_with_offset(offset), 2);
-                if first_level_entry.get_physical_address().is_null() {
-                    let page = Box::leak(Box::new(PageTable::zero()));
-                    first_level_entry.set_physical_address(&mut *page);
-                    first_level_entry.set_validity(true);
-                }
-
-                let second_level_entry = first_level_entry
-                    .get_target_page_table()
-                    .get_entry_for_virtual_address_mut(virtual_address_with_offset(offset), 1);
                 assert!(
-                    !second_level_entry.get_validity()
-                        && second_level_entry.get_physical_address().is_null(),
-                    "Entry must be an invalid value and physical address must be zero"
+                    is_aligned(physical_address_with_offset(offset), PAGE_SIZE),
+                    "Physical address must be aligned with page size"
                 );
+                0
+            };
 
-                second_level_entry.set_xwr_mode(privileges);
-                second_level_entry.set_validity(true);
-                second_level_entry.set_leaf_address(physical_address_with_offset(offset));
-                second_level_entry.set_user_mode_accessible(is_user_mode_accessible);
-                offset += MiB(2);
-                continue;
-            }
+            let entry =
+                root_page_table.get_or_create_entry_mut(virtual_address_with_offset(offset), level);
 
             assert!(
-                is_aligned(virtual_address_with_offset(offset), PAGE_SIZE),
-                "Virtual address must be aligned with page size"
-            );
-            assert!(
-                is_aligned(physical_address_with_offset(offset), PAGE_SIZE),
-                "Physical address must be aligned with page size"
+                !entry.get_validity() && entry.get_physical_address().is_null(),
+                "Entry must be an invalid value and physical address must be zero"
             );
 
-            // Map single page
-            let first_level_entry = root_page_table
-                .get_entry_for_virtual_address_mut(virtual_address_with_offset(offset), 2);
-            if first_level_entry.get_physical_address().is_null() {
-                let page = Box::leak(Box::new(PageTable::zero()));
-                first_level_entry.set_physical_address(&mut *page);
-                first_level_entry.set_validity(true);
-            }
+            entry.set_xwr_mode(privileges);
+            entry.set_validity(true);
+            entry.set_leaf_address(physical_address_with_offset(offset));
+            entry.set_user_mode_accessible(is_user_mode_accessible);
 
-            let second_level_entry = first_level_entry
-                .get_target_page_table()
-                .get_entry_for_virtual_address_mut(virtual_address_with_offset(offset), 1);
-            if second_level_entry.get_physical_address().is_null() {
-                let page = Box::leak(Box::new(PageTable::zero()));
-                second_level_entry.set_physical_address(&mut *page);
-                second_level_entry.set_validity(true);
-            }
+            offset += PageTable::page_size(level);
+        }
+    }
 
-            let third_level_entry = second_level_entry
-                .get_target_page_table()
-                .get_entry_for_virtual_address_mut(virtual_address_with_offset(offset), 0);
+    /// Changes the privileges of an already mapped range. Huge pages
+    /// which are only partially covered by the range are split into
+    /// smaller pages first. The page table must not be active.
+    pub fn protect(
+        &mut self,
+        virtual_address_start: usize,
+        mut size: usize,
+        privileges: XWRMode,
+        name: String,
+    ) {
+        assert_eq!(virtual_address_start % PAGE_SIZE, 0);
+        assert!(size > 0);
+        assert!(!self.is_active(), "Page table is changed while active");
 
-            assert!(!third_level_entry.get_validity());
+        size = align_up(size, PAGE_SIZE);
+
+        let virtual_end = virtual_address_start.wrapping_add(size - 1);
+
+        debug!(
+            "Protect \t{:#018x}-{:#018x} (Size: {:#010x}) ({:?})\t({})",
+            virtual_address_start, virtual_end, size, privileges, name
+        );
+
+        let mut offset = 0;
+
+        while offset < size {
+            let address = virtual_address_start + offset;
+            let remaining = size - offset;
+
+            let mut table = self.table_mut();
+            let mut level = 2;
+
+            offset += loop {
+                let entry = table.get_entry_for_virtual_address_mut(address, level);
+                assert!(
+                    entry.get_validity(),
+                    "Cannot protect {name}. Address {address:#x} is not mapped"
+                );
+                if entry.is_leaf() {
+                    let page_size = PageTable::page_size(level);
+                    let is_covered = is_aligned(address, page_size) && remaining >= page_size;
+                    if is_covered || entry.get_xwr_mode() == privileges {
+                        entry.set_xwr_mode(privileges);
+                        break page_size - address % page_size;
+                    }
+                    entry.split(level);
+                }
+                table = entry.get_target_page_table();
+                level -= 1;
+            };
+        }
+
+        self.split_mapping_entries(virtual_address_start..virtual_end, privileges, name);
+    }
+
+    /// Points an already mapped page to other physical memory, e.g. when a
+    /// shared page is copied on write. A huge page which contains the page
+    /// is split first. The page table must not be active.
+    pub fn remap_page(
+        &mut self,
+        virtual_address: usize,
+        physical_address: usize,
+        privileges: XWRMode,
+        name: String,
+    ) {
+        assert_eq!(virtual_address % PAGE_SIZE, 0);
+        assert_eq!(physical_address % PAGE_SIZE, 0);
+        assert!(!self.is_active(), "Page table is changed while active");
+
+        debug!(
+            "Remap \t{:#018x} -> {:#018x} ({:?})\t({})",
+            virtual_address, physical_address, privileges, name
+        );
+
+        let entry = self.leaf_page_entry_mut(virtual_address);
+        entry.set_leaf_address(physical_address);
+        entry.set_xwr_mode(privileges);
+
+        self.split_mapping_entries(
+            virtual_address..virtual_address + PAGE_SIZE - 1,
+            privileges,
+            name,
+        );
+    }
+
+    /// Removes a page which was mapped on its own or as part of a huge
+    /// page. The page table must not be active.
+    pub fn unmap_page(&mut self, virtual_address: usize) {
+        assert_eq!(virtual_address % PAGE_SIZE, 0);
+        assert!(!self.is_active(), "Page table is changed while active");
+
+        debug!("Unmap \t{:#018x}", virtual_address);
+
+        let entry = self.leaf_page_entry_mut(virtual_address);
+        entry.set_validity(false);
+        entry.set_leaf_address(0);
+        entry.set_xwr_mode(XWRMode::PointerToNextLevel);
+        entry.set_user_mode_accessible(false);
+
+        self.remove_mapping_entries(virtual_address..virtual_address + PAGE_SIZE - 1);
+    }
+
+    /// Returns the entry which maps the page on the lowest level, huge
+    /// pages which contain it are split
+    fn leaf_page_entry_mut(&mut self, virtual_address: usize) -> &mut PageTableEntry {
+        let mut table = self.table_mut();
+        for level in (1..=2).rev() {
+            let entry = table.get_entry_for_virtual_address_mut(virtual_address, level);
+            assert!(
+                entry.get_validity(),
+                "Address {virtual_address:#x} is not mapped"
+            );
+            if entry.is_leaf() {
+                entry.split(level);
+            }
+            table = entry.get_target_page_table();
+        }
+        let entry = table.get_entry_for_virtual_address_mut(virtual_address, 0);
+        assert!(
+            entry.get_validity(),
+            "Address {virtual_address:#x} is not mapped"
+        );
+        entry
+    }
 
-            third_level_entry.set_xwr_mode(privileges);
-            third_level_entry.set_validity(true);
-            third_level_entry.set_leaf_address(physical_address_with_offset(offset));
-            third_level_entry.set_user_mode_accessible(is_user_mode_accessible);
+    /// Replaces the part of the already mapped entries which overlaps
+    /// with range by a new entry
+    fn split_mapping_entries(&mut self, range: Range<usize>, privileges: XWRMode, name: String) {
+        self.remove_mapping_entries(range.clone());
+        self.already_mapped
+            .push(MappingEntry::new(range, name, privileges));
+        self.already_mapped
+            .sort_by_key(|mapping| mapping.virtual_range.start);
+    }
 
-            offset += PAGE_SIZE;
+    /// Cuts the range out of the already mapped entries
+    fn remove_mapping_entries(&mut self, range: Range<usize>) {
+        let mut mappings = Vec::with_capacity(self.already_mapped.len() + 1);
+        for mapping in self.already_mapped.drain(..) {
+            if !mapping.contains(range.clone()) {
+                mappings.push(mapping);
+                continue;
+            }
+            let MappingEntry {
+                virtual_range,
+                name,
+                privileges,
+            } = mapping;
+            if virtual_range.start < range.start {
+                mappings.push(MappingEntry::new(
+                    virtual_range.start..range.start - 1,
+                    name.clone(),
+                    privileges,
+                ));
+            }
+            if virtual_range.end > range.end {
+                mappings.push(MappingEntry::new(
+                    range.end + 1..virtual_range.end,
+                    name,
+                    privileges,
+                ));
+            }
         }
+        self.already_mapped = mappings;
     }
 
     pub fn map_identity_kernel(
@@ -445,9 +607,22 @@ impl RootPageTableHolder {
         );
     }
 
+    /// Returns start address, size and privileges of all mappings which are
+    /// accessible from user mode. The size is returned instead of an end
+    /// address because the stack ends at the very top of the address space.
+    pub fn userspace_mappings(&self) -> impl Iterator<Item = (usize, usize, XWRMode)> + '_ {
+        self.already_mapped
+            .iter()
+            .filter(|mapping| self.is_userspace_address(mapping.virtual_range.start))
+            .map(|mapping| {
+                let range = &mapping.virtual_range;
+                (range.start, range.end - range.start + 1, mapping.privileges)
+            })
+    }
+
     pub fn is_userspace_address(&self, address: usize) -> bool {
         self.get_page_table_entry_for_address(address)
-            .is_some_and(|entry| entry.get_validity() && entry.get_user_mode_accessible())
+            .is_some_and(|(entry, _)| entry.get_validity() && entry.get_user_mode_accessible())
     }
 
     pub fn is_valid_userspace_fat_ptr<PTR: Pointer>(
@@ -458,9 +633,11 @@ impl RootPageTableHolder {
     ) -> bool {
         let start = ptr.as_raw();
         let end = start + (core::mem::size_of::<PTR::Pointee>() * len);
-        // We only need to check for each PAGE_SIZE step if it is mapped
-        for addr in (start..end).step_by(PAGE_SIZE) {
-            let entry = unwrap_or_return!(self.get_page_table_entry_for_address(addr), false);
+        // We only need to check for each PAGE_SIZE step if it is mapped.
+        // Stepping from the page start includes the last page of unaligned
+        // buffers.
+        for addr in (align_down(start, PAGE_SIZE)..end).step_by(PAGE_SIZE) {
+            let (entry, _) = unwrap_or_return!(self.get_page_table_entry_for_address(addr), false);
             let xwr = entry.get_xwr_mode();
             if !entry.get_validity()
                 || !entry.get_user_mode_accessible()
@@ -475,6 +652,19 @@ impl RootPageTableHolder {
         true
     }
 
+    /// Whether the mapped range is backed by one piece of physical memory
+    pub fn is_physically_contiguous(&self, start: usize, size: usize) -> bool {
+        let Some((physical_start, _)) = self.translate(start) else {
+            return false;
+        };
+        (align_up(start + 1, PAGE_SIZE)..start + size)
+            .step_by(PAGE_SIZE)
+            .all(|address| {
+                self.translate(address)
+                    .is_some_and(|(physical, _)| physical == physical_start + (address - start))
+            })
+    }
+
     pub fn is_valid_userspace_ptr(&self, ptr: impl Pointer, writable: bool) -> bool {
         self.is_valid_userspace_fat_ptr(ptr, 1, writable)
     }
@@ -488,12 +678,68 @@ impl RootPageTableHolder {
             return None;
         }
 
-        let offset_from_page_start = address % PAGE_SIZE;
-        self.get_page_table_entry_for_address(address).map(|entry| {
-            PTR::as_pointer(entry.get_physical_address() as usize + offset_from_page_start)
+        self.get_page_table_entry_for_address(address)
+            .map(|(entry, page_size)| {
+                PTR::as_pointer(entry.get_physical_address() as usize + address % page_size)
+            })
+    }
+
+    /// Translates any mapped address and returns the
+    /// privileges of the containing page.
+    pub fn translate(&self, address: usize) -> Option<(usize, XWRMode)> {
+        let (entry, page_size) = self.get_page_table_entry_for_address(address)?;
+        let physical_address = entry.get_physical_address() as usize + address % page_size;
+        Some((physical_address, entry.get_xwr_mode()))
+    }
+
+    /// Walks the hardware page tables instead of trusting the list of
+    /// requested mappings. Neighbouring pages are merged if they continue
+    /// the physical memory with the same attributes.
+    pub fn walk(&self) -> Vec<Mapping> {
+        let mut mappings: Vec<Mapping> = Vec::new();
+        self.table()
+            .for_each_leaf(2, 0, &mut |virtual_address, entry, page_size| {
+                let mapping = Mapping {
+                    virtual_address,
+                    physical_address: entry.get_physical_address().addr(),
+                    size: page_size,
+                    privileges: entry.get_xwr_mode(),
+                    user_mode_accessible: entry.get_user_mode_accessible(),
+                    name: self.mapping_name(virtual_address),
+                };
+                match mappings.last_mut() {
+                    Some(last) if last.continues_with(&mapping) => last.size += mapping.size,
+                    _ => mappings.push(mapping),
+                }
+            });
+        mappings
+    }
+
+    /// Whether any part of the range is mapped already, the range must not
+    /// be empty
+    pub fn is_mapped(&self, range: Range<usize>) -> bool {
+        self.already_mapped
+            .iter()
+            .any(|mapping| mapping.contains(range.start..range.end - 1))
+    }
+
+    /// Whether any page maps physical memory inside the range
+    pub fn maps_physical(&self, physical_range: &Range<usize>) -> bool {
+        self.walk().iter().any(|mapping| {
+            mapping.physical_address < physical_range.end
+                && physical_range.start < mapping.physical_address + mapping.size
         })
     }
 
+    fn mapping_name(&self, virtual_address: usize) -> String {
+        self.already_mapped
+            .iter()
+            .find(|m| {
+                m.virtual_range.start <= virtual_address && virtual_address <= m.virtual_range.end
+            })
+            .map_or("<unknown>".to_string(), |m| m.name.clone())
+    }
+
     pub fn get_satp_value_from_page_tables(&self) -> usize {
         let page_table_address = self.table().get_physical_address();
 
@@ -547,9 +793,64 @@ impl PageTable {
         &self.0[index]
     }
 
+    /// Size of the memory which is mapped by a leaf entry on the level
+    fn page_size(level: u8) -> usize {
+        assert!(level <= 2);
+        PAGE_SIZE << (9 * level)
+    }
+
+    /// Returns the entry on the level for the virtual address. Missing
+    /// page tables on the way are allocated and huge pages are split.
+    fn get_or_create_entry_mut(
+        &mut self,
+        virtual_address: usize,
+        level: u8,
+    ) -> &mut PageTableEntry {
+        let mut table = self;
+        for current_level in (level + 1..=2).rev() {
+            let entry = table.get_entry_for_virtual_address_mut(virtual_address, current_level);
+            if entry.get_physical_address().is_null() {
+                let page = Box::leak(Box::new(PageTable::zero()));
+                entry.set_physical_address(&mut *page);
+                entry.set_validity(true);
+            } else if entry.is_leaf() {
+                entry.split(current_level);
+            }
+            table = entry.get_target_page_table();
+        }
+        table.get_entry_for_virtual_address_mut(virtual_address, level)
+    }
+
     fn get_physical_address(&self) -> usize {
         (self as *const Self).addr()
     }
+
+    /// Calls f for every valid leaf entry in the order of the virtual
+    /// addresses with the virtual address and the size of the page
+    fn for_each_leaf(
+        &self,
+        level: u8,
+        base_address: usize,
+        f: &mut impl FnMut(usize, &PageTableEntry, usize),
+    ) {
+        for (index, entry) in self.0.iter().enumerate() {
+            if !entry.get_validity() {
+                continue;
+            }
+            let mut virtual_address = base_address | (index << (12 + 9 * level));
+            // Sv39 addresses are sign extended from bit 38
+            if level == 2 && index >= 256 {
+                virtual_address |= !((1 << 39) - 1);
+            }
+            if entry.is_leaf() {
+                f(virtual_address, entry, Self::page_size(level));
+            } else {
+                entry
+                    .get_target_page_table()
+                    .for_each_leaf(level - 1, virtual_address, f);
+            }
+        }
+    }
 }
 
 #[repr(transparent)]
@@ -665,6 +966,23 @@ impl PageTableEntry {
         })
     }
 
+    /// Replaces a huge page by a page table which maps the same memory
+    /// with smaller pages and the same attributes
+    fn split(&mut self, level: u8) {
+        assert!(level > 0 && self.is_leaf());
+        let page_size = PageTable::page_size(level - 1);
+        let physical_address = self.get_physical_address().addr();
+        let table = Box::leak(Box::new(PageTable::zero()));
+        for (index, entry) in table.0.iter_mut().enumerate() {
+            *entry = *self;
+            entry.set_leaf_address(physical_address + index * page_size);
+        }
+        // The user bit is reserved for non-leaf entries
+        self.set_xwr_mode(XWRMode::PointerToNextLevel);
+        self.set_user_mode_accessible(false);
+        self.set_physical_address(&mut *table);
+    }
+
     fn get_target_page_table(&self) -> &'static mut PageTable {
         assert!(!self.is_leaf());
         assert!(!self.get_physical_address().is_null());
@@ -675,7 +993,11 @@ impl PageTableEntry {
 
 #[cfg(test)]
 mod tests {
-    use super::RootPageTableHolder;
+    use super::{Mapping, RootPageTableHolder, XWRMode};
+    use crate::{
+        klibc::sizes::{GiB, MiB},
+        memory::page::PAGE_SIZE,
+    };
     use alloc::string::ToString;
 
     #[test_case]
@@ -689,4 +1011,208 @@ mod tests {
             "Test".to_string(),
         );
     }
+
+    #[test_case]
+    fn huge_pages_are_translated() {
+        let mut page_table = RootPageTableHolder::empty();
+        page_table.map(
+            GiB(1),
+            GiB(2),
+            GiB(1) + MiB(2) + PAGE_SIZE,
+            XWRMode::ReadWrite,
+            false,
+            "Test".to_string(),
+        );
+
+        let page_size = |address| {
+            page_table
+                .get_page_table_entry_for_address(address)
+                .map(|(_, page_size)| page_size)
+        };
+        assert_eq!(page_size(GiB(1)), Some(GiB(1)));
+        assert_eq!(page_size(GiB(2)), Some(MiB(2)));
+        assert_eq!(page_size(GiB(2) + MiB(2)), Some(PAGE_SIZE));
+        assert_eq!(page_size(GiB(2) + MiB(2) + PAGE_SIZE), None);
+
+        assert_eq!(
+            page_table.translate(GiB(1) + MiB(5) + 0x123),
+            Some((GiB(2) + MiB(5) + 0x123, XWRMode::ReadWrite))
+        );
+        assert_eq!(
+            page_table.translate(GiB(2) + 0x456),
+            Some((GiB(3) + 0x456, XWRMode::ReadWrite))
+        );
+    }
+
+    #[test_case]
+    fn protect_splits_huge_pages() {
+        let mut page_table = RootPageTableHolder::empty();
+        page_table.map(
+            MiB(2),
+            MiB(6),
+            MiB(4),
+            XWRMode::ReadWrite,
+            false,
+            "Test".to_string(),
+        );
+        page_table.protect(
+            MiB(2) + PAGE_SIZE,
+            PAGE_SIZE,
+            XWRMode::ReadOnly,
+            "Protected".to_string(),
+        );
+
+        assert_eq!(
+            page_table.translate(MiB(2) + PAGE_SIZE + 0x10),
+            Some((MiB(6) + PAGE_SIZE + 0x10, XWRMode::ReadOnly))
+        );
+        assert_eq!(
+            page_table.translate(MiB(2)),
+            Some((MiB(6), XWRMode::ReadWrite))
+        );
+        assert_eq!(
+            page_table.translate(MiB(4) - PAGE_SIZE),
+            Some((MiB(8) - PAGE_SIZE, XWRMode::ReadWrite))
+        );
+
+        // Only the partially covered huge page is split
+        let page_size = |address| {
+            page_table
+                .get_page_table_entry_for_address(address)
+                .map(|(_, page_size)| page_size)
+        };
+        assert_eq!(page_size(MiB(2)), Some(PAGE_SIZE));
+        assert_eq!(page_size(MiB(4)), Some(MiB(2)));
+
+        let names: alloc::vec::Vec<_> = page_table
+            .already_mapped
+            .iter()
+            .map(|mapping| mapping.name.as_str())
+            .collect();
+        assert_eq!(names, ["Test", "Protected", "Test"]);
+    }
+
+    #[test_case]
+    fn remap_page_replaces_the_physical_page() {
+        let mut page_table = RootPageTableHolder::empty();
+        page_table.map(
+            MiB(2),
+            MiB(6),
+            MiB(2),
+            XWRMode::ReadOnly,
+            true,
+            "Test".to_string(),
+        );
+        page_table.remap_page(
+            MiB(2) + PAGE_SIZE,
+            GiB(1),
+            XWRMode::ReadWrite,
+            "Copy".to_string(),
+        );
+
+        assert_eq!(
+            page_table.translate(MiB(2) + PAGE_SIZE + 0x10),
+            Some((GiB(1) + 0x10, XWRMode::ReadWrite))
+        );
+        assert_eq!(
+            page_table.translate(MiB(2) + 2 * PAGE_SIZE),
+            Some((MiB(6) + 2 * PAGE_SIZE, XWRMode::ReadOnly))
+        );
+        assert!(page_table.is_userspace_address(MiB(2) + PAGE_SIZE));
+    }
+
+    #[test_case]
+    fn unmapped_pages_can_be_mapped_again() {
+        let mut page_table = RootPageTableHolder::empty();
+        page_table.map_userspace(
+            0x1000,
+            0x10000,
+            3 * PAGE_SIZE,
+            XWRMode::ReadWrite,
+            "Test".to_string(),
+        );
+        page_table.unmap_page(0x2000);
+        assert_eq!(page_table.translate(0x2000), None);
+        assert!(!page_table.is_mapped(0x2000..0x3000));
+        assert!(page_table.is_mapped(0x2000..0x3001));
+        assert_eq!(
+            page_table.translate(0x3000),
+            Some((0x12000, XWRMode::ReadWrite))
+        );
+        assert_eq!(page_table.already_mapped.len(), 2);
+
+        page_table.map_userspace(
+            0x2000,
+            0x20000,
+            PAGE_SIZE,
+            XWRMode::ReadOnly,
+            "Again".to_string(),
+        );
+        assert_eq!(
+            page_table.translate(0x2000),
+            Some((0x20000, XWRMode::ReadOnly))
+        );
+    }
+
+    #[test_case]
+    fn walk_merges_contiguous_pages() {
+        let mut page_table = RootPageTableHolder::empty();
+        page_table.map(
+            MiB(2) - PAGE_SIZE,
+            MiB(8) - PAGE_SIZE,
+            MiB(2) + PAGE_SIZE,
+            XWRMode::ReadWrite,
+            false,
+            "Data".to_string(),
+        );
+        page_table.map_userspace(
+            usize::MAX - 2 * PAGE_SIZE + 1,
+            0x3000,
+            2 * PAGE_SIZE,
+            XWRMode::ReadOnly,
+            "Stack".to_string(),
+        );
+
+        assert_eq!(
+            page_table.walk(),
+            [
+                Mapping {
+                    virtual_address: MiB(2) - PAGE_SIZE,
+                    physical_address: MiB(8) - PAGE_SIZE,
+                    size: MiB(2) + PAGE_SIZE,
+                    privileges: XWRMode::ReadWrite,
+                    user_mode_accessible: false,
+                    name: "Data".to_string(),
+                },
+                Mapping {
+                    virtual_address: usize::MAX - 2 * PAGE_SIZE + 1,
+                    physical_address: 0x3000,
+                    size: 2 * PAGE_SIZE,
+                    privileges: XWRMode::ReadOnly,
+                    user_mode_accessible: true,
+                    name: "Stack".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test_case]
+    fn maps_physical_compares_physical_addresses() {
+        let mut page_table = RootPageTableHolder::empty();
+        page_table.map_userspace(
+            MiB(2),
+            MiB(8),
+            2 * PAGE_SIZE,
+            XWRMode::ReadWrite,
+            "Device".to_string(),
+        );
+
+        assert!(page_table.maps_physical(&(MiB(8)..MiB(8) + PAGE_SIZE)));
+        assert!(page_table.maps_physical(&(MiB(8) - PAGE_SIZE..MiB(8) + 1)));
+        assert!(page_table.maps_physical(&(MiB(8) + PAGE_SIZE..MiB(9))));
+        assert!(!page_table.maps_physical(&(MiB(8) - PAGE_SIZE..MiB(8))));
+        assert!(!page_table.maps_physical(&(MiB(8) + 2 * PAGE_SIZE..MiB(9))));
+        // Only the physical side counts
+        assert!(!page_table.maps_physical(&(MiB(2)..MiB(2) + PAGE_SIZE)));
+    }
 }
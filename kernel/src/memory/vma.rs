@@ -0,0 +1,654 @@
+//! Areas of the address space of a process whose pages are only mapped
+//! when they are touched for the first time. Pages are shared as long as
+//! they are only read: the pages of files come from the page cache and
+//! writable program segments start with the cached initial content.
+//! Writable areas get a private copy of a page on the first write, the
+//! changes are not written back to the file. Anonymous areas, like the
+//! heap below the program break, get zeroed private pages.
+//!
+//! The kernel accesses the buffers of syscalls through their physical
+//! address. Therefore all private pages of an area live in one
+//! physically contiguous backing, which is allocated on the first
+//! private page and moved when an anonymous area outgrows it.
+//!
+//! Devices may read private pages directly, e.g. the payload of a
+//! datagram, see [`pin`]. While an area is pinned its backing is never
+//! changed in place or freed: resizing works on a copy and the old
+//! backing is retired until the last pin is dropped.
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use common::{mutex::Mutex, util::align_down};
+use core::ops::{Deref, Range};
+
+use crate::{
+    fs::vfs::{FsError, Inode, OpenFile},
+    memory::{
+        dma::DmaBuffer,
+        page::{Page, Pages, PinnedHeapPages},
+        page_cache::{self, CachedContent, PageCacheKey, SharedPages},
+        page_tables::XWRMode,
+        PAGE_SIZE,
+    },
+    processes::process_table::ProcessRef,
+    warn,
+};
+
+#[derive(Clone)]
+pub enum LoadedPage {
+    // Mapped read-only, `index` is the page within the shared pages
+    Shared { pages: SharedPages, index: usize },
+    // Lives in the backing of the area
+    Private,
+}
+
+#[derive(Clone)]
+pub struct MappedFile {
+    inode: Arc<dyn Inode>,
+    // Normalized, used as key of the page cache
+    path: String,
+    // Of the first page of the area, page aligned
+    offset: usize,
+}
+
+impl MappedFile {
+    pub fn new(file: &OpenFile, offset: usize) -> Self {
+        assert_eq!(offset % PAGE_SIZE, 0, "Offset must be page aligned");
+        Self {
+            inode: file.inode().clone(),
+            path: file.path().into(),
+            offset,
+        }
+    }
+
+    /// Behind the end of the file the page is filled with zeros
+    fn read_page(&self, offset: usize) -> Result<PinnedHeapPages, FsError> {
+        let mut page = PinnedHeapPages::new(1);
+        let buffer = page.as_u8_slice();
+        let mut done = 0;
+        while done < PAGE_SIZE {
+            match self.inode.read_at(offset + done, &mut buffer[done..])? {
+                0 => break,
+                count => done += count,
+            }
+        }
+        Ok(page)
+    }
+
+    /// `offset` is relative to the start of the area
+    fn cached_page(&self, offset: usize) -> Result<SharedPages, FsError> {
+        let offset = self.offset + offset;
+        let key = PageCacheKey {
+            content: CachedContent::FilePage,
+            file: self.path.clone(),
+            offset,
+        };
+        page_cache::try_get_or_insert_with(key, || self.read_page(offset))
+    }
+}
+
+#[derive(Clone)]
+pub enum Source {
+    File(MappedFile),
+    // The initial content of a writable program segment
+    Segment(SharedPages),
+    Anonymous,
+}
+
+impl Source {
+    /// The page as long as it isn't written. `offset` is relative to the
+    /// start of the area.
+    fn initial_page(&self, offset: usize) -> Result<LoadedPage, FsError> {
+        match self {
+            Source::File(file) => file
+                .cached_page(offset)
+                .map(|pages| LoadedPage::Shared { pages, index: 0 }),
+            Source::Segment(pages) => Ok(LoadedPage::Shared {
+                pages: pages.clone(),
+                index: offset / PAGE_SIZE,
+            }),
+            Source::Anonymous => Ok(LoadedPage::Private),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Source::File(file) => format!("File {}", file.path),
+            Source::Segment(_) => "LOAD".into(),
+            Source::Anonymous => "Heap".into(),
+        }
+    }
+}
+
+pub struct Vma {
+    range: Range<usize>,
+    privileges: XWRMode,
+    source: Source,
+    // Indexed by the offset of the page within the area
+    pages: BTreeMap<usize, LoadedPage>,
+    // Private pages at their offset within the area
+    backing: Option<PinnedHeapPages>,
+    // Every pin holds a reference, backings which were replaced while
+    // pinned are kept here
+    retired: Retired,
+}
+
+type Retired = Arc<Mutex<Vec<PinnedHeapPages>>>;
+
+/// The changes to the page table after an area was resized
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Resized {
+    // Loaded pages which were cut off
+    pub unmapped: Vec<usize>,
+    // Private pages with their new physical address if the backing moved
+    pub moved: Vec<(usize, usize)>,
+}
+
+impl core::fmt::Debug for Vma {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Vma({:#x}-{:#x} {:?} {}, {} pages loaded)",
+            self.range.start,
+            self.range.end,
+            self.privileges,
+            self.name(),
+            self.pages.len()
+        )
+    }
+}
+
+impl Vma {
+    pub fn new(start: usize, size: usize, privileges: XWRMode, source: Source) -> Self {
+        assert_eq!(start % PAGE_SIZE, 0, "Area must be page aligned");
+        assert_eq!(size % PAGE_SIZE, 0, "Area must consist of whole pages");
+        Self {
+            range: start..start + size,
+            privileges,
+            source,
+            pages: BTreeMap::new(),
+            backing: None,
+            retired: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        self.range.contains(&address)
+    }
+
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    /// Grows or shrinks the area at its end. Private pages which are cut
+    /// off are zeroed in case the area grows again. The backing at least
+    /// doubles if the area outgrows it, a pinned backing is always
+    /// replaced by a copy.
+    pub fn resize(&mut self, size: usize) -> Resized {
+        assert_eq!(size % PAGE_SIZE, 0, "Area must consist of whole pages");
+        self.release_retired();
+        self.range.end = self.range.start + size;
+        let removed = self.pages.split_off(&size);
+
+        let mut moved = Vec::new();
+        let capacity = self.backing.as_ref().map(|backing| backing.len());
+        if let Some(capacity) =
+            capacity.filter(|capacity| *capacity < self.number_of_pages() || self.is_pinned())
+        {
+            let outgrown = capacity < self.number_of_pages();
+            let mut replacement = PinnedHeapPages::new(if outgrown {
+                self.number_of_pages().max(2 * capacity)
+            } else {
+                capacity
+            });
+            let backing = self.backing.take().expect("Backing must be allocated");
+            replacement[..capacity].clone_from_slice(&backing[..]);
+            if self.is_pinned() {
+                self.retired.lock().push(backing);
+            }
+            self.backing = Some(replacement);
+            moved = self
+                .pages
+                .iter()
+                .filter(|(_, page)| matches!(page, LoadedPage::Private))
+                .map(|(offset, _)| (self.range.start + offset, self.backing_address(*offset)))
+                .collect();
+        }
+
+        if let Some(backing) = &mut self.backing {
+            for (offset, page) in &removed {
+                if let LoadedPage::Private = page {
+                    backing[offset / PAGE_SIZE].fill(0);
+                }
+            }
+        }
+        let unmapped = removed
+            .into_keys()
+            .map(|offset| self.range.start + offset)
+            .collect();
+        Resized { unmapped, moved }
+    }
+
+    fn is_pinned(&self) -> bool {
+        Arc::strong_count(&self.retired) > 1
+    }
+
+    /// Pins are only taken with the process locked, there is no new one
+    /// while the retired backings are freed
+    fn release_retired(&self) {
+        if !self.is_pinned() {
+            self.retired.lock().clear();
+        }
+    }
+
+    /// Pins the range if all of its pages are private. The backing stays
+    /// where it is until the returned reference is dropped.
+    fn pin(&self, range: Range<usize>) -> Option<(Retired, usize)> {
+        self.release_retired();
+        if range.is_empty() || range.start < self.range.start || range.end > self.range.end {
+            return None;
+        }
+        let start = self.offset_of(range.start);
+        let end = self.offset_of(range.end - 1);
+        let is_private = (start..=end)
+            .step_by(PAGE_SIZE)
+            .all(|offset| matches!(self.pages.get(&offset), Some(LoadedPage::Private)));
+        if !is_private {
+            return None;
+        }
+        let address = self.backing_address(start) + range.start % PAGE_SIZE;
+        Some((self.retired.clone(), address))
+    }
+
+    /// Pages which are not loaded yet count as well
+    /// The private pages which are freed together with the area. A
+    /// pinned backing is kept until the pin is released.
+    pub fn owned_pages(&self) -> Option<&PinnedHeapPages> {
+        self.backing.as_ref().filter(|_| !self.is_pinned())
+    }
+
+    pub fn number_of_pages(&self) -> usize {
+        self.range.len() / PAGE_SIZE
+    }
+
+    pub fn name(&self) -> String {
+        self.source.name()
+    }
+
+    fn is_writable(&self) -> bool {
+        matches!(
+            self.privileges,
+            XWRMode::ReadWrite | XWRMode::ReadWriteExecute
+        )
+    }
+
+    fn offset_of(&self, address: usize) -> usize {
+        align_down(address, PAGE_SIZE) - self.range.start
+    }
+
+    fn backing_address(&self, offset: usize) -> usize {
+        let backing = self.backing.as_ref().expect("Backing must be allocated");
+        backing[offset / PAGE_SIZE..].as_ptr() as usize
+    }
+
+    /// Allocates the backing for the whole area if necessary
+    fn backing_page(&mut self, offset: usize) -> &mut Page {
+        let number_of_pages = self.number_of_pages();
+        let backing = self
+            .backing
+            .get_or_insert_with(|| PinnedHeapPages::new(number_of_pages));
+        &mut backing[offset / PAGE_SIZE]
+    }
+
+    /// Shared pages are mapped without write access
+    fn privileges_of(&self, page: &LoadedPage) -> XWRMode {
+        match (page, self.privileges) {
+            (LoadedPage::Shared { .. }, XWRMode::ReadWrite) => XWRMode::ReadOnly,
+            (LoadedPage::Shared { .. }, XWRMode::ReadWriteExecute) => XWRMode::ReadExecute,
+            (_, privileges) => privileges,
+        }
+    }
+
+    /// The page if it is loaded and shared with others
+    pub fn shared_page(&self, address: usize) -> Option<LoadedPage> {
+        self.pages
+            .get(&self.offset_of(address))
+            .filter(|page| matches!(page, LoadedPage::Shared { .. }))
+            .cloned()
+    }
+
+    /// Whether the access needs a new page
+    fn needs_page(&self, address: usize, write: bool) -> bool {
+        match self.pages.get(&self.offset_of(address)) {
+            None => true,
+            Some(LoadedPage::Shared { .. }) => write && self.is_writable(),
+            Some(LoadedPage::Private) => false,
+        }
+    }
+
+    /// Stores the page, a shared page is copied into the backing if
+    /// `private` is set. Returns the physical address together with the
+    /// privileges it is mapped with and whether it replaces a shared page.
+    pub fn insert(
+        &mut self,
+        address: usize,
+        page: LoadedPage,
+        private: bool,
+    ) -> (usize, XWRMode, bool) {
+        let offset = self.offset_of(address);
+        let page = match page {
+            LoadedPage::Shared { pages, index } if private => {
+                self.backing_page(offset).copy_from_slice(&pages[index][..]);
+                LoadedPage::Private
+            }
+            LoadedPage::Private => {
+                self.backing_page(offset);
+                LoadedPage::Private
+            }
+            page => page,
+        };
+        let physical_address = match &page {
+            LoadedPage::Shared { pages, index } => pages[*index..].as_ptr() as usize,
+            LoadedPage::Private => self.backing_address(offset),
+        };
+        let privileges = self.privileges_of(&page);
+        let previous = self.pages.insert(offset, page);
+        assert!(
+            !matches!(previous, Some(LoadedPage::Private)),
+            "Private pages are never replaced"
+        );
+        (physical_address, privileges, previous.is_some())
+    }
+}
+
+impl Drop for Vma {
+    fn drop(&mut self) {
+        if let Some(backing) = self.backing.take().filter(|_| self.is_pinned()) {
+            self.retired.lock().push(backing);
+        }
+    }
+}
+
+/// Memory of a process a device reads from, either its own pages or a
+/// copy. The pages are unpinned on drop.
+pub enum PinnedBuffer {
+    Pinned {
+        _retired: Retired,
+        address: usize,
+        length: usize,
+    },
+    Bounced(DmaBuffer),
+}
+
+impl PinnedBuffer {
+    pub fn bounce(data: &[u8]) -> Self {
+        Self::Bounced(DmaBuffer::from_slice(data))
+    }
+
+    pub fn physical_address(&self) -> u64 {
+        match self {
+            Self::Pinned { address, .. } => *address as u64,
+            Self::Bounced(buffer) => buffer.physical_address(),
+        }
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        matches!(self, Self::Pinned { .. })
+    }
+}
+
+impl Deref for PinnedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            // SAFETY: The backing is kept alive and at its place by the pin
+            Self::Pinned {
+                address, length, ..
+            } => unsafe { core::slice::from_raw_parts(*address as *const u8, *length) },
+            Self::Bounced(buffer) => buffer,
+        }
+    }
+}
+
+impl core::fmt::Debug for PinnedBuffer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PinnedBuffer")
+            .field("pinned", &self.is_pinned())
+            .field(
+                "physical_address",
+                &format_args!("{:#x}", self.physical_address()),
+            )
+            .field("length", &self.len())
+            .finish()
+    }
+}
+
+/// Pins the user buffer at `address` whose validated content is `data`.
+/// Buffers outside of the private pages of one area, e.g. on the stack
+/// or in shared pages, are copied into a bounce buffer instead.
+pub fn pin(process: &ProcessRef, address: usize, data: &[u8]) -> PinnedBuffer {
+    let pinned = process.with_lock(|p| {
+        p.vma_containing(address)?
+            .pin(address..address + data.len())
+    });
+    match pinned {
+        // Buffers which were spread over physical memory were handed as
+        // copy, the copy is bounced again
+        Some((retired, physical_address)) if physical_address == data.as_ptr() as usize => {
+            PinnedBuffer::Pinned {
+                _retired: retired,
+                address: physical_address,
+                length: data.len(),
+            }
+        }
+        _ => PinnedBuffer::bounce(data),
+    }
+}
+
+/// Loads the page which contains the address if it belongs to an area of
+/// the process, or copies it if it is written for the first time.
+/// Returns false if the access wasn't allowed.
+pub fn handle_page_fault(process: &ProcessRef, address: usize, write: bool) -> bool {
+    let address = align_down(address, PAGE_SIZE);
+    let request = process.with_lock(|p| {
+        let vma = p.vma_containing(address)?;
+        if !vma.needs_page(address, write) {
+            return None;
+        }
+        let offset = vma.offset_of(address);
+        Some((
+            vma.source.clone(),
+            offset,
+            write && vma.is_writable(),
+            vma.pages.get(&offset).cloned(),
+        ))
+    });
+    let Some((source, offset, private, existing)) = request else {
+        return false;
+    };
+    // The process lock is not held because reading might take some time
+    let page = match existing.map_or_else(|| source.initial_page(offset), Ok) {
+        Ok(page) => page,
+        Err(error) => {
+            warn!("Cannot load page of {}: {error:?}", source.name());
+            return false;
+        }
+    };
+    process.with_lock(|mut p| p.map_vma_page(address, page, private));
+    true
+}
+
+/// Loads all pages of the range which are not mapped yet, or not
+/// writable if `write` is set, e.g. before the kernel accesses a buffer
+/// of a syscall. The stack grows if the range reaches below it.
+pub fn fault_in(process: &ProcessRef, range: Range<usize>, write: bool) {
+    let start = align_down(range.start, PAGE_SIZE);
+    for address in (start..range.end).step_by(PAGE_SIZE) {
+        let is_accessible = process.with_lock(|p| {
+            p.get_page_table()
+                .translate(address)
+                .is_some_and(|(_, privileges)| {
+                    !write || matches!(privileges, XWRMode::ReadWrite | XWRMode::ReadWriteExecute)
+                })
+        });
+        // Buffers on the stack may reach below its current end
+        if !is_accessible && !handle_page_fault(process, address, write) {
+            process.with_lock(|mut p| p.grow_stack(address));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use crate::{
+        fs::{
+            tmpfs::TmpFs,
+            vfs::{FileSystem, InodeKind, OpenFile},
+        },
+        memory::{
+            page::PinnedHeapPages,
+            page_cache,
+            page_tables::XWRMode,
+            vma::{LoadedPage, MappedFile, Resized, Source, Vma},
+            PAGE_SIZE,
+        },
+    };
+
+    fn mapped_file(path: &str, content: &[u8]) -> Source {
+        let inode = TmpFs::new().root().create("file", InodeKind::File).unwrap();
+        inode.write_at(0, content).unwrap();
+        Source::File(MappedFile::new(&OpenFile::new(inode, path.into()), 0))
+    }
+
+    #[test_case]
+    fn pages_are_loaded_from_the_file() {
+        let mut data = [1; PAGE_SIZE + 3];
+        data[PAGE_SIZE..].copy_from_slice(&[2; 3]);
+        let file = mapped_file("/vma-test/loaded", &data);
+
+        let LoadedPage::Shared { pages, index } = file.initial_page(PAGE_SIZE).unwrap() else {
+            panic!("Pages of files must come from the page cache");
+        };
+        let content = &pages[index][..];
+        assert_eq!(content[..3], [2; 3]);
+        assert!(
+            content[3..].iter().all(|byte| *byte == 0),
+            "The page is padded"
+        );
+
+        let LoadedPage::Shared { pages: again, .. } = file.initial_page(PAGE_SIZE).unwrap() else {
+            panic!("Pages of files must come from the page cache");
+        };
+        assert!(Arc::ptr_eq(&pages, &again));
+        page_cache::invalidate_file("/vma-test");
+    }
+
+    #[test_case]
+    fn shared_pages_are_copied_on_write() {
+        let mut segment = PinnedHeapPages::new(2);
+        segment.fill(&[7; 2 * PAGE_SIZE]);
+        let source = Source::Segment(Arc::new(segment));
+        let mut vma = Vma::new(0x1000, 2 * PAGE_SIZE, XWRMode::ReadWrite, source.clone());
+        assert_eq!(vma.number_of_pages(), 2);
+        assert!(vma.contains(0x2fff));
+        assert!(!vma.contains(0x3000));
+        assert!(vma.needs_page(0x2010, false));
+
+        let shared = source.initial_page(PAGE_SIZE).unwrap();
+        let (shared_address, privileges, replaced) = vma.insert(0x2010, shared, false);
+        assert_eq!((privileges, replaced), (XWRMode::ReadOnly, false));
+        assert!(!vma.needs_page(0x2000, false));
+        assert!(vma.needs_page(0x2000, true), "The first write copies");
+        assert!(vma.needs_page(0x1000, false));
+
+        let shared = vma.pages[&PAGE_SIZE].clone();
+        let (private_address, privileges, replaced) = vma.insert(0x2000, shared, true);
+        assert_eq!((privileges, replaced), (XWRMode::ReadWrite, true));
+        assert_ne!(private_address, shared_address);
+        assert_eq!(vma.backing_page(PAGE_SIZE)[..], [7; PAGE_SIZE]);
+        assert!(!vma.needs_page(0x2000, true));
+
+        let shared = source.initial_page(0).unwrap();
+        let (first_address, _, _) = vma.insert(0x1000, shared, true);
+        assert_eq!(
+            first_address + PAGE_SIZE,
+            private_address,
+            "Private pages are physically contiguous"
+        );
+
+        let mut read_only = Vma::new(0x1000, PAGE_SIZE, XWRMode::ReadOnly, source.clone());
+        read_only.insert(0x1000, source.initial_page(0).unwrap(), false);
+        assert!(
+            !read_only.needs_page(0x1000, true),
+            "Writes to read-only areas are not allowed"
+        );
+    }
+
+    #[test_case]
+    fn anonymous_areas_are_resized() {
+        let mut heap = Vma::new(0x1000, 0, XWRMode::ReadWrite, Source::Anonymous);
+        assert!(!heap.contains(0x1000));
+        assert_eq!(heap.resize(3 * PAGE_SIZE), Resized::default());
+        assert!(heap.contains(0x3fff));
+
+        for address in [0x1000, 0x3000] {
+            let page = Source::Anonymous.initial_page(0).unwrap();
+            assert!(matches!(page, LoadedPage::Private));
+            heap.insert(address, page, true);
+        }
+        heap.backing_page(2 * PAGE_SIZE).fill(1);
+        let resized = heap.resize(PAGE_SIZE);
+        assert_eq!(resized.unmapped, [0x3000]);
+        assert!(resized.moved.is_empty());
+        assert!(!heap.contains(0x2000));
+        assert!(!heap.needs_page(0x1000, true));
+        assert_eq!(heap.number_of_pages(), 1);
+
+        heap.backing_page(0).fill(2);
+        let resized = heap.resize(4 * PAGE_SIZE);
+        assert!(resized.unmapped.is_empty());
+        assert_eq!(resized.moved, [(0x1000, heap.backing_address(0))]);
+        assert_eq!(heap.backing_page(0)[..], [2; PAGE_SIZE], "Content is kept");
+        assert_eq!(
+            heap.backing_page(2 * PAGE_SIZE)[..],
+            [0; PAGE_SIZE],
+            "Pages are zeroed when the area grows again"
+        );
+    }
+
+    #[test_case]
+    fn pinned_backings_stay_in_place() {
+        let mut heap = Vma::new(0x1000, 2 * PAGE_SIZE, XWRMode::ReadWrite, Source::Anonymous);
+        assert!(heap.pin(0x1000..0x1010).is_none(), "Pages must be loaded");
+        heap.insert(0x1000, LoadedPage::Private, true);
+        heap.insert(0x2000, LoadedPage::Private, true);
+        heap.backing_page(0).fill(3);
+        assert!(heap.pin(0x2000..0x3001).is_none());
+
+        let (pin, address) = heap.pin(0x1ff0..0x2010).unwrap();
+        assert_eq!(address, heap.backing_address(0) + 0xff0);
+        // SAFETY: The pin keeps the backing alive
+        let pinned = unsafe { core::slice::from_raw_parts(address as *const u8, 0x10) };
+
+        // Resizing works on a copy, the old backing is retired
+        let resized = heap.resize(PAGE_SIZE);
+        assert_eq!(resized.unmapped, [0x2000]);
+        assert_eq!(resized.moved, [(0x1000, heap.backing_address(0))]);
+        assert_ne!(heap.backing_address(0), address - 0xff0);
+        assert_eq!(heap.backing_page(0)[..], [3; PAGE_SIZE]);
+        assert_eq!(pin.lock().len(), 1);
+        assert_eq!(pinned, [3; 0x10]);
+
+        drop(pin);
+        let resized = heap.resize(PAGE_SIZE);
+        assert!(resized.moved.is_empty(), "Unpinned backings stay");
+        assert!(heap.retired.lock().is_empty());
+
+        // The backing of a dropped area is retired as well
+        let (pin, _) = heap.pin(0x1000..0x2000).unwrap();
+        drop(heap);
+        assert_eq!(pin.lock().len(), 1);
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
     },
 };
 
-use super::{current_mac_address, mac::MacAddress, IP_ADDR};
+use super::{mac::MacAddress, NetworkInterface, IP_ADDR};
 
 const ARP_REQUEST: u16 = 1;
 const ARP_RESPONSE: u16 = 2;
@@ -20,6 +20,8 @@ const ARP_RESPONSE: u16 = 2;
 const HARDWARE_ADDRESS_TYPE_ETHERNET: u16 = 1;
 const PROTOCOL_ADDRESS_TYPE_IPV4: u16 = 0x0800;
 
+const BROADCAST_MAC: MacAddress = MacAddress::new([0xff; 6]);
+
 #[derive(Debug)]
 #[repr(C)]
 struct ArpPacket {
@@ -39,7 +41,12 @@ static_assert_size!(ArpPacket, 28);
 impl ByteInterpretable for ArpPacket {}
 
 impl ArpPacket {
-    fn new_reply(destination_mac_address: MacAddress, destination_ip_address: Ipv4Addr) -> Self {
+    fn new(
+        operation: u16,
+        source_mac_address: MacAddress,
+        destination_mac_address: MacAddress,
+        destination_ip_address: Ipv4Addr,
+    ) -> Self {
         Self {
             hardware_address_type: BigEndian::from_little_endian(HARDWARE_ADDRESS_TYPE_ETHERNET),
             protocol_address_type: BigEndian::from_little_endian(PROTOCOL_ADDRESS_TYPE_IPV4),
@@ -49,8 +56,8 @@ impl ArpPacket {
             protocol_address_length: BigEndian::from_little_endian(
                 core::mem::size_of::<Ipv4Addr>() as u8,
             ),
-            operation: BigEndian::from_little_endian(ARP_RESPONSE),
-            source_mac_address: current_mac_address(),
+            operation: BigEndian::from_little_endian(operation),
+            source_mac_address,
             source_ip_address: IP_ADDR,
             destination_mac_address,
             destination_ip_address,
@@ -58,7 +65,24 @@ impl ArpPacket {
     }
 }
 
-pub fn process_and_respond(data: &[u8]) {
+/// Asks for the mac address of `ip_address`. The reply is put into the
+/// [`ARP_CACHE`] when it is processed.
+pub fn send_request(interface: &mut impl NetworkInterface, ip_address: Ipv4Addr) {
+    let own_mac = interface.mac_address();
+    // The target mac is what we ask for
+    let arp_request = ArpPacket::new(ARP_REQUEST, own_mac, MacAddress::new([0; 6]), ip_address);
+    let ethernet_header = EthernetHeader::new(BROADCAST_MAC, own_mac, EtherTypes::Arp);
+
+    let data = [ethernet_header.as_slice(), arp_request.as_slice()].concat();
+    debug!(
+        "ARP request\n\tethernet: {}\n\tarp: {}",
+        ethernet_header, arp_request
+    );
+
+    interface.send_packet(data);
+}
+
+pub fn process_and_respond(interface: &mut impl NetworkInterface, data: &[u8]) {
     if data.len() < core::mem::size_of::<ArpPacket>() {
         panic!("Received ARP packet is too small");
     }
@@ -70,7 +94,8 @@ pub fn process_and_respond(data: &[u8]) {
         arp_header.hardware_address_length.get() as usize == core::mem::size_of::<MacAddress>()
     ); // MAC address length
     assert!(arp_header.protocol_address_length.get() as usize == core::mem::size_of::<Ipv4Addr>()); // IPv4 address length
-    assert!(arp_header.operation.get() == ARP_REQUEST);
+    let operation = arp_header.operation.get();
+    assert!(operation == ARP_REQUEST || operation == ARP_RESPONSE);
     debug!("Received: {:#}", arp_header);
 
     if arp_header.destination_ip_address != super::IP_ADDR {
@@ -78,25 +103,32 @@ pub fn process_and_respond(data: &[u8]) {
     }
 
     ARP_CACHE
-        .lock()
+        .write()
         .insert(arp_header.source_ip_address, arp_header.source_mac_address);
 
-    let arp_reply =
-        ArpPacket::new_reply(arp_header.source_mac_address, arp_header.source_ip_address);
+    // Replies answer our requests and only fill the cache
+    if operation == ARP_RESPONSE {
+        return;
+    }
 
-    let ethernet_reply = EthernetHeader::new(
+    let own_mac = interface.mac_address();
+    let arp_reply = ArpPacket::new(
+        ARP_RESPONSE,
+        own_mac,
         arp_header.source_mac_address,
-        current_mac_address(),
-        EtherTypes::Arp,
+        arp_header.source_ip_address,
     );
 
+    let ethernet_reply =
+        EthernetHeader::new(arp_header.source_mac_address, own_mac, EtherTypes::Arp);
+
     let data = [ethernet_reply.as_slice(), arp_reply.as_slice()].concat();
     debug!(
         "ARP respond\n\tethernet: {}\n\tarp: {}",
         ethernet_reply, arp_reply
     );
 
-    super::send_packet(data);
+    interface.send_packet(data);
 }
 
 impl Display for ArpPacket {
@@ -0,0 +1,140 @@
+//! Raw sockets get a copy of every ethernet frame which is received or
+//! sent, e.g. for packet capture tools. A socket which isn't read fast
+//! enough loses the frames which don't fit into its queue.
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use common::{mutex::Mutex, net::CapturedFrame};
+
+use crate::processes::{poll, timer};
+
+const MAX_QUEUED_FRAMES: usize = 256;
+
+pub type SharedRawSocket = Arc<Mutex<RawSocket>>;
+
+// Closed sockets are removed when the next frame is captured
+static RAW_SOCKETS: Mutex<Vec<Weak<Mutex<RawSocket>>>> = Mutex::new(Vec::new());
+
+struct Frame {
+    data: Vec<u8>,
+    timestamp_microseconds: u64,
+    outgoing: bool,
+}
+
+pub struct RawSocket {
+    frames: VecDeque<Frame>,
+}
+
+impl RawSocket {
+    fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+        }
+    }
+
+    fn put_frame(&mut self, frame: Frame) {
+        if self.frames.len() < MAX_QUEUED_FRAMES {
+            self.frames.push_back(frame);
+        }
+    }
+
+    pub fn has_data(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Copies the oldest frame into `buffer` and removes it
+    pub fn get_frame(&mut self, buffer: &mut [u8]) -> Option<CapturedFrame> {
+        let frame = self.frames.pop_front()?;
+        let length = frame.data.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&frame.data[..length]);
+        Some(CapturedFrame {
+            length,
+            frame_length: frame.data.len(),
+            timestamp_microseconds: frame.timestamp_microseconds,
+            outgoing: frame.outgoing,
+        })
+    }
+}
+
+/// The socket captures frames until it is dropped
+pub fn open() -> SharedRawSocket {
+    let socket = Arc::new(Mutex::new(RawSocket::new()));
+    RAW_SOCKETS.lock().push(Arc::downgrade(&socket));
+    socket
+}
+
+pub fn capture(data: &[u8], outgoing: bool) {
+    capture_parts(&[data], outgoing);
+}
+
+/// Captures the parts as one frame. They are only joined if a socket is
+/// open.
+pub fn capture_parts(parts: &[&[u8]], outgoing: bool) {
+    let sockets: Vec<SharedRawSocket> = RAW_SOCKETS.with_lock(|mut sockets| {
+        sockets.retain(|socket| socket.strong_count() > 0);
+        sockets.iter().filter_map(Weak::upgrade).collect()
+    });
+    if sockets.is_empty() {
+        return;
+    }
+
+    let timestamp_microseconds =
+        timer::get_current_clocks() * 1_000_000 / timer::clocks_per_second();
+    for socket in sockets {
+        socket.lock().put_frame(Frame {
+            data: parts.concat(),
+            timestamp_microseconds,
+            outgoing,
+        });
+    }
+    poll::notify();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capture, open, MAX_QUEUED_FRAMES, RAW_SOCKETS};
+
+    #[test_case]
+    fn frames_are_captured_while_the_socket_is_open() {
+        capture(&[1, 2, 3], false);
+        let socket = open();
+        assert!(!socket.lock().has_data());
+
+        capture(&[4, 5, 6], false);
+        capture(&[7, 8], true);
+
+        let mut buffer = [0; 2];
+        let frame = socket.lock().get_frame(&mut buffer).unwrap();
+        assert_eq!(frame.length, 2);
+        assert_eq!(frame.frame_length, 3);
+        assert!(!frame.outgoing);
+        assert_eq!(buffer, [4, 5]);
+
+        let frame = socket.lock().get_frame(&mut buffer).unwrap();
+        assert!(frame.outgoing);
+        assert_eq!(buffer, [7, 8]);
+        assert!(socket.lock().get_frame(&mut buffer).is_none());
+
+        drop(socket);
+        capture(&[9], false);
+        assert!(RAW_SOCKETS.lock().is_empty());
+    }
+
+    #[test_case]
+    fn frames_are_dropped_if_the_queue_is_full() {
+        let socket = open();
+        for index in 0..=MAX_QUEUED_FRAMES {
+            capture(&index.to_le_bytes(), false);
+        }
+
+        let mut buffer = [0; 8];
+        let mut frames = 0;
+        while socket.lock().get_frame(&mut buffer).is_some() {
+            frames += 1;
+        }
+        assert_eq!(frames, MAX_QUEUED_FRAMES);
+    }
+}
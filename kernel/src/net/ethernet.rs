@@ -8,7 +8,7 @@ use crate::{
     klibc::util::{BufferExtension, ByteInterpretable},
 };
 
-use super::{current_mac_address, mac::MacAddress};
+use super::mac::MacAddress;
 
 const BROADCAST_MAC: MacAddress = MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
 
@@ -80,7 +80,8 @@ impl EthernetHeader {
         }
     }
 
-    pub fn try_parse(data: &[u8]) -> Result<(&Self, &[u8]), ParseError> {
+    /// Only packets for our mac address or broadcasts are accepted
+    pub fn try_parse(data: &[u8], own_mac: MacAddress) -> Result<(&Self, &[u8]), ParseError> {
         if data.len() < Self::MIN_LENGTH {
             return Err(ParseError::PacketTooSmall);
         }
@@ -90,13 +91,10 @@ impl EthernetHeader {
             return Err(ParseError::UnknownEtherType);
         }
 
-        if header.destination_mac != current_mac_address()
-            && header.destination_mac != BROADCAST_MAC
-        {
+        if header.destination_mac != own_mac && header.destination_mac != BROADCAST_MAC {
             debug!(
                 "Unknown destination mac: {}; NIC mac: {}",
-                header.destination_mac,
-                current_mac_address()
+                header.destination_mac, own_mac
             );
             return Err(ParseError::UnknownDestinationMac);
         }
@@ -108,6 +106,10 @@ impl EthernetHeader {
         EtherTypes::try_from(self.ether_type).is_ok()
     }
 
+    pub fn source_mac(&self) -> MacAddress {
+        self.source_mac
+    }
+
     pub fn ether_type(&self) -> EtherTypes {
         EtherTypes::try_from(self.ether_type).expect("Must be already parsed.")
     }
@@ -30,21 +30,47 @@ impl ByteInterpretable for IpV4Header {}
 #[derive(Debug)]
 pub enum IpV4ParseError {
     PacketTooSmall,
+    InvalidChecksum,
 }
 
-const UDP_PROTOCOL_TYPE_UDP: u8 = 17;
-
 impl IpV4Header {
     pub const HEADER_SIZE: usize = core::mem::size_of::<Self>();
 
+    /// Header of a packet from our address, the checksum is computed
+    pub fn new(destination_ip: Ipv4Addr, upper_protocol: u8, payload_length: usize) -> Self {
+        let mut header = Self {
+            version_and_ihl: BigEndian::from_little_endian((4 << 4) | 5), // ip version v4 and header length 5 * 4byte
+            tos: BigEndian::from_little_endian(0),
+            total_packet_length: BigEndian::from_little_endian(
+                u16::try_from(Self::HEADER_SIZE + payload_length)
+                    .expect("Size must not exceed u16"),
+            ),
+            identification: BigEndian::from_little_endian(0),
+            flags_and_offset: BigEndian::from_big_endian(0),
+            ttl: BigEndian::from_little_endian(128),
+            upper_protocol: BigEndian::from_little_endian(upper_protocol),
+            header_checksum: BigEndian::from_little_endian(0),
+            source_ip: super::IP_ADDR,
+            destination_ip,
+        };
+        header.header_checksum = BigEndian::from_little_endian(header.calculate_checksum());
+        header
+    }
+
+    /// The payload is returned without the padding of short ethernet
+    /// frames. The caller dispatches on the upper protocol.
     pub fn process(data: &[u8]) -> Result<(&IpV4Header, &[u8]), IpV4ParseError> {
         if data.len() < core::mem::size_of::<IpV4Header>() {
             return Err(IpV4ParseError::PacketTooSmall);
         }
 
-        let (ipv4_header, rest) = data.split_as::<IpV4Header>();
+        let (ipv4_header, _) = data.split_as::<IpV4Header>();
 
-        assert!(ipv4_header.total_packet_length.get() as usize == data.len());
+        let total_length = ipv4_header.total_packet_length.get() as usize;
+        if total_length < Self::HEADER_SIZE || total_length > data.len() {
+            return Err(IpV4ParseError::PacketTooSmall);
+        }
+        let rest = &data[Self::HEADER_SIZE..total_length];
 
         assert!(
             ipv4_header.flags_and_offset.get() & 0b100 == 0,
@@ -56,15 +82,9 @@ impl IpV4Header {
             "Destination ip address is not ours."
         );
 
-        assert!(
-            ipv4_header.upper_protocol.get() == UDP_PROTOCOL_TYPE_UDP,
-            "Only UDP is supported for now"
-        );
-
-        assert!(
-            ipv4_header.checksum_correct(),
-            "Checksum must be zero to be correct"
-        );
+        if !ipv4_header.checksum_correct() {
+            return Err(IpV4ParseError::InvalidChecksum);
+        }
         Ok((ipv4_header, rest))
     }
 
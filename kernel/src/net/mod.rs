@@ -1,64 +1,313 @@
 use core::{cell::LazyCell, net::Ipv4Addr};
 
 use alloc::{collections::BTreeMap, vec::Vec};
-use common::mutex::Mutex;
+use common::{
+    errors::SysSocketError,
+    mutex::{Mutex, RwLock},
+};
 
 use crate::{
+    cpu::Cpu,
     debug,
-    drivers::virtio::net::NetworkDevice,
-    net::{ipv4::IpV4Header, udp::UdpHeader},
+    drivers::virtio::net::{NetworkDevice, ReceivedPacket},
+    interrupts::nesting::{self, Priority},
+    klibc::counters::Counter,
+    memory::vma::PinnedBuffer,
+    net::{
+        ipv4::{IpV4Header, IpV4ParseError},
+        tcp::{OpenTcpSockets, TcpConnection, TcpParseError},
+        udp::{UdpHeader, UdpParseError},
+    },
+    processes::{kthread, poll, timer},
 };
 
-use self::{ethernet::EthernetHeader, mac::MacAddress, sockets::OpenSockets};
+use self::{
+    ethernet::EthernetHeader, mac::MacAddress, routing::RoutingTable, sockets::OpenSockets,
+};
 
 mod arp;
+pub mod capture;
 mod ethernet;
 mod ipv4;
 pub mod mac;
+pub mod routing;
 pub mod sockets;
+pub mod tcp;
 pub mod udp;
 
-static NETWORK_DEVICE: Mutex<Option<NetworkDevice>> = Mutex::new(None);
+// Only taken for writing when the device is assigned or removed. The
+// queues have locks of their own.
+static NETWORK_DEVICE: RwLock<Option<NetworkDevice>> = RwLock::new(None);
 static IP_ADDR: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
-pub static ARP_CACHE: Mutex<BTreeMap<Ipv4Addr, MacAddress>> = Mutex::new(BTreeMap::new());
+pub static ARP_CACHE: RwLock<BTreeMap<Ipv4Addr, MacAddress>> = RwLock::new(BTreeMap::new());
 pub static OPEN_UDP_SOCKETS: Mutex<LazyCell<OpenSockets>> =
     Mutex::new(LazyCell::new(OpenSockets::new));
+pub static OPEN_TCP_SOCKETS: Mutex<OpenTcpSockets> = Mutex::new(OpenTcpSockets::new());
+pub static ROUTING_TABLE: RwLock<RoutingTable> = RwLock::new(RoutingTable::new());
+
+/// The hardware below the protocols. The kernel talks to the virtio
+/// network card, tests use a fake interface such that the protocols
+/// can be checked on the host, e.g. under Miri.
+pub trait NetworkInterface {
+    fn mac_address(&self) -> MacAddress;
+    // Largest IP packet which can be sent
+    fn mtu(&self) -> usize;
+    fn send_packet(&mut self, packet: Vec<u8>);
+
+    /// Sends the headers followed by the payload
+    fn send_frame(&mut self, headers: Vec<u8>, payload: PinnedBuffer) {
+        self.send_packet([&headers[..], &payload[..]].concat());
+    }
+}
+
+/// The configured network device. The lock is only held for a single
+/// operation because processing a packet takes socket locks.
+struct ConfiguredDevice;
+
+impl NetworkInterface for ConfiguredDevice {
+    fn mac_address(&self) -> MacAddress {
+        current_mac_address()
+    }
+
+    fn mtu(&self) -> usize {
+        current_mtu()
+    }
+
+    fn send_packet(&mut self, packet: Vec<u8>) {
+        send_packet(packet);
+    }
+
+    fn send_frame(&mut self, headers: Vec<u8>, payload: PinnedBuffer) {
+        send_frame(headers, payload);
+    }
+}
+
+// Received packets are processed every period, not only when a socket
+// is read. Otherwise polling sockets would never see new data. There is
+// a poller for every queue pair, the period is stretched such that all
+// of them together take the share of a single one.
+const POLL_PERIOD_MILLISECONDS: u64 = 10;
+const POLL_BUDGET_MILLISECONDS: u64 = 1;
 
 pub fn assign_network_device(device: NetworkDevice) {
-    *NETWORK_DEVICE.lock() = Some(device);
+    let queue_pairs = device.queue_pairs();
+    *NETWORK_DEVICE.write() = Some(device);
+    ROUTING_TABLE.write().add_default_routes();
+    // Hart n uses queue pair n, see own_queue_pair
+    for pair in 0..queue_pairs {
+        kthread::spawn_periodic(
+            "knetd",
+            poller,
+            POLL_PERIOD_MILLISECONDS * queue_pairs as u64,
+            POLL_BUDGET_MILLISECONDS,
+            Some(pair),
+        );
+    }
+}
+
+extern "C" fn poller() -> ! {
+    let pair = Cpu::without_interrupts(|| Cpu::with_current_process(|p| p.pinned_hart()))
+        .expect("Pollers must be pinned to the hart of their queue pair");
+    loop {
+        // Sockets are shared with syscalls, which run with interrupts disabled
+        Cpu::without_interrupts(|| {
+            // The device is gone after a shutdown
+            let packets = NETWORK_DEVICE
+                .read()
+                .as_ref()
+                .map(|device| device.receive_packets(pair));
+            process_received_packets(packets.unwrap_or_default());
+            // One poller is enough to drive the timers of tcp
+            if pair == 0 {
+                tcp::tick(&mut ConfiguredDevice, timer::get_current_clocks());
+                poll::notify();
+            }
+        });
+        kthread::wait_period();
+    }
 }
 
+// Every hart uses its own queue pair if there are enough of them
+fn own_queue_pair(device: &NetworkDevice) -> usize {
+    Cpu::cpu_id() % device.queue_pairs()
+}
+
+/// Resets the network device (on drop) such that it
+/// doesn't access our memory anymore.
+pub fn shutdown() {
+    NETWORK_DEVICE.write().take();
+}
+
+/// Drains all queue pairs, starting with the one of the current hart.
+/// The pollers of the other pairs might run a period later.
 pub fn receive_and_process_packets() {
-    let packets = NETWORK_DEVICE
-        .lock()
-        .as_mut()
-        .expect("There must be a configured network device.")
-        .receive_packets();
+    let packets = NETWORK_DEVICE.with_read_lock(|device| {
+        let device = device
+            .as_ref()
+            .expect("There must be a configured network device.");
+        let own = own_queue_pair(device);
+        (0..device.queue_pairs())
+            .map(|offset| (own + offset) % device.queue_pairs())
+            .flat_map(|pair| device.receive_packets(pair))
+            .collect::<Vec<_>>()
+    });
+    // Processing takes a while, timer interrupts must not wait for it
+    nesting::allow_higher_than(Priority::External, || process_received_packets(packets));
+}
 
+fn process_received_packets(packets: Vec<ReceivedPacket>) {
     for packet in packets {
-        process_packet(packet);
+        capture::capture(&packet.data, false);
+        process_packet(
+            &mut ConfiguredDevice,
+            &packet.data,
+            packet.checksum_validated,
+        );
     }
 }
 
 pub fn send_packet(packet: Vec<u8>) {
-    NETWORK_DEVICE
-        .lock()
-        .as_mut()
-        .expect("There must be a configured network device.")
-        .send_packet(packet)
-        .expect("Packet must be sendable");
+    SENT.increment();
+    capture::capture(&packet, true);
+    NETWORK_DEVICE.with_read_lock(|device| {
+        let device = device
+            .as_ref()
+            .expect("There must be a configured network device.");
+        device
+            .send_packet(own_queue_pair(device), packet)
+            .expect("Packet must be sendable");
+    });
+}
+
+/// The payload is handed to the device as it is, without copying it
+pub fn send_frame(headers: Vec<u8>, payload: PinnedBuffer) {
+    SENT.increment();
+    capture::capture_parts(&[&headers, &payload], true);
+    NETWORK_DEVICE.with_read_lock(|device| {
+        let device = device
+            .as_ref()
+            .expect("There must be a configured network device.");
+        device
+            .send_frame(own_queue_pair(device), &headers, payload)
+            .expect("Frame must be sendable");
+    });
+}
+
+/// Mac address of the next hop towards `destination`. Unknown addresses
+/// are requested via ARP, the caller has to try again later.
+fn resolve(
+    interface: &mut impl NetworkInterface,
+    destination: Ipv4Addr,
+) -> Result<MacAddress, SysSocketError> {
+    let next_hop = ROUTING_TABLE
+        .read()
+        .next_hop(destination)
+        .ok_or(SysSocketError::HostUnreachable)?;
+    let cached = ARP_CACHE.read().get(&next_hop).copied();
+    cached.ok_or_else(|| {
+        arp::send_request(interface, next_hop);
+        SysSocketError::AddressNotResolved
+    })
+}
+
+/// Sends `payload` as one UDP datagram from our `source_port`
+pub fn send_datagram(
+    source_port: u16,
+    destination_ip: Ipv4Addr,
+    destination_port: u16,
+    payload: PinnedBuffer,
+) -> Result<(), SysSocketError> {
+    send_datagram_via(
+        &mut ConfiguredDevice,
+        source_port,
+        destination_ip,
+        destination_port,
+        payload,
+    )
+}
+
+fn send_datagram_via(
+    interface: &mut impl NetworkInterface,
+    source_port: u16,
+    destination_ip: Ipv4Addr,
+    destination_port: u16,
+    payload: PinnedBuffer,
+) -> Result<(), SysSocketError> {
+    if payload.len() > UdpHeader::max_payload_size(interface.mtu()) {
+        return Err(SysSocketError::MessageTooLarge);
+    }
+    let destination_mac = resolve(interface, destination_ip)?;
+    let headers = UdpHeader::create_udp_headers(
+        destination_ip,
+        destination_port,
+        destination_mac,
+        interface.mac_address(),
+        source_port,
+        &payload,
+    );
+    interface.send_frame(headers, payload);
+    Ok(())
+}
+
+/// Sends what the peer can take right away, see TcpConnection::write
+pub fn write_tcp(connection: &mut TcpConnection, data: &[u8]) -> Result<usize, SysSocketError> {
+    connection.write(&mut ConfiguredDevice, data)
 }
 
 pub fn current_mac_address() -> MacAddress {
     NETWORK_DEVICE
-        .lock()
+        .read()
         .as_ref()
         .expect("There must be a configured network device.")
         .get_mac_address()
 }
 
-fn process_packet(packet: Vec<u8>) {
-    let (ethernet_header, rest) = match EthernetHeader::try_parse(&packet) {
+pub fn current_mtu() -> usize {
+    NETWORK_DEVICE
+        .read()
+        .as_ref()
+        .expect("There must be a configured network device.")
+        .mtu()
+}
+
+/// Packets received on every queue pair, shown in /proc/net/queues
+pub fn received_per_queue_pair() -> Vec<usize> {
+    NETWORK_DEVICE
+        .read()
+        .as_ref()
+        .map(NetworkDevice::received_per_queue_pair)
+        .unwrap_or_default()
+}
+
+/// Counters of the receive path, shown in /proc/net/stats. The device
+/// can only validate the UDP and TCP checksums, the one of the IPv4
+/// header is always checked.
+pub struct ReceiveStatistics {
+    pub received: Counter,
+    // Checked by the device instead of us
+    pub checksum_validated: Counter,
+    pub invalid_ip_checksums: Counter,
+    pub invalid_udp_checksums: Counter,
+    pub invalid_tcp_checksums: Counter,
+}
+
+pub static RECEIVE_STATISTICS: ReceiveStatistics = ReceiveStatistics {
+    received: Counter::new("net.received"),
+    checksum_validated: Counter::new("net.checksum_validated"),
+    invalid_ip_checksums: Counter::new("net.invalid_ip_checksums"),
+    invalid_udp_checksums: Counter::new("net.invalid_udp_checksums"),
+    invalid_tcp_checksums: Counter::new("net.invalid_tcp_checksums"),
+};
+
+static SENT: Counter = Counter::new("net.sent");
+
+fn process_packet(interface: &mut impl NetworkInterface, packet: &[u8], checksum_validated: bool) {
+    RECEIVE_STATISTICS.received.increment();
+    if checksum_validated {
+        RECEIVE_STATISTICS.checksum_validated.increment();
+    }
+
+    let (ethernet_header, rest) = match EthernetHeader::try_parse(packet, interface.mac_address()) {
         Ok(p) => p,
         Err(err) => {
             debug!("Could not parse ethernet header: {:?}", err);
@@ -72,20 +321,307 @@ fn process_packet(packet: Vec<u8>) {
 
     match ether_type {
         ethernet::EtherTypes::Arp => {
-            arp::process_and_respond(rest);
+            arp::process_and_respond(interface, rest);
         }
         ethernet::EtherTypes::IPv4 => {
-            let (ipv4_header, rest) =
-                IpV4Header::process(rest).expect("IPv4 packet must be processed.");
-            // We already asserted that it must be UDP in the IpV4Header::process method
-            let (udp_header, data) =
-                UdpHeader::process(rest, ipv4_header).expect("Udp header must be valid.");
-            OPEN_UDP_SOCKETS.lock().put_data(
-                ipv4_header.source_ip,
-                udp_header.source_port(),
-                udp_header.destination_port(),
-                data,
-            );
+            let (ipv4_header, rest) = match IpV4Header::process(rest) {
+                Ok(p) => p,
+                Err(IpV4ParseError::InvalidChecksum) => {
+                    debug!("Dropped IPv4 packet with invalid checksum");
+                    RECEIVE_STATISTICS.invalid_ip_checksums.increment();
+                    return;
+                }
+                Err(err) => panic!("IPv4 packet must be processed: {err:?}"),
+            };
+            match ipv4_header.upper_protocol.get() {
+                UdpHeader::UDP_PROTOCOL_TYPE => {
+                    process_datagram(ipv4_header, rest, checksum_validated);
+                }
+                tcp::TCP_PROTOCOL_TYPE => {
+                    match tcp::process_segment(
+                        interface,
+                        ethernet_header.source_mac(),
+                        ipv4_header,
+                        rest,
+                        checksum_validated,
+                    ) {
+                        Ok(()) => {}
+                        Err(TcpParseError::InvalidChecksum) => {
+                            debug!("Dropped TCP segment with invalid checksum");
+                            RECEIVE_STATISTICS.invalid_tcp_checksums.increment();
+                        }
+                        Err(err) => debug!("Dropped invalid TCP segment: {err:?}"),
+                    }
+                    // Connections are locked while they receive, waking
+                    // the pollers locks processes
+                    poll::notify();
+                }
+                protocol => debug!("Dropped IPv4 packet of unknown protocol {protocol}"),
+            }
+        }
+    }
+}
+
+fn process_datagram(ipv4_header: &IpV4Header, data: &[u8], checksum_validated: bool) {
+    let (udp_header, data) = match UdpHeader::process(data, ipv4_header, checksum_validated) {
+        Ok(p) => p,
+        Err(UdpParseError::InvalidChecksum) => {
+            debug!("Dropped UDP packet with invalid checksum");
+            RECEIVE_STATISTICS.invalid_udp_checksums.increment();
+            return;
         }
+        Err(err) => panic!("Udp header must be valid: {err:?}"),
+    };
+    OPEN_UDP_SOCKETS.lock().put_data(
+        ipv4_header.source_ip,
+        udp_header.source_port(),
+        udp_header.destination_port(),
+        data,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::net::Ipv4Addr;
+
+    use common::errors::SysSocketError;
+
+    use super::{
+        mac::MacAddress, process_packet, resolve, routing::Route, send_datagram_via,
+        udp::UdpHeader, NetworkInterface, ARP_CACHE, IP_ADDR, OPEN_UDP_SOCKETS, RECEIVE_STATISTICS,
+        ROUTING_TABLE,
+    };
+    use crate::memory::{
+        page::{Pages, PinnedHeapPages},
+        vma::PinnedBuffer,
+    };
+
+    const OWN_MAC: MacAddress = MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+    const PEER_MAC: MacAddress = MacAddress::new([0x52, 0x55, 0x0a, 0x00, 0x02, 0x02]);
+    const OTHER_MAC: MacAddress = MacAddress::new([0x52, 0x55, 0x0a, 0x00, 0x02, 0x03]);
+    const PEER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+    const MTU: usize = 1500;
+
+    struct TestInterface {
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl NetworkInterface for TestInterface {
+        fn mac_address(&self) -> MacAddress {
+            OWN_MAC
+        }
+
+        fn mtu(&self) -> usize {
+            MTU
+        }
+
+        fn send_packet(&mut self, packet: Vec<u8>) {
+            self.sent.push(packet);
+        }
+    }
+
+    // The headers are interpreted in place and must be aligned like
+    // the buffers of the network device
+    fn receive(interface: &mut TestInterface, packet: &[u8]) {
+        receive_checked_by_device(interface, packet, false);
+    }
+
+    fn receive_checked_by_device(
+        interface: &mut TestInterface,
+        packet: &[u8],
+        checksum_validated: bool,
+    ) {
+        let mut pages = PinnedHeapPages::new(1);
+        pages.fill(packet);
+        process_packet(
+            interface,
+            &pages.as_u8_slice()[..packet.len()],
+            checksum_validated,
+        );
+    }
+
+    fn arp_request(target_ip: Ipv4Addr) -> Vec<u8> {
+        [
+            &[0xff; 6][..],
+            &[0x52, 0x55, 0x0a, 0x00, 0x02, 0x02],
+            &[0x08, 0x06],
+            // Ethernet, IPv4, address lengths, request
+            &[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x01],
+            &[0x52, 0x55, 0x0a, 0x00, 0x02, 0x02],
+            &PEER_IP.octets(),
+            &[0; 6],
+            &target_ip.octets(),
+        ]
+        .concat()
+    }
+
+    #[test_case]
+    fn arp_requests_for_our_address_are_answered() {
+        let mut interface = TestInterface { sent: vec![] };
+
+        receive(&mut interface, &arp_request(Ipv4Addr::new(10, 0, 2, 42)));
+        assert!(interface.sent.is_empty());
+
+        receive(&mut interface, &arp_request(IP_ADDR));
+        assert_eq!(ARP_CACHE.read().get(&PEER_IP), Some(&PEER_MAC));
+
+        let expected_reply = [
+            &[0x52, 0x55, 0x0a, 0x00, 0x02, 0x02][..],
+            &[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+            &[0x08, 0x06],
+            &[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x02],
+            &[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+            &IP_ADDR.octets(),
+            &[0x52, 0x55, 0x0a, 0x00, 0x02, 0x02],
+            &PEER_IP.octets(),
+        ]
+        .concat();
+        assert_eq!(interface.sent, [expected_reply]);
+    }
+
+    #[test_case]
+    fn next_hops_are_resolved_via_arp() {
+        const GATEWAY_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 77);
+        const DESTINATION: Ipv4Addr = Ipv4Addr::new(192, 168, 77, 5);
+        let mut interface = TestInterface { sent: vec![] };
+        ROUTING_TABLE.write().add_default_routes();
+        ROUTING_TABLE
+            .write()
+            .add(Route::new(Ipv4Addr::new(192, 168, 77, 0), 24, Some(GATEWAY_IP)).unwrap())
+            .unwrap();
+
+        assert!(matches!(
+            resolve(&mut interface, DESTINATION),
+            Err(SysSocketError::AddressNotResolved)
+        ));
+        let expected_request = [
+            &[0xff; 6][..],
+            &[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+            &[0x08, 0x06],
+            &[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x01],
+            &[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+            &IP_ADDR.octets(),
+            &[0; 6],
+            &GATEWAY_IP.octets(),
+        ]
+        .concat();
+        assert_eq!(interface.sent, [expected_request]);
+
+        let reply = [
+            &[0x52, 0x54, 0x00, 0x12, 0x34, 0x56][..],
+            &[0x52, 0x55, 0x0a, 0x00, 0x02, 0x03],
+            &[0x08, 0x06],
+            &[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x02],
+            &[0x52, 0x55, 0x0a, 0x00, 0x02, 0x03],
+            &GATEWAY_IP.octets(),
+            &[0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+            &IP_ADDR.octets(),
+        ]
+        .concat();
+        receive(&mut interface, &reply);
+        // Replies are not answered
+        assert_eq!(interface.sent.len(), 1);
+        assert_eq!(resolve(&mut interface, DESTINATION).unwrap(), OTHER_MAC);
+    }
+
+    #[test_case]
+    fn udp_packets_are_delivered_to_sockets_for_our_mac() {
+        const PORT: u16 = 40000;
+        const PEER_PORT: u16 = 4321;
+        let mut interface = TestInterface { sent: vec![] };
+        let socket = OPEN_UDP_SOCKETS
+            .lock()
+            .try_get_socket(PORT)
+            .expect("Port must be free");
+
+        let for_other_mac =
+            UdpHeader::create_udp_packet(IP_ADDR, PORT, OTHER_MAC, PEER_MAC, PEER_PORT, b"other");
+        receive(&mut interface, &for_other_mac);
+        assert!(!socket.lock().has_data());
+
+        let packet =
+            UdpHeader::create_udp_packet(IP_ADDR, PORT, OWN_MAC, PEER_MAC, PEER_PORT, b"hello");
+        receive(&mut interface, &packet);
+
+        let mut buffer = [0; 16];
+        let mut socket = socket.lock();
+        assert_eq!(socket.get_data(&mut buffer), 5);
+        assert_eq!(&buffer[..5], b"hello");
+        assert_eq!(socket.peer().map(|(_, port)| port), Some(PEER_PORT));
+        assert!(interface.sent.is_empty());
+    }
+
+    #[test_case]
+    fn packets_with_invalid_checksums_are_dropped() {
+        const PORT: u16 = 40001;
+        const PEER_PORT: u16 = 4321;
+        // Offsets of the checksums in the ethernet frame
+        const IP_CHECKSUM: usize = 14 + 10;
+        const UDP_CHECKSUM: usize = 14 + 20 + 6;
+        let mut interface = TestInterface { sent: vec![] };
+        let socket = OPEN_UDP_SOCKETS
+            .lock()
+            .try_get_socket(PORT)
+            .expect("Port must be free");
+        let packet =
+            UdpHeader::create_udp_packet(IP_ADDR, PORT, OWN_MAC, PEER_MAC, PEER_PORT, b"hello");
+        let invalid_ip = RECEIVE_STATISTICS.invalid_ip_checksums.get();
+        let invalid_udp = RECEIVE_STATISTICS.invalid_udp_checksums.get();
+
+        let mut corrupted = packet.clone();
+        corrupted[IP_CHECKSUM] ^= 0xff;
+        receive(&mut interface, &corrupted);
+        let mut corrupted = packet.clone();
+        corrupted[UDP_CHECKSUM] ^= 0xff;
+        receive(&mut interface, &corrupted);
+        assert!(!socket.lock().has_data());
+        assert_eq!(
+            RECEIVE_STATISTICS.invalid_ip_checksums.get(),
+            invalid_ip + 1
+        );
+        assert_eq!(
+            RECEIVE_STATISTICS.invalid_udp_checksums.get(),
+            invalid_udp + 1
+        );
+
+        // UDP checksums the device validated are not checked again
+        receive_checked_by_device(&mut interface, &corrupted, true);
+        assert!(socket.lock().has_data());
+
+        // Zero means the sender didn't compute a checksum
+        let mut without_checksum = packet;
+        without_checksum[UDP_CHECKSUM..UDP_CHECKSUM + 2].fill(0);
+        receive(&mut interface, &without_checksum);
+        let mut buffer = [0; 16];
+        let mut socket = socket.lock();
+        assert_eq!(socket.get_data(&mut buffer), 10);
+        assert_eq!(&buffer[..10], b"hellohello");
+    }
+
+    #[test_case]
+    fn datagrams_must_fit_into_the_mtu() {
+        let mut interface = TestInterface { sent: vec![] };
+        ROUTING_TABLE.write().add_default_routes();
+        ARP_CACHE.write().insert(PEER_IP, PEER_MAC);
+
+        let largest = [7; MTU - 28];
+        let payload = PinnedBuffer::bounce(&largest);
+        send_datagram_via(&mut interface, 40002, PEER_IP, 4321, payload).unwrap();
+        assert_eq!(interface.sent.len(), 1);
+        // Ethernet header on top of the IP packet
+        assert_eq!(interface.sent[0].len(), 14 + MTU);
+        assert_eq!(
+            interface.sent[0],
+            UdpHeader::create_udp_packet(PEER_IP, 4321, PEER_MAC, OWN_MAC, 40002, &largest),
+            "Headers are followed by the payload"
+        );
+
+        let payload = PinnedBuffer::bounce(&[0; MTU - 27]);
+        assert!(matches!(
+            send_datagram_via(&mut interface, 40002, PEER_IP, 4321, payload),
+            Err(SysSocketError::MessageTooLarge)
+        ));
+        assert_eq!(interface.sent.len(), 1);
     }
 }
@@ -0,0 +1,179 @@
+//! Decides where packets are sent to. Destinations which are on-link are
+//! reached directly, all others through the gateway of the longest
+//! matching prefix. Without a matching route the host is unreachable.
+
+use core::net::Ipv4Addr;
+
+use alloc::vec::Vec;
+use common::errors::SysRouteError;
+
+use super::IP_ADDR;
+
+// The network of QEMU's user networking
+const ON_LINK_PREFIX_LENGTH: u8 = 24;
+const DEFAULT_GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    destination: Ipv4Addr,
+    prefix_length: u8,
+    // None if the destination is on-link
+    gateway: Option<Ipv4Addr>,
+}
+
+impl Route {
+    pub fn new(
+        destination: Ipv4Addr,
+        prefix_length: u8,
+        gateway: Option<Ipv4Addr>,
+    ) -> Result<Self, SysRouteError> {
+        if prefix_length > 32 || destination.to_bits() & !mask(prefix_length) != 0 {
+            return Err(SysRouteError::InvalidRoute);
+        }
+        Ok(Self {
+            destination,
+            prefix_length,
+            gateway,
+        })
+    }
+
+    fn has_prefix_of(&self, other: &Route) -> bool {
+        self.destination == other.destination && self.prefix_length == other.prefix_length
+    }
+
+    fn matches(&self, address: Ipv4Addr) -> bool {
+        address.to_bits() & mask(self.prefix_length) == self.destination.to_bits()
+    }
+}
+
+fn mask(prefix_length: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_length as u32).unwrap_or(0)
+}
+
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub const fn new() -> Self {
+        Self { routes: Vec::new() }
+    }
+
+    /// Our network is on-link, everything else goes through the gateway
+    pub fn add_default_routes(&mut self) {
+        let on_link_prefix = Ipv4Addr::from_bits(IP_ADDR.to_bits() & mask(ON_LINK_PREFIX_LENGTH));
+        self.add(Route::new(on_link_prefix, ON_LINK_PREFIX_LENGTH, None).expect("Prefix is valid"))
+            .expect("On-link routes can always be added");
+        self.add(
+            Route::new(Ipv4Addr::UNSPECIFIED, 0, Some(DEFAULT_GATEWAY)).expect("Prefix is valid"),
+        )
+        .expect("Default gateway must be on-link");
+    }
+
+    /// Replaces the route to the same prefix
+    pub fn add(&mut self, route: Route) -> Result<(), SysRouteError> {
+        // The replaced route must not make its own gateway reachable
+        if let Some(gateway) = route.gateway {
+            let gateway_route = self
+                .routes
+                .iter()
+                .filter(|existing| !existing.has_prefix_of(&route) && existing.matches(gateway))
+                .max_by_key(|existing| existing.prefix_length);
+            if gateway_route.is_none_or(|gateway_route| gateway_route.gateway.is_some()) {
+                return Err(SysRouteError::GatewayNotOnLink);
+            }
+        }
+        self.routes
+            .retain(|existing| !existing.has_prefix_of(&route));
+        self.routes.push(route);
+        Ok(())
+    }
+
+    /// The address the packet is sent to on the link
+    pub fn next_hop(&self, destination: Ipv4Addr) -> Option<Ipv4Addr> {
+        self.longest_match(destination)
+            .map(|route| route.gateway.unwrap_or(destination))
+    }
+
+    fn longest_match(&self, address: Ipv4Addr) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter(|route| route.matches(address))
+            .max_by_key(|route| route.prefix_length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv4Addr;
+
+    use common::errors::SysRouteError;
+
+    use super::{Route, RoutingTable, DEFAULT_GATEWAY};
+
+    const PEER: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 42);
+    const REMOTE: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 7);
+    const ROUTER: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 1);
+
+    fn default_table() -> RoutingTable {
+        let mut table = RoutingTable::new();
+        table.add_default_routes();
+        table
+    }
+
+    #[test_case]
+    fn on_link_destinations_are_reached_directly() {
+        assert_eq!(RoutingTable::new().next_hop(PEER), None);
+
+        let table = default_table();
+        assert_eq!(table.next_hop(PEER), Some(PEER));
+        assert_eq!(table.next_hop(REMOTE), Some(DEFAULT_GATEWAY));
+    }
+
+    #[test_case]
+    fn longest_prefix_wins() {
+        let mut table = default_table();
+        table
+            .add(Route::new(Ipv4Addr::new(192, 168, 0, 0), 16, Some(ROUTER)).unwrap())
+            .unwrap();
+        table
+            .add(Route::new(Ipv4Addr::new(192, 168, 1, 0), 24, Some(PEER)).unwrap())
+            .unwrap();
+        assert_eq!(table.next_hop(REMOTE), Some(PEER));
+        assert_eq!(table.next_hop(Ipv4Addr::new(192, 168, 2, 7)), Some(ROUTER));
+        assert_eq!(
+            table.next_hop(Ipv4Addr::new(8, 8, 8, 8)),
+            Some(DEFAULT_GATEWAY)
+        );
+
+        // Replaces the route to the same prefix
+        table
+            .add(Route::new(Ipv4Addr::new(192, 168, 1, 0), 24, None).unwrap())
+            .unwrap();
+        assert_eq!(table.next_hop(REMOTE), Some(REMOTE));
+    }
+
+    #[test_case]
+    fn invalid_routes_are_rejected() {
+        assert!(matches!(
+            Route::new(Ipv4Addr::UNSPECIFIED, 33, None),
+            Err(SysRouteError::InvalidRoute)
+        ));
+        // Host bits must not be set
+        assert!(matches!(
+            Route::new(REMOTE, 24, None),
+            Err(SysRouteError::InvalidRoute)
+        ));
+
+        let mut table = default_table();
+        let behind_gateway = Route::new(Ipv4Addr::new(172, 16, 0, 0), 12, Some(REMOTE)).unwrap();
+        assert!(matches!(
+            table.add(behind_gateway),
+            Err(SysRouteError::GatewayNotOnLink)
+        ));
+        assert_eq!(
+            table.next_hop(Ipv4Addr::new(172, 16, 0, 1)),
+            Some(DEFAULT_GATEWAY)
+        );
+    }
+}
@@ -7,11 +7,17 @@ use alloc::{
 };
 use common::mutex::Mutex;
 
-use crate::debug;
+use crate::{debug, memory::PAGE_SIZE, processes::poll};
 
 pub type SharedAssignedSocket = Arc<Mutex<AssignedSocket>>;
 type WeakSharedAssignedSocket = Weak<Mutex<AssignedSocket>>;
 
+// Datagrams which don't fit anymore are dropped until the socket is read
+const RECEIVE_QUEUE_LIMIT: usize = 64 * 1024;
+
+// Handed out for port 0, see RFC 6335
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
 type MutexSocketMap = Mutex<BTreeMap<u16, WeakSharedAssignedSocket>>;
 type SharedSocketMap = Arc<MutexSocketMap>;
 type WeakSharedSocketMap = Weak<MutexSocketMap>;
@@ -27,8 +33,16 @@ impl OpenSockets {
         }
     }
 
+    /// Port 0 assigns a free ephemeral port
     pub fn try_get_socket(&self, port: u16) -> Option<SharedAssignedSocket> {
         let mut sockets = self.sockets.lock();
+        let port = if port == 0 {
+            EPHEMERAL_PORTS
+                .clone()
+                .find(|port| !sockets.contains_key(port))?
+        } else {
+            port
+        };
         if sockets.contains_key(&port) {
             return None;
         }
@@ -46,6 +60,15 @@ impl OpenSockets {
         Some(arc_socket)
     }
 
+    /// Port and statistics of every open socket
+    pub fn statistics(&self) -> Vec<(u16, SocketStatistics)> {
+        self.sockets
+            .lock()
+            .iter()
+            .filter_map(|(port, socket)| Some((*port, socket.upgrade()?.lock().statistics())))
+            .collect()
+    }
+
     pub fn put_data(&self, from: Ipv4Addr, from_port: u16, port: u16, data: &[u8]) {
         let mut sockets = self.sockets.lock();
         match sockets.entry(port) {
@@ -60,11 +83,23 @@ impl OpenSockets {
     }
 }
 
+/// Counted in datagrams, queued is in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketStatistics {
+    pub queued: usize,
+    pub received: u64,
+    pub dropped: u64,
+    pub sent: u64,
+}
+
 pub struct AssignedSocket {
     buffer: Vec<u8>,
+    statistics: SocketStatistics,
     port: u16,
     received_from: Option<Ipv4Addr>,
     received_port: Option<u16>,
+    // Only datagrams of the peer are received and writes go to it
+    connected_to: Option<(Ipv4Addr, u16)>,
     open_sockets: WeakSharedSocketMap,
 }
 
@@ -72,9 +107,11 @@ impl AssignedSocket {
     fn new(port: u16, open_sockets: WeakSharedSocketMap) -> Self {
         Self {
             buffer: Vec::new(),
+            statistics: SocketStatistics::default(),
             port,
             received_from: None,
             received_port: None,
+            connected_to: None,
             open_sockets,
         }
     }
@@ -83,10 +120,50 @@ impl AssignedSocket {
         self.port
     }
 
+    /// The receive buffer if it is large enough to live in its own
+    /// pages, see memory::leak_check
+    pub fn buffer_pages(&self) -> Option<(usize, usize)> {
+        let capacity = self.buffer.capacity();
+        (capacity >= PAGE_SIZE)
+            .then(|| (self.buffer.as_ptr() as usize, capacity.div_ceil(PAGE_SIZE)))
+    }
+
     fn put_data(&mut self, from: Ipv4Addr, from_port: u16, data: &[u8]) {
+        if self
+            .connected_to
+            .is_some_and(|peer| peer != (from, from_port))
+        {
+            debug!(
+                "Dropped packet from {}:{} on connected socket",
+                from, from_port
+            );
+            return;
+        }
+        if self.buffer.len() + data.len() > RECEIVE_QUEUE_LIMIT {
+            debug!("Dropped packet on {} because the queue is full", self.port);
+            self.statistics.dropped += 1;
+            return;
+        }
+        self.statistics.received += 1;
         self.received_from = Some(from);
         self.received_port = Some(from_port);
-        self.buffer.extend_from_slice(data)
+        self.buffer.extend_from_slice(data);
+        poll::notify();
+    }
+
+    pub fn count_sent(&mut self) {
+        self.statistics.sent += 1;
+    }
+
+    pub fn statistics(&self) -> SocketStatistics {
+        SocketStatistics {
+            queued: self.buffer.len(),
+            ..self.statistics
+        }
+    }
+
+    pub fn has_data(&self) -> bool {
+        !self.buffer.is_empty()
     }
 
     pub fn get_data(&mut self, out_buffer: &mut [u8]) -> usize {
@@ -100,12 +177,14 @@ impl AssignedSocket {
         count
     }
 
-    pub fn get_from(&self) -> Option<Ipv4Addr> {
-        self.received_from
+    pub fn connect(&mut self, ip: Ipv4Addr, port: u16) {
+        self.connected_to = Some((ip, port));
     }
 
-    pub fn get_received_port(&self) -> Option<u16> {
-        self.received_port
+    /// Writes go to the connected peer or otherwise to the last sender
+    pub fn peer(&self) -> Option<(Ipv4Addr, u16)> {
+        self.connected_to
+            .or(self.received_from.zip(self.received_port))
     }
 }
 
@@ -127,7 +206,7 @@ impl Drop for AssignedSocket {
 mod tests {
     use core::net::Ipv4Addr;
 
-    use super::OpenSockets;
+    use super::{OpenSockets, SocketStatistics, EPHEMERAL_PORTS, RECEIVE_QUEUE_LIMIT};
 
     const PORT1: u16 = 1234;
     const FROM1: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
@@ -263,27 +342,97 @@ mod tests {
             .expect("There must be a free socket.");
 
         assert!(
-            assigned_socket.lock().get_from().is_none(),
+            assigned_socket.lock().peer().is_none(),
             "From must be initially empty."
         );
 
         open_sockets.put_data(FROM1, PORT1, PORT1, &[1, 2, 3]);
 
         assert_eq!(
-            assigned_socket.lock().get_from(),
-            Some(FROM1),
+            assigned_socket.lock().peer(),
+            Some((FROM1, PORT1)),
             "There must be the last received ip address."
         );
 
         open_sockets.put_data(FROM2, PORT1, PORT1, &[1, 2, 3]);
 
         assert_eq!(
-            assigned_socket.lock().get_from(),
-            Some(FROM2),
+            assigned_socket.lock().peer(),
+            Some((FROM2, PORT1)),
             "There must be the last received ip address."
         );
     }
 
+    #[test_case]
+    fn port_zero_assigns_an_ephemeral_port() {
+        let open_sockets = OpenSockets::new();
+
+        let first = open_sockets
+            .try_get_socket(0)
+            .expect("There must be a free port.");
+        let second = open_sockets
+            .try_get_socket(0)
+            .expect("There must be a free port.");
+
+        let (first, second) = (first.lock().get_port(), second.lock().get_port());
+        assert!(EPHEMERAL_PORTS.contains(&first));
+        assert!(EPHEMERAL_PORTS.contains(&second));
+        assert_ne!(first, second);
+    }
+
+    #[test_case]
+    fn connected_sockets_only_receive_from_their_peer() {
+        let open_sockets = OpenSockets::new();
+
+        let socket = open_sockets
+            .try_get_socket(PORT1)
+            .expect("There must be a free port.");
+        open_sockets.put_data(FROM1, PORT1, PORT1, &[1]);
+        assert_eq!(socket.lock().peer(), Some((FROM1, PORT1)));
+
+        socket.lock().connect(FROM2, PORT2);
+        assert_eq!(socket.lock().peer(), Some((FROM2, PORT2)));
+
+        open_sockets.put_data(FROM1, PORT1, PORT1, &[2]);
+        open_sockets.put_data(FROM2, PORT1, PORT1, &[3]);
+        open_sockets.put_data(FROM2, PORT2, PORT1, &[4]);
+
+        let mut buffer = [0; 4];
+        assert_eq!(socket.lock().get_data(&mut buffer), 2);
+        assert_eq!(buffer[..2], [1, 4]);
+        assert_eq!(socket.lock().peer(), Some((FROM2, PORT2)));
+    }
+
+    #[test_case]
+    fn datagrams_are_dropped_if_the_queue_is_full() {
+        let open_sockets = OpenSockets::new();
+
+        let socket = open_sockets
+            .try_get_socket(PORT1)
+            .expect("There must be a free port.");
+
+        let datagram = [42; RECEIVE_QUEUE_LIMIT / 2];
+        for _ in 0..3 {
+            open_sockets.put_data(FROM1, PORT1, PORT1, &datagram);
+        }
+        socket.lock().count_sent();
+
+        let expected = SocketStatistics {
+            queued: RECEIVE_QUEUE_LIMIT,
+            received: 2,
+            dropped: 1,
+            sent: 1,
+        };
+        assert_eq!(socket.lock().statistics(), expected);
+        assert_eq!(open_sockets.statistics(), [(PORT1, expected)]);
+
+        // Reading makes room again
+        let mut buffer = [0; 1];
+        socket.lock().get_data(&mut buffer);
+        open_sockets.put_data(FROM1, PORT1, PORT1, &[1]);
+        assert_eq!(socket.lock().statistics().received, 3);
+    }
+
     #[test_case]
     fn drop_must_work_correctly() {
         let open_sockets = OpenSockets::new();
@@ -0,0 +1,1048 @@
+//! A small TCP for servers. Connections are only accepted, the kernel
+//! never opens one itself. Segments have to arrive in order, others are
+//! answered with the sequence number we expect such that the peer sends
+//! them again. We never send options, therefore the peer neither scales
+//! its window nor sends segments larger than the default size.
+
+use core::net::Ipv4Addr;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use common::{
+    big_endian::BigEndian,
+    errors::SysSocketError,
+    mutex::{Mutex, MutexGuard},
+};
+
+use crate::{
+    assert::static_assert_size,
+    debug,
+    klibc::util::{BufferExtension, ByteInterpretable},
+    processes::{process_table::ProcessRef, timer, wait_queue::WaitQueue},
+};
+
+use super::{
+    ethernet::{EtherTypes, EthernetHeader},
+    ipv4::IpV4Header,
+    mac::MacAddress,
+    NetworkInterface,
+};
+
+pub const TCP_PROTOCOL_TYPE: u8 = 6;
+
+// Connections which were not accepted yet. Further connection attempts
+// are ignored, the peer tries again later.
+const BACKLOG: usize = 16;
+// Both fit into the window field without the window scale option
+const RECEIVE_BUFFER_LIMIT: usize = 32 * 1024;
+const SEND_BUFFER_LIMIT: usize = 32 * 1024;
+// Doubled for every retransmission of the same data
+const RETRANSMISSION_TIMEOUT_MILLISECONDS: u64 = 200;
+const MAX_RETRANSMISSIONS: u32 = 8;
+// How long a connection we closed waits for the FIN of the peer
+const FIN_TIMEOUT_MILLISECONDS: u64 = 10_000;
+// Payload the peer takes in one segment if it doesn't announce its own
+// maximum segment size, see RFC 9293 3.7.1
+const DEFAULT_SEGMENT_SIZE: usize = 536;
+
+const FIN: u16 = 1 << 0;
+const SYN: u16 = 1 << 1;
+const RST: u16 = 1 << 2;
+const PSH: u16 = 1 << 3;
+const ACK: u16 = 1 << 4;
+const FLAGS: u16 = FIN | SYN | RST | PSH | ACK;
+
+/// Packed because it follows the IP header at an offset which is not
+/// aligned for the sequence numbers
+#[repr(C, packed)]
+pub struct TcpHeader {
+    source_port: BigEndian<u16>,
+    destination_port: BigEndian<u16>,
+    sequence_number: BigEndian<u32>,
+    acknowledgment_number: BigEndian<u32>,
+    // Length of the header in 32 bit words in the upper 4 bits
+    offset_and_flags: BigEndian<u16>,
+    window: BigEndian<u16>,
+    checksum: BigEndian<u16>,
+    urgent_pointer: BigEndian<u16>,
+}
+
+static_assert_size!(TcpHeader, 20);
+
+impl ByteInterpretable for TcpHeader {}
+
+impl TcpHeader {
+    const HEADER_SIZE: usize = core::mem::size_of::<Self>();
+    const CHECKSUM_OFFSET: usize = 16;
+}
+
+#[derive(Debug)]
+pub enum TcpParseError {
+    PacketTooSmall,
+    InvalidChecksum,
+}
+
+/// A received or outgoing segment with the numbers in host byte order
+struct Segment<'a> {
+    source_port: u16,
+    destination_port: u16,
+    sequence: u32,
+    acknowledgment: u32,
+    flags: u16,
+    window: u16,
+    // Announced by the peer in its SYN
+    maximum_segment_size: Option<usize>,
+    payload: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    /// The checksum is only verified if the device didn't do it already
+    fn parse(
+        data: &'a [u8],
+        ip_header: &IpV4Header,
+        checksum_validated: bool,
+    ) -> Result<Self, TcpParseError> {
+        if data.len() < TcpHeader::HEADER_SIZE {
+            return Err(TcpParseError::PacketTooSmall);
+        }
+        let (header, _) = data.split_as::<TcpHeader>();
+        let offset_and_flags = { header.offset_and_flags }.get();
+        let header_length = usize::from(offset_and_flags >> 12) * 4;
+        if header_length < TcpHeader::HEADER_SIZE || header_length > data.len() {
+            return Err(TcpParseError::PacketTooSmall);
+        }
+        if !checksum_validated && checksum(ip_header.source_ip, ip_header.destination_ip, data) != 0
+        {
+            return Err(TcpParseError::InvalidChecksum);
+        }
+        Ok(Self {
+            source_port: { header.source_port }.get(),
+            destination_port: { header.destination_port }.get(),
+            sequence: { header.sequence_number }.get(),
+            acknowledgment: { header.acknowledgment_number }.get(),
+            flags: offset_and_flags & FLAGS,
+            window: { header.window }.get(),
+            maximum_segment_size: maximum_segment_size(
+                &data[TcpHeader::HEADER_SIZE..header_length],
+            ),
+            payload: &data[header_length..],
+        })
+    }
+
+    fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Sequence numbers the segment occupies, SYN and FIN count as one
+    fn length(&self) -> u32 {
+        self.payload.len() as u32 + u32::from(self.has(SYN)) + u32::from(self.has(FIN))
+    }
+
+    /// The ethernet, IP and TCP headers followed by the payload
+    fn create_packet(
+        &self,
+        destination_ip: Ipv4Addr,
+        destination_mac: MacAddress,
+        source_mac: MacAddress,
+    ) -> Vec<u8> {
+        let header = TcpHeader {
+            source_port: BigEndian::from_little_endian(self.source_port),
+            destination_port: BigEndian::from_little_endian(self.destination_port),
+            sequence_number: BigEndian::from_little_endian(self.sequence),
+            acknowledgment_number: BigEndian::from_little_endian(self.acknowledgment),
+            offset_and_flags: BigEndian::from_little_endian(
+                ((TcpHeader::HEADER_SIZE as u16 / 4) << 12) | self.flags,
+            ),
+            window: BigEndian::from_little_endian(self.window),
+            checksum: BigEndian::from_little_endian(0),
+            urgent_pointer: BigEndian::from_little_endian(0),
+        };
+        let mut segment = [header.as_slice(), self.payload].concat();
+        let checksum = checksum(super::IP_ADDR, destination_ip, &segment);
+        segment[TcpHeader::CHECKSUM_OFFSET..TcpHeader::CHECKSUM_OFFSET + 2]
+            .copy_from_slice(&checksum.to_be_bytes());
+
+        let ip_header = IpV4Header::new(destination_ip, TCP_PROTOCOL_TYPE, segment.len());
+        let ethernet_header = EthernetHeader::new(destination_mac, source_mac, EtherTypes::IPv4);
+
+        [ethernet_header.as_slice(), ip_header.as_slice(), &segment].concat()
+    }
+}
+
+/// Only the maximum segment size is of interest, see RFC 9293 3.1
+fn maximum_segment_size(mut options: &[u8]) -> Option<usize> {
+    while let [kind, rest @ ..] = options {
+        match kind {
+            // End of the option list
+            0 => return None,
+            // No operation, used for padding
+            1 => options = rest,
+            _ => {
+                let length = usize::from(*rest.first()?);
+                if length < 2 || length > options.len() {
+                    return None;
+                }
+                if *kind == 2 && length == 4 {
+                    return Some(usize::from(u16::from_be_bytes([options[2], options[3]])));
+                }
+                options = &options[length..];
+            }
+        }
+    }
+    None
+}
+
+/// Over the pseudo header and the whole segment, see RFC 9293 3.1. A
+/// received segment with a valid checksum sums up to 0.
+fn checksum(source: Ipv4Addr, destination: Ipv4Addr, segment: &[u8]) -> u16 {
+    let length = u16::try_from(segment.len()).expect("Segments must fit into an IP packet");
+    let pseudo_header = [
+        &source.octets()[..],
+        &destination.octets(),
+        &[0, TCP_PROTOCOL_TYPE],
+        &length.to_be_bytes(),
+    ]
+    .concat();
+
+    let mut sum = pseudo_header
+        .chunks(2)
+        .chain(segment.chunks(2))
+        .map(|pair| {
+            u32::from(u16::from_be_bytes([
+                pair[0],
+                pair.get(1).copied().unwrap_or(0),
+            ]))
+        })
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ConnectionId {
+    local_port: u16,
+    peer_ip: Ipv4Addr,
+    peer_port: u16,
+}
+
+pub type SharedTcpListener = Arc<Mutex<TcpListener>>;
+type SharedTcpConnection = Arc<Mutex<TcpConnection>>;
+
+/// Locked before the connections. A listener is locked before the
+/// table, see accept_connection.
+pub struct OpenTcpSockets {
+    listeners: BTreeMap<u16, Weak<Mutex<TcpListener>>>,
+    // Kept until both sides closed, also after the user dropped them
+    connections: BTreeMap<ConnectionId, SharedTcpConnection>,
+}
+
+impl OpenTcpSockets {
+    pub const fn new() -> Self {
+        Self {
+            listeners: BTreeMap::new(),
+            connections: BTreeMap::new(),
+        }
+    }
+
+    /// Returns None if the port is in use
+    pub fn listen(&mut self, port: u16) -> Option<SharedTcpListener> {
+        if port == 0 || self.listeners.contains_key(&port) {
+            return None;
+        }
+        let listener = Arc::new(Mutex::new(TcpListener {
+            port,
+            backlog: VecDeque::new(),
+        }));
+        self.listeners.insert(port, Arc::downgrade(&listener));
+        Some(listener)
+    }
+}
+
+/// Handles a segment for our address and sends the replies right away.
+/// `peer_mac` is the sender of the frame, replies go back to it.
+pub fn process_segment(
+    interface: &mut impl NetworkInterface,
+    peer_mac: MacAddress,
+    ip_header: &IpV4Header,
+    data: &[u8],
+    checksum_validated: bool,
+) -> Result<(), TcpParseError> {
+    let segment = Segment::parse(data, ip_header, checksum_validated)?;
+    let id = ConnectionId {
+        local_port: segment.destination_port,
+        peer_ip: ip_header.source_ip,
+        peer_port: segment.source_port,
+    };
+
+    let sockets = super::OPEN_TCP_SOCKETS.lock();
+    if let Some(connection) = sockets.connections.get(&id).cloned() {
+        drop(sockets);
+        connection.lock().receive(interface, &segment);
+    } else if segment.flags & (SYN | ACK | RST) == SYN {
+        let listener = sockets
+            .listeners
+            .get(&id.local_port)
+            .and_then(Weak::upgrade);
+        // The listener might be the last reference, it locks the table
+        // when it is dropped
+        drop(sockets);
+        match listener {
+            Some(listener) => accept_connection(interface, &listener, id, peer_mac, &segment),
+            None => refuse(interface, id, peer_mac, &segment),
+        }
+    } else if !segment.has(RST) {
+        drop(sockets);
+        debug!("Received segment for unknown connection {:?}", id);
+        refuse(interface, id, peer_mac, &segment);
+    }
+    Ok(())
+}
+
+fn accept_connection(
+    interface: &mut impl NetworkInterface,
+    listener: &SharedTcpListener,
+    id: ConnectionId,
+    peer_mac: MacAddress,
+    syn: &Segment,
+) {
+    let mut listener = listener.lock();
+    if listener.backlog.len() >= BACKLOG {
+        debug!(
+            "Ignored connection attempt on {} because the backlog is full",
+            id.local_port
+        );
+        return;
+    }
+    let mut sockets = super::OPEN_TCP_SOCKETS.lock();
+    // Another hart processed a copy of the SYN in the meantime
+    if sockets.connections.contains_key(&id) {
+        return;
+    }
+    let mut connection = TcpConnection::new(id, peer_mac, syn, interface.mtu());
+    connection.send_syn(interface);
+    let connection = Arc::new(Mutex::new(connection));
+    sockets.connections.insert(id, connection.clone());
+    listener.backlog.push_back(connection);
+}
+
+/// Answers segments without a connection with a reset, see RFC 9293 3.10.7.1
+fn refuse(
+    interface: &mut impl NetworkInterface,
+    id: ConnectionId,
+    peer_mac: MacAddress,
+    segment: &Segment,
+) {
+    let (sequence, acknowledgment, flags) = if segment.has(ACK) {
+        (segment.acknowledgment, 0, RST)
+    } else {
+        (
+            0,
+            segment.sequence.wrapping_add(segment.length()),
+            RST | ACK,
+        )
+    };
+    let reset = Segment {
+        source_port: id.local_port,
+        destination_port: id.peer_port,
+        sequence,
+        acknowledgment,
+        flags,
+        window: 0,
+        maximum_segment_size: None,
+        payload: &[],
+    };
+    interface.send_packet(reset.create_packet(id.peer_ip, peer_mac, interface.mac_address()));
+}
+
+/// Retransmits lost segments, closes connections whose peer is gone
+/// and forgets the connections which are closed. Called periodically.
+pub fn tick(interface: &mut impl NetworkInterface, now: u64) {
+    let connections: Vec<SharedTcpConnection> = super::OPEN_TCP_SOCKETS
+        .lock()
+        .connections
+        .values()
+        .cloned()
+        .collect();
+    for connection in connections {
+        connection.lock().tick(interface, now);
+    }
+    super::OPEN_TCP_SOCKETS
+        .lock()
+        .connections
+        .retain(|_, connection| !connection.lock().is_finished());
+}
+
+/// Connections of the port are accepted until it is dropped
+pub struct TcpListener {
+    port: u16,
+    // Connections are put here when the SYN arrives and taken once they
+    // are established
+    backlog: VecDeque<SharedTcpConnection>,
+}
+
+impl TcpListener {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The oldest established connection, if any. Connections which
+    /// were reset in the meantime are forgotten.
+    pub fn accept(&mut self) -> Option<TcpStream> {
+        self.backlog
+            .retain(|connection| !connection.lock().is_finished());
+        let index = self
+            .backlog
+            .iter()
+            .position(|connection| connection.lock().is_established())?;
+        self.backlog.remove(index).map(TcpStream)
+    }
+
+    pub fn has_connection(&self) -> bool {
+        self.backlog
+            .iter()
+            .any(|connection| connection.lock().is_established())
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        super::OPEN_TCP_SOCKETS.lock().listeners.remove(&self.port);
+        // The resets are sent with the next tick
+        for connection in &self.backlog {
+            connection.lock().abort();
+        }
+    }
+}
+
+/// An accepted connection. It is closed when dropped, which must not
+/// happen while the process which held it is locked.
+pub struct TcpStream(SharedTcpConnection);
+
+pub type SharedTcpStream = Arc<TcpStream>;
+
+impl TcpStream {
+    pub fn lock(&self) -> MutexGuard<'_, TcpConnection> {
+        self.0.lock()
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        // The FIN is sent with the next tick
+        self.0.lock().close();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // Our SYN is not acknowledged yet
+    SynReceived,
+    Established,
+    // Both sides sent a FIN which the other one acknowledged
+    Closed,
+    Reset,
+}
+
+pub struct TcpConnection {
+    id: ConnectionId,
+    peer_mac: MacAddress,
+    state: State,
+    // Sequence number of the first byte in the send buffer. It's the
+    // one of our SYN until the connection is established.
+    send_unacknowledged: u32,
+    send_buffer: VecDeque<u8>,
+    // Bytes at the start of the send buffer which were sent already
+    sent: usize,
+    peer_window: usize,
+    // Largest payload the peer takes in one segment
+    segment_size: usize,
+    receive_next: u32,
+    receive_buffer: VecDeque<u8>,
+    // We advertised a window of 0, the peer learns with the next tick
+    // that it may send again
+    window_closed: bool,
+    // The user dropped the connection, a FIN follows the buffered data
+    closed: bool,
+    fin_sent: bool,
+    fin_acknowledged: bool,
+    // The peer sent a FIN, reads return the end of the stream afterwards
+    peer_closed: bool,
+    // A reset is sent with the next tick
+    aborted: bool,
+    // Clocks at which the oldest unacknowledged segment is sent again
+    retransmit_at: Option<u64>,
+    retransmissions: u32,
+    // Clocks at which we stop waiting for the FIN of the peer
+    linger_until: Option<u64>,
+    // Processes which wait for space in the send buffer. They execute
+    // their write again when woken.
+    writers: WaitQueue<()>,
+}
+
+impl TcpConnection {
+    fn new(id: ConnectionId, peer_mac: MacAddress, syn: &Segment, mtu: usize) -> Self {
+        // Clock driven like suggested by RFC 9293 3.4.1
+        let initial_sequence = timer::get_current_clocks() as u32;
+        let largest_segment = mtu - IpV4Header::HEADER_SIZE - TcpHeader::HEADER_SIZE;
+        Self {
+            id,
+            peer_mac,
+            state: State::SynReceived,
+            send_unacknowledged: initial_sequence,
+            send_buffer: VecDeque::new(),
+            sent: 0,
+            peer_window: usize::from(syn.window),
+            segment_size: syn
+                .maximum_segment_size
+                .unwrap_or(DEFAULT_SEGMENT_SIZE)
+                .min(largest_segment),
+            receive_next: syn.sequence.wrapping_add(1),
+            receive_buffer: VecDeque::new(),
+            window_closed: false,
+            closed: false,
+            fin_sent: false,
+            fin_acknowledged: false,
+            peer_closed: false,
+            aborted: false,
+            retransmit_at: None,
+            retransmissions: 0,
+            linger_until: None,
+            writers: WaitQueue::new(),
+        }
+    }
+
+    fn is_established(&self) -> bool {
+        self.state == State::Established
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self.state, State::Closed | State::Reset)
+    }
+
+    /// Reads return right away, with the end of the stream if the peer
+    /// closed or reset the connection
+    pub fn is_readable(&self) -> bool {
+        !self.receive_buffer.is_empty() || self.peer_closed || self.state == State::Reset
+    }
+
+    /// Returns 0 if nothing arrived yet
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, SysSocketError> {
+        if self.receive_buffer.is_empty() {
+            return match self.state {
+                State::Reset => Err(SysSocketError::ConnectionReset),
+                _ if self.peer_closed => Err(SysSocketError::ConnectionClosed),
+                _ => Ok(0),
+            };
+        }
+        let count = buffer.len().min(self.receive_buffer.len());
+        for (destination, byte) in buffer.iter_mut().zip(self.receive_buffer.drain(..count)) {
+            *destination = byte;
+        }
+        Ok(count)
+    }
+
+    /// Takes as much as fits into the send buffer and sends what the
+    /// peer can take. Returns 0 if the send buffer is full.
+    pub fn write(
+        &mut self,
+        interface: &mut impl NetworkInterface,
+        data: &[u8],
+    ) -> Result<usize, SysSocketError> {
+        if self.state == State::Reset {
+            return Err(SysSocketError::ConnectionReset);
+        }
+        let count = data.len().min(SEND_BUFFER_LIMIT - self.send_buffer.len());
+        self.send_buffer.extend(&data[..count]);
+        self.transmit(interface);
+        Ok(count)
+    }
+
+    /// The process executes its write again once the peer acknowledged
+    /// data or reset the connection
+    pub fn wait_for_space(&mut self, process: &ProcessRef) {
+        self.writers.wait(process);
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+    }
+
+    fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    fn receive(&mut self, interface: &mut impl NetworkInterface, segment: &Segment) {
+        if self.is_finished() {
+            return;
+        }
+        if segment.has(RST) {
+            // Resets with other sequence numbers might be forged, see RFC 5961
+            if segment.sequence == self.receive_next {
+                self.reset();
+            }
+            return;
+        }
+        if segment.has(SYN) {
+            // Our SYN or the acknowledgment of it got lost
+            if self.state == State::SynReceived {
+                self.send_syn(interface);
+            } else {
+                self.send_acknowledgment(interface);
+            }
+            return;
+        }
+        if !segment.has(ACK) {
+            return;
+        }
+        self.acknowledge(segment.acknowledgment, segment.window);
+        if self.state == State::SynReceived {
+            return;
+        }
+        self.take_payload(interface, segment);
+        self.transmit(interface);
+        if self.fin_acknowledged && self.peer_closed {
+            self.state = State::Closed;
+        }
+    }
+
+    fn acknowledge(&mut self, acknowledgment: u32, window: u16) {
+        let acknowledged = acknowledgment.wrapping_sub(self.send_unacknowledged);
+        if acknowledged as usize > self.in_flight() {
+            debug!("Ignored acknowledgment of unsent data on {:?}", self.id);
+            return;
+        }
+        self.peer_window = usize::from(window);
+        if acknowledged == 0 {
+            return;
+        }
+
+        let mut remaining = acknowledged as usize;
+        if self.state == State::SynReceived {
+            self.state = State::Established;
+            remaining -= 1;
+        }
+        let data = remaining.min(self.sent);
+        self.send_buffer.drain(..data);
+        self.sent -= data;
+        if remaining > data {
+            self.fin_acknowledged = true;
+            if !self.peer_closed {
+                self.linger_until = Some(timer::deadline_in(FIN_TIMEOUT_MILLISECONDS));
+            }
+        }
+        self.send_unacknowledged = acknowledgment;
+        self.retransmissions = 0;
+        self.retransmit_at = None;
+        if data > 0 {
+            self.writers.wake_all(());
+        }
+    }
+
+    fn take_payload(&mut self, interface: &mut impl NetworkInterface, segment: &Segment) {
+        if segment.payload.is_empty() && !segment.has(FIN) {
+            return;
+        }
+        // Retransmissions might overlap with what we have already
+        let already_received = self.receive_next.wrapping_sub(segment.sequence) as usize;
+        if let Some(payload) = segment.payload.get(already_received..) {
+            if !self.peer_closed {
+                let space = RECEIVE_BUFFER_LIMIT - self.receive_buffer.len();
+                let taken = payload.len().min(space);
+                self.receive_buffer.extend(&payload[..taken]);
+                self.receive_next = self.receive_next.wrapping_add(taken as u32);
+                if segment.has(FIN) && taken == payload.len() {
+                    self.peer_closed = true;
+                    self.receive_next = self.receive_next.wrapping_add(1);
+                }
+            }
+        }
+        // Also answers segments which are out of order
+        self.send_acknowledgment(interface);
+    }
+
+    /// Sends the buffered data the window of the peer allows and the
+    /// FIN after it once the user closed the connection
+    fn transmit(&mut self, interface: &mut impl NetworkInterface) {
+        self.transmit_within(interface, self.peer_window);
+    }
+
+    fn transmit_within(&mut self, interface: &mut impl NetworkInterface, window: usize) {
+        if self.state != State::Established {
+            return;
+        }
+        while self.sent < self.send_buffer.len() && self.sent < window {
+            let length = (self.send_buffer.len() - self.sent)
+                .min(window - self.sent)
+                .min(self.segment_size);
+            let payload: Vec<u8> = self
+                .send_buffer
+                .range(self.sent..self.sent + length)
+                .copied()
+                .collect();
+            let sequence = self.send_unacknowledged.wrapping_add(self.sent as u32);
+            self.send(interface, sequence, ACK | PSH, &payload);
+            self.sent += length;
+        }
+        if self.closed && !self.fin_sent && self.sent == self.send_buffer.len() {
+            let sequence = self.send_unacknowledged.wrapping_add(self.sent as u32);
+            self.send(interface, sequence, FIN | ACK, &[]);
+            self.fin_sent = true;
+        }
+        // Data which doesn't fit into the window is probed for as well
+        if self.retransmit_at.is_none() && (self.in_flight() > 0 || self.has_unsent_data()) {
+            self.retransmit_at = Some(timer::deadline_in(RETRANSMISSION_TIMEOUT_MILLISECONDS));
+        }
+    }
+
+    fn tick(&mut self, interface: &mut impl NetworkInterface, now: u64) {
+        if self.is_finished() {
+            return;
+        }
+        if self.aborted {
+            self.send(interface, self.send_next(), RST | ACK, &[]);
+            self.reset();
+            return;
+        }
+        if self.linger_until.is_some_and(|deadline| now >= deadline) {
+            debug!("Peer of {:?} didn't close the connection", self.id);
+            self.state = State::Closed;
+            return;
+        }
+        if self.window_closed && self.receive_window() > 0 {
+            self.send_acknowledgment(interface);
+        }
+        // Sends the FIN after the user closed the connection
+        self.transmit(interface);
+        if self.retransmit_at.is_none_or(|deadline| now < deadline) {
+            return;
+        }
+
+        self.retransmissions += 1;
+        if self.retransmissions > MAX_RETRANSMISSIONS {
+            debug!("Peer of {:?} stopped acknowledging", self.id);
+            self.send(interface, self.send_next(), RST | ACK, &[]);
+            self.reset();
+            return;
+        }
+        let backoff = RETRANSMISSION_TIMEOUT_MILLISECONDS << self.retransmissions.min(4);
+        self.retransmit_at = Some(now + timer::milliseconds_to_clocks(backoff));
+        // Everything after the oldest unacknowledged byte is sent again
+        self.sent = 0;
+        self.fin_sent = self.fin_acknowledged;
+        if self.state == State::SynReceived {
+            self.send_syn(interface);
+        } else {
+            // At least one byte probes a window of 0
+            self.transmit_within(interface, self.peer_window.max(1));
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = State::Reset;
+        self.retransmit_at = None;
+        self.writers.wake_all(());
+    }
+
+    fn has_unsent_data(&self) -> bool {
+        self.sent < self.send_buffer.len()
+    }
+
+    /// Sequence numbers which were sent but not acknowledged yet
+    fn in_flight(&self) -> usize {
+        usize::from(self.state == State::SynReceived)
+            + self.sent
+            + usize::from(self.fin_sent && !self.fin_acknowledged)
+    }
+
+    fn send_next(&self) -> u32 {
+        self.send_unacknowledged
+            .wrapping_add(self.in_flight() as u32)
+    }
+
+    fn receive_window(&self) -> u16 {
+        (RECEIVE_BUFFER_LIMIT - self.receive_buffer.len()) as u16
+    }
+
+    fn send_syn(&mut self, interface: &mut impl NetworkInterface) {
+        self.send(interface, self.send_unacknowledged, SYN | ACK, &[]);
+        if self.retransmit_at.is_none() {
+            self.retransmit_at = Some(timer::deadline_in(RETRANSMISSION_TIMEOUT_MILLISECONDS));
+        }
+    }
+
+    fn send_acknowledgment(&mut self, interface: &mut impl NetworkInterface) {
+        self.send(interface, self.send_next(), ACK, &[]);
+    }
+
+    fn send(
+        &mut self,
+        interface: &mut impl NetworkInterface,
+        sequence: u32,
+        flags: u16,
+        payload: &[u8],
+    ) {
+        let window = self.receive_window();
+        self.window_closed = window == 0;
+        let segment = Segment {
+            source_port: self.id.local_port,
+            destination_port: self.id.peer_port,
+            sequence,
+            acknowledgment: self.receive_next,
+            flags,
+            window,
+            maximum_segment_size: None,
+            payload,
+        };
+        interface.send_packet(segment.create_packet(
+            self.id.peer_ip,
+            self.peer_mac,
+            interface.mac_address(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::net::Ipv4Addr;
+
+    use common::errors::SysSocketError;
+
+    use super::{
+        checksum, process_segment, tick, IpV4Header, Segment, ACK, FIN, PSH, RST, SYN,
+        TCP_PROTOCOL_TYPE,
+    };
+    use crate::{
+        net::{mac::MacAddress, NetworkInterface, IP_ADDR, OPEN_TCP_SOCKETS},
+        processes::timer,
+    };
+
+    const OWN_MAC: MacAddress = MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+    const PEER_MAC: MacAddress = MacAddress::new([0x52, 0x55, 0x0a, 0x00, 0x02, 0x02]);
+    const PEER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+    const PEER_SEQUENCE: u32 = 1000;
+
+    // Connections of other tests stay in the table, every test uses
+    // its own ports
+    struct TestInterface {
+        peer_port: u16,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl NetworkInterface for TestInterface {
+        fn mac_address(&self) -> MacAddress {
+            OWN_MAC
+        }
+
+        fn mtu(&self) -> usize {
+            1500
+        }
+
+        fn send_packet(&mut self, packet: Vec<u8>) {
+            let segment = &packet[14 + IpV4Header::HEADER_SIZE..];
+            assert_eq!(checksum(IP_ADDR, PEER_IP, segment), 0);
+            if u16::from_be_bytes([segment[2], segment[3]]) == self.peer_port {
+                self.sent.push(packet);
+            }
+        }
+    }
+
+    impl TestInterface {
+        fn new(peer_port: u16) -> Self {
+            Self {
+                peer_port,
+                sent: vec![],
+            }
+        }
+
+        /// Sequence, acknowledgment, flags and payload of what was sent
+        fn take_sent(&mut self) -> Vec<(u32, u32, u16, Vec<u8>)> {
+            self.sent
+                .drain(..)
+                .map(|packet| {
+                    let segment = Segment::parse(
+                        &packet[14 + IpV4Header::HEADER_SIZE..],
+                        &peer_header(0),
+                        true,
+                    )
+                    .expect("Sent segments must be valid");
+                    (
+                        segment.sequence,
+                        segment.acknowledgment,
+                        segment.flags,
+                        segment.payload.to_vec(),
+                    )
+                })
+                .collect()
+        }
+
+        fn receive(&mut self, local_port: u16, sequence: u32, acknowledgment: u32, flags: u16) {
+            self.receive_payload(local_port, sequence, acknowledgment, flags, &[]);
+        }
+
+        fn receive_payload(
+            &mut self,
+            local_port: u16,
+            sequence: u32,
+            acknowledgment: u32,
+            flags: u16,
+            payload: &[u8],
+        ) {
+            let segment = Segment {
+                source_port: self.peer_port,
+                destination_port: local_port,
+                sequence,
+                acknowledgment,
+                flags,
+                window: 4096,
+                maximum_segment_size: None,
+                payload,
+            };
+            // The test interface sends from our own address, the
+            // headers of the peer are taken from the packet
+            let mut packet = segment.create_packet(PEER_IP, PEER_MAC, OWN_MAC);
+            let data = &mut packet[14 + IpV4Header::HEADER_SIZE..];
+            data[16..18].copy_from_slice(&[0, 0]);
+            let checksum = checksum(PEER_IP, IP_ADDR, data);
+            data[16..18].copy_from_slice(&checksum.to_be_bytes());
+            process_segment(self, PEER_MAC, &peer_header(data.len()), data, false)
+                .expect("Segment must be valid");
+        }
+    }
+
+    fn peer_header(payload_length: usize) -> IpV4Header {
+        let mut header = IpV4Header::new(IP_ADDR, TCP_PROTOCOL_TYPE, payload_length);
+        header.source_ip = PEER_IP;
+        header
+    }
+
+    /// Returns the sequence number of our SYN
+    fn handshake(interface: &mut TestInterface, local_port: u16) -> u32 {
+        interface.receive(local_port, PEER_SEQUENCE, 0, SYN);
+        let sent = interface.take_sent();
+        assert_eq!(sent.len(), 1);
+        let (sequence, acknowledgment, flags, _) = sent[0];
+        assert_eq!(flags, SYN | ACK);
+        assert_eq!(acknowledgment, PEER_SEQUENCE + 1);
+        interface.receive(local_port, PEER_SEQUENCE + 1, sequence.wrapping_add(1), ACK);
+        sequence
+    }
+
+    #[test_case]
+    fn connections_are_accepted_once_established() {
+        let mut interface = TestInterface::new(40001);
+        let listener = OPEN_TCP_SOCKETS.lock().listen(7001).unwrap();
+        assert!(OPEN_TCP_SOCKETS.lock().listen(7001).is_none());
+
+        interface.receive(7001, PEER_SEQUENCE, 0, SYN);
+        let (sequence, ..) = interface.take_sent()[0];
+        assert!(!listener.lock().has_connection());
+        assert!(listener.lock().accept().is_none());
+
+        interface.receive(7001, PEER_SEQUENCE + 1, sequence.wrapping_add(1), ACK);
+        assert!(interface.take_sent().is_empty());
+        assert!(listener.lock().has_connection());
+        assert!(listener.lock().accept().is_some());
+        assert!(!listener.lock().has_connection());
+    }
+
+    #[test_case]
+    fn data_is_acknowledged_and_read_until_the_fin() {
+        let mut interface = TestInterface::new(40002);
+        let listener = OPEN_TCP_SOCKETS.lock().listen(7002).unwrap();
+        let sequence = handshake(&mut interface, 7002).wrapping_add(1);
+        let stream = listener.lock().accept().unwrap();
+        assert!(!stream.lock().is_readable());
+
+        interface.receive_payload(7002, PEER_SEQUENCE + 1, sequence, ACK | PSH, b"hello");
+        assert_eq!(
+            interface.take_sent(),
+            [(sequence, PEER_SEQUENCE + 6, ACK, vec![])]
+        );
+        // Retransmissions are acknowledged but not read twice
+        interface.receive_payload(7002, PEER_SEQUENCE + 1, sequence, ACK | PSH, b"hello!");
+        assert_eq!(interface.take_sent()[0].1, PEER_SEQUENCE + 7);
+
+        let mut buffer = [0; 16];
+        assert!(stream.lock().is_readable());
+        assert!(matches!(stream.lock().read(&mut buffer), Ok(6)));
+        assert_eq!(&buffer[..6], b"hello!");
+        assert!(matches!(stream.lock().read(&mut buffer), Ok(0)));
+
+        interface.receive(7002, PEER_SEQUENCE + 7, sequence, ACK | FIN);
+        assert_eq!(interface.take_sent()[0].1, PEER_SEQUENCE + 8);
+        assert!(stream.lock().is_readable());
+        assert!(matches!(
+            stream.lock().read(&mut buffer),
+            Err(SysSocketError::ConnectionClosed)
+        ));
+    }
+
+    #[test_case]
+    fn written_data_is_sent_again_until_acknowledged() {
+        let mut interface = TestInterface::new(40003);
+        let listener = OPEN_TCP_SOCKETS.lock().listen(7003).unwrap();
+        let sequence = handshake(&mut interface, 7003).wrapping_add(1);
+        let stream = listener.lock().accept().unwrap();
+
+        assert!(matches!(
+            stream.lock().write(&mut interface, b"world"),
+            Ok(5)
+        ));
+        let sent = [(sequence, PEER_SEQUENCE + 1, ACK | PSH, b"world".to_vec())];
+        assert_eq!(interface.take_sent(), sent);
+
+        tick(&mut interface, timer::get_current_clocks());
+        assert!(interface.take_sent().is_empty());
+        let later = timer::get_current_clocks() + timer::milliseconds_to_clocks(1000);
+        tick(&mut interface, later);
+        assert_eq!(interface.take_sent(), sent);
+
+        interface.receive(7003, PEER_SEQUENCE + 1, sequence.wrapping_add(5), ACK);
+        tick(
+            &mut interface,
+            later + timer::milliseconds_to_clocks(10_000),
+        );
+        assert!(interface.take_sent().is_empty());
+
+        // The FIN follows once the stream is dropped
+        drop(stream);
+        tick(&mut interface, timer::get_current_clocks());
+        assert_eq!(
+            interface.take_sent(),
+            [(
+                sequence.wrapping_add(5),
+                PEER_SEQUENCE + 1,
+                FIN | ACK,
+                vec![]
+            )]
+        );
+    }
+
+    #[test_case]
+    fn segments_without_listener_are_refused() {
+        let mut interface = TestInterface::new(40004);
+        interface.receive(7004, PEER_SEQUENCE, 0, SYN);
+        assert_eq!(
+            interface.take_sent(),
+            [(0, PEER_SEQUENCE + 1, RST | ACK, vec![])]
+        );
+
+        interface.receive(7004, PEER_SEQUENCE, 42, ACK);
+        assert_eq!(interface.take_sent(), [(42, 0, RST, vec![])]);
+
+        // Resets are never answered
+        interface.receive(7004, PEER_SEQUENCE, 0, RST);
+        assert!(interface.take_sent().is_empty());
+
+        // Neither after the listener is gone
+        drop(OPEN_TCP_SOCKETS.lock().listen(7004).unwrap());
+        interface.receive(7004, PEER_SEQUENCE, 0, SYN);
+        assert_eq!(interface.take_sent()[0].2, RST | ACK);
+    }
+}
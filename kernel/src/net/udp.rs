@@ -28,11 +28,18 @@ impl ByteInterpretable for UdpHeader {}
 #[derive(Debug)]
 pub enum UdpParseError {
     PacketTooSmall,
+    InvalidChecksum,
 }
 
 impl UdpHeader {
     const UDP_HEADER_SIZE: usize = core::mem::size_of::<Self>();
-    const UDP_PROTOCOL_TYPE: u8 = 17;
+    pub const UDP_PROTOCOL_TYPE: u8 = 17;
+
+    /// Largest payload which fits into a single IP packet. We don't
+    /// fragment, bigger datagrams can't be sent.
+    pub const fn max_payload_size(mtu: usize) -> usize {
+        mtu - IpV4Header::HEADER_SIZE - Self::UDP_HEADER_SIZE
+    }
 
     pub fn destination_port(&self) -> u16 {
         self.destination_port.get()
@@ -41,10 +48,34 @@ impl UdpHeader {
         self.source_port.get()
     }
 
+    #[cfg(test)]
     pub fn create_udp_packet(
         destination_ip: Ipv4Addr,
         destination_port: u16,
         destination_mac: MacAddress,
+        source_mac: MacAddress,
+        source_port: u16,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let headers = Self::create_udp_headers(
+            destination_ip,
+            destination_port,
+            destination_mac,
+            source_mac,
+            source_port,
+            data,
+        );
+        [&headers[..], data].concat()
+    }
+
+    /// The ethernet, IP and UDP headers of a datagram carrying `data`.
+    /// The payload isn't part of the result such that it can be sent
+    /// from where it is.
+    pub fn create_udp_headers(
+        destination_ip: Ipv4Addr,
+        destination_port: u16,
+        destination_mac: MacAddress,
+        source_mac: MacAddress,
         source_port: u16,
         data: &[u8],
     ) -> Vec<u8> {
@@ -58,49 +89,36 @@ impl UdpHeader {
             checksum: BigEndian::from_little_endian(0),
         };
 
-        let mut ip_header = IpV4Header {
-            version_and_ihl: BigEndian::from_little_endian((4 << 4) | 5), // ip version v4 and header length 5 * 4byte
-            tos: BigEndian::from_little_endian(0),
-            total_packet_length: BigEndian::from_little_endian(
-                u16::try_from(IpV4Header::HEADER_SIZE + Self::UDP_HEADER_SIZE + data.len())
-                    .expect("Size must not exceed u16"),
-            ),
-            identification: BigEndian::from_little_endian(0),
-            flags_and_offset: BigEndian::from_big_endian(0), // DF Bits set (don't fragment)
-            ttl: BigEndian::from_little_endian(128),
-            upper_protocol: BigEndian::from_little_endian(Self::UDP_PROTOCOL_TYPE),
-            header_checksum: BigEndian::from_little_endian(0),
-            source_ip: super::IP_ADDR,
+        let ip_header = IpV4Header::new(
             destination_ip,
-        };
+            Self::UDP_PROTOCOL_TYPE,
+            Self::UDP_HEADER_SIZE + data.len(),
+        );
 
         udp_header.checksum =
             BigEndian::from_little_endian(Self::compute_checksum(data, &udp_header, &ip_header));
 
-        ip_header.header_checksum = BigEndian::from_little_endian(ip_header.calculate_checksum());
-
         let ethernet_header = EthernetHeader::new(
             destination_mac,
-            super::current_mac_address(),
+            source_mac,
             crate::net::ethernet::EtherTypes::IPv4,
         );
 
-        let data = [
+        debug!("Sending UDP packet with payload size {}", data.len());
+
+        [
             ethernet_header.as_slice(),
             ip_header.as_slice(),
             udp_header.as_slice(),
-            data,
         ]
-        .concat();
-
-        debug!("Sending UDP packet with size {}", data.len());
-
-        data
+        .concat()
     }
 
+    /// The checksum is only verified if the device didn't do it already
     pub fn process<'a>(
         data: &'a [u8],
         ip_header: &IpV4Header,
+        checksum_validated: bool,
     ) -> Result<(&'a UdpHeader, &'a [u8]), UdpParseError> {
         if data.len() < Self::UDP_HEADER_SIZE {
             return Err(UdpParseError::PacketTooSmall);
@@ -122,17 +140,15 @@ impl UdpHeader {
         let data_length = udp_header.length.get() as usize - Self::UDP_HEADER_SIZE;
         let rest = &rest[..data_length];
 
-        // Check checksum
-        assert!(
-            udp_header.checksum.get() != 0,
-            "we test impl for checksum not zero"
-        );
-
         debug!("Got checksum: {:#x}", udp_header.checksum.get());
 
-        let computed_checksum = Self::compute_checksum(rest, udp_header, ip_header);
-
-        assert_eq!(computed_checksum, 0, "must be zero for a valid packet.");
+        // A checksum of zero means the sender didn't compute one
+        if !checksum_validated
+            && udp_header.checksum.get() != 0
+            && Self::compute_checksum(rest, udp_header, ip_header) != 0
+        {
+            return Err(UdpParseError::InvalidChecksum);
+        }
 
         Ok((udp_header, rest))
     }
@@ -2,7 +2,7 @@
 use crate::{println, test::qemu_exit::wait_for_the_end};
 use core::{
     panic::PanicInfo,
-    sync::atomic::{AtomicIsize, AtomicU8},
+    sync::atomic::{AtomicBool, AtomicIsize, AtomicU8, AtomicUsize, Ordering},
 };
 
 #[cfg(test)]
@@ -11,11 +11,19 @@ use crate::test::qemu_exit::exit_failure;
 static PANIC_COUNTER: AtomicU8 = AtomicU8::new(0);
 static CPU_ENTERED_PANIC: AtomicIsize = AtomicIsize::new(-1);
 
+// Set by the panicking hart to make the other harts dump their state
+static PANIC_BROADCAST: AtomicBool = AtomicBool::new(false);
+// The hart which is allowed to print. Keeps the dumps from interleaving.
+static DUMP_TURN: AtomicUsize = AtomicUsize::new(NO_HART);
+static DUMPS_FINISHED: AtomicBool = AtomicBool::new(false);
+const NO_HART: usize = usize::MAX;
+
+const DUMP_TIMEOUT_MS: u64 = 500;
+const DUMP_BACKTRACE_DEPTH: usize = 16;
+
 #[cfg(not(miri))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    use core::sync::atomic::Ordering;
-
     use crate::{asm::wfi_loop, cpu::Cpu};
 
     unsafe {
@@ -34,6 +42,12 @@ fn panic(info: &PanicInfo) -> ! {
         wfi_loop();
     }
 
+    // Interrupts are off from now on. Panics before the uart is
+    // initialized are printed through the earlycon.
+    crate::io::uart::CONSOLE_UART
+        .lock()
+        .switch_to_synchronous_output();
+
     println!("");
     println!("KERNEL Panic Occured on cpu {}!", Cpu::cpu_id());
     println!("Message: {}", info.message());
@@ -47,6 +61,12 @@ fn panic(info: &PanicInfo) -> ! {
     abort_if_double_panic();
     crate::debugging::backtrace::print();
     crate::debugging::dump_current_state();
+    crate::debugging::crash_dump::record_current_hart();
+    dump_other_harts();
+    crate::debugging::crash_dump::write(info);
+
+    #[cfg(not(test))]
+    crate::debugging::gdb_stub::handle_panic();
 
     println!("Time to attach gdb ;) use 'just attach'");
 
@@ -71,3 +91,79 @@ fn abort_if_double_panic() {
         wait_for_the_end();
     }
 }
+
+/// Lets every other online hart print its state one after another.
+/// Harts which have interrupts disabled can't answer and are skipped
+/// after a timeout.
+#[cfg(not(miri))]
+fn dump_other_harts() {
+    use crate::{
+        cpu::Cpu,
+        processes::{hotplug, timer},
+        sbi::extensions::ipi_extension,
+    };
+
+    let own_hart = Cpu::cpu_id();
+    let other_harts = hotplug::online_harts() & !(1 << own_hart);
+    if other_harts == 0 {
+        return;
+    }
+
+    PANIC_BROADCAST.store(true, Ordering::SeqCst);
+    for hart in (0..u64::BITS as usize).filter(|hart| other_harts & (1 << hart) != 0) {
+        DUMP_TURN.store(hart, Ordering::SeqCst);
+        // Don't panic again if the hart can't be reached
+        let _ = ipi_extension::send_ipi(1, hart as u64);
+
+        let deadline = timer::get_current_clocks() + timer::milliseconds_to_clocks(DUMP_TIMEOUT_MS);
+        while DUMP_TURN.load(Ordering::SeqCst) == hart {
+            if timer::get_current_clocks() > deadline {
+                println!("Hart {hart} did not respond (interrupts disabled?)");
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    DUMPS_FINISHED.store(true, Ordering::SeqCst);
+}
+
+/// Called on every software interrupt. Doesn't return if another
+/// hart panicked.
+pub fn handle_panic_broadcast() {
+    use crate::{asm::wfi_loop, cpu::Cpu, debugging::backtrace};
+
+    if !PANIC_BROADCAST.load(Ordering::SeqCst) {
+        return;
+    }
+
+    // We might have got here through another software interrupt
+    // and have to wait for our turn
+    let cpu_id = Cpu::cpu_id();
+    while DUMP_TURN.load(Ordering::SeqCst) != cpu_id && !DUMPS_FINISHED.load(Ordering::SeqCst) {
+        core::hint::spin_loop();
+    }
+
+    if DUMP_TURN.load(Ordering::SeqCst) == cpu_id {
+        println!("");
+        println!("State of hart {cpu_id}:");
+        println!("PID: {:?}", Cpu::maybe_current_pid());
+        println!(
+            "Interrupted in {} mode at:",
+            if Cpu::is_in_kernel_mode() {
+                "kernel"
+            } else {
+                "user"
+            }
+        );
+        backtrace::print_addresses(core::iter::once(Cpu::read_sepc()));
+        println!("Backtrace:");
+        let mut addresses = [0; DUMP_BACKTRACE_DEPTH];
+        backtrace::capture(&mut addresses);
+        backtrace::print_addresses(addresses.into_iter().take_while(|&address| address != 0));
+        crate::debugging::crash_dump::record_current_hart();
+        DUMP_TURN.store(NO_HART, Ordering::SeqCst);
+    }
+
+    // Frozen until the system is restarted
+    wfi_loop();
+}
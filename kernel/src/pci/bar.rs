@@ -0,0 +1,70 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarKind {
+    Io,
+    Memory32Bit,
+    Memory64Bit,
+}
+
+impl BarKind {
+    pub fn from_bar_value(bar_value: u32) -> Option<Self> {
+        if bar_value & 0x1 == 0x1 {
+            return Some(BarKind::Io);
+        }
+        match (bar_value & 0b110) >> 1 {
+            0x0 => Some(BarKind::Memory32Bit),
+            0x2 => Some(BarKind::Memory64Bit),
+            _ => None,
+        }
+    }
+
+    /// Number of low bits which describe the type of the bar
+    fn flag_bits(&self) -> u32 {
+        match self {
+            BarKind::Io => 0b11,
+            BarKind::Memory32Bit | BarKind::Memory64Bit => 0b1111,
+        }
+    }
+}
+
+/// Computes the size of a bar from the value read back after writing all ones.
+/// `sized_upper` is only used for 64 bit bars.
+pub fn bar_size(kind: BarKind, sized_lower: u32, sized_upper: u32) -> u64 {
+    let lower = sized_lower & !kind.flag_bits();
+    let mask = match kind {
+        BarKind::Memory64Bit => ((sized_upper as u64) << 32) | lower as u64,
+        // Devices may hardwire the upper 16 bits of io bars to zero
+        BarKind::Io if lower & 0xffff_0000 == 0 => 0xffff_ffff_ffff_0000 | lower as u64,
+        BarKind::Io | BarKind::Memory32Bit => 0xffff_ffff_0000_0000 | lower as u64,
+    };
+    // The bits which are not writable are zero because of the alignment
+    (!mask).wrapping_add(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bar_size, BarKind};
+
+    #[test_case]
+    fn bar_kinds() {
+        assert_eq!(BarKind::from_bar_value(0x1), Some(BarKind::Io));
+        assert_eq!(BarKind::from_bar_value(0x0), Some(BarKind::Memory32Bit));
+        assert_eq!(BarKind::from_bar_value(0x8), Some(BarKind::Memory32Bit));
+        assert_eq!(BarKind::from_bar_value(0xc), Some(BarKind::Memory64Bit));
+        assert_eq!(BarKind::from_bar_value(0x2), None);
+    }
+
+    #[test_case]
+    fn bar_sizes() {
+        assert_eq!(bar_size(BarKind::Memory32Bit, 0xffff_f000, 0), 0x1000);
+        assert_eq!(
+            bar_size(BarKind::Memory64Bit, 0xffff_c00c, 0xffff_ffff),
+            0x4000
+        );
+        assert_eq!(
+            bar_size(BarKind::Memory64Bit, 0x0000_000c, 0xffff_fffe),
+            0x2_0000_0000
+        );
+        assert_eq!(bar_size(BarKind::Io, 0xffff_ffe1, 0), 0x20);
+        assert_eq!(bar_size(BarKind::Io, 0x0000_ffe1, 0), 0x20);
+    }
+}
@@ -1,8 +1,7 @@
 use core::fmt::Debug;
 
-use crate::device_tree::{self};
+use crate::device_tree::tree;
 use alloc::vec::Vec;
-use common::big_endian::BigEndian;
 
 #[derive(Debug)]
 pub struct PCIInformation {
@@ -84,60 +83,37 @@ pub struct PCIRange {
     pub size: usize,
 }
 
+/// Boards without a generic ECAM host bridge have no PCI
 pub fn parse() -> Option<PCIInformation> {
-    let mut pci_information = PCIInformation {
-        pci_host_bridge_address: 0,
-        pci_host_bridge_length: 0,
-        ranges: Vec::new(),
-    };
-
-    let dt_root_node = device_tree::THE.root_node();
-
-    let node = dt_root_node.find_node("pci")?;
-
-    let reg_property = node.parse_reg_property()?;
-
-    pci_information.pci_host_bridge_address = reg_property.address;
-    pci_information.pci_host_bridge_length = reg_property.size;
-
-    let mut ranges_property = node.get_property("ranges")?;
-
-    while !ranges_property.empty() {
-        assert_eq!(node.address_cells, Some(3), "pci addresses must be described by 3 u32 values: the bitfield and then the acutal address");
-        let pci_bitfield = ranges_property
-            .consume_sized_type::<BigEndian<u32>>()?
-            .get();
-        let pci_child_address = ranges_property
-            .consume_sized_type::<BigEndian<u64>>()?
-            .get() as usize;
-
-        let parent_address = match node.parent_address_cells? {
-            1 => ranges_property
-                .consume_sized_type::<BigEndian<u32>>()?
-                .get() as usize,
-            2 => ranges_property
-                .consume_sized_type::<BigEndian<u64>>()?
-                .get() as usize,
-            _ => panic!("pci address cannot be larger than 64 bit"),
-        };
-
-        let size = match node.size_cells? {
-            1 => ranges_property
-                .consume_sized_type::<BigEndian<u32>>()?
-                .get() as usize,
-            2 => ranges_property
-                .consume_sized_type::<BigEndian<u64>>()?
-                .get() as usize,
-            _ => panic!("pci size cannot be larger than 64 bit"),
-        };
-
-        pci_information.ranges.push(PCIRange {
-            pci_bitfield: pci_bitfield.into(),
-            pci_address: pci_child_address,
-            cpu_address: parent_address,
-            size,
-        });
-    }
-
-    Some(pci_information)
+    let node = tree::root().walk().find(|node| {
+        node.is_enabled()
+            && node
+                .compatible()
+                .any(|compatible| super::DEVICE_TREE_DRIVER.compatible.contains(&compatible))
+    })?;
+
+    let reg = node.first_reg()?;
+
+    assert_eq!(
+        node.address_cells(),
+        3,
+        "pci addresses must be described by 3 u32 values: the bitfield and then the acutal address"
+    );
+
+    let ranges = node
+        .ranges()
+        .into_iter()
+        .map(|range| PCIRange {
+            pci_bitfield: ((range.child_address >> 64) as u32).into(),
+            pci_address: range.child_address as u64 as usize,
+            cpu_address: range.parent_address,
+            size: range.size,
+        })
+        .collect();
+
+    Some(PCIInformation {
+        pci_host_bridge_address: reg.address,
+        pci_host_bridge_length: reg.size,
+        ranges,
+    })
 }
@@ -0,0 +1,110 @@
+use core::fmt::Display;
+
+/// Size of the configuration space of a single function
+pub const CONFIGURATION_SPACE_SIZE: usize = 4096;
+
+pub const MAX_DEVICES_PER_BUS: u8 = 32;
+pub const MAX_FUNCTIONS_PER_DEVICE: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub fn new(bus: u8, device: u8, function: u8) -> Self {
+        assert!(device < MAX_DEVICES_PER_BUS);
+        assert!(function < MAX_FUNCTIONS_PER_DEVICE);
+        Self {
+            bus,
+            device,
+            function,
+        }
+    }
+
+    fn ecam_offset(&self) -> usize {
+        ((self.bus as usize) << 20)
+            | ((self.device as usize) << 15)
+            | ((self.function as usize) << 12)
+    }
+}
+
+impl Display for PciAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:02x}:{:02x}.{}", self.bus, self.device, self.function)
+    }
+}
+
+/// The enhanced configuration access mechanism maps the configuration
+/// space of every function into one contiguous memory region.
+pub struct Ecam {
+    base: usize,
+    number_of_buses: usize,
+}
+
+impl Ecam {
+    pub fn new(base: usize, length: usize) -> Self {
+        Self {
+            base,
+            // Every bus takes 32 devices * 8 functions * 4 KiB
+            number_of_buses: length >> 20,
+        }
+    }
+
+    pub fn number_of_buses(&self) -> usize {
+        self.number_of_buses
+    }
+
+    /// Returns None if the bus is not covered by the ECAM region.
+    pub fn configuration_space_address(&self, address: PciAddress) -> Option<usize> {
+        if address.bus as usize >= self.number_of_buses {
+            return None;
+        }
+        Some(self.base + address.ecam_offset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ecam, PciAddress};
+
+    #[test_case]
+    fn ecam_offsets() {
+        let ecam = Ecam::new(0x3000_0000, 0x1000_0000);
+        assert_eq!(ecam.number_of_buses(), 256);
+        assert_eq!(
+            ecam.configuration_space_address(PciAddress::new(0, 0, 0)),
+            Some(0x3000_0000)
+        );
+        assert_eq!(
+            ecam.configuration_space_address(PciAddress::new(0, 1, 0)),
+            Some(0x3000_8000)
+        );
+        assert_eq!(
+            ecam.configuration_space_address(PciAddress::new(0, 0, 1)),
+            Some(0x3000_1000)
+        );
+        assert_eq!(
+            ecam.configuration_space_address(PciAddress::new(2, 31, 7)),
+            Some(0x3000_0000 + (2 << 20) + (31 << 15) + (7 << 12))
+        );
+    }
+
+    #[test_case]
+    fn bus_outside_of_ecam() {
+        let ecam = Ecam::new(0x3000_0000, 2 << 20);
+        assert!(ecam
+            .configuration_space_address(PciAddress::new(1, 0, 0))
+            .is_some());
+        assert!(ecam
+            .configuration_space_address(PciAddress::new(2, 0, 0))
+            .is_none());
+    }
+
+    #[test_case]
+    fn address_display() {
+        assert_eq!(alloc::format!("{}", PciAddress::new(0x1a, 3, 2)), "1a:03.2");
+    }
+}
@@ -1,24 +1,79 @@
-use crate::{debug, info, klibc::MMIO, mmio_struct, pci};
+use crate::{
+    debug,
+    drivers::platform::{DeviceTreeDriver, ProbeError},
+    info,
+    klibc::MMIO,
+    mmio_struct, warn,
+};
 use alloc::{collections::BTreeMap, vec::Vec};
 
 mod allocator;
+mod bar;
 mod devic_tree_parser;
+mod ecam;
 mod lookup;
 
+use bar::BarKind;
 use common::mutex::Mutex;
+use core::mem::size_of;
+use ecam::Ecam;
 use lookup::lookup;
 
+pub use ecam::PciAddress;
+
 pub use devic_tree_parser::parse;
 
 use self::allocator::{PCIAllocatedSpace, PCIAllocator};
 pub use self::devic_tree_parser::{PCIBitField, PCIInformation, PCIRange};
 
+// The host bridge is set up by parse and enumerate_devices. The driver
+// only claims the node such that it doesn't show up as unclaimed.
+pub const DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "pci-host-ecam",
+    compatible: &["pci-host-ecam-generic"],
+    needs_mapping: false,
+    probe: |node| {
+        node.first_reg()
+            .map(|_| ())
+            .ok_or(ProbeError::MissingProperty("reg"))
+    },
+};
+
 pub static PCI_ALLOCATOR_64_BIT: Mutex<PCIAllocator> = Mutex::new(PCIAllocator::new());
+pub static PCI_ALLOCATOR_32_BIT: Mutex<PCIAllocator> = Mutex::new(PCIAllocator::new());
+pub static PCI_ALLOCATOR_IO: Mutex<PCIAllocator> = Mutex::new(PCIAllocator::new());
+
+/// Sets up the bar allocators from the ranges of the host bridge. Only the
+/// 64 bit memory window is mandatory; devices with other bars fail to
+/// initialize if the corresponding window is missing.
+pub fn init_allocators(pci_information: &PCIInformation) {
+    let allocators = [
+        (PCIBitField::MEMORY_SPACE_64_BIT_CODE, &PCI_ALLOCATOR_64_BIT),
+        (PCIBitField::MEMORY_SPACE_32_BIT_CODE, &PCI_ALLOCATOR_32_BIT),
+        (PCIBitField::IO_SPACE_CODE, &PCI_ALLOCATOR_IO),
+    ];
+    for (space_code, allocator) in allocators {
+        if let Some(range) = pci_information.get_first_range_for_type(space_code) {
+            debug!("PCI allocator for {:?}", range.pci_bitfield);
+            allocator.lock().init(range);
+        }
+    }
+    assert!(
+        pci_information
+            .get_first_range_for_type(PCIBitField::MEMORY_SPACE_64_BIT_CODE)
+            .is_some(),
+        "There must be a 64 bit allocation space."
+    );
+}
 
 const INVALID_VENDOR_ID: u16 = 0xffff;
 
 const GENERAL_DEVICE_TYPE: u8 = 0x0;
-const GENERAL_DEVICE_TYPE_MASK: u8 = !0x80;
+const PCI_BRIDGE_TYPE: u8 = 0x1;
+const HEADER_TYPE_MASK: u8 = !0x80;
+const MULTI_FUNCTION_BIT: u8 = 0x80;
+
+const INTERRUPT_PIN_OFFSET: usize = 0x3d;
 
 const CAPABILITY_POINTER_MASK: u8 = !0x3;
 
@@ -29,6 +84,7 @@ const VIRTIO_NETWORK_SUBSYSTEM_ID: u16 = 1;
 pub mod command_register {
     pub const IO_SPACE: u16 = 1 << 0;
     pub const MEMORY_SPACE: u16 = 1 << 1;
+    pub const BUS_MASTER: u16 = 1 << 2;
 }
 
 mmio_struct! {
@@ -78,7 +134,7 @@ impl MMIO<GeneralDevicePciHeader> {
 }
 
 pub struct PciCapabilityIter<'a> {
-    pci_device: &'a PCIDevice,
+    pci_device: &'a PciDevice,
     next_offset: u8, // 0 means there is no next pointer
 }
 
@@ -107,30 +163,67 @@ impl Iterator for PciCapabilityIter<'_> {
     }
 }
 
-pub struct PCIDevice {
+pub struct PciDevice {
+    address: PciAddress,
     configuration_space: MMIO<GeneralDevicePciHeader>,
     initialized_bars: BTreeMap<u8, PCIAllocatedSpace>,
 }
 
-impl PCIDevice {
-    pub fn configuration_space_mut(&mut self) -> &mut MMIO<GeneralDevicePciHeader> {
-        &mut self.configuration_space
+impl PciDevice {
+    fn new(address: PciAddress, configuration_space_address: usize) -> Self {
+        Self {
+            address,
+            configuration_space: MMIO::new(configuration_space_address),
+            initialized_bars: BTreeMap::new(),
+        }
     }
 
-    pub fn configuration_space(&self) -> &MMIO<GeneralDevicePciHeader> {
-        &self.configuration_space
+    pub fn address(&self) -> PciAddress {
+        self.address
     }
 
-    unsafe fn try_new(address: usize) -> Option<Self> {
-        let pci_device: MMIO<GeneralDevicePciHeader> = MMIO::new(address);
-        if pci_device.vendor_id().read() == INVALID_VENDOR_ID {
-            return None;
-        }
-        assert!(pci_device.header_type().read() & GENERAL_DEVICE_TYPE_MASK == GENERAL_DEVICE_TYPE);
-        Some(Self {
-            configuration_space: pci_device,
-            initialized_bars: BTreeMap::new(),
-        })
+    pub fn vendor_id(&self) -> u16 {
+        self.configuration_space.vendor_id().read()
+    }
+
+    pub fn device_id(&self) -> u16 {
+        self.configuration_space.device_id().read()
+    }
+
+    pub fn subsystem_id(&self) -> u16 {
+        self.configuration_space.subsystem_id().read()
+    }
+
+    pub fn class_code(&self) -> u8 {
+        self.configuration_space.class_code().read()
+    }
+
+    pub fn subclass(&self) -> u8 {
+        self.configuration_space.subclass().read()
+    }
+
+    /// Reads an arbitrary register of the configuration space. Prefer the
+    /// typed accessors above for registers of the common header.
+    pub fn read_config<T: Copy>(&self, offset: usize) -> T {
+        self.config_register::<T>(offset).read()
+    }
+
+    #[allow(dead_code)]
+    pub fn write_config<T: Copy>(&mut self, offset: usize, value: T) {
+        self.config_register::<T>(offset).write(value);
+    }
+
+    fn config_register<T>(&self, offset: usize) -> MMIO<T> {
+        assert!(
+            offset + size_of::<T>() <= ecam::CONFIGURATION_SPACE_SIZE,
+            "Access outside of configuration space"
+        );
+        assert!(
+            offset % size_of::<T>() == 0,
+            "Configuration space access must be aligned"
+        );
+        // SAFETY: We checked that we stay inside the configuration space
+        unsafe { self.configuration_space.new_type_with_offset(offset) }
     }
 
     const CAPABILITIES_LIST_BIT: u16 = 1 << 4;
@@ -149,43 +242,56 @@ impl PCIDevice {
         }
     }
 
+    /// Sizes the bar and assigns it space from the allocator matching its type.
     pub fn get_or_initialize_bar(&mut self, index: u8) -> PCIAllocatedSpace {
         if let Some(allocated_space) = self.initialized_bars.get(&index) {
             return *allocated_space;
         }
 
-        let configuration_space = self.configuration_space_mut();
+        let configuration_space = &mut self.configuration_space;
 
+        // Decoding must be disabled while sizing
+        let command_register = configuration_space.command_register().read();
         configuration_space.clear_command_register_bits(
             command_register::IO_SPACE | command_register::MEMORY_SPACE,
         );
 
         let original_bar_value = configuration_space.bar(index);
-        assert!(original_bar_value & 0x1 == 0, "Bar must be memory mapped");
-        assert!(
-            (original_bar_value & 0b110) >> 1 == 0x2,
-            "Bar must be 64-bit wide"
-        );
+        let kind = BarKind::from_bar_value(original_bar_value).expect("Bar type must be valid");
 
         // Determine size of bar
         configuration_space.write_bar(index, 0xffffffff);
-        let bar_value = configuration_space.bar(index);
+        let sized_lower = configuration_space.bar(index);
+        let sized_upper = if kind == BarKind::Memory64Bit {
+            configuration_space.write_bar(index + 1, 0xffffffff);
+            configuration_space.bar(index + 1)
+        } else {
+            0
+        };
+        let size = bar::bar_size(kind, sized_lower, sized_upper);
 
-        // Mask out the 4 lower bits because they describe the type of the bar
-        // Invert the value and add 1 to get the size (because the bits that are not set are zero because of alignment)
-        let size = !(bar_value & !0b1111) + 1;
+        debug!("Bar {} ({:?}) size: {:#x}", index, kind, size);
 
-        debug!("Bar {} size: {:#x}", index, size);
+        let (allocator, decode_bit) = match kind {
+            BarKind::Io => (&PCI_ALLOCATOR_IO, command_register::IO_SPACE),
+            BarKind::Memory32Bit => (&PCI_ALLOCATOR_32_BIT, command_register::MEMORY_SPACE),
+            BarKind::Memory64Bit => (&PCI_ALLOCATOR_64_BIT, command_register::MEMORY_SPACE),
+        };
 
-        let space = pci::PCI_ALLOCATOR_64_BIT
+        let space = allocator
             .lock()
             .allocate(size as usize)
             .expect("There must be enough space for the bar");
 
         configuration_space.write_bar(index, space.pci_address as u32);
-        configuration_space.write_bar(index + 1, (space.pci_address >> 32) as u32);
+        if kind == BarKind::Memory64Bit {
+            configuration_space.write_bar(index + 1, (space.pci_address >> 32) as u32);
+        }
 
-        configuration_space.set_command_register_bits(command_register::MEMORY_SPACE);
+        configuration_space.set_command_register_bits(
+            (command_register & (command_register::IO_SPACE | command_register::MEMORY_SPACE))
+                | decode_bit,
+        );
 
         assert!(
             !self.initialized_bars.contains_key(&index),
@@ -198,7 +304,7 @@ impl PCIDevice {
 }
 
 pub struct PciDeviceAddresses {
-    pub network_devices: Vec<PCIDevice>,
+    pub network_devices: Vec<PciDevice>,
 }
 
 impl PciDeviceAddresses {
@@ -209,45 +315,207 @@ impl PciDeviceAddresses {
     }
 }
 
-pub fn enumerate_devices(pci_information: &PCIInformation) -> PciDeviceAddresses {
-    let mut pci_devices = PciDeviceAddresses::new();
-    for bus in 0..255 {
-        for device in 0..32 {
-            for function in 0..8 {
-                let address = pci_address(
-                    pci_information.pci_host_bridge_address,
-                    bus,
-                    device,
-                    function,
-                );
-                let maybe_device = unsafe { PCIDevice::try_new(address) };
-                if let Some(device) = maybe_device {
-                    let vendor_id = device.configuration_space.vendor_id().read();
-                    let device_id = device.configuration_space.device_id().read();
-                    let name = lookup(vendor_id, device_id).expect("PCI Device must be known.");
-                    info!(
-                        "PCI Device {:#x}:{:#x} found at {:#x} ({})",
-                        vendor_id, device_id, address, name
-                    );
-
-                    // Add virtio devices to device list
-                    if vendor_id == VIRTIO_VENDOR_ID
-                        && VIRTIO_DEVICE_ID.contains(&device_id)
-                        && device.configuration_space.subsystem_id().read()
-                            == VIRTIO_NETWORK_SUBSYSTEM_ID
-                    {
-                        pci_devices.network_devices.push(device);
+mmio_struct! {
+    #[repr(C)]
+    struct PciBridgeHeader {
+        vendor_id: u16,
+        device_id: u16,
+        command_register: u16,
+        status_register: u16,
+        revision_id: u8,
+        programming_interface_byte: u8,
+        subclass: u8,
+        class_code: u8,
+        cache_line_size: u8,
+        latency_timer: u8,
+        header_type: u8,
+        built_in_self_test: u8,
+        bars: [u32; 2],
+        primary_bus: u8,
+        secondary_bus: u8,
+        subordinate_bus: u8,
+        secondary_latency_timer: u8,
+        io_base: u8,
+        io_limit: u8,
+        secondary_status: u16,
+        memory_base: u16,
+        memory_limit: u16,
+        prefetchable_memory_base: u16,
+        prefetchable_memory_limit: u16,
+        prefetchable_base_upper: u32,
+        prefetchable_limit_upper: u32,
+        io_base_upper: u16,
+        io_limit_upper: u16,
+        capabilities_pointer: u8,
+    }
+}
+
+/// Walks the bus hierarchy depth-first and assigns bus numbers to bridges
+/// on the way. Firmware doesn't do that for us on QEMU.
+struct Enumeration<'a> {
+    ecam: Ecam,
+    pci_information: &'a PCIInformation,
+    next_bus: usize,
+    devices: PciDeviceAddresses,
+}
+
+impl Enumeration<'_> {
+    fn scan_bus(&mut self, bus: u8) {
+        for device in 0..ecam::MAX_DEVICES_PER_BUS {
+            for function in 0..ecam::MAX_FUNCTIONS_PER_DEVICE {
+                let address = PciAddress::new(bus, device, function);
+                let Some(configuration_space_address) =
+                    self.ecam.configuration_space_address(address)
+                else {
+                    return;
+                };
+                let pci_device = PciDevice::new(address, configuration_space_address);
+                if pci_device.vendor_id() == INVALID_VENDOR_ID {
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+
+                let header_type = pci_device.configuration_space.header_type().read();
+                match header_type & HEADER_TYPE_MASK {
+                    GENERAL_DEVICE_TYPE => self.add_device(pci_device),
+                    PCI_BRIDGE_TYPE => self.configure_bridge(address, configuration_space_address),
+                    _ => {
+                        warn!(
+                            "PCI {}: Ignoring unsupported header type {:#x}",
+                            address, header_type
+                        );
                     }
                 }
+
+                if function == 0 && header_type & MULTI_FUNCTION_BIT == 0 {
+                    break;
+                }
             }
         }
     }
-    pci_devices
+
+    fn add_device(&mut self, device: PciDevice) {
+        let vendor_id = device.vendor_id();
+        let device_id = device.device_id();
+        let name = lookup(vendor_id, device_id).unwrap_or_else(|| "unknown device".into());
+        info!(
+            "PCI Device {:#x}:{:#x} found at {} ({}; class {:#x}:{:#x}; interrupt pin {})",
+            vendor_id,
+            device_id,
+            device.address(),
+            name,
+            device.class_code(),
+            device.subclass(),
+            device.read_config::<u8>(INTERRUPT_PIN_OFFSET)
+        );
+
+        // Add virtio devices to device list
+        if vendor_id == VIRTIO_VENDOR_ID
+            && VIRTIO_DEVICE_ID.contains(&device_id)
+            && device.subsystem_id() == VIRTIO_NETWORK_SUBSYSTEM_ID
+        {
+            self.devices.network_devices.push(device);
+        }
+    }
+
+    fn configure_bridge(&mut self, address: PciAddress, configuration_space_address: usize) {
+        if self.next_bus >= self.ecam.number_of_buses() {
+            warn!("PCI {}: No bus number left for bridge", address);
+            return;
+        }
+        let secondary_bus = self.next_bus as u8;
+        self.next_bus += 1;
+
+        let bridge: MMIO<PciBridgeHeader> = MMIO::new(configuration_space_address);
+
+        bridge.command_register().write(0);
+        bridge.primary_bus().write(address.bus);
+        bridge.secondary_bus().write(secondary_bus);
+        // Route all bus numbers to the bridge until we know how many are behind it
+        bridge.subordinate_bus().write(0xff);
+
+        info!(
+            "PCI bridge found at {}; secondary bus {}",
+            address, secondary_bus
+        );
+
+        self.scan_bus(secondary_bus);
+
+        bridge.subordinate_bus().write((self.next_bus - 1) as u8);
+
+        // BARs are allocated lazily when a driver asks for them. Therefore, we
+        // cannot compute tight windows here and forward the whole windows.
+        // This is fine as long as the bridges don't overlap.
+        let mut command = command_register::BUS_MASTER;
+
+        if let Some(range) = self
+            .pci_information
+            .get_first_range_for_type(PCIBitField::MEMORY_SPACE_64_BIT_CODE)
+        {
+            let (base, limit) = window(range);
+            // Bits 31:20 of the address go into bits 15:4; 0x1 marks 64 bit support
+            bridge
+                .prefetchable_memory_base()
+                .write((((base >> 16) as u16) & 0xfff0) | 0x1);
+            bridge
+                .prefetchable_memory_limit()
+                .write((((limit >> 16) as u16) & 0xfff0) | 0x1);
+            bridge.prefetchable_base_upper().write((base >> 32) as u32);
+            bridge
+                .prefetchable_limit_upper()
+                .write((limit >> 32) as u32);
+            command |= command_register::MEMORY_SPACE;
+        }
+
+        if let Some(range) = self
+            .pci_information
+            .get_first_range_for_type(PCIBitField::MEMORY_SPACE_32_BIT_CODE)
+        {
+            let (base, limit) = window(range);
+            bridge.memory_base().write(((base >> 16) as u16) & 0xfff0);
+            bridge.memory_limit().write(((limit >> 16) as u16) & 0xfff0);
+            command |= command_register::MEMORY_SPACE;
+        } else {
+            // Disable the window (base > limit)
+            bridge.memory_base().write(0xfff0);
+            bridge.memory_limit().write(0x0);
+        }
+
+        if let Some(range) = self
+            .pci_information
+            .get_first_range_for_type(PCIBitField::IO_SPACE_CODE)
+        {
+            let (base, limit) = window(range);
+            // Bits 15:12 of the address go into bits 7:4; 0x1 marks 32 bit support
+            bridge.io_base().write((((base >> 8) as u8) & 0xf0) | 0x1);
+            bridge.io_limit().write((((limit >> 8) as u8) & 0xf0) | 0x1);
+            bridge.io_base_upper().write((base >> 16) as u16);
+            bridge.io_limit_upper().write((limit >> 16) as u16);
+            command |= command_register::IO_SPACE;
+        }
+
+        bridge.command_register().write(command);
+    }
 }
 
-fn pci_address(starting_address: usize, bus: u8, device: u8, function: u8) -> usize {
-    assert!(device < 32);
-    assert!(function < 8);
-    let offset = ((bus as usize) << 20) | ((device as usize) << 15) | ((function as usize) << 12);
-    starting_address + offset
+/// Returns the first and last pci address of the range
+fn window(range: &PCIRange) -> (u64, u64) {
+    let base = range.pci_address as u64;
+    (base, base + range.size as u64 - 1)
+}
+
+pub fn enumerate_devices(pci_information: &PCIInformation) -> PciDeviceAddresses {
+    let mut enumeration = Enumeration {
+        ecam: Ecam::new(
+            pci_information.pci_host_bridge_address,
+            pci_information.pci_host_bridge_length,
+        ),
+        pci_information,
+        next_bus: 1,
+        devices: PciDeviceAddresses::new(),
+    };
+    enumeration.scan_bus(0);
+    enumeration.devices
 }
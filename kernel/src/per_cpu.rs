@@ -0,0 +1,104 @@
+use core::cell::{Cell, UnsafeCell};
+
+use crate::cpu::Cpu;
+
+/// Upper bound of harts we support. Every per-cpu variable
+/// reserves one slot per possible hart.
+pub const MAX_CPUS: usize = 64;
+
+const SSTATUS_SIE: usize = 0b10;
+
+/// Declares a per-hart variable. Every hart sees its own instance
+/// which is selected via the cpu id stored in the cpu struct (sscratch).
+///
+/// ```ignore
+/// per_cpu!(static SCHEDULE_COUNT: u64 = 0);
+/// SCHEDULE_COUNT.with(|count| *count += 1);
+/// ```
+macro_rules! per_cpu {
+    ($vis:vis static $name:ident: $type:ty = $init:expr) => {
+        $vis static $name: $crate::per_cpu::PerCpu<$type> = $crate::per_cpu::PerCpu::from_slots(
+            [const { $crate::per_cpu::PerCpuSlot::new($init) }; $crate::per_cpu::MAX_CPUS],
+        );
+    };
+}
+
+pub(crate) use per_cpu;
+
+pub struct PerCpuSlot<T> {
+    borrowed: Cell<bool>,
+    data: UnsafeCell<T>,
+}
+
+impl<T> PerCpuSlot<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            borrowed: Cell::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
+pub struct PerCpu<T> {
+    slots: [PerCpuSlot<T>; MAX_CPUS],
+}
+
+// SAFETY: Every hart only accesses its own slot and the
+// borrowed flag prevents aliasing on the same hart.
+unsafe impl<T: Send> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+    #[doc(hidden)]
+    pub const fn from_slots(slots: [PerCpuSlot<T>; MAX_CPUS]) -> Self {
+        Self { slots }
+    }
+
+    /// Gives exclusive access to the instance of the current hart.
+    /// Interrupts are disabled during the access, such that interrupt handlers
+    /// can use per cpu variables as well. Panics on recursive access.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let sstatus = Cpu::read_sstatus();
+        Cpu::csrc_sstatus(SSTATUS_SIE);
+        let result = self.with_cpu(Cpu::cpu_id(), f);
+        Cpu::csrs_sstatus(sstatus & SSTATUS_SIE);
+        result
+    }
+
+    fn with_cpu<R>(&self, cpu_id: usize, f: impl FnOnce(&mut T) -> R) -> R {
+        assert!(cpu_id < MAX_CPUS, "cpu id {cpu_id} exceeds MAX_CPUS");
+        let slot = &self.slots[cpu_id];
+        assert!(
+            !slot.borrowed.replace(true),
+            "Per cpu variable is already borrowed on cpu {cpu_id}"
+        );
+        // SAFETY: Only the current hart accesses its slot and
+        // we checked above that there is no other reference.
+        let result = f(unsafe { &mut *slot.data.get() });
+        slot.borrowed.set(false);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::per_cpu;
+
+    per_cpu!(static COUNTER: u64 = 0);
+
+    #[test_case]
+    fn per_cpu_value_is_kept() {
+        COUNTER.with(|c| *c += 1);
+        COUNTER.with(|c| *c += 1);
+        assert_eq!(COUNTER.with(|c| *c), 2);
+    }
+
+    #[test_case]
+    fn cpus_are_independent() {
+        per_cpu!(static VALUE: usize = 42);
+        VALUE.with_cpu(1, |v| *v = 1);
+        VALUE.with_cpu(2, |v| *v = 2);
+        assert_eq!(VALUE.with_cpu(1, |v| *v), 1);
+        assert_eq!(VALUE.with_cpu(2, |v| *v), 2);
+        assert_eq!(VALUE.with_cpu(3, |v| *v), 42);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{
+    cmdline, info,
+    io::uart::CONSOLE_UART,
+    processes::{hotplug, process_table},
+    sbi::extensions::system_reset_extension::{self, ResetReason, ResetType},
+    test::qemu_exit,
+    warn,
+};
+
+pub fn shutdown() -> ! {
+    info!("Shutting down system");
+    teardown();
+    power_off();
+}
+
+/// Turns the machine off without tearing anything down, e.g. because
+/// no process is left
+pub fn power_off() -> ! {
+    CONSOLE_UART.lock().switch_to_synchronous_output();
+    let ret = system_reset_extension::system_reset(ResetType::Shutdown, ResetReason::NoReason);
+    warn!("SBI shutdown failed ({ret:?}); falling back to test device");
+    qemu_exit::exit_success();
+}
+
+pub fn reboot() -> ! {
+    info!("Rebooting system");
+    teardown();
+    let ret = system_reset_extension::system_reset(ResetType::ColdReboot, ResetReason::NoReason);
+    warn!("SBI reboot failed ({ret:?}); falling back to test device");
+    qemu_exit::exit_reset();
+}
+
+/// QEMU exit codes if the kernel runs with `exit_with_init`. Exit
+/// statuses of init in 1..=125 are passed through unchanged.
+pub mod init_exit_code {
+    /// Negative statuses or statuses which don't fit
+    pub const STATUS_OUT_OF_RANGE: u16 = 126;
+    /// Init was killed by the kernel, e.g. because it crashed
+    pub const KILLED: u16 = 127;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitExit {
+    Status(isize),
+    Killed,
+}
+
+/// Exits QEMU if the kernel runs with `exit_with_init`, otherwise
+/// nothing happens.
+pub fn init_exited(exit: InitExit) {
+    if !cmdline::get().exit_with_init {
+        return;
+    }
+    info!("Init exited ({exit:?}), exiting QEMU");
+    teardown();
+    match exit {
+        InitExit::Status(0) => qemu_exit::exit_success(),
+        InitExit::Status(status @ 1..=125) => qemu_exit::exit_failure(status as u16),
+        InitExit::Status(_) => qemu_exit::exit_failure(init_exit_code::STATUS_OUT_OF_RANGE),
+        InitExit::Killed => qemu_exit::exit_failure(init_exit_code::KILLED),
+    }
+}
+
+fn teardown() {
+    // Other harts must not run processes or touch devices anymore
+    hotplug::park_other_harts();
+    process_table::THE.update(|pt| {
+        let number_of_processes = pt.kill_all();
+        info!("Killed {number_of_processes} processes");
+    });
+    #[cfg(feature = "net")]
+    crate::net::shutdown();
+    // The system is gone before the transmitter empty interrupt fires
+    CONSOLE_UART.lock().switch_to_synchronous_output();
+}
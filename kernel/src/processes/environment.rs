@@ -0,0 +1,102 @@
+use alloc::{collections::BTreeMap, string::String};
+use common::errors::SysEnvironmentError;
+
+/// The environment is passed on the single stack page of a new process
+/// together with the arguments. Therefore its size is limited.
+pub const MAX_ENVIRONMENT_SIZE: usize = 1024;
+
+/// The environment variables of one process. Children inherit the
+/// environment of the process which started them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment(BTreeMap<String, String>);
+
+impl Environment {
+    pub const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SysEnvironmentError> {
+        if key.is_empty() || key.contains(['=', '\0']) {
+            return Err(SysEnvironmentError::InvalidKey);
+        }
+        if value.contains('\0') {
+            return Err(SysEnvironmentError::InvalidValue);
+        }
+        let replaced_size = self.get(key).map_or(0, |old| entry_size(key, old));
+        if self.size() - replaced_size + entry_size(key, value) > MAX_ENVIRONMENT_SIZE {
+            return Err(SysEnvironmentError::EnvironmentTooLarge);
+        }
+        self.0.insert(key.into(), value.into());
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Bytes needed to pass the environment as `KEY=VALUE` strings
+    pub fn size(&self) -> usize {
+        self.iter().map(|(key, value)| entry_size(key, value)).sum()
+    }
+}
+
+// KEY=VALUE plus the zero terminator
+fn entry_size(key: &str, value: &str) -> usize {
+    key.len() + 1 + value.len() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use common::errors::SysEnvironmentError;
+
+    use super::{Environment, MAX_ENVIRONMENT_SIZE};
+
+    #[test_case]
+    fn variables_can_be_set_and_unset() {
+        let mut environment = Environment::new();
+        assert!(environment.set("HOME", "/").is_ok());
+        assert!(environment.set("USER", "root").is_ok());
+        assert!(environment.set("HOME", "/root").is_ok());
+        assert_eq!(environment.get("HOME"), Some("/root"));
+        assert_eq!(environment.size(), "HOME=/root\0USER=root\0".len());
+
+        environment.unset("HOME");
+        assert_eq!(environment.get("HOME"), None);
+        assert_eq!(environment.iter().count(), 1);
+    }
+
+    #[test_case]
+    fn invalid_variables_are_rejected() {
+        let mut environment = Environment::new();
+        assert!(matches!(
+            environment.set("", "value"),
+            Err(SysEnvironmentError::InvalidKey)
+        ));
+        assert!(matches!(
+            environment.set("A=B", "value"),
+            Err(SysEnvironmentError::InvalidKey)
+        ));
+        assert!(matches!(
+            environment.set("KEY", "va\0lue"),
+            Err(SysEnvironmentError::InvalidValue)
+        ));
+
+        let value: String = core::iter::repeat_n('x', MAX_ENVIRONMENT_SIZE).collect();
+        assert!(matches!(
+            environment.set("KEY", &value),
+            Err(SysEnvironmentError::EnvironmentTooLarge)
+        ));
+        assert_eq!(environment, Environment::new());
+    }
+}
@@ -0,0 +1,121 @@
+//! Floating point registers are switched lazily. A process runs with
+//! them disabled (sstatus.FS off) until it uses them for the first time.
+//! The illegal instruction trap loads its registers and marks the hart
+//! as their owner. As long as no other process and not the kernel used
+//! them meanwhile, they stay enabled when the process runs again on the
+//! same hart.
+//!
+//! The trap entry only saves them if they are dirty and leaves them
+//! clean such that the kernel can tell if it used them itself.
+
+use common::syscalls::trap_frame::TrapFrame;
+
+use crate::{cpu::Cpu, per_cpu::per_cpu, processes::process::Pid};
+
+const SSTATUS_FS: usize = 0b11 << 13;
+const SSTATUS_FS_CLEAN: usize = 0b10 << 13;
+
+// The process whose registers are loaded on this hart
+per_cpu!(static OWNER: Option<Pid> = None);
+
+fn is_dirty() -> bool {
+    Cpu::read_sstatus() & SSTATUS_FS == SSTATUS_FS
+}
+
+fn set_state(state: usize) {
+    Cpu::csrc_sstatus(SSTATUS_FS);
+    Cpu::csrs_sstatus(state);
+}
+
+fn load(trap_frame: &TrapFrame) {
+    if cfg!(miri) {
+        return;
+    }
+    // SAFETY: All registers which are written are declared as outputs
+    unsafe {
+        core::arch::asm!(
+            "fld f0, 0({registers})",
+            "fld f1, 8({registers})",
+            "fld f2, 16({registers})",
+            "fld f3, 24({registers})",
+            "fld f4, 32({registers})",
+            "fld f5, 40({registers})",
+            "fld f6, 48({registers})",
+            "fld f7, 56({registers})",
+            "fld f8, 64({registers})",
+            "fld f9, 72({registers})",
+            "fld f10, 80({registers})",
+            "fld f11, 88({registers})",
+            "fld f12, 96({registers})",
+            "fld f13, 104({registers})",
+            "fld f14, 112({registers})",
+            "fld f15, 120({registers})",
+            "fld f16, 128({registers})",
+            "fld f17, 136({registers})",
+            "fld f18, 144({registers})",
+            "fld f19, 152({registers})",
+            "fld f20, 160({registers})",
+            "fld f21, 168({registers})",
+            "fld f22, 176({registers})",
+            "fld f23, 184({registers})",
+            "fld f24, 192({registers})",
+            "fld f25, 200({registers})",
+            "fld f26, 208({registers})",
+            "fld f27, 216({registers})",
+            "fld f28, 224({registers})",
+            "fld f29, 232({registers})",
+            "fld f30, 240({registers})",
+            "fld f31, 248({registers})",
+            "fscsr {csr}",
+            registers = in(reg) trap_frame.floating_registers().as_ptr(),
+            csr = in(reg) trap_frame.floating_point_csr(),
+            out("fs0") _, out("fs1") _, out("fs2") _, out("fs3") _, out("fs4") _, out("fs5") _, out("fs6") _, out("fs7") _, out("fs8") _, out("fs9") _, out("fs10") _, out("fs11") _,
+            clobber_abi("C"),
+        );
+    }
+}
+
+/// Loads the registers of the current process if it doesn't own them.
+/// Returns false if it does, the instruction was illegal then.
+pub fn handle_illegal_instruction() -> bool {
+    let owner = OWNER.with(|owner| *owner);
+    let hart = Cpu::cpu_id();
+    let loaded = Cpu::with_scheduler(|s| {
+        let pid = s.current_pid();
+        let mut process = s.get_current_process().lock();
+        if owner == Some(pid) && process.floating_point_hart() == Some(hart) {
+            return None;
+        }
+        load(s.trap_frame());
+        process.set_floating_point_hart(hart);
+        Some(pid)
+    });
+    let Some(pid) = loaded else {
+        return false;
+    };
+    OWNER.with(|owner| *owner = Some(pid));
+    set_state(SSTATUS_FS_CLEAN);
+    true
+}
+
+/// Called by every trap handler before it returns. The registers stay
+/// enabled if they belong to the process which runs next.
+#[no_mangle]
+extern "C" fn prepare_floating_point_return() {
+    if is_dirty() {
+        // The kernel used them
+        OWNER.with(|owner| *owner = None);
+    }
+    let owner = OWNER.with(|owner| *owner);
+    let hart = Cpu::cpu_id();
+    let owned = Cpu::with_scheduler(|s| {
+        owner == Some(s.current_pid())
+            && s.get_current_process().lock().floating_point_hart() == Some(hart)
+    });
+    set_state(if owned { SSTATUS_FS_CLEAN } else { 0 });
+}
+
+/// Every process has to load its registers before it uses them
+pub fn disable() {
+    set_state(0);
+}
@@ -0,0 +1,216 @@
+use core::{
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+};
+
+use alloc::vec::Vec;
+use common::errors::SysHartError;
+
+use crate::{
+    asm::wfi_loop,
+    cpu::{self, Cpu},
+    device_tree::tree::DeviceTreeNode,
+    info,
+    per_cpu::MAX_CPUS,
+    processes::{idle, timer},
+    sbi::extensions::{hart_state_extension, ipi_extension},
+    warn,
+};
+
+// Bit n is set if hart n takes part in scheduling
+static ONLINE_HARTS: AtomicU64 = AtomicU64::new(0);
+// Bit n is set if hart n should stop itself on the next software interrupt
+static STOP_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+// Set once the system goes down, every hart parks itself on the next
+// software interrupt
+static PARK_REQUESTED: AtomicBool = AtomicBool::new(false);
+// Bit n is set once hart n is parked
+static PARKED_HARTS: AtomicU64 = AtomicU64::new(0);
+
+// Harts which have interrupts disabled for longer are given up on
+const PARK_TIMEOUT_MS: u64 = 100;
+
+// We need the cpu struct again when a stopped hart is restarted
+static CPU_STRUCTS: [AtomicPtr<Cpu>; MAX_CPUS] = [const { AtomicPtr::new(null_mut()) }; MAX_CPUS];
+
+/// Harts which can run the kernel. Harts without an MMU, like the
+/// monitor core of the JH7110, are left out.
+pub fn usable_harts(root: &DeviceTreeNode) -> Vec<usize> {
+    root.find("/cpus")
+        .into_iter()
+        .flat_map(|cpus| cpus.children())
+        .filter(|node| {
+            let property = |name| node.property(name).and_then(|p| p.str());
+            property("device_type") == Some("cpu")
+                && node.is_enabled()
+                && property("mmu-type").is_some_and(|mmu| mmu != "riscv,none")
+        })
+        .filter_map(|cpu| cpu.first_reg())
+        .map(|reg| reg.address)
+        .filter(|hart_id| *hart_id < MAX_CPUS)
+        .collect()
+}
+
+pub fn register_cpu_struct(cpu_id: usize, cpu: *mut Cpu) {
+    CPU_STRUCTS[cpu_id].store(cpu, Ordering::SeqCst);
+}
+
+pub fn mark_online() {
+    ONLINE_HARTS.fetch_or(1 << Cpu::cpu_id(), Ordering::SeqCst);
+}
+
+pub fn online_harts() -> u64 {
+    ONLINE_HARTS.load(Ordering::SeqCst)
+}
+
+pub fn is_online(hart_id: usize) -> bool {
+    online_harts() & (1 << hart_id) != 0
+}
+
+/// Asks `hart_id` to stop. The hart puts its current process back into
+/// the process table, hands over its timers and stops itself via SBI HSM.
+pub fn stop_hart(hart_id: usize) -> Result<(), SysHartError> {
+    if hart_id >= MAX_CPUS || CPU_STRUCTS[hart_id].load(Ordering::SeqCst).is_null() {
+        return Err(SysHartError::InvalidHart);
+    }
+    if hart_id == *cpu::STARTING_CPU_ID {
+        return Err(SysHartError::CannotStopBootHart);
+    }
+    if !is_online(hart_id) {
+        return Err(SysHartError::AlreadyStopped);
+    }
+    if online_harts() == 1 << hart_id {
+        return Err(SysHartError::LastRunningHart);
+    }
+    info!("Requesting stop of hart {hart_id}");
+    STOP_REQUESTS.fetch_or(1 << hart_id, Ordering::SeqCst);
+    ipi_extension::send_ipi(1, hart_id as u64).assert_success();
+    Ok(())
+}
+
+pub fn start_hart(hart_id: usize) -> Result<(), SysHartError> {
+    if hart_id >= MAX_CPUS {
+        return Err(SysHartError::InvalidHart);
+    }
+    let cpu_struct = CPU_STRUCTS[hart_id].load(Ordering::SeqCst);
+    if cpu_struct.is_null() {
+        return Err(SysHartError::InvalidHart);
+    }
+    let status = hart_state_extension::get_hart_status(hart_id);
+    if status.is_error() || status.value != hart_state_extension::HART_STATE_STOPPED {
+        return Err(SysHartError::AlreadyRunning);
+    }
+
+    extern "C" {
+        fn start_hart();
+    }
+
+    info!("Restarting hart {hart_id}");
+    hart_state_extension::start_hart(hart_id, start_hart as usize, cpu_struct as usize)
+        .assert_success();
+    Ok(())
+}
+
+/// Called on every software interrupt. Doesn't return
+/// if a stop of the current hart was requested.
+pub fn handle_stop_request() {
+    let hart_bit = 1 << Cpu::cpu_id();
+    if STOP_REQUESTS.fetch_and(!hart_bit, Ordering::SeqCst) & hart_bit == 0 {
+        return;
+    }
+
+    ONLINE_HARTS.fetch_and(!hart_bit, Ordering::SeqCst);
+    idle::leave();
+
+    Cpu::with_scheduler(|s| s.park());
+
+    timer::clear_time_slice();
+    let other_hart = online_harts().trailing_zeros() as u64;
+    if timer::migrate_timers() > 0 {
+        ipi_extension::send_ipi(1, other_hart).assert_success();
+    }
+
+    // The process we just parked should run somewhere else
+    idle::notify_new_work();
+
+    info!("Stopping hart");
+    unsafe {
+        Cpu::disable_global_interrupts();
+    }
+    let ret = hart_state_extension::stop_hart();
+    panic!("Could not stop hart: {ret:?}");
+}
+
+/// Parks all other online harts before the system is torn down such
+/// that nothing runs concurrently to the teardown. Returns once all of
+/// them are parked or the timeout expired. If another hart is already
+/// tearing down the system the current hart parks itself instead.
+pub fn park_other_harts() {
+    if PARK_REQUESTED.swap(true, Ordering::SeqCst) {
+        park();
+    }
+
+    let own_hart = Cpu::cpu_id();
+    let other_harts = online_harts() & !(1 << own_hart);
+    for hart in (0..MAX_CPUS).filter(|hart| other_harts & (1 << hart) != 0) {
+        // The system goes down anyway
+        let _ = ipi_extension::send_ipi(1, hart as u64);
+    }
+
+    let deadline = timer::deadline_in(PARK_TIMEOUT_MS);
+    while PARKED_HARTS.load(Ordering::SeqCst) & other_harts != other_harts {
+        if timer::get_current_clocks() > deadline {
+            let unparked = other_harts & !PARKED_HARTS.load(Ordering::SeqCst);
+            warn!("Harts {unparked:#b} did not park (interrupts disabled?)");
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Called on every software interrupt. Doesn't return
+/// if the system is torn down.
+pub fn handle_park_request() {
+    if PARK_REQUESTED.load(Ordering::SeqCst) {
+        park();
+    }
+}
+
+fn park() -> ! {
+    unsafe {
+        Cpu::disable_global_interrupts();
+    }
+    PARKED_HARTS.fetch_or(1 << Cpu::cpu_id(), Ordering::SeqCst);
+    wfi_loop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{start_hart, stop_hart, usable_harts};
+    use crate::{
+        device_tree::{tree::DeviceTreeNode, DeviceTree, Header},
+        per_cpu::MAX_CPUS,
+    };
+    use common::{errors::SysHartError, include_bytes_align_as};
+
+    const DTB: &[u8] = include_bytes_align_as!(Header, "../test/test_data/dtb");
+
+    #[test_case]
+    fn harts_are_taken_from_the_device_tree() {
+        let tree = DeviceTreeNode::parse(DeviceTree::new(DTB.as_ptr() as *const ()).root_node());
+        assert_eq!(usable_harts(&tree), [0]);
+    }
+
+    #[test_case]
+    fn harts_beyond_the_supported_ones_are_refused() {
+        assert!(matches!(
+            stop_hart(MAX_CPUS),
+            Err(SysHartError::InvalidHart)
+        ));
+        assert!(matches!(
+            start_hart(MAX_CPUS),
+            Err(SysHartError::InvalidHart)
+        ));
+    }
+}
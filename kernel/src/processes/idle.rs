@@ -0,0 +1,102 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{cpu::Cpu, per_cpu::per_cpu, processes::timer, sbi};
+
+// Bit n is set if hart n runs the powersave process. Idle harts have no
+// periodic timer and must be woken up with an IPI if there is new work.
+static IDLE_HARTS: AtomicU64 = AtomicU64::new(0);
+
+struct IdleStatistics {
+    idle_since: Option<u64>,
+    idle_clocks: u64,
+}
+
+per_cpu!(static IDLE_STATISTICS: IdleStatistics = IdleStatistics {
+    idle_since: None,
+    idle_clocks: 0,
+});
+
+pub fn enter() {
+    let now = timer::get_current_clocks();
+    IDLE_STATISTICS.with(|stats| {
+        stats.idle_since.get_or_insert(now);
+    });
+    IDLE_HARTS.fetch_or(hart_bit(), Ordering::SeqCst);
+}
+
+pub fn leave() {
+    IDLE_HARTS.fetch_and(!hart_bit(), Ordering::SeqCst);
+    let now = timer::get_current_clocks();
+    IDLE_STATISTICS.with(|stats| {
+        if let Some(idle_since) = stats.idle_since.take() {
+            stats.idle_clocks += now - idle_since;
+        }
+    });
+}
+
+/// Total time in clocks the current hart spent idling.
+pub fn idle_clocks_on_current_cpu() -> u64 {
+    let now = timer::get_current_clocks();
+    IDLE_STATISTICS
+        .with(|stats| stats.idle_clocks + stats.idle_since.map_or(0, |since| now - since))
+}
+
+/// Must be called whenever a process becomes runnable. Wakes up
+/// one idle hart (might be the current one) to pick it up.
+pub fn notify_new_work() {
+    let idle_harts = IDLE_HARTS.load(Ordering::SeqCst);
+    if idle_harts == 0 {
+        return;
+    }
+    let hart = idle_harts.trailing_zeros() as u64;
+    sbi::extensions::ipi_extension::send_ipi(1, hart).assert_success();
+}
+
+/// Like [`notify_new_work`] for a process which is pinned to the hart
+pub fn notify_new_work_on(hart_id: usize) {
+    if IDLE_HARTS.load(Ordering::SeqCst) & (1 << hart_id) == 0 {
+        // The hart picks it up with its next schedule or is offline
+        notify_new_work();
+        return;
+    }
+    sbi::extensions::ipi_extension::send_ipi(1, hart_id as u64).assert_success();
+}
+
+fn hart_bit() -> u64 {
+    let cpu_id = Cpu::cpu_id();
+    assert!(cpu_id < 64, "Only 64 harts are supported for idle tracking");
+    1 << cpu_id
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::Ordering;
+
+    use crate::processes::timer;
+
+    use super::{enter, hart_bit, idle_clocks_on_current_cpu, leave, IDLE_HARTS};
+
+    fn wait_for_next_clock() {
+        let start = timer::get_current_clocks();
+        while timer::get_current_clocks() == start {}
+    }
+
+    #[test_case]
+    fn only_time_between_enter_and_leave_is_idle() {
+        let before = idle_clocks_on_current_cpu();
+        wait_for_next_clock();
+        assert_eq!(idle_clocks_on_current_cpu(), before);
+
+        enter();
+        assert_ne!(IDLE_HARTS.load(Ordering::SeqCst) & hart_bit(), 0);
+        wait_for_next_clock();
+        // Counted while the hart is still idle
+        assert!(idle_clocks_on_current_cpu() > before);
+        leave();
+        assert_eq!(IDLE_HARTS.load(Ordering::SeqCst) & hart_bit(), 0);
+
+        let after = idle_clocks_on_current_cpu();
+        wait_for_next_clock();
+        assert_eq!(idle_clocks_on_current_cpu(), after);
+    }
+}
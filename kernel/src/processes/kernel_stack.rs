@@ -0,0 +1,129 @@
+//! Kernel threads run on their own stack which ends at STACK_START in
+//! their page table. Userspace processes don't need one: traps run on
+//! the trap stack of the hart (see Cpu::init) and a blocking syscall
+//! returns instead of sleeping on a stack. Only the boot hart starts on
+//! the stack in the linker section.
+//!
+//! The page below a kernel thread stack is never mapped, therefore an
+//! overflow faults instead of overwriting other memory. The stacks of
+//! exited threads are kept for the next threads.
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use common::mutex::SpinLockIrqSave;
+
+use crate::{
+    memory::{page::PinnedHeapPages, PAGE_SIZE},
+    processes::{
+        loader::STACK_START,
+        process::{Pid, KERNEL_THREAD_PID_START},
+    },
+};
+
+pub const KERNEL_STACK_PAGES: usize = 16;
+
+const MAX_CACHED_STACKS: usize = 4;
+
+// Threads might exit in interrupt context
+static CACHE: SpinLockIrqSave<Vec<PinnedHeapPages>> = SpinLockIrqSave::new(Vec::new());
+
+pub struct KernelStack {
+    // Only None while it is returned to the cache
+    pages: Option<PinnedHeapPages>,
+}
+
+impl KernelStack {
+    pub fn allocate() -> Self {
+        let pages = CACHE
+            .lock()
+            .pop()
+            .unwrap_or_else(|| PinnedHeapPages::new(KERNEL_STACK_PAGES));
+        Self { pages: Some(pages) }
+    }
+
+    /// The lowest mapped address of every kernel thread stack
+    pub const fn bottom() -> usize {
+        STACK_START - KERNEL_STACK_PAGES * PAGE_SIZE + 1
+    }
+
+    pub fn pages(&mut self) -> &mut PinnedHeapPages {
+        self.pages.as_mut().expect("Stack is only taken on drop")
+    }
+
+    /// The address of the stack in the kernel mapping
+    pub fn start(&self) -> usize {
+        self.pages
+            .as_ref()
+            .map_or(0, |pages| pages.as_ptr() as usize)
+    }
+
+    pub fn number_of_pages(&self) -> usize {
+        self.pages.as_ref().map_or(0, |pages| pages.len())
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let mut cache = CACHE.lock();
+        if cache.len() < MAX_CACHED_STACKS {
+            cache.extend(self.pages.take());
+        }
+    }
+}
+
+fn guard_page() -> Range<usize> {
+    KernelStack::bottom() - PAGE_SIZE..KernelStack::bottom()
+}
+
+/// Whether a fault in kernel mode at the address is a stack overflow of
+/// the kernel thread
+pub fn is_overflow(pid: Pid, address: usize) -> bool {
+    pid >= KERNEL_THREAD_PID_START && guard_page().contains(&address)
+}
+
+/// Whether the stack starting at the address waits for the next thread
+pub fn is_cached(start: usize) -> bool {
+    CACHE
+        .lock()
+        .iter()
+        .any(|pages| pages.as_ptr() as usize == start)
+}
+
+/// Number of stacks which are ready for new kernel threads
+pub fn cached() -> usize {
+    CACHE.lock().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        memory::PAGE_SIZE,
+        processes::process::{INIT_PID, KERNEL_THREAD_PID_START},
+    };
+
+    use super::{cached, is_overflow, KernelStack, KERNEL_STACK_PAGES, MAX_CACHED_STACKS};
+
+    #[test_case]
+    fn stacks_are_recycled() {
+        let stack = KernelStack::allocate();
+        assert_eq!(stack.number_of_pages(), KERNEL_STACK_PAGES);
+        let after_drop = (cached() + 1).min(MAX_CACHED_STACKS);
+        drop(stack);
+        assert_eq!(cached(), after_drop);
+        let _stack = KernelStack::allocate();
+        assert_eq!(cached(), after_drop - 1);
+    }
+
+    #[test_case]
+    fn only_the_guard_page_of_kernel_threads_overflows() {
+        let below_stack = KernelStack::bottom() - 1;
+        assert!(is_overflow(KERNEL_THREAD_PID_START, below_stack));
+        assert!(!is_overflow(INIT_PID, below_stack));
+        assert!(!is_overflow(KERNEL_THREAD_PID_START, KernelStack::bottom()));
+        assert!(!is_overflow(
+            KERNEL_THREAD_PID_START,
+            KernelStack::bottom() - PAGE_SIZE - 1
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+//! Kernel threads are scheduled like processes but run in supervisor
+//! mode with the kernel mapping. They are preempted by the timer like any
+//! other process, therefore code which takes locks that are shared with
+//! interrupt handlers must run via [`Cpu::without_interrupts`].
+
+use common::wait::ExitStatus;
+
+use crate::{
+    cpu::Cpu,
+    processes::{
+        process::{Pid, Process},
+        process_table, timer,
+    },
+};
+
+pub fn spawn(name: &str, entry: extern "C" fn() -> !) -> Pid {
+    let thread = Process::create_kernel_thread(name, entry);
+    let pid = thread.get_pid();
+    // Kernel threads spawn threads as well and might be preempted
+    Cpu::without_interrupts(|| process_table::THE.update(|pt| pt.add_process(thread)));
+    pid
+}
+
+/// The thread runs in the periodic scheduling class, see
+/// [`super::periodic`]. The reservation must be admitted. A thread
+/// pinned to a hart asks for it with [`Process::pinned_hart`].
+pub fn spawn_periodic(
+    name: &str,
+    entry: extern "C" fn() -> !,
+    period_milliseconds: u64,
+    budget_milliseconds: u64,
+    hart_id: Option<usize>,
+) -> Pid {
+    let mut thread = Process::create_kernel_thread(name, entry);
+    thread
+        .set_reservation(period_milliseconds, budget_milliseconds)
+        .expect("Reservation of kernel thread must be admitted");
+    if let Some(hart_id) = hart_id {
+        thread.pin_to_hart(hart_id);
+    }
+    let pid = thread.get_pid();
+    Cpu::without_interrupts(|| process_table::THE.update(|pt| pt.add_process(thread)));
+    pid
+}
+
+/// Blocks the current periodic kernel thread until its next period starts
+pub fn wait_period() {
+    Cpu::without_interrupts(|| {
+        let (pid, deadline) = Cpu::with_current_process(|mut p| {
+            let deadline = p
+                .reservation_mut()
+                .expect("Only periodic kernel threads have periods")
+                .deadline();
+            (p.get_pid(), deadline)
+        });
+        timer::add_timer(deadline, move || unpark(pid));
+    });
+    park();
+}
+
+/// Blocks the current kernel thread until [`unpark`] is called. Returns
+/// immediately if it was unparked since the last park.
+pub fn park() {
+    Cpu::without_interrupts(|| {
+        Cpu::with_current_process(|mut p| {
+            assert!(p.is_kernel_thread(), "Only kernel threads can be parked");
+            p.request_park();
+        });
+        // The scheduler must save our state, which is only possible in a trap
+        Cpu::raise_software_interrupt();
+    });
+}
+
+/// Removes the current kernel thread. Its memory is freed as soon as
+/// it is descheduled.
+#[allow(dead_code)]
+pub fn exit() -> ! {
+    Cpu::without_interrupts(|| {
+        let pid = Cpu::with_current_process(|p| p.get_pid());
+        process_table::THE.update(|pt| pt.kill(pid, ExitStatus::Exited(0)));
+    });
+    loop {
+        park();
+    }
+}
+
+pub fn unpark(pid: Pid) {
+    process_table::THE.read().unpark(pid);
+}
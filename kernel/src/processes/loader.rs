@@ -8,9 +8,12 @@ use crate::{
     },
     memory::{
         page::{Pages, PinnedHeapPages},
-        page_tables::RootPageTableHolder,
+        page_cache::{self, CachedContent, PageCacheKey, SharedPages},
+        page_tables::{RootPageTableHolder, XWRMode},
+        vma::{Source, Vma},
         PAGE_SIZE,
     },
+    processes::environment::Environment,
 };
 
 pub const STACK_START: usize = usize::MAX;
@@ -20,14 +23,30 @@ pub const STACK_END: usize = STACK_START - PAGE_SIZE + 1;
 pub struct LoadedElf {
     pub entry_address: usize,
     pub page_tables: RootPageTableHolder,
-    pub allocated_pages: Vec<PinnedHeapPages>,
+    // Grows on page faults, see Process::grow_stack
+    pub stack: PinnedHeapPages,
+    // Read-only segments are shared with other instances of the program
+    pub cached_pages: Vec<SharedPages>,
+    // Writable segments share their pages until they are written
+    pub vmas: Vec<Vma>,
+    // Page aligned end of the highest segment
+    pub heap_start: usize,
     pub args_start: usize,
 }
 
-fn set_up_arguments(stack: &mut [u8], name: &str, args: &[&str]) -> Result<usize, LoaderError> {
+/// The arguments are followed by the environment. Both are lists of
+/// zero terminated strings which end with an empty string.
+fn set_up_arguments(
+    stack: &mut [u8],
+    name: &str,
+    args: &[&str],
+    environment: &Environment,
+) -> Result<usize, LoaderError> {
     let mut total_bytes = name.len() + args.iter().map(|arg| arg.len()).sum::<usize>();
     // add zero bytes into account (name, number of args, zero-byte terminator)
     total_bytes += 1 + args.len() + 1;
+    // The size already contains the zero bytes of the variables
+    total_bytes += environment.size() + 1;
 
     let stack_size = stack.len();
 
@@ -44,6 +63,17 @@ fn set_up_arguments(stack: &mut [u8], name: &str, args: &[&str]) -> Result<usize
         copy_slice(arg.as_bytes(), &mut stack[offset..]);
         offset += arg.len() + 1;
     }
+    // Skip the terminator of the arguments
+    offset += 1;
+
+    for (key, value) in environment.iter() {
+        copy_slice(key.as_bytes(), &mut stack[offset..]);
+        offset += key.len();
+        stack[offset] = b'=';
+        offset += 1;
+        copy_slice(value.as_bytes(), &mut stack[offset..]);
+        offset += value.len() + 1;
+    }
 
     assert_eq!(
         stack[offset..].len(),
@@ -55,19 +85,25 @@ fn set_up_arguments(stack: &mut [u8], name: &str, args: &[&str]) -> Result<usize
     Ok(STACK_START - total_bytes + 1)
 }
 
-pub fn load_elf(elf_file: &ElfFile, name: &str, args: &[&str]) -> Result<LoadedElf, LoaderError> {
+pub fn load_elf(
+    elf_file: &ElfFile,
+    name: &str,
+    args: &[&str],
+    environment: &Environment,
+) -> Result<LoadedElf, LoaderError> {
     let mut page_tables = RootPageTableHolder::new_with_kernel_mapping();
 
     let elf_header = elf_file.get_header();
-    let mut allocated_pages = Vec::new();
+    let mut cached_pages = Vec::new();
+    let mut vmas = Vec::new();
+    let mut heap_start = 0;
 
     // Map 4KB stack
     let mut stack = PinnedHeapPages::new(1);
 
-    let args_start = set_up_arguments(stack.as_u8_slice(), name, args)?;
+    let args_start = set_up_arguments(stack.as_u8_slice(), name, args, environment)?;
 
     let stack_addr = stack.addr();
-    allocated_pages.push(stack);
 
     page_tables.map_userspace(
         STACK_END,
@@ -87,27 +123,51 @@ pub fn load_elf(elf_file: &ElfFile, name: &str, args: &[&str]) -> Result<LoadedE
         let data = elf_file.get_program_header_data(program_header);
         let real_size = program_header.memory_size;
         let size_in_pages = minimum_amount_of_pages(real_size as usize);
-
-        let mut pages = PinnedHeapPages::new(size_in_pages);
-        pages.fill(data);
-
-        let pages_addr = pages.addr();
-
-        allocated_pages.push(pages);
+        let privileges: XWRMode = program_header.access_flags.into();
+
+        let create_pages = || {
+            let mut pages = PinnedHeapPages::new(size_in_pages);
+            pages.fill(data);
+            pages
+        };
+
+        let key = PageCacheKey {
+            content: CachedContent::ProgramSegment,
+            file: name.to_string(),
+            offset: program_header.offset_in_file as usize,
+        };
+        let pages = page_cache::get_or_insert_with(key, create_pages);
+        let virtual_address = program_header.virtual_address as usize;
+        heap_start = heap_start.max(virtual_address + size_in_pages * PAGE_SIZE);
+
+        if matches!(privileges, XWRMode::ReadWrite | XWRMode::ReadWriteExecute) {
+            // Mapped by the page fault handler
+            vmas.push(Vma::new(
+                virtual_address,
+                size_in_pages * PAGE_SIZE,
+                privileges,
+                Source::Segment(pages),
+            ));
+            continue;
+        }
 
         page_tables.map_userspace(
-            program_header.virtual_address as usize,
-            pages_addr.get(),
+            virtual_address,
+            pages.as_ptr() as usize,
             size_in_pages * PAGE_SIZE,
-            program_header.access_flags.into(),
+            privileges,
             "LOAD".to_string(),
         );
+        cached_pages.push(pages);
     }
 
     Ok(LoadedElf {
         entry_address: elf_header.entry_point as usize,
         page_tables,
-        allocated_pages,
+        stack,
+        cached_pages,
+        vmas,
+        heap_start,
         args_start,
     })
 }
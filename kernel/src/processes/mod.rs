@@ -1,5 +1,20 @@
+pub mod environment;
+pub mod floating_point;
+pub mod hotplug;
+pub mod idle;
+pub mod kernel_stack;
+pub mod kthread;
 mod loader;
+pub mod oom;
+pub mod periodic;
+pub mod poll;
 pub mod process;
 pub mod process_table;
+pub mod programs;
+pub mod resource_limits;
 pub mod scheduler;
 pub mod timer;
+pub mod vdso;
+pub mod vector;
+pub mod wait_queue;
+pub mod workqueue;
@@ -0,0 +1,137 @@
+//! When the page allocator is exhausted a userspace process is killed
+//! instead of failing the kernel allocation. The victim is the process
+//! which maps the most pages. Init is never chosen.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+use common::wait::ExitStatus;
+
+use crate::{
+    cpu::Cpu,
+    memory,
+    processes::{
+        process::{Pid, ProcessState, INIT_PID},
+        process_table,
+    },
+    warn,
+};
+
+const NO_HART: usize = usize::MAX;
+
+// The hart which is currently selecting a victim
+static HANDLING_HART: AtomicUsize = AtomicUsize::new(NO_HART);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Candidate<'a> {
+    pid: Pid,
+    name: &'a str,
+    state: ProcessState,
+    mapped_pages: usize,
+}
+
+/// Returns true if memory was freed and the allocation should be retried
+pub fn handle_out_of_memory() -> bool {
+    if !process_table::THE.initialized().load(Ordering::SeqCst) {
+        return false;
+    }
+    let hart = Cpu::cpu_id();
+    match HANDLING_HART.compare_exchange(NO_HART, hart, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => {}
+        // An allocation within the handler itself failed
+        Err(handling_hart) if handling_hart == hart => return false,
+        // Another hart frees memory right now. Try again afterwards.
+        Err(_) => {
+            while HANDLING_HART.load(Ordering::SeqCst) != NO_HART {
+                core::hint::spin_loop();
+            }
+            return true;
+        }
+    }
+    let freed = kill_victim();
+    HANDLING_HART.store(NO_HART, Ordering::SeqCst);
+    freed
+}
+
+fn kill_victim() -> bool {
+    // We could be called within an update of the process table
+    if process_table::THE.is_being_updated() {
+        warn!("Out of memory while the process table is updated");
+        return false;
+    }
+
+    let victim = {
+        let process_table = process_table::THE.read();
+        let processes: Vec<_> = process_table
+            .processes()
+            // The allocation might happen while this hart holds the lock
+            .filter(|process| !process.get_locked().load(Ordering::SeqCst))
+            .map(|process| process.lock())
+            .collect();
+        let candidates: Vec<_> = processes
+            .iter()
+            // Kernel threads are needed by the system
+            .filter(|process| !process.is_kernel_thread())
+            .map(|process| Candidate {
+                pid: process.get_pid(),
+                name: process.get_name(),
+                state: process.get_state(),
+                mapped_pages: process.mapped_pages(),
+            })
+            .collect();
+        select_victim(&candidates).map(|victim| (victim.pid, victim.mapped_pages))
+    };
+
+    let Some((pid, mapped_pages)) = victim else {
+        warn!("Out of memory and there is no process which can be killed");
+        return false;
+    };
+
+    let used_pages = memory::used_heap_pages();
+    warn!("Out of memory: Kill PID={pid} which maps {mapped_pages} pages");
+    process_table::THE.update(|pt| pt.kill(pid, ExitStatus::Killed));
+    let freed_pages = used_pages.saturating_sub(memory::used_heap_pages());
+    warn!("Out of memory: Reclaimed {freed_pages} pages of PID={pid}");
+    freed_pages > 0
+}
+
+/// Running processes are skipped because their page tables are active
+/// on some hart and their memory is only freed after they are descheduled.
+fn select_victim<'a>(candidates: &'a [Candidate<'a>]) -> Option<&'a Candidate<'a>> {
+    candidates
+        .iter()
+        .filter(|candidate| candidate.pid != INIT_PID && candidate.state != ProcessState::Running)
+        .max_by_key(|candidate| candidate.mapped_pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select_victim, Candidate};
+    use crate::processes::process::ProcessState;
+
+    fn candidate(pid: u64, name: &str, state: ProcessState, mapped_pages: usize) -> Candidate {
+        Candidate {
+            pid,
+            name,
+            state,
+            mapped_pages,
+        }
+    }
+
+    #[test_case]
+    fn largest_process_is_selected() {
+        let candidates = [
+            candidate(1, "init", ProcessState::Waiting, 1000),
+            candidate(2, "sesh", ProcessState::Waiting, 10),
+            candidate(3, "stress", ProcessState::Runnable, 100),
+            candidate(4, "loop", ProcessState::Running, 500),
+        ];
+        assert_eq!(select_victim(&candidates).map(|c| c.pid), Some(3));
+    }
+
+    #[test_case]
+    fn init_is_never_selected() {
+        let candidates = [candidate(1, "init", ProcessState::Waiting, 1000)];
+        assert_eq!(select_victim(&candidates), None);
+    }
+}
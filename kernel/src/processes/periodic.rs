@@ -0,0 +1,154 @@
+//! Scheduling class for drivers and other work with timing requirements.
+//! A periodic process reserves a budget of CPU time in every period. As
+//! long as budget is left it is picked before the round robin processes,
+//! the one with the earliest deadline (end of its period) first. A
+//! process which used up its budget is throttled until its next period.
+
+use common::{errors::SysPeriodicError, mutex::Mutex};
+
+use crate::processes::timer;
+
+// All reservations together may take half of one hart. Normal processes
+// therefore never starve, even if only one hart is online.
+const MAX_UTILIZATION_PERMILLE: u64 = 500;
+
+static ADMITTED_PERMILLE: Mutex<u64> = Mutex::new(0);
+
+/// The admitted share is given back on drop
+#[derive(Debug)]
+pub struct Reservation {
+    // In clocks like the rest
+    period: u64,
+    budget: u64,
+    // Start of the current period
+    release: u64,
+    // Used in the current period
+    used: u64,
+    utilization_permille: u64,
+}
+
+impl Reservation {
+    /// Admission control: The reservation must fit beside all admitted
+    /// ones. The share of `replaced` is available because it is dropped
+    /// afterwards.
+    pub fn admit(
+        period_milliseconds: u64,
+        budget_milliseconds: u64,
+        replaced: Option<&Reservation>,
+    ) -> Result<Self, SysPeriodicError> {
+        if budget_milliseconds == 0 || budget_milliseconds > period_milliseconds {
+            return Err(SysPeriodicError::InvalidReservation);
+        }
+        let utilization_permille = budget_milliseconds
+            .checked_mul(1000)
+            .ok_or(SysPeriodicError::InvalidReservation)?
+            / period_milliseconds;
+        let replaced_permille = replaced.map_or(0, |r| r.utilization_permille);
+        ADMITTED_PERMILLE.with_lock(|mut admitted| {
+            if *admitted - replaced_permille + utilization_permille > MAX_UTILIZATION_PERMILLE {
+                return Err(SysPeriodicError::Overloaded);
+            }
+            *admitted += utilization_permille;
+            Ok(())
+        })?;
+        Ok(Self {
+            period: timer::milliseconds_to_clocks(period_milliseconds),
+            budget: timer::milliseconds_to_clocks(budget_milliseconds),
+            release: timer::get_current_clocks(),
+            used: 0,
+            utilization_permille,
+        })
+    }
+
+    /// End of the current period
+    pub fn deadline(&self) -> u64 {
+        self.release + self.period
+    }
+
+    /// Starts a new period if the current one is over. Periods which
+    /// passed without the process are skipped.
+    fn update(&mut self, now: u64) {
+        if now < self.deadline() {
+            return;
+        }
+        self.release += (now - self.release) / self.period * self.period;
+        self.used = 0;
+    }
+
+    pub fn is_throttled(&mut self, now: u64) -> bool {
+        self.update(now);
+        self.used >= self.budget
+    }
+
+    /// Budget left in the current period
+    pub fn remaining(&mut self, now: u64) -> u64 {
+        self.update(now);
+        self.budget.saturating_sub(self.used)
+    }
+
+    /// Charges time the process ran, which ended at `now`
+    pub fn account(&mut self, clocks: u64, now: u64) {
+        self.update(now);
+        self.used += clocks;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        *ADMITTED_PERMILLE.lock() -= self.utilization_permille;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::errors::SysPeriodicError;
+
+    use super::{Reservation, ADMITTED_PERMILLE, MAX_UTILIZATION_PERMILLE};
+    use crate::processes::timer;
+
+    #[test_case]
+    fn reservations_are_admitted_up_to_the_limit() {
+        assert!(matches!(
+            Reservation::admit(10, 11, None),
+            Err(SysPeriodicError::InvalidReservation)
+        ));
+        assert!(matches!(
+            Reservation::admit(10, 0, None),
+            Err(SysPeriodicError::InvalidReservation)
+        ));
+
+        // Kernel threads might hold reservations already
+        let available = MAX_UTILIZATION_PERMILLE - *ADMITTED_PERMILLE.lock();
+        let all = Reservation::admit(1000, available, None).unwrap();
+        assert!(matches!(
+            Reservation::admit(1000, 1, None),
+            Err(SysPeriodicError::Overloaded)
+        ));
+        let replacement = Reservation::admit(1000, available, Some(&all)).unwrap();
+        drop(all);
+        assert!(Reservation::admit(1000, 1, None).is_err());
+        drop(replacement);
+        assert!(Reservation::admit(1000, 1, None).is_ok());
+    }
+
+    #[test_case]
+    fn budget_is_renewed_every_period() {
+        let mut reservation = Reservation::admit(10, 2, None).unwrap();
+        let release = reservation.release;
+        let period = timer::milliseconds_to_clocks(10);
+        let budget = timer::milliseconds_to_clocks(2);
+        assert_eq!(reservation.deadline(), release + period);
+
+        reservation.account(budget / 2, release + 1);
+        assert_eq!(reservation.remaining(release + 2), budget / 2);
+        assert!(!reservation.is_throttled(release + 2));
+        reservation.account(budget, release + 3);
+        assert!(reservation.is_throttled(release + 3));
+        assert_eq!(reservation.remaining(release + 3), 0);
+
+        // Two periods passed, the third one started
+        assert!(!reservation.is_throttled(release + 3 * period - 1));
+        assert_eq!(reservation.deadline(), release + 3 * period);
+        assert_eq!(reservation.remaining(release + 3 * period - 1), budget);
+    }
+}
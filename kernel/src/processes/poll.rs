@@ -0,0 +1,42 @@
+//! Processes which wait in `sys_poll` are woken up whenever any pollable
+//! source receives data. They check their sources again afterwards,
+//! therefore spurious wakeups are fine.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use common::{errors::SysPollError, mutex::Mutex};
+
+use crate::processes::{process_table::ProcessRef, wait_queue::WaitQueue};
+
+pub type PollResult = Result<usize, SysPollError>;
+
+// Counts all events such that a process notices events which happened
+// while it was checking its sources
+static EVENTS: AtomicU64 = AtomicU64::new(0);
+
+static WAITING: Mutex<WaitQueue<PollResult>> = Mutex::new(WaitQueue::new());
+
+/// Must be read before the sources are checked
+pub fn events() -> u64 {
+    EVENTS.load(Ordering::SeqCst)
+}
+
+/// Lets the process wait for the next event or the deadline. Returns
+/// false if an event already happened after `seen_events` and the
+/// process keeps running.
+pub fn wait(process: &ProcessRef, seen_events: u64, deadline: u64) -> bool {
+    let mut waiting = WAITING.lock();
+    waiting.wait_until(process, deadline, Ok(0));
+    if events() != seen_events {
+        let pid = process.lock().get_pid();
+        waiting.wake(pid, Ok(0));
+        return false;
+    }
+    true
+}
+
+/// Called by the sources whenever new data can be read
+pub fn notify() {
+    EVENTS.fetch_add(1, Ordering::SeqCst);
+    WAITING.lock().wake_all(Ok(0));
+}
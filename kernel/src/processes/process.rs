@@ -1,34 +1,106 @@
 use crate::{
+    cpu::Cpu,
     debug,
+    fs::vfs::SharedOpenFile,
+    interrupts::userspace::UserspaceInterrupt,
+    io::pty::{PtyMaster, PtySlave},
     klibc::elf::ElfFile,
-    memory::{page::PinnedHeapPages, page_tables::RootPageTableHolder, PAGE_SIZE},
-    net::sockets::SharedAssignedSocket,
-    processes::loader::{self, LoadedElf, STACK_END, STACK_START},
+    memory::{
+        leak_check::{self, Inventory},
+        page::PinnedHeapPages,
+        page_cache::SharedPages,
+        page_tables::{RootPageTableHolder, XWRMode},
+        vma::{LoadedPage, MappedFile, Source, Vma},
+        PAGE_SIZE,
+    },
+    processes::{
+        environment::Environment,
+        hotplug, idle,
+        kernel_stack::{self, KernelStack, KERNEL_STACK_PAGES},
+        loader::{self, LoadedElf, STACK_START},
+        periodic::Reservation,
+        resource_limits::ResourceLimits,
+        timer,
+        vdso::VdsoPage,
+        vector::VectorState,
+        wait_queue::Interruptible,
+    },
 };
 use alloc::{
-    collections::{BTreeMap, BTreeSet},
+    collections::BTreeMap,
+    format,
     string::{String, ToString},
     sync::Arc,
+    vec,
     vec::Vec,
 };
 use common::{
-    errors::LoaderError,
+    accounting::IoStatistics,
+    credentials::Credentials,
+    errors::{LoaderError, SysBrkError, SysPeriodicError},
+    fs::FileDescriptor,
     mutex::Mutex,
-    net::UDPDescriptor,
+    perf::PerfValues,
+    resource_limits::Resource,
     syscalls::trap_frame::{Register, TrapFrame},
-    util::align_down,
+    tty::PtyDescriptor,
+    util::{align_down, align_up},
 };
 use core::{
     any::TypeId,
     fmt::Debug,
+    ops::Range,
     sync::atomic::{AtomicU64, Ordering},
 };
 
+#[cfg(feature = "net")]
+use crate::net::{
+    capture::SharedRawSocket,
+    sockets::SharedAssignedSocket,
+    tcp::{SharedTcpListener, SharedTcpStream},
+};
+#[cfg(feature = "net")]
+use common::net::{RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor};
+
 pub type Pid = u64;
 
 pub const POWERSAVE_PID: Pid = 0;
+// Init is the first process which is started
+pub const INIT_PID: Pid = 1;
+
+// Kernel threads have their own pid range such that they
+// don't shift the pids of userspace processes
+pub const KERNEL_THREAD_PID_START: Pid = 1 << 32;
 
 const FREE_MMAP_START_ADDRESS: usize = 0x2000000000;
+const ROOT_DIRECTORY: &str = "/";
+
+/// What a started program takes over from the process which starts it
+pub struct Inheritance {
+    // The slave side of a pty replaces the console if set
+    pub terminal: Option<PtySlave>,
+    pub resource_limits: ResourceLimits,
+    pub credentials: Credentials,
+    pub environment: Environment,
+    pub working_directory: String,
+    // Numbers in the started program, none are inherited by default
+    pub descriptors: Vec<(u64, SharedDescriptor)>,
+}
+
+/// Any kind of open descriptor, e.g. to hand it to a started program
+#[derive(Clone)]
+pub enum SharedDescriptor {
+    File(SharedOpenFile),
+    Pty(PtyMaster),
+    #[cfg(feature = "net")]
+    UdpSocket(SharedAssignedSocket),
+    #[cfg(feature = "net")]
+    RawSocket(SharedRawSocket),
+    #[cfg(feature = "net")]
+    TcpListener(SharedTcpListener),
+    #[cfg(feature = "net")]
+    TcpStream(SharedTcpStream),
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessState {
@@ -38,14 +110,19 @@ pub enum ProcessState {
 }
 
 fn get_next_pid() -> Pid {
-    // PIDs will start from 1
     // 0 is reserved for the never process which will be never scheduled
-    static PID_COUNTER: AtomicU64 = AtomicU64::new(1);
+    // and init always gets 1, even if it is restarted
+    static PID_COUNTER: AtomicU64 = AtomicU64::new(INIT_PID + 1);
     let next_pid = PID_COUNTER.fetch_add(1, Ordering::Relaxed);
     assert_ne!(next_pid, u64::MAX, "We ran out of process pids");
     next_pid
 }
 
+fn get_next_kernel_thread_pid() -> Pid {
+    static PID_COUNTER: AtomicU64 = AtomicU64::new(KERNEL_THREAD_PID_START);
+    PID_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
 pub struct Process {
     name: String,
     pid: Pid,
@@ -53,13 +130,75 @@ pub struct Process {
     page_table: RootPageTableHolder,
     program_counter: usize,
     allocated_pages: Vec<PinnedHeapPages>,
+    // The main stack of userspace processes. The loaded pages end at
+    // STACK_START, the ones it grows by are mapped below them.
+    stack: Vec<PinnedHeapPages>,
+    // The stack of kernel threads, it ends at STACK_START as well
+    kernel_stack: Option<KernelStack>,
+    cached_pages: Vec<SharedPages>,
+    // Their pages are mapped on the first access
+    vmas: Vec<Vma>,
+    // The heap is the anonymous area which starts here, kernel threads
+    // have none
+    heap_start: Option<usize>,
+    program_break: usize,
     state: ProcessState,
+    // Not scheduled until it is resumed, independent of the state such
+    // that a waiting process can be stopped as well
+    stopped: bool,
+    // Set once it is removed from the process table. Harts with an old
+    // snapshot of the table might still see it.
+    removed: bool,
     free_mmap_address: usize,
     next_free_descriptor: u64,
+    #[cfg(feature = "net")]
     open_udp_sockets: BTreeMap<UDPDescriptor, SharedAssignedSocket>,
+    #[cfg(feature = "net")]
+    open_raw_sockets: BTreeMap<RawDescriptor, SharedRawSocket>,
+    #[cfg(feature = "net")]
+    open_tcp_listeners: BTreeMap<TcpListenerDescriptor, SharedTcpListener>,
+    #[cfg(feature = "net")]
+    open_tcp_streams: BTreeMap<TcpStreamDescriptor, SharedTcpStream>,
+    open_ptys: BTreeMap<PtyDescriptor, PtyMaster>,
+    open_files: BTreeMap<FileDescriptor, SharedOpenFile>,
+    // The slave side of a pty replaces the console if set
+    terminal: Option<PtySlave>,
+    // Released when the process is dropped
+    interrupts: Vec<UserspaceInterrupt>,
     in_kernel_mode: bool,
-    notify_on_die: BTreeSet<Pid>,
+    kernel_thread: bool,
+    // See kthread::park
+    park_requested: bool,
+    unparked: bool,
     waiting_on_syscall: Option<TypeId>,
+    // Resumes the blocking syscall with its interrupted value
+    wait_interrupter: Option<fn(&mut Process)>,
+    // Counts the blocking syscalls such that a timeout only ends the one
+    // it was set for
+    waits: u64,
+    resource_limits: ResourceLimits,
+    credentials: Credentials,
+    environment: Environment,
+    // Absolute and normalized
+    working_directory: String,
+    // Not present for the powersave process
+    vdso: Option<VdsoPage>,
+    // Clocks spent running, without the currently running stretch
+    cpu_clocks: u64,
+    running_since: Option<u64>,
+    // Hardware counters while running, like cpu_clocks
+    perf_values: PerfValues,
+    perf_values_since: Option<PerfValues>,
+    // Set for the periodic scheduling class
+    reservation: Option<Reservation>,
+    // Only scheduled on this hart while it is online
+    pinned_hart: Option<usize>,
+    io_statistics: IoStatistics,
+    // The hart whose floating point registers hold our state, see
+    // floating_point
+    floating_point_hart: Option<usize>,
+    // Allocated when the process uses vector registers for the first time
+    vector_state: Option<VectorState>,
 }
 
 impl Debug for Process {
@@ -92,19 +231,28 @@ impl Process {
             fn powersave();
         }
 
-        let mut allocated_pages = Vec::with_capacity(1);
+        Arc::new(Mutex::new(Self::new_kernel_thread(
+            "powersave",
+            POWERSAVE_PID,
+            powersave as usize,
+        )))
+    }
 
-        // Map 4KB stack
-        let mut stack = PinnedHeapPages::new(1);
-        let stack_addr = stack.addr();
-        allocated_pages.push(stack);
+    /// The thread runs `entry` in supervisor mode with the kernel mapping
+    pub fn create_kernel_thread(name: &str, entry: extern "C" fn() -> !) -> Self {
+        Self::new_kernel_thread(name, get_next_kernel_thread_pid(), entry as usize)
+    }
+
+    fn new_kernel_thread(name: &str, pid: Pid, entry: usize) -> Self {
+        let mut kernel_stack = KernelStack::allocate();
+        let stack_addr = kernel_stack.pages().addr();
 
         let mut page_table = RootPageTableHolder::new_with_kernel_mapping();
 
         page_table.map(
-            STACK_END,
+            KernelStack::bottom(),
             stack_addr.get(),
-            PAGE_SIZE,
+            KERNEL_STACK_PAGES * PAGE_SIZE,
             crate::memory::page_tables::XWRMode::ReadWrite,
             false,
             "Stack".to_string(),
@@ -113,28 +261,73 @@ impl Process {
         let mut register_state = TrapFrame::zero();
         register_state[Register::sp] = STACK_START;
 
-        Arc::new(Mutex::new(Self {
-            name: "powersave".to_string(),
-            pid: POWERSAVE_PID,
+        Self {
+            name: name.into(),
+            pid,
             register_state,
             page_table,
-            program_counter: powersave as usize,
-            allocated_pages,
+            program_counter: entry,
+            allocated_pages: Vec::new(),
+            stack: Vec::new(),
+            kernel_stack: Some(kernel_stack),
+            cached_pages: Vec::new(),
+            vmas: Vec::new(),
+            heap_start: None,
+            program_break: 0,
             state: ProcessState::Runnable,
+            stopped: false,
+            removed: false,
             free_mmap_address: FREE_MMAP_START_ADDRESS,
             next_free_descriptor: 0,
+            #[cfg(feature = "net")]
             open_udp_sockets: BTreeMap::new(),
+            #[cfg(feature = "net")]
+            open_raw_sockets: BTreeMap::new(),
+            #[cfg(feature = "net")]
+            open_tcp_listeners: BTreeMap::new(),
+            #[cfg(feature = "net")]
+            open_tcp_streams: BTreeMap::new(),
+            open_ptys: BTreeMap::new(),
+            open_files: BTreeMap::new(),
+            terminal: None,
+            interrupts: Vec::new(),
             in_kernel_mode: true,
-            notify_on_die: BTreeSet::new(),
+            kernel_thread: true,
+            park_requested: false,
+            unparked: false,
             waiting_on_syscall: None,
-        }))
-    }
-
-    pub fn get_notifies_on_die(&self) -> impl Iterator<Item = &Pid> {
-        self.notify_on_die.iter()
+            wait_interrupter: None,
+            waits: 0,
+            resource_limits: ResourceLimits::default_limits(),
+            credentials: Credentials::ROOT,
+            environment: Environment::new(),
+            working_directory: ROOT_DIRECTORY.into(),
+            vdso: None,
+            cpu_clocks: 0,
+            running_since: None,
+            perf_values: PerfValues::default(),
+            perf_values_since: None,
+            reservation: None,
+            pinned_hart: None,
+            io_statistics: IoStatistics::default(),
+            floating_point_hart: None,
+            vector_state: None,
+        }
     }
 
+    /// Returns a null pointer if the process would exceed its limit
     pub fn mmap_pages(&mut self, number_of_pages: usize) -> *mut u8 {
+        let mapped_pages = self.mapped_pages().saturating_add(number_of_pages);
+        if !self
+            .resource_limits
+            .allows(Resource::MappedPages, mapped_pages as u64)
+        {
+            debug!(
+                "PID={} exceeds its mapped pages limit ({mapped_pages} pages)",
+                self.pid
+            );
+            return core::ptr::null_mut();
+        }
         let pages = PinnedHeapPages::new(number_of_pages);
         self.page_table.map_userspace(
             self.free_mmap_address,
@@ -149,8 +342,364 @@ impl Process {
         ptr
     }
 
-    pub fn add_notify_on_die(&mut self, pid: Pid) {
-        self.notify_on_die.insert(pid);
+    /// Reserves the address range, the pages are loaded by the page fault
+    /// handler. Returns a null pointer if the process would exceed its
+    /// limit.
+    pub fn mmap_file(&mut self, file: MappedFile, size: usize, privileges: XWRMode) -> *mut u8 {
+        let number_of_pages = size.div_ceil(PAGE_SIZE);
+        let mapped_pages = self.mapped_pages().saturating_add(number_of_pages);
+        if !self
+            .resource_limits
+            .allows(Resource::MappedPages, mapped_pages as u64)
+        {
+            debug!(
+                "PID={} exceeds its mapped pages limit ({mapped_pages} pages)",
+                self.pid
+            );
+            return core::ptr::null_mut();
+        }
+        let size = number_of_pages * PAGE_SIZE;
+        self.vmas.push(Vma::new(
+            self.free_mmap_address,
+            size,
+            privileges,
+            Source::File(file),
+        ));
+        let ptr = core::ptr::without_provenance_mut(self.free_mmap_address);
+        self.free_mmap_address += size;
+        ptr
+    }
+
+    pub fn vma_containing(&self, address: usize) -> Option<&Vma> {
+        self.vmas.iter().find(|vma| vma.contains(address))
+    }
+
+    /// The address must be page aligned and belong to an area. A shared
+    /// page which was mapped before is replaced.
+    pub fn map_vma_page(&mut self, address: usize, page: LoadedPage, private: bool) {
+        let vma = self
+            .vmas
+            .iter_mut()
+            .find(|vma| vma.contains(address))
+            .expect("Address must belong to an area");
+        let name = vma.name();
+        let (physical_address, privileges, replaced) = vma.insert(address, page, private);
+        if replaced {
+            self.page_table
+                .remap_page(address, physical_address, privileges, name);
+        } else {
+            self.page_table
+                .map_userspace(address, physical_address, PAGE_SIZE, privileges, name);
+        }
+    }
+
+    /// The physical address behind a user address which the debugger
+    /// patches, e.g. with a breakpoint. Pages which are shared with other
+    /// processes through the page cache are replaced by a private copy
+    /// first such that only this process sees the change.
+    pub fn private_physical_address(&mut self, address: usize) -> Option<usize> {
+        let page_address = align_down(address, PAGE_SIZE);
+        let (physical_address, privileges) = self.page_table.translate(page_address)?;
+        if let Some(page) = self
+            .vma_containing(page_address)
+            .and_then(|vma| vma.shared_page(page_address))
+        {
+            self.map_vma_page(page_address, page, true);
+        } else if let Some(shared) = self.cached_pages.iter().find_map(|pages| {
+            pages
+                .iter()
+                .find(|page| page.as_ptr() as usize == physical_address)
+        }) {
+            // Program segments aren't areas, the copy is a private mapping
+            let mut copy = PinnedHeapPages::new(1);
+            copy[0].copy_from_slice(&shared[..]);
+            self.page_table.remap_page(
+                page_address,
+                copy.as_ptr() as usize,
+                privileges,
+                format!("{} (patched)", self.name),
+            );
+            self.allocated_pages.push(copy);
+        }
+        self.page_table
+            .translate(address)
+            .map(|(physical_address, _)| physical_address)
+    }
+
+    /// Moves the end of the heap, 0 only returns the current break. Pages
+    /// which are cut off are unmapped.
+    pub fn set_program_break(&mut self, new_break: usize) -> Result<usize, SysBrkError> {
+        let heap_start = self.heap_start.ok_or(SysBrkError::InvalidBreak)?;
+        if new_break == 0 {
+            return Ok(self.program_break);
+        }
+        if !(heap_start..=FREE_MMAP_START_ADDRESS).contains(&new_break) {
+            return Err(SysBrkError::InvalidBreak);
+        }
+        let size = align_up(new_break - heap_start, PAGE_SIZE);
+        let mapped_pages = self.mapped_pages();
+        let heap = self
+            .vmas
+            .iter_mut()
+            .find(|vma| vma.start() == heap_start)
+            .expect("Heap must exist");
+        let heap_end = heap_start + heap.number_of_pages() * PAGE_SIZE;
+        if heap_start + size > heap_end && self.page_table.is_mapped(heap_end..heap_start + size) {
+            return Err(SysBrkError::InvalidBreak);
+        }
+        let added_pages = (size / PAGE_SIZE).saturating_sub(heap.number_of_pages());
+        if added_pages > 0
+            && !self
+                .resource_limits
+                .allows(Resource::MappedPages, (mapped_pages + added_pages) as u64)
+        {
+            debug!(
+                "PID={} exceeds its mapped pages limit with break {new_break:#x}",
+                self.pid
+            );
+            return Err(SysBrkError::LimitExceeded);
+        }
+        let name = heap.name();
+        let resized = heap.resize(size);
+        for address in resized.unmapped {
+            self.page_table.unmap_page(address);
+        }
+        for (address, physical_address) in resized.moved {
+            self.page_table
+                .remap_page(address, physical_address, XWRMode::ReadWrite, name.clone());
+        }
+        self.program_break = new_break;
+        Ok(new_break)
+    }
+
+    /// Grows the main stack down to the page of the address if the limits
+    /// allow it. The existing pages are never moved, syscalls might hold
+    /// pointers into them. Returns false if the address can't become
+    /// part of the stack.
+    pub fn grow_stack(&mut self, address: usize) -> bool {
+        if self.stack.is_empty() {
+            return false;
+        }
+        let stack_pages = self.stack.iter().map(|pages| pages.len()).sum::<usize>();
+        let bottom = STACK_START - stack_pages * PAGE_SIZE + 1;
+        if address >= bottom {
+            return false;
+        }
+        let number_of_pages = (STACK_START - align_down(address, PAGE_SIZE)) / PAGE_SIZE + 1;
+        let added_pages = number_of_pages - stack_pages;
+        let mapped_pages = self.mapped_pages() + added_pages;
+        if !self
+            .resource_limits
+            .allows(Resource::StackPages, number_of_pages as u64)
+            || !self
+                .resource_limits
+                .allows(Resource::MappedPages, mapped_pages as u64)
+        {
+            debug!(
+                "PID={} exceeds its limits with a stack of {number_of_pages} pages",
+                self.pid
+            );
+            return false;
+        }
+        let added = PinnedHeapPages::new(added_pages);
+        self.page_table.map_userspace(
+            bottom - added_pages * PAGE_SIZE,
+            added.as_ptr() as usize,
+            added_pages * PAGE_SIZE,
+            XWRMode::ReadWrite,
+            "Stack".to_string(),
+        );
+        self.stack.push(added);
+        true
+    }
+
+    /// The physical memory isn't owned by the process and therefore
+    /// doesn't count against its mapped pages limit
+    pub fn map_physical(&mut self, physical_range: Range<usize>, privileges: XWRMode) -> *mut u8 {
+        let size = physical_range.len();
+        self.page_table.map_userspace(
+            self.free_mmap_address,
+            physical_range.start,
+            size,
+            privileges,
+            "Device".to_string(),
+        );
+        let ptr = core::ptr::without_provenance_mut(self.free_mmap_address);
+        self.free_mmap_address += size;
+        ptr
+    }
+
+    pub fn mapped_pages(&self) -> usize {
+        self.allocated_pages
+            .iter()
+            .map(|pages| pages.len())
+            .chain(self.stack.iter().map(|pages| pages.len()))
+            .chain(self.kernel_stack.iter().map(KernelStack::number_of_pages))
+            .chain(self.cached_pages.iter().map(|pages| pages.len()))
+            .chain(self.vmas.iter().map(Vma::number_of_pages))
+            .sum()
+    }
+
+    pub fn resource_limits(&self) -> ResourceLimits {
+        self.resource_limits
+    }
+
+    pub fn resource_limits_mut(&mut self) -> &mut ResourceLimits {
+        &mut self.resource_limits
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        self.credentials
+    }
+
+    pub fn credentials_mut(&mut self) -> &mut Credentials {
+        &mut self.credentials
+    }
+
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.environment
+    }
+
+    pub fn working_directory(&self) -> &str {
+        &self.working_directory
+    }
+
+    /// The directory must be absolute and normalized
+    pub fn set_working_directory(&mut self, directory: String) {
+        self.working_directory = directory;
+    }
+
+    pub fn inheritance(&self) -> Inheritance {
+        Inheritance {
+            terminal: self.terminal(),
+            resource_limits: self.resource_limits,
+            credentials: self.credentials,
+            environment: self.environment.clone(),
+            working_directory: self.working_directory.clone(),
+            descriptors: Vec::new(),
+        }
+    }
+
+    pub fn start_running(&mut self) {
+        if let Some(vdso) = &mut self.vdso {
+            vdso.set_hart_id(Cpu::cpu_id());
+        }
+        self.running_since = Some(timer::get_current_clocks());
+        self.perf_values_since = Some(PerfValues::read());
+    }
+
+    pub fn stop_running(&mut self) {
+        if let Some(since) = self.perf_values_since.take() {
+            self.perf_values
+                .add(&PerfValues::read().elapsed_since(&since));
+        }
+        let Some(running_since) = self.running_since.take() else {
+            return;
+        };
+        let now = timer::get_current_clocks();
+        let clocks = now.saturating_sub(running_since);
+        self.cpu_clocks += clocks;
+        if let Some(reservation) = &mut self.reservation {
+            reservation.account(clocks, now);
+            if reservation.is_throttled(now) {
+                // An idle hart has to pick the process up again
+                timer::add_timer(reservation.deadline(), idle::notify_new_work);
+            }
+        }
+    }
+
+    /// A period of 0 returns to round robin scheduling
+    pub fn set_reservation(
+        &mut self,
+        period_milliseconds: u64,
+        budget_milliseconds: u64,
+    ) -> Result<(), SysPeriodicError> {
+        if period_milliseconds == 0 {
+            self.reservation = None;
+            return Ok(());
+        }
+        let reservation = Reservation::admit(
+            period_milliseconds,
+            budget_milliseconds,
+            self.reservation.as_ref(),
+        )?;
+        self.reservation = Some(reservation);
+        Ok(())
+    }
+
+    pub fn reservation_mut(&mut self) -> Option<&mut Reservation> {
+        self.reservation.as_mut()
+    }
+
+    /// The process runs only on the hart, e.g. to work on data which
+    /// belongs to it. Other harts take over while it is offline.
+    pub fn pin_to_hart(&mut self, hart_id: usize) {
+        self.pinned_hart = Some(hart_id);
+    }
+
+    pub fn pinned_hart(&self) -> Option<usize> {
+        self.pinned_hart
+    }
+
+    pub fn may_run_on(&self, hart_id: usize) -> bool {
+        self.pinned_hart
+            .is_none_or(|pinned| pinned == hart_id || !hotplug::is_online(pinned))
+    }
+
+    /// Rounded up, None for round robin processes
+    pub fn remaining_budget_milliseconds(&mut self) -> Option<u64> {
+        let now = timer::get_current_clocks();
+        self.reservation.as_mut().map(|reservation| {
+            reservation
+                .remaining(now)
+                .div_ceil(timer::milliseconds_to_clocks(1))
+        })
+    }
+
+    pub fn cpu_time_milliseconds(&self) -> u64 {
+        let running = self
+            .running_since
+            .map_or(0, |since| timer::get_current_clocks().saturating_sub(since));
+        (self.cpu_clocks + running) / timer::milliseconds_to_clocks(1)
+    }
+
+    /// Only valid for the current process of this hart while it runs
+    pub fn perf_values(&self) -> PerfValues {
+        let mut values = self.perf_values;
+        if let Some(since) = &self.perf_values_since {
+            values.add(&PerfValues::read().elapsed_since(since));
+        }
+        values
+    }
+
+    pub fn io_statistics(&self) -> IoStatistics {
+        self.io_statistics
+    }
+
+    pub fn io_statistics_mut(&mut self) -> &mut IoStatistics {
+        &mut self.io_statistics
+    }
+
+    pub fn floating_point_hart(&self) -> Option<usize> {
+        self.floating_point_hart
+    }
+
+    pub fn set_floating_point_hart(&mut self, hart: usize) {
+        self.floating_point_hart = Some(hart);
+    }
+
+    pub fn vector_state(&self) -> Option<&VectorState> {
+        self.vector_state.as_ref()
+    }
+
+    pub fn vector_state_mut(&mut self) -> &mut Option<VectorState> {
+        &mut self.vector_state
+    }
+
+    pub fn exceeds_cpu_time_limit(&self) -> bool {
+        !self
+            .resource_limits
+            .allows(Resource::CpuTime, self.cpu_time_milliseconds())
     }
 
     pub fn get_register_state(&self) -> &TrapFrame {
@@ -177,6 +726,34 @@ impl Process {
         self.state = state;
     }
 
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.removed
+    }
+
+    pub fn set_removed(&mut self) {
+        self.removed = true;
+    }
+
+    /// The state as shown in /proc
+    pub fn state_name(&self) -> &'static str {
+        if self.stopped {
+            return "Stopped";
+        }
+        match self.state {
+            ProcessState::Running => "Running",
+            ProcessState::Runnable => "Runnable",
+            ProcessState::Waiting => "Waiting",
+        }
+    }
+
     pub fn get_page_table(&self) -> &RootPageTableHolder {
         &self.page_table
     }
@@ -197,9 +774,63 @@ impl Process {
         self.in_kernel_mode
     }
 
-    pub fn set_waiting_on_syscall<RetType: 'static>(&mut self) {
+    pub fn is_kernel_thread(&self) -> bool {
+        self.kernel_thread
+    }
+
+    pub fn request_park(&mut self) {
+        self.park_requested = true;
+    }
+
+    pub fn take_park_request(&mut self) -> bool {
+        core::mem::take(&mut self.park_requested)
+    }
+
+    /// Makes the next park return immediately
+    pub fn set_unparked(&mut self) {
+        self.unparked = true;
+    }
+
+    pub fn take_unparked(&mut self) -> bool {
+        core::mem::take(&mut self.unparked)
+    }
+
+    pub fn set_waiting_on_syscall<RetType: Interruptible>(&mut self) {
         self.state = ProcessState::Waiting;
         self.waiting_on_syscall = Some(core::any::TypeId::of::<RetType>());
+        self.wait_interrupter = Some(|process| process.resume_on_syscall(RetType::interrupted()));
+        self.waits += 1;
+    }
+
+    pub fn is_waiting_on_syscall<RetType: 'static>(&self) -> bool {
+        self.waiting_on_syscall == Some(core::any::TypeId::of::<RetType>())
+    }
+
+    /// Identifies the current blocking syscall for its timeout
+    pub fn current_wait(&self) -> u64 {
+        self.waits
+    }
+
+    /// Whether the process still blocks in the syscall of `current_wait`
+    pub fn is_still_waiting<RetType: 'static>(&self, wait: u64) -> bool {
+        self.waits == wait && self.is_waiting_on_syscall::<RetType>()
+    }
+
+    /// Ends the blocking syscall without resuming the process, e.g.
+    /// because it was killed. Wait queues and timeouts skip it afterwards.
+    pub fn cancel_wait(&mut self) {
+        self.waiting_on_syscall = None;
+        self.wait_interrupter = None;
+    }
+
+    /// Resumes the blocking syscall with its interrupted value, e.g.
+    /// because the process was stopped. Returns false if it didn't block.
+    pub fn interrupt_wait(&mut self) -> bool {
+        let Some(interrupter) = self.wait_interrupter else {
+            return false;
+        };
+        interrupter(self);
+        true
     }
 
     pub fn resume_on_syscall<RetType: 'static>(&mut self, return_value: RetType) {
@@ -208,6 +839,16 @@ impl Process {
             Some(core::any::TypeId::of::<RetType>()),
             "resume return type is different than expected"
         );
+        self.waiting_on_syscall = None;
+        self.wait_interrupter = None;
+        self.state = ProcessState::Runnable;
+        idle::notify_new_work();
+
+        // Nothing to write back (and the pointer might be dangling)
+        if core::mem::size_of::<RetType>() == 0 {
+            return;
+        }
+
         let ptr = self.register_state[Register::a2] as *mut RetType;
         assert!(!ptr.is_null() && ptr.is_aligned());
         assert!(self.page_table.is_valid_userspace_ptr(ptr, true));
@@ -220,43 +861,243 @@ impl Process {
         unsafe {
             kernel_ptr.write(return_value);
         }
+    }
 
-        self.waiting_on_syscall = None;
-        self.state = ProcessState::Runnable;
+    pub fn from_elf(
+        elf_file: &ElfFile,
+        name: &str,
+        args: &[&str],
+        environment: Environment,
+    ) -> Result<Self, LoaderError> {
+        Self::from_elf_with_pid(elf_file, name, args, environment, get_next_pid())
+    }
+
+    /// Init has no arguments and an empty environment
+    pub fn new_init(elf_file: &ElfFile, name: &str) -> Result<Self, LoaderError> {
+        Self::from_elf_with_pid(elf_file, name, &[], Environment::new(), INIT_PID)
     }
 
-    pub fn from_elf(elf_file: &ElfFile, name: &str, args: &[&str]) -> Result<Self, LoaderError> {
+    fn from_elf_with_pid(
+        elf_file: &ElfFile,
+        name: &str,
+        args: &[&str],
+        environment: Environment,
+        pid: Pid,
+    ) -> Result<Self, LoaderError> {
         debug!("Create process from elf file");
 
         let LoadedElf {
             entry_address,
-            page_tables: page_table,
-            allocated_pages,
+            page_tables: mut page_table,
+            stack,
+            cached_pages,
+            mut vmas,
+            heap_start,
             args_start,
-        } = loader::load_elf(elf_file, name, args)?;
+        } = loader::load_elf(elf_file, name, args, &environment)?;
+        vmas.push(Vma::new(
+            heap_start,
+            0,
+            XWRMode::ReadWrite,
+            Source::Anonymous,
+        ));
 
         let mut register_state = TrapFrame::zero();
         register_state[Register::a0] = args_start;
         register_state[Register::sp] = align_down(args_start - 1, 8);
 
+        let mut vdso = VdsoPage::new(pid);
+        vdso.map(&mut page_table);
+
         Ok(Self {
             name: name.into(),
-            pid: get_next_pid(),
+            pid,
             register_state,
             page_table,
             program_counter: entry_address,
-            allocated_pages,
+            allocated_pages: Vec::new(),
+            stack: vec![stack],
+            kernel_stack: None,
+            cached_pages,
+            vmas,
+            heap_start: Some(heap_start),
+            program_break: heap_start,
             state: ProcessState::Runnable,
+            stopped: false,
+            removed: false,
             free_mmap_address: FREE_MMAP_START_ADDRESS,
             next_free_descriptor: 0,
+            #[cfg(feature = "net")]
             open_udp_sockets: BTreeMap::new(),
+            #[cfg(feature = "net")]
+            open_raw_sockets: BTreeMap::new(),
+            #[cfg(feature = "net")]
+            open_tcp_listeners: BTreeMap::new(),
+            #[cfg(feature = "net")]
+            open_tcp_streams: BTreeMap::new(),
+            open_ptys: BTreeMap::new(),
+            open_files: BTreeMap::new(),
+            terminal: None,
+            interrupts: Vec::new(),
             in_kernel_mode: false,
-            notify_on_die: BTreeSet::new(),
+            kernel_thread: false,
+            park_requested: false,
+            unparked: false,
             waiting_on_syscall: None,
+            wait_interrupter: None,
+            waits: 0,
+            resource_limits: ResourceLimits::default_limits(),
+            credentials: Credentials::ROOT,
+            environment,
+            working_directory: ROOT_DIRECTORY.into(),
+            vdso: Some(vdso),
+            cpu_clocks: 0,
+            running_since: None,
+            perf_values: PerfValues::default(),
+            perf_values_since: None,
+            reservation: None,
+            pinned_hart: None,
+            io_statistics: IoStatistics::default(),
+            floating_point_hart: None,
+            vector_state: None,
         })
     }
 
-    pub fn put_new_udp_socket(&mut self, socket: SharedAssignedSocket) -> UDPDescriptor {
+    fn can_open_descriptor(&self) -> bool {
+        let open_descriptors =
+            self.open_sockets() + self.open_ptys.len() + self.open_files.len() + 1;
+        self.resource_limits
+            .allows(Resource::OpenDescriptors, open_descriptors as u64)
+    }
+
+    #[cfg(feature = "net")]
+    fn open_sockets(&self) -> usize {
+        self.open_udp_sockets.len()
+            + self.open_raw_sockets.len()
+            + self.open_tcp_listeners.len()
+            + self.open_tcp_streams.len()
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn open_sockets(&self) -> usize {
+        0
+    }
+
+    #[cfg(feature = "net")]
+    fn describe_sockets(&self) -> Vec<(u64, String)> {
+        let udp_sockets = self.open_udp_sockets.iter().map(|(descriptor, socket)| {
+            (
+                descriptor.get(),
+                format!("udp:{}", socket.lock().get_port()),
+            )
+        });
+        let raw_sockets = self
+            .open_raw_sockets
+            .keys()
+            .map(|descriptor| (descriptor.get(), "raw".to_string()));
+        let tcp_listeners = self
+            .open_tcp_listeners
+            .iter()
+            .map(|(descriptor, listener)| {
+                (
+                    descriptor.get(),
+                    format!("tcp-listen:{}", listener.lock().port()),
+                )
+            });
+        let tcp_streams = self
+            .open_tcp_streams
+            .keys()
+            .map(|descriptor| (descriptor.get(), "tcp".to_string()));
+        udp_sockets
+            .chain(raw_sockets)
+            .chain(tcp_listeners)
+            .chain(tcp_streams)
+            .collect()
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn describe_sockets(&self) -> Vec<(u64, String)> {
+        Vec::new()
+    }
+
+    #[cfg(feature = "net")]
+    fn get_socket_descriptor(&self, number: u64) -> Option<SharedDescriptor> {
+        if let Some(socket) = self.open_udp_sockets.get(&UDPDescriptor::new(number)) {
+            return Some(SharedDescriptor::UdpSocket(socket.clone()));
+        }
+        if let Some(socket) = self.open_raw_sockets.get(&RawDescriptor::new(number)) {
+            return Some(SharedDescriptor::RawSocket(socket.clone()));
+        }
+        if let Some(listener) = self
+            .open_tcp_listeners
+            .get(&TcpListenerDescriptor::new(number))
+        {
+            return Some(SharedDescriptor::TcpListener(listener.clone()));
+        }
+        self.open_tcp_streams
+            .get(&TcpStreamDescriptor::new(number))
+            .map(|stream| SharedDescriptor::TcpStream(stream.clone()))
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn get_socket_descriptor(&self, _number: u64) -> Option<SharedDescriptor> {
+        None
+    }
+
+    /// The open descriptor with the number, whatever its kind is
+    pub fn get_descriptor(&self, number: u64) -> Option<SharedDescriptor> {
+        if let Some(file) = self.open_files.get(&FileDescriptor::new(number)) {
+            return Some(SharedDescriptor::File(file.clone()));
+        }
+        if let Some(pty) = self.open_ptys.get(&PtyDescriptor::new(number)) {
+            return Some(SharedDescriptor::Pty(pty.clone()));
+        }
+        self.get_socket_descriptor(number)
+    }
+
+    /// Descriptors which are opened afterwards get higher numbers. The
+    /// number must not be in use.
+    pub fn put_descriptor_at(&mut self, number: u64, descriptor: SharedDescriptor) {
+        self.next_free_descriptor = self.next_free_descriptor.max(number + 1);
+        let unused = match descriptor {
+            SharedDescriptor::File(file) => self
+                .open_files
+                .insert(FileDescriptor::new(number), file)
+                .is_none(),
+            SharedDescriptor::Pty(pty) => self
+                .open_ptys
+                .insert(PtyDescriptor::new(number), pty)
+                .is_none(),
+            #[cfg(feature = "net")]
+            SharedDescriptor::UdpSocket(socket) => self
+                .open_udp_sockets
+                .insert(UDPDescriptor::new(number), socket)
+                .is_none(),
+            #[cfg(feature = "net")]
+            SharedDescriptor::RawSocket(socket) => self
+                .open_raw_sockets
+                .insert(RawDescriptor::new(number), socket)
+                .is_none(),
+            #[cfg(feature = "net")]
+            SharedDescriptor::TcpListener(listener) => self
+                .open_tcp_listeners
+                .insert(TcpListenerDescriptor::new(number), listener)
+                .is_none(),
+            #[cfg(feature = "net")]
+            SharedDescriptor::TcpStream(stream) => self
+                .open_tcp_streams
+                .insert(TcpStreamDescriptor::new(number), stream)
+                .is_none(),
+        };
+        assert!(unused, "Descriptor must be empty.");
+    }
+
+    /// Returns None if the process would exceed its descriptor limit
+    #[cfg(feature = "net")]
+    pub fn put_new_udp_socket(&mut self, socket: SharedAssignedSocket) -> Option<UDPDescriptor> {
+        if !self.can_open_descriptor() {
+            return None;
+        }
         let descriptor = UDPDescriptor::new(self.next_free_descriptor);
         self.next_free_descriptor += 1;
 
@@ -265,15 +1106,262 @@ impl Process {
             "Descriptor must be empty."
         );
 
-        descriptor
+        Some(descriptor)
     }
 
+    #[cfg(feature = "net")]
     pub fn get_shared_udp_socket(
         &mut self,
         descriptor: UDPDescriptor,
     ) -> Option<&mut SharedAssignedSocket> {
         self.open_udp_sockets.get_mut(&descriptor)
     }
+
+    /// Returns None if the process would exceed its descriptor limit
+    #[cfg(feature = "net")]
+    pub fn put_new_raw_socket(&mut self, socket: SharedRawSocket) -> Option<RawDescriptor> {
+        if !self.can_open_descriptor() {
+            return None;
+        }
+        let descriptor = RawDescriptor::new(self.next_free_descriptor);
+        self.next_free_descriptor += 1;
+
+        assert!(
+            self.open_raw_sockets.insert(descriptor, socket).is_none(),
+            "Descriptor must be empty."
+        );
+
+        Some(descriptor)
+    }
+
+    #[cfg(feature = "net")]
+    pub fn get_raw_socket(&self, descriptor: RawDescriptor) -> Option<&SharedRawSocket> {
+        self.open_raw_sockets.get(&descriptor)
+    }
+
+    /// The listener is returned if the process would exceed its
+    /// descriptor limit, it must be dropped after the process is unlocked
+    #[cfg(feature = "net")]
+    pub fn put_new_tcp_listener(
+        &mut self,
+        listener: SharedTcpListener,
+    ) -> Result<TcpListenerDescriptor, SharedTcpListener> {
+        if !self.can_open_descriptor() {
+            return Err(listener);
+        }
+        let descriptor = TcpListenerDescriptor::new(self.next_free_descriptor);
+        self.next_free_descriptor += 1;
+
+        assert!(
+            self.open_tcp_listeners
+                .insert(descriptor, listener)
+                .is_none(),
+            "Descriptor must be empty."
+        );
+
+        Ok(descriptor)
+    }
+
+    #[cfg(feature = "net")]
+    pub fn get_tcp_listener(
+        &self,
+        descriptor: TcpListenerDescriptor,
+    ) -> Option<&SharedTcpListener> {
+        self.open_tcp_listeners.get(&descriptor)
+    }
+
+    /// The returned listener must be dropped after the process is unlocked
+    #[cfg(feature = "net")]
+    pub fn close_tcp_listener(
+        &mut self,
+        descriptor: TcpListenerDescriptor,
+    ) -> Option<SharedTcpListener> {
+        self.open_tcp_listeners.remove(&descriptor)
+    }
+
+    /// Like put_new_tcp_listener
+    #[cfg(feature = "net")]
+    pub fn put_new_tcp_stream(
+        &mut self,
+        stream: SharedTcpStream,
+    ) -> Result<TcpStreamDescriptor, SharedTcpStream> {
+        if !self.can_open_descriptor() {
+            return Err(stream);
+        }
+        let descriptor = TcpStreamDescriptor::new(self.next_free_descriptor);
+        self.next_free_descriptor += 1;
+
+        assert!(
+            self.open_tcp_streams.insert(descriptor, stream).is_none(),
+            "Descriptor must be empty."
+        );
+
+        Ok(descriptor)
+    }
+
+    #[cfg(feature = "net")]
+    pub fn get_tcp_stream(&self, descriptor: TcpStreamDescriptor) -> Option<&SharedTcpStream> {
+        self.open_tcp_streams.get(&descriptor)
+    }
+
+    /// The returned stream must be dropped after the process is unlocked
+    #[cfg(feature = "net")]
+    pub fn close_tcp_stream(&mut self, descriptor: TcpStreamDescriptor) -> Option<SharedTcpStream> {
+        self.open_tcp_streams.remove(&descriptor)
+    }
+
+    /// Returns None if the process would exceed its descriptor limit
+    pub fn put_new_pty(&mut self, pty: PtyMaster) -> Option<PtyDescriptor> {
+        if !self.can_open_descriptor() {
+            return None;
+        }
+        let descriptor = PtyDescriptor::new(self.next_free_descriptor);
+        self.next_free_descriptor += 1;
+
+        assert!(
+            self.open_ptys.insert(descriptor, pty).is_none(),
+            "Descriptor must be empty."
+        );
+
+        Some(descriptor)
+    }
+
+    pub fn get_pty(&self, descriptor: PtyDescriptor) -> Option<&PtyMaster> {
+        self.open_ptys.get(&descriptor)
+    }
+
+    /// The returned handle must be dropped after the process is unlocked
+    pub fn close_pty(&mut self, descriptor: PtyDescriptor) -> Option<PtyMaster> {
+        self.open_ptys.remove(&descriptor)
+    }
+
+    /// Returns None if the process would exceed its descriptor limit
+    pub fn put_new_file(&mut self, file: SharedOpenFile) -> Option<FileDescriptor> {
+        if !self.can_open_descriptor() {
+            return None;
+        }
+        let descriptor = FileDescriptor::new(self.next_free_descriptor);
+        self.next_free_descriptor += 1;
+
+        assert!(
+            self.open_files.insert(descriptor, file).is_none(),
+            "Descriptor must be empty."
+        );
+
+        Some(descriptor)
+    }
+
+    pub fn get_file(&self, descriptor: FileDescriptor) -> Option<&SharedOpenFile> {
+        self.open_files.get(&descriptor)
+    }
+
+    /// One line per open descriptor, what it refers to follows the number
+    pub fn describe_descriptors(&self) -> Vec<String> {
+        let ptys = self
+            .open_ptys
+            .keys()
+            .map(|descriptor| (descriptor.get(), "pty".to_string()));
+        let files = self
+            .open_files
+            .iter()
+            .map(|(descriptor, file)| (descriptor.get(), file.path().to_string()));
+        let mut descriptors: Vec<(u64, String)> = self
+            .describe_sockets()
+            .into_iter()
+            .chain(ptys)
+            .chain(files)
+            .collect();
+        descriptors.sort_by_key(|(descriptor, _)| *descriptor);
+        descriptors
+            .into_iter()
+            .map(|(descriptor, target)| format!("{descriptor} {target}"))
+            .collect()
+    }
+
+    pub fn close_file(&mut self, descriptor: FileDescriptor) -> Option<SharedOpenFile> {
+        self.open_files.remove(&descriptor)
+    }
+
+    pub fn terminal(&self) -> Option<PtySlave> {
+        self.terminal.clone()
+    }
+
+    pub fn set_terminal(&mut self, terminal: Option<PtySlave>) {
+        self.terminal = terminal;
+    }
+
+    /// Closes both sides of all ptys the process uses such that the
+    /// other side notices the exit right away. The handles must be
+    /// dropped after the process is unlocked.
+    pub fn detach_ptys(&mut self) -> (Option<PtySlave>, Vec<PtyMaster>) {
+        let masters = core::mem::take(&mut self.open_ptys).into_values();
+        (self.terminal.take(), masters.collect())
+    }
+
+    /// Connections lock processes when they wake writers, therefore the
+    /// handles must be dropped after the process is unlocked as well
+    #[cfg(feature = "net")]
+    pub fn detach_tcp_sockets(&mut self) -> (Vec<SharedTcpListener>, Vec<SharedTcpStream>) {
+        let listeners = core::mem::take(&mut self.open_tcp_listeners).into_values();
+        let streams = core::mem::take(&mut self.open_tcp_streams).into_values();
+        (listeners.collect(), streams.collect())
+    }
+
+    pub fn add_interrupt(&mut self, interrupt: UserspaceInterrupt) {
+        self.interrupts.push(interrupt);
+    }
+}
+
+impl Process {
+    /// Frees the pages of the process right away instead of after the
+    /// drop, such that leak_check sees them being returned
+    fn verify_teardown(&mut self) {
+        let mut inventory = Inventory::new();
+        for pages in &self.allocated_pages {
+            inventory.add("mmap", pages.as_ptr() as usize, pages.len());
+        }
+        for pages in &self.stack {
+            inventory.add("stack", pages.as_ptr() as usize, pages.len());
+        }
+        if let Some(stack) = &self.kernel_stack {
+            inventory.add("kernel stack", stack.start(), stack.number_of_pages());
+        }
+        for pages in self.vmas.iter().filter_map(Vma::owned_pages) {
+            inventory.add("vma", pages.as_ptr() as usize, pages.len());
+        }
+        if let Some(vdso) = &self.vdso {
+            inventory.add("vdso", vdso.address(), 1);
+        }
+        for page in self.page_table.table_pages() {
+            inventory.add("page tables", page, 1);
+        }
+        // Shared sockets stay open in the other processes
+        #[cfg(feature = "net")]
+        for socket in self.open_udp_sockets.values() {
+            if Arc::strong_count(socket) == 1 {
+                if let Some((start, number_of_pages)) = socket.lock().buffer_pages() {
+                    inventory.add("sockets", start, number_of_pages);
+                }
+            }
+        }
+
+        let owned = (
+            core::mem::take(&mut self.allocated_pages),
+            core::mem::take(&mut self.stack),
+            self.kernel_stack.take(),
+            core::mem::take(&mut self.vmas),
+            self.vdso.take(),
+            core::mem::replace(&mut self.page_table, RootPageTableHolder::empty()),
+        );
+        #[cfg(feature = "net")]
+        let owned = (owned, core::mem::take(&mut self.open_udp_sockets));
+        leak_check::verify_teardown(
+            self.pid,
+            inventory,
+            || drop(owned),
+            |owner, start| owner == "kernel stack" && kernel_stack::is_cached(start),
+        );
+    }
 }
 
 impl Drop for Process {
@@ -282,16 +1370,26 @@ impl Drop for Process {
             "Drop process (PID: {}) (Allocated pages: {:?})",
             self.pid, self.allocated_pages
         );
+        if leak_check::is_enabled() {
+            self.verify_teardown();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use common::syscalls::trap_frame::Register;
+    use common::{
+        syscalls::trap_frame::Register,
+        vdso::{VdsoData, VDSO_ADDRESS},
+    };
 
     use crate::{
-        autogenerated::userspace_programs::PROG1, klibc::elf::ElfFile, memory::PAGE_SIZE,
-        processes::process::FREE_MMAP_START_ADDRESS,
+        autogenerated::userspace_programs::PROG1,
+        klibc::elf::ElfFile,
+        memory::{page_tables::XWRMode, PAGE_SIZE},
+        processes::{
+            environment::Environment, loader::STACK_END, process::FREE_MMAP_START_ADDRESS,
+        },
     };
 
     use super::Process;
@@ -299,14 +1397,16 @@ mod tests {
     #[test_case]
     fn create_process_from_elf() {
         let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
-        let _process = Process::from_elf(&elf, "prog1", &[]);
+        let _process = Process::from_elf(&elf, "prog1", &[], Environment::new());
     }
 
     #[cfg(not(miri))]
     #[test_case]
     fn create_process_from_elf_with_args() {
         let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
-        let process = Process::from_elf(&elf, "prog1", &["arg1", "arg2"]).unwrap();
+        let mut environment = Environment::new();
+        environment.set("HOME", "/").unwrap();
+        let process = Process::from_elf(&elf, "prog1", &["arg1", "arg2"], environment).unwrap();
 
         // a0 points to the start of the arguments
         let mut arg_ptr = core::ptr::without_provenance(process.register_state[Register::a0]);
@@ -332,6 +1432,15 @@ mod tests {
             assert_eq!(arg2, "arg2");
             arg_ptr = arg_ptr.add(arg2.len() + 1);
 
+            let empty = core::ffi::CStr::from_ptr(arg_ptr).to_str().unwrap();
+            assert_eq!(empty, "");
+            arg_ptr = arg_ptr.add(1);
+
+            // The environment follows the arguments
+            let variable = core::ffi::CStr::from_ptr(arg_ptr).to_str().unwrap();
+            assert_eq!(variable, "HOME=/");
+            arg_ptr = arg_ptr.add(variable.len() + 1);
+
             let empty = core::ffi::CStr::from_ptr(arg_ptr).to_str().unwrap();
             assert_eq!(empty, "");
         }
@@ -340,7 +1449,7 @@ mod tests {
     #[test_case]
     fn mmap_process() {
         let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
-        let mut process = Process::from_elf(&elf, "prog1", &[]).unwrap();
+        let mut process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
         assert!(
             process.free_mmap_address == FREE_MMAP_START_ADDRESS,
             "Free MMAP Address must set to correct start"
@@ -364,4 +1473,59 @@ mod tests {
             "Free mmap address must have the value of the next free value"
         );
     }
+
+    #[test_case]
+    fn patched_program_pages_are_private() {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let mut process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        let other = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        let entry = process.program_counter;
+        let (shared, _) = process.page_table.translate(entry).unwrap();
+        assert_eq!(other.page_table.translate(entry).unwrap().0, shared);
+
+        let private = process.private_physical_address(entry).unwrap();
+        assert_ne!(private, shared);
+        // SAFETY: Both point to the code of the program on the kernel heap
+        unsafe { assert_eq!(*(private as *const u16), *(shared as *const u16)) };
+        assert_eq!(process.private_physical_address(entry), Some(private));
+        assert_eq!(other.page_table.translate(entry).unwrap().0, shared);
+    }
+
+    #[test_case]
+    fn vdso_is_mapped_read_only() {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let mut process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        process.start_running();
+
+        let (physical_address, privileges) = process
+            .page_table
+            .translate(VDSO_ADDRESS)
+            .expect("vDSO must be mapped");
+        assert_eq!(privileges, XWRMode::ReadOnly);
+        assert!(process.page_table.is_userspace_address(VDSO_ADDRESS));
+
+        // SAFETY: The kernel heap is identity mapped
+        let data = unsafe { &*(physical_address as *const VdsoData) };
+        assert_eq!(data.pid, process.get_pid());
+        assert_eq!(data.hart_id, crate::cpu::Cpu::cpu_id() as u64);
+        assert!(data.timebase_frequency > 0);
+    }
+
+    #[test_case]
+    fn growing_the_stack_keeps_its_pages() {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let mut process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        let top = process.page_table.translate(STACK_END);
+        let below = STACK_END - 3 * PAGE_SIZE;
+        assert!(process.page_table.translate(below).is_none());
+
+        assert!(process.grow_stack(below));
+        // Syscalls might still hold pointers into the old pages
+        assert_eq!(process.page_table.translate(STACK_END), top);
+        assert_eq!(
+            process.page_table.translate(below).map(|(_, mode)| mode),
+            Some(XWRMode::ReadWrite)
+        );
+        assert!(!process.grow_stack(below + PAGE_SIZE));
+    }
 }
@@ -1,47 +1,128 @@
-use alloc::{collections::BTreeMap, sync::Arc};
-use common::{mutex::Mutex, runtime_initialized::RuntimeInitializedData};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
+use common::{
+    errors::SysWaitError,
+    mutex::Mutex,
+    runtime_initialized::RuntimeInitializedData,
+    wait::{ChildExit, ExitStatus, WaitOptions, ANY_CHILD},
+};
 
-use crate::{autogenerated::userspace_programs::INIT, debug, info, klibc::elf::ElfFile};
+use crate::{
+    cmdline,
+    cpu::Cpu,
+    debug, info,
+    klibc::{elf::ElfFile, rcu::Rcu},
+};
 
-use super::process::{Pid, Process, ProcessState, POWERSAVE_PID};
+use super::{
+    idle,
+    process::{Pid, Process, ProcessState, INIT_PID, KERNEL_THREAD_PID_START, POWERSAVE_PID},
+    programs, timer,
+    wait_queue::WaitQueue,
+};
 
 pub type ProcessRef = Arc<Mutex<Process>>;
 
-pub static THE: RuntimeInitializedData<Mutex<ProcessTable>> = RuntimeInitializedData::new();
+// Lookups happen on every schedule on every hart. Therefore readers work on
+// a snapshot and don't serialize against process creation on another hart.
+pub static THE: RuntimeInitializedData<Rcu<ProcessTable>> = RuntimeInitializedData::new();
 
 pub fn init() {
     let mut process_table = ProcessTable::new();
+    process_table.add_process(create_init());
+    THE.initialize(Rcu::new(process_table));
+}
 
-    let elf = ElfFile::parse(INIT).expect("Cannot parse ELF file");
-    let process = Process::from_elf(&elf, "init", &[]).expect("init must succeed");
-    process_table.add_process(process);
+fn create_init() -> Process {
+    let init = cmdline::get().init;
+    let program = programs::find(init).expect("init program must exist");
+    let elf = ElfFile::parse(program.data()).expect("Cannot parse ELF file");
+    Process::new_init(&elf, init).expect("init must succeed")
+}
 
-    THE.initialize(Mutex::new(process_table));
+/// Starts init again once all user processes are gone, see
+/// cmdline::Idle::RestartInit
+pub fn restart_init() {
+    THE.update(|pt| {
+        // Another hart might have restarted it already
+        if pt.has_user_processes() {
+            return;
+        }
+        info!("No more processes to schedule, restarting init");
+        pt.add_process(create_init());
+    });
 }
 
+#[derive(Clone)]
 pub struct ProcessTable {
     processes: BTreeMap<Pid, ProcessRef>,
+    // Child to parent, including zombies. Init and kernel threads have
+    // no parent.
+    parents: BTreeMap<Pid, Pid>,
+    // Exited children until their parent waits for them
+    zombies: BTreeMap<Pid, ExitStatus>,
+    // Parent to the child it waits for, which might be ANY_CHILD
+    waiting_parents: BTreeMap<Pid, Pid>,
+    // The parents retry their wait once woken up, therefore they get
+    // no value
+    child_waits: WaitQueue<()>,
+    // Stopped children until their parent waits for them with
+    // WaitOptions::Untraced
+    unreported_stops: BTreeSet<Pid>,
 }
 
 impl ProcessTable {
     pub fn new() -> Self {
         Self {
             processes: BTreeMap::new(),
+            parents: BTreeMap::new(),
+            zombies: BTreeMap::new(),
+            waiting_parents: BTreeMap::new(),
+            child_waits: WaitQueue::new(),
+            unreported_stops: BTreeSet::new(),
         }
     }
 
     pub fn add_process(&mut self, process: Process) {
         self.processes
             .insert(process.get_pid(), Arc::new(Mutex::new(process)));
+        idle::notify_new_work();
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.processes.is_empty()
+    /// The parent can wait for the exit of the child. Without a living
+    /// parent it is added like any other process.
+    pub fn add_child(&mut self, process: Process, parent: Pid) {
+        if self.processes.contains_key(&parent) {
+            self.parents.insert(process.get_pid(), parent);
+        }
+        self.add_process(process);
+    }
+
+    pub fn parent_of(&self, pid: Pid) -> Option<Pid> {
+        self.parents.get(&pid).copied()
+    }
+
+    fn children_of(&self, parent: Pid) -> impl Iterator<Item = Pid> + '_ {
+        self.parents
+            .iter()
+            .filter(move |(_, p)| **p == parent)
+            .map(|(child, _)| *child)
+    }
+
+    /// Kernel threads alone don't keep the system running
+    pub fn has_user_processes(&self) -> bool {
+        self.processes
+            .range(..KERNEL_THREAD_PID_START)
+            .next()
+            .is_some()
     }
 
     pub fn get_highest_pid_without(&self, process_names: &[&str]) -> Option<Pid> {
         self.processes
-            .iter()
+            .range(..KERNEL_THREAD_PID_START)
             .max_by_key(|(pid, _)| *pid)
             .filter(|(_, p)| {
                 let p = p.lock();
@@ -50,6 +131,14 @@ impl ProcessTable {
             .map(|(pid, _)| *pid)
     }
 
+    pub fn processes(&self) -> impl Iterator<Item = &ProcessRef> {
+        self.processes.values()
+    }
+
+    pub fn pids(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.processes.keys().copied()
+    }
+
     pub fn dump(&self) {
         for (pid, process) in &self.processes {
             let process = process.lock();
@@ -63,57 +152,493 @@ impl ProcessTable {
         }
     }
 
-    pub fn kill(&mut self, pid: Pid) {
+    /// The process stays a zombie until its parent waits for it
+    pub fn kill(&mut self, pid: Pid, status: ExitStatus) {
         assert!(
             pid != POWERSAVE_PID,
             "We are not allowed to kill the never process"
         );
         debug!("Removing pid={pid} from process table");
-        if let Some(process) = self.processes.remove(&pid) {
-            for pid in process.lock().get_notifies_on_die() {
-                self.wake_process_up(*pid);
+        let Some(process) = self.processes.remove(&pid) else {
+            return;
+        };
+        // Readers with an old snapshot of the table might still see
+        // this process. Make sure they don't schedule it anymore.
+        let ptys = process.with_lock(|mut p| {
+            p.set_removed();
+            p.set_state(ProcessState::Waiting);
+            p.cancel_wait();
+            p.detach_ptys()
+        });
+        drop(ptys);
+        #[cfg(feature = "net")]
+        drop(process.with_lock(|mut p| p.detach_tcp_sockets()));
+
+        self.waiting_parents.remove(&pid);
+        self.unreported_stops.remove(&pid);
+        self.adopt_children_of(pid);
+        if let Some(&parent) = self.parents.get(&pid) {
+            self.zombies.insert(pid, status);
+            self.wake_waiting_parent(parent, pid);
+        }
+    }
+
+    /// Init adopts the orphans and reaps them. Without init nobody
+    /// waits for them anymore.
+    fn adopt_children_of(&mut self, pid: Pid) {
+        let init_alive = pid != INIT_PID && self.processes.contains_key(&INIT_PID);
+        let orphans: Vec<Pid> = self.children_of(pid).collect();
+        for orphan in orphans {
+            // Only the original parent is interested in them
+            self.unreported_stops.remove(&orphan);
+            if init_alive {
+                self.parents.insert(orphan, INIT_PID);
+                if self.zombies.contains_key(&orphan) {
+                    self.wake_waiting_parent(INIT_PID, orphan);
+                }
+            } else {
+                self.parents.remove(&orphan);
+                self.zombies.remove(&orphan);
+            }
+        }
+    }
+
+    /// The parent retries its wait and reaps the child or notices that it
+    /// stopped then
+    fn wake_waiting_parent(&mut self, parent: Pid, child: Pid) {
+        if !self
+            .waiting_parents
+            .get(&parent)
+            .is_some_and(|waits_for| *waits_for == child || *waits_for == ANY_CHILD)
+        {
+            return;
+        }
+        self.waiting_parents.remove(&parent);
+        self.child_waits.wake(parent, ());
+    }
+
+    /// Reaps the exited child or any exited child for ANY_CHILD. If
+    /// none exited yet, the parent is put to sleep if it blocks.
+    pub fn wait_for_child(
+        &mut self,
+        parent: Pid,
+        pid: Pid,
+        options: WaitOptions,
+    ) -> Result<Option<ChildExit>, SysWaitError> {
+        let children: Vec<Pid> = self
+            .children_of(parent)
+            .filter(|child| pid == ANY_CHILD || *child == pid)
+            .collect();
+        if children.is_empty() {
+            return Err(if pid == ANY_CHILD {
+                SysWaitError::NoChildren
+            } else {
+                SysWaitError::InvalidPid
+            });
+        }
+        if let Some(&child) = children
+            .iter()
+            .find(|child| self.zombies.contains_key(child))
+        {
+            self.parents.remove(&child);
+            let status = self.zombies.remove(&child).expect("Zombie must exist");
+            return Ok(Some(ChildExit { pid: child, status }));
+        }
+        if options == WaitOptions::Untraced {
+            if let Some(child) = children
+                .iter()
+                .find(|child| self.unreported_stops.contains(child))
+            {
+                self.unreported_stops.remove(child);
+                return Ok(Some(ChildExit {
+                    pid: *child,
+                    status: ExitStatus::Stopped,
+                }));
             }
         }
+        if options != WaitOptions::NoHang {
+            self.waiting_parents.insert(parent, pid);
+            if let Some(process) = self.processes.get(&parent) {
+                self.child_waits.wait(process);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Kills every process without waking up waiters.
+    /// Returns the number of killed processes.
+    pub fn kill_all(&mut self) -> usize {
+        self.parents.clear();
+        self.zombies.clear();
+        self.waiting_parents.clear();
+        self.child_waits = WaitQueue::new();
+        self.unreported_stops.clear();
+        let processes = core::mem::take(&mut self.processes);
+        for process in processes.values() {
+            // Same as in kill: stale readers must not schedule it anymore
+            process.with_lock(|mut p| {
+                p.set_removed();
+                p.set_state(ProcessState::Waiting);
+                p.cancel_wait();
+            });
+        }
+        processes.len()
     }
 
+    /// Periodic processes with budget left come first, the earliest
+    /// deadline first. The others take turns. Processes pinned to other
+    /// harts are skipped. The returned process is already marked as
+    /// running.
     pub fn next_runnable(&self, old_pid: Pid) -> Option<ProcessRef> {
-        let mut next_iter = self
+        let now = timer::get_current_clocks();
+        let hart_id = Cpu::cpu_id();
+        let has_budget = |p: &mut Process| {
+            p.reservation_mut()
+                .is_some_and(|reservation| !reservation.is_throttled(now))
+        };
+        // A claim only fails if the process changed after it was picked.
+        // Then it isn't picked again.
+        while let Some((_, process)) = self
             .processes
+            .values()
+            .filter_map(|process| {
+                let mut p = process.lock();
+                if p.get_state() != ProcessState::Runnable
+                    || p.is_stopped()
+                    || !p.may_run_on(hart_id)
+                    || !has_budget(&mut p)
+                {
+                    return None;
+                }
+                Some((p.reservation_mut()?.deadline(), process))
+            })
+            .min_by_key(|(deadline, _)| *deadline)
+        {
+            if Self::claim(process, hart_id, has_budget) {
+                return Some(process.clone());
+            }
+        }
+
+        self.processes
             .range(old_pid..)
             .skip(1)
-            .filter_map(Self::filter_map_runnable_processes);
+            .chain(self.processes.range(..=old_pid))
+            .map(|(_, process)| process)
+            .find(|process| Self::claim(process, hart_id, |p| p.reservation_mut().is_none()))
+            .cloned()
+    }
 
-        if let Some(next_process) = next_iter.next() {
-            Some(next_process.clone())
-        } else {
-            self.processes
-                .iter()
-                .filter_map(Self::filter_map_runnable_processes)
-                .next()
-                .cloned()
+    /// Marks the process as running if it can be scheduled. The lock is
+    /// held from the check to the transition, otherwise two harts could
+    /// pick the same process or run a process which was killed in between.
+    /// Stopped processes aren't picked at all. The table might be an old
+    /// snapshot, a process which was removed since then might have been
+    /// woken up and must not run again.
+    fn claim(
+        process: &ProcessRef,
+        hart_id: usize,
+        class_matches: impl FnOnce(&mut Process) -> bool,
+    ) -> bool {
+        let mut p = process.lock();
+        if p.get_state() != ProcessState::Runnable
+            || p.is_stopped()
+            || p.is_removed()
+            || !p.may_run_on(hart_id)
+            || !class_matches(&mut p)
+        {
+            return false;
+        }
+        p.set_state(ProcessState::Running);
+        true
+    }
+
+    pub fn get_process(&self, pid: Pid) -> Option<&ProcessRef> {
+        self.processes.get(&pid)
+    }
+
+    /// The process isn't scheduled anymore. If it is running on another
+    /// hart, it stops at the end of its time slice. A blocking syscall is
+    /// interrupted and returns once the process is resumed. Returns false
+    /// if the process was already stopped.
+    pub fn suspend(&mut self, pid: Pid) -> bool {
+        let Some(process) = self.processes.get(&pid) else {
+            return false;
+        };
+        let mut process = process.lock();
+        if process.is_stopped() {
+            return false;
         }
+        process.set_stopped(true);
+        process.interrupt_wait();
+        drop(process);
+        if let Some(&parent) = self.parents.get(&pid) {
+            self.unreported_stops.insert(pid);
+            self.wake_waiting_parent(parent, pid);
+        }
+        true
     }
 
-    fn filter_map_runnable_processes<'a>((_, p): (&Pid, &'a ProcessRef)) -> Option<&'a ProcessRef> {
-        if p.lock().get_state() == ProcessState::Runnable {
-            Some(p)
+    /// Returns false if the process isn't stopped
+    pub fn resume(&mut self, pid: Pid) -> bool {
+        let Some(process) = self.processes.get(&pid) else {
+            return false;
+        };
+        let mut process = process.lock();
+        if !process.is_stopped() {
+            return false;
+        }
+        process.set_stopped(false);
+        drop(process);
+        self.unreported_stops.remove(&pid);
+        idle::notify_new_work();
+        true
+    }
+
+    /// Resumes a parked kernel thread. If it is not parked, its next
+    /// park returns immediately.
+    pub fn unpark(&self, pid: Pid) {
+        let Some(process) = self.processes.get(&pid) else {
+            return;
+        };
+        let mut process = process.lock();
+        if process.get_state() == ProcessState::Waiting {
+            process.set_state(ProcessState::Runnable);
+            match process.pinned_hart() {
+                Some(hart_id) => idle::notify_new_work_on(hart_id),
+                None => idle::notify_new_work(),
+            }
         } else {
-            None
+            process.set_unparked();
         }
     }
+}
 
-    pub fn get_process(&self, pid: Pid) -> Option<&ProcessRef> {
-        self.processes.get(&pid)
+#[cfg(test)]
+mod tests {
+    use common::{
+        errors::SysWaitError,
+        wait::{ChildExit, ExitStatus, WaitOptions, ANY_CHILD},
+    };
+
+    use crate::{
+        autogenerated::userspace_programs::PROG1,
+        cpu::Cpu,
+        klibc::elf::ElfFile,
+        processes::{environment::Environment, hotplug, process::ProcessState, wait_queue},
+    };
+
+    use super::{Pid, Process, ProcessTable};
+
+    fn add(table: &mut ProcessTable, parent: Option<Pid>) -> Pid {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        let pid = process.get_pid();
+        match parent {
+            Some(parent) => table.add_child(process, parent),
+            None => table.add_process(process),
+        }
+        pid
     }
 
-    pub fn wake_process_up(&self, pid: Pid) {
-        debug!("Waking process up with pid={pid}");
-        let mut process = self.processes.get(&pid).expect("Process must exist").lock();
-        assert_eq!(
-            process.get_state(),
-            ProcessState::Waiting,
-            "Process must be in waiting state to be woken up"
-        );
-        process.set_state(ProcessState::Runnable);
+    fn state(table: &ProcessTable, pid: Pid) -> ProcessState {
+        table.get_process(pid).unwrap().lock().get_state()
+    }
+
+    #[test_case]
+    fn exited_children_are_reaped_once() {
+        let mut table = ProcessTable::new();
+        let parent = add(&mut table, None);
+        let child = add(&mut table, Some(parent));
+
+        assert!(matches!(
+            table.wait_for_child(parent, child, WaitOptions::NoHang),
+            Ok(None)
+        ));
+        table.kill(child, ExitStatus::Exited(3));
+        assert!(matches!(
+            table.wait_for_child(parent, ANY_CHILD, WaitOptions::NoHang),
+            Ok(Some(ChildExit { pid, status: ExitStatus::Exited(3) })) if pid == child
+        ));
+        assert!(matches!(
+            table.wait_for_child(parent, child, WaitOptions::NoHang),
+            Err(SysWaitError::InvalidPid)
+        ));
+        assert!(matches!(
+            table.wait_for_child(parent, ANY_CHILD, WaitOptions::NoHang),
+            Err(SysWaitError::NoChildren)
+        ));
+    }
+
+    #[test_case]
+    fn waiting_parent_is_woken_by_its_child() {
+        let mut table = ProcessTable::new();
+        let parent = add(&mut table, None);
+        let child = add(&mut table, Some(parent));
+        let sibling = add(&mut table, Some(parent));
+
+        assert!(matches!(
+            table.wait_for_child(parent, child, WaitOptions::Block),
+            Ok(None)
+        ));
+        assert_eq!(state(&table, parent), ProcessState::Waiting);
+        table.kill(sibling, ExitStatus::Killed);
+        assert_eq!(state(&table, parent), ProcessState::Waiting);
+        table.kill(child, ExitStatus::Killed);
+        assert_eq!(state(&table, parent), ProcessState::Runnable);
+    }
+
+    #[test_case]
+    fn children_of_exited_parents_are_not_kept() {
+        let mut table = ProcessTable::new();
+        // Created first, therefore no other process of the table can be
+        // init and adopt the children
+        let waiter = add(&mut table, None);
+        let zombie = add(&mut table, Some(waiter));
+        let orphan = add(&mut table, Some(waiter));
+
+        table.kill(zombie, ExitStatus::Exited(0));
+        assert!(matches!(
+            table.wait_for_child(waiter, orphan, WaitOptions::Block),
+            Ok(None)
+        ));
+        table.kill(waiter, ExitStatus::Killed);
+        assert_eq!(table.parent_of(orphan), None);
+        assert_eq!(table.parent_of(zombie), None);
+        // The waiter is gone and must not be woken up
+        table.kill(orphan, ExitStatus::Killed);
+        assert!(table.zombies.is_empty());
+        assert!(table.waiting_parents.is_empty());
+    }
+
+    #[test_case]
+    fn claimed_processes_are_not_picked_twice() {
+        let mut table = ProcessTable::new();
+        let pid = add(&mut table, None);
+
+        assert!(table
+            .next_runnable(pid)
+            .is_some_and(|p| p.lock().get_pid() == pid));
+        assert_eq!(state(&table, pid), ProcessState::Running);
+        // Another hart must not run it at the same time
+        assert!(table.next_runnable(pid).is_none());
+    }
+
+    #[test_case]
+    fn pinned_processes_only_run_on_their_hart() {
+        let mut table = ProcessTable::new();
+        let pid = add(&mut table, None);
+        let hart_id = Cpu::cpu_id();
+
+        // Pinned to a hart which is online, but not the current one
+        let other_hart = (0..64).find(|&id| id != hart_id && hotplug::is_online(id));
+        if let Some(other_hart) = other_hart {
+            table
+                .get_process(pid)
+                .unwrap()
+                .lock()
+                .pin_to_hart(other_hart);
+            assert!(table.next_runnable(pid).is_none());
+            assert_eq!(state(&table, pid), ProcessState::Runnable);
+        }
+
+        // Offline harts don't keep their processes
+        if let Some(offline_hart) = (0..64).find(|&id| !hotplug::is_online(id)) {
+            table
+                .get_process(pid)
+                .unwrap()
+                .lock()
+                .pin_to_hart(offline_hart);
+            assert!(table.get_process(pid).unwrap().lock().may_run_on(hart_id));
+        }
+
+        table.get_process(pid).unwrap().lock().pin_to_hart(hart_id);
+        assert!(table
+            .next_runnable(pid)
+            .is_some_and(|p| p.lock().get_pid() == pid));
+    }
+
+    #[test_case]
+    fn removed_processes_are_not_picked_from_old_snapshots() {
+        let mut table = ProcessTable::new();
+        let pid = add(&mut table, None);
+        let snapshot = table.clone();
+
+        table.kill(pid, ExitStatus::Exited(0));
+        // E.g. a timer which fired after the kill
+        let process = snapshot.get_process(pid).unwrap();
+        process.lock().set_state(ProcessState::Runnable);
+
+        assert!(snapshot.next_runnable(pid).is_none());
+        assert_eq!(process.lock().get_state(), ProcessState::Runnable);
+    }
+
+    #[test_case]
+    fn stopped_children_are_reported_once() {
+        let mut table = ProcessTable::new();
+        let parent = add(&mut table, None);
+        let child = add(&mut table, Some(parent));
+
+        assert!(matches!(
+            table.wait_for_child(parent, child, WaitOptions::Untraced),
+            Ok(None)
+        ));
+        assert!(table.suspend(child));
+        assert!(!table.suspend(child));
+        assert_eq!(state(&table, parent), ProcessState::Runnable);
+        assert!(table
+            .next_runnable(parent)
+            .is_some_and(|p| p.lock().get_pid() == parent));
+        assert!(matches!(
+            table.wait_for_child(parent, child, WaitOptions::Untraced),
+            Ok(Some(ChildExit { pid, status: ExitStatus::Stopped })) if pid == child
+        ));
+        assert!(matches!(
+            table.wait_for_child(parent, child, WaitOptions::Untraced),
+            Ok(None)
+        ));
+
+        assert!(table.resume(child));
+        assert!(!table.resume(child));
+        assert!(!table.get_process(child).unwrap().lock().is_stopped());
+    }
+
+    #[test_case]
+    fn stopped_processes_return_from_their_syscall_once_resumed() {
+        let mut table = ProcessTable::new();
+        let pid = add(&mut table, None);
+        let process = table.get_process(pid).unwrap().clone();
+        wait_queue::block::<()>(&mut process.lock());
+
+        assert!(table.suspend(pid));
+        assert_eq!(state(&table, pid), ProcessState::Runnable);
+        assert!(table.next_runnable(pid).is_none());
+        assert!(table.resume(pid));
+        assert!(table
+            .next_runnable(pid)
+            .is_some_and(|p| p.lock().get_pid() == pid));
+    }
+
+    #[test_case]
+    fn kernel_threads_are_unparked_once() {
+        extern "C" fn entry() -> ! {
+            panic!("Kernel threads don't run in unit tests");
+        }
+        let mut table = ProcessTable::new();
+        let thread = Process::create_kernel_thread("unpark_test", entry);
+        let pid = thread.get_pid();
+        assert!(thread.is_kernel_thread());
+        table.add_process(thread);
+        let thread = table.get_process(pid).unwrap().clone();
+
+        // The next park returns right away
+        table.unpark(pid);
+        assert_eq!(state(&table, pid), ProcessState::Runnable);
+        assert!(thread.lock().take_unparked());
+        assert!(!thread.lock().take_unparked());
+
+        thread.lock().set_state(ProcessState::Waiting);
+        table.unpark(pid);
+        assert_eq!(state(&table, pid), ProcessState::Runnable);
+        assert!(!thread.lock().take_unparked());
     }
 }
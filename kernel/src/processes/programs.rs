@@ -0,0 +1,168 @@
+//! Programs are looked up by name or path. Sources which are registered
+//! at runtime, e.g. a filesystem, are searched first. The programs which
+//! are embedded into the kernel are the fallback and are also found
+//! under /bin. Therefore new programs don't need a kernel rebuild.
+
+use alloc::{collections::BTreeSet, string::String, sync::Arc, vec::Vec};
+use common::mutex::Mutex;
+
+use crate::{
+    autogenerated::userspace_programs::PROGRAMS, klibc::util::minimum_amount_of_pages,
+    memory::page::PinnedHeapPages,
+};
+
+const EMBEDDED_DIRECTORY: &str = "/bin/";
+
+static SOURCES: Mutex<ProgramSources> = Mutex::new(ProgramSources::new());
+
+/// Program segments are cached by the name of the program.
+/// Therefore a source must not change a program once it was loaded.
+pub trait ProgramSource: Send + Sync {
+    fn load(&self, name: &str) -> Option<Program>;
+    fn names(&self) -> Vec<String>;
+}
+
+#[derive(Debug, Clone)]
+pub enum Program {
+    // Lives as long as the kernel, e.g. embedded into the kernel image
+    Static(&'static [u8]),
+    Loaded(Arc<LoadedProgram>),
+}
+
+/// The ELF parser needs 8 byte aligned data, which is guaranteed by the pages
+#[derive(Debug)]
+pub struct LoadedProgram {
+    pages: PinnedHeapPages,
+    length: usize,
+}
+
+impl Program {
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let mut pages = PinnedHeapPages::new(minimum_amount_of_pages(data.len()).max(1));
+        pages.fill(data);
+        Self::Loaded(Arc::new(LoadedProgram {
+            pages,
+            length: data.len(),
+        }))
+    }
+
+    /// Copies the data only if it is not aligned
+    pub fn from_static(data: &'static [u8]) -> Self {
+        if data.as_ptr() as usize % 8 == 0 {
+            Self::Static(data)
+        } else {
+            Self::from_bytes(data)
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::Static(data) => data,
+            Self::Loaded(program) => {
+                // SAFETY: The pages contain at least length bytes
+                unsafe {
+                    core::slice::from_raw_parts(program.pages.as_ptr() as *const u8, program.length)
+                }
+            }
+        }
+    }
+}
+
+struct ProgramSources {
+    sources: Vec<Arc<dyn ProgramSource>>,
+}
+
+impl ProgramSources {
+    const fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+        }
+    }
+}
+
+struct EmbeddedPrograms;
+
+impl ProgramSource for EmbeddedPrograms {
+    fn load(&self, name: &str) -> Option<Program> {
+        let name = name.strip_prefix(EMBEDDED_DIRECTORY).unwrap_or(name);
+        PROGRAMS
+            .iter()
+            .find(|(program_name, _)| *program_name == name)
+            .map(|(_, elf)| Program::Static(elf))
+    }
+
+    fn names(&self) -> Vec<String> {
+        PROGRAMS.iter().map(|(name, _)| (*name).into()).collect()
+    }
+}
+
+pub fn register_source(source: Arc<dyn ProgramSource>) {
+    SOURCES.lock().sources.push(source);
+}
+
+fn sources() -> Vec<Arc<dyn ProgramSource>> {
+    // Loading might take some time, don't hold the lock meanwhile
+    let mut sources = SOURCES.lock().sources.clone();
+    sources.push(Arc::new(EmbeddedPrograms));
+    sources
+}
+
+fn find_in(sources: &[Arc<dyn ProgramSource>], name: &str) -> Option<Program> {
+    sources.iter().find_map(|source| source.load(name))
+}
+
+fn names_in(sources: &[Arc<dyn ProgramSource>]) -> BTreeSet<String> {
+    sources.iter().flat_map(|source| source.names()).collect()
+}
+
+pub fn find(name: &str) -> Option<Program> {
+    find_in(&sources(), name)
+}
+
+/// The names of all programs in sorted order
+pub fn names() -> BTreeSet<String> {
+    names_in(&sources())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+    use super::{find_in, names_in, EmbeddedPrograms, Program, ProgramSource};
+
+    struct TestSource;
+
+    impl ProgramSource for TestSource {
+        fn load(&self, name: &str) -> Option<Program> {
+            (name == "/mnt/prog1").then(|| Program::from_bytes(b"test program"))
+        }
+
+        fn names(&self) -> Vec<String> {
+            vec!["/mnt/prog1".into()]
+        }
+    }
+
+    #[test_case]
+    fn registered_sources_are_searched_first() {
+        let sources: Vec<Arc<dyn ProgramSource>> =
+            vec![Arc::new(TestSource), Arc::new(EmbeddedPrograms)];
+
+        let program = find_in(&sources, "/mnt/prog1").expect("Program must be found");
+        assert_eq!(program.data(), b"test program");
+        assert_eq!(program.data().as_ptr() as usize % 8, 0);
+
+        assert!(matches!(
+            find_in(&sources, "prog1"),
+            Some(Program::Static(_))
+        ));
+        assert!(matches!(
+            find_in(&sources, "/bin/prog1"),
+            Some(Program::Static(_))
+        ));
+        assert!(find_in(&sources, "/mnt/prog2").is_none());
+
+        let names = names_in(&sources);
+        assert!(names.contains("/mnt/prog1"));
+        assert!(names.contains("prog1"));
+    }
+}
@@ -0,0 +1,91 @@
+use common::{
+    errors::SysResourceLimitError,
+    resource_limits::{Resource, ResourceLimit, UNLIMITED},
+};
+
+use crate::memory;
+
+const DEFAULT_OPEN_DESCRIPTORS: ResourceLimit = ResourceLimit::new(64, 1024);
+// 1 MiB, the hard limit is only bounded by the mapped pages limit
+const DEFAULT_STACK_PAGES: ResourceLimit = ResourceLimit::new(256, UNLIMITED);
+
+/// The limits of one process. Children inherit the limits of the
+/// process which started them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits([ResourceLimit; Resource::COUNT]);
+
+impl ResourceLimits {
+    /// A single process must not be able to take the whole memory of
+    /// the kernel. Therefore it can only map half of the heap pages.
+    pub fn default_limits() -> Self {
+        let mapped_pages = (memory::total_heap_pages() / 2) as u64;
+        let mut limits = Self([ResourceLimit::unlimited(); Resource::COUNT]);
+        limits.0[Resource::MappedPages as usize] = ResourceLimit::new(mapped_pages, mapped_pages);
+        limits.0[Resource::OpenDescriptors as usize] = DEFAULT_OPEN_DESCRIPTORS;
+        limits.0[Resource::StackPages as usize] = DEFAULT_STACK_PAGES;
+        limits
+    }
+
+    pub fn get(&self, resource: Resource) -> ResourceLimit {
+        self.0[resource as usize]
+    }
+
+    pub fn allows(&self, resource: Resource, usage: u64) -> bool {
+        self.get(resource).allows(usage)
+    }
+
+    /// The limit must already be checked for soft <= hard
+    pub fn set(
+        &mut self,
+        resource: Resource,
+        limit: ResourceLimit,
+    ) -> Result<(), SysResourceLimitError> {
+        let current = &mut self.0[resource as usize];
+        if limit.hard > current.hard {
+            return Err(SysResourceLimitError::HardLimitRaised);
+        }
+        *current = limit;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        errors::SysResourceLimitError,
+        resource_limits::{Resource, ResourceLimit, UNLIMITED},
+    };
+
+    use super::ResourceLimits;
+
+    #[test_case]
+    fn soft_limit_can_be_raised_up_to_hard_limit() {
+        let mut limits = ResourceLimits::default_limits();
+        assert_eq!(
+            limits.get(Resource::OpenDescriptors),
+            ResourceLimit::new(64, 1024)
+        );
+        assert!(limits.allows(Resource::OpenDescriptors, 64));
+        assert!(!limits.allows(Resource::OpenDescriptors, 65));
+
+        assert!(limits
+            .set(Resource::OpenDescriptors, ResourceLimit::new(1024, 1024))
+            .is_ok());
+        assert!(limits.allows(Resource::OpenDescriptors, 65));
+    }
+
+    #[test_case]
+    fn hard_limit_can_only_be_lowered() {
+        let mut limits = ResourceLimits::default_limits();
+        assert_eq!(limits.get(Resource::CpuTime).hard, UNLIMITED);
+
+        assert!(limits
+            .set(Resource::CpuTime, ResourceLimit::new(100, 200))
+            .is_ok());
+        assert!(matches!(
+            limits.set(Resource::CpuTime, ResourceLimit::new(100, 300)),
+            Err(SysResourceLimitError::HardLimitRaised)
+        ));
+        assert_eq!(limits.get(Resource::CpuTime), ResourceLimit::new(100, 200));
+    }
+}
@@ -1,28 +1,78 @@
-use common::{errors::SchedulerError, unwrap_or_return};
-use core::mem::offset_of;
+use common::{errors::SchedulerError, wait::ExitStatus};
+use core::{
+    mem::offset_of,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use alloc::sync::Arc;
 use common::syscalls::trap_frame::TrapFrame;
 
 use crate::{
-    autogenerated::userspace_programs::PROGRAMS,
+    cmdline::{self, Idle},
     cpu::Cpu,
-    debug, info,
-    klibc::elf::ElfFile,
-    processes::{process::Process, timer},
-    test::qemu_exit,
+    debug,
+    debugging::profiler,
+    info,
+    klibc::{counters::Counter, elf::ElfFile},
+    per_cpu::per_cpu,
+    power::{self, InitExit},
+    processes::{
+        idle,
+        process::{Inheritance, Process},
+        programs, timer, vector,
+    },
+    warn,
 };
 
 use super::{
-    process::{Pid, ProcessState, POWERSAVE_PID},
+    process::{Pid, ProcessState, INIT_PID, POWERSAVE_PID},
     process_table::{self, ProcessRef},
 };
 
 pub const TRAP_FRAME_OFFSET: usize = offset_of!(CpuScheduler, trap_frame);
 
+const TIME_SLICE_MILLISECONDS: u64 = 10;
+
+per_cpu!(static CONTEXT_SWITCHES: u64 = 0);
+static ALL_CONTEXT_SWITCHES: Counter = Counter::new("scheduler.context_switches");
+
+/// How a program is started besides what it inherits
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StartOptions {
+    // Not scheduled until it is resumed
+    pub suspended: bool,
+    // Adopted by init right away, the starting process can't wait for it
+    pub detached: bool,
+}
+
+pub fn context_switches_on_current_cpu() -> u64 {
+    CONTEXT_SWITCHES.with(|c| *c)
+}
+
+/// Decided by the idle option of the kernel command line
+fn no_user_processes_left() {
+    match cmdline::get().idle {
+        Idle::Shutdown => {
+            info!("No more processes to schedule, shutting down system");
+            power::power_off();
+        }
+        Idle::RestartInit => process_table::restart_init(),
+        Idle::Wfi => {
+            // Every hart gets here on every schedule from now on
+            static REPORTED: AtomicBool = AtomicBool::new(false);
+            if !REPORTED.swap(true, Ordering::Relaxed) {
+                info!("No more processes to schedule, waiting for interrupts");
+            }
+        }
+    }
+}
+
 pub struct CpuScheduler {
     trap_frame: TrapFrame,
     current_process: ProcessRef,
+    // Mirrors the pid of current_process such that it can be read
+    // without taking the process lock (used by lock debugging)
+    current_pid: Pid,
     powersave_process: ProcessRef,
 }
 
@@ -33,6 +83,7 @@ impl CpuScheduler {
         Self {
             trap_frame: TrapFrame::zero(),
             current_process: powersave_process.clone(),
+            current_pid: POWERSAVE_PID,
             powersave_process,
         }
     }
@@ -49,6 +100,10 @@ impl CpuScheduler {
         &self.current_process
     }
 
+    pub fn current_pid(&self) -> Pid {
+        self.current_pid
+    }
+
     pub fn is_current_process_energy_saver(&self) -> bool {
         Arc::ptr_eq(&self.current_process, &self.powersave_process)
     }
@@ -56,72 +111,171 @@ impl CpuScheduler {
     pub fn schedule(&mut self) {
         debug!("Schedule next process");
         self.prepare_next_process();
-        timer::set_timer(10);
+        if self.is_current_process_energy_saver() {
+            // Tickless idle: We wait for an interrupt or an IPI
+            // which tells us that there is something to do.
+            idle::enter();
+            timer::clear_time_slice();
+        } else {
+            idle::leave();
+            // Periodic processes are preempted when their budget is used up
+            let budget = self
+                .current_process
+                .with_lock(|mut p| p.remaining_budget_milliseconds());
+            timer::set_timer(budget.map_or(TIME_SLICE_MILLISECONDS, |budget| {
+                budget.clamp(1, TIME_SLICE_MILLISECONDS)
+            }));
+        }
     }
 
     pub fn kill_current_process(&mut self) {
+        self.exit_current_process(ExitStatus::Killed);
+    }
+
+    /// The parent gets the status when it waits for the process
+    pub fn exit_current_process(&mut self, status: ExitStatus) {
         let pid = self.current_process.lock().get_pid();
+        if pid == INIT_PID {
+            // An exit of init was already reported by sys_exit
+            power::init_exited(InitExit::Killed);
+        }
         self.queue_current_process_back();
-        process_table::THE.lock().kill(pid);
+        process_table::THE.update(|pt| pt.kill(pid, status));
         self.schedule();
     }
 
-    pub fn let_current_process_wait_for(&self, pid: Pid) -> bool {
-        let wait_for_process =
-            unwrap_or_return!(process_table::THE.lock().get_process(pid).cloned(), false);
+    /// Takes the crashed current process off the hart without killing
+    /// it. It waits without a wake up until its core dump was printed.
+    pub fn hold_crashed_current_process(&mut self) {
+        self.unschedule_current_process(|_| ProcessState::Waiting);
+        self.schedule();
+    }
 
-        let mut current_process = self.current_process.lock();
+    /// Kills the current process if it used up its CPU time.
+    /// Returns true in that case.
+    pub fn enforce_cpu_time_limit(&mut self) -> bool {
+        let exceeded = self.current_process.with_lock(|p| {
+            let exceeded = p.exceeds_cpu_time_limit();
+            if exceeded {
+                warn!(
+                    "Kill PID={} NAME={}: CPU time limit exceeded",
+                    p.get_pid(),
+                    p.get_name()
+                );
+            }
+            exceeded
+        });
+        if exceeded {
+            self.kill_current_process();
+        }
+        exceeded
+    }
 
-        current_process.set_state(ProcessState::Waiting);
+    pub fn send_ctrl_c(&mut self) {
+        self.queue_current_process_back();
 
-        wait_for_process
-            .lock()
-            .add_notify_on_die(current_process.get_pid());
+        process_table::THE.update(|pt| {
+            let highest_pid = pt.get_highest_pid_without(&["sesh"]);
 
-        true
+            if let Some(pid) = highest_pid {
+                pt.kill(pid, ExitStatus::Killed);
+            }
+        });
+
+        self.schedule();
     }
 
-    pub fn send_ctrl_c(&mut self) {
+    /// Stops the foreground process like send_ctrl_c kills it. The shell
+    /// waiting for it gets control back.
+    pub fn send_ctrl_z(&mut self) {
         self.queue_current_process_back();
 
-        process_table::THE.with_lock(|mut pt| {
+        process_table::THE.update(|pt| {
             let highest_pid = pt.get_highest_pid_without(&["sesh"]);
 
             if let Some(pid) = highest_pid {
-                pt.kill(pid);
+                pt.suspend(pid);
             }
         });
 
         self.schedule();
     }
 
-    pub fn start_program(&mut self, name: &str, args: &[&str]) -> Result<Pid, SchedulerError> {
-        for (prog_name, elf) in PROGRAMS {
-            if name == *prog_name {
-                let elf = ElfFile::parse(elf).expect("Cannot parse ELF file");
-                let process = Process::from_elf(&elf, prog_name, args)?;
-                let pid = process.get_pid();
-                process_table::THE.lock().add_process(process);
-                return Ok(pid);
-            }
+    /// Puts the current process back such that another hart can
+    /// pick it up. Used when the current hart goes offline.
+    pub fn park(&mut self) {
+        self.queue_current_process_back();
+    }
+
+    pub fn start_program(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        inheritance: Inheritance,
+        options: StartOptions,
+    ) -> Result<Pid, SchedulerError> {
+        let program = programs::find(name).ok_or(SchedulerError::InvalidProgramName)?;
+        let elf = ElfFile::parse(program.data()).expect("Cannot parse ELF file");
+        let mut process = Process::from_elf(&elf, name, args, inheritance.environment)?;
+        process.set_terminal(inheritance.terminal);
+        *process.resource_limits_mut() = inheritance.resource_limits;
+        *process.credentials_mut() = inheritance.credentials;
+        process.set_working_directory(inheritance.working_directory);
+        for (number, descriptor) in inheritance.descriptors {
+            process.put_descriptor_at(number, descriptor);
+        }
+        process.set_stopped(options.suspended);
+        let pid = process.get_pid();
+        let parent = if options.detached {
+            INIT_PID
+        } else {
+            self.current_process.lock().get_pid()
+        };
+        process_table::THE.update(|pt| pt.add_child(process, parent));
+        Ok(pid)
+    }
+
+    /// Lets the current kernel thread wait if it requested it with
+    /// kthread::park. The state is saved before the thread is marked as
+    /// waiting such that an unpark on another hart can't resume it early.
+    pub fn park_current_process_if_requested(&mut self) {
+        if !self.current_process.lock().take_park_request() {
+            return;
         }
-        Err(SchedulerError::InvalidProgramName)
+        self.unschedule_current_process(|p| {
+            if p.take_unparked() {
+                ProcessState::Runnable
+            } else {
+                ProcessState::Waiting
+            }
+        });
+        self.schedule();
     }
 
     fn queue_current_process_back(&mut self) -> Pid {
+        self.unschedule_current_process(|p| match p.get_state() {
+            ProcessState::Running => ProcessState::Runnable,
+            ProcessState::Waiting => ProcessState::Waiting,
+            ProcessState::Runnable => panic!("Inavlid process state."),
+        })
+    }
+
+    fn unschedule_current_process(
+        &mut self,
+        next_state: impl FnOnce(&mut Process) -> ProcessState,
+    ) -> Pid {
         if self.current_process.lock().get_pid() == POWERSAVE_PID {
             return POWERSAVE_PID;
         }
         self.swap_current_with_powersave().with_lock(|mut p| {
-            match p.get_state() {
-                ProcessState::Running => p.set_state(ProcessState::Runnable),
-                ProcessState::Waiting => {}
-                ProcessState::Runnable => panic!("Inavlid process state."),
-            }
+            p.stop_running();
 
             p.set_program_counter(Cpu::read_sepc());
             p.set_in_kernel_mode(Cpu::is_in_kernel_mode());
             p.set_register_state(&self.trap_frame);
+            vector::save_if_dirty(&mut p);
+            let state = next_state(&mut p);
+            p.set_state(state);
             let pid = p.get_pid();
             debug!("Unscheduling PID={} NAME={}", pid, p.get_name());
             pid
@@ -131,19 +285,34 @@ impl CpuScheduler {
     fn prepare_next_process(&mut self) {
         let old_pid = self.queue_current_process_back();
 
-        process_table::THE.with_lock(|pt| {
-            if pt.is_empty() {
-                info!("No more processes to schedule, shutting down system");
-                qemu_exit::exit_success();
-            }
-            let next_runnable = pt
-                .next_runnable(old_pid)
-                .unwrap_or(self.powersave_process.clone());
+        let mut pt = process_table::THE.read();
+        if !pt.has_user_processes() {
+            // The snapshot would miss a restarted init
+            drop(pt);
+            no_user_processes_left();
+            pt = process_table::THE.read();
+        }
+        // The process is already marked as running, setting the state here
+        // would revive a process which was killed in the meantime
+        let next_runnable = pt.next_runnable(old_pid).unwrap_or_else(|| {
+            self.powersave_process
+                .lock()
+                .set_state(ProcessState::Running);
+            self.powersave_process.clone()
+        });
 
-            self.current_process = next_runnable;
-            self.current_process.lock().set_state(ProcessState::Running);
+        self.current_process = next_runnable;
+        self.current_pid = self.current_process.with_lock(|mut p| {
+            p.start_running();
+            profiler::note_process(p.get_pid(), p.get_name());
+            p.get_pid()
         });
 
+        if self.current_pid != old_pid {
+            CONTEXT_SWITCHES.with(|c| *c += 1);
+            ALL_CONTEXT_SWITCHES.increment();
+        }
+
         self.set_cpu_reg_for_current_process();
     }
 
@@ -164,6 +333,7 @@ impl CpuScheduler {
     }
 
     fn swap_current_with_powersave(&mut self) -> ProcessRef {
+        self.current_pid = POWERSAVE_PID;
         core::mem::replace(&mut self.current_process, self.powersave_process.clone())
     }
 }
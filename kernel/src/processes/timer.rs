@@ -1,39 +1,325 @@
-use crate::{cpu::Cpu, debug, device_tree, sbi};
-use common::{big_endian::BigEndian, runtime_initialized::RuntimeInitializedData};
-use core::arch::asm;
+use crate::{
+    cpu::Cpu, debug, device_tree::tree, drivers::platform::DeviceTreeDriver, info, initcall,
+    interrupts::deferred, per_cpu::per_cpu, sbi,
+};
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use common::{mutex::Mutex, runtime_initialized::RuntimeInitializedData};
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-pub const CLINT_BASE: usize = 0x2000000;
-pub const CLINT_SIZE: usize = 0x10000;
+// The timer is programmed through the SBI or with Sstc and the software
+// interrupts are sent through the SBI. The CLINT is only claimed such
+// that it doesn't show up as unclaimed.
+pub const CLINT_DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "clint",
+    compatible: &["sifive,clint0", "riscv,clint0"],
+    needs_mapping: false,
+    probe: |_| Ok(()),
+};
 
 static CLOCKS_PER_SEC: RuntimeInitializedData<u64> = RuntimeInitializedData::new();
 
+// With the Sstc extension we can program the timer without going through the SBI
+static SSTC_AVAILABLE: RuntimeInitializedData<bool> = RuntimeInitializedData::new();
+
+const CSR_STIMECMP: usize = 0x14d;
+
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(1);
+
+pub type TimerCallback = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimerId(u64);
+
+/// All pending timers of one hart. The hardware timer is programmed
+/// to the earliest deadline of the scheduler time slice and the
+/// one-shot timers.
+struct TimerQueue {
+    time_slice_deadline: Option<u64>,
+    timers: BTreeMap<(u64, TimerId), TimerCallback>,
+}
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self {
+            time_slice_deadline: None,
+            timers: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, deadline: u64, id: TimerId, callback: TimerCallback) {
+        self.timers.insert((deadline, id), callback);
+    }
+
+    fn cancel(&mut self, id: TimerId) -> bool {
+        let key = self
+            .timers
+            .keys()
+            .find(|(_, timer_id)| *timer_id == id)
+            .copied();
+        key.and_then(|key| self.timers.remove(&key)).is_some()
+    }
+
+    fn next_deadline(&self) -> Option<u64> {
+        let next_timer = self
+            .timers
+            .first_key_value()
+            .map(|((deadline, _), _)| *deadline);
+        match (self.time_slice_deadline, next_timer) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn take_expired(&mut self, now: u64) -> Vec<TimerCallback> {
+        let mut expired = Vec::new();
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            expired.push(entry.remove());
+        }
+        expired
+    }
+
+    fn take_time_slice_expired(&mut self, now: u64) -> bool {
+        if self
+            .time_slice_deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.time_slice_deadline = None;
+            return true;
+        }
+        false
+    }
+}
+
+per_cpu!(static TIMER_QUEUE: TimerQueue = TimerQueue::new());
+
+// Timers of harts which went offline. Adopted by the next hart
+// which receives a software interrupt.
+static MIGRATED_TIMERS: Mutex<Vec<(u64, TimerId, TimerCallback)>> = Mutex::new(Vec::new());
+
+initcall!(Core, init);
+
 pub fn init() {
-    let clocks_per_sec = device_tree::THE
-        .root_node()
-        .find_node("cpus")
+    let clocks_per_sec = tree::root()
+        .find("/cpus")
         .expect("There must be a cpus node")
-        .get_property("timebase-frequency")
+        .property("timebase-frequency")
         .expect("There must be a timebase-frequency")
-        .consume_sized_type::<BigEndian<u32>>()
-        .expect("The value must be u32")
-        .get() as u64;
+        .u64()
+        .expect("The value must be one or two cells");
     CLOCKS_PER_SEC.initialize(clocks_per_sec);
+
+    let sstc_available = tree::root()
+        .find("/cpus/cpu")
+        .is_some_and(|cpu| cpu.has_riscv_isa_extension("sstc"));
+    info!(
+        "Sstc extension {}",
+        if sstc_available {
+            "available"
+        } else {
+            "not available; using SBI timer"
+        }
+    );
+    SSTC_AVAILABLE.initialize(sstc_available);
 }
 
+/// Sets the time slice of the scheduler. When it expires the timer
+/// interrupt handler schedules the next process.
 #[no_mangle]
 pub extern "C" fn set_timer(milliseconds: u64) {
     debug!("enabling timer {milliseconds} ms");
-    let current = get_current_clocks();
-    assert_eq!(*CLOCKS_PER_SEC / 1000, 10_000);
-    let next = current + ((*CLOCKS_PER_SEC / 1000) * milliseconds);
-    sbi::extensions::timer_extension::sbi_set_timer(next).assert_success();
+    let deadline = get_current_clocks() + milliseconds_to_clocks(milliseconds);
+    TIMER_QUEUE.with(|queue| {
+        queue.time_slice_deadline = Some(deadline);
+        arm(queue);
+    });
     Cpu::enable_timer_interrupt();
 }
 
-fn get_current_clocks() -> u64 {
+/// Stops the time slice such that the timer only fires for pending
+/// one-shot timers. Used when the hart becomes idle.
+pub fn clear_time_slice() {
+    TIMER_QUEUE.with(|queue| {
+        queue.time_slice_deadline = None;
+        arm(queue);
+    });
+}
+
+/// Calls `callback` on the current hart as soon as the absolute
+/// `deadline` (in clocks, see [`get_current_clocks`]) is reached.
+/// The callback runs as deferred work of the timer interrupt.
+pub fn add_timer(deadline: u64, callback: impl FnOnce() + Send + 'static) -> TimerId {
+    let id = TimerId(NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed));
+    TIMER_QUEUE.with(|queue| {
+        queue.add(deadline, id, Box::new(callback));
+        arm(queue);
+    });
+    Cpu::enable_timer_interrupt();
+    id
+}
+
+pub fn add_timer_in(milliseconds: u64, callback: impl FnOnce() + Send + 'static) -> TimerId {
+    add_timer(deadline_in(milliseconds), callback)
+}
+
+/// The absolute deadline the given milliseconds from now
+pub fn deadline_in(milliseconds: u64) -> u64 {
+    get_current_clocks() + milliseconds_to_clocks(milliseconds)
+}
+
+/// Returns false if the timer already fired or was
+/// never registered on this hart.
+#[allow(dead_code)]
+pub fn cancel_timer(id: TimerId) -> bool {
+    TIMER_QUEUE.with(|queue| {
+        let cancelled = queue.cancel(id);
+        arm(queue);
+        cancelled
+    })
+}
+
+/// Moves all one-shot timers of the current hart to the migration list.
+/// Returns the number of migrated timers.
+pub fn migrate_timers() -> usize {
+    let timers = TIMER_QUEUE.with(|queue| {
+        let timers = core::mem::take(&mut queue.timers);
+        arm(queue);
+        timers
+    });
+    let count = timers.len();
+    MIGRATED_TIMERS.with_lock(|mut migrated| {
+        migrated.extend(
+            timers
+                .into_iter()
+                .map(|((deadline, id), callback)| (deadline, id, callback)),
+        );
+    });
+    count
+}
+
+pub fn adopt_migrated_timers() {
+    let timers = core::mem::take(&mut *MIGRATED_TIMERS.lock());
+    if timers.is_empty() {
+        return;
+    }
+    TIMER_QUEUE.with(|queue| {
+        for (deadline, id, callback) in timers {
+            queue.add(deadline, id, callback);
+        }
+        arm(queue);
+    });
+    Cpu::enable_timer_interrupt();
+}
+
+/// Runs all expired timer callbacks. Returns true if the
+/// time slice of the current process has expired.
+pub fn handle_timer_interrupt() -> bool {
+    let now = get_current_clocks();
+    let (expired, time_slice_expired) = TIMER_QUEUE.with(|queue| {
+        let expired = queue.take_expired(now);
+        let time_slice_expired = queue.take_time_slice_expired(now);
+        arm(queue);
+        (expired, time_slice_expired)
+    });
+    // Callbacks are run outside of the queue such that they can add new timers
+    for callback in expired {
+        deferred::defer(callback);
+    }
+    time_slice_expired
+}
+
+fn arm(queue: &TimerQueue) {
+    // Without any deadline we push the next interrupt into the far future
+    let deadline = queue.next_deadline().unwrap_or(u64::MAX);
+    if *SSTC_AVAILABLE {
+        write_stimecmp(deadline);
+    } else {
+        sbi::extensions::timer_extension::sbi_set_timer(deadline).assert_success();
+    }
+}
+
+fn write_stimecmp(deadline: u64) {
+    unsafe {
+        asm!("csrw {csr}, {deadline}", csr = const CSR_STIMECMP, deadline = in(reg) deadline);
+    }
+}
+
+pub fn clocks_per_second() -> u64 {
+    *CLOCKS_PER_SEC
+}
+
+pub fn milliseconds_to_clocks(milliseconds: u64) -> u64 {
+    (*CLOCKS_PER_SEC / 1000) * milliseconds
+}
+
+pub fn get_current_clocks() -> u64 {
     let current: u64;
     unsafe {
         asm!("rdtime {current}", current = out(reg)current);
     };
     current
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{TimerId, TimerQueue};
+    use alloc::{boxed::Box, sync::Arc};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test_case]
+    fn next_deadline_is_earliest() {
+        let mut queue = TimerQueue::new();
+        assert_eq!(queue.next_deadline(), None);
+        queue.time_slice_deadline = Some(100);
+        assert_eq!(queue.next_deadline(), Some(100));
+        queue.add(50, TimerId(1), Box::new(|| {}));
+        assert_eq!(queue.next_deadline(), Some(50));
+        queue.time_slice_deadline = None;
+        queue.add(20, TimerId(2), Box::new(|| {}));
+        assert_eq!(queue.next_deadline(), Some(20));
+    }
+
+    #[test_case]
+    fn only_expired_timers_are_taken() {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let mut queue = TimerQueue::new();
+        for (id, deadline) in [(1, 10), (2, 30), (3, 20)] {
+            let fired = fired.clone();
+            queue.add(
+                deadline,
+                TimerId(id),
+                Box::new(move || {
+                    fired.fetch_add(1, Ordering::Relaxed);
+                }),
+            );
+        }
+        let expired = queue.take_expired(20);
+        assert_eq!(expired.len(), 2);
+        expired.into_iter().for_each(|callback| callback());
+        assert_eq!(fired.load(Ordering::Relaxed), 2);
+        assert_eq!(queue.next_deadline(), Some(30));
+    }
+
+    #[test_case]
+    fn cancel_timer() {
+        let mut queue = TimerQueue::new();
+        queue.add(10, TimerId(1), Box::new(|| {}));
+        assert!(queue.cancel(TimerId(1)));
+        assert!(!queue.cancel(TimerId(1)));
+        assert_eq!(queue.next_deadline(), None);
+    }
+
+    #[test_case]
+    fn time_slice_expiry() {
+        let mut queue = TimerQueue::new();
+        queue.time_slice_deadline = Some(10);
+        assert!(!queue.take_time_slice_expired(9));
+        assert!(queue.take_time_slice_expired(10));
+        assert_eq!(queue.time_slice_deadline, None);
+    }
+}
@@ -0,0 +1,80 @@
+use common::{
+    runtime_initialized::RuntimeInitializedData,
+    vdso::{VdsoData, CPU_FEATURE_VECTOR, VDSO_ADDRESS},
+};
+
+use crate::{
+    drivers::rtc,
+    memory::{
+        page::PinnedHeapPages,
+        page_tables::{RootPageTableHolder, XWRMode},
+        PAGE_SIZE,
+    },
+    processes::{process::Pid, timer, vector},
+};
+
+use alloc::string::ToString;
+
+// The per-boot part which is the same for every process
+static BOOT_DATA: RuntimeInitializedData<VdsoData> = RuntimeInitializedData::new();
+
+/// Must be called after the timer, the RTC and the vector extension
+/// are initialized
+pub fn init(number_of_harts: usize) {
+    // Taken as close together as possible such that userspace can
+    // calculate the wall clock time from the time CSR
+    let epoch_clocks = timer::get_current_clocks();
+    let epoch_nanoseconds = rtc::now_nanoseconds().unwrap_or(0);
+    BOOT_DATA.initialize(VdsoData {
+        timebase_frequency: timer::clocks_per_second(),
+        epoch_nanoseconds,
+        epoch_clocks,
+        number_of_harts: number_of_harts as u64,
+        pid: 0,
+        hart_id: 0,
+        cpu_features: if vector::is_available() {
+            CPU_FEATURE_VECTOR
+        } else {
+            0
+        },
+    });
+}
+
+/// The vDSO page of one process
+#[derive(Debug)]
+pub struct VdsoPage(PinnedHeapPages);
+
+impl VdsoPage {
+    pub fn new(pid: Pid) -> Self {
+        let mut page = Self(PinnedHeapPages::new(1));
+        let data = VdsoData { pid, ..*BOOT_DATA };
+        // SAFETY: The page is large and aligned enough
+        unsafe { page.data().write(data) };
+        page
+    }
+
+    /// The address of the page in the kernel mapping
+    pub fn address(&self) -> usize {
+        self.0.as_ptr() as usize
+    }
+
+    pub fn map(&mut self, page_table: &mut RootPageTableHolder) {
+        page_table.map_userspace(
+            VDSO_ADDRESS,
+            self.0.addr().get(),
+            PAGE_SIZE,
+            XWRMode::ReadOnly,
+            "vDSO".to_string(),
+        );
+    }
+
+    pub fn set_hart_id(&mut self, hart_id: usize) {
+        // SAFETY: The page contains VdsoData. Volatile because
+        // userspace reads it concurrently.
+        unsafe { core::ptr::addr_of_mut!((*self.data()).hart_id).write_volatile(hart_id as u64) };
+    }
+
+    fn data(&mut self) -> *mut VdsoData {
+        self.0.as_mut_ptr().as_ptr().cast()
+    }
+}
@@ -0,0 +1,225 @@
+//! Vector registers are switched like the floating point registers, see
+//! floating_point. Processes which never used them have no vector state
+//! at all. The kernel itself doesn't use them, their size is only known
+//! at runtime and therefore the trap entry doesn't save them. They are
+//! saved when a process is unscheduled after it wrote them.
+
+use alloc::{boxed::Box, vec};
+use common::runtime_initialized::RuntimeInitializedData;
+use core::arch::asm;
+
+use crate::{
+    cpu::Cpu,
+    device_tree::tree,
+    info, initcall,
+    per_cpu::per_cpu,
+    processes::process::{Pid, Process},
+};
+
+const SSTATUS_VS: usize = 0b11 << 9;
+const SSTATUS_VS_CLEAN: usize = 0b10 << 9;
+
+// The register groups of vl8re8.v and vs8r.v
+const REGISTER_GROUPS: usize = 4;
+const REGISTERS_PER_GROUP: usize = 8;
+
+static AVAILABLE: RuntimeInitializedData<bool> = RuntimeInitializedData::new();
+
+// The process whose registers are loaded on this hart
+per_cpu!(static OWNER: Option<Pid> = None);
+
+pub struct VectorState {
+    registers: Box<[u8]>,
+    vl: usize,
+    vtype: usize,
+    vstart: usize,
+    vcsr: usize,
+    // The hart whose registers are at least as new as the saved state
+    hart: Option<usize>,
+}
+
+impl VectorState {
+    fn new() -> Self {
+        let vlenb: usize;
+        // SAFETY: Only reads a csr, the registers are enabled
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrr {vlenb}, vlenb",
+                ".option pop",
+                vlenb = out(reg) vlenb,
+            );
+        }
+        Self {
+            registers: vec![0; vlenb * REGISTER_GROUPS * REGISTERS_PER_GROUP].into_boxed_slice(),
+            vl: 0,
+            // vill, like after a reset
+            vtype: 1 << 63,
+            vstart: 0,
+            vcsr: 0,
+            hart: None,
+        }
+    }
+
+    fn group_size(&self) -> usize {
+        self.registers.len() / REGISTER_GROUPS
+    }
+
+    fn save(&mut self) {
+        // SAFETY: The buffer holds all 32 registers. Whole register
+        // stores don't depend on vtype but on vstart, which is reset.
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrr {vl}, vl",
+                "csrr {vtype}, vtype",
+                "csrr {vstart}, vstart",
+                "csrr {vcsr}, vcsr",
+                "csrw vstart, zero",
+                "vs8r.v v0, ({registers})",
+                "add {registers}, {registers}, {group_size}",
+                "vs8r.v v8, ({registers})",
+                "add {registers}, {registers}, {group_size}",
+                "vs8r.v v16, ({registers})",
+                "add {registers}, {registers}, {group_size}",
+                "vs8r.v v24, ({registers})",
+                ".option pop",
+                registers = inout(reg) self.registers.as_mut_ptr() => _,
+                group_size = in(reg) self.group_size(),
+                vl = out(reg) self.vl,
+                vtype = out(reg) self.vtype,
+                vstart = out(reg) self.vstart,
+                vcsr = out(reg) self.vcsr,
+            );
+        }
+    }
+
+    fn load(&self) {
+        // SAFETY: The buffer holds all 32 registers. The kernel isn't
+        // compiled with the vector extension, none of them is in use.
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrw vstart, zero",
+                "vl8re8.v v0, ({registers})",
+                "add {registers}, {registers}, {group_size}",
+                "vl8re8.v v8, ({registers})",
+                "add {registers}, {registers}, {group_size}",
+                "vl8re8.v v16, ({registers})",
+                "add {registers}, {registers}, {group_size}",
+                "vl8re8.v v24, ({registers})",
+                "vsetvl zero, {vl}, {vtype}",
+                "csrw vstart, {vstart}",
+                "csrw vcsr, {vcsr}",
+                ".option pop",
+                registers = inout(reg) self.registers.as_ptr() => _,
+                group_size = in(reg) self.group_size(),
+                vl = in(reg) self.vl,
+                vtype = in(reg) self.vtype,
+                vstart = in(reg) self.vstart,
+                vcsr = in(reg) self.vcsr,
+            );
+        }
+    }
+}
+
+initcall!(Core, init);
+
+pub fn init() {
+    let available = tree::root()
+        .find("/cpus/cpu")
+        .is_some_and(|cpu| cpu.has_riscv_isa_extension("v"));
+    info!(
+        "Vector extension {}",
+        if available {
+            "available"
+        } else {
+            "not available"
+        }
+    );
+    AVAILABLE.initialize(available);
+}
+
+pub fn is_available() -> bool {
+    *AVAILABLE
+}
+
+fn state() -> usize {
+    Cpu::read_sstatus() & SSTATUS_VS
+}
+
+fn set_state(state: usize) {
+    Cpu::csrc_sstatus(SSTATUS_VS);
+    Cpu::csrs_sstatus(state);
+}
+
+fn owns_registers(process: &Process, owner: Option<Pid>) -> bool {
+    owner == Some(process.get_pid())
+        && process
+            .vector_state()
+            .is_some_and(|state| state.hart == Some(Cpu::cpu_id()))
+}
+
+/// Loads the registers of the current process if it doesn't own them.
+/// Returns false if it does, the instruction was illegal then.
+pub fn handle_illegal_instruction() -> bool {
+    if !is_available() {
+        return false;
+    }
+    let owner = OWNER.with(|owner| *owner);
+    let loaded = Cpu::with_current_process(|mut process| {
+        if owns_registers(&process, owner) {
+            return None;
+        }
+        set_state(SSTATUS_VS_CLEAN);
+        let state = process
+            .vector_state_mut()
+            .get_or_insert_with(VectorState::new);
+        state.load();
+        state.hart = Some(Cpu::cpu_id());
+        Some(process.get_pid())
+    });
+    let Some(pid) = loaded else {
+        return false;
+    };
+    OWNER.with(|owner| *owner = Some(pid));
+    set_state(SSTATUS_VS_CLEAN);
+    true
+}
+
+/// Saves the registers of a process which is unscheduled if it wrote
+/// them. They stay loaded.
+pub fn save_if_dirty(process: &mut Process) {
+    if state() != SSTATUS_VS {
+        return;
+    }
+    process
+        .vector_state_mut()
+        .as_mut()
+        .expect("Only processes with vector state can write the registers")
+        .save();
+    set_state(SSTATUS_VS_CLEAN);
+}
+
+/// Called by every trap handler before it returns
+#[no_mangle]
+extern "C" fn prepare_vector_return() {
+    if !is_available() {
+        return;
+    }
+    let owner = OWNER.with(|owner| *owner);
+    let owned = Cpu::with_current_process(|process| owns_registers(&process, owner));
+    if !owned {
+        set_state(0);
+    } else if state() == 0 {
+        set_state(SSTATUS_VS_CLEAN);
+    }
+}
+
+/// Every process has to load its registers before it uses them
+pub fn disable() {
+    set_state(0);
+}
@@ -0,0 +1,326 @@
+//! Processes which block in a syscall until an event happens. A wait
+//! queue resumes them with the return value of the syscall, therefore
+//! all waiters of a queue block in syscalls with the same return type.
+//!
+//! Every blocking syscall of a process is counted. A waiter which was
+//! resumed by its timeout or whose process was killed in the meantime is
+//! skipped, therefore nobody has to remove it from the queue.
+//!
+//! Stopping a process interrupts its blocking syscall, see
+//! `Interruptible`. It returns once the process continues.
+
+use core::marker::PhantomData;
+
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+};
+use common::{
+    deadline::NO_DEADLINE,
+    errors::{SysInputError, SysIrqError, SysPeriodicError, SysPollError},
+    mutex::Mutex,
+};
+
+use crate::processes::{
+    process::{Pid, Process},
+    process_table::ProcessRef,
+    timer,
+};
+
+/// The return value of a blocking syscall which was interrupted, like
+/// EINTR on Linux. Syscalls without a return value end early; the ones
+/// which are executed again when woken (see SyscallHandler::restart)
+/// block again.
+pub trait Interruptible: 'static {
+    fn interrupted() -> Self;
+}
+
+impl Interruptible for () {
+    fn interrupted() -> Self {}
+}
+
+impl<T: 'static> Interruptible for Result<T, SysInputError> {
+    fn interrupted() -> Self {
+        Err(SysInputError::Interrupted)
+    }
+}
+
+impl<T: 'static> Interruptible for Result<T, SysPollError> {
+    fn interrupted() -> Self {
+        Err(SysPollError::Interrupted)
+    }
+}
+
+impl<T: 'static> Interruptible for Result<T, SysIrqError> {
+    fn interrupted() -> Self {
+        Err(SysIrqError::Interrupted)
+    }
+}
+
+impl<T: 'static> Interruptible for Result<T, SysPeriodicError> {
+    fn interrupted() -> Self {
+        Err(SysPeriodicError::Interrupted)
+    }
+}
+
+/// Blocks the process in a syscall with the return type `R`. Returns
+/// the wait which the timeout and the wait queues refer to.
+pub fn block<R: Interruptible>(process: &mut Process) -> u64 {
+    process.set_waiting_on_syscall::<R>();
+    process.current_wait()
+}
+
+/// Resumes the process with `value` at the deadline unless it was
+/// resumed before
+pub fn time_out_at<R: Interruptible + Send>(
+    deadline: u64,
+    process: &ProcessRef,
+    wait: u64,
+    value: R,
+) {
+    if deadline == NO_DEADLINE {
+        return;
+    }
+    let process = Arc::downgrade(process);
+    timer::add_timer(deadline, move || {
+        time_out(&process, wait, value);
+    });
+}
+
+/// The timer of a wait fires even if the process was resumed or has
+/// blocked in another syscall in the meantime
+fn time_out<R: 'static>(process: &Weak<Mutex<Process>>, wait: u64, value: R) -> bool {
+    // The process might have been killed in the meantime
+    let Some(process) = process.upgrade() else {
+        return false;
+    };
+    let mut process = process.lock();
+    if !process.is_still_waiting::<R>(wait) {
+        return false;
+    }
+    process.resume_on_syscall(value);
+    true
+}
+
+#[derive(Clone)]
+struct Waiter {
+    pid: Pid,
+    wait: u64,
+    process: Weak<Mutex<Process>>,
+}
+
+#[derive(Clone)]
+pub struct WaitQueue<R> {
+    waiters: VecDeque<Waiter>,
+    return_type: PhantomData<fn(R)>,
+}
+
+impl<R: Interruptible + Clone> WaitQueue<R> {
+    pub const fn new() -> Self {
+        Self {
+            waiters: VecDeque::new(),
+            return_type: PhantomData,
+        }
+    }
+
+    /// Blocks the process until it is woken up. Must not be called with
+    /// the process locked.
+    pub fn wait(&mut self, process: &ProcessRef) {
+        self.enqueue(process);
+    }
+
+    /// Like wait, but the process gets `timed_out` once the deadline
+    /// passed, see common::deadline
+    pub fn wait_until(&mut self, process: &ProcessRef, deadline: u64, timed_out: R)
+    where
+        R: Send,
+    {
+        let wait = self.enqueue(process);
+        time_out_at(deadline, process, wait, timed_out);
+    }
+
+    fn enqueue(&mut self, process: &ProcessRef) -> u64 {
+        let (pid, wait) = process.with_lock(|mut p| (p.get_pid(), block::<R>(&mut p)));
+        // A process blocks in one syscall at a time, older entries are stale
+        self.waiters
+            .retain(|waiter| waiter.pid != pid && waiter.process.strong_count() > 0);
+        self.waiters.push_back(Waiter {
+            pid,
+            wait,
+            process: Arc::downgrade(process),
+        });
+        wait
+    }
+
+    /// Whether nobody was queued since the last wake up. Unlike
+    /// has_waiters it doesn't lock the processes.
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    /// Whether a wake up would resume any process
+    pub fn has_waiters(&self) -> bool {
+        self.waiters.iter().any(|waiter| {
+            waiter
+                .process
+                .upgrade()
+                .is_some_and(|process| process.lock().is_still_waiting::<R>(waiter.wait))
+        })
+    }
+
+    /// Resumes the process which waits the longest. Returns false if
+    /// nobody was waiting.
+    pub fn wake_one(&mut self, value: R) -> bool {
+        while let Some(waiter) = self.waiters.pop_front() {
+            if Self::resume(&waiter, value.clone()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns false if nobody was waiting
+    pub fn wake_all(&mut self, value: R) -> bool {
+        let mut woken = false;
+        for waiter in core::mem::take(&mut self.waiters) {
+            woken |= Self::resume(&waiter, value.clone());
+        }
+        woken
+    }
+
+    /// Resumes the given process if it waits in this queue
+    pub fn wake(&mut self, pid: Pid, value: R) -> bool {
+        let mut woken = false;
+        self.waiters.retain(|waiter| {
+            if waiter.pid != pid {
+                return true;
+            }
+            woken |= Self::resume(waiter, value.clone());
+            false
+        });
+        woken
+    }
+
+    fn resume(waiter: &Waiter, value: R) -> bool {
+        let Some(process) = waiter.process.upgrade() else {
+            return false;
+        };
+        let mut process = process.lock();
+        if !process.is_still_waiting::<R>(waiter.wait) {
+            return false;
+        }
+        process.resume_on_syscall(value);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use common::mutex::Mutex;
+
+    use crate::{
+        autogenerated::userspace_programs::PROG1,
+        klibc::elf::ElfFile,
+        processes::{
+            environment::Environment,
+            process::{Process, ProcessState},
+            process_table::ProcessRef,
+        },
+    };
+
+    use super::{block, time_out, WaitQueue};
+
+    fn process() -> ProcessRef {
+        let elf = ElfFile::parse(PROG1).expect("Cannot parse elf file");
+        let process = Process::from_elf(&elf, "prog1", &[], Environment::new()).unwrap();
+        Arc::new(Mutex::new(process))
+    }
+
+    fn state(process: &ProcessRef) -> ProcessState {
+        process.lock().get_state()
+    }
+
+    #[test_case]
+    fn waiters_are_woken_in_order() {
+        let mut queue = WaitQueue::<()>::new();
+        let first = process();
+        let second = process();
+        queue.wait(&first);
+        queue.wait(&second);
+        assert_eq!(state(&first), ProcessState::Waiting);
+
+        assert!(queue.wake_one(()));
+        assert_eq!(state(&first), ProcessState::Runnable);
+        assert_eq!(state(&second), ProcessState::Waiting);
+        assert!(queue.wake_one(()));
+        assert_eq!(state(&second), ProcessState::Runnable);
+        assert!(!queue.wake_one(()));
+    }
+
+    #[test_case]
+    fn resumed_and_killed_waiters_are_skipped() {
+        let mut queue = WaitQueue::<()>::new();
+        let resumed = process();
+        let killed = process();
+        queue.wait(&resumed);
+        queue.wait(&killed);
+        assert!(queue.has_waiters());
+
+        resumed.lock().resume_on_syscall(());
+        killed.lock().cancel_wait();
+        assert!(!queue.has_waiters());
+        assert!(!queue.wake_all(()));
+    }
+
+    #[test_case]
+    fn only_the_given_process_is_woken() {
+        let mut queue = WaitQueue::<()>::new();
+        let first = process();
+        let second = process();
+        queue.wait(&first);
+        queue.wait(&second);
+
+        let pid = second.lock().get_pid();
+        assert!(queue.wake(pid, ()));
+        assert_eq!(state(&first), ProcessState::Waiting);
+        assert_eq!(state(&second), ProcessState::Runnable);
+        assert!(queue.wake_all(()));
+        assert_eq!(state(&first), ProcessState::Runnable);
+    }
+
+    #[test_case]
+    fn stale_timeouts_do_not_resume() {
+        let process = process();
+        let timer_of = |wait| {
+            let process = Arc::downgrade(&process);
+            move || time_out(&process, wait, ())
+        };
+
+        let first = timer_of(block::<()>(&mut process.lock()));
+        process.lock().resume_on_syscall(());
+        let second = timer_of(block::<()>(&mut process.lock()));
+
+        // The first sleep already ended, its timer must not end the second one
+        assert!(!first());
+        assert_eq!(state(&process), ProcessState::Waiting);
+        assert!(second());
+        assert_eq!(state(&process), ProcessState::Runnable);
+
+        let killed = timer_of(block::<()>(&mut process.lock()));
+        drop(process);
+        assert!(!killed());
+    }
+
+    #[test_case]
+    fn interrupted_waiters_are_not_woken_again() {
+        let mut queue = WaitQueue::<()>::new();
+        let process = process();
+        queue.wait(&process);
+
+        assert!(process.lock().interrupt_wait());
+        assert_eq!(state(&process), ProcessState::Runnable);
+        assert!(!process.lock().interrupt_wait());
+        assert!(!queue.wake_all(()));
+    }
+}
@@ -0,0 +1,40 @@
+//! Work which doesn't need to happen in interrupt context, e.g. reclaiming
+//! memory, is submitted to the work queue and executed by a kernel thread.
+//! Work items run one after another with interrupts disabled, the worker
+//! is only preempted between them.
+
+use alloc::{boxed::Box, collections::VecDeque};
+use common::{mutex::SpinLockIrqSave, runtime_initialized::RuntimeInitializedData};
+
+use crate::{
+    cpu::Cpu,
+    initcall,
+    processes::{kthread, process::Pid},
+};
+
+pub type Work = Box<dyn FnOnce() + Send>;
+
+static QUEUE: SpinLockIrqSave<VecDeque<Work>> = SpinLockIrqSave::new(VecDeque::new());
+
+static WORKER: RuntimeInitializedData<Pid> = RuntimeInitializedData::new();
+
+initcall!(Late, init);
+
+pub fn init() {
+    WORKER.initialize(kthread::spawn("kworker", worker));
+}
+
+/// Can be called from interrupt context
+pub fn submit(work: impl FnOnce() + Send + 'static) {
+    QUEUE.lock().push_back(Box::new(work));
+    kthread::unpark(*WORKER);
+}
+
+extern "C" fn worker() -> ! {
+    loop {
+        while let Some(work) = QUEUE.lock().pop_front() {
+            Cpu::without_interrupts(work);
+        }
+        kthread::park();
+    }
+}
@@ -1,6 +1,8 @@
 use crate::sbi;
 
 const EID: u64 = 0x10;
+const FID_GET_SPEC_VERSION: u64 = 0x0;
+const FID_PROBE_EXTENSION: u64 = 0x3;
 
 pub struct SbiSpecVersion {
     pub minor: u32,
@@ -8,9 +10,15 @@ pub struct SbiSpecVersion {
 }
 
 pub fn sbi_get_spec_version() -> SbiSpecVersion {
-    let result = sbi::sbi_call(EID, 0x0);
+    let result = sbi::sbi_call(EID, FID_GET_SPEC_VERSION);
     SbiSpecVersion {
         minor: result.value as u32 & 0xffffff,
         major: (result.value >> 24) as u32,
     }
 }
+
+/// Whether the firmware implements the extension with the given id
+pub fn sbi_probe_extension(extension_id: u64) -> bool {
+    let result = sbi::sbi_call_1(EID, FID_PROBE_EXTENSION, extension_id);
+    !result.is_error() && result.value != 0
+}
@@ -0,0 +1,43 @@
+use crate::sbi::{self, sbi_call::SbiRet};
+
+use super::base_extension;
+
+const EID: u64 = 0x4442434E;
+const FID_CONSOLE_WRITE: u64 = 0x0;
+const FID_CONSOLE_READ: u64 = 0x1;
+const FID_CONSOLE_WRITE_BYTE: u64 = 0x2;
+
+/// The extension is optional, older firmware doesn't have it
+pub fn is_available() -> bool {
+    base_extension::sbi_probe_extension(EID)
+}
+
+/// Returns the number of written bytes in the value, which might be
+/// less than requested. The firmware accesses the buffer by its
+/// physical address, therefore it must be identity mapped.
+pub fn write(bytes: &[u8]) -> SbiRet {
+    sbi::sbi_call_3(
+        EID,
+        FID_CONSOLE_WRITE,
+        bytes.len() as u64,
+        bytes.as_ptr() as u64,
+        0,
+    )
+}
+
+/// Doesn't block, returns the number of read bytes in the value. The
+/// buffer must be identity mapped like for [`write`].
+pub fn read(buffer: &mut [u8]) -> SbiRet {
+    sbi::sbi_call_3(
+        EID,
+        FID_CONSOLE_READ,
+        buffer.len() as u64,
+        buffer.as_mut_ptr() as u64,
+        0,
+    )
+}
+
+/// Blocks until the firmware wrote the byte
+pub fn write_byte(byte: u8) -> SbiRet {
+    sbi::sbi_call_1(EID, FID_CONSOLE_WRITE_BYTE, byte as u64)
+}
@@ -2,19 +2,18 @@ use crate::sbi::{self, sbi_call::SbiRet};
 
 const EID: u64 = 0x48534D;
 const FID_HART_START: u64 = 0x0;
+const FID_HART_STOP: u64 = 0x1;
 const FID_GET_STATUS: u64 = 0x2;
 
-pub fn get_number_of_harts() -> usize {
-    let mut harts = 0;
+pub const HART_STATE_STOPPED: i64 = 0x1;
 
-    loop {
-        if sbi::sbi_call_1(EID, FID_GET_STATUS, harts as u64).is_error() {
-            break;
-        }
-        harts += 1;
-    }
+/// Stops the calling hart. Only returns on error.
+pub fn stop_hart() -> SbiRet {
+    sbi::sbi_call(EID, FID_HART_STOP)
+}
 
-    harts
+pub fn get_hart_status(hart_id: usize) -> SbiRet {
+    sbi::sbi_call_1(EID, FID_GET_STATUS, hart_id as u64)
 }
 
 pub fn start_hart(hart_id: usize, start_addr: usize, opaque: usize) -> SbiRet {
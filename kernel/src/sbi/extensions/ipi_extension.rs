@@ -0,0 +1,10 @@
+use crate::sbi::{self, sbi_call::SbiRet};
+
+const EID: u64 = 0x735049;
+const FID_SEND_IPI: u64 = 0x0;
+
+/// Raises a supervisor software interrupt on all harts
+/// in hart_mask (relative to hart_mask_base).
+pub fn send_ipi(hart_mask: u64, hart_mask_base: u64) -> SbiRet {
+    sbi::sbi_call_2(EID, FID_SEND_IPI, hart_mask, hart_mask_base)
+}
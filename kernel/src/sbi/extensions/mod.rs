@@ -1,3 +1,7 @@
 pub mod base_extension;
+pub mod debug_console_extension;
 pub mod hart_state_extension;
+pub mod ipi_extension;
+pub mod rfence_extension;
+pub mod system_reset_extension;
 pub mod timer_extension;
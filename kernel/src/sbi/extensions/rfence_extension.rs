@@ -0,0 +1,13 @@
+use crate::sbi::{self, sbi_call::SbiRet};
+
+const EID: u64 = 0x52464E43;
+const FID_REMOTE_FENCE_I: u64 = 0x0;
+
+/// A hart_mask_base of all ones selects every hart
+pub const ALL_HARTS: u64 = u64::MAX;
+
+/// Executes fence.i on all harts in hart_mask (relative to
+/// hart_mask_base), e.g. after instructions were changed.
+pub fn remote_fence_i(hart_mask: u64, hart_mask_base: u64) -> SbiRet {
+    sbi::sbi_call_2(EID, FID_REMOTE_FENCE_I, hart_mask, hart_mask_base)
+}
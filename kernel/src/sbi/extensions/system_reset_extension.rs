@@ -0,0 +1,31 @@
+use crate::sbi::{self, sbi_call::SbiRet};
+
+const EID: u64 = 0x53525354;
+const FID_SYSTEM_RESET: u64 = 0x0;
+
+#[repr(u64)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum ResetType {
+    Shutdown = 0x0,
+    ColdReboot = 0x1,
+    WarmReboot = 0x2,
+}
+
+#[repr(u64)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum ResetReason {
+    NoReason = 0x0,
+    SystemFailure = 0x1,
+}
+
+/// Only returns if the reset failed
+pub fn system_reset(reset_type: ResetType, reset_reason: ResetReason) -> SbiRet {
+    sbi::sbi_call_2(
+        EID,
+        FID_SYSTEM_RESET,
+        reset_type as u64,
+        reset_reason as u64,
+    )
+}
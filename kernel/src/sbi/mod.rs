@@ -1,4 +1,4 @@
 pub mod extensions;
 mod sbi_call;
 
-use sbi_call::{sbi_call, sbi_call_1, sbi_call_3};
+use sbi_call::{sbi_call, sbi_call_1, sbi_call_2, sbi_call_3};
@@ -76,6 +76,16 @@ pub fn sbi_call_1(eid: u64, fid: u64, arg0: u64) -> SbiRet {
     }
 }
 
+pub fn sbi_call_2(eid: u64, fid: u64, arg0: u64, arg1: u64) -> SbiRet {
+    let mut error: i64;
+    let mut value: i64;
+
+    unsafe {
+        asm!("ecall", in("a7") eid, in("a6") fid, in("a0") arg0, in("a1") arg1, lateout("a0") error, lateout("a1") value);
+        SbiRet::new(error, value)
+    }
+}
+
 pub fn sbi_call_3(eid: u64, fid: u64, arg0: u64, arg1: u64, arg2: u64) -> SbiRet {
     let mut error: i64;
     let mut value: i64;
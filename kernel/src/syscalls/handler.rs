@@ -1,25 +1,102 @@
 use common::{
-    errors::{SysExecuteError, SysSocketError, SysWaitError, ValidationError},
-    net::UDPDescriptor,
-    pointer::Pointer,
-    syscalls::{kernel::KernelSyscalls, syscall_argument::SyscallArgument, SyscallStatus},
-    unwrap_or_return,
+    accounting::{IoStatistics, ProcessInfo},
+    constructable::Constructable,
+    credentials::{Gid, Uid},
+    deadline::NO_DEADLINE,
+    errors::{
+        SysBrkError, SysEnvironmentError, SysExecuteError, SysFileError, SysHartError,
+        SysInputError, SysIrqError, SysKillError, SysMapPhysicalError, SysPageTablesError,
+        SysPeriodicError, SysPermissionError, SysPollError, SysProcessInfoError, SysProfilerError,
+        SysPtyError, SysResourceLimitError, SysResumeError, SysRouteError, SysSocketError,
+        SysSpawnError, SysSuspendError, SysSymbolizeError, SysWaitError, ValidationError,
+    },
+    fs::{FileDescriptor, FileKind, FileStat},
+    mmap::MapFlags,
+    net::{
+        CapturedFrame, RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor,
+    },
+    perf::PerfCounter,
+    poll::{PollKind, PollSource, POLL_FOREVER},
+    resource_limits::{Resource, ResourceLimit},
+    spawn::{DescriptorMapping, SpawnOptions, INHERIT_TERMINAL},
+    symbols::SymbolInfo,
+    syscalls::{
+        batch::SyscallRequest,
+        kernel::KernelSyscalls,
+        numbers,
+        syscall_argument::{SyscallArgument, UserspaceSlice},
+        SyscallStatus,
+    },
+    tty::{InputMode, KeyEvent, PtyDescriptor},
+    wait::{ChildExit, ExitStatus, WaitOptions},
 };
 
 use crate::{
-    autogenerated::userspace_programs::PROGRAMS,
     cpu::Cpu,
     debug,
-    io::stdin_buf::STDIN_BUFFER,
-    net::{udp::UdpHeader, ARP_CACHE, OPEN_UDP_SOCKETS},
+    debugging::{demangle::demangle, profiler},
+    fs::{
+        self, block_cache, devfs,
+        vfs::{self, InodeKind},
+    },
+    interrupts,
+    io::{
+        pty::Pty,
+        stdin_buf::{self, StdinBuffer},
+    },
+    klibc::{counters, elf::ElfFile, util::is_aligned},
+    memory::{
+        self,
+        page_tables::{XWRMode, PHYSICAL_ADDRESS_LIMIT},
+        vma::MappedFile,
+        PAGE_SIZE,
+    },
+    power::{self, InitExit},
     print, println,
-    processes::{process::Pid, process_table::ProcessRef},
+    processes::{
+        hotplug, poll,
+        process::{Pid, ProcessState, INIT_PID, POWERSAVE_PID},
+        process_table::{self, ProcessRef},
+        programs,
+        scheduler::StartOptions,
+        timer, wait_queue,
+    },
 };
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+#[cfg(feature = "net")]
+use crate::net::{capture, routing::Route, OPEN_TCP_SOCKETS, OPEN_UDP_SOCKETS, ROUTING_TABLE};
+#[cfg(feature = "net")]
+use core::net::Ipv4Addr;
 
 use super::validator::{UserspaceArgument, Validatable};
 
+// Blocking syscalls write their return value when the process is resumed.
+// That only works for syscalls which are issued directly with ecall.
+// Syscalls which are executed again once woken end the batch instead,
+// see sys_batch.
+const NOT_BATCHABLE: [usize; 9] = [
+    numbers::sys_batch,
+    numbers::sys_irq_wait,
+    numbers::sys_poll,
+    numbers::sys_read_input_wait,
+    numbers::sys_read_key_event,
+    numbers::sys_sleep,
+    numbers::sys_wait,
+    numbers::sys_wait_child,
+    numbers::sys_write_tcp_stream,
+];
+
 pub(super) struct SyscallHandler {
     process_exit: bool,
+    // The ecall is executed again when the process is resumed
+    restart: bool,
+    // Kept alive until the return value is written into its memory
+    exited_process: Option<ProcessRef>,
     current_process: ProcessRef,
     current_pid: Pid,
 }
@@ -30,6 +107,8 @@ impl SyscallHandler {
         let current_pid = current_process.lock().get_pid();
         Self {
             process_exit: false,
+            restart: false,
+            exited_process: None,
             current_process,
             current_pid,
         }
@@ -38,47 +117,254 @@ impl SyscallHandler {
     pub fn current_process(&self) -> &ProcessRef {
         &self.current_process
     }
+
+    fn ensure_root(&self) -> Result<(), SysPermissionError> {
+        if self.current_process.lock().credentials().is_root() {
+            Ok(())
+        } else {
+            Err(SysPermissionError::PermissionDenied)
+        }
+    }
+
+    /// Only init is allowed to change credentials, e.g. to drop root
+    /// before it starts processes on behalf of a user.
+    fn ensure_init(&self) -> Result<(), SysPermissionError> {
+        if self.current_pid == INIT_PID {
+            Ok(())
+        } else {
+            Err(SysPermissionError::PermissionDenied)
+        }
+    }
+
+    /// Counts the I/O of the current process
+    fn account(&self, update: impl FnOnce(&mut IoStatistics)) {
+        update(self.current_process.lock().io_statistics_mut());
+    }
+
+    /// A blocked process executes the syscall again when a child exited,
+    /// the None which is returned meanwhile never reaches it
+    fn wait_for_child(
+        &mut self,
+        pid: Pid,
+        options: WaitOptions,
+    ) -> Result<Option<ChildExit>, SysWaitError> {
+        let parent = self.current_pid;
+        let exit = process_table::THE.update(|pt| pt.wait_for_child(parent, pid, options))?;
+        self.restart = exit.is_none() && options != WaitOptions::NoHang;
+        Ok(exit)
+    }
+
+    /// Whether the current process may kill, stop or resume the process.
+    /// None if it doesn't exist.
+    fn may_control(&self, pid: Pid) -> Option<bool> {
+        let (target, kernel_thread) =
+            process_table::THE.read().get_process(pid).map(|process| {
+                let process = process.lock();
+                (process.credentials(), process.is_kernel_thread())
+            })?;
+        let credentials = self.current_process.lock().credentials();
+        Some(!kernel_thread && credentials.may_kill(&target))
+    }
+
+    /// Relative paths start at the working directory of the process
+    fn absolute_path(&self, path: &str) -> String {
+        vfs::absolute_path(self.current_process.lock().working_directory(), path)
+    }
+
+    /// Names without a slash are searched in the program sources
+    fn absolute_program_path(&self, name: &str) -> String {
+        if name.contains('/') {
+            self.absolute_path(name)
+        } else {
+            name.into()
+        }
+    }
+
+    fn is_ready(&self, source: &PollSource) -> Result<bool, SysPollError> {
+        let kind = PollKind::try_from(source.kind).map_err(|_| ValidationError::InvalidValue)?;
+        let ready = match kind {
+            PollKind::Stdin => self.with_stdin(|stdin| stdin.has_data()),
+            #[cfg(feature = "net")]
+            PollKind::UdpSocket => {
+                let descriptor = UDPDescriptor::new(source.descriptor);
+                let socket = self
+                    .current_process
+                    .with_lock(|mut p| p.get_shared_udp_socket(descriptor).cloned())
+                    .ok_or(SysPollError::InvalidDescriptor)?;
+                let ready = socket.lock().has_data();
+                ready
+            }
+            PollKind::PtyMaster => {
+                let descriptor = PtyDescriptor::new(source.descriptor);
+                let pty = self
+                    .current_process
+                    .with_lock(|p| p.get_pty(descriptor).cloned())
+                    .ok_or(SysPollError::InvalidDescriptor)?;
+                let ready = pty.lock().is_readable();
+                ready
+            }
+            #[cfg(feature = "net")]
+            PollKind::RawSocket => {
+                let descriptor = RawDescriptor::new(source.descriptor);
+                let socket = self
+                    .current_process
+                    .with_lock(|p| p.get_raw_socket(descriptor).cloned())
+                    .ok_or(SysPollError::InvalidDescriptor)?;
+                let ready = socket.lock().has_data();
+                ready
+            }
+            #[cfg(feature = "net")]
+            PollKind::TcpListener => {
+                let descriptor = TcpListenerDescriptor::new(source.descriptor);
+                let listener = self
+                    .current_process
+                    .with_lock(|p| p.get_tcp_listener(descriptor).cloned())
+                    .ok_or(SysPollError::InvalidDescriptor)?;
+                let ready = listener.lock().has_connection();
+                ready
+            }
+            #[cfg(feature = "net")]
+            PollKind::TcpStream => {
+                let descriptor = TcpStreamDescriptor::new(source.descriptor);
+                let stream = self
+                    .current_process
+                    .with_lock(|p| p.get_tcp_stream(descriptor).cloned())
+                    .ok_or(SysPollError::InvalidDescriptor)?;
+                let ready = stream.lock().is_readable();
+                ready
+            }
+            // Sockets can't be opened without networking
+            #[cfg(not(feature = "net"))]
+            PollKind::UdpSocket
+            | PollKind::RawSocket
+            | PollKind::TcpListener
+            | PollKind::TcpStream => {
+                return Err(SysPollError::InvalidDescriptor);
+            }
+        };
+        Ok(ready)
+    }
+
+    /// Runs `f` with the input of the current process. That is either
+    /// the console or the slave side of its pty.
+    fn with_stdin<R>(&self, f: impl FnOnce(&mut StdinBuffer) -> R) -> R {
+        let terminal = self.current_process.lock().terminal();
+        stdin_buf::with_input(terminal.as_ref(), f)
+    }
 }
 
 impl KernelSyscalls for SyscallHandler {
     type ArgWrapper<T: SyscallArgument> = UserspaceArgument<T>;
 
     fn sys_print_programs(&mut self) {
-        for (name, _) in PROGRAMS {
+        for name in programs::names() {
             print!("{name} ");
         }
         println!("");
     }
-    fn sys_panic(&mut self) {
+    fn sys_shutdown(&mut self) -> Result<(), SysPermissionError> {
+        self.ensure_root()?;
+        power::shutdown();
+    }
+    fn sys_reboot(&mut self) -> Result<(), SysPermissionError> {
+        self.ensure_root()?;
+        power::reboot();
+    }
+    fn sys_stop_hart(&mut self, hart_id: UserspaceArgument<usize>) -> Result<(), SysHartError> {
+        self.ensure_root()?;
+        hotplug::stop_hart(*hart_id)
+    }
+    fn sys_start_hart(&mut self, hart_id: UserspaceArgument<usize>) -> Result<(), SysHartError> {
+        self.ensure_root()?;
+        hotplug::start_hart(*hart_id)
+    }
+    fn sys_panic(&mut self) -> Result<(), SysPermissionError> {
+        self.ensure_root()?;
         panic!("Userspace triggered kernel panic");
     }
-    fn sys_write(&mut self, s: UserspaceArgument<&str>) -> Result<(), ValidationError> {
-        let s = s.validate(self)?;
-        print!("{s}");
+    fn sys_write(&mut self, s: &str) -> Result<(), ValidationError> {
+        let terminal = self.current_process.lock().terminal();
+        match terminal {
+            Some(pty) => {
+                let mut pty = pty.lock();
+                if !pty.write_from_slave(s.as_bytes()) {
+                    // The whole write is done again once the master read
+                    pty.wait_for_space(&self.current_process);
+                    self.restart = true;
+                    return Ok(());
+                }
+            }
+            None => print!("{s}"),
+        }
+        self.account(|io| io.bytes_written += s.len() as u64);
+        Ok(())
+    }
+
+    fn sys_set_input_mode(
+        &mut self,
+        mode: UserspaceArgument<InputMode>,
+    ) -> Result<(), ValidationError> {
+        let mode = mode.validate(self)?;
+        self.with_stdin(|stdin| stdin.set_mode(mode));
         Ok(())
     }
 
     fn sys_read_input(&mut self) -> Option<u8> {
-        let mut stdin = STDIN_BUFFER.lock();
-        stdin.pop()
+        self.with_stdin(|stdin| stdin.pop())
     }
-    fn sys_read_input_wait(&mut self) -> u8 {
-        let input = STDIN_BUFFER.lock().pop();
-        if let Some(input) = input {
-            input
-        } else {
-            STDIN_BUFFER.lock().register_wakeup(self.current_pid);
-            self.current_process.lock().set_waiting_on_syscall::<u8>();
-            0
-        }
+    fn sys_read_input_wait(
+        &mut self,
+        deadline: UserspaceArgument<u64>,
+    ) -> Result<u8, SysInputError> {
+        let terminal = self.current_process.lock().terminal();
+        stdin_buf::with_input(terminal.as_ref(), |stdin| {
+            if let Some(input) = stdin.pop() {
+                return Ok(input);
+            }
+            if stdin.is_hung_up() {
+                return Err(SysInputError::HungUp);
+            }
+            if *deadline <= timer::get_current_clocks() {
+                return Err(SysInputError::TimedOut);
+            }
+            stdin.wait_for_input(&self.current_process, *deadline);
+            // Overwritten when the process is resumed
+            Ok(0)
+        })
+    }
+
+    fn sys_read_key_event(
+        &mut self,
+        deadline: UserspaceArgument<u64>,
+    ) -> Result<KeyEvent, SysInputError> {
+        let terminal = self.current_process.lock().terminal();
+        stdin_buf::with_input(terminal.as_ref(), |stdin| {
+            if let Some(event) = stdin.pop_key_event() {
+                return Ok(event);
+            }
+            if stdin.is_hung_up() {
+                return Err(SysInputError::HungUp);
+            }
+            if *deadline <= timer::get_current_clocks() {
+                return Err(SysInputError::TimedOut);
+            }
+            stdin.wait_for_key_event(&self.current_process, *deadline);
+            // Overwritten when the process is resumed
+            Ok(KeyEvent::Enter)
+        })
     }
 
     fn sys_exit(&mut self, status: UserspaceArgument<isize>) {
+        if self.current_pid == INIT_PID {
+            power::init_exited(InitExit::Status(*status));
+        }
         // We don't want to overwrite the next process trap frame
         self.process_exit = true;
         Cpu::with_scheduler(|s| {
-            s.kill_current_process();
-            self.current_process = s.get_current_process().clone();
+            s.exit_current_process(ExitStatus::Exited(*status));
+            let exited_process =
+                core::mem::replace(&mut self.current_process, s.get_current_process().clone());
+            self.exited_process = Some(exited_process);
         });
 
         self.current_pid = self.current_process.lock().get_pid();
@@ -86,30 +372,785 @@ impl KernelSyscalls for SyscallHandler {
         debug!("Exit process with status: {}\n", *status);
     }
 
-    fn sys_execute<'a>(
+    fn sys_execute(&mut self, name: &str, args: Vec<&str>) -> Result<u64, SysExecuteError> {
+        // Children inherit the terminal, the resource limits, the credentials,
+        // the environment and the working directory
+        let inheritance = self.current_process.lock().inheritance();
+        let name = self.absolute_program_path(name);
+        let pid = Cpu::with_scheduler(|s| {
+            s.start_program(&name, &args, inheritance, StartOptions::default())
+        })?;
+        Ok(pid)
+    }
+
+    fn sys_open_pty(&mut self) -> Result<PtyDescriptor, SysPtyError> {
+        let pty = Pty::open();
+        // The handle must not be dropped while the process is locked
+        let descriptor = self.current_process.lock().put_new_pty(pty.clone());
+        descriptor.ok_or(SysPtyError::TooManyDescriptors)
+    }
+
+    fn sys_execute_on_pty(
         &mut self,
-        name: UserspaceArgument<&str>,
-        args: UserspaceArgument<&'a [&'a str]>,
+        name: &str,
+        args: Vec<&str>,
+        pty: UserspaceArgument<PtyDescriptor>,
     ) -> Result<u64, SysExecuteError> {
-        let name = name.validate(self)?;
-        let args = args.validate(self)?;
+        let pty = pty
+            .validate(self)
+            .map_err(|_| SysExecuteError::InvalidTerminal)?;
 
-        let pid = Cpu::with_scheduler(|s| s.start_program(name, &args))?;
+        let mut inheritance = self.current_process.lock().inheritance();
+        inheritance.terminal = Some(pty.slave());
+        let name = self.absolute_program_path(name);
+        let pid = Cpu::with_scheduler(|s| {
+            s.start_program(&name, &args, inheritance, StartOptions::default())
+        })?;
         Ok(pid)
     }
 
+    fn sys_spawn<'a>(
+        &mut self,
+        program: &'a str,
+        args: Vec<&'a str>,
+        descriptors: &'a [DescriptorMapping],
+        working_directory: &'a str,
+        options: UserspaceArgument<SpawnOptions>,
+    ) -> Result<u64, SysSpawnError> {
+        let options = options.validate(self)?;
+
+        // Everything is checked before the program is started, it either
+        // starts with the complete setup or not at all
+        let mut inheritance = self.current_process.lock().inheritance();
+        if options.terminal != INHERIT_TERMINAL {
+            let pty = self
+                .current_process
+                .lock()
+                .get_pty(PtyDescriptor::new(options.terminal))
+                .cloned()
+                .ok_or(SysSpawnError::InvalidDescriptor)?;
+            inheritance.terminal = Some(pty.slave());
+        }
+        if !working_directory.is_empty() {
+            let path = vfs::normalize(&self.absolute_path(working_directory))
+                .map_err(|_| SysSpawnError::InvalidWorkingDirectory)?;
+            match vfs::resolve(&path) {
+                Ok(inode) if inode.kind() == InodeKind::Directory => {}
+                _ => return Err(SysSpawnError::InvalidWorkingDirectory),
+            }
+            inheritance.working_directory = path;
+        }
+        for mapping in descriptors {
+            let descriptor = self
+                .current_process
+                .lock()
+                .get_descriptor(mapping.parent)
+                .ok_or(SysSpawnError::InvalidDescriptor)?;
+            if inheritance
+                .descriptors
+                .iter()
+                .any(|(child, _)| *child == mapping.child)
+            {
+                return Err(SysSpawnError::InvalidDescriptor);
+            }
+            inheritance.descriptors.push((mapping.child, descriptor));
+        }
+        if !inheritance
+            .resource_limits
+            .allows(Resource::OpenDescriptors, descriptors.len() as u64)
+        {
+            return Err(SysSpawnError::TooManyDescriptors);
+        }
+
+        let program = self.absolute_program_path(program);
+        let options = StartOptions {
+            suspended: options.suspended != 0,
+            detached: options.detached != 0,
+        };
+        let pid = Cpu::with_scheduler(|s| s.start_program(&program, &args, inheritance, options))?;
+        Ok(pid)
+    }
+
+    fn sys_read_pty(
+        &mut self,
+        pty: UserspaceArgument<PtyDescriptor>,
+        buffer: &mut [u8],
+    ) -> Result<usize, SysPtyError> {
+        let count = pty.validate(self)?.lock().read_from_master(buffer);
+        self.account(|io| io.bytes_read += count as u64);
+        Ok(count)
+    }
+
+    fn sys_read_pty_wait(
+        &mut self,
+        pty: UserspaceArgument<PtyDescriptor>,
+        buffer: &mut [u8],
+        deadline: UserspaceArgument<u64>,
+    ) -> Result<usize, SysPtyError> {
+        let master = pty.validate(self)?;
+        let mut pty = master.lock();
+        let count = pty.read_from_master(buffer);
+        if count > 0 || pty.is_hung_up() {
+            self.account(|io| io.bytes_read += count as u64);
+            return Ok(count);
+        }
+        if *deadline <= timer::get_current_clocks() {
+            return Err(SysPtyError::TimedOut);
+        }
+        // The read is done again once there is output or at the deadline
+        pty.wait_for_output(&self.current_process, *deadline);
+        self.restart = true;
+        Ok(0)
+    }
+
+    fn sys_close_pty(&mut self, pty: UserspaceArgument<PtyDescriptor>) -> Result<(), SysPtyError> {
+        let master = self.current_process.lock().close_pty(*pty);
+        // Dropped here, after the process was unlocked
+        master.map(drop).ok_or(SysPtyError::InvalidDescriptor)
+    }
+
+    fn sys_write_pty(
+        &mut self,
+        pty: UserspaceArgument<PtyDescriptor>,
+        buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysPtyError> {
+        pty.validate(self)?.lock().write_from_master(&buffer);
+        self.account(|io| io.bytes_written += buffer.len() as u64);
+        Ok(buffer.len())
+    }
+
     fn sys_wait(&mut self, pid: UserspaceArgument<u64>) -> Result<(), SysWaitError> {
-        if Cpu::with_scheduler(|s| s.let_current_process_wait_for(*pid)) {
-            Ok(())
+        self.wait_for_child(*pid, WaitOptions::Block).map(|_| ())
+    }
+
+    fn sys_wait_child(
+        &mut self,
+        pid: UserspaceArgument<u64>,
+        options: UserspaceArgument<WaitOptions>,
+    ) -> Result<Option<ChildExit>, SysWaitError> {
+        let options = options.validate(self)?;
+        self.wait_for_child(*pid, options)
+    }
+
+    fn sys_sleep(&mut self, milliseconds: UserspaceArgument<u64>) {
+        let deadline = timer::deadline_in(*milliseconds);
+        let wait = wait_queue::block::<()>(&mut self.current_process.lock());
+        wait_queue::time_out_at(deadline, &self.current_process, wait, ());
+    }
+
+    fn sys_getrlimit(
+        &mut self,
+        resource: UserspaceArgument<Resource>,
+    ) -> Result<ResourceLimit, ValidationError> {
+        let resource = resource.validate(self)?;
+        Ok(self.current_process.lock().resource_limits().get(resource))
+    }
+
+    fn sys_setrlimit(
+        &mut self,
+        resource: UserspaceArgument<Resource>,
+        limit: UserspaceArgument<ResourceLimit>,
+    ) -> Result<(), SysResourceLimitError> {
+        let resource = resource.validate(self)?;
+        let limit = limit.validate(self)?;
+        self.current_process
+            .lock()
+            .resource_limits_mut()
+            .set(resource, limit)
+    }
+
+    fn sys_getuid(&mut self) -> Uid {
+        self.current_process.lock().credentials().uid
+    }
+
+    fn sys_getgid(&mut self) -> Gid {
+        self.current_process.lock().credentials().gid
+    }
+
+    fn sys_setuid(&mut self, uid: UserspaceArgument<Uid>) -> Result<(), SysPermissionError> {
+        self.ensure_init()?;
+        self.current_process.lock().credentials_mut().uid = *uid;
+        Ok(())
+    }
+
+    fn sys_setgid(&mut self, gid: UserspaceArgument<Gid>) -> Result<(), SysPermissionError> {
+        self.ensure_init()?;
+        self.current_process.lock().credentials_mut().gid = *gid;
+        Ok(())
+    }
+
+    fn sys_kill(&mut self, pid: UserspaceArgument<u64>) -> Result<(), SysKillError> {
+        let pid = *pid;
+        if pid == POWERSAVE_PID {
+            return Err(SysKillError::InvalidPid);
+        }
+        if pid == INIT_PID {
+            return Err(SysKillError::PermissionDenied);
+        }
+        if pid == self.current_pid {
+            self.sys_exit(UserspaceArgument::new(-1));
+            return Ok(());
+        }
+
+        if !self.may_control(pid).ok_or(SysKillError::InvalidPid)? {
+            return Err(SysKillError::PermissionDenied);
+        }
+
+        process_table::THE.update(|pt| pt.kill(pid, ExitStatus::Killed));
+        Ok(())
+    }
+
+    fn sys_suspend(&mut self, pid: UserspaceArgument<u64>) -> Result<(), SysSuspendError> {
+        let pid = *pid;
+        if pid == POWERSAVE_PID {
+            return Err(SysSuspendError::InvalidPid);
+        }
+        // Nobody would reap the orphans anymore
+        if pid == INIT_PID {
+            return Err(SysSuspendError::PermissionDenied);
+        }
+        if !self.may_control(pid).ok_or(SysSuspendError::InvalidPid)? {
+            return Err(SysSuspendError::PermissionDenied);
+        }
+
+        // A process which stops itself is unscheduled when the syscall
+        // returns
+        if !process_table::THE.update(|pt| pt.suspend(pid)) {
+            return Err(SysSuspendError::AlreadyStopped);
+        }
+        Ok(())
+    }
+
+    fn sys_resume(&mut self, pid: UserspaceArgument<u64>) -> Result<(), SysResumeError> {
+        let pid = *pid;
+        if !self.may_control(pid).ok_or(SysResumeError::InvalidPid)? {
+            return Err(SysResumeError::PermissionDenied);
+        }
+
+        if !process_table::THE.update(|pt| pt.resume(pid)) {
+            return Err(SysResumeError::NotStopped);
+        }
+        Ok(())
+    }
+
+    fn sys_batch(&mut self, requests: &mut [SyscallRequest]) -> Result<usize, ValidationError> {
+        // The syscalls might write into the requests themselves
+        let (requests, len) = (requests.as_mut_ptr(), requests.len());
+
+        let mut executed = 0;
+        while executed < len && !self.process_exit {
+            // SAFETY: The requests were validated and are kept alive
+            // even if the process exits within the batch
+            let request = unsafe { requests.add(executed) };
+            let SyscallRequest { nr, arg, ret, .. } = unsafe { request.read() };
+            let mut status = if NOT_BATCHABLE.contains(&nr) {
+                SyscallStatus::NotAllowedInBatch
+            } else {
+                self.account(|io| io.syscalls += 1);
+                self.dispatch(nr, arg, ret)
+            };
+            // Executing the batch again would repeat the syscalls before
+            // this one, therefore the syscall doesn't wait at all
+            let would_block = core::mem::take(&mut self.restart);
+            if would_block {
+                self.current_process.with_lock(|mut p| {
+                    p.cancel_wait();
+                    p.set_state(ProcessState::Running);
+                });
+                status = SyscallStatus::NotAllowedInBatch;
+            }
+            unsafe { (*request).status = status as usize };
+            executed += 1;
+            if would_block {
+                break;
+            }
+        }
+
+        Ok(executed)
+    }
+
+    fn sys_setenv<'a>(&mut self, key: &'a str, value: &'a str) -> Result<(), SysEnvironmentError> {
+        self.current_process
+            .lock()
+            .environment_mut()
+            .set(key, value)
+    }
+
+    fn sys_unsetenv(&mut self, key: &str) -> Result<(), SysEnvironmentError> {
+        self.current_process.lock().environment_mut().unset(key);
+        Ok(())
+    }
+
+    /// Looks up the address in the symbol table of the program of the
+    /// current process. Used by userspace to print backtraces.
+    fn sys_symbolize(
+        &mut self,
+        address: UserspaceArgument<usize>,
+        name: &mut [u8],
+    ) -> Result<SymbolInfo, SysSymbolizeError> {
+        let program_name = self.current_process.lock().get_name().to_string();
+        let program = programs::find(&program_name).ok_or(SysSymbolizeError::NoSymbolTable)?;
+        let elf = ElfFile::parse(program.data()).expect("Programs must be valid ELF files");
+        let symbol_table = elf
+            .get_symbol_table()
+            .ok_or(SysSymbolizeError::NoSymbolTable)?;
+        let (symbol, offset) = symbol_table
+            .find_function(*address)
+            .ok_or(SysSymbolizeError::NoSymbol)?;
+
+        let symbol = demangle(symbol);
+        let name_length = symbol.len().min(name.len());
+        name[..name_length].copy_from_slice(&symbol.as_bytes()[..name_length]);
+        Ok(SymbolInfo {
+            name_length,
+            offset,
+        })
+    }
+
+    /// Marks the sources which can be read without blocking. If none is
+    /// ready the process waits until some source receives data or the
+    /// timeout expires and gets 0. It has to poll again afterwards.
+    fn sys_poll(
+        &mut self,
+        sources: &mut [PollSource],
+        timeout_milliseconds: UserspaceArgument<u64>,
+    ) -> Result<usize, SysPollError> {
+        let seen_events = poll::events();
+
+        let mut ready_sources = 0;
+        for source in sources.iter_mut() {
+            let ready = self.is_ready(source)?;
+            source.ready = ready as usize;
+            ready_sources += ready as usize;
+        }
+        if ready_sources > 0 || *timeout_milliseconds == 0 {
+            return Ok(ready_sources);
+        }
+
+        let deadline = if *timeout_milliseconds == POLL_FOREVER {
+            NO_DEADLINE
         } else {
-            Err(SysWaitError::InvalidPid)
+            timer::deadline_in(*timeout_milliseconds)
+        };
+        if !poll::wait(&self.current_process, seen_events, deadline) {
+            return Ok(0);
+        }
+        // Overwritten when the process is resumed
+        Ok(0)
+    }
+
+    fn sys_print_page_tables(
+        &mut self,
+        pid: UserspaceArgument<u64>,
+    ) -> Result<(), SysPageTablesError> {
+        self.ensure_root()?;
+        let pid = *pid;
+        let mappings = if pid == POWERSAVE_PID {
+            Cpu::maybe_kernel_page_tables()
+                .expect("Kernel page tables must exist after boot")
+                .walk()
+        } else {
+            process_table::THE
+                .read()
+                .get_process(pid)
+                .map(|process| process.lock().get_page_table().walk())
+                .ok_or(SysPageTablesError::InvalidPid)?
+        };
+        // Printed without holding the process lock
+        for mapping in mappings {
+            println!("{mapping}");
         }
+        Ok(())
+    }
+
+    fn sys_map_physical(
+        &mut self,
+        physical_address: UserspaceArgument<usize>,
+        length: UserspaceArgument<usize>,
+        flags: UserspaceArgument<MapFlags>,
+    ) -> Result<*mut u8, SysMapPhysicalError> {
+        self.ensure_root()?;
+        let flags = flags.validate(self)?;
+        let physical_address = *physical_address;
+        let length = *length;
+        if length == 0 || !is_aligned(physical_address, PAGE_SIZE) || !is_aligned(length, PAGE_SIZE)
+        {
+            return Err(SysMapPhysicalError::InvalidRange);
+        }
+        let physical_range = physical_address
+            ..physical_address
+                .checked_add(length)
+                .filter(|end| *end <= PHYSICAL_ADDRESS_LIMIT)
+                .ok_or(SysMapPhysicalError::InvalidRange)?;
+        if memory::is_kernel_owned(&physical_range) {
+            return Err(SysMapPhysicalError::KernelOwned);
+        }
+        let privileges = match flags {
+            MapFlags::ReadOnly => XWRMode::ReadOnly,
+            MapFlags::ReadWrite => XWRMode::ReadWrite,
+        };
+        Ok(self
+            .current_process
+            .lock()
+            .map_physical(physical_range, privileges))
+    }
+
+    fn sys_irq_register(
+        &mut self,
+        interrupt_id: UserspaceArgument<u32>,
+    ) -> Result<(), SysIrqError> {
+        self.ensure_root()?;
+        let interrupt = interrupts::userspace::register(*interrupt_id, self.current_pid)?;
+        self.current_process.lock().add_interrupt(interrupt);
+        Ok(())
+    }
+
+    fn sys_irq_wait(
+        &mut self,
+        interrupt_id: UserspaceArgument<u32>,
+        deadline: UserspaceArgument<u64>,
+    ) -> Result<u64, SysIrqError> {
+        interrupts::userspace::wait(
+            *interrupt_id,
+            *deadline,
+            self.current_pid,
+            &self.current_process,
+        )
+    }
+
+    fn sys_open_file(&mut self, path: &str) -> Result<FileDescriptor, SysFileError> {
+        let path = self.absolute_path(path);
+        let file = vfs::open(&path)?;
+        self.current_process
+            .lock()
+            .put_new_file(Arc::new(file))
+            .ok_or(SysFileError::TooManyDescriptors)
+    }
+
+    fn sys_read_file(
+        &mut self,
+        file: UserspaceArgument<FileDescriptor>,
+        buffer: &mut [u8],
+    ) -> Result<usize, SysFileError> {
+        let count = file.validate(self)?.read(buffer)?;
+        self.account(|io| io.bytes_read += count as u64);
+        Ok(count)
+    }
+
+    fn sys_write_file(
+        &mut self,
+        file: UserspaceArgument<FileDescriptor>,
+        buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysFileError> {
+        let count = file.validate(self)?.write(&buffer)?;
+        self.account(|io| io.bytes_written += count as u64);
+        Ok(count)
+    }
+
+    fn sys_close_file(
+        &mut self,
+        file: UserspaceArgument<FileDescriptor>,
+    ) -> Result<(), SysFileError> {
+        self.current_process
+            .lock()
+            .close_file(*file)
+            .map(|_| ())
+            .ok_or(SysFileError::InvalidDescriptor)
+    }
+
+    fn sys_read_directory(&mut self, path: &str, buffer: &mut [u8]) -> Result<usize, SysFileError> {
+        let path = self.absolute_path(path);
+        let mut listing = String::new();
+        for entry in vfs::read_dir(&path)? {
+            listing.push_str(&entry.name);
+            if entry.kind == InodeKind::Directory {
+                listing.push('/');
+            }
+            listing.push('\n');
+        }
+        let destination = buffer
+            .get_mut(..listing.len())
+            .ok_or(SysFileError::BufferTooSmall)?;
+        destination.copy_from_slice(listing.as_bytes());
+        Ok(listing.len())
+    }
+
+    fn sys_perf_read(
+        &mut self,
+        counter: UserspaceArgument<PerfCounter>,
+    ) -> Result<u64, ValidationError> {
+        let counter = counter.validate(self)?;
+        Ok(self.current_process.lock().perf_values().get(counter))
+    }
+
+    fn sys_profiler_start(
+        &mut self,
+        interval_milliseconds: UserspaceArgument<u64>,
+    ) -> Result<(), SysProfilerError> {
+        self.ensure_root()?;
+        if *interval_milliseconds == 0 {
+            return Err(SysProfilerError::InvalidInterval);
+        }
+        if !profiler::start(*interval_milliseconds) {
+            return Err(SysProfilerError::AlreadyRunning);
+        }
+        Ok(())
+    }
+
+    fn sys_profiler_stop(&mut self) -> Result<(), SysProfilerError> {
+        self.ensure_root()?;
+        if !profiler::stop() {
+            return Err(SysProfilerError::NotRunning);
+        }
+        Ok(())
+    }
+
+    fn sys_attach_loop(&mut self, path: &str) -> Result<u64, SysFileError> {
+        self.ensure_root()?;
+        let path = self.absolute_path(path);
+        Ok(devfs::attach_loop_device(&path)?)
+    }
+
+    fn sys_sync(&mut self) -> Result<(), SysFileError> {
+        Ok(block_cache::sync()?)
+    }
+
+    fn sys_mount(
+        &mut self,
+        filesystem: &str,
+        source: &str,
+        target: &str,
+    ) -> Result<(), SysFileError> {
+        self.ensure_root()?;
+        let source = self.absolute_path(source);
+        let target = self.absolute_path(target);
+        Ok(fs::mount(filesystem, &source, &target)?)
+    }
+
+    fn sys_chdir(&mut self, path: &str) -> Result<(), SysFileError> {
+        let path = vfs::normalize(&self.absolute_path(path))?;
+        if vfs::resolve(&path)?.kind() != InodeKind::Directory {
+            return Err(SysFileError::NotADirectory);
+        }
+        self.current_process.lock().set_working_directory(path);
+        Ok(())
+    }
+
+    fn sys_getcwd(&mut self, buffer: &mut [u8]) -> Result<usize, SysFileError> {
+        let process = self.current_process.lock();
+        let directory = process.working_directory();
+        buffer
+            .get_mut(..directory.len())
+            .ok_or(SysFileError::BufferTooSmall)?
+            .copy_from_slice(directory.as_bytes());
+        Ok(directory.len())
+    }
+
+    fn sys_stat(&mut self, path: &str) -> Result<FileStat, SysFileError> {
+        let inode = vfs::resolve(&self.absolute_path(path))?;
+        let kind = match inode.kind() {
+            InodeKind::File => FileKind::File,
+            InodeKind::Directory => FileKind::Directory,
+            InodeKind::CharacterDevice => FileKind::CharacterDevice,
+            InodeKind::BlockDevice => FileKind::BlockDevice,
+        };
+        Ok(FileStat {
+            kind,
+            size: inode.size()? as u64,
+        })
+    }
+
+    fn sys_create_file(&mut self, path: &str) -> Result<FileDescriptor, SysFileError> {
+        let file = vfs::create_file(&self.absolute_path(path))?;
+        self.current_process
+            .lock()
+            .put_new_file(Arc::new(file))
+            .ok_or(SysFileError::TooManyDescriptors)
+    }
+
+    fn sys_mkdir(&mut self, path: &str) -> Result<(), SysFileError> {
+        vfs::create(&self.absolute_path(path), InodeKind::Directory)?;
+        Ok(())
+    }
+
+    fn sys_unlink(&mut self, path: &str) -> Result<(), SysFileError> {
+        Ok(vfs::unlink(&self.absolute_path(path))?)
+    }
+
+    fn sys_rename(&mut self, from: &str, to: &str) -> Result<(), SysFileError> {
+        Ok(vfs::rename(
+            &self.absolute_path(from),
+            &self.absolute_path(to),
+        )?)
+    }
+
+    fn sys_mmap_file(
+        &mut self,
+        file: UserspaceArgument<FileDescriptor>,
+        offset: UserspaceArgument<usize>,
+        length: UserspaceArgument<usize>,
+        flags: UserspaceArgument<MapFlags>,
+    ) -> Result<*mut u8, SysFileError> {
+        let flags = flags.validate(self)?;
+        let (offset, length) = (*offset, *length);
+        if length == 0 || !is_aligned(offset, PAGE_SIZE) {
+            return Err(SysFileError::InvalidRange);
+        }
+        let file = file.validate(self)?;
+        if file.inode().kind() != InodeKind::File {
+            return Err(SysFileError::NotAFile);
+        }
+        let privileges = match flags {
+            MapFlags::ReadOnly => XWRMode::ReadOnly,
+            MapFlags::ReadWrite => XWRMode::ReadWrite,
+        };
+        let ptr = self.current_process.lock().mmap_file(
+            MappedFile::new(&file, offset),
+            length,
+            privileges,
+        );
+        if ptr.is_null() {
+            return Err(SysFileError::LimitExceeded);
+        }
+        Ok(ptr)
+    }
+
+    fn sys_brk(&mut self, new_break: UserspaceArgument<usize>) -> Result<usize, SysBrkError> {
+        self.current_process.lock().set_program_break(*new_break)
+    }
+
+    fn sys_set_periodic(
+        &mut self,
+        period_milliseconds: UserspaceArgument<u64>,
+        budget_milliseconds: UserspaceArgument<u64>,
+    ) -> Result<(), SysPeriodicError> {
+        self.current_process
+            .lock()
+            .set_reservation(*period_milliseconds, *budget_milliseconds)
+    }
+
+    fn sys_wait_period(&mut self) -> Result<(), SysPeriodicError> {
+        let (deadline, wait) = self
+            .current_process
+            .with_lock(|mut p| {
+                let deadline = p.reservation_mut()?.deadline();
+                Some((
+                    deadline,
+                    wait_queue::block::<Result<(), SysPeriodicError>>(&mut p),
+                ))
+            })
+            .ok_or(SysPeriodicError::NotPeriodic)?;
+        wait_queue::time_out_at::<Result<(), SysPeriodicError>>(
+            deadline,
+            &self.current_process,
+            wait,
+            Ok(()),
+        );
+        // Overwritten when the process is resumed
+        Ok(())
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_route_add(
+        &mut self,
+        destination: UserspaceArgument<u32>,
+        prefix_length: UserspaceArgument<u8>,
+        gateway: UserspaceArgument<u32>,
+    ) -> Result<(), SysRouteError> {
+        self.ensure_root()?;
+        let gateway = (*gateway != 0).then(|| Ipv4Addr::from_bits(*gateway));
+        let route = Route::new(Ipv4Addr::from_bits(*destination), *prefix_length, gateway)?;
+        ROUTING_TABLE.write().add(route)
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_open_raw_socket(&mut self) -> Result<RawDescriptor, SysSocketError> {
+        self.ensure_root()?;
+        self.current_process
+            .lock()
+            .put_new_raw_socket(capture::open())
+            .ok_or(SysSocketError::TooManyDescriptors)
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_read_raw_socket(
+        &mut self,
+        descriptor: UserspaceArgument<RawDescriptor>,
+        buffer: &mut [u8],
+    ) -> Result<Option<CapturedFrame>, SysSocketError> {
+        let socket = descriptor.validate(self)?;
+        let frame = socket.lock().get_frame(buffer);
+        if let Some(frame) = &frame {
+            self.account(|io| io.bytes_received += frame.length as u64);
+        }
+        Ok(frame)
+    }
+
+    fn sys_process_info(
+        &mut self,
+        pid: UserspaceArgument<u64>,
+    ) -> Result<ProcessInfo, SysProcessInfoError> {
+        let process = match *pid {
+            0 => self.current_process.clone(),
+            pid => process_table::THE
+                .read()
+                .get_process(pid)
+                .cloned()
+                .ok_or(SysProcessInfoError::InvalidPid)?,
+        };
+        let process = process.lock();
+        Ok(ProcessInfo {
+            cpu_time_milliseconds: process.cpu_time_milliseconds(),
+            mapped_pages: process.mapped_pages(),
+            io: process.io_statistics(),
+        })
+    }
+
+    fn sys_read_counters(&mut self, buffer: &mut [u8]) -> Result<usize, ValidationError> {
+        let listing = counters::listing();
+        if let Some(destination) = buffer.get_mut(..listing.len()) {
+            destination.copy_from_slice(listing.as_bytes());
+        }
+        Ok(listing.len())
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_connect_udp_socket(
+        &mut self,
+        descriptor: UserspaceArgument<UDPDescriptor>,
+        ip: UserspaceArgument<u32>,
+        port: UserspaceArgument<u16>,
+    ) -> Result<(), SysSocketError> {
+        let (ip, port) = validate_peer(*ip, *port)?;
+        descriptor.validate(self)?.lock().connect(ip, port);
+        Ok(())
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_sendto(
+        &mut self,
+        descriptor: UserspaceArgument<UDPDescriptor>,
+        ip: UserspaceArgument<u32>,
+        port: UserspaceArgument<u16>,
+        buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysSocketError> {
+        let (ip, port) = validate_peer(*ip, *port)?;
+        let payload = memory::vma::pin(&self.current_process, buffer.address(), &buffer);
+        let length = payload.len();
+        descriptor.validate(self)?.with_lock(|mut socket| {
+            crate::net::send_datagram(socket.get_port(), ip, port, payload)?;
+            socket.count_sent();
+            Ok::<_, SysSocketError>(())
+        })?;
+        self.account(|io| io.bytes_sent += length as u64);
+        Ok(length)
     }
 
     fn sys_mmap_pages(&mut self, number_of_pages: UserspaceArgument<usize>) -> *mut u8 {
         self.current_process.lock().mmap_pages(*number_of_pages)
     }
 
+    #[cfg(feature = "net")]
     fn sys_open_udp_socket(
         &mut self,
         port: UserspaceArgument<u16>,
@@ -118,77 +1159,267 @@ impl KernelSyscalls for SyscallHandler {
             None => return Err(SysSocketError::PortAlreadyUsed),
             Some(socket) => socket,
         };
-        Ok(self.current_process.lock().put_new_udp_socket(socket))
+        self.current_process
+            .lock()
+            .put_new_udp_socket(socket)
+            .ok_or(SysSocketError::TooManyDescriptors)
     }
 
+    #[cfg(feature = "net")]
     fn sys_write_back_udp_socket(
         &mut self,
         descriptor: UserspaceArgument<UDPDescriptor>,
-        buffer: UserspaceArgument<&[u8]>,
+        buffer: UserspaceSlice<u8>,
     ) -> Result<usize, SysSocketError> {
-        let buffer = buffer.validate(self)?;
-
-        descriptor.validate(self)?.with_lock(|socket| {
-            let recv_ip = unwrap_or_return!(socket.get_from(), Err(SysSocketError::NoReceiveIPYet));
-            let recv_port = unwrap_or_return!(
-                socket.get_received_port(),
-                Err(SysSocketError::NoReceiveIPYet)
-            );
-
-            // Get mac address of receiver
-            // Since we already received a packet we should have it in the cache
-            let destination_mac = *ARP_CACHE
-                .lock()
-                .get(&recv_ip)
-                .expect("There must be a receiver mac already in the arp cache.");
-            let constructed_packet = UdpHeader::create_udp_packet(
-                recv_ip,
-                recv_port,
-                destination_mac,
-                socket.get_port(),
-                buffer,
-            );
-            crate::net::send_packet(constructed_packet);
-            Ok(buffer.len())
-        })
+        let payload = memory::vma::pin(&self.current_process, buffer.address(), &buffer);
+        let length = payload.len();
+
+        descriptor.validate(self)?.with_lock(|mut socket| {
+            let (ip, port) = socket.peer().ok_or(SysSocketError::NoReceiveIPYet)?;
+            crate::net::send_datagram(socket.get_port(), ip, port, payload)?;
+            socket.count_sent();
+            Ok::<_, SysSocketError>(())
+        })?;
+        self.account(|io| io.bytes_sent += length as u64);
+        Ok(length)
     }
 
+    #[cfg(feature = "net")]
     fn sys_read_udp_socket(
         &mut self,
         descriptor: UserspaceArgument<UDPDescriptor>,
-        buffer: UserspaceArgument<&mut [u8]>,
+        buffer: &mut [u8],
     ) -> Result<usize, SysSocketError> {
         // Process packets
         crate::net::receive_and_process_packets();
 
-        let buffer = buffer.validate(self)?;
+        let count = descriptor
+            .validate(self)?
+            .with_lock(|mut socket| socket.get_data(buffer));
+        self.account(|io| io.bytes_received += count as u64);
+        Ok(count)
+    }
 
+    #[cfg(feature = "net")]
+    fn sys_open_tcp_listener(
+        &mut self,
+        port: UserspaceArgument<u16>,
+    ) -> Result<TcpListenerDescriptor, SysSocketError> {
+        let listener = OPEN_TCP_SOCKETS
+            .lock()
+            .listen(*port)
+            .ok_or(SysSocketError::PortAlreadyUsed)?;
+        let descriptor = self.current_process.lock().put_new_tcp_listener(listener);
+        // A listener which didn't get a descriptor is dropped here, after
+        // the process was unlocked
+        descriptor.map_err(|_| SysSocketError::TooManyDescriptors)
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_accept_tcp_connection(
+        &mut self,
+        listener: UserspaceArgument<TcpListenerDescriptor>,
+    ) -> Result<Option<TcpStreamDescriptor>, SysSocketError> {
+        let Some(stream) = listener.validate(self)?.lock().accept() else {
+            return Ok(None);
+        };
+        let descriptor = self
+            .current_process
+            .lock()
+            .put_new_tcp_stream(Arc::new(stream));
         descriptor
-            .validate(self)?
-            .with_lock(|mut socket| Ok(socket.get_data(buffer)))
+            .map(Some)
+            .map_err(|_| SysSocketError::TooManyDescriptors)
     }
 
-    #[doc = r" Validate a pointer such that it is a valid userspace pointer"]
-    fn validate_and_translate_pointer<PTR: Pointer>(&self, ptr: PTR) -> Option<PTR> {
-        self.current_process.with_lock(|p| {
-            let pt = p.get_page_table();
-            if !pt.is_valid_userspace_ptr(ptr, true) {
-                return None;
-            }
-            let physical_address = unwrap_or_return!(
-                pt.translate_userspace_address_to_physical_address(ptr),
-                None
-            );
-            Some(physical_address)
-        })
+    #[cfg(feature = "net")]
+    fn sys_read_tcp_stream(
+        &mut self,
+        stream: UserspaceArgument<TcpStreamDescriptor>,
+        buffer: &mut [u8],
+    ) -> Result<usize, SysSocketError> {
+        let count = stream.validate(self)?.lock().read(buffer)?;
+        self.account(|io| io.bytes_received += count as u64);
+        Ok(count)
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_write_tcp_stream(
+        &mut self,
+        stream: UserspaceArgument<TcpStreamDescriptor>,
+        buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysSocketError> {
+        let stream = stream.validate(self)?;
+        let mut connection = stream.lock();
+        let count = crate::net::write_tcp(&mut connection, &buffer)?;
+        if count == 0 && !buffer.is_empty() {
+            // The write is done again once the peer acknowledged data
+            connection.wait_for_space(&self.current_process);
+            self.restart = true;
+        }
+        drop(connection);
+        self.account(|io| io.bytes_sent += count as u64);
+        Ok(count)
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_close_tcp_listener(
+        &mut self,
+        listener: UserspaceArgument<TcpListenerDescriptor>,
+    ) -> Result<(), SysSocketError> {
+        let listener = self.current_process.lock().close_tcp_listener(*listener);
+        // Dropped here, after the process was unlocked
+        listener.map(drop).ok_or(SysSocketError::InvalidDescriptor)
+    }
+
+    #[cfg(feature = "net")]
+    fn sys_close_tcp_stream(
+        &mut self,
+        stream: UserspaceArgument<TcpStreamDescriptor>,
+    ) -> Result<(), SysSocketError> {
+        let stream = self.current_process.lock().close_tcp_stream(*stream);
+        // Dropped here, after the process was unlocked
+        stream.map(drop).ok_or(SysSocketError::InvalidDescriptor)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_route_add(
+        &mut self,
+        _destination: UserspaceArgument<u32>,
+        _prefix_length: UserspaceArgument<u8>,
+        _gateway: UserspaceArgument<u32>,
+    ) -> Result<(), SysRouteError> {
+        Err(SysRouteError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_open_raw_socket(&mut self) -> Result<RawDescriptor, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_read_raw_socket(
+        &mut self,
+        _descriptor: UserspaceArgument<RawDescriptor>,
+        _buffer: &mut [u8],
+    ) -> Result<Option<CapturedFrame>, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_connect_udp_socket(
+        &mut self,
+        _descriptor: UserspaceArgument<UDPDescriptor>,
+        _ip: UserspaceArgument<u32>,
+        _port: UserspaceArgument<u16>,
+    ) -> Result<(), SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_sendto(
+        &mut self,
+        _descriptor: UserspaceArgument<UDPDescriptor>,
+        _ip: UserspaceArgument<u32>,
+        _port: UserspaceArgument<u16>,
+        _buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_open_udp_socket(
+        &mut self,
+        _port: UserspaceArgument<u16>,
+    ) -> Result<UDPDescriptor, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_write_back_udp_socket(
+        &mut self,
+        _descriptor: UserspaceArgument<UDPDescriptor>,
+        _buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_read_udp_socket(
+        &mut self,
+        _descriptor: UserspaceArgument<UDPDescriptor>,
+        _buffer: &mut [u8],
+    ) -> Result<usize, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_open_tcp_listener(
+        &mut self,
+        _port: UserspaceArgument<u16>,
+    ) -> Result<TcpListenerDescriptor, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_accept_tcp_connection(
+        &mut self,
+        _listener: UserspaceArgument<TcpListenerDescriptor>,
+    ) -> Result<Option<TcpStreamDescriptor>, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_read_tcp_stream(
+        &mut self,
+        _stream: UserspaceArgument<TcpStreamDescriptor>,
+        _buffer: &mut [u8],
+    ) -> Result<usize, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_write_tcp_stream(
+        &mut self,
+        _stream: UserspaceArgument<TcpStreamDescriptor>,
+        _buffer: UserspaceSlice<u8>,
+    ) -> Result<usize, SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_close_tcp_listener(
+        &mut self,
+        _listener: UserspaceArgument<TcpListenerDescriptor>,
+    ) -> Result<(), SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+
+    #[cfg(not(feature = "net"))]
+    fn sys_close_tcp_stream(
+        &mut self,
+        _stream: UserspaceArgument<TcpStreamDescriptor>,
+    ) -> Result<(), SysSocketError> {
+        Err(SysSocketError::NotSupported)
+    }
+}
+
+// Datagrams can't go to the unspecified address or port 0
+#[cfg(feature = "net")]
+fn validate_peer(ip: u32, port: u16) -> Result<(Ipv4Addr, u16), SysSocketError> {
+    let ip = Ipv4Addr::from_bits(ip);
+    if ip.is_unspecified() || port == 0 {
+        return Err(SysSocketError::InvalidAddress);
     }
+    Ok((ip, port))
 }
 
 pub fn handle_syscall(nr: usize, arg: usize, ret: usize) -> Option<SyscallStatus> {
     let mut handler = SyscallHandler::new();
+    handler.account(|io| io.syscalls += 1);
     let ret = handler.dispatch(nr, arg, ret);
 
-    if handler.process_exit {
+    if handler.process_exit || handler.restart {
         None
     } else {
         Some(ret)
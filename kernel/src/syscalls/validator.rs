@@ -1,17 +1,37 @@
-use core::ops::{Deref, DerefMut};
+use core::ops::{Deref, DerefMut, Range};
 
 use common::{
     constructable::Constructable,
-    errors::{SysSocketError, ValidationError},
-    net::UDPDescriptor,
-    pointer::{FatPointer, Pointer},
-    syscalls::syscall_argument::SyscallArgument,
-    unwrap_or_return,
+    errors::{SysFileError, SysPtyError, ValidationError},
+    fs::FileDescriptor,
+    mmap::MapFlags,
+    perf::PerfCounter,
+    pointer::Pointer,
+    resource_limits::{Resource, ResourceLimit},
+    spawn::SpawnOptions,
+    syscalls::syscall_argument::{SyscallArgument, UserspaceMemory},
+    tty::{InputMode, PtyDescriptor},
+    wait::WaitOptions,
 };
 
-use alloc::vec::Vec;
-
-use crate::net::sockets::SharedAssignedSocket;
+#[cfg(feature = "net")]
+use crate::net::{
+    capture::SharedRawSocket,
+    sockets::SharedAssignedSocket,
+    tcp::{SharedTcpListener, SharedTcpStream},
+};
+use crate::{
+    fs::vfs::SharedOpenFile,
+    io::pty::PtyMaster,
+    memory::{vma, PAGE_SIZE},
+    processes::process_table::ProcessRef,
+};
+#[cfg(feature = "net")]
+use common::{
+    errors::SysSocketError,
+    net::{RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor},
+    unwrap_or_return,
+};
 
 use super::handler::SyscallHandler;
 
@@ -32,6 +52,7 @@ pub trait Validatable<T: Sized> {
     fn validate(self, handler: &mut SyscallHandler) -> Result<T, Self::Error>;
 }
 
+#[cfg(feature = "net")]
 impl Validatable<SharedAssignedSocket> for UserspaceArgument<UDPDescriptor> {
     type Error = SysSocketError;
 
@@ -46,84 +67,214 @@ impl Validatable<SharedAssignedSocket> for UserspaceArgument<UDPDescriptor> {
     }
 }
 
-impl<'a> Validatable<&'a str> for UserspaceArgument<&'a str> {
+#[cfg(feature = "net")]
+impl Validatable<SharedRawSocket> for UserspaceArgument<RawDescriptor> {
+    type Error = SysSocketError;
+
+    fn validate(self, handler: &mut SyscallHandler) -> Result<SharedRawSocket, Self::Error> {
+        handler
+            .current_process()
+            .with_lock(|p| p.get_raw_socket(self.inner).cloned())
+            .ok_or(SysSocketError::InvalidDescriptor)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Validatable<SharedTcpListener> for UserspaceArgument<TcpListenerDescriptor> {
+    type Error = SysSocketError;
+
+    fn validate(self, handler: &mut SyscallHandler) -> Result<SharedTcpListener, Self::Error> {
+        handler
+            .current_process()
+            .with_lock(|p| p.get_tcp_listener(self.inner).cloned())
+            .ok_or(SysSocketError::InvalidDescriptor)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Validatable<SharedTcpStream> for UserspaceArgument<TcpStreamDescriptor> {
+    type Error = SysSocketError;
+
+    fn validate(self, handler: &mut SyscallHandler) -> Result<SharedTcpStream, Self::Error> {
+        handler
+            .current_process()
+            .with_lock(|p| p.get_tcp_stream(self.inner).cloned())
+            .ok_or(SysSocketError::InvalidDescriptor)
+    }
+}
+
+impl Validatable<PtyMaster> for UserspaceArgument<PtyDescriptor> {
+    type Error = SysPtyError;
+
+    fn validate(self, handler: &mut SyscallHandler) -> Result<PtyMaster, Self::Error> {
+        handler
+            .current_process()
+            .with_lock(|p| p.get_pty(self.inner).cloned())
+            .ok_or(SysPtyError::InvalidDescriptor)
+    }
+}
+
+impl Validatable<SharedOpenFile> for UserspaceArgument<FileDescriptor> {
+    type Error = SysFileError;
+
+    fn validate(self, handler: &mut SyscallHandler) -> Result<SharedOpenFile, Self::Error> {
+        handler
+            .current_process()
+            .with_lock(|p| p.get_file(self.inner).cloned())
+            .ok_or(SysFileError::InvalidDescriptor)
+    }
+}
+
+impl Validatable<InputMode> for UserspaceArgument<InputMode> {
     type Error = ValidationError;
 
-    fn validate(self, handler: &mut SyscallHandler) -> Result<&'a str, Self::Error> {
-        let ptr = validate_and_translate_slice_ptr(self.inner, handler)?;
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<InputMode, Self::Error> {
+        InputMode::try_from(self.inner).map_err(|_| ValidationError::InvalidValue)
+    }
+}
 
-        // SAFETY: we validated the pointer above
-        unsafe { Ok(core::str::from_raw_parts(ptr, self.inner.len())) }
+impl Validatable<MapFlags> for UserspaceArgument<MapFlags> {
+    type Error = ValidationError;
+
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<MapFlags, Self::Error> {
+        MapFlags::try_from(self.inner).map_err(|_| ValidationError::InvalidValue)
     }
 }
 
-impl<'a> Validatable<&'a [u8]> for UserspaceArgument<&'a [u8]> {
+impl Validatable<Resource> for UserspaceArgument<Resource> {
     type Error = ValidationError;
 
-    fn validate(self, handler: &mut SyscallHandler) -> Result<&'a [u8], Self::Error> {
-        let ptr = validate_and_translate_slice_ptr(self.inner, handler)?;
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<Resource, Self::Error> {
+        Resource::try_from(self.inner).map_err(|_| ValidationError::InvalidValue)
+    }
+}
+
+impl Validatable<PerfCounter> for UserspaceArgument<PerfCounter> {
+    type Error = ValidationError;
 
-        // SAFETY: we validated the pointer above
-        unsafe { Ok(core::slice::from_raw_parts(ptr, self.inner.len())) }
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<PerfCounter, Self::Error> {
+        PerfCounter::try_from(self.inner).map_err(|_| ValidationError::InvalidValue)
     }
 }
 
-impl<'a> Validatable<&'a mut [u8]> for UserspaceArgument<&'a mut [u8]> {
+impl Validatable<WaitOptions> for UserspaceArgument<WaitOptions> {
     type Error = ValidationError;
 
-    fn validate(self, handler: &mut SyscallHandler) -> Result<&'a mut [u8], Self::Error> {
-        let ptr = validate_and_translate_slice_ptr(self.inner, handler)?;
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<WaitOptions, Self::Error> {
+        WaitOptions::try_from(self.inner).map_err(|_| ValidationError::InvalidValue)
+    }
+}
+
+impl Validatable<SpawnOptions> for UserspaceArgument<SpawnOptions> {
+    type Error = ValidationError;
 
-        // SAFETY: we validated the pointer above
-        unsafe { Ok(core::slice::from_raw_parts_mut(ptr, self.inner.len())) }
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<SpawnOptions, Self::Error> {
+        let SpawnOptions {
+            suspended,
+            detached,
+            ..
+        } = self.inner;
+        if suspended > 1 || detached > 1 {
+            return Err(ValidationError::InvalidValue);
+        }
+        Ok(self.inner)
     }
 }
 
-impl<'a> Validatable<Vec<&'a str>> for UserspaceArgument<&'a [&'a str]> {
+impl Validatable<ResourceLimit> for UserspaceArgument<ResourceLimit> {
     type Error = ValidationError;
 
-    fn validate(self, handler: &mut SyscallHandler) -> Result<Vec<&'a str>, Self::Error> {
-        // If we have zero length the pointer is not even allocated
-        if self.inner.len() == 0 {
-            return Ok(Vec::new());
+    fn validate(self, _handler: &mut SyscallHandler) -> Result<ResourceLimit, Self::Error> {
+        if self.inner.soft > self.inner.hard {
+            return Err(ValidationError::InvalidValue);
         }
+        Ok(self.inner)
+    }
+}
 
-        let outer_slice_ptr = validate_and_translate_slice_ptr(self.inner, handler)?;
+/// Buffers are faulted in, the checks which don't depend on the process
+/// are done by the caller. Buffers which are not physically contiguous
+/// are copied page by page.
+impl UserspaceMemory for SyscallHandler {
+    type Owner = ProcessRef;
 
-        // SAFETY: we validated the pointer above
-        let outer_slice = unsafe { core::slice::from_raw_parts(outer_slice_ptr, self.inner.len()) };
+    fn owner(&self) -> ProcessRef {
+        self.current_process().clone()
+    }
+
+    fn translate<PTR: Pointer>(&mut self, ptr: PTR, len: usize) -> Option<PTR> {
+        let size = core::mem::size_of::<PTR::Pointee>() * len;
 
-        let mut result = Vec::with_capacity(outer_slice.len());
+        let process = self.current_process();
+        vma::fault_in(process, ptr.as_raw()..ptr.as_raw() + size, PTR::WRITABLE);
+        process.with_lock(|p| {
+            let pt = p.get_page_table();
+            // Pages of areas are loaded one by one, the kernel needs the
+            // whole buffer in one piece
+            if !pt.is_valid_userspace_fat_ptr(ptr, len, PTR::WRITABLE)
+                || !pt.is_physically_contiguous(ptr.as_raw(), size)
+            {
+                return None;
+            }
+            pt.translate_userspace_address_to_physical_address(ptr)
+        })
+    }
 
-        for fat_ptr in outer_slice {
-            let ptr = validate_and_translate_slice_ptr(*fat_ptr, handler)?;
-            // SAFETY: We just validated the pointer above
+    fn copy_from(&mut self, address: usize, buffer: &mut [u8]) -> bool {
+        self.for_each_piece(address, buffer.len(), false, |physical, range| {
+            // SAFETY: The piece is mapped in the process and as long as the range
             unsafe {
-                result.push(core::str::from_raw_parts(ptr, fat_ptr.len()));
+                core::ptr::copy_nonoverlapping(
+                    physical as *const u8,
+                    buffer[range.clone()].as_mut_ptr(),
+                    range.len(),
+                );
             }
-        }
+        })
+    }
 
-        Ok(result)
+    fn copy_to(&mut self, address: usize, data: &[u8]) -> bool {
+        self.for_each_piece(address, data.len(), true, |physical, range| {
+            // SAFETY: The piece is writable in the process and as long as the range
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    data[range.clone()].as_ptr(),
+                    physical as *mut u8,
+                    range.len(),
+                );
+            }
+        })
     }
 }
 
-fn validate_and_translate_slice_ptr<PTR: Pointer>(
-    fat_pointer: FatPointer<PTR>,
-    handler: &mut SyscallHandler,
-) -> Result<PTR, ValidationError> {
-    let ptr = fat_pointer.ptr();
-    let len = fat_pointer.len();
-
-    handler
-        .current_process()
-        .with_lock(|p| {
+impl SyscallHandler {
+    /// Calls `f` with the physical address and the range within the
+    /// buffer of every page the buffer at `address` touches. Nothing is
+    /// called if the process can't access the whole buffer.
+    fn for_each_piece(
+        &self,
+        address: usize,
+        length: usize,
+        writable: bool,
+        mut f: impl FnMut(usize, Range<usize>),
+    ) -> bool {
+        self.current_process().with_lock(|p| {
             let pt = p.get_page_table();
-            if !pt.is_valid_userspace_fat_ptr(ptr, len, PTR::WRITABLE) {
-                return None;
+            if !pt.is_valid_userspace_fat_ptr(address as *const u8, length, writable) {
+                return false;
             }
-            pt.translate_userspace_address_to_physical_address(ptr)
+            let mut offset = 0;
+            while offset < length {
+                let piece = (PAGE_SIZE - (address + offset) % PAGE_SIZE).min(length - offset);
+                let Some((physical, _)) = pt.translate(address + offset) else {
+                    return false;
+                };
+                f(physical, offset..offset + piece);
+                offset += piece;
+            }
+            true
         })
-        .ok_or(ValidationError::InvalidPtr)
+    }
 }
 
 macro_rules! simple_type {
@@ -146,6 +297,14 @@ macro_rules! simple_type {
 
 simple_type!(char);
 
+// Closing only needs the descriptor itself
+simple_type!(FileDescriptor);
+simple_type!(PtyDescriptor);
+#[cfg(feature = "net")]
+simple_type!(TcpListenerDescriptor);
+#[cfg(feature = "net")]
+simple_type!(TcpStreamDescriptor);
+
 simple_type!(u8);
 simple_type!(u16);
 simple_type!(u32);
@@ -4,12 +4,15 @@ mod array_vec;
 mod leb128;
 mod mutex;
 mod runtime_initialized;
+mod syscall_argument;
+mod syscall_numbers;
 
 pub mod qemu_exit;
 
 // Inspired by https://os.phil-opp.com/testing/
 
 pub trait Testable {
+    fn name(&self) -> &'static str;
     fn run(&self);
 }
 
@@ -17,29 +20,50 @@ impl<T> Testable for T
 where
     T: Fn(),
 {
+    fn name(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
+
     fn run(&self) {
-        print!("TEST: {} ... ", core::any::type_name::<T>());
+        print!("TEST: {} ... ", self.name());
         self();
         println!("OK");
     }
 }
 
+// Selected with test= on the kernel command line
+#[cfg(not(miri))]
+fn test_filter() -> alloc::string::String {
+    crate::cmdline::get().test.unwrap_or("").into()
+}
+
+// Selected like a normal cargo test, e.g. `cargo miri test -- mutex`
+#[cfg(miri)]
+fn test_filter() -> alloc::string::String {
+    std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .unwrap_or_default()
+}
+
+/// Under Miri there is no kernel_init which prepared everything
+#[cfg(miri)]
+fn prepare_host() {
+    crate::cpu::STARTING_CPU_ID.initialize(0);
+    crate::memory::init_page_allocator(core::iter::empty());
+}
+
 #[allow(dead_code)]
 pub fn test_runner(tests: &[&dyn Testable]) {
+    #[cfg(miri)]
+    prepare_host();
+    let filter = test_filter();
+    let tests: alloc::vec::Vec<_> = tests
+        .iter()
+        .filter(|test| test.name().contains(filter.as_str()))
+        .collect();
     println!("Running {} tests", tests.len());
     crate::memory::initialize_runtime_mappings(&[]);
-    // #[cfg(miri)]
-    // {
-    //     use crate::memory::{self, PAGE_SIZE};
-    //     use core::alloc::Layout;
-
-    //     // Allocate memory for the page allocator
-    //     let heap_size = 16 * 1024 * 1024; // 128 MB should be enough for the tests
-    //     let page_allocator_layout = Layout::from_size_align(heap_size, PAGE_SIZE).unwrap();
-    //     let page_allocator_heap = unsafe { std::alloc::alloc_zeroed(page_allocator_layout) };
-    //     assert!(!page_allocator_heap.is_null());
-    //     memory::init_page_allocator(page_allocator_heap, heap_size);
-    // }
     for test in tests {
         test.run();
     }
@@ -2,7 +2,14 @@
 mod tests {
     use core::sync::atomic::Ordering;
 
-    use common::mutex::Mutex;
+    use core::{assert_matches::assert_matches, panic::Location};
+
+    use common::{
+        lock_debug::{self, Acquisition, LockOwner, LockViolation, BACKTRACE_DEPTH},
+        mutex::{Mutex, RwLock, SpinLockIrqSave},
+    };
+
+    use crate::cpu::Cpu;
 
     use crate::debug;
 
@@ -59,4 +66,125 @@ mod tests {
         let mutex_guard = mutex.lock();
         debug!("{mutex_guard:?}");
     }
+
+    fn acquisition(hart: usize) -> Acquisition {
+        Acquisition {
+            owner: LockOwner { hart, pid: None },
+            location: Location::caller(),
+            backtrace: [0; BACKTRACE_DEPTH],
+        }
+    }
+
+    #[test_case]
+    fn lock_debug_detects_recursive_acquisition() {
+        let lock = 0x1000;
+        assert!(lock_debug::check_and_record(lock, acquisition(0)).is_ok());
+        assert_matches!(
+            lock_debug::check_and_record(lock, acquisition(0)),
+            Err(LockViolation::Recursive { lock: 0x1000, .. })
+        );
+        // Another hart is just waiting for the lock
+        assert!(lock_debug::check_and_record(lock, acquisition(1)).is_ok());
+        lock_debug::remove_held(lock, 1);
+        lock_debug::remove_held(lock, 0);
+        assert!(lock_debug::holder(lock).is_none());
+    }
+
+    #[test_case]
+    fn lock_debug_detects_order_inversion() {
+        let (a, b) = (0x2000, 0x3000);
+        assert!(lock_debug::check_and_record(a, acquisition(0)).is_ok());
+        assert!(lock_debug::check_and_record(b, acquisition(0)).is_ok());
+        lock_debug::remove_held(b, 0);
+        lock_debug::remove_held(a, 0);
+
+        assert!(lock_debug::check_and_record(b, acquisition(1)).is_ok());
+        assert_matches!(
+            lock_debug::check_and_record(a, acquisition(1)),
+            Err(LockViolation::OrderInversion {
+                held_lock: 0x3000,
+                requested_lock: 0x2000,
+                ..
+            })
+        );
+        lock_debug::remove_held(b, 1);
+
+        lock_debug::forget_unconditionally(a);
+        assert!(lock_debug::check_and_record(b, acquisition(1)).is_ok());
+        assert!(lock_debug::check_and_record(a, acquisition(1)).is_ok());
+        lock_debug::remove_held(a, 1);
+        lock_debug::remove_held(b, 1);
+        lock_debug::forget_unconditionally(a);
+        lock_debug::forget_unconditionally(b);
+    }
+
+    #[test_case]
+    fn lock_debug_is_disabled_by_default() {
+        assert!(!lock_debug::is_enabled());
+        let mutex = Mutex::new(42);
+        let _lock = mutex.lock();
+        assert!(mutex.holder().is_none());
+    }
+
+    #[test_case]
+    fn irq_save_lock_disables_interrupts() {
+        const SSTATUS_SIE: usize = 0b10;
+        let sstatus_before = Cpu::read_sstatus();
+        let lock = SpinLockIrqSave::new(42);
+        lock.with_lock(|mut d| {
+            assert_eq!(Cpu::read_sstatus() & SSTATUS_SIE, 0);
+            assert!(lock.get_locked().load(Ordering::Acquire));
+            *d = 45;
+        });
+        assert!(!lock.get_locked().load(Ordering::Acquire));
+        assert_eq!(Cpu::read_sstatus(), sstatus_before);
+        assert_eq!(*lock.lock(), 45);
+    }
+
+    #[test_case]
+    fn try_lock_fails_while_locked() {
+        let mutex = Mutex::new(42);
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert_eq!(*mutex.try_lock().unwrap(), 42);
+        assert!(!mutex.get_locked().load(Ordering::Acquire));
+    }
+
+    #[test_case]
+    fn irq_save_try_lock_restores_interrupts_on_failure() {
+        let sstatus_before = Cpu::read_sstatus();
+        let lock = SpinLockIrqSave::new(42);
+        let guard = lock.lock();
+        assert!(lock.try_lock().is_none());
+        drop(guard);
+        assert_eq!(Cpu::read_sstatus(), sstatus_before);
+        assert_eq!(*lock.try_lock().unwrap(), 42);
+        assert_eq!(Cpu::read_sstatus(), sstatus_before);
+    }
+
+    #[test_case]
+    fn rw_lock_allows_multiple_readers() {
+        let lock = RwLock::new(42);
+        {
+            let first = lock.read();
+            let second = lock.read();
+            assert_eq!(lock.readers(), 2);
+            assert!(!lock.is_write_locked());
+            assert_eq!(*first + *second, 84);
+        }
+        assert_eq!(lock.readers(), 0);
+    }
+
+    #[test_case]
+    fn rw_lock_write() {
+        let lock = RwLock::new(42);
+        lock.with_write_lock(|mut d| {
+            assert!(lock.is_write_locked());
+            assert_eq!(lock.readers(), 0);
+            *d = 45;
+        });
+        assert!(!lock.is_write_locked());
+        assert_eq!(lock.with_read_lock(|d| *d), 45);
+    }
 }
@@ -1,32 +1,76 @@
 use common::mutex::Mutex;
 
-use crate::{cpu::Cpu, io::TEST_DEVICE_ADDRESSS, klibc::MMIO};
+use crate::{
+    cpu::Cpu,
+    device_tree::tree::DeviceTreeNode,
+    drivers::platform::{DeviceTreeDriver, ProbeError},
+    io::uart::CONSOLE_UART,
+    klibc::MMIO,
+    sbi::extensions::system_reset_extension::{self, ResetReason, ResetType},
+};
 
 const EXIT_SUCCESS_CODE: u32 = 0x5555;
-#[allow(dead_code)]
 const EXIT_FAILURE_CODE: u32 = 0x3333;
 #[allow(dead_code)]
 const EXIT_RESET_CODE: u32 = 0x7777;
 
-static TEST_DEVICE: Mutex<MMIO<u32>> = Mutex::new(MMIO::new(TEST_DEVICE_ADDRESSS));
+pub const DEVICE_TREE_DRIVER: DeviceTreeDriver = DeviceTreeDriver {
+    name: "qemu-test",
+    compatible: &["sifive,test0"],
+    needs_mapping: true,
+    probe,
+};
 
-pub fn exit_success() -> ! {
-    TEST_DEVICE.lock().write(EXIT_SUCCESS_CODE);
+// Only QEMU has the test device. Without it the machine is reset
+// through the SBI, which loses the exit code.
+static TEST_DEVICE: Mutex<Option<MMIO<u32>>> = Mutex::new(None);
+
+fn probe(node: &DeviceTreeNode) -> Result<(), ProbeError> {
+    let reg = node.first_reg().ok_or(ProbeError::MissingProperty("reg"))?;
+    *TEST_DEVICE.lock() = Some(MMIO::new(reg.address));
+    Ok(())
+}
+
+// QEMU exits immediately; queued output would be lost otherwise
+fn flush_output() {
+    CONSOLE_UART.lock().switch_to_synchronous_output();
+}
+
+fn exit(code: u32, reset_type: ResetType, reset_reason: ResetReason) -> ! {
+    flush_output();
+    if let Some(test_device) = TEST_DEVICE.lock().as_mut() {
+        test_device.write(code);
+    } else {
+        // Only returns on failure and there is nothing left to try
+        let _ = system_reset_extension::system_reset(reset_type, reset_reason);
+    }
     wait_for_the_end();
 }
 
-#[allow(dead_code)]
+pub fn exit_success() -> ! {
+    exit(
+        EXIT_SUCCESS_CODE,
+        ResetType::Shutdown,
+        ResetReason::NoReason,
+    );
+}
+
+/// QEMU exits with the given code
 pub fn exit_failure(code: u16) -> ! {
-    TEST_DEVICE
-        .lock()
-        .write(EXIT_FAILURE_CODE | ((code as u32) << 16));
-    wait_for_the_end();
+    exit(
+        EXIT_FAILURE_CODE | ((code as u32) << 16),
+        ResetType::Shutdown,
+        ResetReason::SystemFailure,
+    );
 }
 
 #[allow(dead_code)]
 pub fn exit_reset() -> ! {
-    TEST_DEVICE.lock().write(EXIT_RESET_CODE);
-    wait_for_the_end();
+    exit(
+        EXIT_RESET_CODE,
+        ResetType::ColdReboot,
+        ResetReason::NoReason,
+    );
 }
 
 pub fn wait_for_the_end() -> ! {
@@ -0,0 +1,274 @@
+#[cfg(test)]
+mod tests {
+    use core::{assert_matches::assert_matches, ops::Range};
+
+    use common::{
+        constructable::Constructable,
+        errors::ValidationError,
+        numbers::Number,
+        pointer::{FatPointer, Pointer},
+        syscalls::syscall_argument::{
+            ArgumentScope, SyscallArgument, UserspaceMemory, UserspaceSlice,
+        },
+    };
+
+    /// Only plain values are wrapped
+    struct Unwrapped;
+
+    impl<T> Constructable<T> for Unwrapped {
+        fn new(_value: T) -> Self {
+            Unwrapped
+        }
+    }
+
+    /// Memory of a process which is identity mapped to the kernel
+    struct FakeMemory {
+        accessible: Range<usize>,
+        // Pretends that the memory is spread over physical memory
+        scattered: bool,
+    }
+
+    impl FakeMemory {
+        fn new(buffer: &[u64]) -> Self {
+            let start = buffer.as_ptr() as usize;
+            Self {
+                accessible: start..start + size_of_val(buffer),
+                scattered: false,
+            }
+        }
+
+        fn contains(&self, address: usize, size: usize) -> bool {
+            self.accessible.start <= address && address + size <= self.accessible.end
+        }
+    }
+
+    impl UserspaceMemory for FakeMemory {
+        type Owner = ();
+
+        fn owner(&self) {}
+
+        fn translate<PTR: Pointer>(&mut self, ptr: PTR, len: usize) -> Option<PTR> {
+            let size = len * size_of::<PTR::Pointee>();
+            (!self.scattered && self.contains(ptr.as_raw(), size)).then_some(ptr)
+        }
+
+        fn copy_from(&mut self, address: usize, buffer: &mut [u8]) -> bool {
+            if !self.contains(address, buffer.len()) {
+                return false;
+            }
+            // SAFETY: The memory belongs to the buffer of the test
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    address as *const u8,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                )
+            };
+            true
+        }
+
+        fn copy_to(&mut self, address: usize, data: &[u8]) -> bool {
+            if !self.contains(address, data.len()) {
+                return false;
+            }
+            // SAFETY: The memory belongs to the buffer of the test
+            unsafe {
+                core::ptr::copy_nonoverlapping(data.as_ptr(), address as *mut u8, data.len())
+            };
+            true
+        }
+    }
+
+    fn hand_slice<'s, T: Number + 'static>(
+        memory: &mut FakeMemory,
+        scope: &'s ArgumentScope<()>,
+        address: usize,
+        len: usize,
+    ) -> Result<UserspaceSlice<'s, T>, ValidationError> {
+        let pointer = FatPointer::new(address as *const T, len);
+        <&[T] as SyscallArgument>::hand::<Unwrapped, _>(pointer, memory, scope)
+    }
+
+    fn hand_slice_mut<'s>(
+        memory: &mut FakeMemory,
+        scope: &'s ArgumentScope<()>,
+        address: usize,
+        len: usize,
+    ) -> Result<&'s mut [u8], ValidationError> {
+        let pointer = FatPointer::new(address as *mut u8, len);
+        <&mut [u8] as SyscallArgument>::hand::<Unwrapped, _>(pointer, memory, scope)
+    }
+
+    #[test_case]
+    fn buffers_inside_the_memory_are_handed() {
+        let buffer = [0x0102_0304_0506_0708u64; 4];
+        let mut memory = FakeMemory::new(&buffer);
+        let scope = ArgumentScope::new(());
+        let address = buffer.as_ptr() as usize;
+
+        let slice = hand_slice::<u64>(&mut memory, &scope, address, 4).unwrap();
+        assert_eq!(&*slice, &buffer);
+        // Empty buffers don't need to point anywhere
+        assert!(hand_slice::<u64>(&mut memory, &scope, 0, 0)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test_case]
+    fn overflowing_lengths_are_rejected() {
+        let buffer = [0u64; 4];
+        let mut memory = FakeMemory::new(&buffer);
+        let scope = ArgumentScope::new(());
+        let address = buffer.as_ptr() as usize;
+
+        // The size in bytes overflows
+        assert_matches!(
+            hand_slice::<u64>(&mut memory, &scope, address, usize::MAX / 4),
+            Err(ValidationError::InvalidPtr)
+        );
+        // Larger than isize::MAX bytes
+        assert_matches!(
+            hand_slice::<u8>(&mut memory, &scope, address, isize::MAX as usize + 1),
+            Err(ValidationError::InvalidPtr)
+        );
+        // The end of the buffer overflows the address space
+        assert_matches!(
+            hand_slice::<u8>(&mut memory, &scope, usize::MAX - 1, 4),
+            Err(ValidationError::InvalidPtr)
+        );
+    }
+
+    #[test_case]
+    fn misaligned_buffers_are_rejected() {
+        let buffer = [0u64; 4];
+        let mut memory = FakeMemory::new(&buffer);
+        let scope = ArgumentScope::new(());
+        let address = buffer.as_ptr() as usize;
+
+        assert_matches!(
+            hand_slice::<u64>(&mut memory, &scope, address + 1, 1),
+            Err(ValidationError::InvalidPtr)
+        );
+        assert_matches!(
+            hand_slice::<u32>(&mut memory, &scope, address + 2, 1),
+            Err(ValidationError::InvalidPtr)
+        );
+        // Bytes don't need any alignment
+        assert!(hand_slice::<u8>(&mut memory, &scope, address + 1, 1).is_ok());
+    }
+
+    #[test_case]
+    fn buffers_outside_the_memory_are_rejected() {
+        let buffer = [0u64; 4];
+        let mut memory = FakeMemory::new(&buffer);
+        let scope = ArgumentScope::new(());
+        let address = buffer.as_ptr() as usize;
+
+        assert_matches!(
+            hand_slice::<u64>(&mut memory, &scope, address, 5),
+            Err(ValidationError::InvalidPtr)
+        );
+        assert_matches!(
+            hand_slice::<u64>(&mut memory, &scope, address - 8, 1),
+            Err(ValidationError::InvalidPtr)
+        );
+    }
+
+    #[test_case]
+    fn mutable_buffers_must_not_alias_other_arguments() {
+        let buffer = [0u64; 4];
+        let mut memory = FakeMemory::new(&buffer);
+        let address = buffer.as_ptr() as usize;
+
+        // Shared buffers may overlap each other
+        let scope = ArgumentScope::new(());
+        assert!(hand_slice::<u8>(&mut memory, &scope, address, 16).is_ok());
+        assert!(hand_slice::<u8>(&mut memory, &scope, address + 8, 16).is_ok());
+        assert_matches!(
+            hand_slice_mut(&mut memory, &scope, address + 15, 4),
+            Err(ValidationError::InvalidPtr)
+        );
+        assert!(hand_slice_mut(&mut memory, &scope, address + 24, 8).is_ok());
+
+        // A shared buffer must not overlap a mutable one either
+        let scope = ArgumentScope::new(());
+        assert!(hand_slice_mut(&mut memory, &scope, address, 8).is_ok());
+        assert_matches!(
+            hand_slice::<u8>(&mut memory, &scope, address + 7, 1),
+            Err(ValidationError::InvalidPtr)
+        );
+        assert!(hand_slice::<u8>(&mut memory, &scope, address + 8, 1).is_ok());
+
+        // Every syscall starts with a new scope
+        let scope = ArgumentScope::new(());
+        assert!(hand_slice_mut(&mut memory, &scope, address, 8).is_ok());
+    }
+
+    #[test_case]
+    fn scattered_buffers_are_copied() {
+        let mut buffer = [0x0102_0304_0506_0708u64; 4];
+        let address = buffer.as_mut_ptr() as usize;
+        let mut memory = FakeMemory::new(&buffer);
+        memory.scattered = true;
+
+        let mut scope = ArgumentScope::new(());
+        let slice = hand_slice::<u64>(&mut memory, &scope, address, 2).unwrap();
+        assert_ne!(slice.as_ptr() as usize, address);
+        assert_eq!(&*slice, &buffer[..2]);
+
+        let copy = hand_slice_mut(&mut memory, &scope, address + 16, 8).unwrap();
+        assert_ne!(copy.as_ptr() as usize, address + 16);
+        assert_eq!(copy, &[0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        copy.fill(0);
+        // Written back once the handler returned
+        scope.write_back(&mut memory);
+        // SAFETY: The process memory is the buffer, read it like the process
+        let written = unsafe { core::ptr::read_volatile(&buffer[2]) };
+        assert_eq!(written, 0);
+        assert_eq!(buffer[3], 0x0102_0304_0506_0708);
+
+        // Scattered buffers must be accessible as well
+        let scope = ArgumentScope::new(());
+        assert_matches!(
+            hand_slice::<u64>(&mut memory, &scope, address, 5),
+            Err(ValidationError::InvalidPtr)
+        );
+    }
+
+    #[test_case]
+    fn arguments_must_not_alias_the_return_value() {
+        let buffer = [0u64; 4];
+        let mut memory = FakeMemory::new(&buffer);
+        let address = buffer.as_ptr() as usize;
+
+        let scope = ArgumentScope::new(());
+        assert!(scope
+            .translate_struct(&mut memory, (address + 16) as *mut u64)
+            .is_ok());
+        assert_matches!(
+            hand_slice::<u8>(&mut memory, &scope, address + 20, 1),
+            Err(ValidationError::InvalidPtr)
+        );
+        assert!(hand_slice_mut(&mut memory, &scope, address, 16).is_ok());
+    }
+
+    #[test_case]
+    fn scattered_return_values_are_written_back() {
+        let mut buffer = [0u64; 4];
+        let address = buffer.as_mut_ptr() as usize;
+        let mut memory = FakeMemory::new(&buffer);
+        memory.scattered = true;
+
+        let mut scope = ArgumentScope::new(());
+        let ret = scope
+            .translate_struct(&mut memory, (address + 8) as *mut u64)
+            .unwrap();
+        assert_ne!(ret as usize, address + 8);
+        // SAFETY: The scope keeps the copy alive
+        unsafe { ret.write(42) };
+        scope.write_back(&mut memory);
+        // SAFETY: The process memory is the buffer, read it like the process
+        let written = unsafe { core::ptr::read_volatile(&buffer[1]) };
+        assert_eq!(written, 42);
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use common::syscalls::{
+        description::{
+            numbers_are_ascending, numbers_are_assigned, SyscallDescription, ASSIGNED_NUMBERS,
+        },
+        SYSCALLS,
+    };
+
+    #[test_case]
+    fn syscalls_match_the_assigned_numbers() {
+        assert!(numbers_are_ascending(SYSCALLS));
+        assert!(numbers_are_assigned(SYSCALLS, ASSIGNED_NUMBERS));
+    }
+
+    #[test_case]
+    fn renumbered_syscalls_are_rejected() {
+        // Swapping two names keeps the numbers ascending
+        let mut syscalls: Vec<SyscallDescription> = SYSCALLS.to_vec();
+        let (first, second) = (syscalls[3].name, syscalls[4].name);
+        syscalls[3].name = second;
+        syscalls[4].name = first;
+        assert!(numbers_are_ascending(&syscalls));
+        assert!(!numbers_are_assigned(&syscalls, ASSIGNED_NUMBERS));
+
+        // A gap keeps the numbers ascending as well
+        let mut syscalls: Vec<SyscallDescription> = SYSCALLS.to_vec();
+        let last = syscalls.len() - 1;
+        syscalls[last].number += 1;
+        assert!(numbers_are_ascending(&syscalls));
+        assert!(!numbers_are_assigned(&syscalls, ASSIGNED_NUMBERS));
+    }
+
+    #[test_case]
+    fn removed_syscalls_are_rejected() {
+        let mut syscalls: Vec<SyscallDescription> = SYSCALLS.to_vec();
+        syscalls.remove(10);
+        assert!(!numbers_are_assigned(&syscalls, ASSIGNED_NUMBERS));
+    }
+}
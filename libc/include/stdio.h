@@ -0,0 +1,28 @@
+#ifndef _STDIO_H
+#define _STDIO_H
+
+#include <stddef.h>
+
+#define EOF (-1)
+
+typedef struct File FILE;
+
+extern FILE *stdin;
+extern FILE *stdout;
+extern FILE *stderr;
+
+int putchar(int c);
+int fputc(int c, FILE *stream);
+int puts(const char *s);
+int fputs(const char *s, FILE *stream);
+int getchar(void);
+int fgetc(FILE *stream);
+char *fgets(char *s, int size, FILE *stream);
+int fflush(FILE *stream);
+
+int printf(const char *format, ...);
+int fprintf(FILE *stream, const char *format, ...);
+int sprintf(char *str, const char *format, ...);
+int snprintf(char *str, size_t size, const char *format, ...);
+
+#endif
@@ -0,0 +1,21 @@
+#ifndef _STDLIB_H
+#define _STDLIB_H
+
+#include <stddef.h>
+
+#define EXIT_SUCCESS 0
+#define EXIT_FAILURE 1
+
+void *malloc(size_t size);
+void *calloc(size_t count, size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+
+void exit(int status) __attribute__((noreturn));
+void abort(void) __attribute__((noreturn));
+
+int abs(int value);
+int atoi(const char *s);
+char *getenv(const char *name);
+
+#endif
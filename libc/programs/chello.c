@@ -0,0 +1,30 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+int main(int argc, char **argv) {
+    printf("Hello from C!\n");
+    for (int i = 1; i < argc; i++) {
+        printf("argv[%d] = %s\n", i, argv[i]);
+    }
+
+    char *greeting = malloc(32);
+    strcpy(greeting, "Numbers:");
+    for (int i = 1; i <= 3; i++) {
+        char number[8];
+        snprintf(number, sizeof(number), " %d", i * 10);
+        strcat(greeting, number);
+    }
+    puts(greeting);
+    free(greeting);
+
+    printf("Enter your name:\n");
+    char name[64];
+    fgets(name, sizeof(name), stdin);
+    size_t length = strlen(name);
+    if (length > 0 && name[length - 1] == '\n') {
+        name[length - 1] = '\0';
+    }
+    printf("Hello %s, 0x%04x %5.2f\n", name, 42, 3.14159);
+    return 0;
+}
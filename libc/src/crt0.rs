@@ -0,0 +1,56 @@
+#[cfg(not(test))]
+use core::ffi::c_int;
+use core::{
+    ffi::{c_char, CStr},
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+#[cfg(not(test))]
+use crate::{malloc::malloc, stdlib::exit};
+
+#[cfg(not(test))]
+unsafe extern "C" {
+    fn main(argc: c_int, argv: *mut *mut c_char) -> c_int;
+}
+
+// The first KEY=VALUE string, the list is terminated by an empty string
+static ENVIRONMENT: AtomicPtr<c_char> = AtomicPtr::new(null_mut());
+
+/// The kernel passes the arguments and the environment as zero
+/// terminated strings. Each list ends with an empty string.
+#[cfg(not(test))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn _start(args: *mut c_char) -> ! {
+    let mut argc = 0;
+    let mut current = args;
+    while *current != 0 {
+        argc += 1;
+        current = current.add(CStr::from_ptr(current).count_bytes() + 1);
+    }
+    ENVIRONMENT.store(current.add(1), Ordering::Relaxed);
+
+    let argv = malloc((argc + 1) * size_of::<*mut c_char>()) as *mut *mut c_char;
+    assert!(!argv.is_null(), "No memory for the arguments");
+    let mut current = args;
+    for index in 0..argc {
+        *argv.add(index) = current;
+        current = current.add(CStr::from_ptr(current).count_bytes() + 1);
+    }
+    *argv.add(argc) = null_mut();
+
+    exit(main(argc as c_int, argv))
+}
+
+pub(crate) fn environment() -> impl Iterator<Item = &'static CStr> {
+    let mut current = ENVIRONMENT.load(Ordering::Relaxed);
+    core::iter::from_fn(move || {
+        // SAFETY: The environment was written by the kernel and is never changed
+        let entry = unsafe { CStr::from_ptr(current) };
+        if entry.is_empty() {
+            return None;
+        }
+        current = unsafe { current.add(entry.count_bytes() + 1) };
+        Some(entry)
+    })
+}
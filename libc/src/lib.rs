@@ -0,0 +1,20 @@
+//! A minimal C library on top of the YaOS syscalls. It is linked
+//! statically into C programs, see `programs/` for examples.
+
+// The tests run on the host with std. The functions are only exported
+// outside of tests, they would replace the C library of the host
+// otherwise. Unexported they look unused.
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(test, allow(dead_code))]
+#![feature(c_variadic)]
+// The contracts of the exported functions are the ones of the C standard
+#![allow(clippy::missing_safety_doc)]
+
+mod crt0;
+mod malloc;
+#[cfg(not(test))]
+mod panic;
+mod printf;
+mod stdio;
+mod stdlib;
+mod string;
@@ -0,0 +1,306 @@
+//! Every block starts with a header which stores the size of the block.
+//! Freed blocks are kept in a list and reused first fit. New memory is
+//! mapped with `sys_mmap_pages`, which is never given back.
+
+#[cfg(not(test))]
+use core::alloc::GlobalAlloc;
+use core::{
+    alloc::Layout,
+    ffi::c_void,
+    ptr::{self, null_mut},
+};
+
+use common::mutex::Mutex;
+#[cfg(not(test))]
+use common::syscalls::sys_mmap_pages;
+
+const PAGE_SIZE: usize = 4096;
+// Enough for every C type. The header has the same size so that the
+// returned pointers keep the alignment.
+const ALIGNMENT: usize = 16;
+const HEADER_SIZE: usize = 16;
+// Small allocations don't map pages one by one
+const MINIMUM_MAPPED_PAGES: usize = 16;
+
+#[repr(C)]
+struct Header {
+    size: usize,
+    // Only used while the block is free
+    next: *mut Header,
+}
+
+struct Heap {
+    free_list: *mut Header,
+    // The not yet used rest of the last mapping
+    current: *mut u8,
+    end: *mut u8,
+}
+
+// C programs are single threaded
+unsafe impl Send for Heap {}
+
+static HEAP: Mutex<Heap> = Mutex::new(Heap::new());
+
+impl Heap {
+    unsafe fn take_free_block(&mut self, size: usize) -> *mut Header {
+        let mut link = &raw mut self.free_list;
+        while !(*link).is_null() {
+            let block = *link;
+            if (*block).size >= size {
+                *link = (*block).next;
+                if (*block).size >= size + HEADER_SIZE + ALIGNMENT {
+                    let rest = (block as *mut u8).add(HEADER_SIZE + size) as *mut Header;
+                    (*rest).size = (*block).size - size - HEADER_SIZE;
+                    self.insert_free_block(rest);
+                    (*block).size = size;
+                }
+                return block;
+            }
+            link = &raw mut (*block).next;
+        }
+        null_mut()
+    }
+
+    unsafe fn insert_free_block(&mut self, block: *mut Header) {
+        (*block).next = self.free_list;
+        self.free_list = block;
+    }
+
+    unsafe fn take_new_block(&mut self, size: usize) -> *mut Header {
+        let needed = HEADER_SIZE + size;
+        if (self.end as usize) - (self.current as usize) < needed {
+            let pages = needed.div_ceil(PAGE_SIZE).max(MINIMUM_MAPPED_PAGES);
+            let mapping = map_pages(pages);
+            if mapping.is_null() {
+                return null_mut();
+            }
+            // The rest of the old mapping is not lost
+            if (self.end as usize) - (self.current as usize) >= HEADER_SIZE + ALIGNMENT {
+                let rest = self.current as *mut Header;
+                (*rest).size = (self.end as usize) - (self.current as usize) - HEADER_SIZE;
+                self.insert_free_block(rest);
+            }
+            self.current = mapping;
+            self.end = mapping.add(pages * PAGE_SIZE);
+        }
+        let block = self.current as *mut Header;
+        (*block).size = size;
+        self.current = self.current.add(needed);
+        block
+    }
+}
+
+unsafe fn header(pointer: *mut c_void) -> *mut Header {
+    (pointer as *mut u8).sub(HEADER_SIZE) as *mut Header
+}
+
+#[cfg(not(test))]
+fn map_pages(pages: usize) -> *mut u8 {
+    sys_mmap_pages(pages)
+}
+
+// The tests take the memory from the host
+#[cfg(test)]
+fn map_pages(pages: usize) -> *mut u8 {
+    let layout = Layout::from_size_align(pages * PAGE_SIZE, PAGE_SIZE).unwrap();
+    unsafe { std::alloc::alloc(layout) }
+}
+
+impl Heap {
+    const fn new() -> Self {
+        Self {
+            free_list: null_mut(),
+            current: null_mut(),
+            end: null_mut(),
+        }
+    }
+
+    unsafe fn allocate(&mut self, size: usize) -> *mut c_void {
+        let Some(size) = size.max(1).checked_next_multiple_of(ALIGNMENT) else {
+            return null_mut();
+        };
+        if size > isize::MAX as usize / 2 {
+            return null_mut();
+        }
+        let mut block = self.take_free_block(size);
+        if block.is_null() {
+            block = self.take_new_block(size);
+        }
+        if block.is_null() {
+            return null_mut();
+        }
+        (block as *mut u8).add(HEADER_SIZE) as *mut c_void
+    }
+
+    unsafe fn free(&mut self, pointer: *mut c_void) {
+        if !pointer.is_null() {
+            self.insert_free_block(header(pointer));
+        }
+    }
+
+    unsafe fn reallocate(&mut self, pointer: *mut c_void, size: usize) -> *mut c_void {
+        if pointer.is_null() {
+            return self.allocate(size);
+        }
+        if size == 0 {
+            self.free(pointer);
+            return null_mut();
+        }
+        let old_size = (*header(pointer)).size;
+        if old_size >= size {
+            return pointer;
+        }
+        let new_pointer = self.allocate(size);
+        if !new_pointer.is_null() {
+            ptr::copy_nonoverlapping(pointer as *const u8, new_pointer as *mut u8, old_size);
+            self.free(pointer);
+        }
+        new_pointer
+    }
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    HEAP.lock().allocate(size)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn free(pointer: *mut c_void) {
+    HEAP.lock().free(pointer)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(total) = count.checked_mul(size) else {
+        return null_mut();
+    };
+    let pointer = malloc(total);
+    if !pointer.is_null() {
+        ptr::write_bytes(pointer as *mut u8, 0, total);
+    }
+    pointer
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn realloc(pointer: *mut c_void, size: usize) -> *mut c_void {
+    HEAP.lock().reallocate(pointer, size)
+}
+
+/// The common crate needs an allocator. Larger alignments than the one
+/// of malloc are not supported.
+#[cfg(not(test))]
+struct Allocator;
+
+#[cfg(not(test))]
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() > ALIGNMENT {
+            return null_mut();
+        }
+        malloc(layout.size()) as *mut u8
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, _layout: Layout) {
+        free(pointer as *mut c_void)
+    }
+}
+
+#[cfg(not(test))]
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator;
+
+#[cfg(test)]
+mod tests {
+    use core::ffi::c_void;
+
+    use super::{Heap, ALIGNMENT, HEADER_SIZE, MINIMUM_MAPPED_PAGES, PAGE_SIZE};
+
+    #[test]
+    fn freed_blocks_are_reused() {
+        let mut heap = Heap::new();
+        unsafe {
+            let first = heap.allocate(100);
+            let second = heap.allocate(100);
+            assert_ne!(first, second);
+            heap.free(first);
+            assert_eq!(heap.allocate(90), first);
+        }
+    }
+
+    #[test]
+    fn blocks_are_aligned_and_do_not_overlap() {
+        let mut heap = Heap::new();
+        unsafe {
+            let first = heap.allocate(1) as usize;
+            let second = heap.allocate(17) as usize;
+            assert_eq!(first % ALIGNMENT, 0);
+            assert_eq!(second % ALIGNMENT, 0);
+            assert!(second >= first + ALIGNMENT + HEADER_SIZE);
+        }
+    }
+
+    #[test]
+    fn large_free_blocks_are_split() {
+        let mut heap = Heap::new();
+        unsafe {
+            let large = heap.allocate(1024);
+            heap.free(large);
+            let first = heap.allocate(32);
+            let second = heap.allocate(32);
+            assert_eq!(first, large);
+            assert_eq!(second as usize, large as usize + 32 + HEADER_SIZE);
+        }
+    }
+
+    #[test]
+    fn allocations_larger_than_a_mapping_work() {
+        let mut heap = Heap::new();
+        let size = MINIMUM_MAPPED_PAGES * PAGE_SIZE * 2;
+        unsafe {
+            let pointer = heap.allocate(size) as *mut u8;
+            assert!(!pointer.is_null());
+            pointer.write_bytes(0xab, size);
+            assert_eq!(*pointer.add(size - 1), 0xab);
+        }
+    }
+
+    #[test]
+    fn realloc_keeps_the_content() {
+        let mut heap = Heap::new();
+        unsafe {
+            let pointer = heap.allocate(16) as *mut u8;
+            pointer.copy_from(b"0123456789abcdef".as_ptr(), 16);
+            // Shrinking keeps the block
+            assert_eq!(
+                heap.reallocate(pointer as *mut c_void, 8),
+                pointer as *mut c_void
+            );
+
+            let grown = heap.reallocate(pointer as *mut c_void, 64) as *mut u8;
+            assert_ne!(grown, pointer);
+            assert_eq!(core::slice::from_raw_parts(grown, 16), b"0123456789abcdef");
+            // The old block is free again
+            assert_eq!(heap.allocate(16), pointer as *mut c_void);
+        }
+    }
+
+    #[test]
+    fn realloc_handles_null_and_zero() {
+        let mut heap = Heap::new();
+        unsafe {
+            let pointer = heap.reallocate(core::ptr::null_mut(), 32);
+            assert!(!pointer.is_null());
+            assert!(heap.reallocate(pointer, 0).is_null());
+            assert_eq!(heap.allocate(32), pointer);
+        }
+    }
+
+    #[test]
+    fn impossible_sizes_fail() {
+        let mut heap = Heap::new();
+        unsafe {
+            assert!(heap.allocate(usize::MAX).is_null());
+            assert!(heap.allocate(isize::MAX as usize).is_null());
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+};
+
+use common::syscalls::{sys_exit, sys_write};
+
+struct Writer;
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        sys_write(s).map_err(|_| fmt::Error)
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let _ = writeln!(Writer, "\nlibc panic: {}", info.message());
+    sys_exit(-1);
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
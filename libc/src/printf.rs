@@ -0,0 +1,509 @@
+//! The printf family. Supported are the flags `-0+ #`, width and
+//! precision (also as `*`), the length modifiers `hh h l ll z j t` and
+//! the conversions `d i u x X o c s p f %`.
+
+use core::ffi::{c_char, c_int, CStr, VaListImpl};
+
+use crate::stdio::{descriptor, stdout, write_bytes, File};
+
+trait Output {
+    fn put(&mut self, byte: u8);
+
+    fn put_all(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.put(*byte);
+        }
+    }
+}
+
+/// Collects small writes such that not every character is a syscall
+struct StreamOutput {
+    descriptor: c_int,
+    buffer: [u8; 128],
+    length: usize,
+    failed: bool,
+}
+
+impl StreamOutput {
+    fn new(descriptor: c_int) -> Self {
+        Self {
+            descriptor,
+            buffer: [0; 128],
+            length: 0,
+            failed: false,
+        }
+    }
+
+    fn flush(&mut self) {
+        if write_bytes(self.descriptor, &self.buffer[..self.length]) < 0 {
+            self.failed = true;
+        }
+        self.length = 0;
+    }
+}
+
+impl Output for StreamOutput {
+    fn put(&mut self, byte: u8) {
+        if self.length == self.buffer.len() {
+            self.flush();
+        }
+        self.buffer[self.length] = byte;
+        self.length += 1;
+    }
+}
+
+/// Drops everything which doesn't fit and always terminates the string
+struct BufferOutput {
+    buffer: *mut c_char,
+    capacity: usize,
+    length: usize,
+}
+
+impl Output for BufferOutput {
+    fn put(&mut self, byte: u8) {
+        if self.length + 1 < self.capacity {
+            // SAFETY: The caller guarantees the capacity of the buffer
+            unsafe { *self.buffer.add(self.length) = byte as c_char };
+            self.length += 1;
+        }
+    }
+}
+
+impl BufferOutput {
+    fn terminate(&mut self) {
+        if self.capacity > 0 {
+            unsafe { *self.buffer.add(self.length) = 0 };
+        }
+    }
+}
+
+/// Counts the bytes even if they are dropped by the output
+struct Counting<'a, O: Output> {
+    output: &'a mut O,
+    count: usize,
+}
+
+impl<O: Output> Output for Counting<'_, O> {
+    fn put(&mut self, byte: u8) {
+        self.output.put(byte);
+        self.count += 1;
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Specification {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    long: bool,
+    length_bits: u32,
+}
+
+impl Specification {
+    /// Writes the field with the prefix (sign or 0x) in front of the
+    /// zeros which pad the body to the precision
+    fn pad(&self, output: &mut impl Output, prefix: &[u8], zeros: usize, body: &[u8]) {
+        let content = prefix.len() + zeros + body.len();
+        let padding = self.width.saturating_sub(content);
+        if self.left {
+            output.put_all(prefix);
+            (0..zeros).for_each(|_| output.put(b'0'));
+            output.put_all(body);
+            (0..padding).for_each(|_| output.put(b' '));
+        } else if self.zero {
+            output.put_all(prefix);
+            (0..zeros + padding).for_each(|_| output.put(b'0'));
+            output.put_all(body);
+        } else {
+            (0..padding).for_each(|_| output.put(b' '));
+            output.put_all(prefix);
+            (0..zeros).for_each(|_| output.put(b'0'));
+            output.put_all(body);
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static [u8] {
+        if negative {
+            b"-"
+        } else if self.plus {
+            b"+"
+        } else if self.space {
+            b" "
+        } else {
+            b""
+        }
+    }
+
+    fn write_integer(
+        &self,
+        output: &mut impl Output,
+        value: u64,
+        base: u64,
+        upper: bool,
+        prefix: &[u8],
+    ) {
+        let digits: &[u8; 16] = if upper {
+            b"0123456789ABCDEF"
+        } else {
+            b"0123456789abcdef"
+        };
+        let mut buffer = [0u8; 64];
+        let mut start = buffer.len();
+        let mut remaining = value;
+        // With a precision of zero the value zero has no digits
+        while remaining > 0 || (start == buffer.len() && self.precision != Some(0)) {
+            start -= 1;
+            buffer[start] = digits[(remaining % base) as usize];
+            remaining /= base;
+        }
+        let body = &buffer[start..];
+        let zeros = self.precision.unwrap_or(0).saturating_sub(body.len());
+        self.pad(output, prefix, zeros, body);
+    }
+
+    /// Values are converted with 64 bit integers, larger values are
+    /// printed saturated.
+    fn write_float(&self, output: &mut impl Output, value: f64) {
+        let negative = value.is_sign_negative();
+        let value = if negative { -value } else { value };
+        let prefix = self.sign(negative);
+        if value.is_nan() || value.is_infinite() {
+            let body: &[u8] = if value.is_nan() { b"nan" } else { b"inf" };
+            let specification = Specification {
+                zero: false,
+                ..*self
+            };
+            return specification.pad(output, prefix, 0, body);
+        }
+
+        let precision = self.precision.unwrap_or(6).min(17);
+        let mut rounding = 0.5;
+        for _ in 0..precision {
+            rounding /= 10.0;
+        }
+        let value = value + rounding;
+        let integer = value as u64;
+        let mut fraction = value - integer as f64;
+
+        let mut buffer = [0u8; 40];
+        let mut length = 0;
+        let mut remaining = integer;
+        loop {
+            buffer[length] = b'0' + (remaining % 10) as u8;
+            length += 1;
+            remaining /= 10;
+            if remaining == 0 {
+                break;
+            }
+        }
+        buffer[..length].reverse();
+        if precision > 0 || self.alternate {
+            buffer[length] = b'.';
+            length += 1;
+        }
+        for _ in 0..precision {
+            fraction *= 10.0;
+            let digit = (fraction as u8).min(9);
+            buffer[length] = b'0' + digit;
+            length += 1;
+            fraction -= digit as f64;
+        }
+        self.pad(output, prefix, 0, &buffer[..length]);
+    }
+}
+
+unsafe fn parse_number(format: &mut *const u8) -> usize {
+    let mut number: usize = 0;
+    while (**format).is_ascii_digit() {
+        number = number
+            .saturating_mul(10)
+            .saturating_add((**format - b'0') as usize);
+        *format = format.add(1);
+    }
+    number
+}
+
+unsafe fn format(
+    format_string: *const c_char,
+    args: &mut VaListImpl,
+    output: &mut impl Output,
+) -> usize {
+    // c_char is signed on some hosts the tests run on
+    let mut format = format_string.cast::<u8>();
+    let mut output = Counting { output, count: 0 };
+    while *format != 0 {
+        let byte = *format;
+        format = format.add(1);
+        if byte != b'%' {
+            output.put(byte);
+            continue;
+        }
+
+        let mut specification = Specification::default();
+        loop {
+            match *format {
+                b'-' => specification.left = true,
+                b'0' => specification.zero = true,
+                b'+' => specification.plus = true,
+                b' ' => specification.space = true,
+                b'#' => specification.alternate = true,
+                _ => break,
+            }
+            format = format.add(1);
+        }
+
+        if *format == b'*' {
+            format = format.add(1);
+            let width = args.arg::<c_int>();
+            specification.left |= width < 0;
+            specification.width = width.unsigned_abs() as usize;
+        } else {
+            specification.width = parse_number(&mut format);
+        }
+
+        if *format == b'.' {
+            format = format.add(1);
+            if *format == b'*' {
+                format = format.add(1);
+                let precision = args.arg::<c_int>();
+                // A negative precision is taken as if it was omitted
+                specification.precision = usize::try_from(precision).ok();
+            } else {
+                specification.precision = Some(parse_number(&mut format));
+            }
+        }
+
+        specification.length_bits = 32;
+        loop {
+            match *format {
+                b'h' => specification.length_bits /= 2,
+                b'l' | b'z' | b'j' | b't' => specification.long = true,
+                _ => break,
+            }
+            format = format.add(1);
+        }
+
+        let conversion = *format;
+        if conversion == 0 {
+            break;
+        }
+        format = format.add(1);
+
+        match conversion {
+            b'd' | b'i' => {
+                let value: i64 = if specification.long {
+                    args.arg::<i64>()
+                } else {
+                    match specification.length_bits {
+                        32 => args.arg::<c_int>() as i64,
+                        16 => args.arg::<c_int>() as i16 as i64,
+                        _ => args.arg::<c_int>() as i8 as i64,
+                    }
+                };
+                if specification.precision.is_some() {
+                    specification.zero = false;
+                }
+                let prefix = specification.sign(value < 0);
+                specification.write_integer(&mut output, value.unsigned_abs(), 10, false, prefix);
+            }
+            b'u' | b'x' | b'X' | b'o' => {
+                let value: u64 = if specification.long {
+                    args.arg::<u64>()
+                } else {
+                    match specification.length_bits {
+                        32 => args.arg::<u32>() as u64,
+                        16 => args.arg::<u32>() as u16 as u64,
+                        _ => args.arg::<u32>() as u8 as u64,
+                    }
+                };
+                if specification.precision.is_some() {
+                    specification.zero = false;
+                }
+                let (base, prefix): (u64, &[u8]) = match conversion {
+                    b'x' if specification.alternate && value != 0 => (16, b"0x"),
+                    b'X' if specification.alternate && value != 0 => (16, b"0X"),
+                    b'x' | b'X' => (16, b""),
+                    b'o' if specification.alternate => (8, b"0"),
+                    b'o' => (8, b""),
+                    _ => (10, b""),
+                };
+                specification.write_integer(&mut output, value, base, conversion == b'X', prefix);
+            }
+            b'p' => {
+                let value = args.arg::<*const u8>() as u64;
+                specification.write_integer(&mut output, value, 16, false, b"0x");
+            }
+            b'c' => {
+                let character = args.arg::<c_int>() as u8;
+                specification.pad(&mut output, b"", 0, &[character]);
+            }
+            b's' => {
+                let string = args.arg::<*const c_char>();
+                let bytes = if string.is_null() {
+                    b"(null)"
+                } else {
+                    CStr::from_ptr(string).to_bytes()
+                };
+                let length = specification
+                    .precision
+                    .map_or(bytes.len(), |precision| precision.min(bytes.len()));
+                specification.zero = false;
+                specification.pad(&mut output, b"", 0, &bytes[..length]);
+            }
+            b'f' | b'F' => {
+                let value = args.arg::<f64>();
+                specification.write_float(&mut output, value);
+            }
+            b'%' => output.put(b'%'),
+            unknown => {
+                output.put(b'%');
+                output.put(unknown);
+            }
+        }
+    }
+    output.count
+}
+
+unsafe fn print_to(
+    descriptor: c_int,
+    format_string: *const c_char,
+    args: &mut VaListImpl,
+) -> c_int {
+    let mut output = StreamOutput::new(descriptor);
+    let count = format(format_string, args, &mut output);
+    output.flush();
+    if output.failed {
+        return -1;
+    }
+    count as c_int
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn printf(format_string: *const c_char, mut args: ...) -> c_int {
+    print_to(descriptor(stdout), format_string, &mut args)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn fprintf(
+    stream: *const File,
+    format_string: *const c_char,
+    mut args: ...
+) -> c_int {
+    print_to(descriptor(stream), format_string, &mut args)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn snprintf(
+    buffer: *mut c_char,
+    size: usize,
+    format_string: *const c_char,
+    mut args: ...
+) -> c_int {
+    let mut output = BufferOutput {
+        buffer,
+        capacity: size,
+        length: 0,
+    };
+    let count = format(format_string, &mut args, &mut output);
+    output.terminate();
+    count as c_int
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn sprintf(
+    buffer: *mut c_char,
+    format_string: *const c_char,
+    mut args: ...
+) -> c_int {
+    let mut output = BufferOutput {
+        buffer,
+        capacity: usize::MAX,
+        length: 0,
+    };
+    let count = format(format_string, &mut args, &mut output);
+    output.terminate();
+    count as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ffi::c_char;
+
+    use super::snprintf;
+
+    macro_rules! formatted {
+        ($format:literal $(, $arg:expr)*) => {{
+            let mut buffer = [0 as c_char; 64];
+            let count = unsafe {
+                snprintf(buffer.as_mut_ptr(), buffer.len(), $format.as_ptr() $(, $arg)*)
+            };
+            let string = unsafe { core::ffi::CStr::from_ptr(buffer.as_ptr()) };
+            assert_eq!(count as usize, string.count_bytes());
+            string.to_str().unwrap().to_owned()
+        }};
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(formatted!(c"%d %i", 42, -7), "42 -7");
+        assert_eq!(formatted!(c"%u", u32::MAX), "4294967295");
+        assert_eq!(formatted!(c"%ld", i64::MIN), "-9223372036854775808");
+        assert_eq!(formatted!(c"%hhd %hd", 257, 65537), "1 1");
+        assert_eq!(formatted!(c"%x %X %o", 255, 255, 8), "ff FF 10");
+        assert_eq!(formatted!(c"%#x %#o %#x", 255, 8, 0), "0xff 010 0");
+        assert_eq!(formatted!(c"%+d % d", 5, 5), "+5  5");
+    }
+
+    #[test]
+    fn width_and_precision() {
+        assert_eq!(formatted!(c"[%5d]", 42), "[   42]");
+        assert_eq!(formatted!(c"[%-5d]", 42), "[42   ]");
+        assert_eq!(formatted!(c"[%05d]", -42), "[-0042]");
+        assert_eq!(formatted!(c"[%.3d]", 7), "[007]");
+        // The zero flag is ignored with a precision
+        assert_eq!(formatted!(c"[%06.3d]", 7), "[   007]");
+        assert_eq!(formatted!(c"[%.0d]", 0), "[]");
+        assert_eq!(formatted!(c"[%*d]", 4, 1), "[   1]");
+        assert_eq!(formatted!(c"[%*d]", -4, 1), "[1   ]");
+        assert_eq!(formatted!(c"[%.*s]", 2, c"abc".as_ptr()), "[ab]");
+        assert_eq!(formatted!(c"[%#06x]", 255), "[0x00ff]");
+    }
+
+    #[test]
+    fn strings_characters_and_pointers() {
+        assert_eq!(formatted!(c"%s!", c"hello".as_ptr()), "hello!");
+        assert_eq!(formatted!(c"[%6s]", c"abc".as_ptr()), "[   abc]");
+        assert_eq!(formatted!(c"%s", core::ptr::null::<c_char>()), "(null)");
+        assert_eq!(formatted!(c"%c%c", b'o' as i32, b'k' as i32), "ok");
+        assert_eq!(formatted!(c"%p", 0x1000 as *const u8), "0x1000");
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(formatted!(c"%f", 1.5), "1.500000");
+        assert_eq!(formatted!(c"%.2f", 1.23456), "1.23");
+        assert_eq!(formatted!(c"%5.1f", -2.25), " -2.2");
+        assert_eq!(formatted!(c"%.0f", 2.5), "3");
+        assert_eq!(formatted!(c"%f %f", f64::NAN, f64::INFINITY), "nan inf");
+    }
+
+    #[test]
+    fn percent_and_unknown_conversions() {
+        assert_eq!(formatted!(c"100%%"), "100%");
+        assert_eq!(formatted!(c"%q"), "%q");
+        // An incomplete conversion at the end is dropped
+        assert_eq!(formatted!(c"abc%"), "abc");
+    }
+
+    #[test]
+    fn output_is_truncated_but_counted() {
+        let mut buffer = [0x55 as c_char; 4];
+        let count = unsafe { snprintf(buffer.as_mut_ptr(), buffer.len(), c"%d".as_ptr(), 123456) };
+        assert_eq!(count, 6);
+        assert_eq!(buffer, [b'1' as c_char, b'2' as c_char, b'3' as c_char, 0]);
+    }
+}
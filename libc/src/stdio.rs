@@ -0,0 +1,182 @@
+use core::{
+    ffi::{c_char, c_int, c_void, CStr},
+    ptr::null_mut,
+    slice,
+};
+
+use common::{
+    deadline::NO_DEADLINE,
+    errors::SysInputError,
+    syscalls::{sys_read_input, sys_read_input_wait, sys_write},
+};
+
+pub const EOF: c_int = -1;
+
+const STDIN_FILENO: c_int = 0;
+const STDOUT_FILENO: c_int = 1;
+const STDERR_FILENO: c_int = 2;
+
+/// Only the three standard streams exist. They are unbuffered because
+/// the terminal buffers lines itself.
+#[repr(C)]
+pub struct File {
+    descriptor: c_int,
+}
+
+static STDIN: File = File {
+    descriptor: STDIN_FILENO,
+};
+static STDOUT: File = File {
+    descriptor: STDOUT_FILENO,
+};
+static STDERR: File = File {
+    descriptor: STDERR_FILENO,
+};
+
+#[allow(non_upper_case_globals)]
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub static mut stdin: *const File = &STDIN;
+#[allow(non_upper_case_globals)]
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub static mut stdout: *const File = &STDOUT;
+#[allow(non_upper_case_globals)]
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub static mut stderr: *const File = &STDERR;
+
+/// Writes to stdout and stderr both end up on the terminal. Invalid
+/// UTF-8 is replaced because the kernel only accepts strings.
+pub(crate) fn write_bytes(descriptor: c_int, bytes: &[u8]) -> isize {
+    if descriptor != STDOUT_FILENO && descriptor != STDERR_FILENO {
+        return -1;
+    }
+    for chunk in bytes.utf8_chunks() {
+        if sys_write(chunk.valid()).is_err() {
+            return -1;
+        }
+        if !chunk.invalid().is_empty() && sys_write("\u{FFFD}").is_err() {
+            return -1;
+        }
+    }
+    bytes.len() as isize
+}
+
+/// Blocks for the next byte. Like with SA_RESTART, a read which was
+/// interrupted because the program was stopped continues afterwards.
+fn read_byte_wait() -> Result<u8, SysInputError> {
+    loop {
+        match sys_read_input_wait(NO_DEADLINE) {
+            Err(SysInputError::Interrupted) => {}
+            result => return result,
+        }
+    }
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn write(descriptor: c_int, buffer: *const c_void, count: usize) -> isize {
+    write_bytes(
+        descriptor,
+        slice::from_raw_parts(buffer as *const u8, count),
+    )
+}
+
+/// Blocks until at least one byte is available and returns at the end
+/// of a line or when no more input is available.
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn read(descriptor: c_int, buffer: *mut c_void, count: usize) -> isize {
+    if descriptor != STDIN_FILENO {
+        return -1;
+    }
+    if count == 0 {
+        return 0;
+    }
+    let buffer = slice::from_raw_parts_mut(buffer as *mut u8, count);
+    let Ok(byte) = read_byte_wait() else {
+        return -1;
+    };
+    buffer[0] = byte;
+    let mut read = 1;
+    while read < count && buffer[read - 1] != b'\n' {
+        let Some(byte) = sys_read_input() else {
+            break;
+        };
+        buffer[read] = byte;
+        read += 1;
+    }
+    read as isize
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn fputc(character: c_int, stream: *const File) -> c_int {
+    if write_bytes((*stream).descriptor, &[character as u8]) < 0 {
+        return EOF;
+    }
+    character as u8 as c_int
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn putchar(character: c_int) -> c_int {
+    fputc(character, stdout)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn fputs(string: *const c_char, stream: *const File) -> c_int {
+    if write_bytes((*stream).descriptor, CStr::from_ptr(string).to_bytes()) < 0 {
+        return EOF;
+    }
+    0
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn puts(string: *const c_char) -> c_int {
+    if fputs(string, stdout) == EOF {
+        return EOF;
+    }
+    putchar(b'\n' as c_int)
+}
+
+/// The terminal never ends, therefore EOF is only returned for streams
+/// which can't be read.
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn fgetc(stream: *const File) -> c_int {
+    if (*stream).descriptor != STDIN_FILENO {
+        return EOF;
+    }
+    read_byte_wait().map_or(EOF, c_int::from)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn getchar() -> c_int {
+    fgetc(stdin)
+}
+
+/// The newline is kept like in every C library
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn fgets(
+    string: *mut c_char,
+    size: c_int,
+    stream: *const File,
+) -> *mut c_char {
+    if size <= 0 || (*stream).descriptor != STDIN_FILENO {
+        return null_mut();
+    }
+    let mut index = 0;
+    while index + 1 < size as usize {
+        let character = fgetc(stream) as c_char;
+        *string.add(index) = character;
+        index += 1;
+        if character == b'\n' as c_char {
+            break;
+        }
+    }
+    *string.add(index) = 0;
+    string
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub extern "C" fn fflush(_stream: *const File) -> c_int {
+    0
+}
+
+pub(crate) unsafe fn descriptor(stream: *const File) -> c_int {
+    (*stream).descriptor
+}
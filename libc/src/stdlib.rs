@@ -0,0 +1,55 @@
+use core::ffi::{c_char, c_int, CStr};
+
+use common::syscalls::sys_exit;
+
+use crate::crt0::environment;
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub extern "C" fn exit(status: c_int) -> ! {
+    sys_exit(status as isize);
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub extern "C" fn abort() -> ! {
+    exit(-1)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub extern "C" fn abs(value: c_int) -> c_int {
+    value.wrapping_abs()
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn atoi(string: *const c_char) -> c_int {
+    let mut bytes = CStr::from_ptr(string)
+        .to_bytes()
+        .iter()
+        .skip_while(|byte| byte.is_ascii_whitespace())
+        .peekable();
+    let negative = bytes.next_if(|byte| **byte == b'-').is_some();
+    if !negative {
+        bytes.next_if(|byte| **byte == b'+');
+    }
+    let mut value: c_int = 0;
+    for byte in bytes.take_while(|byte| byte.is_ascii_digit()) {
+        value = value.wrapping_mul(10).wrapping_add((byte - b'0') as c_int);
+    }
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn getenv(name: *const c_char) -> *const c_char {
+    let name = CStr::from_ptr(name).to_bytes();
+    environment()
+        .find_map(|entry| {
+            let value = entry.to_bytes().strip_prefix(name)?.strip_prefix(b"=")?;
+            Some(value.as_ptr() as *const c_char)
+        })
+        .unwrap_or(core::ptr::null())
+}
@@ -0,0 +1,70 @@
+//! memcpy, memmove, memset, memcmp and strlen are provided by compiler_builtins
+
+use core::{
+    ffi::{c_char, c_int, CStr},
+    ptr::null_mut,
+};
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn strcmp(left: *const c_char, right: *const c_char) -> c_int {
+    strncmp(left, right, usize::MAX)
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn strncmp(left: *const c_char, right: *const c_char, count: usize) -> c_int {
+    for index in 0..count {
+        let (left, right) = (*left.add(index), *right.add(index));
+        if left != right || left == 0 {
+            return left as c_int - right as c_int;
+        }
+    }
+    0
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn strcpy(destination: *mut c_char, source: *const c_char) -> *mut c_char {
+    let length = CStr::from_ptr(source).count_bytes();
+    core::ptr::copy_nonoverlapping(source, destination, length + 1);
+    destination
+}
+
+/// Like in C the destination is not terminated if the source is too long
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn strncpy(
+    destination: *mut c_char,
+    source: *const c_char,
+    count: usize,
+) -> *mut c_char {
+    let mut index = 0;
+    while index < count && *source.add(index) != 0 {
+        *destination.add(index) = *source.add(index);
+        index += 1;
+    }
+    while index < count {
+        *destination.add(index) = 0;
+        index += 1;
+    }
+    destination
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn strcat(destination: *mut c_char, source: *const c_char) -> *mut c_char {
+    let length = CStr::from_ptr(destination).count_bytes();
+    strcpy(destination.add(length), source);
+    destination
+}
+
+#[cfg_attr(not(test), unsafe(no_mangle))]
+pub unsafe extern "C" fn strchr(string: *const c_char, character: c_int) -> *mut c_char {
+    let character = character as c_char;
+    let mut current = string;
+    loop {
+        if *current == character {
+            return current as *mut c_char;
+        }
+        if *current == 0 {
+            return null_mut();
+        }
+        current = current.add(1);
+    }
+}
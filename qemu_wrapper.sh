@@ -6,11 +6,24 @@ cd "$(dirname "$0")"
 
 QEMU_CMD="qemu-system-riscv64 \
     -machine virt \
-    -cpu rv64 \
+    -cpu rv64,v=true \
     -m 128M \
     -nographic \
     -serial mon:stdio"
 
+# Host port forwardings of the network card, more can be added with --hostfwd
+HOSTFWD="hostfwd=udp::1234-:1234,hostfwd=tcp::2323-:2323"
+NET=false
+NET_QUEUES=""
+# Multiple queue pairs need a tap device, user networking only offers
+# one. It has to be set up once:
+#   sudo ip tuntap add dev sentientos0 mode tap multi_queue user $USER
+#   sudo ip addr add 10.0.2.2/24 dev sentientos0
+#   sudo ip link set sentientos0 up
+# The guest is reachable at 10.0.2.15 then, host ports aren't forwarded.
+TAP_DEVICE="sentientos0"
+GDBSTUB_PORT=""
+
 # Process options
 while [[ $# -gt 0 ]]; do
     case "$1" in
@@ -18,18 +31,40 @@ while [[ $# -gt 0 ]]; do
             QEMU_CMD+=" -object filter-dump,id=f1,netdev=netdev1,file=network.pcap "
             shift
             ;;
+        --cmdline)
+            QEMU_CMD+=" -append '$2'"
+            shift 2
+            ;;
         --gdb)
             QEMU_CMD+=" -s"
             shift
             ;;
+        --gdbstub)
+            GDBSTUB_PORT=1235
+            shift
+            ;;
+        --gdbstub-port)
+            GDBSTUB_PORT="$2"
+            shift 2
+            ;;
+        --hostfwd)
+            HOSTFWD+=",hostfwd=$2"
+            shift 2
+            ;;
         --help|-h)
             echo "Usage: $0 [OPTIONS] <KERNEL_PATH>"
             echo ""
             echo "Options:"
+            echo "  --cmdline ARGS Pass ARGS as kernel command line, e.g. \"test=mutex\""
             echo "  --gdb          Let qemu listen on :1234 for gdb connections"
+            echo "  --gdbstub      Connect the gdb stub of the kernel to :1235"
+            echo "  --gdbstub-port PORT Connect the gdb stub of the kernel to :PORT"
+            echo "  --hostfwd RULE Forward a host port, e.g. \"udp::5555-:1234\" (with --net)"
             echo "  --log          Log qemu events to /tmp/sentientos.log"
             echo "  --capture      Capture network traffic into network.pcap"
             echo "  --net          Enable network card"
+            echo "  --net-queues N Enable network card with N queue pairs on the tap device $TAP_DEVICE"
+            echo "  --share DIR    Share the host directory DIR, mounted at /host"
             echo "  -h, --help     Show this help message"
             echo "  --wait         Wait cpu until gdb is attached"
             exit 0
@@ -39,9 +74,19 @@ while [[ $# -gt 0 ]]; do
             shift
             ;;
         --net)
-            QEMU_CMD+=" -netdev user,id=netdev1,hostfwd=udp::1234-:1234 -device virtio-net-pci,netdev=netdev1"
+            NET=true
             shift
             ;;
+        --net-queues)
+            NET=true
+            NET_QUEUES="$2"
+            shift 2
+            ;;
+        --share)
+            # Shared read-only with the mount tag "host", mounted at /host
+            QEMU_CMD+=" -fsdev local,id=share,path=$2,security_model=none,readonly=on -device virtio-9p-device,fsdev=share,mount_tag=host"
+            shift 2
+            ;;
         --smp)
             QEMU_CMD+=" -smp $(nproc)"
             shift
@@ -69,9 +114,25 @@ if [[ -z "$KERNEL_PATH" ]]; then
     exit 1
 fi
 
+if [[ -n "$GDBSTUB_PORT" ]]; then
+    QEMU_CMD+=" -device virtio-serial-device -chardev socket,id=gdbstub,host=localhost,port=$GDBSTUB_PORT,server=on,wait=off -device virtconsole,chardev=gdbstub"
+fi
+
+if [[ "$NET" == true && -n "$NET_QUEUES" ]]; then
+    QEMU_CMD+=" -netdev tap,id=netdev1,ifname=$TAP_DEVICE,script=no,downscript=no,queues=$NET_QUEUES"
+    QEMU_CMD+=" -device virtio-net-pci,netdev=netdev1,mq=on,vectors=$((2 * NET_QUEUES + 2))"
+elif [[ "$NET" == true ]]; then
+    QEMU_CMD+=" -netdev user,id=netdev1,$HOSTFWD -device virtio-net-pci,netdev=netdev1"
+fi
+
 # Add the kernel option
 QEMU_CMD+=" -kernel $KERNEL_PATH"
 
+# The userspace programs which are built together with the kernel
+if [[ -f kernel/initramfs.cpio ]]; then
+    QEMU_CMD+=" -initrd kernel/initramfs.cpio"
+fi
+
 # Execute the QEMU command
 echo "Executing: $QEMU_CMD"
 
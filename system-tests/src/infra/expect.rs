@@ -0,0 +1,138 @@
+//! Scripted interaction with the guest, similar to expect(1). A script
+//! is a sequence of send and expect steps which are executed in order:
+//!
+//! ```ignore
+//! let matches = Script::new()
+//!     .send_line("id")
+//!     .expect_regex(r"uid=(?<uid>\d+)")
+//!     .expect(PROMPT)
+//!     .run(&mut sentientos)
+//!     .await?;
+//! assert_eq!(matches[0].name("uid"), Some("0"));
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use regex::bytes::Regex;
+use tokio::io::AsyncWriteExt;
+
+use super::qemu::QemuInstance;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Step {
+    Send(String),
+    Expect(Regex),
+    Timeout(Duration),
+}
+
+/// The result of an expect step
+#[derive(Debug)]
+pub struct Match {
+    /// The output between the previous match and this one
+    pub before: String,
+    groups: Vec<Option<String>>,
+    names: Vec<Option<String>>,
+}
+
+impl Match {
+    /// Group 0 is the whole match
+    pub fn get(&self, group: usize) -> Option<&str> {
+        self.groups.get(group)?.as_deref()
+    }
+
+    pub fn name(&self, name: &str) -> Option<&str> {
+        let group = self
+            .names
+            .iter()
+            .position(|group_name| group_name.as_deref() == Some(name))?;
+        self.get(group)
+    }
+}
+
+#[derive(Default)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(mut self, input: &str) -> Self {
+        self.steps.push(Step::Send(input.into()));
+        self
+    }
+
+    pub fn send_line(self, line: &str) -> Self {
+        self.send(&format!("{line}\n"))
+    }
+
+    /// Waits for the literal text
+    pub fn expect(self, text: &str) -> Self {
+        self.expect_regex(&regex::escape(text))
+    }
+
+    /// Panics if the pattern is invalid, it is part of the test anyway
+    pub fn expect_regex(mut self, pattern: &str) -> Self {
+        let regex = Regex::new(pattern).expect("Pattern must be valid");
+        self.steps.push(Step::Expect(regex));
+        self
+    }
+
+    /// Applies to all following expect steps
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.steps.push(Step::Timeout(timeout));
+        self
+    }
+
+    /// Returns one match per expect step in the order of the steps
+    pub async fn run(self, qemu: &mut QemuInstance) -> anyhow::Result<Vec<Match>> {
+        let mut timeout = DEFAULT_TIMEOUT;
+        let mut matches = Vec::new();
+        for (index, step) in self.steps.into_iter().enumerate() {
+            match step {
+                Step::Send(input) => {
+                    qemu.stdin()
+                        .write_all(input.as_bytes())
+                        .await
+                        .with_context(|| format!("Step {index}: Could not send {input:?}"))?;
+                }
+                Step::Expect(regex) => {
+                    let found = tokio::time::timeout(timeout, qemu.stdout().read_until_regex(&regex))
+                        .await
+                        .map_err(|_| {
+                            anyhow!(
+                                "Step {index}: Timeout after {timeout:?} while waiting for {regex}. Pending output: {:?}",
+                                qemu.stdout().pending_output()
+                            )
+                        })?
+                        .with_context(|| format!("Step {index}"))?;
+                    matches.push(Self::to_match(&regex, found));
+                }
+                Step::Timeout(new_timeout) => timeout = new_timeout,
+            }
+        }
+        Ok(matches)
+    }
+
+    fn to_match(regex: &Regex, (front, groups): (Vec<u8>, Vec<Option<String>>)) -> Match {
+        let whole_match = groups[0].as_deref().unwrap_or_default();
+        let front = String::from_utf8_lossy(&front);
+        // The match is always at the end of the front
+        let before = front
+            .strip_suffix(whole_match)
+            .unwrap_or(&front)
+            .to_string();
+        Match {
+            before,
+            groups,
+            names: regex
+                .capture_names()
+                .map(|name| name.map(String::from))
+                .collect(),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod expect;
+pub mod net_peer;
 pub mod qemu;
 pub mod read_asserter;
 mod searchable_buffer;
@@ -0,0 +1,148 @@
+//! The host side of network tests. QEMU forwards a host port to the
+//! guest, the peer talks to the guest through it.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time::timeout,
+};
+
+/// Fixed address of the guest, see IP_ADDR of the kernel
+pub const GUEST_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(10);
+// Larger than any datagram which fits into an ethernet frame
+const BUFFER_SIZE: usize = 2048;
+
+pub struct UdpPeer {
+    socket: UdpSocket,
+}
+
+impl UdpPeer {
+    pub async fn connect(host_port: u16) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(("127.0.0.1", host_port)).await?;
+        Ok(Self { socket })
+    }
+
+    /// Talks to the guest without a forwarded port, e.g. over a tap device
+    pub async fn connect_directly(guest: SocketAddr) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(guest).await?;
+        Ok(Self { socket })
+    }
+
+    /// A peer for conversations which the guest starts. The guest
+    /// reaches it at the gateway address and the returned port.
+    pub async fn listen() -> anyhow::Result<(Self, u16)> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let port = socket.local_addr()?.port();
+        Ok((Self { socket }, port))
+    }
+
+    /// Waits for the first datagram of the guest and talks only to
+    /// its sender afterwards
+    pub async fn accept(&self) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = [0; BUFFER_SIZE];
+        let (bytes, sender) = timeout(RECEIVE_TIMEOUT, self.socket.recv_from(&mut buffer))
+            .await
+            .map_err(|_| anyhow!("Nothing received within {RECEIVE_TIMEOUT:?}"))??;
+        self.socket.connect(sender).await?;
+        Ok(buffer[..bytes].to_vec())
+    }
+
+    pub async fn send(&self, data: &[u8]) -> anyhow::Result<()> {
+        let sent = self.socket.send(data).await?;
+        if sent != data.len() {
+            bail!("Only {sent} of {} bytes were sent", data.len());
+        }
+        Ok(())
+    }
+
+    async fn receive(&self, buffer: &mut [u8]) -> anyhow::Result<usize> {
+        timeout(RECEIVE_TIMEOUT, self.socket.recv(buffer))
+            .await
+            .map_err(|_| anyhow!("Nothing received within {RECEIVE_TIMEOUT:?}"))?
+            .map_err(Into::into)
+    }
+
+    /// The guest doesn't preserve datagram boundaries, therefore the
+    /// data might arrive in more or fewer datagrams than it was sent.
+    pub async fn receive_exactly(&self, length: usize) -> anyhow::Result<Vec<u8>> {
+        let mut received = Vec::with_capacity(length);
+        let mut buffer = [0; BUFFER_SIZE];
+        while received.len() < length {
+            let bytes = self.receive(&mut buffer).await?;
+            received.extend_from_slice(&buffer[..bytes]);
+        }
+        if received.len() > length {
+            bail!("Received {} bytes instead of {length}", received.len());
+        }
+        Ok(received)
+    }
+
+    pub async fn receive_until(&self, needle: &str) -> anyhow::Result<String> {
+        let mut received = String::new();
+        let mut buffer = [0; BUFFER_SIZE];
+        while !received.contains(needle) {
+            let bytes = self.receive(&mut buffer).await?;
+            received.push_str(&String::from_utf8_lossy(&buffer[..bytes]));
+        }
+        Ok(received)
+    }
+}
+
+pub struct TcpPeer {
+    stream: TcpStream,
+}
+
+impl TcpPeer {
+    pub async fn connect(host_port: u16) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(("127.0.0.1", host_port)).await?;
+        Ok(Self { stream })
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.stream.write_all(data).await.map_err(Into::into)
+    }
+
+    /// Returns 0 once the guest closed the connection
+    async fn receive(&mut self, buffer: &mut [u8]) -> anyhow::Result<usize> {
+        timeout(RECEIVE_TIMEOUT, self.stream.read(buffer))
+            .await
+            .map_err(|_| anyhow!("Nothing received within {RECEIVE_TIMEOUT:?}"))?
+            .map_err(Into::into)
+    }
+
+    pub async fn receive_until(&mut self, needle: &str) -> anyhow::Result<String> {
+        let mut received = String::new();
+        let mut buffer = [0; BUFFER_SIZE];
+        while !received.contains(needle) {
+            let bytes = self.receive(&mut buffer).await?;
+            if bytes == 0 {
+                bail!("Connection closed before {needle:?} arrived, got {received:?}");
+            }
+            received.push_str(&String::from_utf8_lossy(&buffer[..bytes]));
+        }
+        Ok(received)
+    }
+
+    /// Everything the guest sends until it closes the connection
+    pub async fn receive_until_closed(&mut self) -> anyhow::Result<String> {
+        let mut received = String::new();
+        let mut buffer = [0; BUFFER_SIZE];
+        loop {
+            let bytes = self.receive(&mut buffer).await?;
+            if bytes == 0 {
+                return Ok(received);
+            }
+            received.push_str(&String::from_utf8_lossy(&buffer[..bytes]));
+        }
+    }
+}
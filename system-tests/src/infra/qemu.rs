@@ -1,15 +1,44 @@
 use anyhow::anyhow;
-use std::process::{ExitStatus, Stdio};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+};
 use tokio::{
     io::AsyncWriteExt,
+    net::TcpStream,
     process::{Child, ChildStdin, ChildStdout, Command},
 };
 
-use super::{read_asserter::ReadAsserter, PROMPT};
+use super::{
+    net_peer::{TcpPeer, UdpPeer},
+    read_asserter::ReadAsserter,
+    PROMPT,
+};
+
+/// QEMU exit codes if the kernel runs with `exit_with_init`, see
+/// `power::init_exit_code` of the kernel. Exit statuses of init in
+/// 1..=125 are passed through unchanged.
+pub mod init_exit_code {
+    pub const STATUS_OUT_OF_RANGE: i32 = 126;
+}
 
 pub struct QemuOptions {
     add_network_card: bool,
     use_smp: bool,
+    init: Option<String>,
+    exit_with_init: bool,
+    udp_forwardings: Vec<u16>,
+    tcp_forwardings: Vec<u16>,
+    net_queues: Option<usize>,
+    share: Option<PathBuf>,
+    ramdisk_size: usize,
+    leak_check: bool,
+    page_debugging: bool,
+    lock_debug: bool,
+    sbi_console: bool,
+    idle: Option<String>,
+    gdb_stub: bool,
 }
 
 impl Default for QemuOptions {
@@ -17,6 +46,19 @@ impl Default for QemuOptions {
         Self {
             add_network_card: false,
             use_smp: true,
+            init: None,
+            exit_with_init: false,
+            udp_forwardings: Vec::new(),
+            tcp_forwardings: Vec::new(),
+            net_queues: None,
+            share: None,
+            ramdisk_size: 0,
+            leak_check: false,
+            page_debugging: false,
+            lock_debug: false,
+            sbi_console: false,
+            idle: None,
+            gdb_stub: false,
         }
     }
 }
@@ -31,20 +73,197 @@ impl QemuOptions {
         self
     }
 
-    fn apply(self, command: &mut Command) {
-        if self.add_network_card {
-            command.arg("--net");
+    /// Starts the given program as PID 1 instead of init.
+    /// The program must be a shell which prints the sesh banner.
+    pub fn init(mut self, program: &str) -> Self {
+        self.init = Some(program.into());
+        self
+    }
+    /// QEMU exits with the exit status of init as soon as it exits
+    pub fn exit_with_init(mut self, value: bool) -> Self {
+        self.exit_with_init = value;
+        self
+    }
+
+    /// Forwards a free host port to the udp port of the guest.
+    /// Implies a network card.
+    pub fn forward_udp(mut self, guest_port: u16) -> Self {
+        self.add_network_card = true;
+        self.udp_forwardings.push(guest_port);
+        self
+    }
+
+    /// Like `forward_udp` for a tcp port of the guest
+    pub fn forward_tcp(mut self, guest_port: u16) -> Self {
+        self.add_network_card = true;
+        self.tcp_forwardings.push(guest_port);
+        self
+    }
+
+    /// A network card with multiple queue pairs on the tap device of
+    /// qemu_wrapper.sh, which must be set up beforehand. The guest is
+    /// reached directly at `net_peer::GUEST_IP`, forwardings don't apply.
+    pub fn net_queues(mut self, queue_pairs: usize) -> Self {
+        self.add_network_card = true;
+        self.net_queues = Some(queue_pairs);
+        self
+    }
+
+    /// The directory is mounted read-only at /host
+    pub fn share(mut self, directory: &Path) -> Self {
+        self.share = Some(directory.into());
+        self
+    }
+
+    /// Creates /dev/ram0 with the given size in KiB
+    pub fn ramdisk_size(mut self, kib: usize) -> Self {
+        self.ramdisk_size = kib;
+        self
+    }
+
+    /// Exiting processes report pages which they didn't return
+    pub fn leak_check(mut self, value: bool) -> Self {
+        self.leak_check = value;
+        self
+    }
+
+    /// Poisons freed pages and records the backtraces of page allocations
+    pub fn page_debugging(mut self, value: bool) -> Self {
+        self.page_debugging = value;
+        self
+    }
+
+    /// Every lock acquisition is checked for recursion and order inversion
+    pub fn lock_debug(mut self, value: bool) -> Self {
+        self.lock_debug = value;
+        self
+    }
+
+    /// The kernel uses the debug console of the firmware instead of the uart
+    pub fn sbi_console(mut self, value: bool) -> Self {
+        self.sbi_console = value;
+        self
+    }
+
+    /// What the kernel does once no process is left: shutdown,
+    /// restart_init or wfi
+    pub fn idle(mut self, policy: &str) -> Self {
+        self.idle = Some(policy.into());
+        self
+    }
+
+    /// Connects the gdb stub of the kernel to a free host tcp port
+    pub fn gdb_stub(mut self, value: bool) -> Self {
+        self.gdb_stub = value;
+        self
+    }
+
+    fn cmdline(&self) -> Vec<String> {
+        let mut cmdline = Vec::new();
+        if let Some(init) = &self.init {
+            cmdline.push(format!("init={init}"));
+        }
+        if self.exit_with_init {
+            cmdline.push("exit_with_init".into());
+        }
+        if self.ramdisk_size > 0 {
+            cmdline.push(format!("ramdisk_size={}", self.ramdisk_size));
+        }
+        if self.leak_check {
+            cmdline.push("leak_check".into());
+        }
+        if self.page_debugging {
+            cmdline.push("page_poison".into());
+            cmdline.push("page_backtraces".into());
+        }
+        if self.lock_debug {
+            cmdline.push("lock_debug".into());
+        }
+        if self.sbi_console {
+            cmdline.push("console=sbi".into());
+        }
+        if let Some(idle) = &self.idle {
+            cmdline.push(format!("idle={idle}"));
+        }
+        cmdline
+    }
+
+    fn apply(self, command: &mut Command) -> anyhow::Result<HostPorts> {
+        match self.net_queues {
+            Some(queue_pairs) => {
+                command.arg("--net-queues").arg(queue_pairs.to_string());
+            }
+            None if self.add_network_card => {
+                command.arg("--net");
+            }
+            None => {}
+        }
+        let mut udp_ports = HashMap::new();
+        for &guest_port in &self.udp_forwardings {
+            let host_port = free_udp_port()?;
+            command
+                .arg("--hostfwd")
+                .arg(format!("udp::{host_port}-:{guest_port}"));
+            udp_ports.insert(guest_port, host_port);
+        }
+        let mut tcp_ports = HashMap::new();
+        for &guest_port in &self.tcp_forwardings {
+            let host_port = free_tcp_port()?;
+            command
+                .arg("--hostfwd")
+                .arg(format!("tcp::{host_port}-:{guest_port}"));
+            tcp_ports.insert(guest_port, host_port);
+        }
+        let mut gdb_stub = None;
+        if self.gdb_stub {
+            let port = free_tcp_port()?;
+            command.arg("--gdbstub-port").arg(port.to_string());
+            gdb_stub = Some(port);
+        }
+        if let Some(share) = &self.share {
+            // The wrapper changes its working directory
+            command.arg("--share").arg(std::path::absolute(share)?);
         }
         if self.use_smp {
             command.arg("--smp");
         }
+        let cmdline = self.cmdline();
+        if !cmdline.is_empty() {
+            command.arg("--cmdline").arg(cmdline.join(" "));
+        }
+        Ok(HostPorts {
+            udp: udp_ports,
+            tcp: tcp_ports,
+            gdb_stub,
+        })
     }
 }
 
+struct HostPorts {
+    // The host port for every forwarded guest port
+    udp: HashMap<u16, u16>,
+    tcp: HashMap<u16, u16>,
+    gdb_stub: Option<u16>,
+}
+
+// The port might be taken by someone else until QEMU binds it.
+// That is unlikely enough for tests.
+fn free_udp_port() -> anyhow::Result<u16> {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+    Ok(socket.local_addr()?.port())
+}
+
+fn free_tcp_port() -> anyhow::Result<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
 pub struct QemuInstance {
     instance: Child,
     stdin: ChildStdin,
     stdout: ReadAsserter<ChildStdout>,
+    host_ports: HostPorts,
+    boot_output: String,
 }
 
 impl QemuInstance {
@@ -61,7 +280,8 @@ impl QemuInstance {
             .stderr(Stdio::inherit())
             .kill_on_drop(true);
 
-        options.apply(&mut command);
+        let starts_init = options.init.is_none();
+        let host_ports = options.apply(&mut command)?;
 
         command.arg("target/riscv64gc-unknown-none-elf/release/kernel");
 
@@ -79,11 +299,13 @@ impl QemuInstance {
 
         let mut stdout = ReadAsserter::new(stdout);
 
-        stdout
+        let mut boot_output = stdout
             .assert_read_until("Hello World from SentientOS!")
             .await;
-        stdout.assert_read_until("kernel_init done!").await;
-        stdout.assert_read_until("init process started").await;
+        boot_output.extend(stdout.assert_read_until("kernel_init done!").await);
+        if starts_init {
+            stdout.assert_read_until("init process started").await;
+        }
         stdout
             .assert_read_until("### SeSH - Sentient Shell ###")
             .await;
@@ -93,13 +315,52 @@ impl QemuInstance {
             instance,
             stdin,
             stdout,
+            host_ports,
+            boot_output: String::from_utf8_lossy(&boot_output).into_owned(),
         })
     }
 
+    /// Everything the kernel printed up to the end of kernel_init
+    pub fn boot_output(&self) -> &str {
+        &self.boot_output
+    }
+
     pub fn stdout(&mut self) -> &mut ReadAsserter<ChildStdout> {
         &mut self.stdout
     }
 
+    /// Connects to a guest port which was forwarded with
+    /// `QemuOptions::forward_udp`
+    pub async fn udp_peer(&self, guest_port: u16) -> anyhow::Result<UdpPeer> {
+        let host_port = self
+            .host_ports
+            .udp
+            .get(&guest_port)
+            .ok_or(anyhow!("Udp port {guest_port} is not forwarded"))?;
+        UdpPeer::connect(*host_port).await
+    }
+
+    /// Connects to a guest port which was forwarded with
+    /// `QemuOptions::forward_tcp`
+    pub async fn tcp_peer(&self, guest_port: u16) -> anyhow::Result<TcpPeer> {
+        let host_port = self
+            .host_ports
+            .tcp
+            .get(&guest_port)
+            .ok_or(anyhow!("Tcp port {guest_port} is not forwarded"))?;
+        TcpPeer::connect(*host_port).await
+    }
+
+    /// Connects to the gdb stub which was enabled with
+    /// `QemuOptions::gdb_stub`
+    pub async fn gdb_stub(&self) -> anyhow::Result<TcpStream> {
+        let port = self
+            .host_ports
+            .gdb_stub
+            .ok_or(anyhow!("The gdb stub is not enabled"))?;
+        Ok(TcpStream::connect(("127.0.0.1", port)).await?)
+    }
+
     pub fn stdin(&mut self) -> &mut ChildStdin {
         &mut self.stdin
     }
@@ -113,6 +374,14 @@ impl QemuInstance {
         Ok(self.instance.wait().await?)
     }
 
+    /// Returns the exit code of QEMU. Fails if QEMU was killed by a signal.
+    pub async fn wait_for_exit_code(self) -> anyhow::Result<i32> {
+        let status = self.wait_for_qemu_to_exit().await?;
+        status
+            .code()
+            .ok_or(anyhow!("QEMU was terminated by a signal: {status}"))
+    }
+
     pub async fn run_prog(&mut self, prog_name: &str) -> anyhow::Result<String> {
         self.run_prog_waiting_for(prog_name, PROMPT).await
     }
@@ -1,3 +1,5 @@
+use anyhow::bail;
+use regex::bytes::Regex;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
 use super::searchable_buffer::SearchableBuffer;
@@ -27,18 +29,45 @@ impl<Reader: AsyncRead + Unpin> ReadAsserter<Reader> {
             if let Some(front) = self.buffer.find_and_remove(needle) {
                 return front;
             }
-            let mut local_buffer = [0u8; 1024];
-            let bytes = self
-                .reader
-                .read(&mut local_buffer)
-                .await
-                .expect("Read must succeed.");
-            let input = &local_buffer[0..bytes];
-            self.print_to_stderr(input).await;
-            self.buffer.append(input);
+            self.read_more().await;
         }
     }
 
+    /// Returns the output up to the end of the first match and the
+    /// captured groups. Fails if the output ends before a match.
+    pub async fn read_until_regex(
+        &mut self,
+        regex: &Regex,
+    ) -> anyhow::Result<(Vec<u8>, Vec<Option<String>>)> {
+        loop {
+            if let Some(found) = self.buffer.find_regex_and_remove(regex) {
+                return Ok(found);
+            }
+            if self.read_more().await == 0 {
+                bail!("Output ended while waiting for {regex}");
+            }
+        }
+    }
+
+    /// The output which was read but not consumed yet
+    pub fn pending_output(&self) -> String {
+        self.buffer.as_lossy_string()
+    }
+
+    // Cancel safe: The data is only appended after the read finished
+    async fn read_more(&mut self) -> usize {
+        let mut local_buffer = [0u8; 1024];
+        let bytes = self
+            .reader
+            .read(&mut local_buffer)
+            .await
+            .expect("Read must succeed.");
+        let input = &local_buffer[0..bytes];
+        self.print_to_stderr(input).await;
+        self.buffer.append(input);
+        bytes
+    }
+
     async fn print_to_stderr(&mut self, data: &[u8]) {
         self.stderr
             .write_all(data)
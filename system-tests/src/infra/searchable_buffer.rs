@@ -1,3 +1,5 @@
+use regex::bytes::Regex;
+
 pub(super) struct SearchableBuffer {
     buffer: Vec<u8>,
 }
@@ -15,6 +17,25 @@ impl SearchableBuffer {
         None
     }
 
+    /// Returns everything up to the end of the first match and the
+    /// captured groups. Group 0 is the whole match.
+    pub fn find_regex_and_remove(
+        &mut self,
+        regex: &Regex,
+    ) -> Option<(Vec<u8>, Vec<Option<String>>)> {
+        let captures = regex.captures(&self.buffer)?;
+        let end = captures.get(0)?.end();
+        let groups = captures
+            .iter()
+            .map(|group| group.map(|group| String::from_utf8_lossy(group.as_bytes()).into_owned()))
+            .collect();
+        Some((self.shift_to_front(end), groups))
+    }
+
+    pub fn as_lossy_string(&self) -> String {
+        String::from_utf8_lossy(&self.buffer).into_owned()
+    }
+
     pub fn append(&mut self, data: &[u8]) {
         self.buffer.extend_from_slice(data);
     }
@@ -60,4 +81,20 @@ mod tests {
         assert_eq!(&front, "foo 42".as_bytes());
         assert_eq!(searchable_buffer.buffer, " bar".as_bytes());
     }
+
+    #[test]
+    fn regex() {
+        let mut searchable_buffer = SearchableBuffer::new(INPUT.to_vec());
+
+        let regex = Regex::new(r"(\d+) (?<word>\w+)").unwrap();
+        let (front, groups) = searchable_buffer.find_regex_and_remove(&regex).unwrap();
+
+        assert_eq!(&front, INPUT);
+        assert_eq!(
+            groups,
+            [Some("42 bar".into()), Some("42".into()), Some("bar".into())]
+        );
+        assert!(searchable_buffer.buffer.is_empty());
+        assert!(searchable_buffer.find_regex_and_remove(&regex).is_none());
+    }
 }
@@ -1,6 +1,9 @@
 use serial_test::file_serial;
 
-use crate::infra::qemu::{QemuInstance, QemuOptions};
+use crate::infra::{
+    qemu::{init_exit_code, QemuInstance, QemuOptions},
+    PROMPT,
+};
 
 #[tokio::test]
 async fn boot_smp() -> anyhow::Result<()> {
@@ -34,6 +37,91 @@ async fn shutdown() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn init_is_restarted() -> anyhow::Result<()> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().idle("restart_init")).await?;
+
+    sentientos
+        .run_prog_waiting_for("exit", "restarting init")
+        .await?;
+    sentientos
+        .stdout()
+        .assert_read_until("### SeSH - Sentient Shell ###")
+        .await;
+    sentientos.stdout().assert_read_until(PROMPT).await;
+
+    let output = sentientos.run_prog("prog1").await?;
+    assert_eq!(output, "Hello from Prog1\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn idle_without_processes() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().idle("wfi")).await?;
+
+    sentientos
+        .run_prog_waiting_for("exit", "waiting for interrupts")
+        .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_builtin() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    sentientos
+        .run_prog_waiting_for("shutdown", "Shutting down system")
+        .await?;
+
+    assert!(sentientos.wait_for_qemu_to_exit().await?.success());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_while_other_harts_are_busy() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    for _ in 0..2 {
+        sentientos
+            .run_prog_waiting_for("loop &", "Looping... 0")
+            .await?;
+    }
+    sentientos
+        .run_prog_waiting_for("shutdown", "Shutting down system")
+        .await?;
+    sentientos.stdout().assert_read_until("Killed").await;
+
+    assert!(sentientos.wait_for_qemu_to_exit().await?.success());
+
+    Ok(())
+}
+
+async fn exit_code_of_init(exit_command: &str) -> anyhow::Result<i32> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().init("sesh").exit_with_init(true)).await?;
+
+    sentientos
+        .run_prog_waiting_for(exit_command, "exiting QEMU")
+        .await?;
+
+    sentientos.wait_for_exit_code().await
+}
+
+#[tokio::test]
+async fn init_exit_status_is_propagated() -> anyhow::Result<()> {
+    assert_eq!(exit_code_of_init("exit").await?, 0);
+    assert_eq!(exit_code_of_init("exit 3").await?, 3);
+    assert_eq!(
+        exit_code_of_init("exit -1").await?,
+        init_exit_code::STATUS_OUT_OF_RANGE
+    );
+    Ok(())
+}
+
 #[tokio::test]
 async fn execute_program() -> anyhow::Result<()> {
     let mut sentientos = QemuInstance::start().await?;
@@ -72,3 +160,119 @@ async fn execute_different_programs() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn print_page_tables() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("pagetables").await?;
+    assert!(output.contains("(ReadExecute)\t(text)"));
+
+    // The shell itself
+    let output = sentientos.run_prog("pagetables 2").await?;
+    assert!(output.contains("(ReadWrite, U)\t(Stack)"));
+
+    let output = sentientos.run_prog("pagetables 1000").await?;
+    assert_eq!(output, "Cannot print page tables: InvalidPid\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn map_physical_memory() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("fw_cfg").await?;
+    assert_eq!(output, "fw_cfg signature: QEMU\n");
+
+    // The kernel image
+    let output = sentientos.run_prog("fw_cfg 0x80200000").await?;
+    assert_eq!(output, "Cannot map fw_cfg: KernelOwned\n");
+
+    // The PLIC
+    let output = sentientos.run_prog("fw_cfg 0xc000000").await?;
+    assert_eq!(output, "Cannot map fw_cfg: KernelOwned\n");
+
+    let output = sentientos.run_prog("fw_cfg 0x10100008").await?;
+    assert_eq!(output, "Cannot map fw_cfg: InvalidRange\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn userspace_interrupts() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("irq 0").await?;
+    assert_eq!(output, "Cannot register interrupt 0: InvalidInterrupt\n");
+
+    // The UART is handled by the kernel
+    let output = sentientos.run_prog("irq 10").await?;
+    assert_eq!(output, "Cannot register interrupt 10: AlreadyRegistered\n");
+
+    // The interrupt is released when the program exits
+    for _ in 0..2 {
+        let output = sentientos.run_prog("irq 11 0").await?;
+        assert_eq!(output, "Registered interrupt 11\n");
+    }
+
+    // Nothing raises the interrupt
+    let output = sentientos.run_prog("irq 11 1 50").await?;
+    assert_eq!(
+        output,
+        "Registered interrupt 11\nCannot wait for interrupt 11: TimedOut\n"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn exited_processes_leak_no_pages() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().leak_check(true)).await?;
+
+    for prog in ["prog1", "stack", "stack -l", "cat /proc/meminfo"] {
+        let output = sentientos.run_prog(prog).await?;
+        assert!(!output.contains("leaked"), "{prog}: {output}");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lock_debugging_finds_no_violations() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().lock_debug(true)).await?;
+
+    // A violation panics the kernel and the programs never finish
+    for prog in ["prog1", "stack", "cat /proc/meminfo"] {
+        sentientos.run_prog(prog).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sbi_debug_console() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().sbi_console(true)).await?;
+
+    // Input is polled from the firmware
+    let output = sentientos.run_prog("echo 1 2 3").await?;
+    assert_eq!(output, "1 2 3\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn early_output_is_printed_once() -> anyhow::Result<()> {
+    for sbi_console in [false, true] {
+        let sentientos =
+            QemuInstance::start_with(QemuOptions::default().sbi_console(sbi_console)).await?;
+
+        // Printed before the uart is initialized, it must neither be
+        // lost nor repeated when switching to the uart
+        let output = sentientos.boot_output();
+        assert_eq!(output.matches("Hello World from SentientOS!").count(), 1);
+        assert_eq!(output.matches("SBI version").count(), 1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,16 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn syscalls_are_executed_in_a_batch() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("batch").await?;
+
+    assert_eq!(
+        output,
+        "Hello from a batch\nExecuted 4 syscalls\nwrite: Ok(Ok(()))\ngetuid: Ok(0)\nsleep: Err(NotAllowedInBatch)\n\
+         Written once\nExecuted 2 syscalls\nread: Err(NotAllowedInBatch)\nafter: Err(NotExecuted)\n"
+    );
+
+    Ok(())
+}
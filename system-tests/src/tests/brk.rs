@@ -0,0 +1,15 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn program_break_grows_and_shrinks() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("brk").await?;
+
+    assert!(output.contains("Growing the heap: Ok(())"));
+    assert!(output.contains("Printed across a page boundary"));
+    assert!(output.contains("Pages are zeroed after shrinking: true"));
+    assert!(output.contains("Break below the heap: Err(InvalidBreak)"));
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn segfault_writes_core_dump() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("segfault").await?;
+
+    assert!(output.contains("Hello from Segfault! Writing to the null pointer"));
+    assert!(!output.contains("Survived the segfault"));
+    assert!(output.contains("Process segfault (PID=3) crashed: Store/AMO page fault"));
+
+    let core_dump = output
+        .split_once("-----BEGIN CORE DUMP-----\n")
+        .and_then(|(_, rest)| rest.split_once("-----END CORE DUMP-----"))
+        .map(|(dump, _)| dump)
+        .expect("Core dump must be printed");
+    let mut lines = core_dump.lines();
+    assert!(lines
+        .next()
+        .is_some_and(|line| line.starts_with("pid: 3 name: segfault size: ")));
+    // base64 of the ELF magic
+    assert!(lines.next().is_some_and(|line| line.starts_with("f0VMRg")));
+
+    // The kernel is still alive
+    let output = sentientos.run_prog("prog1").await?;
+    assert_eq!(output, "Hello from Prog1\n");
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn processes_inherit_root_from_init() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("id").await?;
+    assert_eq!(output, "uid=0 gid=0\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn init_cannot_be_killed() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("kill 1").await?;
+    assert_eq!(output, "Error: PermissionDenied\n");
+
+    let output = sentientos.run_prog("kill 1000").await?;
+    assert_eq!(output, "Error: InvalidPid\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_root_can_panic_the_kernel() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("panic 1000").await?;
+    assert_eq!(
+        output,
+        "Hello from Panic! Triggering kernel panic\nError: PermissionDenied\n"
+    );
+
+    let output = sentientos.run_prog("id").await?;
+    assert_eq!(output, "uid=0 gid=0\n");
+
+    Ok(())
+}
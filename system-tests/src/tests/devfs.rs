@@ -0,0 +1,35 @@
+use crate::infra::qemu::{QemuInstance, QemuOptions};
+
+#[tokio::test]
+async fn devices_are_listed() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("ls /dev").await?;
+    for device in ["console", "null", "random", "zero"] {
+        assert!(
+            output.lines().any(|line| line == device),
+            "{device} missing"
+        );
+    }
+
+    let output = sentientos.run_prog("cat /dev/null").await?;
+    assert_eq!(output, "");
+
+    let output = sentientos.run_prog("ls /dev/null").await?;
+    assert_eq!(output, "ls: /dev/null: NotADirectory\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ramdisk_is_created_on_request() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+    let output = sentientos.run_prog("ls /dev").await?;
+    assert!(!output.lines().any(|line| line == "ram0"));
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().ramdisk_size(64)).await?;
+    let output = sentientos.run_prog("ls /dev").await?;
+    assert!(output.lines().any(|line| line == "ram0"));
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn floating_point_registers_are_kept_per_process() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("fpu").await?;
+
+    for worker in 1..=4 {
+        assert!(output.contains(&format!("Worker {worker}: registers kept: true")));
+    }
+    assert!(output.contains("Done!"));
+
+    Ok(())
+}
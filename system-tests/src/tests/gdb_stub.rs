@@ -0,0 +1,55 @@
+use regex::bytes::Regex;
+use tokio::io::AsyncWriteExt;
+
+use crate::infra::{
+    qemu::{QemuInstance, QemuOptions},
+    read_asserter::ReadAsserter,
+};
+
+const INTERRUPT: u8 = 0x03;
+// 32 general purpose registers and pc with 16 hex digits each
+const REGISTERS_LENGTH: usize = 33 * 16;
+
+fn packet(payload: &str) -> String {
+    let checksum = payload
+        .bytes()
+        .fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${payload}#{checksum:02x}")
+}
+
+#[tokio::test]
+async fn gdb_can_break_in_inspect_and_resume() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().gdb_stub(true)).await?;
+
+    let (reader, mut writer) = sentientos.gdb_stub().await?.into_split();
+    let mut reader = ReadAsserter::new(reader);
+
+    writer.write_all(&[INTERRUPT]).await?;
+    reader.assert_read_until(&packet("S02")).await;
+
+    writer.write_all(packet("g").as_bytes()).await?;
+    let registers = Regex::new(r"\+\$([0-9a-f]+)#[0-9a-f]{2}")?;
+    let (_, groups) = reader.read_until_regex(&registers).await?;
+    let registers = groups[1].clone().expect("Registers must be captured");
+    assert_eq!(registers.len(), REGISTERS_LENGTH);
+
+    // The stopped instruction must be readable wherever the hart stopped
+    let pc = u64::from_str_radix(&registers[REGISTERS_LENGTH - 16..], 16)?.swap_bytes();
+    writer
+        .write_all(packet(&format!("m{pc:x},2")).as_bytes())
+        .await?;
+    let memory = Regex::new(r"\+\$([0-9a-f]{4})#[0-9a-f]{2}")?;
+    reader.read_until_regex(&memory).await?;
+
+    // Packets with a wrong checksum are rejected
+    writer.write_all(b"$g#00").await?;
+    reader.assert_read_until("-").await;
+
+    writer.write_all(packet("c").as_bytes()).await?;
+    reader.assert_read_until("+").await;
+
+    let output = sentientos.run_prog("echo still running").await?;
+    assert_eq!(output, "still running\n");
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use crate::infra::qemu::{QemuInstance, QemuOptions};
+
+/// Removes the directory when the test is done
+pub(super) struct TemporaryDirectory(pub(super) PathBuf);
+
+impl TemporaryDirectory {
+    pub(super) fn new(name: &str) -> anyhow::Result<Self> {
+        let path = std::env::temp_dir().join(format!("sentientos-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TemporaryDirectory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn programs_are_loaded_from_the_host() -> anyhow::Result<()> {
+    let share = TemporaryDirectory::new("share")?;
+    std::fs::create_dir(share.0.join("bin"))?;
+    std::fs::copy(
+        "../kernel/compiled_userspace/prog1",
+        share.0.join("bin/hello"),
+    )?;
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    let output = sentientos.run_prog("/host/bin/hello").await?;
+    assert_eq!(output, "Hello from Prog1\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_are_read_through_the_mount_point() -> anyhow::Result<()> {
+    let share = TemporaryDirectory::new("files")?;
+    std::fs::create_dir(share.0.join("etc"))?;
+    std::fs::write(share.0.join("etc/motd"), "Welcome\n")?;
+    std::fs::write(share.0.join("notes"), "")?;
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    let output = sentientos.run_prog("cat /host/etc/../etc/motd").await?;
+    assert_eq!(output, "Welcome\n");
+
+    let output = sentientos.run_prog("ls /host").await?;
+    assert_eq!(output, "etc/\nnotes\n");
+
+    let output = sentientos.run_prog("ls /").await?;
+    assert!(output.lines().any(|line| line == "host/"));
+
+    let output = sentientos.run_prog("cat /host/etc").await?;
+    assert_eq!(output, "cat: /host/etc: IsADirectory\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_are_attached_as_loop_devices() -> anyhow::Result<()> {
+    let share = TemporaryDirectory::new("loop")?;
+    let mut image = b"Block device content\n".to_vec();
+    image.resize(512, b'\n');
+    std::fs::write(share.0.join("disk.img"), &image)?;
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    let output = sentientos.run_prog("losetup /host/disk.img").await?;
+    assert_eq!(output, "/dev/loop0\n");
+
+    let output = sentientos.run_prog("cat /dev/loop0").await?;
+    assert_eq!(output.as_bytes(), image);
+
+    let output = sentientos.run_prog("sync").await?;
+    assert_eq!(output, "");
+
+    let output = sentientos.run_prog("losetup /host").await?;
+    assert_eq!(output, "losetup: /host: IsADirectory\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ext2_images_are_mounted() -> anyhow::Result<()> {
+    let content = TemporaryDirectory::new("ext2-content")?;
+    std::fs::create_dir(content.0.join("bin"))?;
+    std::fs::create_dir(content.0.join("etc"))?;
+    std::fs::copy(
+        "../kernel/compiled_userspace/prog1",
+        content.0.join("bin/hello"),
+    )?;
+    std::fs::write(content.0.join("etc/motd"), "Welcome to ext2\n")?;
+
+    let share = TemporaryDirectory::new("ext2")?;
+    let status = std::process::Command::new("mke2fs")
+        .args(["-q", "-t", "ext2", "-d"])
+        .arg(&content.0)
+        .arg(share.0.join("disk.img"))
+        .arg("4M")
+        .status()?;
+    assert!(status.success(), "mke2fs failed");
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    let output = sentientos.run_prog("losetup /host/disk.img").await?;
+    assert_eq!(output, "/dev/loop0\n");
+
+    let output = sentientos.run_prog("mount /dev/loop0 /mnt").await?;
+    assert_eq!(output, "");
+
+    let output = sentientos.run_prog("ls /mnt").await?;
+    assert_eq!(output, "bin/\netc/\nlost+found/\n");
+
+    let output = sentientos.run_prog("cat /mnt/etc/motd").await?;
+    assert_eq!(output, "Welcome to ext2\n");
+
+    let output = sentientos.run_prog("/mnt/bin/hello").await?;
+    assert_eq!(output, "Hello from Prog1\n");
+
+    let output = sentientos.run_prog("mount /host/disk.img /mnt2").await?;
+    assert_eq!(output, "mount: /host/disk.img: NotABlockDevice\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_are_mapped() -> anyhow::Result<()> {
+    let share = TemporaryDirectory::new("mmap")?;
+    // Spans more than one page
+    let content: String = (0..2000).map(|line| format!("{line}\n")).collect();
+    std::fs::write(share.0.join("lines"), &content)?;
+    std::fs::write(share.0.join("empty"), "")?;
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    let output = sentientos.run_prog("mmap /host/lines").await?;
+    assert_eq!(output, content);
+
+    let output = sentientos.run_prog("mmap -w /host/lines").await?;
+    assert_eq!(output, format!("#{}", &content[1..]));
+
+    let output = sentientos.run_prog("cat /host/lines").await?;
+    assert_eq!(output, content, "Private changes don't reach the file");
+
+    let output = sentientos.run_prog("mmap /host/empty").await?;
+    assert_eq!(output, "mmap: /host/empty: InvalidRange\n");
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn harts_can_be_stopped_and_started_again() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    // OpenSBI picks the boot hart, it is the only one which can't be stopped
+    let harts = std::thread::available_parallelism()?.get();
+    let mut stopped = Vec::new();
+    for hart in 0..harts {
+        let output = sentientos.run_prog(&format!("hart stop {hart}")).await?;
+        match output.as_str() {
+            "OK\n" => stopped.push(hart),
+            "Error: CannotStopBootHart\n" => {}
+            _ => panic!("Unexpected output for hart {hart}: {output}"),
+        }
+    }
+    assert_eq!(stopped.len(), harts - 1);
+
+    for hart in &stopped {
+        let output = sentientos.run_prog(&format!("hart stop {hart}")).await?;
+        assert_eq!(output, "Error: AlreadyStopped\n");
+    }
+    // Everything runs on the boot hart now
+    assert_eq!(sentientos.run_prog("prog1").await?, "Hello from Prog1\n");
+
+    for hart in &stopped {
+        let output = sentientos.run_prog(&format!("hart start {hart}")).await?;
+        assert_eq!(output, "OK\n");
+    }
+    assert_eq!(sentientos.run_prog("prog1").await?, "Hello from Prog1\n");
+
+    let output = sentientos.run_prog(&format!("hart stop {harts}")).await?;
+    assert_eq!(output, "Error: InvalidHart\n");
+
+    Ok(())
+}
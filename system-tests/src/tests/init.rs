@@ -0,0 +1,60 @@
+use tokio::io::AsyncWriteExt;
+
+use crate::infra::qemu::{QemuInstance, QemuOptions};
+
+use super::hostfs::TemporaryDirectory;
+
+#[tokio::test]
+async fn services_start_in_order_of_their_dependencies() -> anyhow::Result<()> {
+    let share = TemporaryDirectory::new("services")?;
+    std::fs::write(
+        share.0.join("services"),
+        "# Test services\n\
+         setup wait - echo set up\n\
+         worker once setup echo working\n\
+         flaky respawn setup echo respawned\n\
+         main final worker,flaky sleep 500\n",
+    )?;
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    let output = sentientos.run_prog("init /host/services").await?;
+    let position = |line: &str| {
+        output
+            .lines()
+            .position(|output_line| output_line == line)
+            .unwrap_or_else(|| panic!("{line} missing in {output}"))
+    };
+    assert!(position("set up") < position("init: starting worker"));
+    assert!(position("init: setup exited") < position("init: starting flaky"));
+    assert!(position("init: starting worker") < position("init: starting main"));
+    assert!(output.matches("respawned\n").count() >= 2);
+    assert!(output.contains("init: main exited\n"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_services_fall_back_to_the_built_in_ones() -> anyhow::Result<()> {
+    let share = TemporaryDirectory::new("invalid-services")?;
+    std::fs::write(share.0.join("services"), "shell forever - sesh\n")?;
+
+    let mut sentientos = QemuInstance::start_with(QemuOptions::default().share(&share.0)).await?;
+
+    sentientos
+        .stdin()
+        .write_all(b"init /host/services\n")
+        .await?;
+    sentientos
+        .stdout()
+        .assert_read_until(
+            "init: /host/services: line 1: unknown policy forever, using the built-in services\n",
+        )
+        .await;
+    sentientos
+        .stdout()
+        .assert_read_until("init: starting shell\n")
+        .await;
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use tokio::io::AsyncWriteExt;
+
+use crate::infra::{qemu::QemuInstance, PROMPT};
+
+#[tokio::test]
+async fn c_program_runs() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos
+        .run_prog_waiting_for("chello one two", "Enter your name:\n")
+        .await?;
+    assert_eq!(
+        output,
+        "Hello from C!\nargv[1] = one\nargv[2] = two\nNumbers: 10 20 30\n"
+    );
+    sentientos.stdin().write_all(b"Ada\n").await?;
+    sentientos
+        .stdout()
+        .assert_read_until("Hello Ada, 0x002a  3.14\n")
+        .await;
+    sentientos.stdout().assert_read_until(PROMPT).await;
+
+    Ok(())
+}
@@ -1,5 +1,32 @@
 mod basics;
+mod batch;
+mod brk;
+mod core_dump;
+mod credentials;
+mod devfs;
 mod echo;
+mod floating_point;
+mod gdb_stub;
+mod hostfs;
+mod hotplug;
+mod init;
+mod libc;
 mod net;
 mod panic;
+mod perf;
+mod periodic;
+mod procfs;
+mod profiler;
+mod pty;
+mod rlimit;
+mod route;
+mod shell;
 mod signals;
+mod spawn;
+mod stack;
+mod tmpfs;
+mod ustd;
+mod vdso;
+mod vector;
+mod wait;
+mod yadump;
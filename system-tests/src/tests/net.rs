@@ -1,33 +1,206 @@
+use std::time::{Duration, Instant};
+
 use serial_test::file_serial;
 use tokio::io::AsyncWriteExt;
 
-use crate::infra::qemu::{QemuInstance, QemuOptions};
+use crate::infra::{
+    net_peer::{UdpPeer, GUEST_IP},
+    qemu::{QemuInstance, QemuOptions},
+    PROMPT,
+};
+
+const UDP_PORT: u16 = 1234;
+const RSH_PORT: u16 = 2323;
 
 #[file_serial]
 #[tokio::test]
 async fn udp() -> anyhow::Result<()> {
-    let mut sentientos = QemuInstance::start_with(QemuOptions::default().add_network_card(true)).await?;
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().forward_udp(UDP_PORT)).await?;
 
-    sentientos.run_prog_waiting_for("udp", "Listening on 1234\n")
+    sentientos
+        .run_prog_waiting_for("udp", "Listening on 1234\n")
         .await
         .expect("udp program must succeed to start");
 
-    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
-    socket.connect("127.0.0.1:1234").await?;
+    let peer = sentientos.udp_peer(UDP_PORT).await?;
 
-    socket.send("42\n".as_bytes()).await?;
+    peer.send("42\n".as_bytes()).await?;
     sentientos.stdout().assert_read_until("42\n").await;
 
-    sentientos.stdin().write("Hello from SentientOS!\n".as_bytes()).await?;
-
-    let mut buf = [0; 128];
-    let bytes = socket.recv(&mut buf).await?;
-    let response = String::from_utf8_lossy(&buf[0..bytes]);
+    sentientos
+        .stdin()
+        .write_all("Hello from SentientOS!\n".as_bytes())
+        .await?;
 
+    let response = peer.receive_until("\n").await?;
     assert_eq!(response, "Hello from SentientOS!\n");
 
-    socket.send("Finalize test\n".as_bytes()).await?;
-    sentientos.stdout().assert_read_until("Finalize test\n").await;
+    peer.send("Finalize test\n".as_bytes()).await?;
+    sentientos
+        .stdout()
+        .assert_read_until("Finalize test\n")
+        .await;
+
+    Ok(())
+}
+
+#[file_serial]
+#[tokio::test]
+async fn remote_shell() -> anyhow::Result<()> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().forward_tcp(RSH_PORT)).await?;
+
+    sentientos
+        .run_prog_waiting_for("rsh", "Remote shell listening on 2323\n")
+        .await
+        .expect("rsh program must succeed to start");
+
+    let mut peer = sentientos.tcp_peer(RSH_PORT).await?;
+    peer.receive_until("$ ").await?;
+
+    // Already the first input reaches the shell
+    peer.send("echo remote\n".as_bytes()).await?;
+    let output = peer.receive_until("$ ").await?;
+    assert!(output.contains("remote\n"));
+
+    // The connection is closed once the shell exits
+    peer.send("exit\n".as_bytes()).await?;
+    peer.receive_until_closed().await?;
+
+    // The next client gets a shell of its own
+    let mut peer = sentientos.tcp_peer(RSH_PORT).await?;
+    peer.receive_until("$ ").await?;
+    peer.send("echo again\n".as_bytes()).await?;
+    let output = peer.receive_until("$ ").await?;
+    assert!(output.contains("again\n"));
+
+    Ok(())
+}
+
+#[file_serial]
+#[tokio::test]
+async fn guest_starts_udp_conversation() -> anyhow::Result<()> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().add_network_card(true)).await?;
+    let (peer, port) = UdpPeer::listen().await?;
+
+    // The host is reachable at the gateway
+    sentientos
+        .stdin()
+        .write_all(format!("udp_client 10.0.2.2 {port} ping\n").as_bytes())
+        .await?;
+    assert_eq!(peer.accept().await?, b"ping");
+
+    peer.send("pong".as_bytes()).await?;
+    sentientos.stdout().assert_read_until("Reply: pong\n").await;
+
+    Ok(())
+}
+
+async fn start_udp_echo() -> anyhow::Result<QemuInstance> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().forward_udp(UDP_PORT)).await?;
+    sentientos
+        .run_prog_waiting_for("udp_echo", "Echoing on 1234\n")
+        .await?;
+    Ok(sentientos)
+}
+
+// Every byte value must survive the way through the stack
+fn payload(sequence: usize, length: usize) -> Vec<u8> {
+    (0..length).map(|index| (sequence + index) as u8).collect()
+}
+
+#[file_serial]
+#[tokio::test]
+async fn udp_echo_is_correct() -> anyhow::Result<()> {
+    let sentientos = start_udp_echo().await?;
+    let peer = sentientos.udp_peer(UDP_PORT).await?;
+
+    for (sequence, length) in [1, 2, 63, 64, 65, 512, 1000, 1400].into_iter().enumerate() {
+        let data = payload(sequence, length);
+        peer.send(&data).await?;
+        assert_eq!(
+            peer.receive_exactly(length).await?,
+            data,
+            "Datagram of {length} bytes"
+        );
+    }
+
+    Ok(())
+}
+
+#[file_serial]
+#[tokio::test]
+async fn udp_echo_throughput() -> anyhow::Result<()> {
+    const DATAGRAMS: usize = 500;
+    const DATAGRAM_SIZE: usize = 1024;
+    // Far below what the stack manages, only catches severe regressions
+    const MINIMUM_BYTES_PER_SECOND: f64 = 16.0 * 1024.0;
+
+    let sentientos = start_udp_echo().await?;
+    let peer = sentientos.udp_peer(UDP_PORT).await?;
+
+    let start = Instant::now();
+    // One datagram at a time, the guest drops datagrams if it can't keep up
+    for sequence in 0..DATAGRAMS {
+        let data = payload(sequence, DATAGRAM_SIZE);
+        peer.send(&data).await?;
+        assert_eq!(peer.receive_exactly(DATAGRAM_SIZE).await?, data);
+    }
+    let elapsed = start.elapsed().max(Duration::from_millis(1));
+
+    let bytes_per_second = (2 * DATAGRAMS * DATAGRAM_SIZE) as f64 / elapsed.as_secs_f64();
+    eprintln!(
+        "Echoed {DATAGRAMS} datagrams of {DATAGRAM_SIZE} bytes in {elapsed:?} ({:.1} KiB/s)",
+        bytes_per_second / 1024.0
+    );
+    assert!(bytes_per_second > MINIMUM_BYTES_PER_SECOND);
+
+    Ok(())
+}
+
+#[file_serial]
+#[tokio::test]
+#[ignore = "needs the tap device described in qemu_wrapper.sh"]
+async fn udp_echo_on_multiple_queue_pairs() -> anyhow::Result<()> {
+    const QUEUE_PAIRS: usize = 2;
+    // Every flow is steered to one queue of the tap device
+    const FLOWS: usize = 16;
+
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().net_queues(QUEUE_PAIRS)).await?;
+    sentientos
+        .run_prog_waiting_for("udp_echo &", "Echoing on 1234\n")
+        .await?;
+
+    for sequence in 0..FLOWS {
+        let peer = UdpPeer::connect_directly((GUEST_IP, UDP_PORT).into()).await?;
+        let data = payload(sequence, 64);
+        peer.send(&data).await?;
+        assert_eq!(peer.receive_exactly(data.len()).await?, data);
+    }
+
+    // There is a pair per hart, up to the number QEMU offers
+    let harts = std::thread::available_parallelism()?.get();
+    // The prompt of the background job might still be pending
+    sentientos
+        .stdin()
+        .write_all("cat /proc/net/queues\n".as_bytes())
+        .await?;
+    sentientos
+        .stdout()
+        .assert_read_until("Pair\tReceived\n")
+        .await;
+    let table = sentientos.stdout().assert_read_until(PROMPT).await;
+    let table = String::from_utf8_lossy(&table);
+    let received: Vec<usize> = table
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1)?.parse().ok())
+        .collect();
+    assert_eq!(received.len(), QUEUE_PAIRS.min(harts), "{table}");
+    assert!(received.iter().all(|&packets| packets > 0), "{table}");
 
     Ok(())
 }
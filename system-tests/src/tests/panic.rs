@@ -15,5 +15,33 @@ async fn panic() -> anyhow::Result<()> {
     assert!(output
         .contains("[info][kernel::debugging] Current Process: PID=3 NAME=panic STATE=Running"));
 
+    // Every other hart dumps its state after the panicking one
+    let panicking_hart: usize = output
+        .split_once("KERNEL Panic Occured on cpu ")
+        .and_then(|(_, rest)| rest.split_once('!'))
+        .and_then(|(hart, _)| hart.parse().ok())
+        .expect("Panicking hart must be printed");
+    let harts = std::thread::available_parallelism()?.get();
+    for hart in (0..harts).filter(|&hart| hart != panicking_hart) {
+        assert!(
+            output.contains(&format!("State of hart {hart}:\nPID: ")),
+            "Hart {hart} missing in {output}"
+        );
+    }
+
+    let crash_dump = output
+        .split_once("-----BEGIN CRASH DUMP-----\n")
+        .and_then(|(_, rest)| rest.split_once("-----END CRASH DUMP-----"))
+        .map(|(dump, _)| dump)
+        .expect("Crash dump must be printed");
+    assert!(crash_dump
+        .lines()
+        .all(|line| line.starts_with('{') && line.ends_with('}')));
+    assert!(crash_dump.contains("\"message\":\"Userspace triggered kernel panic\""));
+    assert!(crash_dump
+        .contains("{\"type\":\"process\",\"pid\":3,\"name\":\"panic\",\"state\":\"Running\""));
+    assert!(crash_dump.contains("{\"type\":\"hart\",\"hart\":0,\"pid\":3,"));
+    assert!(crash_dump.contains("{\"type\":\"log\",\"tail\":"));
+
     Ok(())
 }
@@ -0,0 +1,16 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn performance_counters_are_readable() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("perf").await?;
+
+    assert!(output.contains("Sum: 30000"));
+    assert!(output.contains("Loop: 10000 iterations, per iteration:"));
+    assert!(output.contains("Hart counters advance: true"));
+    assert!(output.contains("Process counters advance: true"));
+    assert!(output.contains("Instructions counted: true"));
+
+    Ok(())
+}
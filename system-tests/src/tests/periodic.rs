@@ -0,0 +1,15 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn periodic_processes_wait_for_their_periods() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("periodic").await?;
+
+    assert!(output.contains("Waiting without reservation: Err(NotPeriodic)"));
+    assert!(output.contains("Budget above the period: Err(InvalidReservation)"));
+    assert!(output.contains("Reserving more than admitted: Err(Overloaded)"));
+    assert!(output.contains("Waited for 5 periods: true"));
+
+    Ok(())
+}
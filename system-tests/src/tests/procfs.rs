@@ -0,0 +1,151 @@
+use crate::infra::qemu::{QemuInstance, QemuOptions};
+
+#[tokio::test]
+async fn kernel_state_is_readable() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("cat /proc/meminfo").await?;
+    assert!(output.starts_with("MemTotal:"));
+    assert!(output.lines().any(|line| line.starts_with("DmaBuffers:\t")));
+    assert!(output
+        .lines()
+        .any(|line| line.starts_with("CachedKernelStacks:\t")));
+
+    // The console fires for every typed character
+    let output = sentientos.run_prog("cat /proc/interrupts").await?;
+    assert!(output.lines().any(|line| line.starts_with("  10: ")));
+
+    let output = sentientos.run_prog("cat /proc/syscalls").await?;
+    assert!(output
+        .lines()
+        .any(|line| line == "  3 sys_exit(status: isize) -> ()"));
+
+    let output = sentientos.run_prog("cat /proc/net/arp").await?;
+    assert!(output.starts_with("IP address"));
+
+    let output = sentientos.run_prog("ls /proc").await?;
+    assert!(output.lines().any(|line| line == "1/"));
+    assert!(output.lines().any(|line| line == "self/"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn processes_have_directories() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("cat /proc/1/status").await?;
+    assert!(output.contains("Pid:\t1\n"));
+
+    let output = sentientos.run_prog("cat /proc/self/status").await?;
+    assert!(output.starts_with("Name:\tcat\n"));
+
+    let output = sentientos.run_prog("cat /proc/self/maps").await?;
+    assert!(output.lines().all(|line| line.contains(", U)")));
+
+    let output = sentientos.run_prog("cat /proc/self/fd").await?;
+    assert_eq!(output, "0 /proc/self/fd\n");
+
+    let output = sentientos.run_prog("cat /proc/100000/status").await?;
+    assert_eq!(output, "cat: /proc/100000/status: NotFound\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn io_is_accounted() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("cat /proc/self/io").await?;
+    assert!(output.starts_with("Syscalls:\t"));
+    assert!(output.contains("\nBytesSent:\t0\n"));
+
+    let output = sentientos.run_prog("iostat").await?;
+    let syscalls = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Syscalls: "))
+        .expect("Syscalls must be shown");
+    assert!(syscalls.parse::<u64>()? > 0);
+    assert!(output.contains("Sent: 0 bytes"));
+
+    let output = sentientos.run_prog("iostat 100000").await?;
+    assert_eq!(output, "iostat: InvalidPid\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn event_counters_are_listed() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("cat /proc/counters").await?;
+    assert!(output
+        .lines()
+        .any(|line| line.starts_with("memory.heap_allocations\t")));
+    assert!(output
+        .lines()
+        .any(|line| line.starts_with("scheduler.context_switches\t")));
+
+    // The same listing through the syscall
+    let output = sentientos.run_prog("counters").await?;
+    let timer_interrupts = output
+        .lines()
+        .find_map(|line| line.strip_prefix("interrupts.timer\t"))
+        .expect("Timer interrupts must be counted");
+    assert!(timer_interrupts.parse::<u64>()? > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn boot_stages_are_timed() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("cat /proc/boot").await?;
+    for stage in [
+        "early",
+        "page allocator",
+        "device tree",
+        "platform",
+        "filesystems",
+        "pci",
+        "processes",
+        "drivers",
+        "harts",
+        "scheduler",
+    ] {
+        assert!(
+            output.lines().any(|line| line.starts_with(stage)),
+            "Stage {stage} is missing"
+        );
+    }
+    let total: Vec<u64> = output
+        .lines()
+        .find_map(|line| line.strip_prefix("total"))
+        .expect("The total must be listed")
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    assert!(total.iter().all(|value| *value > 0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn page_owners_are_listed() -> anyhow::Result<()> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().page_debugging(true)).await?;
+
+    // Frees pages which are allocated again by the next programs
+    sentientos.run_prog("stack").await?;
+
+    let output = sentientos.run_prog("cat /proc/page_owner").await?;
+    assert!(output.starts_with("PoisonViolations:\t0\n"));
+    assert!(output.lines().any(|line| line.ends_with("\theap")));
+    // The shell and cat
+    assert!(output.lines().any(|line| line.ends_with("\tPID 1")));
+    assert!(output.contains(" pages in "));
+    assert!(!output.contains("Freed page written"));
+
+    Ok(())
+}
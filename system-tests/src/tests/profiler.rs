@@ -0,0 +1,26 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn profile_lists_hotspots() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("profile loop").await?;
+    assert!(output.contains("Looping... 9"));
+
+    let samples = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Samples: "))
+        .and_then(|line| line.split_whitespace().next())
+        .expect("The profile must be printed");
+    assert!(samples.parse::<u64>()? > 0);
+    assert!(
+        output.lines().any(|line| line.contains("% loop: ")),
+        "The program itself must be sampled"
+    );
+
+    // The samples are kept until the next run
+    let output = sentientos.run_prog("cat /proc/profile").await?;
+    assert!(output.lines().any(|line| line.contains("% loop: ")));
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn output_of_program_on_pty() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("pty echo 1 2 3").await?;
+    assert_eq!(output, "1 2 3\n");
+
+    let output = sentientos.run_prog("pty").await?;
+    assert_eq!(output, "Usage: pty <program> [args...]\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn master_blocks_until_the_program_exits() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    // The output is read while the program still runs and pauses
+    let output = sentientos.run_prog("pty loop").await?;
+    assert!(output.starts_with("Hello from Loop\n"), "{output}");
+    assert!(output.ends_with("Looping... 9\n"), "{output}");
+
+    // Nothing is written, the end of the output is noticed anyway
+    let output = sentientos.run_prog("pty sleep 100").await?;
+    assert_eq!(output, "");
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn resource_limits_are_enforced() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("rlimit").await?;
+
+    assert!(output.contains("Mapping 16 pages succeeded: true"));
+    assert!(output.contains("Mapping 16 pages above the limit succeeded: false"));
+    assert!(output.contains("Raising the hard limit: Err(HardLimitRaised)"));
+    assert!(output.contains("Kill PID=3 NAME=rlimit: CPU time limit exceeded"));
+
+    let output = sentientos.run_prog("prog1").await?;
+    assert_eq!(output, "Hello from Prog1\n");
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use serial_test::file_serial;
+
+use crate::infra::qemu::{QemuInstance, QemuOptions};
+
+// The network card forwards fixed host ports
+#[file_serial]
+#[tokio::test]
+async fn routes_are_added() -> anyhow::Result<()> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().add_network_card(true)).await?;
+
+    let output = sentientos
+        .run_prog("route add 192.168.0.0/16 10.0.2.2")
+        .await?;
+    assert_eq!(output, "OK\n");
+
+    // Replaces the route above
+    let output = sentientos.run_prog("route add 192.168.0.0/16").await?;
+    assert_eq!(output, "OK\n");
+
+    let output = sentientos.run_prog("route add 192.168.0.1/16").await?;
+    assert_eq!(output, "Error: InvalidRoute\n");
+
+    let output = sentientos
+        .run_prog("route add 172.16.0.0/12 192.168.1.1")
+        .await?;
+    assert_eq!(output, "OK\n");
+
+    let output = sentientos
+        .run_prog("route add 10.10.0.0/16 8.8.8.8")
+        .await?;
+    assert_eq!(output, "Error: GatewayNotOnLink\n");
+
+    Ok(())
+}
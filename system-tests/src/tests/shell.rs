@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use crate::infra::{expect::Script, qemu::QemuInstance, PROMPT};
+
+#[tokio::test]
+async fn interactive_session() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let matches = Script::new()
+        .timeout(Duration::from_secs(5))
+        .send_line("export GREETING=hello")
+        .expect(PROMPT)
+        .send_line("env")
+        .expect_regex(r"GREETING=(\w+)")
+        .expect(PROMPT)
+        .send_line("id")
+        .expect_regex(r"uid=(?<uid>\d+) gid=(?<gid>\d+)")
+        .expect(PROMPT)
+        .run(&mut sentientos)
+        .await?;
+
+    assert_eq!(matches.len(), 5);
+    assert_eq!(matches[1].get(1), Some("hello"));
+    assert_eq!(matches[3].name("uid"), Some("0"));
+    assert_eq!(matches[3].name("gid"), Some("0"));
+    assert_eq!(matches[4].before, "\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn working_directory_is_inherited() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("pwd").await?;
+    assert_eq!(output, "/\n");
+
+    let output = sentientos.run_prog("cd /proc/net").await?;
+    assert_eq!(output, "");
+    let output = sentientos.run_prog("pwd").await?;
+    assert_eq!(output, "/proc/net\n");
+
+    let output = sentientos.run_prog("ls").await?;
+    assert_eq!(output, "arp\n");
+    let output = sentientos.run_prog("cat ../self/status").await?;
+    assert!(output.starts_with("Name:\tcat\n"));
+
+    let output = sentientos.run_prog("cd ../meminfo").await?;
+    assert_eq!(output, "cd: ../meminfo: NotADirectory\n");
+
+    let output = sentientos.run_prog("cd ../..").await?;
+    assert_eq!(output, "");
+    let output = sentientos.run_prog("pwd").await?;
+    assert_eq!(output, "/\n");
+
+    Ok(())
+}
@@ -36,3 +36,32 @@ async fn should_not_exit_sesh() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn ctrl_z_stops_the_program_until_it_is_continued() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    sentientos
+        .run_prog_waiting_for("loop", "Looping... 0")
+        .await?;
+    sentientos.stdin().write_all(&[0x1a]).await?;
+    let output = sentientos.stdout().assert_read_until(PROMPT).await;
+    assert!(String::from_utf8_lossy(&output).contains("Stopped loop"));
+
+    let jobs = sentientos.run_prog("jobs").await?;
+    let pid = jobs
+        .strip_prefix('[')
+        .and_then(|jobs| jobs.strip_suffix("] Stopped loop\n"))
+        .expect("The stopped job must be listed");
+    let status = sentientos
+        .run_prog(&format!("cat /proc/{pid}/status"))
+        .await?;
+    assert!(status.contains("State:\tStopped\n"), "{status}");
+
+    sentientos.run_prog_waiting_for("fg", "Looping...").await?;
+    sentientos.stdin().write_all(&[0x03]).await?;
+    sentientos.stdout().assert_read_until(PROMPT).await;
+    assert_eq!(sentientos.run_prog("jobs").await?, "");
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn spawn_sets_up_the_program_before_it_runs() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("spawn").await?;
+    for line in [
+        "5 /etc/services",
+        "services",
+        "Spawned: Stopped",
+        "Resumed: Ok(())",
+        "Resumed again: Err(NotStopped)",
+        "Unknown descriptor: Err(InvalidDescriptor)",
+        "Not a directory: Err(InvalidWorkingDirectory)",
+    ] {
+        assert!(
+            output.lines().any(|l| l == line),
+            "{line} missing in {output}"
+        );
+    }
+
+    Ok(())
+}
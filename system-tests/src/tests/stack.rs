@@ -0,0 +1,16 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn stack_grows_up_to_its_limit() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("stack").await?;
+    assert!(output.contains("Sum of 64 KiB on the stack: 8355840"));
+
+    let output = sentientos.run_prog("stack -l").await?;
+    assert!(output.contains("Lowered the stack limit to 4 pages"));
+    assert!(output.contains("Process stack (PID=4) crashed"));
+    assert!(!output.contains("Sum of"));
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn files_are_created_moved_and_removed() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    sentientos.run_prog("mkdir /tmp/dir").await?;
+    sentientos.run_prog("touch /tmp/dir/file").await?;
+    let output = sentientos.run_prog("ls /tmp").await?;
+    assert_eq!(output, "dir/\n");
+
+    let output = sentientos.run_prog("stat /tmp/dir/file").await?;
+    assert_eq!(output, "/tmp/dir/file: File 0 bytes\n");
+
+    let output = sentientos.run_prog("rm /tmp/dir").await?;
+    assert_eq!(output, "rm: /tmp/dir: DirectoryNotEmpty\n");
+
+    sentientos.run_prog("mv /tmp/dir/file /tmp/moved").await?;
+    let output = sentientos.run_prog("ls /tmp/dir").await?;
+    assert_eq!(output, "");
+
+    let output = sentientos.run_prog("mv /tmp/moved /dev/moved").await?;
+    assert_eq!(output, "mv: /tmp/moved: CrossDevice\n");
+
+    sentientos.run_prog("rm /tmp/moved /tmp/dir").await?;
+    let output = sentientos.run_prog("ls /tmp").await?;
+    assert_eq!(output, "");
+
+    let output = sentientos.run_prog("stat /tmp/dir").await?;
+    assert_eq!(output, "stat: /tmp/dir: NotFound\n");
+
+    let output = sentientos.run_prog("mkdir /proc/dir").await?;
+    assert_eq!(output, "mkdir: /proc/dir: ReadOnly\n");
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use tokio::io::AsyncWriteExt;
+
+use crate::infra::{qemu::QemuInstance, PROMPT};
+
+#[tokio::test]
+async fn environment_is_inherited() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("env").await?;
+    assert_eq!(output, "");
+
+    sentientos.run_prog("export GREETING=hello").await?;
+    sentientos.run_prog("export USER=root").await?;
+    let output = sentientos.run_prog("env").await?;
+    assert_eq!(output, "GREETING=hello\nUSER=root\n");
+
+    sentientos.run_prog("unset GREETING").await?;
+    let output = sentientos.run_prog("env").await?;
+    assert_eq!(output, "USER=root\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn formatted_input() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    sentientos
+        .run_prog_waiting_for("sum", "Enter numbers, 0 ends the input:\n")
+        .await?;
+    sentientos.stdin().write_all(b"1 2 3\n4 0\n").await?;
+    sentientos.stdout().assert_read_until("Sum: 10\n").await;
+    sentientos.stdout().assert_read_until(PROMPT).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn panic_prints_backtrace() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("backtrace").await?;
+    assert!(output.contains("Message: Panic with backtrace"));
+    assert!(output.contains("Backtrace:"));
+    assert!(output.contains("<backtrace::inner+"));
+    assert!(output.contains("<backtrace::outer+"));
+    assert!(output.contains("<main+"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_runtime_runs_timers_and_stdin_concurrently() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    sentientos.run_prog_waiting_for("tick", "tick 3\n").await?;
+    sentientos.stdin().write_all(b"hello\n").await?;
+    sentientos.stdout().assert_read_until("Got: hello\n").await;
+    sentientos.stdout().assert_read_until(PROMPT).await;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reads_give_up_at_their_deadline() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("read 100").await?;
+    assert_eq!(output, "read: timed out\n");
+
+    sentientos.stdin().write_all(b"read 10000\nhello\n").await?;
+    sentientos.stdout().assert_read_until("Read: hello\n").await;
+    sentientos.stdout().assert_read_until(PROMPT).await;
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn vdso_is_readable_without_syscalls() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("vdso").await?;
+
+    assert!(output.contains("Timebase frequency: 10000000\n"));
+    assert!(output.contains("PID: 3\n"));
+    assert!(output.contains("Hart is valid: true\n"));
+    assert!(output.contains("Time is monotonic: true\n"));
+
+    Ok(())
+}
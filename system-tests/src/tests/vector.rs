@@ -0,0 +1,15 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn vector_registers_are_kept_per_process() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("vector").await?;
+
+    for worker in 1..=4 {
+        assert!(output.contains(&format!("Worker {worker}: vector registers kept: true")));
+    }
+    assert!(output.contains("Done!"));
+
+    Ok(())
+}
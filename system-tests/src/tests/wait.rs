@@ -0,0 +1,42 @@
+use crate::infra::qemu::QemuInstance;
+
+#[tokio::test]
+async fn children_are_reaped_with_their_status() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("reap").await?;
+    for line in [
+        "Without children: Err(NoChildren)",
+        "Not a child: Err(InvalidPid)",
+        "Waiting for init: Err(InvalidPid)",
+        "Still running: Ok(None)",
+        "segfault: Killed",
+        "sleep: Exited(0)",
+        "All reaped: Err(NoChildren)",
+    ] {
+        assert!(
+            output.lines().any(|l| l == line),
+            "{line} missing in {output}"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn orphans_are_adopted_by_init() -> anyhow::Result<()> {
+    let mut sentientos = QemuInstance::start().await?;
+
+    let output = sentientos.run_prog("reap orphan").await?;
+    let pid = output
+        .trim()
+        .strip_prefix("Orphan: ")
+        .expect("Pid of the orphan must be printed");
+
+    let output = sentientos
+        .run_prog(&format!("cat /proc/{pid}/status"))
+        .await?;
+    assert!(output.contains("PPid:\t1\n"), "{output}");
+
+    Ok(())
+}
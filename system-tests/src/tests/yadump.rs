@@ -0,0 +1,26 @@
+use serial_test::file_serial;
+
+use crate::infra::qemu::{QemuInstance, QemuOptions};
+
+const UDP_PORT: u16 = 1234;
+
+#[file_serial]
+#[tokio::test]
+async fn received_frames_are_captured() -> anyhow::Result<()> {
+    let mut sentientos =
+        QemuInstance::start_with(QemuOptions::default().forward_udp(UDP_PORT)).await?;
+
+    sentientos
+        .run_prog_waiting_for("yadump", "Capturing frames\n")
+        .await?;
+
+    let peer = sentientos.udp_peer(UDP_PORT).await?;
+    peer.send("hello".as_bytes()).await?;
+
+    sentientos
+        .stdout()
+        .assert_read_until("> 10.0.2.15:1234 UDP length 5\n")
+        .await;
+
+    Ok(())
+}
@@ -10,6 +10,14 @@ pub fn args() -> Args {
     Args::new(*ARGS_START)
 }
 
+/// The environment follows the arguments in the same format
+pub(crate) fn environment() -> Args {
+    let mut args = args();
+    for _ in args.by_ref() {}
+    // Skip the empty string which terminates the arguments
+    Args::new(unsafe { args.current.add(1) })
+}
+
 pub struct Args {
     current: *const u8,
 }
@@ -0,0 +1,78 @@
+//! Programs are compiled with frame pointers. Therefore the return
+//! addresses can be collected by following the frame records on the
+//! stack. The kernel looks up the symbols in the program file.
+
+extern crate alloc;
+
+use core::arch::asm;
+
+use alloc::string::String;
+use common::syscalls::sys_symbolize;
+
+use crate::println;
+
+const PAGE_SIZE: usize = 4096;
+const MAX_FRAMES: usize = 64;
+const MAX_SYMBOL_LENGTH: usize = 256;
+
+/// Calls `f` with the return address of every frame, starting with the
+/// caller of the current function
+#[inline(always)]
+pub fn trace(mut f: impl FnMut(usize)) {
+    let mut frame_pointer: usize;
+    let stack_pointer: usize;
+    unsafe {
+        asm!("mv {}, s0", out(reg) frame_pointer);
+        asm!("mv {}, sp", out(reg) stack_pointer);
+    }
+    // The stack is a single page
+    let stack_end = stack_pointer | (PAGE_SIZE - 1);
+
+    for _ in 0..MAX_FRAMES {
+        let Some(record) = frame_pointer.checked_sub(2 * size_of::<usize>()) else {
+            break;
+        };
+        if frame_pointer % size_of::<usize>() != 0
+            || record < stack_pointer
+            || frame_pointer > stack_end
+        {
+            break;
+        }
+        // The frame record is right below the frame pointer
+        let (previous_frame_pointer, return_address) = unsafe {
+            let record = record as *const usize;
+            (*record, *record.add(1))
+        };
+        if return_address == 0 {
+            break;
+        }
+        f(return_address);
+        // Frames of callers are always above on the stack
+        if previous_frame_pointer <= frame_pointer {
+            break;
+        }
+        frame_pointer = previous_frame_pointer;
+    }
+}
+
+/// Returns the demangled name of the function and the offset into it
+pub fn symbolize(address: usize) -> Option<(String, usize)> {
+    let mut name = [0u8; MAX_SYMBOL_LENGTH];
+    let symbol = sys_symbolize(address, &mut name).ok()?;
+    let name = String::from_utf8_lossy(&name[..symbol.name_length]).into_owned();
+    Some((name, symbol.offset))
+}
+
+pub fn print() {
+    let mut counter = 0;
+    trace(|return_address| {
+        // The return address could already belong to the next function
+        match symbolize(return_address - 1) {
+            Some((name, offset)) => {
+                println!("{counter}: {return_address:#x} <{name}+{}>", offset + 1)
+            }
+            None => println!("{counter}: {return_address:#x}"),
+        }
+        counter += 1;
+    });
+}
@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+use core::hint::black_box;
+
+extern crate userspace;
+
+#[inline(never)]
+fn outer() {
+    inner();
+    // Prevents a tail call which would remove the frame
+    black_box(());
+}
+
+#[inline(never)]
+fn inner() {
+    panic!("Panic with backtrace");
+}
+
+#[unsafe(no_mangle)]
+fn main() {
+    outer();
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+use common::{
+    deadline::NO_DEADLINE,
+    syscalls::{batch::execute, prepared, sys_open_pty},
+};
+use userspace::println;
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut hello = prepared::sys_write("Hello ");
+    let mut world = prepared::sys_write("from a batch\n");
+    let mut uid = prepared::sys_getuid();
+    let mut sleep = prepared::sys_sleep(1);
+
+    let executed = execute(&mut [&mut hello, &mut world, &mut uid, &mut sleep])
+        .expect("Requests must be valid");
+
+    println!("Executed {executed} syscalls");
+    println!("write: {:?}", hello.result());
+    println!("getuid: {:?}", uid.result());
+    println!("sleep: {:?}", sleep.result());
+
+    // Nothing was written to the pty, reading it would have to wait
+    let pty = sys_open_pty().expect("Must be able to open a pty");
+    let mut buffer = [0; 16];
+    let mut before = prepared::sys_write("Written once\n");
+    let mut read = prepared::sys_read_pty_wait(pty, &mut buffer, NO_DEADLINE);
+    let mut after = prepared::sys_getuid();
+
+    let executed =
+        execute(&mut [&mut before, &mut read, &mut after]).expect("Requests must be valid");
+
+    println!("Executed {executed} syscalls");
+    println!("read: {:?}", read.result());
+    println!("after: {:?}", after.result());
+}
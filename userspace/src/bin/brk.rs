@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::sys_brk;
+use userspace::println;
+
+extern crate userspace;
+
+const PAGE_SIZE: usize = 4096;
+const MESSAGE: &[u8] = b"Printed across a page boundary";
+
+/// Grows the heap behind the allocator, which only grows it as well and
+/// therefore never uses the memory above the current break.
+#[unsafe(no_mangle)]
+fn main() {
+    let start = sys_brk(0).expect("The break must be readable");
+    let end = start + 3 * PAGE_SIZE + 8;
+    println!("Growing the heap: {:?}", sys_brk(end).map(|_| ()));
+
+    // SAFETY: The memory between start and end belongs to the heap and is
+    // not used by the allocator
+    let heap = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, end - start) };
+    heap.fill(0xaa);
+    let boundary = PAGE_SIZE - start % PAGE_SIZE;
+    let message = &mut heap[boundary - MESSAGE.len() / 2..][..MESSAGE.len()];
+    message.copy_from_slice(MESSAGE);
+    println!(
+        "{}",
+        core::str::from_utf8(message).expect("Message must be valid UTF-8")
+    );
+
+    sys_brk(start).expect("The heap must shrink");
+    sys_brk(end).expect("The heap must grow again");
+    // SAFETY: See above
+    let heap = unsafe { core::slice::from_raw_parts(start as *const u8, end - start) };
+    println!(
+        "Pages are zeroed after shrinking: {}",
+        heap[boundary..].iter().all(|byte| *byte == 0)
+    );
+
+    println!("Break below the heap: {:?}", sys_brk(1));
+}
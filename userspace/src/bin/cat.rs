@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+use alloc::string::String;
+use userspace::{args, fs::File, print, println};
+
+extern crate alloc;
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1).peekable();
+    if args.peek().is_none() {
+        println!("Usage: cat <path>...");
+        return;
+    }
+    for path in args {
+        match File::open(path).and_then(|mut file| file.read_to_end()) {
+            Ok(data) => print!("{}", String::from_utf8_lossy(&data)),
+            Err(err) => println!("cat: {path}: {err:?}"),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+use alloc::vec;
+use common::syscalls::sys_read_counters;
+use userspace::{print, println};
+
+extern crate alloc;
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    // Counters might be registered between the calls
+    let mut buffer = vec![0; 1024];
+    loop {
+        match sys_read_counters(&mut buffer) {
+            Ok(length) if length <= buffer.len() => {
+                print!(
+                    "{}",
+                    core::str::from_utf8(&buffer[..length]).unwrap_or_default()
+                );
+                return;
+            }
+            Ok(length) => buffer.resize(length * 2, 0),
+            Err(err) => {
+                println!("counters: {err:?}");
+                return;
+            }
+        }
+    }
+}
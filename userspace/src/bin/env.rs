@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+use userspace::{env, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    for (key, value) in env::vars() {
+        println!("{key}={value}");
+    }
+}
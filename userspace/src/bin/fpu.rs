@@ -0,0 +1,166 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::{sys_execute, sys_wait};
+use core::arch::asm;
+use userspace::{args, println};
+
+use alloc::{format, vec::Vec};
+
+extern crate alloc;
+extern crate userspace;
+
+const WORKERS: u64 = 4;
+const ROUNDS: u64 = 3;
+// Long enough to be preempted several times
+const ITERATIONS: u64 = 20_000_000;
+
+/// Loads `values` and the rounding mode into the floating point
+/// registers, spins and writes the registers back
+fn spin_with_registers(values: &mut [u64; 32], rounding_mode: u64) -> u64 {
+    let rounding_mode_after: u64;
+    // SAFETY: All registers which are written are declared as outputs
+    unsafe {
+        asm!(
+            "fld f0, 0({values})",
+            "fld f1, 8({values})",
+            "fld f2, 16({values})",
+            "fld f3, 24({values})",
+            "fld f4, 32({values})",
+            "fld f5, 40({values})",
+            "fld f6, 48({values})",
+            "fld f7, 56({values})",
+            "fld f8, 64({values})",
+            "fld f9, 72({values})",
+            "fld f10, 80({values})",
+            "fld f11, 88({values})",
+            "fld f12, 96({values})",
+            "fld f13, 104({values})",
+            "fld f14, 112({values})",
+            "fld f15, 120({values})",
+            "fld f16, 128({values})",
+            "fld f17, 136({values})",
+            "fld f18, 144({values})",
+            "fld f19, 152({values})",
+            "fld f20, 160({values})",
+            "fld f21, 168({values})",
+            "fld f22, 176({values})",
+            "fld f23, 184({values})",
+            "fld f24, 192({values})",
+            "fld f25, 200({values})",
+            "fld f26, 208({values})",
+            "fld f27, 216({values})",
+            "fld f28, 224({values})",
+            "fld f29, 232({values})",
+            "fld f30, 240({values})",
+            "fld f31, 248({values})",
+            "fsrm {rounding_mode}",
+            "1:",
+            "addi {counter}, {counter}, -1",
+            "bnez {counter}, 1b",
+            "frrm {rounding_mode}",
+            "fsd f0, 0({values})",
+            "fsd f1, 8({values})",
+            "fsd f2, 16({values})",
+            "fsd f3, 24({values})",
+            "fsd f4, 32({values})",
+            "fsd f5, 40({values})",
+            "fsd f6, 48({values})",
+            "fsd f7, 56({values})",
+            "fsd f8, 64({values})",
+            "fsd f9, 72({values})",
+            "fsd f10, 80({values})",
+            "fsd f11, 88({values})",
+            "fsd f12, 96({values})",
+            "fsd f13, 104({values})",
+            "fsd f14, 112({values})",
+            "fsd f15, 120({values})",
+            "fsd f16, 128({values})",
+            "fsd f17, 136({values})",
+            "fsd f18, 144({values})",
+            "fsd f19, 152({values})",
+            "fsd f20, 160({values})",
+            "fsd f21, 168({values})",
+            "fsd f22, 176({values})",
+            "fsd f23, 184({values})",
+            "fsd f24, 192({values})",
+            "fsd f25, 200({values})",
+            "fsd f26, 208({values})",
+            "fsd f27, 216({values})",
+            "fsd f28, 224({values})",
+            "fsd f29, 232({values})",
+            "fsd f30, 240({values})",
+            "fsd f31, 248({values})",
+            values = in(reg) values.as_mut_ptr(),
+            counter = inout(reg) ITERATIONS => _,
+            rounding_mode = inout(reg) rounding_mode => rounding_mode_after,
+            out("f0") _,
+            out("f1") _,
+            out("f2") _,
+            out("f3") _,
+            out("f4") _,
+            out("f5") _,
+            out("f6") _,
+            out("f7") _,
+            out("f8") _,
+            out("f9") _,
+            out("f10") _,
+            out("f11") _,
+            out("f12") _,
+            out("f13") _,
+            out("f14") _,
+            out("f15") _,
+            out("f16") _,
+            out("f17") _,
+            out("f18") _,
+            out("f19") _,
+            out("f20") _,
+            out("f21") _,
+            out("f22") _,
+            out("f23") _,
+            out("f24") _,
+            out("f25") _,
+            out("f26") _,
+            out("f27") _,
+            out("f28") _,
+            out("f29") _,
+            out("f30") _,
+            out("f31") _,
+        );
+    }
+    rounding_mode_after
+}
+
+fn worker(seed: u64) {
+    let mut kept = true;
+    for round in 0..ROUNDS {
+        let expected: [u64; 32] =
+            core::array::from_fn(|index| (seed << 32) | (round << 8) | index as u64);
+        let mut values = expected;
+        // Valid rounding modes are 0 to 4
+        let rounding_mode = (seed + round) % 5;
+        kept &= spin_with_registers(&mut values, rounding_mode) == rounding_mode;
+        kept &= values == expected;
+    }
+    println!("Worker {seed}: registers kept: {kept}");
+}
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    if let (Some("worker"), Some(seed)) = (args.next(), args.next()) {
+        worker(seed.parse().expect("Seed must be a number"));
+        return;
+    }
+
+    let pids: Vec<u64> = (1..=WORKERS)
+        .map(|seed| {
+            sys_execute("fpu", &["worker", &format!("{seed}")])
+                .expect("Process must be successfully startable")
+        })
+        .collect();
+    for pid in pids {
+        sys_wait(pid).expect("Started programs are children");
+    }
+    println!("Done!");
+}
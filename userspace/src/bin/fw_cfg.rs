@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+use common::{mmap::MapFlags, syscalls::sys_map_physical};
+use userspace::{args, println};
+
+extern crate userspace;
+
+// The address of the fw_cfg device on the QEMU virt machine
+const DEFAULT_ADDRESS: usize = 0x1010_0000;
+const PAGE_SIZE: usize = 4096;
+
+const DATA_OFFSET: usize = 0;
+const SELECTOR_OFFSET: usize = 8;
+const SIGNATURE_SELECTOR: u16 = 0;
+
+/// Reads the signature of the QEMU firmware configuration device from
+/// userspace. Serves as an example for userspace drivers.
+#[unsafe(no_mangle)]
+fn main() {
+    let address = match args().nth(1) {
+        None => DEFAULT_ADDRESS,
+        Some(address) => {
+            let digits = address.strip_prefix("0x").unwrap_or(address);
+            let Ok(address) = usize::from_str_radix(digits, 16) else {
+                println!("Usage: fw_cfg [ADDRESS]");
+                return;
+            };
+            address
+        }
+    };
+
+    let device = match sys_map_physical(address, PAGE_SIZE, MapFlags::ReadWrite) {
+        Ok(device) => device,
+        Err(err) => {
+            println!("Cannot map fw_cfg: {:?}", err);
+            return;
+        }
+    };
+
+    let mut signature = [0u8; 4];
+    // SAFETY: The device is mapped and the registers are within the page
+    unsafe {
+        // The selector is big endian
+        device
+            .add(SELECTOR_OFFSET)
+            .cast::<u16>()
+            .write_volatile(SIGNATURE_SELECTOR.to_be());
+        for byte in &mut signature {
+            *byte = device.add(DATA_OFFSET).read_volatile();
+        }
+    }
+
+    match core::str::from_utf8(&signature) {
+        Ok(signature) => println!("fw_cfg signature: {signature}"),
+        Err(_) => println!("fw_cfg signature: {signature:?}"),
+    }
+}
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::{sys_start_hart, sys_stop_hart};
+use userspace::{args, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    let (Some(command), Some(hart_id)) = (args.next(), args.next()) else {
+        println!("Usage: hart <start|stop> <hart id>");
+        return;
+    };
+    let Ok(hart_id) = hart_id.parse::<usize>() else {
+        println!("Invalid hart id: {hart_id}");
+        return;
+    };
+    let result = match command {
+        "start" => sys_start_hart(hart_id),
+        "stop" => sys_stop_hart(hart_id),
+        _ => {
+            println!("Unknown command: {command}");
+            return;
+        }
+    };
+    match result {
+        Ok(()) => println!("OK"),
+        Err(err) => println!("Error: {err:?}"),
+    }
+}
@@ -0,0 +1,12 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::{sys_getgid, sys_getuid};
+use userspace::println;
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    println!("uid={} gid={}", sys_getuid(), sys_getgid());
+}
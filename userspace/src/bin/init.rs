@@ -1,17 +1,227 @@
 #![no_std]
 #![no_main]
 
-use common::syscalls::{sys_execute, sys_wait};
-use userspace::println;
+//! Starts the services of /etc/services (see userspace/etc/services) and
+//! supervises them. Another configuration can be passed as argument.
+//! Orphans are adopted by init and reaped here as well.
 
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use common::{
+    syscalls::{sys_execute, sys_kill, sys_sleep, sys_wait_child},
+    wait::{WaitOptions, ANY_CHILD},
+};
+use userspace::{args, fs::File, println};
+
+extern crate alloc;
 extern crate userspace;
 
+const CONFIG_PATH: &str = "/etc/services";
+// Used if the configuration can't be read
+const BUILT_IN_CONFIG: &str = include_str!("../../etc/services");
+
+// Keeps a service which exits immediately from hogging the system
+const RESPAWN_DELAY_MILLISECONDS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Policy {
+    Wait,
+    Once,
+    Respawn,
+    Final,
+}
+
+impl Policy {
+    fn parse(policy: &str) -> Option<Self> {
+        match policy {
+            "wait" => Some(Self::Wait),
+            "once" => Some(Self::Once),
+            "respawn" => Some(Self::Respawn),
+            "final" => Some(Self::Final),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Pending,
+    Running(u64),
+    Exited,
+    Failed,
+}
+
+struct Service<'a> {
+    name: &'a str,
+    policy: Policy,
+    dependencies: Vec<&'a str>,
+    program: &'a str,
+    arguments: Vec<&'a str>,
+    state: State,
+}
+
+fn parse(config: &str) -> Result<Vec<Service<'_>>, String> {
+    let mut services: Vec<Service> = Vec::new();
+    for (index, line) in config.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(policy), Some(dependencies), Some(program)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(format!(
+                "line {}: expected <name> <policy> <dependencies> <program>",
+                index + 1
+            ));
+        };
+        let policy = Policy::parse(policy)
+            .ok_or_else(|| format!("line {}: unknown policy {policy}", index + 1))?;
+        if services.iter().any(|service| service.name == name) {
+            return Err(format!("line {}: {name} is defined twice", index + 1));
+        }
+        services.push(Service {
+            name,
+            policy,
+            dependencies: if dependencies == "-" {
+                Vec::new()
+            } else {
+                dependencies.split(',').collect()
+            },
+            program,
+            arguments: fields.collect(),
+            state: State::Pending,
+        });
+    }
+
+    for service in &services {
+        for dependency in &service.dependencies {
+            if !services.iter().any(|other| other.name == *dependency) {
+                return Err(format!("{}: unknown dependency {dependency}", service.name));
+            }
+        }
+    }
+    Ok(services)
+}
+
+fn read_config(path: &str) -> Result<String, String> {
+    let data = File::open(path)
+        .and_then(|mut file| file.read_to_end())
+        .map_err(|err| format!("{err:?}"))?;
+    String::from_utf8(data).map_err(|_| "not valid UTF-8".to_string())
+}
+
+fn is_satisfied(services: &[Service], dependency: &str) -> bool {
+    services
+        .iter()
+        .filter(|service| service.name == dependency)
+        .all(|service| match service.policy {
+            Policy::Wait => service.state == State::Exited,
+            _ => matches!(service.state, State::Running(_) | State::Exited),
+        })
+}
+
+fn start(service: &mut Service) {
+    println!("init: starting {}", service.name);
+    service.state = match sys_execute(service.program, &service.arguments) {
+        Ok(pid) => State::Running(pid),
+        Err(err) => {
+            println!("init: {} can't be started: {err:?}", service.name);
+            State::Failed
+        }
+    };
+}
+
+/// Starts every pending service whose dependencies are satisfied,
+/// including the ones which depend on services started meanwhile
+fn start_ready(services: &mut [Service]) {
+    while let Some(index) = services.iter().position(|service| {
+        service.state == State::Pending
+            && service
+                .dependencies
+                .iter()
+                .all(|dependency| is_satisfied(services, dependency))
+    }) {
+        start(&mut services[index]);
+    }
+}
+
+/// Blocks until a service exited and returns its index. Adopted
+/// orphans are reaped meanwhile.
+fn wait_for_exit(services: &[Service]) -> usize {
+    loop {
+        let exit = sys_wait_child(ANY_CHILD, WaitOptions::Block)
+            .expect("Running services are children")
+            .expect("Blocking waits return a child");
+        if let Some(index) = services
+            .iter()
+            .position(|service| service.state == State::Running(exit.pid))
+        {
+            return index;
+        }
+    }
+}
+
+fn stop_all(services: &mut [Service]) {
+    for service in services {
+        if let State::Running(pid) = service.state {
+            println!("init: stopping {}", service.name);
+            let _ = sys_kill(pid);
+            service.state = State::Exited;
+        }
+    }
+}
+
+fn supervise(services: &mut [Service]) {
+    loop {
+        start_ready(services);
+        if !services
+            .iter()
+            .any(|service| matches!(service.state, State::Running(_)))
+        {
+            let stuck: Vec<&str> = services
+                .iter()
+                .filter(|service| service.state == State::Pending)
+                .map(|service| service.name)
+                .collect();
+            if !stuck.is_empty() {
+                println!("init: dependencies never met: {}", stuck.join(", "));
+            }
+            return;
+        }
+
+        let service = &mut services[wait_for_exit(services)];
+        println!("init: {} exited", service.name);
+        service.state = State::Exited;
+        match service.policy {
+            Policy::Final => {
+                stop_all(services);
+                return;
+            }
+            Policy::Respawn => {
+                sys_sleep(RESPAWN_DELAY_MILLISECONDS);
+                service.state = State::Pending;
+            }
+            Policy::Wait | Policy::Once => {}
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 fn main() {
     println!("init process started");
-    println!("starting shell");
-    let shell_name = "sesh";
-    let shell_pid = sys_execute(shell_name, &[]).unwrap();
-    sys_wait(shell_pid as u64).unwrap();
-    println!("Initial shell has exited...");
+    let path = args().nth(1).unwrap_or(CONFIG_PATH);
+    let config = read_config(path).unwrap_or_else(|err| {
+        println!("init: {path}: {err}, using the built-in services");
+        BUILT_IN_CONFIG.to_string()
+    });
+    let mut services = parse(&config).unwrap_or_else(|err| {
+        println!("init: {path}: {err}, using the built-in services");
+        parse(BUILT_IN_CONFIG).expect("The built-in services must be valid")
+    });
+    supervise(&mut services);
 }
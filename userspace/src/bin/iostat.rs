@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::sys_process_info;
+use userspace::{args, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    // Without a pid our own statistics are shown
+    let pid = match args().nth(1).map(str::parse::<u64>) {
+        None => 0,
+        Some(Ok(pid)) => pid,
+        Some(Err(_)) => {
+            println!("Usage: iostat [pid]");
+            return;
+        }
+    };
+    match sys_process_info(pid) {
+        Ok(info) => {
+            let io = info.io;
+            println!("CPU time: {} ms", info.cpu_time_milliseconds);
+            println!("Syscalls: {}", io.syscalls);
+            println!("Read: {} bytes", io.bytes_read);
+            println!("Written: {} bytes", io.bytes_written);
+            println!("Sent: {} bytes", io.bytes_sent);
+            println!("Received: {} bytes", io.bytes_received);
+        }
+        Err(err) => println!("iostat: {err:?}"),
+    }
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+use common::{
+    deadline::NO_DEADLINE,
+    errors::SysIrqError,
+    syscalls::{sys_irq_register, sys_irq_wait},
+};
+use userspace::{args, println, vdso};
+
+extern crate userspace;
+
+/// Waits for interrupts like a userspace driver would, each wait for at
+/// most the timeout if one is given. The interrupt is released when the
+/// program exits.
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    let Some(interrupt_id) = args.next().and_then(|id| id.parse::<u32>().ok()) else {
+        println!("Usage: irq <interrupt id> [count] [timeout in milliseconds]");
+        return;
+    };
+    let Ok(count) = args.next().map_or(Ok(1), |count| count.parse::<usize>()) else {
+        println!("Usage: irq <interrupt id> [count] [timeout in milliseconds]");
+        return;
+    };
+    let Ok(timeout) = args
+        .next()
+        .map(|timeout| timeout.parse::<u64>())
+        .transpose()
+    else {
+        println!("Usage: irq <interrupt id> [count] [timeout in milliseconds]");
+        return;
+    };
+
+    if let Err(err) = sys_irq_register(interrupt_id) {
+        println!("Cannot register interrupt {interrupt_id}: {err:?}");
+        return;
+    }
+    println!("Registered interrupt {interrupt_id}");
+
+    for _ in 0..count {
+        let deadline = timeout.map_or(NO_DEADLINE, vdso::deadline_in);
+        let result = loop {
+            // Stopping the driver interrupts the wait, it waits again once it continues
+            match sys_irq_wait(interrupt_id, deadline) {
+                Err(SysIrqError::Interrupted) => {}
+                result => break result,
+            }
+        };
+        match result {
+            Ok(fired) => println!("Interrupt {interrupt_id} fired {fired} times"),
+            Err(err) => {
+                println!("Cannot wait for interrupt {interrupt_id}: {err:?}");
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::sys_kill;
+use userspace::{args, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let Some(pid) = args().nth(1) else {
+        println!("Usage: kill <pid>");
+        return;
+    };
+    let Ok(pid) = pid.parse::<u64>() else {
+        println!("Invalid pid: {pid}");
+        return;
+    };
+    match sys_kill(pid) {
+        Ok(()) => println!("OK"),
+        Err(err) => println!("Error: {err:?}"),
+    }
+}
@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::attach_loop, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let Some(path) = args().nth(1) else {
+        println!("Usage: losetup <file>");
+        return;
+    };
+    match attach_loop(path) {
+        Ok(device) => println!("{device}"),
+        Err(err) => println!("losetup: {path}: {err:?}"),
+    }
+}
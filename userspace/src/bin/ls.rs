@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::read_directory, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let path = args().nth(1).unwrap_or(".");
+    match read_directory(path) {
+        Ok(entries) => {
+            for entry in entries {
+                println!("{entry}");
+            }
+        }
+        Err(err) => println!("ls: {path}: {err:?}"),
+    }
+}
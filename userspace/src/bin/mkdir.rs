@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::create_dir, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut paths = args().skip(1).peekable();
+    if paths.peek().is_none() {
+        println!("Usage: mkdir <directory>...");
+    }
+    for path in paths {
+        if let Err(err) = create_dir(path) {
+            println!("mkdir: {path}: {err:?}");
+        }
+    }
+}
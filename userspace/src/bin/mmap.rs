@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use alloc::string::String;
+use common::mmap::MapFlags;
+use userspace::{
+    args,
+    fs::{stat, File},
+    print, println,
+};
+
+extern crate alloc;
+extern crate userspace;
+
+/// Prints a file through a mapping. With -w the mapping is writable and
+/// the first byte is replaced, which must not change the file.
+#[unsafe(no_mangle)]
+fn main() {
+    let (flags, path) = match (args().nth(1), args().nth(2)) {
+        (Some("-w"), Some(path)) => (MapFlags::ReadWrite, path),
+        (Some(path), None) if path != "-w" => (MapFlags::ReadOnly, path),
+        _ => {
+            println!("Usage: mmap [-w] <file>");
+            return;
+        }
+    };
+    let mapped = stat(path).and_then(|stat| {
+        let length = stat.size as usize;
+        File::open(path)?
+            .map(0, length, flags)
+            .map(|ptr| (ptr, length))
+    });
+    let (ptr, length) = match mapped {
+        Ok(mapped) => mapped,
+        Err(err) => {
+            println!("mmap: {path}: {err:?}");
+            return;
+        }
+    };
+    // SAFETY: The kernel mapped length bytes
+    let data = unsafe { core::slice::from_raw_parts_mut(ptr, length) };
+    if flags == MapFlags::ReadWrite {
+        data[0] = b'#';
+    }
+    // Text is handed to the kernel straight from the pages of the mapping
+    match core::str::from_utf8(data) {
+        Ok(text) => print!("{text}"),
+        Err(_) => print!("{}", String::from_utf8_lossy(data)),
+    }
+}
@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::mount, println};
+
+extern crate userspace;
+
+const DEFAULT_FILESYSTEM: &str = "ext2";
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    let mut filesystem = DEFAULT_FILESYSTEM;
+    let mut source = args.next();
+    if source == Some("-t") {
+        filesystem = args.next().unwrap_or_default();
+        source = args.next();
+    }
+    let (Some(source), Some(target)) = (source, args.next()) else {
+        println!("Usage: mount [-t <filesystem>] <device> <directory>");
+        return;
+    };
+    if let Err(err) = mount(filesystem, source, target) {
+        println!("mount: {source}: {err:?}");
+    }
+}
@@ -0,0 +1,17 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::rename, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let (Some(from), Some(to)) = (args().nth(1), args().nth(2)) else {
+        println!("Usage: mv <from> <to>");
+        return;
+    };
+    if let Err(err) = rename(from, to) {
+        println!("mv: {from}: {err:?}");
+    }
+}
@@ -1,13 +1,23 @@
 #![no_std]
 #![no_main]
 
-use common::syscalls::sys_panic;
-use userspace::println;
+use common::syscalls::{sys_panic, sys_setuid};
+use userspace::{args, println};
 
 extern crate userspace;
 
+/// `panic <uid>` tries it as another user
 #[unsafe(no_mangle)]
 fn main() {
+    if let Some(uid) = args().nth(1) {
+        let Ok(uid) = uid.parse() else {
+            println!("Invalid uid: {uid}");
+            return;
+        };
+        sys_setuid(uid).expect("Root can change its uid");
+    }
     println!("Hello from Panic! Triggering kernel panic");
-    sys_panic();
+    if let Err(err) = sys_panic() {
+        println!("Error: {err:?}");
+    }
 }
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use common::{
+    perf::{bench, PerfCounter, PerfValues},
+    syscalls::sys_perf_read,
+};
+use core::hint::black_box;
+use userspace::println;
+
+extern crate userspace;
+
+const ITERATIONS: u64 = 10_000;
+
+fn process_values() -> [u64; PerfCounter::COUNT] {
+    PerfCounter::ALL.map(|counter| sys_perf_read(counter).expect("Every counter must be readable"))
+}
+
+#[unsafe(no_mangle)]
+fn main() {
+    let hart_start = PerfValues::read();
+    let process_start = process_values();
+
+    let mut sum = 0u64;
+    let result = bench(ITERATIONS, || {
+        sum = black_box(sum.wrapping_add(black_box(3)));
+    });
+    println!("Sum: {sum}");
+    println!("Loop: {result}");
+
+    let hart_elapsed = PerfValues::read().elapsed_since(&hart_start);
+    let process_end = process_values();
+    println!(
+        "Hart counters advance: {}",
+        PerfCounter::ALL
+            .iter()
+            .all(|counter| hart_elapsed.get(*counter) > 0)
+    );
+    println!(
+        "Process counters advance: {}",
+        process_start
+            .iter()
+            .zip(process_end)
+            .all(|(start, end)| end > *start)
+    );
+    println!(
+        "Instructions counted: {}",
+        result.total.get(PerfCounter::Instructions) >= ITERATIONS
+    );
+}
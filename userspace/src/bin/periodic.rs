@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::{sys_set_periodic, sys_wait_period};
+use userspace::{println, vdso};
+
+extern crate userspace;
+
+const PERIOD_MILLISECONDS: u64 = 10;
+const PERIODS: u64 = 5;
+
+#[unsafe(no_mangle)]
+fn main() {
+    println!("Waiting without reservation: {:?}", sys_wait_period());
+    println!(
+        "Budget above the period: {:?}",
+        sys_set_periodic(PERIOD_MILLISECONDS, PERIOD_MILLISECONDS + 1)
+    );
+    println!(
+        "Reserving more than admitted: {:?}",
+        sys_set_periodic(PERIOD_MILLISECONDS, PERIOD_MILLISECONDS)
+    );
+
+    sys_set_periodic(PERIOD_MILLISECONDS, 2).expect("Reservation must be admitted");
+    // The first period is partly over already
+    sys_wait_period().expect("Process must be periodic");
+    let start = vdso::uptime_milliseconds();
+    for _ in 0..PERIODS {
+        sys_wait_period().expect("Process must be periodic");
+    }
+    // The wake up latency of the first and the last period differs
+    let elapsed = vdso::uptime_milliseconds() - start;
+    println!(
+        "Waited for {PERIODS} periods: {}",
+        elapsed >= (PERIODS - 1) * PERIOD_MILLISECONDS
+    );
+    sys_set_periodic(0, 0).expect("Returning to round robin must work");
+}
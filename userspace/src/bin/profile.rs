@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use alloc::{string::String, vec::Vec};
+use common::syscalls::{sys_execute, sys_profiler_start, sys_profiler_stop, sys_wait};
+use userspace::{args, fs::File, print, println};
+
+extern crate alloc;
+extern crate userspace;
+
+const INTERVAL_MILLISECONDS: u64 = 1;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let args: Vec<&str> = args().skip(1).collect();
+    let Some((program, program_args)) = args.split_first() else {
+        println!("Usage: profile <program> [args]...");
+        return;
+    };
+
+    if let Err(err) = sys_profiler_start(INTERVAL_MILLISECONDS) {
+        println!("profile: {err:?}");
+        return;
+    }
+    match sys_execute(program, program_args) {
+        Ok(pid) => {
+            sys_wait(pid).expect("Started programs are children");
+        }
+        Err(err) => println!("profile: {program}: {err:?}"),
+    }
+    let _ = sys_profiler_stop();
+
+    match File::open("/proc/profile").and_then(|mut file| file.read_to_end()) {
+        Ok(data) => print!("{}", String::from_utf8_lossy(&data)),
+        Err(err) => println!("profile: /proc/profile: {err:?}"),
+    }
+}
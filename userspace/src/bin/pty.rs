@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use alloc::vec::Vec;
+use common::{deadline::NO_DEADLINE, syscalls::sys_wait};
+use userspace::{args, print, println, pty::Pty};
+
+extern crate alloc;
+extern crate userspace;
+
+/// Runs a program on a pseudo terminal and prints what it wrote
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    let Some(name) = args.next() else {
+        println!("Usage: pty <program> [args...]");
+        return;
+    };
+    let args: Vec<&str> = args.collect();
+
+    let mut pty = Pty::open().expect("Pty must be openable.");
+    let pid = match pty.execute(name, &args) {
+        Ok(pid) => pid,
+        Err(err) => {
+            println!("Error executing program: {err:?}");
+            return;
+        }
+    };
+    // The output is picked up while the program runs, it blocks once
+    // the pty is full
+    let mut buffer = [0; 64];
+    loop {
+        let count = pty
+            .read_wait(&mut buffer, NO_DEADLINE)
+            .expect("Reads without deadline don't time out");
+        if count == 0 {
+            break;
+        }
+        print!("{}", core::str::from_utf8(&buffer[..count]).unwrap_or("?"));
+    }
+    sys_wait(pid).expect("Started programs are children");
+}
@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, io::stdin, println, vdso};
+
+extern crate userspace;
+
+/// Reads a line and prints it, gives up after the timeout if one is given
+#[unsafe(no_mangle)]
+fn main() {
+    let line = match args().nth(1).map(|timeout| timeout.parse::<u64>()) {
+        None => Some(stdin().read_line()),
+        Some(Ok(timeout)) => stdin().read_line_before(vdso::deadline_in(timeout)),
+        Some(Err(_)) => {
+            println!("Usage: read [timeout in milliseconds]");
+            return;
+        }
+    };
+    match line {
+        Some(line) => println!("Read: {line}"),
+        None => println!("read: timed out"),
+    }
+}
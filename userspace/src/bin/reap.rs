@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+use common::{
+    syscalls::{sys_execute, sys_wait, sys_wait_child},
+    wait::{WaitOptions, ANY_CHILD},
+};
+use userspace::{args, println};
+
+extern crate userspace;
+
+const INIT_PID: u64 = 1;
+
+/// Waits for children in several ways. `reap orphan` leaves a child
+/// behind which is adopted by init.
+#[unsafe(no_mangle)]
+fn main() {
+    if args().nth(1) == Some("orphan") {
+        let pid = sys_execute("sleep", &["500"]).expect("sleep must exist");
+        println!("Orphan: {pid}");
+        return;
+    }
+
+    println!(
+        "Without children: {:?}",
+        sys_wait_child(ANY_CHILD, WaitOptions::NoHang)
+    );
+    println!(
+        "Not a child: {:?}",
+        sys_wait_child(INIT_PID, WaitOptions::Block)
+    );
+    println!("Waiting for init: {:?}", sys_wait(INIT_PID));
+
+    let sleeper = sys_execute("sleep", &["100"]).expect("sleep must exist");
+    println!(
+        "Still running: {:?}",
+        sys_wait_child(sleeper, WaitOptions::NoHang)
+    );
+    sys_execute("segfault", &[]).expect("segfault must exist");
+    for _ in 0..2 {
+        let exit = sys_wait_child(ANY_CHILD, WaitOptions::Block)
+            .expect("There are children")
+            .expect("Blocking waits return a child");
+        let name = if exit.pid == sleeper {
+            "sleep"
+        } else {
+            "segfault"
+        };
+        println!("{name}: {:?}", exit.status);
+    }
+    println!(
+        "All reaped: {:?}",
+        sys_wait_child(ANY_CHILD, WaitOptions::NoHang)
+    );
+}
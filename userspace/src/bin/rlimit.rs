@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+use common::{
+    resource_limits::{Resource, ResourceLimit},
+    syscalls::{sys_getrlimit, sys_mmap_pages, sys_setrlimit},
+};
+use userspace::println;
+
+extern crate userspace;
+
+const PAGES: usize = 16;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let limit = sys_getrlimit(Resource::MappedPages).expect("Resource must be valid");
+    println!("Mapped pages limit: {}", limit.soft);
+
+    let ptr = sys_mmap_pages(PAGES);
+    println!("Mapping {PAGES} pages succeeded: {}", !ptr.is_null());
+
+    sys_setrlimit(Resource::MappedPages, ResourceLimit::new(0, limit.hard))
+        .expect("Soft limit must be lowerable");
+    let ptr = sys_mmap_pages(PAGES);
+    // Restore the limit before printing because printing might allocate
+    sys_setrlimit(Resource::MappedPages, limit).expect("Soft limit must be raisable");
+    println!(
+        "Mapping {PAGES} pages above the limit succeeded: {}",
+        !ptr.is_null()
+    );
+
+    let raised = sys_setrlimit(
+        Resource::MappedPages,
+        ResourceLimit::new(limit.soft, limit.hard.saturating_add(1)),
+    );
+    println!("Raising the hard limit: {raised:?}");
+
+    sys_setrlimit(Resource::CpuTime, ResourceLimit::new(100, 100))
+        .expect("CPU time must be limitable");
+    println!("Spinning with a CPU time limit of 100 ms");
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::remove, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut paths = args().skip(1).peekable();
+    if paths.peek().is_none() {
+        println!("Usage: rm <path>...");
+    }
+    for path in paths {
+        if let Err(err) = remove(path) {
+            println!("rm: {path}: {err:?}");
+        }
+    }
+}
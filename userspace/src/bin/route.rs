@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+use core::net::Ipv4Addr;
+
+use common::syscalls::sys_route_add;
+use userspace::{args, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    let (Some("add"), Some(prefix)) = (args.next(), args.next()) else {
+        println!("Usage: route add <destination>/<prefix length> [gateway]");
+        return;
+    };
+    let Some((destination, prefix_length)) =
+        prefix.split_once('/').and_then(|(destination, length)| {
+            Some((
+                destination.parse::<Ipv4Addr>().ok()?,
+                length.parse::<u8>().ok()?,
+            ))
+        })
+    else {
+        println!("Invalid prefix: {prefix}");
+        return;
+    };
+    // Without a gateway the destination is on-link
+    let gateway = match args.next().map(|gateway| gateway.parse::<Ipv4Addr>()) {
+        None => Ipv4Addr::UNSPECIFIED,
+        Some(Ok(gateway)) => gateway,
+        Some(Err(_)) => {
+            println!("Invalid gateway");
+            return;
+        }
+    };
+    match sys_route_add(destination.to_bits(), prefix_length, gateway.to_bits()) {
+        Ok(()) => println!("OK"),
+        Err(err) => println!("Error: {err:?}"),
+    }
+}
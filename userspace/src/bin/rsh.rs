@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::sys_wait;
+use userspace::{
+    net::{TcpListener, TcpStream},
+    println,
+    pty::Pty,
+    util::wait_for_sources,
+};
+
+extern crate userspace;
+
+const PORT: u16 = 2323;
+
+/// Remote shell over tcp. Every connection gets a shell on a pty of its
+/// own, one connection after the other. Everything the client sends is
+/// terminal input, the output of the shell goes back to it. The
+/// connection is closed once the shell exits.
+#[unsafe(no_mangle)]
+fn main() {
+    let mut listener = TcpListener::try_open(PORT).expect("Port must be free.");
+    println!("Remote shell listening on {PORT}");
+
+    loop {
+        let stream = listener.accept().expect("Connection must be acceptable.");
+        serve(stream);
+    }
+}
+
+fn serve(mut stream: TcpStream) {
+    let mut pty = Pty::open().expect("Pty must be openable.");
+    let shell = pty.execute("sesh", &[]).expect("Shell must be executable");
+    let mut buffer = [0; 256];
+
+    loop {
+        let mut sources = [stream.poll_source(), pty.poll_source()];
+        wait_for_sources(&mut sources);
+
+        if sources[0].is_ready() {
+            match stream.read(&mut buffer) {
+                Ok(count) => {
+                    pty.write(&buffer[..count]).expect("Pty must be valid");
+                }
+                // The client is gone
+                Err(_) => break,
+            }
+        }
+        if sources[1].is_ready() {
+            let count = pty.read(&mut buffer).expect("Pty must be valid");
+            // The shell exited
+            if count == 0 || stream.write_all(&buffer[..count]).is_err() {
+                break;
+            }
+        }
+    }
+
+    // A shell which still runs reads the hang up and exits
+    drop(pty);
+    drop(stream);
+    sys_wait(shell).expect("Started programs are children");
+}
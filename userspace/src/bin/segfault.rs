@@ -0,0 +1,15 @@
+#![no_std]
+#![no_main]
+
+use userspace::println;
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    println!("Hello from Segfault! Writing to the null pointer");
+    let pointer = core::ptr::null_mut::<u64>();
+    // SAFETY: It isn't. That's the point of this program.
+    unsafe { pointer.write_volatile(0xdead) };
+    println!("Survived the segfault");
+}
@@ -5,26 +5,80 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use common::syscalls::{sys_execute, sys_exit, sys_print_programs, sys_wait};
-use userspace::{print, println, util::read_line};
+use common::{
+    spawn::SpawnOptions,
+    syscalls::{
+        sys_exit, sys_print_page_tables, sys_print_programs, sys_reboot, sys_resume, sys_shutdown,
+        sys_spawn, sys_wait_child,
+    },
+    wait::{ExitStatus, WaitOptions, ANY_CHILD},
+};
+use userspace::{env, line_editor::LineEditor, print, println};
 
 extern crate alloc;
 extern crate userspace;
 
+/// A program which was stopped with Ctrl+Z, possibly continued in the
+/// background afterwards
+struct Job {
+    pid: u64,
+    command: String,
+    stopped: bool,
+}
+
 #[unsafe(no_mangle)]
 fn main() {
     println!();
     println!("### SeSH - Sentient Shell ###");
     println!("Type 'help' for a list of available commands.");
+    let mut line_editor = LineEditor::new();
+    let mut jobs = Vec::new();
     loop {
+        reap_jobs(&mut jobs);
         print!("$ ");
-        let input = read_line();
+        let input = line_editor.read_line();
         // Parse input and execute
-        parse_command_and_execute(input);
+        parse_command_and_execute(input, &mut jobs);
     }
 }
 
-fn parse_command_and_execute(mut command: String) {
+/// Jobs are the only children which aren't waited for right away
+fn reap_jobs(jobs: &mut Vec<Job>) {
+    while let Ok(Some(exit)) = sys_wait_child(ANY_CHILD, WaitOptions::NoHang) {
+        if let Some(index) = jobs.iter().position(|job| job.pid == exit.pid) {
+            let job = jobs.remove(index);
+            println!("[{}] Done {}", job.pid, job.command);
+        }
+    }
+}
+
+/// Returns when the program exited or was stopped, a stopped program
+/// becomes a job
+fn run_in_foreground(pid: u64, command: &str, jobs: &mut Vec<Job>) {
+    if let Ok(Some(exit)) = sys_wait_child(pid, WaitOptions::Untraced) {
+        if exit.status == ExitStatus::Stopped {
+            println!("\n[{pid}] Stopped {command}");
+            jobs.push(Job {
+                pid,
+                command: command.into(),
+                stopped: true,
+            });
+        }
+    }
+}
+
+/// The job with the pid or the latest one
+fn take_job(jobs: &mut Vec<Job>, pid: &str) -> Option<Job> {
+    let index = if pid.is_empty() {
+        jobs.len().checked_sub(1)?
+    } else {
+        let pid: u64 = pid.parse().ok()?;
+        jobs.iter().position(|job| job.pid == pid)?
+    };
+    Some(jobs.remove(index))
+}
+
+fn parse_command_and_execute(mut command: String, jobs: &mut Vec<Job>) {
     command = command.trim().to_string();
     match command.as_str() {
         "" => {}
@@ -32,13 +86,110 @@ fn parse_command_and_execute(mut command: String) {
             println!("Exiting...");
             sys_exit(0);
         }
+        "shutdown" => {
+            if let Err(err) = sys_shutdown() {
+                println!("Cannot shutdown: {:?}", err);
+            }
+        }
+        "reboot" => {
+            if let Err(err) = sys_reboot() {
+                println!("Cannot reboot: {:?}", err);
+            }
+        }
         "help" => {
             println!("Available commands:");
-            println!("exit - Exit the shell");
+            println!("exit [STATUS] - Exit the shell");
             println!("help - Print this help message");
+            println!("shutdown - Power off the system");
+            println!("reboot - Restart the system");
+            println!("export KEY=VALUE - Set an environment variable");
+            println!("unset KEY - Remove an environment variable");
+            println!("cd [DIRECTORY] - Change the working directory, HOME by default");
+            println!("pwd - Print the working directory");
+            println!("pagetables [PID] - Print the mappings of a process or the kernel");
+            println!("jobs - List the programs stopped with Ctrl+Z");
+            println!("fg [PID] - Continue a job in the foreground, the latest by default");
+            println!("bg [PID] - Continue a job in the background, the latest by default");
             println!("\nFollowing programs exist and can be called:");
             sys_print_programs();
         }
+        _ if command.starts_with("exit ") => {
+            let Ok(status) = command["exit ".len()..].trim().parse() else {
+                println!("Usage: exit [STATUS]");
+                return;
+            };
+            println!("Exiting...");
+            sys_exit(status);
+        }
+        _ if command == "pagetables" || command.starts_with("pagetables ") => {
+            let pid = command["pagetables".len()..].trim();
+            // The kernel is selected with pid 0
+            let Ok(pid) = (if pid.is_empty() { Ok(0) } else { pid.parse() }) else {
+                println!("Usage: pagetables [PID]");
+                return;
+            };
+            if let Err(err) = sys_print_page_tables(pid) {
+                println!("Cannot print page tables: {:?}", err);
+            }
+        }
+        _ if command.starts_with("export ") => {
+            let Some((key, value)) = command["export ".len()..].split_once('=') else {
+                println!("Usage: export KEY=VALUE");
+                return;
+            };
+            if let Err(err) = env::set_var(key.trim(), value.trim()) {
+                println!("Cannot export: {:?}", err);
+            }
+        }
+        "pwd" => match env::current_dir() {
+            Ok(directory) => println!("{directory}"),
+            Err(err) => println!("Cannot print working directory: {:?}", err),
+        },
+        _ if command == "cd" || command.starts_with("cd ") => {
+            let directory = command["cd".len()..].trim();
+            let directory = if directory.is_empty() {
+                env::var("HOME").unwrap_or_else(|| "/".into())
+            } else {
+                directory.into()
+            };
+            if let Err(err) = env::set_current_dir(&directory) {
+                println!("cd: {directory}: {:?}", err);
+            }
+        }
+        "jobs" => {
+            for job in jobs.iter() {
+                let state = if job.stopped { "Stopped" } else { "Running" };
+                println!("[{}] {state} {}", job.pid, job.command);
+            }
+        }
+        _ if command == "fg" || command.starts_with("fg ") => {
+            let Some(job) = take_job(jobs, command["fg".len()..].trim()) else {
+                println!("fg: no such job");
+                return;
+            };
+            println!("{}", job.command);
+            if job.stopped {
+                let _ = sys_resume(job.pid);
+            }
+            run_in_foreground(job.pid, &job.command, jobs);
+        }
+        _ if command == "bg" || command.starts_with("bg ") => {
+            let Some(mut job) = take_job(jobs, command["bg".len()..].trim()) else {
+                println!("bg: no such job");
+                return;
+            };
+            if job.stopped {
+                let _ = sys_resume(job.pid);
+                job.stopped = false;
+            }
+            println!("[{}] {} &", job.pid, job.command);
+            jobs.push(job);
+        }
+        _ if command.starts_with("unset ") => {
+            if let Err(err) = env::remove_var(command["unset ".len()..].trim()) {
+                println!("Cannot unset: {:?}", err);
+            }
+        }
         _ => {
             let mut background = false;
 
@@ -55,11 +206,17 @@ fn parse_command_and_execute(mut command: String) {
 
             let args: Vec<&str> = split.filter(|arg| !arg.trim().is_empty()).collect();
 
-            let execute_result = sys_execute(prog_name, &args);
+            // Background jobs are reaped by init
+            let options = if background {
+                SpawnOptions::new().detached()
+            } else {
+                SpawnOptions::new()
+            };
+            let execute_result = sys_spawn(prog_name, &args, &[], "", options);
             match execute_result {
                 Ok(pid) => {
                     if !background {
-                        let _ = sys_wait(pid);
+                        run_in_foreground(pid, &command, jobs);
                     }
                 }
                 Err(err) => {
@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::sys_sleep;
+use userspace::{args, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let Some(milliseconds) = args().nth(1) else {
+        println!("Usage: sleep <milliseconds>");
+        return;
+    };
+    let Ok(milliseconds) = milliseconds.parse::<u64>() else {
+        println!("Invalid duration: {milliseconds}");
+        return;
+    };
+    sys_sleep(milliseconds);
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+use alloc::{format, string::String};
+use common::{
+    spawn::{DescriptorMapping, SpawnOptions},
+    syscalls::{sys_resume, sys_spawn, sys_wait},
+};
+use userspace::{fs::File, println};
+
+extern crate alloc;
+extern crate userspace;
+
+const CHILD_DESCRIPTOR: u64 = 5;
+
+fn state_of(pid: u64) -> String {
+    let status = File::open(&format!("/proc/{pid}/status"))
+        .and_then(|mut file| file.read_to_end())
+        .expect("The child must have a status");
+    String::from_utf8(status)
+        .expect("Status must be UTF-8")
+        .lines()
+        .find_map(|line| line.strip_prefix("State:\t"))
+        .expect("Status must contain the state")
+        .into()
+}
+
+/// Starts programs with descriptors, a working directory and suspended
+#[unsafe(no_mangle)]
+fn main() {
+    let file = File::open("/etc/services").expect("/etc/services must exist");
+    let mapping = [DescriptorMapping::new(
+        file.descriptor().get(),
+        CHILD_DESCRIPTOR,
+    )];
+    let pid = sys_spawn("cat", &["/proc/self/fd"], &mapping, "", SpawnOptions::new())
+        .expect("cat must exist");
+    sys_wait(pid).expect("Started programs are children");
+
+    let pid = sys_spawn("ls", &[], &[], "/etc", SpawnOptions::new()).expect("ls must exist");
+    sys_wait(pid).expect("Started programs are children");
+
+    let pid = sys_spawn("sleep", &["10"], &[], "", SpawnOptions::new().suspended())
+        .expect("sleep must exist");
+    println!("Spawned: {}", state_of(pid));
+    println!("Resumed: {:?}", sys_resume(pid));
+    println!("Resumed again: {:?}", sys_resume(pid));
+    sys_wait(pid).expect("Started programs are children");
+
+    let unknown = [DescriptorMapping::new(1000, CHILD_DESCRIPTOR)];
+    println!(
+        "Unknown descriptor: {:?}",
+        sys_spawn("sleep", &["10"], &unknown, "", SpawnOptions::new())
+    );
+    println!(
+        "Not a directory: {:?}",
+        sys_spawn("sleep", &["10"], &[], "/etc/services", SpawnOptions::new())
+    );
+}
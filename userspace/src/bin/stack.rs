@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use common::{
+    resource_limits::{Resource, ResourceLimit},
+    syscalls::{sys_getrlimit, sys_setrlimit},
+};
+use userspace::{args, println};
+
+extern crate userspace;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+#[inline(never)]
+fn fill_stack() -> u64 {
+    let mut buffer = [0u8; BUFFER_SIZE];
+    for (index, byte) in buffer.iter_mut().enumerate() {
+        *byte = index as u8;
+    }
+    core::hint::black_box(&mut buffer);
+    buffer.iter().map(|byte| u64::from(*byte)).sum()
+}
+
+/// Uses more stack than the single page a program starts with. With -l
+/// the stack limit is lowered first, which kills the program.
+#[unsafe(no_mangle)]
+fn main() {
+    if args().nth(1) == Some("-l") {
+        let limit = sys_getrlimit(Resource::StackPages).expect("Resource must be valid");
+        sys_setrlimit(Resource::StackPages, ResourceLimit::new(4, limit.hard))
+            .expect("Soft limit must be lowerable");
+        println!("Lowered the stack limit to 4 pages");
+    }
+    println!(
+        "Sum of {} KiB on the stack: {}",
+        BUFFER_SIZE / 1024,
+        fill_stack()
+    );
+}
@@ -0,0 +1,18 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, fs::stat, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let Some(path) = args().nth(1) else {
+        println!("Usage: stat <path>");
+        return;
+    };
+    match stat(path) {
+        Ok(stat) => println!("{path}: {:?} {} bytes", stat.kind, stat.size),
+        Err(err) => println!("stat: {path}: {err:?}"),
+    }
+}
@@ -21,7 +21,7 @@ fn main() {
     }
 
     for pid in pids {
-        let _ = sys_wait(pid);
+        sys_wait(pid).expect("Started programs are children");
     }
 
     println!("Done!");
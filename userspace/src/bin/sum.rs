@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+use userspace::{io::stdin, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    println!("Enter numbers, 0 ends the input:");
+    let mut stdin = stdin();
+    let mut sum = 0i64;
+    loop {
+        match stdin.read::<i64>() {
+            Ok(0) => break,
+            Ok(number) => sum += number,
+            Err(err) => println!("Not a number: {err}"),
+        }
+    }
+    println!("Sum: {sum}");
+}
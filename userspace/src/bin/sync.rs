@@ -0,0 +1,13 @@
+#![no_std]
+#![no_main]
+
+use userspace::{fs, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    if let Err(err) = fs::sync() {
+        println!("sync: {err:?}");
+    }
+}
@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+use userspace::{
+    io::read_line,
+    println,
+    runtime::{block_on, sleep, spawn},
+};
+
+extern crate userspace;
+
+async fn ticker() {
+    for tick in 1..=3 {
+        sleep(100).await;
+        println!("tick {tick}");
+    }
+}
+
+#[unsafe(no_mangle)]
+fn main() {
+    block_on(async {
+        spawn(ticker());
+        let line = read_line().await;
+        println!("Got: {line}");
+    });
+}
@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+use common::errors::SysFileError;
+use userspace::{args, fs::File, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut paths = args().skip(1).peekable();
+    if paths.peek().is_none() {
+        println!("Usage: touch <file>...");
+    }
+    for path in paths {
+        match File::create(path) {
+            Ok(_) | Err(SysFileError::AlreadyExists) => {}
+            Err(err) => println!("touch: {path}: {err:?}"),
+        }
+    }
+}
@@ -9,7 +9,6 @@ extern crate alloc;
 extern crate userspace;
 
 const PORT: u16 = 1234;
-const DELETE: u8 = 127;
 
 #[unsafe(no_mangle)]
 fn main() {
@@ -28,27 +27,12 @@ fn main() {
             print!("{}", text);
         }
 
+        // The input is line buffered and already echoed by the kernel
         if let Some(c) = sys_read_input() {
-            match c {
-                b'\r' | b'\n' => {
-                    // Carriage return
-                    // Send data
-                    println!();
-                    input.push(b'\n' as char);
-                    socket.transmit(input.as_bytes());
-                    input.clear();
-                }
-                DELETE => {
-                    if input.pop().is_some() {
-                        print!("{}{}{}", 8 as char, ' ', 8 as char);
-                    }
-                }
-                _ => {
-                    assert!(c.is_ascii());
-                    let result = c as char;
-                    input.push(result);
-                    print!("{}", result);
-                }
+            input.push(c as char);
+            if c == b'\n' {
+                socket.transmit(input.as_bytes());
+                input.clear();
             }
         }
     }
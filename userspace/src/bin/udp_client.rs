@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+use core::net::Ipv4Addr;
+
+use userspace::{args, net::UdpSocket, println};
+
+extern crate userspace;
+
+#[unsafe(no_mangle)]
+fn main() {
+    let mut args = args().skip(1);
+    let (Some(ip), Some(port), Some(message)) = (args.next(), args.next(), args.next()) else {
+        println!("Usage: udp_client <ip> <port> <message>");
+        return;
+    };
+    let (Ok(ip), Ok(port)) = (ip.parse::<Ipv4Addr>(), port.parse::<u16>()) else {
+        println!("Invalid address: {ip}:{port}");
+        return;
+    };
+
+    let mut socket = UdpSocket::try_open_ephemeral().expect("There must be a free port.");
+    if let Err(err) = socket.connect(ip, port) {
+        println!("Error: {err:?}");
+        return;
+    }
+    if let Err(err) = socket.send_to(ip, port, message.as_bytes()) {
+        println!("Error: {err:?}");
+        return;
+    }
+
+    let mut buffer = [0; 64];
+    let count = loop {
+        let count = socket.receive(&mut buffer);
+        if count > 0 {
+            break count;
+        }
+    };
+    let reply = core::str::from_utf8(&buffer[..count]).expect("Reply must be valid utf8");
+    println!("Reply: {reply}");
+}
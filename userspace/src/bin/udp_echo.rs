@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+use userspace::{args, net::UdpSocket, println, runtime::block_on};
+
+extern crate userspace;
+
+const DEFAULT_PORT: u16 = 1234;
+// Larger than any datagram which fits into an ethernet frame
+const BUFFER_SIZE: usize = 1536;
+
+/// Sends everything back to the sender. Used by the network system tests.
+#[unsafe(no_mangle)]
+fn main() {
+    let port = args()
+        .nth(1)
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+    let mut socket = UdpSocket::try_open(port).expect("Socket must be openable.");
+    println!("Echoing on {port}");
+
+    block_on(async {
+        let mut buffer = [0; BUFFER_SIZE];
+        loop {
+            let count = socket.receive_async(&mut buffer).await;
+            socket.transmit(&buffer[..count]);
+        }
+    });
+}
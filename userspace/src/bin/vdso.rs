@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use userspace::{println, vdso};
+
+extern crate userspace;
+
+const ITERATIONS: usize = 100_000;
+
+#[unsafe(no_mangle)]
+fn main() {
+    println!("Timebase frequency: {}", vdso::timebase_frequency());
+    println!("PID: {}", vdso::pid());
+    println!(
+        "Hart is valid: {}",
+        vdso::current_hart() < vdso::number_of_harts()
+    );
+
+    // Reading the time in a hot loop doesn't trap into the kernel
+    let mut last = vdso::uptime_milliseconds();
+    let mut monotonic = true;
+    for _ in 0..ITERATIONS {
+        let now = vdso::uptime_milliseconds();
+        monotonic &= now >= last;
+        last = now;
+    }
+    println!("Time is monotonic: {monotonic}");
+
+    if let Some(realtime) = vdso::realtime_nanoseconds() {
+        println!("Seconds since epoch: {}", realtime / 1_000_000_000);
+    }
+}
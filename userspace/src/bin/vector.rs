@@ -0,0 +1,271 @@
+#![no_std]
+#![no_main]
+
+use common::syscalls::{sys_execute, sys_wait};
+use core::arch::asm;
+use userspace::{args, println, vdso};
+
+use alloc::{format, vec::Vec};
+
+extern crate alloc;
+extern crate userspace;
+
+const WORKERS: u64 = 4;
+const ROUNDS: u64 = 3;
+// Long enough to be preempted several times
+const ITERATIONS: u64 = 20_000_000;
+// Every implementation of the V extension has room for two elements
+const ELEMENTS: u64 = 2;
+
+/// Fills the vector registers with consecutive values starting at
+/// `first`, spins and returns if the registers and vl were kept
+fn spin_with_registers(first: u64) -> bool {
+    let differences: u64;
+    let vl: u64;
+    // SAFETY: The program isn't compiled with the vector extension, the
+    // compiler doesn't use the vector registers.
+    unsafe {
+        asm!(
+            ".option push",
+            ".option arch, +v",
+            "vsetvli zero, {elements}, e64, m1, ta, ma",
+            "vmv.v.x v0, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v1, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v2, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v3, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v4, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v5, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v6, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v7, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v8, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v9, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v10, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v11, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v12, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v13, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v14, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v15, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v16, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v17, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v18, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v19, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v20, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v21, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v22, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v23, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v24, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v25, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v26, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v27, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v28, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v29, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v30, {value}",
+            "addi {value}, {value}, 1",
+            "vmv.v.x v31, {value}",
+            "addi {value}, {value}, 1",
+            "1:",
+            "addi {counter}, {counter}, -1",
+            "bnez {counter}, 1b",
+            "mv {value}, {first}",
+            "li {differences}, 0",
+            "vmv.x.s {temporary}, v0",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v1",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v2",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v3",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v4",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v5",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v6",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v7",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v8",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v9",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v10",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v11",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v12",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v13",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v14",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v15",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v16",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v17",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v18",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v19",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v20",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v21",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v22",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v23",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v24",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v25",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v26",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v27",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v28",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v29",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v30",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "vmv.x.s {temporary}, v31",
+            "xor {temporary}, {temporary}, {value}",
+            "or {differences}, {differences}, {temporary}",
+            "addi {value}, {value}, 1",
+            "csrr {vl}, vl",
+            ".option pop",
+            elements = in(reg) ELEMENTS,
+            first = in(reg) first,
+            value = inout(reg) first => _,
+            counter = inout(reg) ITERATIONS => _,
+            temporary = out(reg) _,
+            differences = out(reg) differences,
+            vl = out(reg) vl,
+        );
+    }
+    differences == 0 && vl == ELEMENTS
+}
+
+fn worker(seed: u64) {
+    let kept = (0..ROUNDS).all(|round| spin_with_registers((seed << 32) | (round << 8)));
+    println!("Worker {seed}: vector registers kept: {kept}");
+}
+
+#[unsafe(no_mangle)]
+fn main() {
+    if !vdso::has_vector_extension() {
+        println!("No vector extension");
+        return;
+    }
+
+    let mut args = args().skip(1);
+    if let (Some("worker"), Some(seed)) = (args.next(), args.next()) {
+        worker(seed.parse().expect("Seed must be a number"));
+        return;
+    }
+
+    let pids: Vec<u64> = (1..=WORKERS)
+        .map(|seed| {
+            sys_execute("vector", &["worker", &format!("{seed}")])
+                .expect("Process must be successfully startable")
+        })
+        .collect();
+    for pid in pids {
+        sys_wait(pid).expect("Started programs are children");
+    }
+    println!("Done!");
+}
@@ -0,0 +1,131 @@
+#![no_std]
+#![no_main]
+
+use core::{fmt::Display, net::Ipv4Addr};
+
+use alloc::{format, string::String};
+use userspace::{args, net::RawSocket, println};
+
+extern crate alloc;
+extern crate userspace;
+
+// Ethernet header and the largest payload
+const MAX_FRAME_SIZE: usize = 1514;
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ETHER_TYPE_ARP: u16 = 0x0806;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+struct Mac<'a>(&'a [u8]);
+
+impl Display for Mac<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (index, byte) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+fn ip(bytes: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+fn big_endian(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn describe_arp(packet: &[u8]) -> Option<String> {
+    let packet = packet.get(..28)?;
+    let description = match big_endian(&packet[6..8]) {
+        1 => format!(
+            "who-has {} tell {}",
+            ip(&packet[24..28]),
+            ip(&packet[14..18])
+        ),
+        2 => format!("{} is-at {}", ip(&packet[14..18]), Mac(&packet[8..14])),
+        operation => format!("operation {operation}"),
+    };
+    Some(format!("ARP {description}"))
+}
+
+fn describe_ipv4(packet: &[u8]) -> Option<String> {
+    let header_length = (*packet.first()? as usize & 0xf) * 4;
+    if header_length < 20 {
+        return None;
+    }
+    let header = packet.get(..header_length)?;
+    let (source, destination) = (ip(&header[12..16]), ip(&header[16..20]));
+    let protocol = header[9];
+    if protocol != IP_PROTOCOL_UDP {
+        return Some(format!("IP {source} > {destination} protocol {protocol}"));
+    }
+    let udp = packet.get(header_length..header_length + 8)?;
+    Some(format!(
+        "IP {source}:{} > {destination}:{} UDP length {}",
+        big_endian(&udp[0..2]),
+        big_endian(&udp[2..4]),
+        big_endian(&udp[4..6]).saturating_sub(8)
+    ))
+}
+
+fn describe(frame: &[u8]) -> Option<String> {
+    let header = frame.get(..14)?;
+    let payload = &frame[14..];
+    let protocol = match big_endian(&header[12..14]) {
+        ETHER_TYPE_ARP => describe_arp(payload)?,
+        ETHER_TYPE_IPV4 => describe_ipv4(payload)?,
+        ether_type => format!("ethertype {ether_type:#06x} length {}", payload.len()),
+    };
+    Some(format!(
+        "{} > {} {protocol}",
+        Mac(&header[6..12]),
+        Mac(&header[0..6])
+    ))
+}
+
+#[unsafe(no_mangle)]
+fn main() {
+    // Captures until killed without -c
+    let count = match args().skip(1).collect::<alloc::vec::Vec<_>>().as_slice() {
+        [] => None,
+        ["-c", count] => match count.parse::<usize>() {
+            Ok(count) => Some(count),
+            Err(_) => {
+                println!("Invalid count: {count}");
+                return;
+            }
+        },
+        _ => {
+            println!("Usage: yadump [-c <count>]");
+            return;
+        }
+    };
+
+    let mut socket = match RawSocket::try_open() {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("Error: {err:?}");
+            return;
+        }
+    };
+    println!("Capturing frames");
+
+    let mut buffer = [0; MAX_FRAME_SIZE];
+    let mut captured = 0;
+    while count.is_none_or(|count| captured < count) {
+        let frame = socket.receive(&mut buffer);
+        captured += 1;
+        let description = describe(&buffer[..frame.length])
+            .unwrap_or_else(|| format!("truncated frame of {} bytes", frame.frame_length));
+        println!(
+            "{}.{:06} {} {description}",
+            frame.timestamp_microseconds / 1_000_000,
+            frame.timestamp_microseconds % 1_000_000,
+            if frame.outgoing { "Out" } else { "In " },
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Environment variables. The kernel passes them behind the arguments.
+//! Changes are forwarded to the kernel such that started programs
+//! inherit them. The working directory is kept by the kernel and
+//! inherited as well.
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use common::{
+    errors::{SysEnvironmentError, SysFileError},
+    mutex::Mutex,
+    syscalls::{sys_chdir, sys_getcwd, sys_setenv, sys_unsetenv},
+};
+
+use crate::args;
+
+// Parsed on first use
+static ENVIRONMENT: Mutex<Option<BTreeMap<String, String>>> = Mutex::new(None);
+
+fn with_environment<R>(f: impl FnOnce(&mut BTreeMap<String, String>) -> R) -> R {
+    let mut environment = ENVIRONMENT.lock();
+    let environment = environment.get_or_insert_with(|| {
+        args::environment()
+            .filter_map(|variable| variable.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    });
+    f(environment)
+}
+
+pub fn var(key: &str) -> Option<String> {
+    with_environment(|environment| environment.get(key).cloned())
+}
+
+/// Sorted by key
+pub fn vars() -> Vec<(String, String)> {
+    with_environment(|environment| {
+        environment
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    })
+}
+
+pub fn set_var(key: &str, value: &str) -> Result<(), SysEnvironmentError> {
+    sys_setenv(key, value)?;
+    with_environment(|environment| environment.insert(key.to_string(), value.to_string()));
+    Ok(())
+}
+
+pub fn remove_var(key: &str) -> Result<(), SysEnvironmentError> {
+    sys_unsetenv(key)?;
+    with_environment(|environment| environment.remove(key));
+    Ok(())
+}
+
+pub fn current_dir() -> Result<String, SysFileError> {
+    let mut buffer = vec![0; 64];
+    let length = loop {
+        match sys_getcwd(&mut buffer) {
+            Ok(length) => break length,
+            Err(SysFileError::BufferTooSmall) => buffer.resize(buffer.len() * 2, 0),
+            Err(error) => return Err(error),
+        }
+    };
+    buffer.truncate(length);
+    String::from_utf8(buffer).map_err(|_| SysFileError::IoError)
+}
+
+/// Relative paths start at the current working directory
+pub fn set_current_dir(path: &str) -> Result<(), SysFileError> {
+    sys_chdir(path)
+}
@@ -0,0 +1,121 @@
+extern crate alloc;
+
+use alloc::{string::String, vec, vec::Vec};
+use common::{
+    errors::SysFileError,
+    fs::{FileDescriptor, FileStat},
+    mmap::MapFlags,
+    syscalls::{
+        sys_attach_loop, sys_close_file, sys_create_file, sys_mkdir, sys_mmap_file, sys_mount,
+        sys_open_file, sys_read_directory, sys_read_file, sys_rename, sys_stat, sys_sync,
+        sys_unlink, sys_write_file,
+    },
+};
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// A file or device which is closed when it is dropped
+pub struct File(FileDescriptor);
+
+impl File {
+    pub fn open(path: &str) -> Result<Self, SysFileError> {
+        sys_open_file(path).map(Self)
+    }
+
+    /// Fails if the file already exists
+    pub fn create(path: &str) -> Result<Self, SysFileError> {
+        sys_create_file(path).map(Self)
+    }
+
+    pub fn descriptor(&self) -> FileDescriptor {
+        self.0
+    }
+
+    /// Returns 0 at the end of the file
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, SysFileError> {
+        sys_read_file(self.0, buffer)
+    }
+
+    /// Returns how many bytes were written
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, SysFileError> {
+        sys_write_file(self.0, data)
+    }
+
+    /// Maps `length` bytes from the page aligned offset on, the mapping
+    /// stays valid after the file is closed. Writes to a ReadWrite mapping
+    /// don't reach the file.
+    pub fn map(
+        &self,
+        offset: usize,
+        length: usize,
+        flags: MapFlags,
+    ) -> Result<*mut u8, SysFileError> {
+        sys_mmap_file(self.0, offset, length, flags)
+    }
+
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>, SysFileError> {
+        let mut data = Vec::new();
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        loop {
+            let count = self.read(&mut chunk)?;
+            if count == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&chunk[..count]);
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        // The descriptor is valid as long as the file exists
+        let _ = sys_close_file(self.0);
+    }
+}
+
+/// Names of directories end with a slash
+pub fn read_directory(path: &str) -> Result<Vec<String>, SysFileError> {
+    let mut buffer = vec![0; READ_CHUNK_SIZE];
+    let length = loop {
+        match sys_read_directory(path, &mut buffer) {
+            Ok(length) => break length,
+            Err(SysFileError::BufferTooSmall) => buffer.resize(buffer.len() * 2, 0),
+            Err(error) => return Err(error),
+        }
+    };
+    let listing = core::str::from_utf8(&buffer[..length]).map_err(|_| SysFileError::IoError)?;
+    Ok(listing.lines().map(String::from).collect())
+}
+
+/// Returns the path of the new loop device
+pub fn attach_loop(path: &str) -> Result<String, SysFileError> {
+    sys_attach_loop(path).map(|number| alloc::format!("/dev/loop{number}"))
+}
+
+/// Writes all cached blocks back to their devices
+pub fn sync() -> Result<(), SysFileError> {
+    sys_sync()
+}
+
+/// Mounts the filesystem on the block device at `source` on `target`
+pub fn mount(filesystem: &str, source: &str, target: &str) -> Result<(), SysFileError> {
+    sys_mount(filesystem, source, target)
+}
+
+pub fn stat(path: &str) -> Result<FileStat, SysFileError> {
+    sys_stat(path)
+}
+
+pub fn create_dir(path: &str) -> Result<(), SysFileError> {
+    sys_mkdir(path)
+}
+
+/// Removes a file or an empty directory
+pub fn remove(path: &str) -> Result<(), SysFileError> {
+    sys_unlink(path)
+}
+
+/// Both paths must be on the same filesystem
+pub fn rename(from: &str, to: &str) -> Result<(), SysFileError> {
+    sys_rename(from, to)
+}
@@ -10,7 +10,10 @@ use core::{
     ptr::{null_mut, NonNull},
 };
 
-use common::{mutex::Mutex, syscalls::sys_mmap_pages};
+use common::{
+    mutex::Mutex,
+    syscalls::{sys_brk, sys_mmap_pages},
+};
 
 const PAGE_SIZE: usize = 4096;
 
@@ -68,7 +71,6 @@ impl Pages for [Page] {
 
 pub trait PageAllocator {
     fn alloc(number_of_pages_requested: usize) -> Option<Range<NonNull<Page>>>;
-    fn dealloc(page: NonNull<Page>);
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -170,8 +172,24 @@ impl FreeBlock {
     }
 }
 
+/// The allocator never gives pages back. Therefore freed page allocations
+/// are kept in a list of page runs and reused before new pages are mapped.
+#[repr(C, align(8))]
+struct FreePageRun {
+    next: Option<NonNull<FreePageRun>>,
+    pages: usize,
+}
+
+impl FreePageRun {
+    fn end(run_ptr: NonNull<FreePageRun>) -> *mut u8 {
+        let pages = unsafe { run_ptr.as_ref().pages };
+        unsafe { run_ptr.cast::<u8>().as_ptr().add(pages * PAGE_SIZE) }
+    }
+}
+
 struct Heap<Allocator: PageAllocator> {
     genesis_block: FreeBlock,
+    free_page_runs: Option<NonNull<FreePageRun>>,
     allocator: PhantomData<Allocator>,
 }
 
@@ -181,18 +199,26 @@ impl<Allocator: PageAllocator> Heap<Allocator> {
     const fn new() -> Self {
         Self {
             genesis_block: FreeBlock::new(),
+            free_page_runs: None,
             allocator: PhantomData,
         }
     }
 
+    /// Blocks of the free list are only aligned to 8 bytes. Bigger
+    /// alignments are served with whole pages.
     fn is_page_allocator_allocation(&self, layout: &Layout) -> bool {
-        layout.size() >= PAGE_SIZE || layout.align() == PAGE_SIZE
+        layout.size() >= PAGE_SIZE || layout.align() > FreeBlock::DATA_ALIGNMENT
     }
 
     fn alloc(&mut self, layout: core::alloc::Layout) -> *mut u8 {
         if self.is_page_allocator_allocation(&layout) {
-            // Allocate directly from the page allocator
+            if layout.align() > PAGE_SIZE {
+                return null_mut();
+            }
             let pages = minimum_amount_of_pages(layout.size());
+            if let Some(run) = self.take_page_run(pages) {
+                return run.cast().as_ptr();
+            }
             if let Some(allocation) = Allocator::alloc(pages) {
                 return allocation.start.cast().as_ptr();
             } else {
@@ -224,9 +250,9 @@ impl<Allocator: PageAllocator> Heap<Allocator> {
     fn dealloc(&mut self, ptr: *mut u8, layout: core::alloc::Layout) {
         assert!(!ptr.is_null());
         if self.is_page_allocator_allocation(&layout) {
-            // Deallocate directly to the page allocator
+            let pages = minimum_amount_of_pages(layout.size());
             unsafe {
-                Allocator::dealloc(NonNull::new_unchecked(ptr).cast());
+                self.insert_page_run(NonNull::new_unchecked(ptr).cast(), pages);
             }
             return;
         }
@@ -246,6 +272,60 @@ impl<Allocator: PageAllocator> Heap<Allocator> {
         self.genesis_block.next = Some(block_ptr);
     }
 
+    /// First fit. The remainder of a bigger run stays in the list.
+    fn take_page_run(&mut self, pages: usize) -> Option<NonNull<FreePageRun>> {
+        let mut current = &mut self.free_page_runs;
+        while let Some(mut run_ptr) = *current {
+            let run = unsafe { run_ptr.as_mut() };
+            if run.pages < pages {
+                current = &mut run.next;
+                continue;
+            }
+            if run.pages == pages {
+                *current = run.next.take();
+            } else {
+                let remainder_ptr: NonNull<FreePageRun> =
+                    unsafe { run_ptr.byte_add(pages * PAGE_SIZE) };
+                unsafe {
+                    remainder_ptr.write(FreePageRun {
+                        next: run.next.take(),
+                        pages: run.pages - pages,
+                    });
+                }
+                *current = Some(remainder_ptr);
+            }
+            return Some(run_ptr);
+        }
+        None
+    }
+
+    /// Adjacent runs are merged such that bigger allocations can reuse them
+    fn insert_page_run(&mut self, mut run_ptr: NonNull<FreePageRun>, mut pages: usize) {
+        let mut current = &mut self.free_page_runs;
+        while let Some(mut neighbour_ptr) = *current {
+            let neighbour = unsafe { neighbour_ptr.as_mut() };
+            let run_start = run_ptr.cast::<u8>().as_ptr();
+            let run_end = unsafe { run_start.add(pages * PAGE_SIZE) };
+            if FreePageRun::end(neighbour_ptr) == run_start {
+                run_ptr = neighbour_ptr;
+            } else if run_end != neighbour_ptr.cast::<u8>().as_ptr() {
+                current = &mut neighbour.next;
+                continue;
+            }
+            // Take the neighbour out of the list and retry with the merged run
+            pages += neighbour.pages;
+            *current = neighbour.next.take();
+            current = &mut self.free_page_runs;
+        }
+        unsafe {
+            run_ptr.write(FreePageRun {
+                next: self.free_page_runs.take(),
+                pages,
+            });
+        }
+        self.free_page_runs = Some(run_ptr);
+    }
+
     fn split_if_necessary(
         &mut self,
         block_ptr: NonNull<FreeBlock>,
@@ -306,9 +386,24 @@ unsafe impl<Allocator: PageAllocator> GlobalAlloc for MutexHeap<Allocator> {
 
 struct KernelSyscallAllocator;
 
+// Bigger allocations get pages of their own instead of growing the heap
+const MMAP_THRESHOLD_PAGES: usize = 16;
+
+impl KernelSyscallAllocator {
+    fn grow_heap(number_of_pages: usize) -> Option<*mut Page> {
+        let start = align_up(sys_brk(0).ok()?, PAGE_SIZE);
+        sys_brk(start + number_of_pages * PAGE_SIZE).ok()?;
+        Some(start as *mut Page)
+    }
+}
+
 impl PageAllocator for KernelSyscallAllocator {
     fn alloc(number_of_pages_requested: usize) -> Option<Range<NonNull<Page>>> {
-        let ptr = sys_mmap_pages(number_of_pages_requested) as *mut Page;
+        let ptr = if number_of_pages_requested < MMAP_THRESHOLD_PAGES {
+            Self::grow_heap(number_of_pages_requested)?
+        } else {
+            sys_mmap_pages(number_of_pages_requested) as *mut Page
+        };
         if ptr.is_null() {
             return None;
         }
@@ -318,10 +413,6 @@ impl PageAllocator for KernelSyscallAllocator {
             Some(NonNull::new_unchecked(ptr)..NonNull::new_unchecked(end))
         }
     }
-
-    fn dealloc(page: NonNull<Page>) {
-        todo!()
-    }
 }
 
 #[global_allocator]
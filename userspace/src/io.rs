@@ -0,0 +1,175 @@
+extern crate alloc;
+
+use core::str::FromStr;
+
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use common::{
+    deadline::NO_DEADLINE,
+    errors::SysInputError,
+    mutex::{Mutex, MutexGuard},
+    poll::PollSource,
+    syscalls::{sys_exit, sys_read_input, sys_read_input_wait},
+};
+
+use crate::runtime::readable;
+
+/// Bytes which are read from the terminal in one go after a blocking read
+const BUFFER_CAPACITY: usize = 256;
+
+/// Exit status of programs whose pty was closed, the same as a shell
+/// killed by SIGHUP gets
+pub const HUNG_UP_EXIT_STATUS: isize = 129;
+
+static STDIN: Mutex<Stdin> = Mutex::new(Stdin::new());
+
+/// Returns the locked standard input of the process
+pub fn stdin() -> MutexGuard<'static, Stdin> {
+    STDIN.lock()
+}
+
+/// Buffered input of the terminal. Bytes which are not consumed by a
+/// read stay in the buffer for the next one, e.g. the rest of a line
+/// after reading a single value.
+pub struct Stdin {
+    buffer: VecDeque<u8>,
+}
+
+/// Reads which were interrupted because the program was stopped are
+/// done again once it continues
+fn read_input_wait(deadline: u64) -> Result<u8, SysInputError> {
+    loop {
+        match sys_read_input_wait(deadline) {
+            Err(SysInputError::Interrupted) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Reads without deadline only fail once the master side of the pty
+/// was closed. Nobody can type anymore, therefore the program exits.
+pub fn exit_on_hang_up(error: SysInputError) -> ! {
+    assert!(
+        matches!(error, SysInputError::HungUp),
+        "Reads without deadline don't time out"
+    );
+    sys_exit(HUNG_UP_EXIT_STATUS);
+    unreachable!("Exited programs don't continue");
+}
+
+impl Stdin {
+    const fn new() -> Self {
+        Self {
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Waits for the first byte and takes everything else which is
+    /// already available without blocking.
+    fn fill_buffer(&mut self) {
+        let byte = read_input_wait(NO_DEADLINE).unwrap_or_else(|error| exit_on_hang_up(error));
+        self.buffer.push_back(byte);
+        self.take_available();
+    }
+
+    fn take_available(&mut self) {
+        while self.buffer.len() < BUFFER_CAPACITY {
+            let Some(byte) = sys_read_input() else {
+                break;
+            };
+            self.buffer.push_back(byte);
+        }
+    }
+
+    fn peek_byte(&mut self) -> u8 {
+        if self.buffer.is_empty() {
+            self.fill_buffer();
+        }
+        self.buffer[0]
+    }
+
+    pub fn read_byte(&mut self) -> u8 {
+        let byte = self.peek_byte();
+        self.buffer.pop_front();
+        byte
+    }
+
+    /// The trailing newline is not part of the result
+    pub fn read_line(&mut self) -> String {
+        let mut line = Vec::new();
+        loop {
+            let byte = self.read_byte();
+            if byte == b'\n' {
+                break;
+            }
+            line.push(byte);
+        }
+        String::from_utf8_lossy(&line).into_owned()
+    }
+
+    /// Returns None instead of blocking if no complete line is available
+    pub fn try_read_line(&mut self) -> Option<String> {
+        self.take_available();
+        let end = self.buffer.iter().position(|byte| *byte == b'\n')?;
+        let line: Vec<u8> = self.buffer.drain(..=end).take(end).collect();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    /// Like read_line, but returns None if the line isn't complete before
+    /// the deadline, see vdso::deadline_in. What was read stays in the
+    /// buffer.
+    pub fn read_line_before(&mut self, deadline: u64) -> Option<String> {
+        loop {
+            if let Some(line) = self.try_read_line() {
+                return Some(line);
+            }
+            let byte = read_input_wait(deadline).ok()?;
+            self.buffer.push_back(byte);
+        }
+    }
+
+    /// The terminal never ends, therefore the iterator never ends either
+    pub fn lines(&mut self) -> Lines<'_> {
+        Lines { stdin: self }
+    }
+
+    /// Skips leading whitespace and returns everything up to the next
+    /// whitespace. The whitespace behind the token stays in the buffer.
+    pub fn read_token(&mut self) -> String {
+        while self.peek_byte().is_ascii_whitespace() {
+            self.read_byte();
+        }
+        let mut token = Vec::new();
+        while !self.peek_byte().is_ascii_whitespace() {
+            token.push(self.read_byte());
+        }
+        String::from_utf8_lossy(&token).into_owned()
+    }
+
+    /// Parses the next whitespace separated token, e.g. `stdin().read::<u32>()`
+    pub fn read<T: FromStr>(&mut self) -> Result<T, T::Err> {
+        self.read_token().parse()
+    }
+}
+
+/// Reads a line without blocking other tasks of the async runtime
+pub async fn read_line() -> String {
+    loop {
+        if let Some(line) = stdin().try_read_line() {
+            return line;
+        }
+        // Stdin is always a valid source
+        let _ = readable(PollSource::stdin()).await;
+    }
+}
+
+pub struct Lines<'a> {
+    stdin: &'a mut Stdin,
+}
+
+impl Iterator for Lines<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.stdin.read_line())
+    }
+}
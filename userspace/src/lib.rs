@@ -4,10 +4,18 @@
 
 mod _start;
 mod args;
+pub mod backtrace;
+pub mod env;
+pub mod fs;
 mod heap;
+pub mod io;
+pub mod line_editor;
 pub mod net;
 mod panic;
 pub mod print;
+pub mod pty;
+pub mod runtime;
 pub mod util;
+pub mod vdso;
 
 pub use args::{args, Args};
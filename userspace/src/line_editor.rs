@@ -0,0 +1,203 @@
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+use common::{
+    deadline::NO_DEADLINE,
+    errors::SysInputError,
+    syscalls::{sys_read_key_event, sys_set_input_mode},
+    tty::{InputMode, KeyEvent},
+};
+use core::cmp::Ordering;
+
+use crate::{io::exit_on_hang_up, print, println};
+
+const MAX_HISTORY_ENTRIES: usize = 100;
+
+/// Line editing with a command history. Uses raw mode while reading
+/// such that the cursor keys can be interpreted.
+pub struct LineEditor {
+    history: Vec<String>,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        Self {
+            history: Vec::new(),
+        }
+    }
+
+    pub fn read_line(&mut self) -> String {
+        sys_set_input_mode(InputMode::Raw).expect("Raw mode must be settable");
+
+        let mut line = Line::default();
+        // Index into the history while browsing it. The line which was
+        // typed before browsing is kept in draft.
+        let mut history_index = self.history.len();
+        let mut draft = String::new();
+
+        loop {
+            let event = match sys_read_key_event(NO_DEADLINE) {
+                // The shell was stopped, the line goes on once it continues
+                Err(SysInputError::Interrupted) => continue,
+                result => result.unwrap_or_else(|error| exit_on_hang_up(error)),
+            };
+            match event {
+                KeyEvent::Enter => {
+                    line.end();
+                    println!();
+                    break;
+                }
+                KeyEvent::Character(c) => line.insert(c as char),
+                KeyEvent::Backspace => {
+                    if line.left() {
+                        line.delete();
+                    }
+                }
+                KeyEvent::Delete => line.delete(),
+                KeyEvent::Left => {
+                    line.left();
+                }
+                KeyEvent::Right => line.right(),
+                KeyEvent::Home | KeyEvent::Control(b'a') => line.home(),
+                KeyEvent::End | KeyEvent::Control(b'e') => line.end(),
+                KeyEvent::Control(b'u') => line.kill_to_start(),
+                KeyEvent::Control(b'w') => line.kill_word(),
+                KeyEvent::Up if history_index > 0 => {
+                    if history_index == self.history.len() {
+                        draft = line.text.clone();
+                    }
+                    history_index -= 1;
+                    line.replace(&self.history[history_index]);
+                }
+                KeyEvent::Down if history_index < self.history.len() => {
+                    history_index += 1;
+                    line.replace(self.history.get(history_index).unwrap_or(&draft));
+                }
+                _ => {}
+            }
+        }
+
+        sys_set_input_mode(InputMode::Cooked).expect("Cooked mode must be settable");
+
+        self.add_to_history(&line.text);
+        line.text
+    }
+
+    fn add_to_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        if self.history.len() == MAX_HISTORY_ENTRIES {
+            self.history.remove(0);
+        }
+        self.history.push(line.into());
+    }
+}
+
+/// The edited line together with the cursor position. Every operation
+/// keeps the terminal in sync. Only ascii is supported such that byte
+/// offsets are equal to columns.
+#[derive(Default)]
+struct Line {
+    text: String,
+    cursor: usize,
+}
+
+impl Line {
+    fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += 1;
+        print!("{c}");
+        self.redraw_tail(0);
+    }
+
+    /// Deletes the character under the cursor
+    fn delete(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+            self.redraw_tail(1);
+        }
+    }
+
+    fn left(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.move_cursor_to(self.cursor - 1);
+        true
+    }
+
+    fn right(&mut self) {
+        if self.cursor < self.text.len() {
+            self.move_cursor_to(self.cursor + 1);
+        }
+    }
+
+    fn home(&mut self) {
+        self.move_cursor_to(0);
+    }
+
+    fn end(&mut self) {
+        self.move_cursor_to(self.text.len());
+    }
+
+    fn kill_to_start(&mut self) {
+        self.remove_before_cursor(0);
+    }
+
+    fn kill_word(&mut self) {
+        let start = self.text[..self.cursor]
+            .trim_end()
+            .trim_end_matches(|c| c != ' ')
+            .len();
+        self.remove_before_cursor(start);
+    }
+
+    fn replace(&mut self, text: &str) {
+        self.end();
+        self.kill_to_start();
+        self.text = text.into();
+        self.cursor = self.text.len();
+        print!("{text}");
+    }
+
+    fn remove_before_cursor(&mut self, start: usize) {
+        let removed = self.cursor - start;
+        if removed == 0 {
+            return;
+        }
+        self.move_cursor_to(start);
+        self.text.replace_range(start..start + removed, "");
+        self.redraw_tail(removed);
+    }
+
+    /// Prints everything after the cursor, blanks out the given number
+    /// of stale columns and moves back to the cursor.
+    fn redraw_tail(&self, stale_columns: usize) {
+        let tail = &self.text[self.cursor..];
+        print!("{tail}{:stale_columns$}", "");
+        move_left(tail.len() + stale_columns);
+    }
+
+    fn move_cursor_to(&mut self, position: usize) {
+        match position.cmp(&self.cursor) {
+            Ordering::Less => move_left(self.cursor - position),
+            Ordering::Greater => print!("{}", &self.text[self.cursor..position]),
+            Ordering::Equal => {}
+        }
+        self.cursor = position;
+    }
+}
+
+fn move_left(columns: usize) {
+    // A count of zero would still move by one column
+    if columns > 0 {
+        print!("\x1b[{columns}D");
+    }
+}
@@ -1,9 +1,21 @@
+use core::net::Ipv4Addr;
+
 use common::{
     errors::SysSocketError,
-    net::UDPDescriptor,
-    syscalls::{sys_open_udp_socket, sys_read_udp_socket, sys_write_back_udp_socket},
+    net::{
+        CapturedFrame, RawDescriptor, TcpListenerDescriptor, TcpStreamDescriptor, UDPDescriptor,
+    },
+    poll::PollSource,
+    syscalls::{
+        sys_accept_tcp_connection, sys_close_tcp_listener, sys_close_tcp_stream,
+        sys_connect_udp_socket, sys_open_raw_socket, sys_open_tcp_listener, sys_open_udp_socket,
+        sys_read_raw_socket, sys_read_tcp_stream, sys_read_udp_socket, sys_sendto,
+        sys_write_back_udp_socket, sys_write_tcp_stream,
+    },
 };
 
+use crate::{runtime::readable, util::wait_for_sources};
+
 pub struct UdpSocket(UDPDescriptor);
 
 impl UdpSocket {
@@ -11,14 +23,141 @@ impl UdpSocket {
         sys_open_udp_socket(port).map(Self)
     }
 
+    /// Client sockets don't care about their port
+    pub fn try_open_ephemeral() -> Result<Self, SysSocketError> {
+        Self::try_open(0)
+    }
+
+    /// Only datagrams of the peer are received afterwards and
+    /// [`UdpSocket::transmit`] sends to it
+    pub fn connect(&mut self, ip: Ipv4Addr, port: u16) -> Result<(), SysSocketError> {
+        sys_connect_udp_socket(self.0, ip.to_bits(), port)
+    }
+
     pub fn receive(&mut self, buffer: &mut [u8]) -> usize {
         let len = buffer.len();
         sys_read_udp_socket(self.0, buffer)
             .expect("This must succeed since it is a valid descriptor.")
     }
 
+    /// Waits for a datagram without blocking other tasks of the async runtime
+    pub async fn receive_async(&mut self, buffer: &mut [u8]) -> usize {
+        loop {
+            let received = self.receive(buffer);
+            if received > 0 {
+                return received;
+            }
+            readable(PollSource::udp_socket(self.0))
+                .await
+                .expect("This must succeed since it is a valid descriptor.");
+        }
+    }
+
     pub fn transmit(&mut self, buffer: &[u8]) -> usize {
         let len = buffer.len();
-        sys_write_back_udp_socket(self.0, buffer).expect("Sending must be successful.")
+        retry_unresolved(|| sys_write_back_udp_socket(self.0, buffer))
+            .expect("Sending must be successful.")
+    }
+
+    pub fn send_to(
+        &mut self,
+        ip: Ipv4Addr,
+        port: u16,
+        buffer: &[u8],
+    ) -> Result<usize, SysSocketError> {
+        retry_unresolved(|| sys_sendto(self.0, ip.to_bits(), port, buffer))
+    }
+}
+
+// The kernel asked for the mac address of the next hop, the answer
+// arrives shortly
+fn retry_unresolved(
+    send: impl Fn() -> Result<usize, SysSocketError>,
+) -> Result<usize, SysSocketError> {
+    loop {
+        match send() {
+            Err(SysSocketError::AddressNotResolved) => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Receives copies of all ethernet frames, see `sys_open_raw_socket`
+pub struct RawSocket(RawDescriptor);
+
+impl RawSocket {
+    pub fn try_open() -> Result<Self, SysSocketError> {
+        sys_open_raw_socket().map(Self)
+    }
+
+    /// Blocks until a frame was captured
+    pub fn receive(&mut self, buffer: &mut [u8]) -> CapturedFrame {
+        loop {
+            if let Some(frame) = sys_read_raw_socket(self.0, buffer)
+                .expect("This must succeed since it is a valid descriptor.")
+            {
+                return frame;
+            }
+            wait_for_sources(&mut [PollSource::raw_socket(self.0)]);
+        }
+    }
+}
+
+/// Accepts tcp connections on a port until it is dropped
+pub struct TcpListener(TcpListenerDescriptor);
+
+impl TcpListener {
+    pub fn try_open(port: u16) -> Result<Self, SysSocketError> {
+        sys_open_tcp_listener(port).map(Self)
+    }
+
+    /// Blocks until a connection is established
+    pub fn accept(&mut self) -> Result<TcpStream, SysSocketError> {
+        loop {
+            if let Some(stream) = sys_accept_tcp_connection(self.0)? {
+                return Ok(TcpStream(stream));
+            }
+            wait_for_sources(&mut [PollSource::tcp_listener(self.0)]);
+        }
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        // The descriptor is valid as long as the listener exists
+        let _ = sys_close_tcp_listener(self.0);
+    }
+}
+
+/// An accepted tcp connection, the peer reads the end of the stream
+/// once it is dropped
+pub struct TcpStream(TcpStreamDescriptor);
+
+impl TcpStream {
+    /// Does not block, returns 0 if nothing arrived yet and
+    /// SysSocketError::ConnectionClosed at the end of the stream
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, SysSocketError> {
+        sys_read_tcp_stream(self.0, buffer)
+    }
+
+    /// Blocks until everything is buffered for sending
+    pub fn write_all(&mut self, mut buffer: &[u8]) -> Result<(), SysSocketError> {
+        while !buffer.is_empty() {
+            let written = sys_write_tcp_stream(self.0, buffer)?;
+            buffer = &buffer[written..];
+        }
+        Ok(())
+    }
+
+    /// Ready once data or the end of the stream can be read
+    pub fn poll_source(&self) -> PollSource {
+        PollSource::tcp_stream(self.0)
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        // The descriptor is valid as long as the stream exists
+        let _ = sys_close_tcp_stream(self.0);
     }
 }
@@ -2,7 +2,7 @@ use core::panic::PanicInfo;
 
 use common::syscalls::sys_exit;
 
-use crate::println;
+use crate::{backtrace, println};
 
 #[panic_handler]
 pub fn panic(info: &PanicInfo) -> ! {
@@ -12,6 +12,8 @@ pub fn panic(info: &PanicInfo) -> ! {
     if let Some(location) = info.location() {
         println!("Location: {}", location);
     }
+    println!("Backtrace:");
+    backtrace::print();
 
     sys_exit(-1);
     loop {}
@@ -0,0 +1,52 @@
+use common::{
+    errors::{SysExecuteError, SysPtyError},
+    poll::PollSource,
+    syscalls::{
+        sys_close_pty, sys_execute_on_pty, sys_open_pty, sys_read_pty, sys_read_pty_wait,
+        sys_write_pty,
+    },
+    tty::PtyDescriptor,
+};
+
+/// The master side of a pseudo terminal, it is closed when dropped
+pub struct Pty(PtyDescriptor);
+
+impl Pty {
+    pub fn open() -> Result<Self, SysPtyError> {
+        sys_open_pty().map(Self)
+    }
+
+    /// Starts a program which uses the slave side as its console
+    pub fn execute(&self, name: &str, args: &[&str]) -> Result<u64, SysExecuteError> {
+        sys_execute_on_pty(name, args, self.0)
+    }
+
+    /// Reads the output of the slave side. Does not block.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, SysPtyError> {
+        sys_read_pty(self.0, buffer)
+    }
+
+    /// Blocks until the slave side wrote output or the deadline passed,
+    /// see vdso::deadline_in. Returns 0 once all programs on the slave
+    /// side exited and their output was read.
+    pub fn read_wait(&mut self, buffer: &mut [u8], deadline: u64) -> Result<usize, SysPtyError> {
+        sys_read_pty_wait(self.0, buffer, deadline)
+    }
+
+    /// Ready once there is output or all programs on the slave side exited
+    pub fn poll_source(&self) -> PollSource {
+        PollSource::pty_master(self.0)
+    }
+
+    /// Sends input to the slave side
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, SysPtyError> {
+        sys_write_pty(self.0, buffer)
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        // The descriptor is valid as long as the pty exists
+        let _ = sys_close_pty(self.0);
+    }
+}
@@ -0,0 +1,108 @@
+//! A single threaded async executor. Tasks which can't make progress
+//! register their interest in the reactor, which blocks in `sys_poll`
+//! or `sys_sleep` until one of them can continue.
+
+extern crate alloc;
+
+use core::{
+    future::Future,
+    pin::{pin, Pin},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+};
+use common::mutex::Mutex;
+
+mod reactor;
+
+pub use reactor::{readable, sleep, Readable, Sleep};
+
+type TaskId = usize;
+
+// The future passed to block_on
+const MAIN_TASK: TaskId = usize::MAX;
+
+struct LocalTask(Pin<Box<dyn Future<Output = ()>>>);
+
+// Userspace programs are single threaded. Therefore tasks never leave
+// the thread which created them.
+unsafe impl Send for LocalTask {}
+
+struct Executor {
+    tasks: BTreeMap<TaskId, LocalTask>,
+    ready: VecDeque<TaskId>,
+    next_id: TaskId,
+}
+
+static EXECUTOR: Mutex<Executor> = Mutex::new(Executor {
+    tasks: BTreeMap::new(),
+    ready: VecDeque::new(),
+    next_id: 0,
+});
+
+fn schedule(id: TaskId) {
+    let mut executor = EXECUTOR.lock();
+    if !executor.ready.contains(&id) {
+        executor.ready.push_back(id);
+    }
+}
+
+const VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |data| RawWaker::new(data, &VTABLE),
+    |data| schedule(data as TaskId),
+    |data| schedule(data as TaskId),
+    |_| {},
+);
+
+fn waker(id: TaskId) -> Waker {
+    // SAFETY: The waker only carries the task id and the vtable upholds
+    // the RawWaker contract
+    unsafe { Waker::from_raw(RawWaker::new(id as *const (), &VTABLE)) }
+}
+
+/// Spawned tasks make progress while `block_on` runs
+pub fn spawn(future: impl Future<Output = ()> + 'static) {
+    let mut executor = EXECUTOR.lock();
+    let id = executor.next_id;
+    executor.next_id += 1;
+    executor.tasks.insert(id, LocalTask(Box::pin(future)));
+    executor.ready.push_back(id);
+}
+
+/// Runs the future and all spawned tasks until the future completes
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let main_waker = waker(MAIN_TASK);
+    schedule(MAIN_TASK);
+    loop {
+        loop {
+            let Some(id) = EXECUTOR.lock().ready.pop_front() else {
+                break;
+            };
+            if id == MAIN_TASK {
+                let mut context = Context::from_waker(&main_waker);
+                if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                    return output;
+                }
+            } else {
+                run_task(id);
+            }
+        }
+        reactor::wait();
+    }
+}
+
+fn run_task(id: TaskId) {
+    // Taken out of the executor such that the task can spawn other tasks
+    let Some(mut task) = EXECUTOR.lock().tasks.remove(&id) else {
+        return;
+    };
+    let task_waker = waker(id);
+    let mut context = Context::from_waker(&task_waker);
+    if task.0.as_mut().poll(&mut context).is_pending() {
+        EXECUTOR.lock().tasks.insert(id, task);
+    }
+}
@@ -0,0 +1,134 @@
+extern crate alloc;
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use common::{
+    mutex::Mutex,
+    poll::{PollSource, POLL_FOREVER},
+    syscalls::{sys_poll, sys_sleep},
+};
+
+use crate::vdso::uptime_milliseconds;
+
+struct Reactor {
+    sources: Vec<(PollSource, Waker)>,
+    // Ordered by deadline, the second key keeps equal deadlines apart
+    timers: BTreeMap<(u64, usize), Waker>,
+    next_timer: usize,
+}
+
+static REACTOR: Mutex<Reactor> = Mutex::new(Reactor {
+    sources: Vec::new(),
+    timers: BTreeMap::new(),
+    next_timer: 0,
+});
+
+fn register_source(source: PollSource, waker: &Waker) {
+    REACTOR.lock().sources.push((source, waker.clone()));
+}
+
+fn register_timer(deadline: u64, waker: &Waker) {
+    let mut reactor = REACTOR.lock();
+    let id = reactor.next_timer;
+    reactor.next_timer += 1;
+    reactor.timers.insert((deadline, id), waker.clone());
+}
+
+/// Blocks until a registered source is ready or the next timer expires
+/// and wakes the corresponding tasks
+pub(super) fn wait() {
+    let (interests, next_deadline) = {
+        let mut reactor = REACTOR.lock();
+        let next_deadline = reactor
+            .timers
+            .first_key_value()
+            .map(|((deadline, _), _)| *deadline);
+        (core::mem::take(&mut reactor.sources), next_deadline)
+    };
+    assert!(
+        !interests.is_empty() || next_deadline.is_some(),
+        "No task can make progress"
+    );
+
+    let timeout = next_deadline.map_or(POLL_FOREVER, |deadline| {
+        deadline.saturating_sub(uptime_milliseconds())
+    });
+    let mut sources: Vec<PollSource> = interests.iter().map(|(source, _)| *source).collect();
+    if sources.is_empty() {
+        sys_sleep(timeout);
+    } else if let Ok(0) = sys_poll(&mut sources, timeout) {
+        // A blocking poll doesn't tell which sources became ready
+        let _ = sys_poll(&mut sources, 0);
+    }
+
+    for ((source, waker), polled) in interests.into_iter().zip(&sources) {
+        // Invalid sources are woken as well such that the task sees the error
+        if polled.is_ready() || sys_poll(&mut [source], 0).is_err() {
+            waker.wake();
+        } else {
+            register_source(source, &waker);
+        }
+    }
+
+    let expired = {
+        let mut reactor = REACTOR.lock();
+        let pending = reactor.timers.split_off(&(uptime_milliseconds() + 1, 0));
+        core::mem::replace(&mut reactor.timers, pending)
+    };
+    for waker in expired.into_values() {
+        waker.wake();
+    }
+}
+
+/// Completes when data can be read from the source without blocking
+pub fn readable(source: PollSource) -> Readable {
+    Readable { source }
+}
+
+pub struct Readable {
+    source: PollSource,
+}
+
+impl Future for Readable {
+    // Err if the descriptor of the source is invalid
+    type Output = Result<(), common::errors::SysPollError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut source = [self.source];
+        match sys_poll(&mut source, 0) {
+            Err(err) => Poll::Ready(Err(err)),
+            Ok(0) => {
+                register_source(self.source, context.waker());
+                Poll::Pending
+            }
+            Ok(_) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+pub fn sleep(milliseconds: u64) -> Sleep {
+    Sleep {
+        deadline: uptime_milliseconds().saturating_add(milliseconds),
+    }
+}
+
+pub struct Sleep {
+    deadline: u64,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        if uptime_milliseconds() >= self.deadline {
+            return Poll::Ready(());
+        }
+        register_timer(self.deadline, context.waker());
+        Poll::Pending
+    }
+}
@@ -1,12 +1,14 @@
 extern crate alloc;
 
 use alloc::string::String;
-use common::syscalls::sys_read_input_wait;
+use common::{
+    errors::SysPollError,
+    poll::{PollSource, POLL_FOREVER},
+    syscalls::sys_poll,
+};
 use core::arch::asm;
 
-use crate::{print, println};
-
-const DELETE: u8 = 127;
+use crate::io::stdin;
 
 pub fn wait(cycles: usize) {
     for _ in 0..cycles {
@@ -16,28 +18,20 @@ pub fn wait(cycles: usize) {
     }
 }
 
-pub fn read_line() -> String {
-    let mut input = String::new();
-    loop {
-        let result = sys_read_input_wait();
-        match result {
-            b'\r' | b'\n' => {
-                // Carriage return
-                println!();
-                break;
-            }
-            DELETE => {
-                if input.pop().is_some() {
-                    print!("{}{}{}", 8 as char, ' ', 8 as char);
-                }
-            }
-            _ => {
-                assert!(result.is_ascii());
-                let result = result as char;
-                input.push(result);
-                print!("{}", result);
-            }
-        }
+/// Blocks until any of the sources is ready. A blocking poll doesn't
+/// tell which ones, neither does one which was interrupted because the
+/// program was stopped; the caller checks them again in both cases.
+pub fn wait_for_sources(sources: &mut [PollSource]) {
+    if let Err(err) = sys_poll(sources, POLL_FOREVER) {
+        assert!(
+            matches!(err, SysPollError::Interrupted),
+            "Sources must be valid: {err:?}"
+        );
     }
-    input
+}
+
+/// Reads a line in cooked mode. The kernel already did the echo and
+/// the editing, the trailing newline is not part of the result.
+pub fn read_line() -> String {
+    stdin().read_line()
 }
@@ -0,0 +1,70 @@
+//! Reads the vDSO page which the kernel maps into every process. None of
+//! these functions do a syscall.
+
+use core::arch::asm;
+
+use common::vdso::{VdsoData, CPU_FEATURE_VECTOR, VDSO_ADDRESS};
+
+fn data() -> *const VdsoData {
+    VDSO_ADDRESS as *const VdsoData
+}
+
+/// Current value of the time CSR
+pub fn clocks() -> u64 {
+    let clocks: u64;
+    unsafe {
+        asm!("rdtime {clocks}", clocks = out(reg) clocks);
+    }
+    clocks
+}
+
+pub fn timebase_frequency() -> u64 {
+    // SAFETY: The kernel maps the page into every process
+    unsafe { (*data()).timebase_frequency }
+}
+
+pub fn uptime_milliseconds() -> u64 {
+    clocks() / (timebase_frequency() / 1000)
+}
+
+/// The deadline of a blocking syscall which times out after the given
+/// milliseconds
+pub fn deadline_in(milliseconds: u64) -> u64 {
+    clocks().saturating_add(milliseconds.saturating_mul(timebase_frequency() / 1000))
+}
+
+/// Nanoseconds since the unix epoch. None if the system has no RTC.
+pub fn realtime_nanoseconds() -> Option<u64> {
+    // SAFETY: The kernel maps the page into every process
+    let (epoch_nanoseconds, epoch_clocks) =
+        unsafe { ((*data()).epoch_nanoseconds, (*data()).epoch_clocks) };
+    if epoch_nanoseconds == 0 {
+        return None;
+    }
+    let elapsed = (clocks() - epoch_clocks) as u128 * 1_000_000_000;
+    Some(epoch_nanoseconds + (elapsed / timebase_frequency() as u128) as u64)
+}
+
+pub fn number_of_harts() -> usize {
+    // SAFETY: The kernel maps the page into every process
+    unsafe { (*data()).number_of_harts as usize }
+}
+
+pub fn pid() -> u64 {
+    // SAFETY: The kernel maps the page into every process
+    unsafe { (*data()).pid }
+}
+
+/// The hart the process was running on when it was scheduled the last
+/// time. It might already run on another one when this returns.
+pub fn current_hart() -> usize {
+    // SAFETY: The kernel maps the page into every process. It changes
+    // the value whenever the process is scheduled.
+    unsafe { core::ptr::addr_of!((*data()).hart_id).read_volatile() as usize }
+}
+
+/// Vector instructions can be used if this returns true
+pub fn has_vector_extension() -> bool {
+    // SAFETY: The kernel maps the page into every process
+    unsafe { (*data()).cpu_features & CPU_FEATURE_VECTOR != 0 }
+}